@@ -0,0 +1,149 @@
+//! Several SPI transfers run in one transaction, ie. all the registers of an IMU read under one chip select
+//! instead of one transaction per register.
+//!
+//! The transfers are queued in a `CuSpiBatch`, which returns a `CuSpiReply` for each one reading, then run on any
+//! `SpiDevice`. The batch keeps its buffers between the runs: clear it and queue the same transfers every cycle.
+
+use embedded_hal::spi::{Operation, SpiDevice};
+use std::ops::Range;
+
+/// Where the bytes read by a transfer of the batch are, see `CuSpiBatch::reply`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuSpiReply(Range<usize>);
+
+#[derive(Debug, Clone)]
+enum BatchOp {
+    Write(Range<usize>),
+    Read(Range<usize>),
+    Transfer(Range<usize>),
+    Delay(u32),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CuSpiBatch {
+    ops: Vec<BatchOp>,
+    /// The bytes written and read by all the operations, one after the other.
+    data: Vec<u8>,
+}
+
+impl CuSpiBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) -> Range<usize> {
+        let start = self.data.len();
+        self.data.extend_from_slice(bytes);
+        start..self.data.len()
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        let range = self.push_bytes(bytes);
+        self.ops.push(BatchOp::Write(range));
+    }
+
+    pub fn read(&mut self, len: usize) -> CuSpiReply {
+        let start = self.data.len();
+        self.data.resize(start + len, 0);
+        self.ops.push(BatchOp::Read(start..start + len));
+        CuSpiReply(start..start + len)
+    }
+
+    /// A full duplex transfer, the reply has the length of the bytes written.
+    pub fn transfer(&mut self, bytes: &[u8]) -> CuSpiReply {
+        let range = self.push_bytes(bytes);
+        self.ops.push(BatchOp::Transfer(range.clone()));
+        CuSpiReply(range)
+    }
+
+    /// Writes a register address then reads len bytes, the usual register read of the SPI sensors.
+    pub fn read_register(&mut self, address: u8, len: usize) -> CuSpiReply {
+        self.write(&[address]);
+        self.read(len)
+    }
+
+    pub fn delay_ns(&mut self, ns: u32) {
+        self.ops.push(BatchOp::Delay(ns));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Removes the transfers, keeping the buffers.
+    pub fn clear(&mut self) {
+        self.ops.clear();
+        self.data.clear();
+    }
+
+    /// Runs the transfers in one transaction of the device.
+    pub fn run<D: SpiDevice>(&mut self, device: &mut D) -> Result<(), D::Error> {
+        let mut rest = self.data.as_mut_slice();
+        let mut offset = 0;
+        let mut operations = Vec::with_capacity(self.ops.len());
+        for op in &self.ops {
+            let range = match op {
+                BatchOp::Write(range) | BatchOp::Read(range) | BatchOp::Transfer(range) => range,
+                BatchOp::Delay(ns) => {
+                    operations.push(Operation::DelayNs(*ns));
+                    continue;
+                }
+            };
+            // The ranges follow each other in data, so each one is split from the front of the rest.
+            let (bytes, tail) = std::mem::take(&mut rest).split_at_mut(range.end - offset);
+            rest = tail;
+            offset = range.end;
+            operations.push(match op {
+                BatchOp::Write(_) => Operation::Write(bytes),
+                BatchOp::Read(_) => Operation::Read(bytes),
+                _ => Operation::TransferInPlace(bytes),
+            });
+        }
+        device.transaction(&mut operations)
+    }
+
+    /// The bytes read by a transfer, after the run.
+    pub fn reply(&self, reply: &CuSpiReply) -> &[u8] {
+        &self.data[reply.0.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{CuMockSpi, CuSpiEvent};
+
+    #[test]
+    fn test_batch() {
+        let mut spi = CuMockSpi::new();
+        spi.reply(&[0xAA, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0xBB, 0xCC]);
+        let mut batch = CuSpiBatch::new();
+        batch.write(&[0x10, 0x20]);
+        let status = batch.read(1);
+        batch.delay_ns(500);
+        let accel = batch.read_register(0x3B | 0x80, 6);
+        let duplex = batch.transfer(&[0x55, 0x66]);
+        batch.run(&mut spi).unwrap();
+
+        assert_eq!(batch.reply(&status), [0xAA]);
+        assert_eq!(batch.reply(&accel), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(batch.reply(&duplex), [0xBB, 0xCC]);
+        use CuSpiEvent::*;
+        assert_eq!(
+            spi.events(),
+            [
+                Begin,
+                Write(vec![0x10, 0x20]),
+                Read(1),
+                Delay(500),
+                Write(vec![0xBB]),
+                Read(6),
+                Transfer(vec![0x55, 0x66]),
+                End,
+            ]
+        );
+
+        batch.clear();
+        assert!(batch.is_empty());
+    }
+}
@@ -0,0 +1,16 @@
+//! Hardware utilities shared by the drivers of Copper, on top of the embedded-hal 1.0 traits.
+//!
+//! - `spi`: a SPI bus shared by several devices, each with its own chip select pin.
+//! - `batch`: several SPI transfers queued and run in one transaction, under one chip select.
+//! - `spidev`: opens the Linux spidev devices and buses from the config of a task.
+//! - `mock`: a SPI bus and chip select pins recording what the drivers do, for their tests.
+
+pub mod batch;
+pub mod mock;
+pub mod spi;
+pub mod spidev;
+
+pub use batch::{CuSpiBatch, CuSpiReply};
+pub use mock::{CuMockPin, CuMockSpi, CuSpiEvent};
+pub use spi::{CuChipSelectPolarity, CuSpiBus, CuSpiDevice};
+pub use spidev::CuSpiConfig;
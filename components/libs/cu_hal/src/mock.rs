@@ -0,0 +1,157 @@
+//! A SPI bus recording what the drivers do, to test them without the hardware.
+//!
+//! `CuMockSpi` is both a bus and a device: give it to a driver directly, or share it with `CuSpiBus` and the pins
+//! made by `pin`, which record their levels in the same list of events. The bytes read are the ones queued with
+//! `reply`, in order, then zeros. The mock is cloned to keep a handle on it after it is given to the driver.
+
+use embedded_hal::digital::{self, OutputPin};
+use embedded_hal::spi::{self, ErrorKind, Operation, SpiBus, SpiDevice};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+/// What happened on the mock bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CuSpiEvent {
+    /// A transaction of the mock used as a device.
+    Begin,
+    End,
+    /// A pin made by `pin` set to the level, true for high.
+    Pin(String, bool),
+    Write(Vec<u8>),
+    /// The number of bytes read.
+    Read(usize),
+    /// The bytes written by a full duplex transfer.
+    Transfer(Vec<u8>),
+    Delay(u32),
+    Flush,
+}
+
+#[derive(Default)]
+struct MockState {
+    events: Vec<CuSpiEvent>,
+    replies: VecDeque<u8>,
+    failure: Option<ErrorKind>,
+}
+
+#[derive(Clone, Default)]
+pub struct CuMockSpi {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl CuMockSpi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the bytes the next reads and transfers get.
+    pub fn reply(&self, bytes: &[u8]) {
+        self.state.lock().unwrap().replies.extend(bytes);
+    }
+
+    /// Makes the next operation on the bus fail with this error.
+    pub fn fail_next(&self, error: ErrorKind) {
+        self.state.lock().unwrap().failure = Some(error);
+    }
+
+    /// The events so far.
+    pub fn events(&self) -> Vec<CuSpiEvent> {
+        self.state.lock().unwrap().events.clone()
+    }
+
+    pub fn clear_events(&self) {
+        self.state.lock().unwrap().events.clear();
+    }
+
+    /// A chip select pin recording its levels on this bus.
+    pub fn pin(&self, name: &str) -> CuMockPin {
+        CuMockPin {
+            name: name.to_string(),
+            state: self.state.clone(),
+        }
+    }
+
+    /// Records the event unless a failure was injected, then fills the buffer with the queued replies.
+    fn record(&self, event: CuSpiEvent, read: &mut [u8]) -> Result<(), ErrorKind> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(error) = state.failure.take() {
+            return Err(error);
+        }
+        state.events.push(event);
+        for byte in read {
+            *byte = state.replies.pop_front().unwrap_or(0);
+        }
+        Ok(())
+    }
+}
+
+impl spi::ErrorType for CuMockSpi {
+    type Error = ErrorKind;
+}
+
+impl SpiBus for CuMockSpi {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), ErrorKind> {
+        self.record(CuSpiEvent::Read(words.len()), words)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), ErrorKind> {
+        self.record(CuSpiEvent::Write(words.to_vec()), &mut [])
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), ErrorKind> {
+        self.record(CuSpiEvent::Transfer(write.to_vec()), read)
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), ErrorKind> {
+        self.record(CuSpiEvent::Transfer(words.to_vec()), words)
+    }
+
+    fn flush(&mut self) -> Result<(), ErrorKind> {
+        self.record(CuSpiEvent::Flush, &mut [])
+    }
+}
+
+impl SpiDevice for CuMockSpi {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), ErrorKind> {
+        self.record(CuSpiEvent::Begin, &mut [])?;
+        for operation in operations {
+            match operation {
+                Operation::Read(buffer) => SpiBus::read(self, buffer),
+                Operation::Write(bytes) => SpiBus::write(self, bytes),
+                Operation::Transfer(read, write) => SpiBus::transfer(self, read, write),
+                Operation::TransferInPlace(buffer) => SpiBus::transfer_in_place(self, buffer),
+                Operation::DelayNs(ns) => self.record(CuSpiEvent::Delay(*ns), &mut []),
+            }?;
+        }
+        self.record(CuSpiEvent::End, &mut [])
+    }
+}
+
+/// A pin of the mock bus, see `CuMockSpi::pin`.
+pub struct CuMockPin {
+    name: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl CuMockPin {
+    fn set(&mut self, high: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.events.push(CuSpiEvent::Pin(self.name.clone(), high));
+    }
+}
+
+impl digital::ErrorType for CuMockPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for CuMockPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.set(true);
+        Ok(())
+    }
+}
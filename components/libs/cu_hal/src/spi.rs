@@ -0,0 +1,193 @@
+//! A SPI bus shared by several devices selected by their own GPIO, ie. an IMU and an ADC on the same bus.
+//!
+//! The bus is cloned for every driver and each one makes its `CuSpiDevice` from it with its chip select pin. A
+//! transaction of a device locks the bus, asserts its chip select, runs the operations, flushes the bus and
+//! deasserts the chip select, so the transactions of the drivers running on different threads do not interleave.
+//! The drivers only see the embedded-hal `SpiDevice` trait, the same as for a device selected by the kernel.
+
+use cu29::lockaudit::{self, LockSite};
+use cu29::{CuError, CuResult};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::{Error, ErrorKind, ErrorType, Operation, SpiBus, SpiDevice};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+static SPI_BUS_LOCK: LockSite = LockSite::new("spi_bus");
+
+/// The level of the chip select pin selecting the device.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CuChipSelectPolarity {
+    /// Selected when low, the usual case.
+    #[default]
+    ActiveLow,
+    ActiveHigh,
+}
+
+impl CuChipSelectPolarity {
+    /// From the config value "low" or "high".
+    pub fn from_config(value: &str) -> CuResult<Self> {
+        match value {
+            "low" => Ok(CuChipSelectPolarity::ActiveLow),
+            "high" => Ok(CuChipSelectPolarity::ActiveHigh),
+            _ => Err(CuError::from(format!(
+                "Unknown chip select polarity '{}', expected 'low' or 'high'.",
+                value
+            ))),
+        }
+    }
+}
+
+/// A SPI bus shared by the devices made from it, cheap to clone.
+pub struct CuSpiBus<B> {
+    bus: Arc<Mutex<B>>,
+}
+
+impl<B> Clone for CuSpiBus<B> {
+    fn clone(&self) -> Self {
+        CuSpiBus {
+            bus: self.bus.clone(),
+        }
+    }
+}
+
+impl<B: SpiBus> CuSpiBus<B> {
+    /// Shares a bus whose controller does not drive any chip select, ie. a spidev bus in SPI_NO_CS mode.
+    pub fn new(bus: B) -> Self {
+        CuSpiBus {
+            bus: Arc::new(Mutex::new(bus)),
+        }
+    }
+
+    /// A device of the bus selected by the given pin, deselected until its first transaction.
+    pub fn device<CS: OutputPin>(
+        &self,
+        cs: CS,
+        polarity: CuChipSelectPolarity,
+    ) -> CuResult<CuSpiDevice<B, CS>> {
+        let mut device = CuSpiDevice {
+            bus: self.bus.clone(),
+            cs,
+            polarity,
+        };
+        device
+            .select(false)
+            .map_err(|e| CuError::from(format!("Could not deselect the SPI device: {:?}", e)))?;
+        Ok(device)
+    }
+}
+
+/// A device of a shared bus with its chip select.
+pub struct CuSpiDevice<B, CS> {
+    bus: Arc<Mutex<B>>,
+    cs: CS,
+    polarity: CuChipSelectPolarity,
+}
+
+impl<B, CS: OutputPin> CuSpiDevice<B, CS> {
+    fn select(&mut self, selected: bool) -> Result<(), CS::Error> {
+        let high = selected == (self.polarity == CuChipSelectPolarity::ActiveHigh);
+        if high {
+            self.cs.set_high()
+        } else {
+            self.cs.set_low()
+        }
+    }
+}
+
+impl<B: SpiBus, CS: OutputPin> ErrorType for CuSpiDevice<B, CS> {
+    type Error = ErrorKind;
+}
+
+impl<B: SpiBus, CS: OutputPin> SpiDevice for CuSpiDevice<B, CS> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), ErrorKind> {
+        let bus = self.bus.clone();
+        let mut bus = lockaudit::lock(&SPI_BUS_LOCK, &bus).map_err(|_| ErrorKind::Other)?;
+        self.select(true).map_err(|_| ErrorKind::ChipSelectFault)?;
+        let result = run_operations(&mut *bus, operations);
+        // The flush and the deselect are done even if an operation failed, the first error is returned.
+        let flushed = bus.flush().map_err(|e| e.kind());
+        let deselected = self.select(false).map_err(|_| ErrorKind::ChipSelectFault);
+        result.and(flushed).and(deselected)
+    }
+}
+
+fn run_operations<B: SpiBus>(
+    bus: &mut B,
+    operations: &mut [Operation<'_, u8>],
+) -> Result<(), ErrorKind> {
+    for operation in operations {
+        match operation {
+            Operation::Read(buffer) => bus.read(buffer),
+            Operation::Write(bytes) => bus.write(bytes),
+            Operation::Transfer(read, write) => bus.transfer(read, write),
+            Operation::TransferInPlace(buffer) => bus.transfer_in_place(buffer),
+            Operation::DelayNs(ns) => {
+                bus.flush().map_err(|e| e.kind())?;
+                std::thread::sleep(Duration::from_nanos(*ns as u64));
+                Ok(())
+            }
+        }
+        .map_err(|e| e.kind())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{CuMockSpi, CuSpiEvent};
+
+    #[test]
+    fn test_chip_selects() {
+        let mock = CuMockSpi::new();
+        mock.reply(&[0x71, 0x12, 0x34]);
+        let bus = CuSpiBus::new(mock.clone());
+        let mut imu = bus
+            .device(mock.pin("imu"), CuChipSelectPolarity::ActiveLow)
+            .unwrap();
+        let mut adc = bus
+            .device(mock.pin("adc"), CuChipSelectPolarity::ActiveHigh)
+            .unwrap();
+
+        let mut who_am_i = [0u8; 1];
+        imu.transaction(&mut [Operation::Write(&[0xF5]), Operation::Read(&mut who_am_i)])
+            .unwrap();
+        assert_eq!(who_am_i, [0x71]);
+        let mut sample = [0u8; 2];
+        adc.read(&mut sample).unwrap();
+        assert_eq!(sample, [0x12, 0x34]);
+
+        use CuSpiEvent::*;
+        assert_eq!(
+            mock.events(),
+            [
+                Pin("imu".to_string(), true),
+                Pin("adc".to_string(), false),
+                Pin("imu".to_string(), false),
+                Write(vec![0xF5]),
+                Read(1),
+                Flush,
+                Pin("imu".to_string(), true),
+                Pin("adc".to_string(), true),
+                Read(2),
+                Flush,
+                Pin("adc".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deselect_on_error() {
+        let mock = CuMockSpi::new();
+        let bus = CuSpiBus::new(mock.clone());
+        let mut imu = bus
+            .device(mock.pin("imu"), CuChipSelectPolarity::ActiveLow)
+            .unwrap();
+        mock.fail_next(ErrorKind::Overrun);
+        assert_eq!(imu.write(&[0x01]), Err(ErrorKind::Overrun));
+        assert_eq!(
+            mock.events().last(),
+            Some(&CuSpiEvent::Pin("imu".to_string(), true))
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! The Linux spidev devices, configured from the config of the task:
+//!
+//! ```ron
+//! config: {
+//!     "spi_dev": "/dev/spidev0.0",
+//!     "max_speed_hz": 10000000,
+//!     "spi_mode": 3,
+//! }
+//! ```
+//!
+//! `open_device` gives a device selected by the kernel with the chip select of its spidev node. `open_bus` gives
+//! a bus without chip select for `CuSpiBus`, whose devices are selected by GPIOs.
+
+use cu29::config::ComponentConfig;
+use cu29::{CuError, CuResult};
+use linux_embedded_hal::spidev::{SpiModeFlags, SpidevOptions};
+use linux_embedded_hal::{SpidevBus, SpidevDevice};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuSpiConfig {
+    /// The spidev node, "/dev/spidev0.0" by default.
+    pub path: String,
+    /// 1MHz by default.
+    pub max_speed_hz: u32,
+    /// The SPI mode from 0 to 3, 0 by default.
+    pub mode: u8,
+}
+
+impl Default for CuSpiConfig {
+    fn default() -> Self {
+        CuSpiConfig {
+            path: "/dev/spidev0.0".to_string(),
+            max_speed_hz: 1_000_000,
+            mode: 0,
+        }
+    }
+}
+
+impl CuSpiConfig {
+    /// From the keys spi_dev, max_speed_hz and spi_mode of the config, the defaults for the missing ones.
+    pub fn from_config(config: Option<&ComponentConfig>) -> CuResult<Self> {
+        let mut spi_config = CuSpiConfig::default();
+        let Some(config) = config else {
+            return Ok(spi_config);
+        };
+        if let Some(path) = config.get::<String>("spi_dev") {
+            spi_config.path = path;
+        }
+        if let Some(max_speed_hz) = config.get::<u32>("max_speed_hz") {
+            spi_config.max_speed_hz = max_speed_hz;
+        }
+        if let Some(mode) = config.get::<u8>("spi_mode") {
+            if mode > 3 {
+                return Err(CuError::from(format!(
+                    "Invalid spi_mode {}, expected 0 to 3.",
+                    mode
+                )));
+            }
+            spi_config.mode = mode;
+        }
+        Ok(spi_config)
+    }
+
+    fn options(&self, no_cs: bool) -> SpidevOptions {
+        let mut mode = match self.mode {
+            0 => SpiModeFlags::SPI_MODE_0,
+            1 => SpiModeFlags::SPI_MODE_1,
+            2 => SpiModeFlags::SPI_MODE_2,
+            _ => SpiModeFlags::SPI_MODE_3,
+        };
+        if no_cs {
+            mode |= SpiModeFlags::SPI_NO_CS;
+        }
+        SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(self.max_speed_hz)
+            .mode(mode)
+            .build()
+    }
+
+    /// Opens the device selected by the kernel.
+    pub fn open_device(&self) -> CuResult<SpidevDevice> {
+        let mut device = SpidevDevice::open(&self.path).map_err(|e| {
+            CuError::new_with_cause(&format!("Could not open the SPI device {}", self.path), e)
+        })?;
+        device.configure(&self.options(false)).map_err(|e| {
+            CuError::new_with_cause(
+                &format!("Could not configure the SPI device {}", self.path),
+                e,
+            )
+        })?;
+        Ok(device)
+    }
+
+    /// Opens the bus of the node without driving its chip select.
+    pub fn open_bus(&self) -> CuResult<SpidevBus> {
+        let mut bus = SpidevBus::open(&self.path).map_err(|e| {
+            CuError::new_with_cause(&format!("Could not open the SPI bus {}", self.path), e)
+        })?;
+        bus.configure(&self.options(true)).map_err(|e| {
+            CuError::new_with_cause(&format!("Could not configure the SPI bus {}", self.path), e)
+        })?;
+        Ok(bus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spi_config() {
+        assert_eq!(
+            CuSpiConfig::from_config(None).unwrap(),
+            CuSpiConfig::default()
+        );
+        let mut config = ComponentConfig::new();
+        config.set("spi_dev", "/dev/spidev1.2".to_string());
+        config.set("spi_mode", 3u8);
+        let spi_config = CuSpiConfig::from_config(Some(&config)).unwrap();
+        assert_eq!(spi_config.path, "/dev/spidev1.2");
+        assert_eq!(spi_config.mode, 3);
+        assert_eq!(spi_config.max_speed_hz, 1_000_000);
+        config.set("spi_mode", 4u8);
+        assert!(CuSpiConfig::from_config(Some(&config)).is_err());
+    }
+}
@@ -584,11 +584,14 @@ impl CuMonitor for CuConsoleMon {
         let quitting = self.quitting.clone();
 
         // Start the main UI loop
-        thread::spawn(move || {
-            let mut ui = UI::new(config_dup, taskids, task_stats_ui, error_states);
-            ui.run_app(&mut terminal).expect("Failed to run app");
-            quitting.store(true, Ordering::SeqCst);
-        });
+        thread::Builder::new()
+            .name("cu:consolemon".to_string())
+            .spawn(move || {
+                let mut ui = UI::new(config_dup, taskids, task_stats_ui, error_states);
+                ui.run_app(&mut terminal).expect("Failed to run app");
+                quitting.store(true, Ordering::SeqCst);
+            })
+            .map_err(|e| CuError::new_with_cause("Could not start the console thread", e))?;
 
         Ok(())
     }
@@ -0,0 +1,172 @@
+use cu29::clock::{CuDuration, RobotClock};
+use cu29::config::ComponentConfig;
+use cu29::cutask::CuMsgMetadata;
+use cu29::monitoring::{CuMonitor, CuTaskState, Decision};
+use cu29::CuResult;
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use sd_notify::NotifyState;
+use signal_hook::consts::SIGTERM;
+use signal_hook::SigId;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Integrates a copper application with systemd when it runs as a `Type=notify` service:
+/// - it notifies systemd that the service is ready once all the tasks are started and a first copper list went
+///   through them,
+/// - it feeds the systemd watchdog from the copper loop when `WatchdogSec=` is set, so a stuck loop gets the
+///   service restarted,
+/// - it turns a SIGTERM (`systemctl stop`) into an error at the end of the current copper list so the runtime stops
+///   all the tasks cleanly.
+///
+/// Outside of systemd the notifications are no-ops.
+pub struct SystemdMonitor {
+    taskids: &'static [&'static str],
+    handle_sigterm: bool,
+    clock: Option<RobotClock>,
+    /// Half the watchdog timeout of the service, None when the watchdog is disabled.
+    watchdog_period: Option<CuDuration>,
+    last_feed: AtomicU64,
+    ready: AtomicBool,
+    terminate: Arc<AtomicBool>,
+    sigterm: Option<SigId>,
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        let e = e.to_string();
+        debug!("SystemdMonitor: could not notify systemd: {}", e);
+    }
+}
+
+impl SystemdMonitor {
+    /// Feeds the watchdog if half its timeout elapsed since the last time.
+    fn feed_watchdog(&self) {
+        let (Some(period), Some(clock)) = (self.watchdog_period, &self.clock) else {
+            return;
+        };
+        let now = clock.now().0;
+        if now.saturating_sub(self.last_feed.load(Ordering::Relaxed)) >= period.0 {
+            self.last_feed.store(now, Ordering::Relaxed);
+            notify(&[NotifyState::Watchdog]);
+        }
+    }
+}
+
+impl CuMonitor for SystemdMonitor {
+    fn new(config: Option<&ComponentConfig>, taskids: &'static [&'static str]) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let handle_sigterm = config
+            .and_then(|config| config.get::<bool>("handle_sigterm"))
+            .unwrap_or(true);
+        Ok(SystemdMonitor {
+            taskids,
+            handle_sigterm,
+            clock: None,
+            watchdog_period: None,
+            last_feed: AtomicU64::new(0),
+            ready: AtomicBool::new(false),
+            terminate: Arc::new(AtomicBool::new(false)),
+            sigterm: None,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        let mut watchdog_usec = 0u64;
+        self.watchdog_period = sd_notify::watchdog_enabled(false, &mut watchdog_usec)
+            .then(|| CuDuration::from(watchdog_usec * 1000 / 2));
+        self.last_feed.store(clock.now().0, Ordering::Relaxed);
+        self.clock = Some(clock.clone());
+        self.ready.store(false, Ordering::Relaxed);
+        self.terminate.store(false, Ordering::Relaxed);
+        if self.handle_sigterm && self.sigterm.is_none() {
+            let id = signal_hook::flag::register(SIGTERM, self.terminate.clone())
+                .map_err(|e| CuError::new_with_cause("Could not install the SIGTERM handler", e))?;
+            self.sigterm = Some(id);
+        }
+        Ok(())
+    }
+
+    fn process_copperlist(&self, _msgs: &[&CuMsgMetadata]) -> CuResult<()> {
+        if self.terminate.load(Ordering::Relaxed) {
+            return Err("SIGTERM received, stopping.".into());
+        }
+        if !self.ready.swap(true, Ordering::Relaxed) {
+            debug!("SystemdMonitor: all the tasks are ready.");
+            notify(&[NotifyState::Ready]);
+        }
+        self.feed_watchdog();
+        Ok(())
+    }
+
+    fn process_error(&self, taskid: usize, step: CuTaskState, error: &CuError) -> Decision {
+        match step {
+            CuTaskState::Start | CuTaskState::Stop => {
+                let status = format!("Task {} failed: {}", self.taskids[taskid], error);
+                notify(&[NotifyState::Status(&status)]);
+                Decision::Shutdown
+            }
+            CuTaskState::Preprocess => Decision::Abort,
+            CuTaskState::Process | CuTaskState::Postprocess => Decision::Ignore,
+        }
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        notify(&[NotifyState::Stopping]);
+        if let Some(id) = self.sigterm.take() {
+            signal_hook::low_level::unregister(id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+    use std::time::Duration;
+
+    #[test]
+    fn test_notify_watchdog_and_sigterm() {
+        let path = std::env::temp_dir().join(format!("cu_systemd_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        std::env::set_var("NOTIFY_SOCKET", &path);
+        std::env::set_var("WATCHDOG_USEC", "2000000");
+        std::env::set_var("WATCHDOG_PID", std::process::id().to_string());
+        let received = || {
+            let mut buffer = [0u8; 256];
+            let mut messages = Vec::new();
+            while let Ok(len) = socket.recv(&mut buffer) {
+                messages.push(String::from_utf8_lossy(&buffer[..len]).trim().to_string());
+            }
+            messages
+        };
+
+        let (clock, mock) = RobotClock::mock();
+        let mut monitor = SystemdMonitor::new(None, &["a", "b"]).unwrap();
+        monitor.start(&clock).unwrap();
+        assert_eq!(
+            monitor.watchdog_period,
+            Some(CuDuration::from(1_000_000_000))
+        );
+
+        monitor.process_copperlist(&[]).unwrap();
+        assert_eq!(received(), vec!["READY=1"]);
+        mock.increment(Duration::from_millis(500));
+        monitor.process_copperlist(&[]).unwrap();
+        assert!(received().is_empty());
+        mock.increment(Duration::from_millis(600));
+        monitor.process_copperlist(&[]).unwrap();
+        assert_eq!(received(), vec!["WATCHDOG=1"]);
+
+        signal_hook::low_level::raise(SIGTERM).unwrap();
+        assert!(monitor.process_copperlist(&[]).is_err());
+        monitor.stop(&clock).unwrap();
+        assert_eq!(received(), vec!["STOPPING=1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+//! Common payloads produced by sensors.
+//! All the units are SI: meters and radians.
+
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use cu29::device::CuDeviceHandle;
+use cu29::pod::{decode_pod_vec, encode_pod_slice};
+use cu29::pool::CuHandle;
+use cu29::registry::register_json_msg_type;
+use cu29::CuResult;
+use serde::{Deserialize, Serialize};
+
+/// A planar scan from a 2D lidar, the ranges are ordered by increasing angle.
+/// The angles are counterclockwise in the frame of the sensor, 0 being straight ahead.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LaserScan {
+    pub angle_min: f32,
+    pub angle_increment: f32,
+    pub range_min: f32,
+    pub range_max: f32,
+    /// Out of range measurements are reported as a value outside [range_min, range_max] (or NaN).
+    pub ranges: Vec<f32>,
+}
+
+/// Same encoding as a derived one, the ranges are copied at once.
+impl Encode for LaserScan {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.angle_min.encode(encoder)?;
+        self.angle_increment.encode(encoder)?;
+        self.range_min.encode(encoder)?;
+        self.range_max.encode(encoder)?;
+        encode_pod_slice(&self.ranges, encoder)
+    }
+}
+
+impl Decode for LaserScan {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(LaserScan {
+            angle_min: f32::decode(decoder)?,
+            angle_increment: f32::decode(decoder)?,
+            range_min: f32::decode(decoder)?,
+            range_max: f32::decode(decoder)?,
+            ranges: decode_pod_vec(decoder)?,
+        })
+    }
+}
+
+impl LaserScan {
+    /// Iterates over the (angle, range) of the valid measurements.
+    pub fn valid_points(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| **range >= self.range_min && **range <= self.range_max)
+            .map(|(i, range)| (self.angle_min + i as f32 * self.angle_increment, *range))
+    }
+}
+
+/// Layout of the pixels of an [Image].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum ImageFormat {
+    /// 8 bits grayscale.
+    #[default]
+    Mono8,
+    Rgb8,
+    Bgr8,
+    Rgba8,
+    /// Packed YUV 4:2:2, 2 pixels in 4 bytes: Y0 U Y1 V.
+    Yuyv,
+}
+
+impl ImageFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            ImageFormat::Mono8 => 1,
+            ImageFormat::Yuyv => 2,
+            ImageFormat::Rgb8 | ImageFormat::Bgr8 => 3,
+            ImageFormat::Rgba8 => 4,
+        }
+    }
+}
+
+/// A camera frame. The pixels are usually in a buffer from a pool of the task producing the image, rows are
+/// `stride` bytes apart.
+///
+/// In a GPU pipeline the pixels stay in the memory of the device, in `device`, and `data` is empty: the accessors
+/// to the pixels below are only for the images in the memory of the CPU.
+#[derive(Debug, Default, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub format: ImageFormat,
+    pub data: CuHandle<u8>,
+    /// The pixels when they are in the memory of a GPU, with the same layout.
+    pub device: Option<CuDeviceHandle>,
+}
+
+impl Image {
+    /// An image whose pixels are in the memory of a GPU.
+    pub fn on_device(
+        width: u32,
+        height: u32,
+        stride: u32,
+        format: ImageFormat,
+        device: CuDeviceHandle,
+    ) -> Self {
+        Image {
+            width,
+            height,
+            stride,
+            format,
+            data: CuHandle::default(),
+            device: Some(device),
+        }
+    }
+
+    pub fn is_on_device(&self) -> bool {
+        self.device.is_some()
+    }
+
+    /// The luminance of the pixel at x, y.
+    pub fn luma(&self, x: u32, y: u32) -> u8 {
+        let row = (y * self.stride) as usize;
+        let bpp = self.format.bytes_per_pixel();
+        let p = &self.data[row + x as usize * bpp..];
+        match self.format {
+            ImageFormat::Mono8 | ImageFormat::Yuyv => p[0],
+            ImageFormat::Rgb8 | ImageFormat::Rgba8 => {
+                ((p[0] as u32 * 77 + p[1] as u32 * 150 + p[2] as u32 * 29) >> 8) as u8
+            }
+            ImageFormat::Bgr8 => {
+                ((p[2] as u32 * 77 + p[1] as u32 * 150 + p[0] as u32 * 29) >> 8) as u8
+            }
+        }
+    }
+
+    /// The red, green and blue components of the pixel at x, y.
+    pub fn rgb(&self, x: u32, y: u32) -> [u8; 3] {
+        let row = (y * self.stride) as usize;
+        match self.format {
+            ImageFormat::Mono8 => {
+                let v = self.data[row + x as usize];
+                [v, v, v]
+            }
+            ImageFormat::Rgb8 | ImageFormat::Rgba8 => {
+                let p = &self.data[row + x as usize * self.format.bytes_per_pixel()..];
+                [p[0], p[1], p[2]]
+            }
+            ImageFormat::Bgr8 => {
+                let p = &self.data[row + x as usize * 3..];
+                [p[2], p[1], p[0]]
+            }
+            ImageFormat::Yuyv => {
+                let pair = &self.data[row + (x as usize & !1) * 2..];
+                let luma = pair[(x as usize & 1) * 2] as f32;
+                let (u, v) = (pair[1] as f32 - 128.0, pair[3] as f32 - 128.0);
+                // BT.601
+                [
+                    luma + 1.402 * v,
+                    luma - 0.344 * u - 0.714 * v,
+                    luma + 1.772 * u,
+                ]
+                .map(|c| c.round().clamp(0.0, 255.0) as u8)
+            }
+        }
+    }
+
+    /// Writes the grayscale version of the image to out, width * height bytes without padding.
+    pub fn to_luma(&self, out: &mut [u8]) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                out[(y * self.width + x) as usize] = self.luma(x, y);
+            }
+        }
+    }
+}
+
+/// A fixed number of PCM samples, interleaved by channel: left, right, left, right... for a stereo frame.
+/// The samples are in [-1, 1], usually in a buffer from a pool of the task producing the frame. The time of
+/// validity of the message is the time of the first sample.
+#[derive(Debug, Default, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct AudioFrame {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: CuHandle<f32>,
+}
+
+impl AudioFrame {
+    /// Number of samples per channel.
+    pub fn len(&self) -> usize {
+        if self.channels == 0 {
+            0
+        } else {
+            self.samples.len() / self.channels as usize
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Duration of the frame in nanoseconds.
+    pub fn duration_ns(&self) -> u64 {
+        if self.sample_rate == 0 {
+            0
+        } else {
+            self.len() as u64 * 1_000_000_000 / self.sample_rate as u64
+        }
+    }
+}
+
+/// A reading of an inertial measurement unit in the frame of the sensor, z up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct Imu {
+    /// In m/s², gravity included.
+    pub linear_acceleration: [f64; 3],
+    /// In rad/s.
+    pub angular_velocity: [f64; 3],
+}
+
+/// Registers the payloads of this crate in cu29::registry, for the bridges and exporters to handle them by name.
+pub fn register_msg_types() -> CuResult<()> {
+    register_json_msg_type::<LaserScan>("cu_sensor_payloads::LaserScan")?;
+    register_json_msg_type::<Image>("cu_sensor_payloads::Image")?;
+    register_json_msg_type::<AudioFrame>("cu_sensor_payloads::AudioFrame")?;
+    register_json_msg_type::<Imu>("cu_sensor_payloads::Imu")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_luma() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            stride: 8,
+            format: ImageFormat::Bgr8,
+            data: CuHandle::new_detached(vec![0, 0, 255, 255, 255, 255, 0, 0]),
+            device: None,
+        };
+        let mut out = [0u8; 2];
+        image.to_luma(&mut out);
+        assert_eq!(out, [76, 255]);
+        assert_eq!(image.rgb(0, 0), [255, 0, 0]);
+
+        let yuyv = Image {
+            width: 2,
+            height: 1,
+            stride: 4,
+            format: ImageFormat::Yuyv,
+            data: CuHandle::new_detached(vec![100, 128, 200, 128]),
+            device: None,
+        };
+        assert_eq!(yuyv.rgb(0, 0), [100, 100, 100]);
+        assert_eq!(yuyv.rgb(1, 0), [200, 200, 200]);
+    }
+
+    #[test]
+    fn test_image_on_device() {
+        let buffer = CuDeviceHandle::new_borrowed(
+            cu29::device::CuDeviceMemory::Cuda {
+                device: 0,
+                ptr: 0x1000,
+            },
+            640 * 480,
+        );
+        let image = Image::on_device(640, 480, 640, ImageFormat::Mono8, buffer);
+        assert!(image.is_on_device() && image.data.is_empty());
+        let config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&image, config).unwrap();
+        let (decoded, _): (Image, usize) = bincode::decode_from_slice(&encoded, config).unwrap();
+        let device = decoded.device.unwrap();
+        assert_eq!((device.len(), device.memory()), (640 * 480, None));
+    }
+
+    #[test]
+    fn test_audio_frame() {
+        let frame = AudioFrame {
+            sample_rate: 16000,
+            channels: 2,
+            samples: CuHandle::new_detached(vec![0.0; 320]),
+        };
+        assert_eq!(frame.len(), 160);
+        assert_eq!(frame.duration_ns(), 10_000_000);
+    }
+
+    #[test]
+    fn test_valid_points() {
+        let scan = LaserScan {
+            angle_min: -1.0,
+            angle_increment: 0.5,
+            range_min: 0.1,
+            range_max: 10.0,
+            ranges: vec![1.0, f32::NAN, 0.05, 20.0, 2.0],
+        };
+        let points: Vec<_> = scan.valid_points().collect();
+        assert_eq!(points, vec![(-1.0, 1.0), (1.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_scan_encoding() {
+        let scan = LaserScan {
+            angle_min: -1.0,
+            angle_increment: 0.5,
+            range_min: 0.1,
+            range_max: 10.0,
+            ranges: vec![1.0, 3.5, 2.0],
+        };
+        let config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&scan, config).unwrap();
+        let fields = (-1.0f32, 0.5f32, 0.1f32, 10.0f32, scan.ranges.clone());
+        assert_eq!(encoded, bincode::encode_to_vec(fields, config).unwrap());
+        let (decoded, _): (LaserScan, usize) =
+            bincode::decode_from_slice(&encoded, config).unwrap();
+        assert_eq!(decoded, scan);
+    }
+
+    #[test]
+    fn test_register_msg_types() {
+        register_msg_types().unwrap();
+        let scan_type = cu29::registry::msg_type("cu_sensor_payloads::LaserScan").unwrap();
+        let scan = LaserScan {
+            ranges: vec![1.0, 2.0],
+            ..Default::default()
+        };
+        let decoded = scan_type.decode(&scan_type.encode(&scan).unwrap()).unwrap();
+        assert_eq!(decoded.downcast_ref::<LaserScan>(), Some(&scan));
+    }
+}
@@ -0,0 +1,109 @@
+//! Common spatial payloads exchanged between tasks.
+//! All the units are SI: meters, radians, meters per second and radians per second.
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// A pose in 3D: position and orientation as roll, pitch, yaw.
+/// For planar robots only x, y and yaw are used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct Pose {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+}
+
+impl Pose {
+    pub fn new_2d(x: f64, y: f64, yaw: f64) -> Self {
+        Pose {
+            x,
+            y,
+            yaw,
+            ..Default::default()
+        }
+    }
+
+    /// Euclidean distance between the positions of 2 poses.
+    pub fn distance(&self, other: &Pose) -> f64 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2))
+            .sqrt()
+    }
+}
+
+/// A planar pose with its uncertainty, ie. from an odometry for an EKF.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct PoseWithCovariance {
+    pub pose: Pose,
+    /// The covariance of x, y and yaw, row major.
+    pub covariance: [f64; 9],
+}
+
+/// A velocity in 3D expressed in the frame of the robot.
+/// For planar robots only vx and wz are usually used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct Twist {
+    pub vx: f64,
+    pub vy: f64,
+    pub vz: f64,
+    pub wx: f64,
+    pub wy: f64,
+    pub wz: f64,
+}
+
+impl Twist {
+    pub fn new_2d(vx: f64, wz: f64) -> Self {
+        Twist {
+            vx,
+            wz,
+            ..Default::default()
+        }
+    }
+
+    /// Norm of the linear velocity.
+    pub fn linear_speed(&self) -> f64 {
+        (self.vx.powi(2) + self.vy.powi(2) + self.vz.powi(2)).sqrt()
+    }
+}
+
+/// The state of a set of joints, like an arm.
+/// The vectors are indexed by joint, velocities and efforts can be left empty if unknown.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct JointStates {
+    pub positions: Vec<f64>,
+    pub velocities: Vec<f64>,
+    pub efforts: Vec<f64>,
+}
+
+/// The state of one joint, ie. the feedback of its motor. See JointStatesAggregator in cu_joints.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct JointState {
+    pub position: f64,
+    pub velocity: Option<f64>,
+    pub effort: Option<f64>,
+}
+
+/// The setpoints of a set of joints, indexed like JointStates.
+/// The vectors that are not controlled are left empty.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct JointCommands {
+    pub positions: Vec<f64>,
+    pub velocities: Vec<f64>,
+    pub efforts: Vec<f64>,
+}
+
+/// The setpoints of one joint, see JointCommandSelect in cu_joints.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct JointCommand {
+    pub position: Option<f64>,
+    pub velocity: Option<f64>,
+    pub effort: Option<f64>,
+}
+
+/// An ordered list of poses to follow, the orientation of the waypoints is optional for most followers.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct Waypoints {
+    pub poses: Vec<Pose>,
+}
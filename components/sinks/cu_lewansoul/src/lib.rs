@@ -2,8 +2,8 @@ use bincode::de::Decoder;
 use bincode::enc::Encoder;
 use bincode::error::{DecodeError, EncodeError};
 use bincode::{Decode, Encode};
-use cu29::clock::RobotClock;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSinkTask, CuTaskLifecycle, Freezable};
 use cu29::{input_msg, CuError, CuResult};
 use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
@@ -254,7 +254,7 @@ impl Decode for ServoPositions {
 impl<'cl> CuSinkTask<'cl> for Lewansoul {
     type Input = input_msg!('cl, ServoPositions);
 
-    fn process(&mut self, _clock: &RobotClock, _input: Self::Input) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, _input: Self::Input) -> CuResult<()> {
         todo!()
     }
 }
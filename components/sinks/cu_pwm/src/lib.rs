@@ -0,0 +1,276 @@
+mod sysfs;
+
+pub use sysfs::SysfsPwm;
+
+use bincode::{Decode, Encode};
+use cu29::clock::{CuDuration, CuTime, RobotClock};
+use cu29::config::{is_dry_run, ComponentConfig};
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSinkTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The command of one channel of the pwmchip.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct PwmChannelCommand {
+    /// The channel number on the pwmchip, it must be in the channels of the sink.
+    pub channel: u8,
+    pub frequency_hz: f32,
+    /// From 0.0 to 1.0.
+    pub duty: f32,
+}
+
+/// The frequency and duty cycle of the channels to change, the others keep their output.
+#[derive(Debug, Clone, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct PwmCommand {
+    pub channels: Vec<PwmChannelCommand>,
+}
+
+impl PwmCommand {
+    pub fn set(mut self, channel: u8, frequency_hz: f32, duty: f32) -> Self {
+        self.channels.push(PwmChannelCommand {
+            channel,
+            frequency_hz,
+            duty,
+        });
+        self
+    }
+}
+
+/// Produces the hardware PWM of the channels of a pwmchip from the PwmCommand messages.
+///
+/// The outputs go to their safe duty cycle on start, on stop and when no command came for timeout_ms, ie. because
+/// the task computing them crashed or is stuck. A safe duty of 0 also disables the outputs on stop, a non zero one
+/// (the neutral of a servo or of an ESC) keeps them running.
+pub struct PwmSink {
+    outputs: Vec<(u8, SysfsPwm)>,
+    safe_duty: f32,
+    /// The period of the outputs until their first command.
+    default_period_ns: u64,
+    timeout: Option<CuDuration>,
+    last_command: Option<CuTime>,
+    safe: bool,
+}
+
+fn period_ns(frequency_hz: f64) -> CuResult<u64> {
+    if !frequency_hz.is_finite() || frequency_hz <= 0.0 {
+        return Err(CuError::from(format!(
+            "Invalid PWM frequency {} Hz.",
+            frequency_hz
+        )));
+    }
+    Ok((1e9 / frequency_hz).round() as u64)
+}
+
+fn duty_ns(period_ns: u64, duty: f32) -> CuResult<u64> {
+    if !(0.0..=1.0).contains(&duty) {
+        return Err(CuError::from(format!(
+            "Invalid PWM duty cycle {}, expected 0.0 to 1.0.",
+            duty
+        )));
+    }
+    Ok((period_ns as f64 * duty as f64).round() as u64)
+}
+
+impl PwmSink {
+    fn apply_safe_state(&mut self) -> CuResult<()> {
+        for (_, output) in self.outputs.iter_mut() {
+            let period_ns = match output.period_ns() {
+                0 => self.default_period_ns,
+                period_ns => period_ns,
+            };
+            output.set(period_ns, duty_ns(period_ns, self.safe_duty)?)?;
+        }
+        self.safe = true;
+        Ok(())
+    }
+
+    fn apply(&mut self, command: &PwmCommand) -> CuResult<()> {
+        for channel_command in &command.channels {
+            let output = self
+                .outputs
+                .iter_mut()
+                .find(|(channel, _)| *channel == channel_command.channel)
+                .map(|(_, output)| output)
+                .ok_or_else(|| {
+                    CuError::from(format!(
+                        "The PWM channel {} is not an output of this sink.",
+                        channel_command.channel
+                    ))
+                })?;
+            let period_ns = period_ns(channel_command.frequency_hz as f64)?;
+            output.set(period_ns, duty_ns(period_ns, channel_command.duty)?)?;
+        }
+        self.safe = false;
+        Ok(())
+    }
+}
+
+impl Freezable for PwmSink {}
+
+impl CuTaskLifecycle for PwmSink {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("PwmSink needs a config with its channels.")?;
+        let chip = PathBuf::from(
+            config
+                .get::<String>("sysfs_root")
+                .unwrap_or("/sys/class/pwm".to_string()),
+        )
+        .join(format!(
+            "pwmchip{}",
+            config.get::<u32>("pwmchip").unwrap_or(0)
+        ));
+        let channels = config
+            .get::<String>("channels")
+            .unwrap_or("0".to_string())
+            .split(',')
+            .map(|channel| {
+                channel.trim().parse::<u8>().map_err(|e| {
+                    CuError::new_with_cause(&format!("Invalid PWM channel '{}'", channel), e)
+                })
+            })
+            .collect::<CuResult<Vec<_>>>()?;
+        // A dry run checks the config without opening the pwmchip.
+        let outputs = if is_dry_run(Some(config)) {
+            Vec::new()
+        } else {
+            channels
+                .into_iter()
+                .map(|channel| Ok((channel, SysfsPwm::open(&chip, channel)?)))
+                .collect::<CuResult<Vec<_>>>()?
+        };
+        let safe_duty = config.get::<f64>("safe_duty").unwrap_or(0.0) as f32;
+        duty_ns(1, safe_duty)?;
+        let timeout_ms = config.get::<u32>("timeout_ms").unwrap_or(500);
+        Ok(PwmSink {
+            outputs,
+            safe_duty,
+            default_period_ns: period_ns(config.get::<f64>("frequency_hz").unwrap_or(1000.0))?,
+            timeout: (timeout_ms > 0).then(|| CuDuration::from(timeout_ms as u64 * 1_000_000)),
+            last_command: None,
+            safe: false,
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        debug!("Starting the PWM outputs in their safe state.");
+        self.apply_safe_state()?;
+        self.last_command = None;
+        for (_, output) in self.outputs.iter_mut() {
+            output.enable(true)?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        debug!("Stopping the PWM outputs in their safe state.");
+        self.apply_safe_state()?;
+        if self.safe_duty == 0.0 {
+            for (_, output) in self.outputs.iter_mut() {
+                output.enable(false)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'cl> CuSinkTask<'cl> for PwmSink {
+    type Input = input_msg!('cl, PwmCommand);
+
+    fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+        let now = ctx.now();
+        match input.payload() {
+            Some(command) => {
+                if let Err(e) = self.apply(command) {
+                    self.apply_safe_state()?;
+                    return Err(e);
+                }
+                self.last_command = Some(now);
+            }
+            None => {
+                let timed_out = match (self.timeout, self.last_command) {
+                    (Some(timeout), Some(last_command)) => now - last_command > timeout,
+                    _ => false,
+                };
+                if timed_out && !self.safe {
+                    debug!(
+                        "No PWM command for {}, going to the safe state.",
+                        (now - self.last_command.unwrap()).to_string()
+                    );
+                    self.apply_safe_state()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysfs::tests::{fake_chip, value};
+    use cu29::config::DRY_RUN_KEY;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pwm_sink() {
+        let root = fake_chip(&[0, 1]);
+        let chip = root.path().join("pwmchip0");
+        let mut config = ComponentConfig::new();
+        config.set("sysfs_root", root.path().to_str().unwrap().to_string());
+        config.set("channels", "0, 1".to_string());
+        config.set("frequency_hz", 50.0);
+        config.set("safe_duty", 0.075);
+        config.set("timeout_ms", 100u32);
+
+        let (clock, mock) = RobotClock::mock();
+        let mut sink = PwmSink::new(Some(&config)).unwrap();
+        sink.start(&clock).unwrap();
+        assert_eq!(value(&chip, 0, "period"), 20_000_000);
+        assert_eq!(value(&chip, 0, "duty_cycle"), 1_500_000);
+        assert_eq!(value(&chip, 1, "enable"), 1);
+
+        let mut input = CuMsg::new(Some(PwmCommand::default().set(1, 1000.0, 0.25)));
+        sink.process(&CuContext::from(&clock), &input).unwrap();
+        assert_eq!(value(&chip, 1, "period"), 1_000_000);
+        assert_eq!(value(&chip, 1, "duty_cycle"), 250_000);
+        assert_eq!(value(&chip, 0, "duty_cycle"), 1_500_000);
+
+        // No command for longer than the timeout.
+        input.clear_payload();
+        mock.increment(Duration::from_millis(50));
+        sink.process(&CuContext::from(&clock), &input).unwrap();
+        assert_eq!(value(&chip, 1, "duty_cycle"), 250_000);
+        mock.increment(Duration::from_millis(60));
+        sink.process(&CuContext::from(&clock), &input).unwrap();
+        assert_eq!(value(&chip, 1, "duty_cycle"), 75_000);
+
+        let input = CuMsg::new(Some(PwmCommand::default().set(3, 1000.0, 0.5)));
+        assert!(sink.process(&CuContext::from(&clock), &input).is_err());
+        let input = CuMsg::new(Some(PwmCommand::default().set(0, 1000.0, 1.5)));
+        assert!(sink.process(&CuContext::from(&clock), &input).is_err());
+
+        sink.stop(&clock).unwrap();
+        // The neutral is kept on stop.
+        assert_eq!(value(&chip, 0, "enable"), 1);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let mut config = ComponentConfig::new();
+        config.set("sysfs_root", "/nonexistent".to_string());
+        config.set("channels", "0, 1".to_string());
+        assert!(PwmSink::new(Some(&config)).is_err());
+
+        config.set(DRY_RUN_KEY, true);
+        assert!(PwmSink::new(Some(&config)).is_ok());
+        config.set("channels", "0, a".to_string());
+        assert!(PwmSink::new(Some(&config)).is_err());
+    }
+}
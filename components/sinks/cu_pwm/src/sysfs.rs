@@ -0,0 +1,145 @@
+//! A channel of a pwmchip through the sysfs interface of Linux, see
+//! https://www.kernel.org/doc/html/latest/driver-api/pwm.html#using-pwms-with-the-sysfs-interface
+
+use cu29::{CuError, CuResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for udev to give access to a channel just exported.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct SysfsPwm {
+    dir: PathBuf,
+    period_ns: u64,
+    duty_ns: u64,
+    enabled: bool,
+}
+
+impl SysfsPwm {
+    /// Opens the channel of the chip, ie. /sys/class/pwm/pwmchip0, exporting it if needed.
+    pub fn open(chip: &Path, channel: u8) -> CuResult<Self> {
+        let dir = chip.join(format!("pwm{}", channel));
+        if !dir.exists() {
+            write(&chip.join("export"), channel)?;
+            let start = Instant::now();
+            // The files are created by the kernel but made writable by udev a bit later.
+            while fs::metadata(dir.join("enable"))
+                .map(|m| m.permissions().readonly())
+                .unwrap_or(true)
+            {
+                if start.elapsed() > EXPORT_TIMEOUT {
+                    return Err(CuError::from(format!(
+                        "The PWM channel {} is not accessible after its export.",
+                        dir.display()
+                    )));
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        Ok(SysfsPwm {
+            period_ns: read(&dir.join("period"))?,
+            duty_ns: read(&dir.join("duty_cycle"))?,
+            enabled: read(&dir.join("enable"))? != 0,
+            dir,
+        })
+    }
+
+    pub fn period_ns(&self) -> u64 {
+        self.period_ns
+    }
+
+    /// Sets the period and the duty cycle, writing only what changed.
+    pub fn set(&mut self, period_ns: u64, duty_ns: u64) -> CuResult<()> {
+        let duty_ns = duty_ns.min(period_ns);
+        // The kernel refuses a duty cycle longer than the period at any time.
+        if period_ns < self.duty_ns {
+            self.set_duty(duty_ns)?;
+            self.set_period(period_ns)
+        } else {
+            self.set_period(period_ns)?;
+            self.set_duty(duty_ns)
+        }
+    }
+
+    fn set_period(&mut self, period_ns: u64) -> CuResult<()> {
+        if period_ns != self.period_ns {
+            write(&self.dir.join("period"), period_ns)?;
+            self.period_ns = period_ns;
+        }
+        Ok(())
+    }
+
+    fn set_duty(&mut self, duty_ns: u64) -> CuResult<()> {
+        if duty_ns != self.duty_ns {
+            write(&self.dir.join("duty_cycle"), duty_ns)?;
+            self.duty_ns = duty_ns;
+        }
+        Ok(())
+    }
+
+    pub fn enable(&mut self, enabled: bool) -> CuResult<()> {
+        if enabled != self.enabled {
+            write(&self.dir.join("enable"), enabled as u8)?;
+            self.enabled = enabled;
+        }
+        Ok(())
+    }
+}
+
+fn write(path: &Path, value: impl ToString) -> CuResult<()> {
+    fs::write(path, value.to_string())
+        .map_err(|e| CuError::new_with_cause(&format!("Could not write {}", path.display()), e))
+}
+
+fn read(path: &Path) -> CuResult<u64> {
+    let value = fs::read_to_string(path)
+        .map_err(|e| CuError::new_with_cause(&format!("Could not read {}", path.display()), e))?;
+    value
+        .trim()
+        .parse()
+        .map_err(|e| CuError::new_with_cause(&format!("Invalid value in {}", path.display()), e))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A sysfs root with a pwmchip0 whose channels are already exported.
+    pub(crate) fn fake_chip(channels: &[u8]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for channel in channels {
+            let dir = root.path().join(format!("pwmchip0/pwm{}", channel));
+            fs::create_dir_all(&dir).unwrap();
+            for file in ["period", "duty_cycle", "enable"] {
+                fs::write(dir.join(file), "0\n").unwrap();
+            }
+        }
+        root
+    }
+
+    pub(crate) fn value(chip: &Path, channel: u8, file: &str) -> u64 {
+        read(&chip.join(format!("pwm{}", channel)).join(file)).unwrap()
+    }
+
+    #[test]
+    fn test_sysfs_pwm() {
+        let root = fake_chip(&[1]);
+        let chip = root.path().join("pwmchip0");
+        let mut pwm = SysfsPwm::open(&chip, 1).unwrap();
+        pwm.set(1_000_000, 250_000).unwrap();
+        pwm.enable(true).unwrap();
+        assert_eq!(value(&chip, 1, "period"), 1_000_000);
+        assert_eq!(value(&chip, 1, "duty_cycle"), 250_000);
+        assert_eq!(value(&chip, 1, "enable"), 1);
+
+        // A shorter period, the duty is capped.
+        pwm.set(100_000, 250_000).unwrap();
+        assert_eq!(value(&chip, 1, "period"), 100_000);
+        assert_eq!(value(&chip, 1, "duty_cycle"), 100_000);
+
+        // Not exported and no udev to do it in the fake sysfs.
+        assert!(SysfsPwm::open(&chip, 0).is_err());
+    }
+}
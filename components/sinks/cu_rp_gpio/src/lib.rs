@@ -1,5 +1,6 @@
 use bincode::{Decode, Encode};
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSinkTask, CuTaskLifecycle, Freezable};
 use cu29::CuResult;
 use cu29::{clock, input_msg};
@@ -93,7 +94,7 @@ impl CuTaskLifecycle for RPGpio {
 impl<'cl> CuSinkTask<'cl> for RPGpio {
     type Input = input_msg!('cl, RPGpioPayload);
 
-    fn process(&mut self, clock: &clock::RobotClock, msg: Self::Input) -> CuResult<()> {
+    fn process(&mut self, ctx: &CuContext, msg: Self::Input) -> CuResult<()> {
         #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
         self.pin.write(msg.payload.into());
         #[cfg(target_arch = "x86_64")]
@@ -101,7 +102,7 @@ impl<'cl> CuSinkTask<'cl> for RPGpio {
             "Would write to pin {} the value {}. Creation to Actuation: {}",
             self.pin,
             msg.payload().unwrap().on,
-            clock.now() - msg.payload().unwrap().creation.unwrap()
+            ctx.now() - msg.payload().unwrap().creation.unwrap()
         );
 
         Ok(())
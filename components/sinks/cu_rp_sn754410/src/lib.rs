@@ -4,6 +4,7 @@ use bincode::error::{DecodeError, EncodeError};
 use bincode::{Decode, Encode};
 use cu29::clock::{CuTime, RobotClock};
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSinkTask, CuTaskLifecycle, Freezable};
 use cu29::{input_msg, CuResult};
 use serde::{Deserialize, Serialize};
@@ -138,7 +139,7 @@ impl Freezable for SN754410 {
 impl<'cl> CuSinkTask<'cl> for SN754410 {
     type Input = input_msg!('cl, MotorPayload);
 
-    fn process(&mut self, clock: &RobotClock, input: Self::Input) -> CuResult<()> {
+    fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()> {
         if let Some(power) = input.payload() {
             if self.dryrun {
                 debug!(
@@ -169,7 +170,7 @@ impl<'cl> CuSinkTask<'cl> for SN754410 {
                 } else {
                     self.stop()?;
                 }
-                self.last_update = clock.now();
+                self.last_update = ctx.now();
             } else {
                 debug!("Power is the same {}, skipping.", deadzone_compensated);
             }
@@ -184,8 +185,8 @@ impl<'cl> CuSinkTask<'cl> for SN754410 {
 
 pub mod test_support {
     use crate::MotorPayload;
-    use cu29::clock::RobotClock;
     use cu29::config::ComponentConfig;
+    use cu29::context::CuContext;
     use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
     use cu29::{output_msg, CuResult};
 
@@ -202,7 +203,7 @@ pub mod test_support {
     impl<'cl> CuSrcTask<'cl> for SN754410TestSrc {
         type Output = output_msg!('cl, MotorPayload);
 
-        fn process(&mut self, _clock: &RobotClock, _new_msg: Self::Output) -> CuResult<()> {
+        fn process(&mut self, _ctx: &CuContext, _new_msg: Self::Output) -> CuResult<()> {
             todo!()
         }
     }
@@ -1,6 +1,7 @@
 use bincode::{Decode, Encode};
 use cu29::clock::RobotClock;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
 use cu29::{output_msg, CuError, CuResult};
 use cu29_log_derive::debug;
@@ -124,14 +125,14 @@ fn read_adc(spi: &mut Spidev) -> io::Result<u16> {
 impl<'cl> CuSrcTask<'cl> for ADS7883 {
     type Output = output_msg!('cl, ADSReadingPayload);
 
-    fn process(&mut self, clock: &RobotClock, new_msg: Self::Output) -> CuResult<()> {
-        let bf = clock.now();
+    fn process(&mut self, ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+        let bf = ctx.now();
         let analog_value = read_adc(&mut self.spi).map_err(|e| {
             CuError::new_with_cause("Could not read the ADC value from the ADS7883", e)
         })?;
         // hard to know exactly when the value was read.
         // Should be within a couple of microseconds with the ioctl opverhead.
-        let af = clock.now();
+        let af = ctx.now();
         new_msg.metadata.tov = Some((af + bf) / 2u64).into();
 
         self.integrated_value = ((self.integrated_value + analog_value as u64)
@@ -143,7 +144,7 @@ impl<'cl> CuSrcTask<'cl> for ADS7883 {
             analog_value: result,
         };
         new_msg.set_payload(output);
-        new_msg.metadata.tov = ((clock.now() + bf) / 2u64).into();
+        new_msg.metadata.tov = ((ctx.now() + bf) / 2u64).into();
         new_msg.metadata.set_status(result);
         Ok(())
     }
@@ -168,7 +169,7 @@ pub mod test_support {
     impl<'cl> CuSinkTask<'cl> for ADS78883TestSink {
         type Input = input_msg!('cl, ADSReadingPayload);
 
-        fn process(&mut self, _clock: &RobotClock, new_msg: Self::Input) -> CuResult<()> {
+        fn process(&mut self, _ctx: &CuContext, new_msg: Self::Input) -> CuResult<()> {
             debug!("Received: {}", &new_msg.payload());
             Ok(())
         }
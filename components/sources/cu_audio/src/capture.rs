@@ -0,0 +1,132 @@
+use crate::{AudioConfig, SampleQueue};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, Stream};
+use cu29::clock::RobotClock;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
+use cu29::pool::CuPool;
+use cu29::{output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use cu_sensor_payloads::AudioFrame;
+use std::sync::{Arc, Mutex};
+
+/// Captures audio from an input device and emits fixed-size frames of `frame_size` samples per channel, stamped
+/// with the time of their first sample.
+///
+/// The audio thread of the device fills a queue of `buffer_ms` of audio, the copper loop emits at most one frame
+/// per iteration from it: it must run at least as often as the frames are produced or the oldest samples are
+/// dropped.
+pub struct AudioCapture {
+    config: AudioConfig,
+    device: Device,
+    stream: Option<Stream>,
+    queue: Arc<Mutex<SampleQueue>>,
+    /// The last error reported by the audio thread.
+    error: Arc<Mutex<Option<String>>>,
+    pool: CuPool<f32>,
+    dropped: u64,
+}
+
+impl Freezable for AudioCapture {}
+
+impl CuTaskLifecycle for AudioCapture {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let audio_config = AudioConfig::from_config(config, 200)?;
+        let get_u32 = |key: &str, default: u32| {
+            config
+                .and_then(|config| config.get::<u32>(key))
+                .unwrap_or(default)
+        };
+        let channels = audio_config.stream.channels;
+        // 20ms at 16kHz by default.
+        let frame_size = get_u32("frame_size", 320) as usize;
+        let pool_size = get_u32("pool_size", 8) as usize;
+        let device = audio_config.find_device(true)?;
+        let name = device.name().unwrap_or_default();
+        debug!("AudioCapture: capturing from {}.", name);
+        Ok(AudioCapture {
+            queue: Arc::new(Mutex::new(SampleQueue::new(
+                channels,
+                audio_config.stream.sample_rate.0,
+                audio_config.buffer_frames.max(frame_size),
+            ))),
+            config: audio_config,
+            device,
+            stream: None,
+            error: Arc::new(Mutex::new(None)),
+            pool: CuPool::new("audio_capture", pool_size, frame_size * channels as usize),
+            dropped: 0,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        let clock = clock.clone();
+        let queue = self.queue.clone();
+        let error = self.error.clone();
+        let stream = self
+            .device
+            .build_input_stream(
+                &self.config.stream,
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    queue.lock().unwrap().push(clock.now(), data);
+                },
+                move |e| *error.lock().unwrap() = Some(e.to_string()),
+                None,
+            )
+            .map_err(|e| CuError::new_with_cause("Could not open the audio input", e))?;
+        stream
+            .play()
+            .map_err(|e| CuError::new_with_cause("Could not start the audio input", e))?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.stream = None;
+        Ok(())
+    }
+}
+
+impl<'cl> CuSrcTask<'cl> for AudioCapture {
+    type Output = output_msg!('cl, AudioFrame);
+
+    fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+        if let Some(error) = self.error.lock().unwrap().take() {
+            return Err(format!("Audio input error: {}", error).into());
+        }
+        new_msg.clear_payload();
+        // If all the buffers are still held downstream, the samples wait in the queue.
+        let Some(mut samples) = self.pool.acquire() else {
+            new_msg.metadata.set_status("pool exhausted");
+            return Ok(());
+        };
+        let mut queue = self.queue.lock().unwrap();
+        if queue.dropped() != self.dropped {
+            debug!(
+                "AudioCapture: {} samples dropped, the loop is too slow.",
+                queue.dropped() - self.dropped
+            );
+            self.dropped = queue.dropped();
+        }
+        let Some(tov) = queue.pop(
+            samples
+                .get_mut()
+                .expect("A buffer fresh from the pool is not shared"),
+        ) else {
+            return Ok(());
+        };
+        drop(queue);
+        new_msg.set_payload(AudioFrame {
+            sample_rate: self.config.stream.sample_rate.0,
+            channels: self.config.stream.channels,
+            samples,
+        });
+        new_msg.metadata.tov = Some(tov).into();
+        Ok(())
+    }
+}
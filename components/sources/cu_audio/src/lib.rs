@@ -0,0 +1,72 @@
+mod capture;
+mod playback;
+mod queue;
+
+pub use capture::AudioCapture;
+pub use playback::AudioPlayback;
+pub use queue::SampleQueue;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{BufferSize, Device, SampleRate, StreamConfig};
+use cu29::config::ComponentConfig;
+use cu29::CuResult;
+use cu29_traits::CuError;
+
+/// The parameters of the stream common to the capture and the playback.
+struct AudioConfig {
+    device: Option<String>,
+    stream: StreamConfig,
+    /// Capacity of the queue between the audio thread and the copper loop, in samples per channel.
+    buffer_frames: usize,
+}
+
+impl AudioConfig {
+    fn from_config(config: Option<&ComponentConfig>, default_buffer_ms: u32) -> CuResult<Self> {
+        let get_u32 = |key: &str, default: u32| {
+            config
+                .and_then(|config| config.get::<u32>(key))
+                .unwrap_or(default)
+        };
+        let sample_rate = get_u32("sample_rate", 16000);
+        let channels = get_u32("channels", 1);
+        if sample_rate == 0 || channels == 0 || channels > u16::MAX as u32 {
+            return Err(format!(
+                "Invalid audio format: {} Hz, {} channels.",
+                sample_rate, channels
+            )
+            .into());
+        }
+        Ok(AudioConfig {
+            device: config.and_then(|config| config.get::<String>("device")),
+            stream: StreamConfig {
+                channels: channels as u16,
+                sample_rate: SampleRate(sample_rate),
+                buffer_size: BufferSize::Default,
+            },
+            buffer_frames: (get_u32("buffer_ms", default_buffer_ms) as u64 * sample_rate as u64
+                / 1000) as usize,
+        })
+    }
+
+    /// The device whose name contains the configured "device", or the default one.
+    fn find_device(&self, input: bool) -> CuResult<Device> {
+        let host = cpal::default_host();
+        let Some(name) = &self.device else {
+            let device = if input {
+                host.default_input_device()
+            } else {
+                host.default_output_device()
+            };
+            return device.ok_or_else(|| "No default audio device.".into());
+        };
+        let mut devices = if input {
+            host.input_devices()
+        } else {
+            host.output_devices()
+        }
+        .map_err(|e| CuError::new_with_cause("Could not list the audio devices", e))?;
+        devices
+            .find(|device| device.name().is_ok_and(|n| n.contains(name.as_str())))
+            .ok_or_else(|| format!("No audio device matching '{}'.", name).into())
+    }
+}
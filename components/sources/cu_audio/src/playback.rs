@@ -0,0 +1,100 @@
+use crate::{AudioConfig, SampleQueue};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, Stream};
+use cu29::clock::RobotClock;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSinkTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use cu_sensor_payloads::AudioFrame;
+use std::sync::{Arc, Mutex};
+
+/// Plays the received audio frames on an output device, for alerts or speech.
+///
+/// The frames are queued and played as soon as possible, silence is played when the queue is empty. When more
+/// than `buffer_ms` of audio is queued, the oldest samples are dropped. The frames must have the sample rate and
+/// the number of channels of the config, they are not resampled.
+pub struct AudioPlayback {
+    config: AudioConfig,
+    device: Device,
+    stream: Option<Stream>,
+    queue: Arc<Mutex<SampleQueue>>,
+    /// The last error reported by the audio thread.
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Freezable for AudioPlayback {}
+
+impl CuTaskLifecycle for AudioPlayback {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let audio_config = AudioConfig::from_config(config, 2000)?;
+        let device = audio_config.find_device(false)?;
+        let name = device.name().unwrap_or_default();
+        debug!("AudioPlayback: playing on {}.", name);
+        Ok(AudioPlayback {
+            queue: Arc::new(Mutex::new(SampleQueue::new(
+                audio_config.stream.channels,
+                audio_config.stream.sample_rate.0,
+                audio_config.buffer_frames,
+            ))),
+            config: audio_config,
+            device,
+            stream: None,
+            error: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        let queue = self.queue.clone();
+        let error = self.error.clone();
+        let stream = self
+            .device
+            .build_output_stream(
+                &self.config.stream,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    queue.lock().unwrap().pop_or_silence(data);
+                },
+                move |e| *error.lock().unwrap() = Some(e.to_string()),
+                None,
+            )
+            .map_err(|e| CuError::new_with_cause("Could not open the audio output", e))?;
+        stream
+            .play()
+            .map_err(|e| CuError::new_with_cause("Could not start the audio output", e))?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.stream = None;
+        Ok(())
+    }
+}
+
+impl<'cl> CuSinkTask<'cl> for AudioPlayback {
+    type Input = input_msg!('cl, AudioFrame);
+
+    fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+        if let Some(error) = self.error.lock().unwrap().take() {
+            return Err(format!("Audio output error: {}", error).into());
+        }
+        let Some(frame) = input.payload() else {
+            return Ok(());
+        };
+        let stream = &self.config.stream;
+        if frame.sample_rate != stream.sample_rate.0 || frame.channels != stream.channels {
+            return Err(format!(
+                "Got a frame at {} Hz with {} channels, the output is at {} Hz with {} channels.",
+                frame.sample_rate, frame.channels, stream.sample_rate.0, stream.channels
+            )
+            .into());
+        }
+        self.queue.lock().unwrap().push(ctx.now(), &frame.samples);
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! The buffer between the audio thread and the copper loop.
+
+use cu29::clock::CuTime;
+use std::collections::VecDeque;
+
+/// A bounded queue of interleaved samples keeping the time of its first sample.
+/// When it is full, the oldest samples are dropped.
+pub struct SampleQueue {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    channels: usize,
+    sample_rate: u32,
+    /// Time of the first sample of the queue in nanoseconds, None when it is empty.
+    front_time: Option<u64>,
+    dropped: u64,
+}
+
+impl SampleQueue {
+    /// A queue of at most capacity_frames samples per channel, allocated upfront.
+    pub fn new(channels: u16, sample_rate: u32, capacity_frames: usize) -> Self {
+        let capacity = capacity_frames * channels as usize;
+        SampleQueue {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            channels: channels as usize,
+            sample_rate,
+            front_time: None,
+            dropped: 0,
+        }
+    }
+
+    fn duration_ns(&self, samples: usize) -> u64 {
+        (samples / self.channels) as u64 * 1_000_000_000 / self.sample_rate as u64
+    }
+
+    /// Appends the samples, the last one being captured at now.
+    pub fn push(&mut self, now: CuTime, samples: &[f32]) {
+        let now: u64 = now.into();
+        if self.front_time.is_none() {
+            self.front_time = Some(now.saturating_sub(self.duration_ns(samples.len())));
+        }
+        let excess = (self.samples.len() + samples.len()).saturating_sub(self.capacity);
+        // Keep whole frames to stay aligned on the channels.
+        let excess = excess.div_ceil(self.channels) * self.channels;
+        let from_queue = excess.min(self.samples.len());
+        self.samples.drain(..from_queue);
+        self.front_time = self.front_time.map(|t| t + self.duration_ns(excess));
+        self.dropped += excess as u64;
+        self.samples
+            .extend(&samples[(excess - from_queue).min(samples.len())..]);
+        if self.samples.is_empty() {
+            self.front_time = None;
+        }
+    }
+
+    /// Fills out with the oldest samples if there are enough, returns the time of the first one.
+    pub fn pop(&mut self, out: &mut [f32]) -> Option<CuTime> {
+        if self.samples.len() < out.len() {
+            return None;
+        }
+        let time = self.front_time?;
+        let len = out.len();
+        for (o, s) in out.iter_mut().zip(self.samples.drain(..len)) {
+            *o = s;
+        }
+        self.front_time = if self.samples.is_empty() {
+            None
+        } else {
+            Some(time + self.duration_ns(out.len()))
+        };
+        Some(CuTime::from(time))
+    }
+
+    /// Fills out with the oldest samples and silence for what is missing. Returns the number of missing samples.
+    pub fn pop_or_silence(&mut self, out: &mut [f32]) -> usize {
+        let available = self.samples.len().min(out.len());
+        for (o, s) in out.iter_mut().zip(self.samples.drain(..available)) {
+            *o = s;
+        }
+        out[available..].fill(0.0);
+        self.front_time = self
+            .front_time
+            .filter(|_| !self.samples.is_empty())
+            .map(|t| t + self.duration_ns(available));
+        out.len() - available
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Number of samples dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamps() {
+        // Stereo at 1kHz: a frame per millisecond.
+        let mut queue = SampleQueue::new(2, 1000, 8);
+        queue.push(CuTime::from(10_000_000), &[1.0, -1.0, 2.0, -2.0]);
+        queue.push(CuTime::from(12_000_000), &[3.0, -3.0, 4.0, -4.0]);
+        let mut out = [0.0; 6];
+        assert_eq!(queue.pop(&mut out), Some(CuTime::from(8_000_000)));
+        assert_eq!(out, [1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+        assert_eq!(queue.pop(&mut out), None);
+        assert_eq!(queue.len(), 2);
+        let mut out = [0.0; 2];
+        assert_eq!(queue.pop(&mut out), Some(CuTime::from(11_000_000)));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_overflow_drops_the_oldest() {
+        let mut queue = SampleQueue::new(1, 1000, 3);
+        queue.push(CuTime::from(2_000_000), &[1.0, 2.0]);
+        queue.push(CuTime::from(4_000_000), &[3.0, 4.0]);
+        assert_eq!(queue.dropped(), 1);
+        let mut out = [0.0; 3];
+        assert_eq!(queue.pop(&mut out), Some(CuTime::from(1_000_000)));
+        assert_eq!(out, [2.0, 3.0, 4.0]);
+
+        // More than the capacity at once.
+        queue.push(CuTime::from(10_000_000), &[5.0, 6.0, 7.0, 8.0, 9.0]);
+        assert_eq!(queue.pop(&mut out), Some(CuTime::from(7_000_000)));
+        assert_eq!(out, [7.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn test_pop_or_silence() {
+        let mut queue = SampleQueue::new(1, 1000, 4);
+        queue.push(CuTime::from(0), &[1.0, 2.0]);
+        let mut out = [9.0; 3];
+        assert_eq!(queue.pop_or_silence(&mut out), 1);
+        assert_eq!(out, [1.0, 2.0, 0.0]);
+    }
+}
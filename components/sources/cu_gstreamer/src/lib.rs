@@ -0,0 +1,108 @@
+mod sink;
+mod source;
+
+pub use sink::GStreamerSink;
+pub use source::GStreamerSource;
+
+use cu29::CuResult;
+use cu29_traits::CuError;
+use cu_sensor_payloads::ImageFormat;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_video::VideoFormat;
+
+/// Name of the appsink or appsrc element connecting a pipeline to Copper.
+pub const ELEMENT_NAME: &str = "copper";
+
+/// The GStreamer format of the pixels of an image.
+pub fn video_format(format: ImageFormat) -> VideoFormat {
+    match format {
+        ImageFormat::Mono8 => VideoFormat::Gray8,
+        ImageFormat::Rgb8 => VideoFormat::Rgb,
+        ImageFormat::Bgr8 => VideoFormat::Bgr,
+        ImageFormat::Rgba8 => VideoFormat::Rgba,
+        ImageFormat::Yuyv => VideoFormat::Yuy2,
+    }
+}
+
+/// The image format of a GStreamer format, None if it has no equivalent.
+pub fn image_format(format: VideoFormat) -> Option<ImageFormat> {
+    match format {
+        VideoFormat::Gray8 => Some(ImageFormat::Mono8),
+        VideoFormat::Rgb => Some(ImageFormat::Rgb8),
+        VideoFormat::Bgr => Some(ImageFormat::Bgr8),
+        VideoFormat::Rgba => Some(ImageFormat::Rgba8),
+        VideoFormat::Yuy2 => Some(ImageFormat::Yuyv),
+        _ => None,
+    }
+}
+
+/// Parses a pipeline description and finds its element named [ELEMENT_NAME].
+fn launch(description: &str) -> CuResult<(gst::Pipeline, gst::Element)> {
+    gst::init().map_err(|e| CuError::new_with_cause("Could not initialize GStreamer", e))?;
+    let pipeline = gst::parse::launch(description)
+        .map_err(|e| CuError::new_with_cause(&format!("Invalid pipeline '{}'", description), e))?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| CuError::from(format!("'{}' is not a pipeline.", description)))?;
+    let element = pipeline.by_name(ELEMENT_NAME).ok_or_else(|| {
+        CuError::from(format!(
+            "The pipeline '{}' has no element named '{}'.",
+            description, ELEMENT_NAME
+        ))
+    })?;
+    Ok((pipeline, element))
+}
+
+fn set_state(pipeline: &gst::Pipeline, state: gst::State) -> CuResult<()> {
+    pipeline.set_state(state).map_err(|e| {
+        CuError::new_with_cause(&format!("Could not set the pipeline to {:?}", state), e)
+    })?;
+    Ok(())
+}
+
+/// Reports the first error posted on the bus of the pipeline, if any.
+fn check_bus(pipeline: &gst::Pipeline) -> CuResult<()> {
+    let Some(bus) = pipeline.bus() else {
+        return Ok(());
+    };
+    while let Some(message) = bus.pop_filtered(&[gst::MessageType::Error, gst::MessageType::Eos]) {
+        match message.view() {
+            gst::MessageView::Error(error) => {
+                return Err(format!(
+                    "GStreamer error from {}: {} ({:?})",
+                    error
+                        .src()
+                        .map(|src| src.path_string().to_string())
+                        .unwrap_or_default(),
+                    error.error(),
+                    error.debug()
+                )
+                .into())
+            }
+            gst::MessageView::Eos(_) => {
+                return Err("The GStreamer pipeline reached its end.".into())
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_round_trip() {
+        for format in [
+            ImageFormat::Mono8,
+            ImageFormat::Rgb8,
+            ImageFormat::Bgr8,
+            ImageFormat::Rgba8,
+            ImageFormat::Yuyv,
+        ] {
+            assert_eq!(image_format(video_format(format)), Some(format));
+        }
+        assert_eq!(image_format(VideoFormat::Nv12), None);
+    }
+}
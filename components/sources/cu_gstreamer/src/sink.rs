@@ -0,0 +1,242 @@
+use crate::{check_bus, launch, set_state, video_format, ELEMENT_NAME};
+use cu29::clock::RobotClock;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSinkTask, CuTaskLifecycle, Freezable};
+use cu29::threads::spawn_task_thread;
+use cu29::{input_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use cu_sensor_payloads::Image;
+use gstreamer as gst;
+use gstreamer::glib;
+use gstreamer::prelude::*;
+use gstreamer_app::AppSrc;
+use gstreamer_rtsp_server::prelude::*;
+use gstreamer_rtsp_server::{RTSPMediaFactory, RTSPServer};
+use gstreamer_video::VideoInfo;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// The default H.264 encoder of the RTSP stream. On a Jetson, `nvv4l2h264enc` uses the hardware encoder, on a
+/// Raspberry Pi `v4l2h264enc`.
+const DEFAULT_ENCODER: &str = "x264enc tune=zerolatency speed-preset=ultrafast";
+
+/// Where the frames go.
+enum Output {
+    /// A pipeline from the config starting with an `appsrc name=copper`.
+    Pipeline(gst::Pipeline),
+    /// An RTSP server: its pipeline is created when the first client connects, shared by all the clients.
+    Rtsp {
+        main_loop: glib::MainLoop,
+        thread: Option<JoinHandle<()>>,
+    },
+}
+
+/// Pushes the images into a GStreamer pipeline, typically to encode and stream or record them.
+///
+/// With a `pipeline` in the config, it must start with an `appsrc name=copper`, for example
+/// `appsrc name=copper ! videoconvert ! x264enc tune=zerolatency ! rtph264pay ! udpsink host=10.0.0.2 port=5000`.
+/// Otherwise the images are served as an H.264 RTSP stream on `rtsp://<host>:<rtsp_port><rtsp_path>`, encoded by
+/// the `encoder` element.
+pub struct GStreamerSink {
+    output: Output,
+    /// None until the pipeline exists.
+    appsrc: Arc<Mutex<Option<AppSrc>>>,
+    /// The layout of the last frame and its caps, the caps of the appsrc are updated when it changes.
+    frame: Option<(VideoInfo, gst::Caps)>,
+}
+
+impl Freezable for GStreamerSink {}
+
+fn configure_appsrc(appsrc: &AppSrc) {
+    appsrc.set_format(gst::Format::Time);
+    appsrc.set_is_live(true);
+    appsrc.set_do_timestamp(true);
+}
+
+/// The description of the pipeline of the RTSP stream.
+fn rtsp_launch(encoder: &str) -> String {
+    format!(
+        "( appsrc name={} ! videoconvert ! {} ! rtph264pay name=pay0 pt=96 )",
+        ELEMENT_NAME, encoder
+    )
+}
+
+impl CuTaskLifecycle for GStreamerSink {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("GStreamerSink needs a config.")?;
+        let appsrc = Arc::new(Mutex::new(None));
+        let output = match config.get::<String>("pipeline") {
+            Some(description) => {
+                let (pipeline, element) = launch(&description)?;
+                let src = element.dynamic_cast::<AppSrc>().map_err(|_| {
+                    CuError::from("The 'copper' element of the pipeline is not an appsrc.")
+                })?;
+                configure_appsrc(&src);
+                *appsrc.lock().unwrap() = Some(src);
+                Output::Pipeline(pipeline)
+            }
+            None => {
+                gst::init()
+                    .map_err(|e| CuError::new_with_cause("Could not initialize GStreamer", e))?;
+                let port = config.get::<u32>("rtsp_port").unwrap_or(8554);
+                let path = config
+                    .get::<String>("rtsp_path")
+                    .unwrap_or("/copper".to_string());
+                let encoder = config
+                    .get::<String>("encoder")
+                    .unwrap_or(DEFAULT_ENCODER.to_string());
+
+                let server = RTSPServer::new();
+                server.set_service(&port.to_string());
+                let factory = RTSPMediaFactory::new();
+                factory.set_launch(&rtsp_launch(&encoder));
+                factory.set_shared(true);
+                let shared = appsrc.clone();
+                factory.connect_media_configure(move |_, media| {
+                    let src = media
+                        .element()
+                        .dynamic_cast_ref::<gst::Bin>()
+                        .and_then(|bin| bin.by_name_recurse_up(ELEMENT_NAME))
+                        .and_then(|element| element.dynamic_cast::<AppSrc>().ok());
+                    if let Some(src) = src {
+                        configure_appsrc(&src);
+                        *shared.lock().unwrap() = Some(src);
+                    }
+                });
+                server
+                    .mount_points()
+                    .ok_or("The RTSP server has no mount points.")?
+                    .add_factory(&path, factory);
+                server
+                    .attach(None)
+                    .map_err(|e| CuError::new_with_cause("Could not start the RTSP server", e))?;
+                debug!(
+                    "GStreamerSink: serving rtsp://0.0.0.0:{}{}",
+                    port,
+                    path.clone()
+                );
+                Output::Rtsp {
+                    main_loop: glib::MainLoop::new(None, false),
+                    thread: None,
+                }
+            }
+        };
+        Ok(GStreamerSink {
+            output,
+            appsrc,
+            frame: None,
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        match &mut self.output {
+            Output::Pipeline(pipeline) => set_state(pipeline, gst::State::Playing),
+            Output::Rtsp { main_loop, thread } => {
+                let main_loop = main_loop.clone();
+                *thread = Some(spawn_task_thread("gstreamer_rtsp", move || {
+                    main_loop.run()
+                })?);
+                Ok(())
+            }
+        }
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        if let Some(appsrc) = self.appsrc.lock().unwrap().as_ref() {
+            let _ = appsrc.end_of_stream();
+        }
+        match &mut self.output {
+            Output::Pipeline(pipeline) => set_state(pipeline, gst::State::Null),
+            Output::Rtsp { main_loop, thread } => {
+                main_loop.quit();
+                if let Some(thread) = thread.take() {
+                    thread
+                        .join()
+                        .map_err(|_| CuError::from("The RTSP server thread panicked."))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'cl> CuSinkTask<'cl> for GStreamerSink {
+    type Input = input_msg!('cl, Image);
+
+    fn process(&mut self, _ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+        if let Output::Pipeline(pipeline) = &self.output {
+            check_bus(pipeline)?;
+        }
+        let Some(image) = input.payload() else {
+            return Ok(());
+        };
+        let mut appsrc = self.appsrc.lock().unwrap();
+        // No RTSP client yet.
+        let Some(src) = appsrc.as_ref() else {
+            return Ok(());
+        };
+
+        let format = video_format(image.format);
+        if self.frame.as_ref().is_none_or(|(info, _)| {
+            info.width() != image.width || info.height() != image.height || info.format() != format
+        }) {
+            let info = VideoInfo::builder(format, image.width, image.height)
+                .build()
+                .map_err(|e| CuError::new_with_cause("Invalid image size", e))?;
+            let caps = info
+                .to_caps()
+                .map_err(|e| CuError::new_with_cause("Invalid image caps", e))?;
+            self.frame = Some((info, caps));
+        }
+        let (info, caps) = self.frame.as_ref().expect("Set above");
+        // Also set for the appsrc of a new RTSP client.
+        if src.caps().as_ref() != Some(caps) {
+            src.set_caps(Some(caps));
+        }
+
+        // GStreamer expects its own row alignment, copy row by row.
+        let row = image.width as usize * image.format.bytes_per_pixel();
+        let stride = info.stride()[0] as usize;
+        let mut buffer = gst::Buffer::with_size(info.size())
+            .map_err(|e| CuError::new_with_cause("Could not allocate a frame", e))?;
+        {
+            let mut map = buffer
+                .get_mut()
+                .expect("A new buffer is not shared")
+                .map_writable()
+                .map_err(|e| CuError::new_with_cause("Could not map the frame", e))?;
+            for y in 0..image.height as usize {
+                let from = y * image.stride as usize;
+                map[y * stride..y * stride + row].copy_from_slice(&image.data[from..from + row]);
+            }
+        }
+        if let Err(e) = src.push_buffer(buffer) {
+            match self.output {
+                Output::Pipeline(_) => {
+                    return Err(CuError::new_with_cause("Could not push a frame", e))
+                }
+                // The clients are gone, wait for the next one to create a new pipeline.
+                Output::Rtsp { .. } => *appsrc = None,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtsp_launch() {
+        assert_eq!(
+            rtsp_launch("nvv4l2h264enc"),
+            "( appsrc name=copper ! videoconvert ! nvv4l2h264enc ! rtph264pay name=pay0 pt=96 )"
+        );
+    }
+}
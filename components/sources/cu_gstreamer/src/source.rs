@@ -0,0 +1,135 @@
+use crate::{check_bus, image_format, launch, set_state};
+use cu29::clock::RobotClock;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
+use cu29::pool::CuPool;
+use cu29::{output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use cu_sensor_payloads::Image;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app::AppSink;
+use gstreamer_video::{VideoCapsBuilder, VideoFormat, VideoInfo};
+
+/// Pulls the frames of an arbitrary GStreamer pipeline ending with an `appsink name=copper`, for example
+/// `v4l2src ! videoconvert ! appsink name=copper` or `nvarguscamerasrc ! nvvidconv ! appsink name=copper`.
+///
+/// The appsink only keeps the latest frame: if the copper loop is slower than the camera, frames are dropped
+/// instead of queued. Each frame is copied into a buffer of a pool of `pool_size` buffers, allocated when the size
+/// of the frames is known.
+pub struct GStreamerSource {
+    pipeline: gst::Pipeline,
+    appsink: AppSink,
+    pool: Option<CuPool<u8>>,
+    pool_size: usize,
+}
+
+impl Freezable for GStreamerSource {}
+
+impl CuTaskLifecycle for GStreamerSource {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("GStreamerSource needs a config.")?;
+        let description = config
+            .get::<String>("pipeline")
+            .ok_or("GStreamerSource needs a 'pipeline'.")?;
+        let (pipeline, element) = launch(&description)?;
+        let appsink = element.dynamic_cast::<AppSink>().map_err(|_| {
+            CuError::from("The 'copper' element of the pipeline is not an appsink.")
+        })?;
+        // Let the pipeline negotiate one of the formats an Image can hold.
+        appsink.set_caps(Some(
+            &VideoCapsBuilder::new()
+                .format_list([
+                    VideoFormat::Gray8,
+                    VideoFormat::Rgb,
+                    VideoFormat::Bgr,
+                    VideoFormat::Rgba,
+                    VideoFormat::Yuy2,
+                ])
+                .build(),
+        ));
+        appsink.set_max_buffers(1);
+        appsink.set_drop(true);
+        appsink.set_sync(false);
+        Ok(GStreamerSource {
+            pipeline,
+            appsink,
+            pool: None,
+            pool_size: config.get::<u32>("pool_size").unwrap_or(4) as usize,
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        set_state(&self.pipeline, gst::State::Playing)
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        set_state(&self.pipeline, gst::State::Null)
+    }
+}
+
+impl<'cl> CuSrcTask<'cl> for GStreamerSource {
+    type Output = output_msg!('cl, Image);
+
+    fn process(&mut self, ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+        check_bus(&self.pipeline)?;
+        new_msg.clear_payload();
+        // Never block the copper loop waiting for a frame.
+        let Some(sample) = self.appsink.try_pull_sample(gst::ClockTime::ZERO) else {
+            return Ok(());
+        };
+        let tov = ctx.now();
+        let info = sample
+            .caps()
+            .ok_or("A sample without caps.")
+            .and_then(|caps| VideoInfo::from_caps(caps).map_err(|_| "Invalid video caps."))?;
+        let format = image_format(info.format())
+            .ok_or_else(|| CuError::from(format!("Unsupported format {:?}.", info.format())))?;
+        let stride = info.stride()[0] as usize;
+        let len = stride * info.height() as usize;
+
+        if self
+            .pool
+            .as_ref()
+            .is_none_or(|pool| pool.stats().buffer_len != len)
+        {
+            debug!(
+                "GStreamerSource: allocating {} buffers of {} bytes.",
+                self.pool_size, len
+            );
+            self.pool = Some(CuPool::new("gstreamer", self.pool_size, len));
+        }
+        // If all the buffers are still held downstream, drop this frame.
+        let Some(mut data) = self.pool.as_ref().and_then(CuPool::acquire) else {
+            new_msg.metadata.set_status("pool exhausted");
+            return Ok(());
+        };
+        let buffer = sample.buffer().ok_or("A sample without buffer.")?;
+        let map = buffer
+            .map_readable()
+            .map_err(|e| CuError::new_with_cause("Could not map the frame", e))?;
+        if map.len() < len {
+            return Err(format!("Got a frame of {} bytes, expected {}.", map.len(), len).into());
+        }
+        data.get_mut()
+            .expect("A buffer fresh from the pool is not shared")
+            .copy_from_slice(&map[..len]);
+
+        new_msg.set_payload(Image {
+            width: info.width(),
+            height: info.height(),
+            stride: stride as u32,
+            format,
+            data,
+            device: None,
+        });
+        // The time the frame was pulled: the latency of the pipeline is not accounted for.
+        new_msg.metadata.tov = Some(tov).into();
+        Ok(())
+    }
+}
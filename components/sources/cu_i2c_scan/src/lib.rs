@@ -0,0 +1,266 @@
+mod probe;
+
+pub use probe::{candidates, identify, scan, Signature, SCAN_ADDRESSES, SIGNATURES};
+
+use bincode::{Decode, Encode};
+use cu29::clock::{CuTime, RobotClock};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
+use cu29::threads::task_thread_name;
+use cu29::{output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use linux_embedded_hal::I2cdev;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// A device which answered on a bus.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct I2cDevice {
+    pub address: u8,
+    /// The known device recognized from its registers, see SIGNATURES.
+    pub device: Option<String>,
+    /// The known devices which can answer at this address.
+    pub candidates: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct I2cBusScan {
+    pub bus: String,
+    /// Why the bus could not be opened, ie. a missing device or permissions.
+    pub error: Option<String>,
+    pub devices: Vec<I2cDevice>,
+}
+
+/// The devices found on all the scanned buses.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct I2cScan {
+    pub buses: Vec<I2cBusScan>,
+}
+
+impl I2cScan {
+    pub fn device_count(&self) -> usize {
+        self.buses.iter().map(|bus| bus.devices.len()).sum()
+    }
+
+    /// The bus and the address of the known device, ie. "WT901".
+    pub fn find(&self, device: &str) -> Option<(&str, u8)> {
+        self.buses.iter().find_map(|bus| {
+            bus.devices
+                .iter()
+                .find(|d| d.device.as_deref() == Some(device))
+                .map(|d| (bus.bus.as_str(), d.address))
+        })
+    }
+}
+
+impl Display for I2cScan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for bus in &self.buses {
+            if let Some(error) = &bus.error {
+                writeln!(f, "{}: {}", bus.bus, error)?;
+                continue;
+            }
+            writeln!(f, "{}: {} devices", bus.bus, bus.devices.len())?;
+            for device in &bus.devices {
+                let identified = match (&device.device, device.candidates.as_slice()) {
+                    (Some(name), _) => name.clone(),
+                    (None, []) => "unknown".to_string(),
+                    (None, candidates) => format!("unknown, maybe {}", candidates.join(" or ")),
+                };
+                writeln!(f, "  0x{:02x}  {}", device.address, identified)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scans a Linux I2C bus, ie. "/dev/i2c-1".
+pub fn scan_bus(path: &str) -> I2cBusScan {
+    match I2cdev::new(path) {
+        Ok(mut bus) => I2cBusScan {
+            bus: path.to_string(),
+            error: None,
+            devices: scan(&mut bus),
+        },
+        Err(e) => I2cBusScan {
+            bus: path.to_string(),
+            error: Some(format!("cannot open the bus: {}", e)),
+            devices: Vec::new(),
+        },
+    }
+}
+
+pub fn scan_buses(paths: &[String]) -> I2cScan {
+    I2cScan {
+        buses: paths.iter().map(|path| scan_bus(path)).collect(),
+    }
+}
+
+/// The last scan and the time it was taken.
+type Latest = Arc<Mutex<Option<(I2cScan, CuTime)>>>;
+
+/// Scans the configured I2C buses and emits an `I2cScan` after every round with the addresses which answered and
+/// the known devices recognized at them, to check the wiring of a new robot from the log or the monitoring.
+///
+/// The scans run every `period_ms` in a background thread, a scan takes a while on a bus with nothing to ACK.
+/// Between two rounds the source emits nothing.
+pub struct I2cScanSource {
+    buses: Vec<String>,
+    period: Duration,
+    latest: Latest,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    device_count: Option<usize>,
+}
+
+impl Freezable for I2cScanSource {}
+
+impl CuTaskLifecycle for I2cScanSource {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("I2cScanSource needs a config.")?;
+        let buses: Vec<String> = config
+            .get::<String>("buses")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|bus| !bus.is_empty())
+            .map(str::to_string)
+            .collect();
+        if buses.is_empty() {
+            return Err("I2cScanSource needs some 'buses' to scan, ie. \"/dev/i2c-1\".".into());
+        }
+        let period_ms = config.get::<u32>("period_ms").unwrap_or(5000);
+        Ok(I2cScanSource {
+            buses,
+            period: Duration::from_millis(period_ms as u64),
+            latest: Arc::new(Mutex::new(None)),
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+            device_count: None,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.running.store(true, Ordering::Relaxed);
+        let (buses, period, clock) = (self.buses.clone(), self.period, clock.clone());
+        let (latest, running) = (self.latest.clone(), self.running.clone());
+        let thread = std::thread::Builder::new()
+            .name(task_thread_name("i2c_scan"))
+            .spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    let scan = scan_buses(&buses);
+                    *latest.lock().unwrap() = Some((scan, clock.now()));
+                    std::thread::park_timeout(period);
+                }
+            })
+            .map_err(|e| CuError::new_with_cause("Could not start the I2C scans", e))?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            thread
+                .join()
+                .map_err(|_| CuError::from("The I2C scan thread panicked."))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'cl> CuSrcTask<'cl> for I2cScanSource {
+    type Output = output_msg!('cl, I2cScan);
+
+    fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+        let Some((scan, tov)) = self.latest.lock().unwrap().take() else {
+            new_msg.clear_payload();
+            return Ok(());
+        };
+        let device_count = scan.device_count();
+        if self.device_count != Some(device_count) {
+            // A device appearing or disappearing is usually a loose wire.
+            let report = scan.to_string();
+            debug!("I2cScanSource: {} devices found.\n{}", device_count, report);
+            self.device_count = Some(device_count);
+        }
+        new_msg
+            .metadata
+            .set_status(format!("{} devices", device_count));
+        new_msg.set_payload(scan);
+        new_msg.metadata.tov = Some(tov).into();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emits_after_each_round() {
+        let mut config = ComponentConfig::new();
+        assert!(I2cScanSource::new(Some(&config)).is_err());
+        config.set("buses", "/dev/i2c-copper-test".to_string());
+        config.set("period_ms", 20u32);
+        let mut source = I2cScanSource::new(Some(&config)).unwrap();
+        let clock = RobotClock::default();
+        let mut msg = CuMsg::new(None);
+        source.start(&clock).unwrap();
+        for _ in 0..200 {
+            source.process(&CuContext::from(&clock), &mut msg).unwrap();
+            if msg.payload().is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        source.stop(&clock).unwrap();
+
+        let scan = msg.payload().expect("No scan results");
+        assert_eq!(scan.buses.len(), 1);
+        assert_eq!(scan.buses[0].bus, "/dev/i2c-copper-test");
+        assert!(scan.buses[0].error.is_some());
+        assert_eq!(scan.device_count(), 0);
+        assert!(scan
+            .to_string()
+            .starts_with("/dev/i2c-copper-test: cannot open"));
+    }
+
+    #[test]
+    fn test_report() {
+        let scan = I2cScan {
+            buses: vec![I2cBusScan {
+                bus: "/dev/i2c-1".to_string(),
+                error: None,
+                devices: vec![
+                    I2cDevice {
+                        address: 0x50,
+                        device: Some("WT901".to_string()),
+                        candidates: vec!["WT901".to_string()],
+                    },
+                    I2cDevice {
+                        address: 0x68,
+                        device: None,
+                        candidates: vec!["MPU6050".to_string(), "MPU9250".to_string()],
+                    },
+                ],
+            }],
+        };
+        assert_eq!(scan.find("WT901"), Some(("/dev/i2c-1", 0x50)));
+        assert_eq!(scan.find("BNO055"), None);
+        assert_eq!(
+            scan.to_string(),
+            "/dev/i2c-1: 2 devices\n  0x50  WT901\n  0x68  unknown, maybe MPU6050 or MPU9250\n"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use cu_i2c_scan::{scan_buses, SIGNATURES};
+
+/// Scans the given I2C buses, all the /dev/i2c-* ones by default, and prints the devices which answered.
+fn main() {
+    let mut buses: Vec<String> = std::env::args().skip(1).collect();
+    if buses.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("Usage: cu-i2c-scan [/dev/i2c-N...]");
+        println!("Scans the I2C buses, all of them by default, and identifies the known devices:");
+        let names: Vec<&str> = SIGNATURES.iter().map(|signature| signature.name).collect();
+        println!("{}", names.join(", "));
+        return;
+    }
+    if buses.is_empty() {
+        buses = std::fs::read_dir("/dev")
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path().display().to_string())
+                    .filter(|path| path.starts_with("/dev/i2c-"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        buses.sort();
+    }
+    if buses.is_empty() {
+        eprintln!("No I2C bus found, is the i2c-dev module loaded?");
+        std::process::exit(1);
+    }
+    print!("{}", scan_buses(&buses));
+}
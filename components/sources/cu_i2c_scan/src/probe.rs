@@ -0,0 +1,209 @@
+use crate::I2cDevice;
+use embedded_hal::i2c::I2c;
+
+/// The range of the addresses scanned, the others are reserved by the I2C specification.
+pub const SCAN_ADDRESSES: std::ops::RangeInclusive<u8> = 0x08..=0x77;
+
+/// How to recognize a device: the value of one of its registers after reset, usually an identification register.
+#[derive(Debug, Clone, Copy)]
+pub struct Signature {
+    pub name: &'static str,
+    /// The addresses the device can be configured at.
+    pub addresses: &'static [u8],
+    pub register: u8,
+    pub value: u8,
+}
+
+/// The devices recognized by the scan. The register read is harmless for all of them.
+pub const SIGNATURES: &[Signature] = &[
+    Signature {
+        name: "WT901",
+        addresses: &[0x50],
+        // IICADDR holds the address of the device.
+        register: 0x1A,
+        value: 0x50,
+    },
+    Signature {
+        name: "BNO055",
+        addresses: &[0x28, 0x29],
+        register: 0x00,
+        value: 0xA0,
+    },
+    Signature {
+        name: "VL53L0X",
+        addresses: &[0x29],
+        register: 0xC0,
+        value: 0xEE,
+    },
+    Signature {
+        name: "INA219",
+        addresses: &[
+            0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D,
+            0x4E, 0x4F,
+        ],
+        // High byte of the default configuration 0x399F.
+        register: 0x00,
+        value: 0x39,
+    },
+    Signature {
+        name: "PCA9685",
+        addresses: &[0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47],
+        // MODE1 after reset.
+        register: 0x00,
+        value: 0x11,
+    },
+    Signature {
+        name: "ADS1115",
+        addresses: &[0x48, 0x49, 0x4A, 0x4B],
+        // High byte of the default configuration 0x8583.
+        register: 0x01,
+        value: 0x85,
+    },
+    Signature {
+        name: "MPU6050",
+        addresses: &[0x68, 0x69],
+        register: 0x75,
+        value: 0x68,
+    },
+    Signature {
+        name: "MPU9250",
+        addresses: &[0x68, 0x69],
+        register: 0x75,
+        value: 0x71,
+    },
+    Signature {
+        name: "ICM20948",
+        addresses: &[0x68, 0x69],
+        register: 0x00,
+        value: 0xEA,
+    },
+    Signature {
+        name: "LSM6DS3",
+        addresses: &[0x6A, 0x6B],
+        register: 0x0F,
+        value: 0x69,
+    },
+    Signature {
+        name: "BMP280",
+        addresses: &[0x76, 0x77],
+        register: 0xD0,
+        value: 0x58,
+    },
+    Signature {
+        name: "BME280",
+        addresses: &[0x76, 0x77],
+        register: 0xD0,
+        value: 0x60,
+    },
+];
+
+/// The known devices which can answer at this address.
+pub fn candidates(address: u8) -> Vec<&'static str> {
+    SIGNATURES
+        .iter()
+        .filter(|signature| signature.addresses.contains(&address))
+        .map(|signature| signature.name)
+        .collect()
+}
+
+/// Reads the registers of the signatures of the address until one matches.
+pub fn identify<B: I2c>(bus: &mut B, address: u8) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .filter(|signature| signature.addresses.contains(&address))
+        .find(|signature| {
+            let mut value = [0u8];
+            bus.write_read(address, &[signature.register], &mut value)
+                .is_ok()
+                && value[0] == signature.value
+        })
+        .map(|signature| signature.name)
+}
+
+/// Probes every address of the bus with a read of one byte, like `i2cdetect -r`, and identifies the devices which
+/// answered.
+pub fn scan<B: I2c>(bus: &mut B) -> Vec<I2cDevice> {
+    let answering: Vec<u8> = SCAN_ADDRESSES
+        .filter(|&address| bus.read(address, &mut [0u8]).is_ok())
+        .collect();
+    answering
+        .into_iter()
+        .map(|address| I2cDevice {
+            address,
+            device: identify(bus, address).map(str::to_string),
+            candidates: candidates(address)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, Operation};
+    use std::collections::HashMap;
+
+    /// A bus with devices answering the value of their registers.
+    struct FakeBus {
+        devices: HashMap<u8, HashMap<u8, u8>>,
+    }
+
+    impl ErrorType for FakeBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for FakeBus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            let registers = self.devices.get(&address).ok_or(ErrorKind::Other)?;
+            let mut register = 0;
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => register = bytes[0],
+                    Operation::Read(buffer) => {
+                        buffer[0] = registers.get(&register).copied().unwrap_or(0xFF)
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut bus = FakeBus {
+            devices: HashMap::from([
+                (0x29, HashMap::from([(0xC0, 0xEE)])),
+                (0x40, HashMap::from([(0x00, 0x39)])),
+                (0x50, HashMap::from([(0x1A, 0x50)])),
+                (0x68, HashMap::from([(0x75, 0x42)])),
+                (0x10, HashMap::new()),
+            ]),
+        };
+        let devices = scan(&mut bus);
+        let found: Vec<(u8, Option<&str>)> = devices
+            .iter()
+            .map(|device| (device.address, device.device.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0x10, None),
+                (0x29, Some("VL53L0X")),
+                (0x40, Some("INA219")),
+                (0x50, Some("WT901")),
+                (0x68, None),
+            ]
+        );
+        assert_eq!(
+            devices[4].candidates,
+            vec!["MPU6050", "MPU9250", "ICM20948"]
+        );
+        assert!(devices[0].candidates.is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+mod probe;
+
+pub use probe::{interface_up, parse_wireless, Target};
+
+use bincode::{Decode, Encode};
+use cu29::clock::{CuDuration, CuTime, RobotClock};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
+use cu29::threads::task_thread_name;
+use cu29::{output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct InterfaceHealth {
+    pub name: String,
+    pub up: bool,
+    /// Link quality as reported by the driver, for the wireless interfaces.
+    pub link_quality: Option<f32>,
+    /// Signal level in dBm, for the wireless interfaces.
+    pub signal_dbm: Option<f32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct HostHealth {
+    pub host: String,
+    /// Round trip time, None if the host did not answer within the timeout.
+    pub rtt: Option<CuDuration>,
+}
+
+/// The state of the network at the time of the probes.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct NetworkHealth {
+    pub interfaces: Vec<InterfaceHealth>,
+    pub hosts: Vec<HostHealth>,
+}
+
+impl NetworkHealth {
+    /// All the monitored interfaces are up and all the hosts answered.
+    pub fn connected(&self) -> bool {
+        self.interfaces.iter().all(|interface| interface.up)
+            && self.hosts.iter().all(|host| host.rtt.is_some())
+    }
+
+    /// The worst round trip time, None if a host did not answer.
+    pub fn max_rtt(&self) -> Option<CuDuration> {
+        self.hosts
+            .iter()
+            .try_fold(CuDuration::default(), |max, host| {
+                host.rtt.map(|rtt| rtt.max(max))
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Probes {
+    interfaces: Vec<String>,
+    targets: Vec<Target>,
+    timeout: Duration,
+}
+
+impl Probes {
+    fn run(&self, sequence: u16) -> NetworkHealth {
+        let wireless = std::fs::read_to_string("/proc/net/wireless").unwrap_or_default();
+        NetworkHealth {
+            interfaces: self
+                .interfaces
+                .iter()
+                .map(|name| {
+                    let signal = parse_wireless(&wireless, name);
+                    InterfaceHealth {
+                        name: name.clone(),
+                        up: interface_up(Path::new("/sys"), name),
+                        link_quality: signal.map(|(quality, _)| quality),
+                        signal_dbm: signal.map(|(_, level)| level),
+                    }
+                })
+                .collect(),
+            hosts: self
+                .targets
+                .iter()
+                .map(|target| HostHealth {
+                    host: target.host().to_string(),
+                    rtt: target
+                        .probe(sequence, self.timeout)
+                        .map(|rtt| CuDuration::from(rtt.as_nanos() as u64)),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The last results of the probes and the time they were taken.
+type Latest = Arc<Mutex<Option<(NetworkHealth, CuTime)>>>;
+
+/// Monitors the state of network interfaces (including the signal of the wireless ones) and the round trip time
+/// to hosts, and emits a `NetworkHealth` after every round of probes, so the autonomy or the monitoring can react
+/// to a loss of connectivity.
+///
+/// The probes run every `period_ms` in a background thread so the copper loop never waits on the network.
+/// Between two rounds the source emits nothing.
+pub struct NetworkHealthSource {
+    probes: Probes,
+    period: Duration,
+    latest: Latest,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    connected: bool,
+}
+
+impl Freezable for NetworkHealthSource {}
+
+/// Splits a comma separated list.
+fn list(config: &ComponentConfig, key: &str) -> Vec<String> {
+    config
+        .get::<String>(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl CuTaskLifecycle for NetworkHealthSource {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("NetworkHealthSource needs a config.")?;
+        let targets = list(config, "hosts")
+            .iter()
+            .map(|host| Target::parse(host))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(CuError::from)?;
+        let interfaces = list(config, "interfaces");
+        if targets.is_empty() && interfaces.is_empty() {
+            return Err(
+                "NetworkHealthSource needs some 'interfaces' or 'hosts' to monitor.".into(),
+            );
+        }
+        let period_ms = config.get::<u32>("period_ms").unwrap_or(1000);
+        let timeout_ms = config.get::<u32>("timeout_ms").unwrap_or(period_ms / 2);
+        Ok(NetworkHealthSource {
+            probes: Probes {
+                interfaces,
+                targets,
+                timeout: Duration::from_millis(timeout_ms as u64),
+            },
+            period: Duration::from_millis(period_ms as u64),
+            latest: Arc::new(Mutex::new(None)),
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+            connected: true,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.running.store(true, Ordering::Relaxed);
+        let (probes, period, clock) = (self.probes.clone(), self.period, clock.clone());
+        let (latest, running) = (self.latest.clone(), self.running.clone());
+        let thread = std::thread::Builder::new()
+            .name(task_thread_name("network_health"))
+            .spawn(move || {
+                let mut sequence = 0u16;
+                while running.load(Ordering::Relaxed) {
+                    let health = probes.run(sequence);
+                    *latest.lock().unwrap() = Some((health, clock.now()));
+                    sequence = sequence.wrapping_add(1);
+                    std::thread::park_timeout(period);
+                }
+            })
+            .map_err(|e| CuError::new_with_cause("Could not start the network probes", e))?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            thread
+                .join()
+                .map_err(|_| CuError::from("The network probes thread panicked."))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'cl> CuSrcTask<'cl> for NetworkHealthSource {
+    type Output = output_msg!('cl, NetworkHealth);
+
+    fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+        let Some((health, tov)) = self.latest.lock().unwrap().take() else {
+            new_msg.clear_payload();
+            return Ok(());
+        };
+        let connected = health.connected();
+        if connected != self.connected {
+            let state = if connected { "restored" } else { "lost" };
+            debug!("NetworkHealthSource: connectivity {}.", state);
+            self.connected = connected;
+        }
+        let answered = health
+            .hosts
+            .iter()
+            .filter(|host| host.rtt.is_some())
+            .count();
+        new_msg
+            .metadata
+            .set_status(format!("{}/{} hosts", answered, health.hosts.len()));
+        new_msg.set_payload(health);
+        new_msg.metadata.tov = Some(tov).into();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_emits_after_each_round() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut config = ComponentConfig::new();
+        config.set("hosts", format!("127.0.0.1:{}, 127.0.0.1:1", port));
+        config.set("period_ms", 20u32);
+        let mut source = NetworkHealthSource::new(Some(&config)).unwrap();
+        let clock = RobotClock::default();
+        let mut msg = CuMsg::new(None);
+        source.start(&clock).unwrap();
+        for _ in 0..200 {
+            source.process(&CuContext::from(&clock), &mut msg).unwrap();
+            if msg.payload().is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        source.stop(&clock).unwrap();
+
+        let health = msg.payload().expect("No probe results");
+        assert_eq!(health.hosts.len(), 2);
+        assert!(health.hosts[0].rtt.is_some());
+        assert!(health.hosts[1].rtt.is_none());
+        assert!(!health.connected());
+        assert_eq!(health.max_rtt(), None);
+    }
+
+    #[test]
+    fn test_config() {
+        let mut config = ComponentConfig::new();
+        assert!(NetworkHealthSource::new(Some(&config)).is_err());
+        config.set("hosts", "base:ssh".to_string());
+        assert!(NetworkHealthSource::new(Some(&config)).is_err());
+        config.set("hosts", "10.0.0.1, base:22".to_string());
+        config.set("interfaces", "wlan0".to_string());
+        let source = NetworkHealthSource::new(Some(&config)).unwrap();
+        assert_eq!(source.probes.targets.len(), 2);
+        assert_eq!(source.probes.interfaces, vec!["wlan0"]);
+    }
+}
@@ -0,0 +1,182 @@
+//! The probes of the interfaces and the hosts, Linux only.
+
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io::Read;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How the reachability of a host is checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// An ICMP echo, through an unprivileged ICMP socket (see net.ipv4.ping_group_range).
+    Ping(String),
+    /// A TCP connection to a port, when ICMP is filtered or not allowed.
+    Tcp(String, u16),
+}
+
+impl Target {
+    /// Parses "host" as a ping and "host:port" as a TCP connection. IPv6 addresses go in brackets: "[::1]:22".
+    pub fn parse(target: &str) -> Result<Self, String> {
+        let target = target.trim();
+        if target.is_empty() {
+            return Err("Empty host.".to_string());
+        }
+        if let Ok(addr) = target.parse::<SocketAddr>() {
+            return Ok(Target::Tcp(addr.ip().to_string(), addr.port()));
+        }
+        match target.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => port
+                .parse()
+                .map(|port| Target::Tcp(host.to_string(), port))
+                .map_err(|_| format!("Invalid port in '{}'.", target)),
+            _ => Ok(Target::Ping(
+                target
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .to_string(),
+            )),
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        match self {
+            Target::Ping(host) | Target::Tcp(host, _) => host,
+        }
+    }
+
+    /// The round trip time, None if the host did not answer within the timeout.
+    pub fn probe(&self, sequence: u16, timeout: Duration) -> Option<Duration> {
+        match self {
+            Target::Ping(host) => {
+                let addr = (host.as_str(), 0).to_socket_addrs().ok()?.next()?;
+                ping(addr, sequence, timeout)
+            }
+            Target::Tcp(host, port) => {
+                let addr = (host.as_str(), *port).to_socket_addrs().ok()?.next()?;
+                let start = Instant::now();
+                TcpStream::connect_timeout(&addr, timeout).ok()?;
+                Some(start.elapsed())
+            }
+        }
+    }
+}
+
+/// Sends an ICMP echo request and waits for its reply.
+fn ping(addr: SocketAddr, sequence: u16, timeout: Duration) -> Option<Duration> {
+    let (domain, protocol, request, reply) = match addr {
+        SocketAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4, 8, 0),
+        SocketAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6, 128, 129),
+    };
+    let mut socket = Socket::new(domain, Type::DGRAM, Some(protocol)).ok()?;
+    // The kernel sets the identifier and the checksum of the datagram ICMP sockets.
+    let [seq_hi, seq_lo] = sequence.to_be_bytes();
+    let packet = [
+        request, 0, 0, 0, 0, 0, seq_hi, seq_lo, b'c', b'u', b'2', b'9',
+    ];
+    let start = Instant::now();
+    socket.send_to(&packet, &SockAddr::from(addr)).ok()?;
+    let mut buffer = [0u8; 64];
+    loop {
+        let remaining = timeout.checked_sub(start.elapsed())?;
+        socket.set_read_timeout(Some(remaining)).ok()?;
+        let len = socket.read(&mut buffer).ok()?;
+        // Skip the replies to the previous probes that timed out.
+        if len >= 8 && buffer[0] == reply && buffer[6..8] == [seq_hi, seq_lo] {
+            return Some(start.elapsed());
+        }
+    }
+}
+
+/// Whether the interface is up, from its state in sys_root/class/net.
+pub fn interface_up(sys_root: &Path, name: &str) -> bool {
+    let dir = sys_root.join("class/net").join(name);
+    let read = |file: &str| std::fs::read_to_string(dir.join(file)).unwrap_or_default();
+    match read("operstate").trim() {
+        "up" => true,
+        // Virtual interfaces (loopback, tunnels) do not report their state.
+        "unknown" => read("carrier").trim() == "1",
+        _ => false,
+    }
+}
+
+/// The link quality and the signal level in dBm of a wireless interface from the content of /proc/net/wireless.
+pub fn parse_wireless(content: &str, name: &str) -> Option<(f32, f32)> {
+    content.lines().skip(2).find_map(|line| {
+        let (interface, values) = line.split_once(':')?;
+        if interface.trim() != name {
+            return None;
+        }
+        // status, link quality, signal level, noise... the values may end with a '.' when they were updated.
+        let mut values = values
+            .split_whitespace()
+            .skip(1)
+            .map(|v| v.trim_end_matches('.').parse::<f32>());
+        Some((values.next()?.ok()?, values.next()?.ok()?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            Target::parse("10.0.0.1"),
+            Ok(Target::Ping("10.0.0.1".to_string()))
+        );
+        assert_eq!(
+            Target::parse(" base.local:22 "),
+            Ok(Target::Tcp("base.local".to_string(), 22))
+        );
+        assert_eq!(
+            Target::parse("[::1]:8080"),
+            Ok(Target::Tcp("::1".to_string(), 8080))
+        );
+        assert_eq!(Target::parse("::1"), Ok(Target::Ping("::1".to_string())));
+        assert!(Target::parse("host:port").is_err());
+    }
+
+    #[test]
+    fn test_tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let timeout = Duration::from_millis(500);
+        let target = Target::Tcp("127.0.0.1".to_string(), port);
+        assert!(target.probe(0, timeout).is_some());
+        drop(listener);
+        assert!(target.probe(1, timeout).is_none());
+    }
+
+    #[test]
+    fn test_parse_wireless() {
+        let content =
+            "Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+wlp2s0: 0000   54.  -56.  -256        0      0      0      0     42        0
+";
+        assert_eq!(parse_wireless(content, "wlp2s0"), Some((54.0, -56.0)));
+        assert_eq!(parse_wireless(content, "wlan0"), None);
+    }
+
+    #[test]
+    fn test_interface_up() {
+        let root = std::env::temp_dir().join(format!("cu_network_health_{}", std::process::id()));
+        let write = |name: &str, file: &str, content: &str| {
+            let dir = root.join("class/net").join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(file), content).unwrap();
+        };
+        write("eth0", "operstate", "up\n");
+        write("wlan0", "operstate", "down\n");
+        write("tun0", "operstate", "unknown\n");
+        write("tun0", "carrier", "1\n");
+        assert!(interface_up(&root, "eth0"));
+        assert!(!interface_up(&root, "wlan0"));
+        assert!(interface_up(&root, "tun0"));
+        assert!(!interface_up(&root, "missing0"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 use bincode::{Decode, Encode};
 use cu29::clock::{CuDuration, RobotClock};
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
 use cu29::output_msg;
 use cu29::{CuError, CuResult};
@@ -104,9 +105,9 @@ impl CuTaskLifecycle for Encoder {
 impl<'cl> CuSrcTask<'cl> for Encoder {
     type Output = output_msg!('cl, EncoderPayload);
 
-    fn process(&mut self, clock: &RobotClock, new_msg: Self::Output) -> CuResult<()> {
+    fn process(&mut self, ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
         let idata = self.data_from_interrupts.lock().unwrap();
-        new_msg.metadata.tov = Some(clock.now()).into();
+        new_msg.metadata.tov = Some(ctx.now()).into();
         new_msg.metadata.set_status(idata.ticks);
         new_msg.set_payload(EncoderPayload { ticks: idata.ticks });
         Ok(())
@@ -7,6 +7,7 @@ use bincode::error::{DecodeError, EncodeError};
 use bincode::{Decode, Encode};
 use cu29::clock::RobotClock;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
 use cu29::{output_msg, CuResult};
 use cu29_soa_derive::soa;
@@ -125,7 +126,7 @@ impl XYZ {
 impl<'cl> CuSrcTask<'cl> for Vlp16 {
     type Output = output_msg!('cl, XYZSoa<10000>);
 
-    fn process(&mut self, _clock: &RobotClock, new_msg: Self::Output) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
         let socket = self.socket.as_ref().unwrap();
         let mut packet = [0u8; 1206];
         let (read_size, _peer_addr) = socket.recv_from(&mut packet).unwrap();
@@ -181,7 +182,7 @@ mod tests {
             socket.send_to(data, "127.0.0.1:2368").unwrap();
             // process
             let mut msg = CuMsg::new(Some(XYZSoa::<10000>::default()));
-            drv.process(&clk, &mut msg).unwrap();
+            drv.process(&CuContext::from(&clk), &mut msg).unwrap();
             assert_eq!(0.009406593f32, msg.payload().unwrap().x[0].0.value);
             break;
         }
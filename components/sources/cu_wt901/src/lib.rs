@@ -4,8 +4,11 @@ use bincode::error::{DecodeError, EncodeError};
 use bincode::{Decode, Encode};
 use cu29::clock::RobotClock;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
-use cu29::{output_msg, CuResult};
+use cu29::selftest::CuSelfTest;
+use cu29::units::CuUnitValue;
+use cu29::{output_msg, unit_value, CuResult};
 use embedded_hal::i2c::I2c;
 use linux_embedded_hal::{I2CError, I2cdev};
 use std::fmt::Display;
@@ -21,6 +24,8 @@ use uom::si::magnetic_flux_density::{nanotesla, tesla};
 // FIXME: remove.
 const I2C_BUS: &str = "/dev/i2c-9";
 const WT901_I2C_ADDRESS: u8 = 0x50;
+// Holds the i2c address of the WT901, its closest thing to a WHO_AM_I register.
+const IIC_ADDR_REGISTER: u8 = 0x1A;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -96,41 +101,65 @@ impl Display for PositionalReadings {
     }
 }
 
+/// Serialized in SI units, with their unit so the readers of the JSON do not have to guess.
 impl Serialize for PositionalReadings {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         let mut s = serializer.serialize_struct("PositionalReadings", 12)?;
-        s.serialize_field("acc_x", &self.acc_x.value)?;
-        s.serialize_field("acc_y", &self.acc_y.value)?;
-        s.serialize_field("acc_z", &self.acc_z.value)?;
-        s.serialize_field("gyro_x", &self.gyro_x.value)?;
-        s.serialize_field("gyro_y", &self.gyro_y.value)?;
-        s.serialize_field("gyro_z", &self.gyro_z.value)?;
-        s.serialize_field("mag_x", &self.mag_x.value)?;
-        s.serialize_field("mag_y", &self.mag_y.value)?;
-        s.serialize_field("mag_z", &self.mag_z.value)?;
-        s.serialize_field("roll", &self.roll.value)?;
-        s.serialize_field("pitch", &self.pitch.value)?;
-        s.serialize_field("yaw", &self.yaw.value)?;
+        s.serialize_field("acc_x", &unit_value!(self.acc_x, meter_per_second_squared))?;
+        s.serialize_field("acc_y", &unit_value!(self.acc_y, meter_per_second_squared))?;
+        s.serialize_field("acc_z", &unit_value!(self.acc_z, meter_per_second_squared))?;
+        s.serialize_field("gyro_x", &unit_value!(self.gyro_x, radian_per_second))?;
+        s.serialize_field("gyro_y", &unit_value!(self.gyro_y, radian_per_second))?;
+        s.serialize_field("gyro_z", &unit_value!(self.gyro_z, radian_per_second))?;
+        s.serialize_field("mag_x", &unit_value!(self.mag_x, tesla))?;
+        s.serialize_field("mag_y", &unit_value!(self.mag_y, tesla))?;
+        s.serialize_field("mag_z", &unit_value!(self.mag_z, tesla))?;
+        s.serialize_field("roll", &unit_value!(self.roll, radian))?;
+        s.serialize_field("pitch", &unit_value!(self.pitch, radian))?;
+        s.serialize_field("yaw", &unit_value!(self.yaw, radian))?;
         s.end()
     }
 }
 
+/// The serialized form of the readings, every value with its unit.
+#[derive(serde::Deserialize)]
+struct SerializedReadings {
+    acc_x: CuUnitValue,
+    acc_y: CuUnitValue,
+    acc_z: CuUnitValue,
+    gyro_x: CuUnitValue,
+    gyro_y: CuUnitValue,
+    gyro_z: CuUnitValue,
+    mag_x: CuUnitValue,
+    mag_y: CuUnitValue,
+    mag_z: CuUnitValue,
+    roll: CuUnitValue,
+    pitch: CuUnitValue,
+    yaw: CuUnitValue,
+}
+
 impl<'de> Deserialize<'de> for PositionalReadings {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let values = <[f32; 12]>::deserialize(deserializer)?;
+        let r = SerializedReadings::deserialize(deserializer)?;
+        let si = |value: &CuUnitValue, unit: &str| {
+            value
+                .value_in(unit)
+                .map(|value| value as f32)
+                .map_err(|e| serde::de::Error::custom(e.to_string()))
+        };
         Ok(PositionalReadings {
-            acc_x: Acceleration::new::<standard_gravity>(values[0]),
-            acc_y: Acceleration::new::<standard_gravity>(values[1]),
-            acc_z: Acceleration::new::<standard_gravity>(values[2]),
-            gyro_x: AngularVelocity::new::<degree_per_second>(values[3]),
-            gyro_y: AngularVelocity::new::<degree_per_second>(values[4]),
-            gyro_z: AngularVelocity::new::<degree_per_second>(values[5]),
-            mag_x: MagneticFluxDensity::new::<nanotesla>(values[6]),
-            mag_y: MagneticFluxDensity::new::<nanotesla>(values[7]),
-            mag_z: MagneticFluxDensity::new::<nanotesla>(values[8]),
-            roll: Angle::new::<degree>(values[9]),
-            pitch: Angle::new::<degree>(values[10]),
-            yaw: Angle::new::<degree>(values[11]),
+            acc_x: Acceleration::new::<meter_per_second_squared>(si(&r.acc_x, "m/s²")?),
+            acc_y: Acceleration::new::<meter_per_second_squared>(si(&r.acc_y, "m/s²")?),
+            acc_z: Acceleration::new::<meter_per_second_squared>(si(&r.acc_z, "m/s²")?),
+            gyro_x: AngularVelocity::new::<radian_per_second>(si(&r.gyro_x, "rad/s")?),
+            gyro_y: AngularVelocity::new::<radian_per_second>(si(&r.gyro_y, "rad/s")?),
+            gyro_z: AngularVelocity::new::<radian_per_second>(si(&r.gyro_z, "rad/s")?),
+            mag_x: MagneticFluxDensity::new::<tesla>(si(&r.mag_x, "T")?),
+            mag_y: MagneticFluxDensity::new::<tesla>(si(&r.mag_y, "T")?),
+            mag_z: MagneticFluxDensity::new::<tesla>(si(&r.mag_z, "T")?),
+            roll: Angle::new::<radian>(si(&r.roll, "rad")?),
+            pitch: Angle::new::<radian>(si(&r.pitch, "rad")?),
+            yaw: Angle::new::<radian>(si(&r.yaw, "rad")?),
         })
     }
 }
@@ -221,12 +250,31 @@ impl CuTaskLifecycle for WT901 {
             i2c: Box::new(i2cdev),
         })
     }
+
+    fn self_test(&mut self, _clock: &RobotClock) -> CuResult<CuSelfTest> {
+        let mut buf = [0u8; 2];
+        self.i2c
+            .write_read(WT901_I2C_ADDRESS, &[IIC_ADDR_REGISTER], &mut buf)
+            .map_err(|e| {
+                CuError::from(format!("No answer from the WT901 on {}: {:?}", I2C_BUS, e))
+            })?;
+        if buf[0] != WT901_I2C_ADDRESS {
+            return Err(CuError::from(format!(
+                "Unexpected address register of the WT901: 0x{:02x} instead of 0x{:02x}",
+                buf[0], WT901_I2C_ADDRESS
+            )));
+        }
+        Ok(CuSelfTest::passed(format!(
+            "address register 0x{:02x} on {}",
+            buf[0], I2C_BUS
+        )))
+    }
 }
 
 impl<'cl> CuSrcTask<'cl> for WT901 {
     type Output = output_msg!('cl, PositionalReadings);
 
-    fn process(&mut self, _clock: &RobotClock, new_msg: Self::Output) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
         let mut pos = PositionalReadings::default();
         self.bulk_position_read(&mut pos)
             .map_err(|e| CuError::from(format!("Error reading WT901: {:?}", e)))?;
@@ -270,3 +318,23 @@ fn convert_angle(angle: i16) -> Angle {
     let angle = angle as f32 / 32768.0 * 180.0;
     Angle::new::<degree>(angle)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_units() {
+        let readings = PositionalReadings {
+            acc_z: Acceleration::new::<standard_gravity>(1.0),
+            yaw: Angle::new::<degree>(90.0),
+            ..Default::default()
+        };
+        let json = cu29::units::to_telemetry_json(&readings).unwrap();
+        assert!(json.contains(r#""unit":"m/s²""#));
+        assert!(json.contains(r#""yaw":{"value":1.57"#));
+        let back: PositionalReadings = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.acc_z, readings.acc_z);
+        assert!(serde_json::from_str::<PositionalReadings>(&json.replace("m/s²", "g")).is_err());
+    }
+}
@@ -1,5 +1,5 @@
-use cu29::clock::RobotClock;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSinkTask, CuTaskLifecycle, Freezable};
 use cu29::{input_msg, CuResult};
 use cu29_derive::copper_runtime;
@@ -26,7 +26,7 @@ impl CuTaskLifecycle for WT910TestSink {
 impl<'cl> CuSinkTask<'cl> for WT910TestSink {
     type Input = input_msg!('cl, PositionalReadings);
 
-    fn process(&mut self, _clock: &RobotClock, new_msg: Self::Input) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, new_msg: Self::Input) -> CuResult<()> {
         debug!("Received: {}", &new_msg.payload());
         Ok(())
     }
@@ -0,0 +1,458 @@
+//! A simple tag detector: adaptive thresholding, quads fitted on the dark connected components, then the
+//! cells of the candidates are sampled through the homography of the quad and matched against the family.
+
+use crate::family::TagFamily;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectorParams {
+    /// Half size in pixels of the window used to compute the local threshold. It should be larger than the
+    /// cells of the tags in the image.
+    pub threshold_radius: usize,
+    /// A pixel is dark if it is darker than the mean of its window by this much.
+    pub threshold_offset: u8,
+    /// Minimum length of the sides of a tag in pixels.
+    pub min_side: f64,
+    /// Minimum difference between the black border and the white surrounding of a tag.
+    pub min_contrast: u8,
+    /// Maximum number of bits corrected when matching the codes.
+    pub max_hamming: u32,
+}
+
+impl Default for DetectorParams {
+    fn default() -> Self {
+        DetectorParams {
+            threshold_radius: 25,
+            threshold_offset: 10,
+            min_side: 12.0,
+            min_contrast: 30,
+            max_hamming: 1,
+        }
+    }
+}
+
+/// A tag found in the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawDetection {
+    pub id: u32,
+    pub hamming: u32,
+    /// Outer corners of the black border in pixels, starting from the top left corner of the tag and
+    /// going clockwise as seen in the image.
+    pub corners: [(f64, f64); 4],
+    /// Maps the tag coordinates, [-1, 1] from the top left to the bottom right corner, to the image.
+    pub homography: Homography,
+}
+
+/// A 3x3 homography, row major.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homography(pub [f64; 9]);
+
+impl Homography {
+    /// The homography mapping the corners of the square [-1, 1] (top left first, clockwise) to corners.
+    pub fn from_square(corners: &[(f64, f64); 4]) -> Option<Self> {
+        let square = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let mut a = [[0.0f64; 9]; 8];
+        for (i, ((x, y), (u, v))) in square.iter().zip(corners).enumerate() {
+            a[2 * i] = [*x, *y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, *u];
+            a[2 * i + 1] = [0.0, 0.0, 0.0, *x, *y, 1.0, -v * x, -v * y, *v];
+        }
+        // Gauss-Jordan elimination with partial pivoting.
+        for col in 0..8 {
+            let pivot = (col..8).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            let pivot_row = a[col];
+            for (i, row) in a.iter_mut().enumerate() {
+                if i != col {
+                    let factor = row[col] / pivot_row[col];
+                    for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(col) {
+                        *value -= factor * pivot_value;
+                    }
+                }
+            }
+        }
+        let mut h = [1.0; 9];
+        for (i, row) in a.iter().enumerate() {
+            h[i] = row[8] / row[i];
+        }
+        Some(Homography(h))
+    }
+
+    pub fn project(&self, x: f64, y: f64) -> (f64, f64) {
+        let h = &self.0;
+        let w = h[6] * x + h[7] * y + h[8];
+        (
+            (h[0] * x + h[1] * y + h[2]) / w,
+            (h[3] * x + h[4] * y + h[5]) / w,
+        )
+    }
+}
+
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// The detector keeps its buffers from one image to the next, they are only reallocated when the size of the
+/// images changes.
+pub struct Detector {
+    params: DetectorParams,
+    family: TagFamily,
+    width: usize,
+    height: usize,
+    integral: Vec<u32>,
+    dark: Vec<bool>,
+    visited: Vec<bool>,
+    stack: Vec<u32>,
+    component: Vec<u32>,
+}
+
+impl Detector {
+    pub fn new(params: DetectorParams, family: TagFamily) -> Self {
+        Detector {
+            params,
+            family,
+            width: 0,
+            height: 0,
+            integral: Vec::new(),
+            dark: Vec::new(),
+            visited: Vec::new(),
+            stack: Vec::new(),
+            component: Vec::new(),
+        }
+    }
+
+    pub fn family(&self) -> &TagFamily {
+        &self.family
+    }
+
+    fn resize(&mut self, width: usize, height: usize) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.integral = vec![0; (width + 1) * (height + 1)];
+            self.dark = vec![false; width * height];
+            self.visited = vec![false; width * height];
+        }
+    }
+
+    fn threshold(&mut self, gray: &[u8]) {
+        let (w, h) = (self.width, self.height);
+        for y in 0..h {
+            let mut row_sum = 0;
+            for x in 0..w {
+                row_sum += gray[y * w + x] as u32;
+                self.integral[(y + 1) * (w + 1) + x + 1] =
+                    self.integral[y * (w + 1) + x + 1] + row_sum;
+            }
+        }
+        let r = self.params.threshold_radius;
+        for y in 0..h {
+            let (y0, y1) = (y.saturating_sub(r), (y + r + 1).min(h));
+            for x in 0..w {
+                let (x0, x1) = (x.saturating_sub(r), (x + r + 1).min(w));
+                let sum = self.integral[y1 * (w + 1) + x1] + self.integral[y0 * (w + 1) + x0]
+                    - self.integral[y0 * (w + 1) + x1]
+                    - self.integral[y1 * (w + 1) + x0];
+                let mean = sum / ((x1 - x0) * (y1 - y0)) as u32;
+                self.dark[y * w + x] =
+                    (gray[y * w + x] as u32) + (self.params.threshold_offset as u32) < mean;
+            }
+        }
+    }
+
+    /// Detects the tags in a grayscale image of width * height pixels.
+    pub fn detect(
+        &mut self,
+        gray: &[u8],
+        width: usize,
+        height: usize,
+        detections: &mut Vec<RawDetection>,
+    ) {
+        detections.clear();
+        self.resize(width, height);
+        self.threshold(gray);
+        self.visited.fill(false);
+
+        for start in 0..width * height {
+            if !self.dark[start] || self.visited[start] {
+                continue;
+            }
+            self.flood(start);
+            if let Some(corners) = self.fit_quad() {
+                if let Some(detection) = self.decode(gray, corners) {
+                    detections.push(detection);
+                }
+            }
+        }
+    }
+
+    /// Collects the 8-connected dark component of start.
+    fn flood(&mut self, start: usize) {
+        let (w, h) = (self.width as i64, self.height as i64);
+        self.component.clear();
+        self.stack.clear();
+        self.stack.push(start as u32);
+        self.visited[start] = true;
+        while let Some(index) = self.stack.pop() {
+            self.component.push(index);
+            let (x, y) = (index as i64 % w, index as i64 / w);
+            for (dx, dy) in [
+                (1, 0),
+                (-1, 0),
+                (0, 1),
+                (0, -1),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    continue;
+                }
+                let neighbor = (ny * w + nx) as usize;
+                if self.dark[neighbor] && !self.visited[neighbor] {
+                    self.visited[neighbor] = true;
+                    self.stack.push(neighbor as u32);
+                }
+            }
+        }
+    }
+
+    /// Fits a quad on the component: the farthest pixel from the centroid is a corner, the farthest from it the
+    /// opposite one, and the 2 others are the farthest on each side of this diagonal.
+    fn fit_quad(&self) -> Option<[(f64, f64); 4]> {
+        let min_pixels = (4.0 * self.params.min_side) as usize;
+        if self.component.len() < min_pixels {
+            return None;
+        }
+        let w = self.width as u32;
+        let point = |index: &u32| ((index % w) as f64 + 0.5, (index / w) as f64 + 0.5);
+        let n = self.component.len() as f64;
+        let centroid = self
+            .component
+            .iter()
+            .map(point)
+            .fold((0.0, 0.0), |acc, p| (acc.0 + p.0 / n, acc.1 + p.1 / n));
+        let farthest_from = |from: (f64, f64)| {
+            self.component
+                .iter()
+                .map(point)
+                .max_by(|a, b| distance(*a, from).total_cmp(&distance(*b, from)))
+                .unwrap()
+        };
+        let c0 = farthest_from(centroid);
+        let c2 = farthest_from(c0);
+        let side = |sign: f64| {
+            self.component
+                .iter()
+                .map(point)
+                .max_by(|a, b| (sign * cross(c0, c2, *a)).total_cmp(&(sign * cross(c0, c2, *b))))
+                .unwrap()
+        };
+        let (c1, c3) = (side(-1.0), side(1.0));
+        let mut corners = [c0, c1, c2, c3];
+
+        // Push the corners from the centers of the pixels to the outer edge of the border.
+        let center = (
+            corners.iter().map(|c| c.0).sum::<f64>() / 4.0,
+            corners.iter().map(|c| c.1).sum::<f64>() / 4.0,
+        );
+        for corner in corners.iter_mut() {
+            corner.0 += 0.5 * (corner.0 - center.0).signum();
+            corner.1 += 0.5 * (corner.1 - center.1).signum();
+        }
+        // Clockwise in the image (y down) is a positive cross product.
+        if cross(corners[0], corners[1], corners[2]) < 0.0 {
+            corners.swap(1, 3);
+        }
+        for i in 0..4 {
+            let (a, b, c) = (corners[i], corners[(i + 1) % 4], corners[(i + 2) % 4]);
+            if distance(a, b) < self.params.min_side || cross(a, b, c) <= 0.0 {
+                return None;
+            }
+        }
+        Some(corners)
+    }
+
+    fn sample(&self, gray: &[u8], homography: &Homography, u: f64, v: f64) -> Option<u8> {
+        let (x, y) = homography.project(u, v);
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some(gray[y as usize * self.width + x as usize])
+    }
+
+    fn decode(&self, gray: &[u8], corners: [(f64, f64); 4]) -> Option<RawDetection> {
+        let homography = Homography::from_square(&corners)?;
+        let n = self.family.dim as i64;
+        let cells = n + 2;
+        let cell = 2.0 / cells as f64;
+        let center = |i: i64| -1.0 + (i as f64 + 0.5) * cell;
+
+        // The black border is the ring of cells 0 and n + 1, the white surrounding is the ring just outside.
+        let (mut dark_sum, mut dark_count, mut light_sum, mut light_count) =
+            (0u32, 0u32, 0u32, 0u32);
+        let mut border = Vec::with_capacity(4 * cells as usize);
+        for i in -1..=cells {
+            for j in -1..=cells {
+                let ring = i.min(j).min(cells - 1 - i).min(cells - 1 - j);
+                if ring > 0 {
+                    continue;
+                }
+                let value = self.sample(gray, &homography, center(j), center(i));
+                if ring == 0 {
+                    let value = value?;
+                    dark_sum += value as u32;
+                    dark_count += 1;
+                    border.push(value);
+                } else if let Some(value) = value {
+                    light_sum += value as u32;
+                    light_count += 1;
+                }
+            }
+        }
+        if light_count == 0 {
+            return None;
+        }
+        let (dark, light) = (dark_sum / dark_count, light_sum / light_count);
+        if light < dark + self.params.min_contrast as u32 {
+            return None;
+        }
+        let threshold = ((dark + light) / 2) as u8;
+        if border.iter().filter(|v| **v >= threshold).count() > border.len() / 10 {
+            return None;
+        }
+
+        let mut code = 0u64;
+        for row in 1..=n {
+            for col in 1..=n {
+                code <<= 1;
+                if self.sample(gray, &homography, center(col), center(row))? >= threshold {
+                    code |= 1;
+                }
+            }
+        }
+        let (id, hamming, rotation) = self.family.decode(code, self.params.max_hamming)?;
+        // The observed code was rotated clockwise to match, so the top left corner of the tag is `rotation`
+        // corners further counterclockwise.
+        let mut oriented = corners;
+        oriented.rotate_right(rotation);
+        Some(RawDetection {
+            id,
+            hamming,
+            corners: oriented,
+            homography: Homography::from_square(&oriented)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Renders the tag code of family with its top left corner at origin, rotated by angle (radians,
+    /// clockwise in the image), cell pixels per cell, on a white image.
+    pub(crate) fn render(
+        family: &TagFamily,
+        code: u64,
+        width: usize,
+        height: usize,
+        origin: (f64, f64),
+        cell: f64,
+        angle: f64,
+    ) -> Vec<u8> {
+        let n = family.dim as i64;
+        let mut image = vec![230u8; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let (dx, dy) = (x as f64 + 0.5 - origin.0, y as f64 + 0.5 - origin.1);
+                // back to the tag frame.
+                let (tx, ty) = (
+                    dx * angle.cos() + dy * angle.sin(),
+                    -dx * angle.sin() + dy * angle.cos(),
+                );
+                let (col, row) = ((tx / cell).floor() as i64, (ty / cell).floor() as i64);
+                if col < 0 || row < 0 || col > n + 1 || row > n + 1 {
+                    continue;
+                }
+                let white = if col == 0 || row == 0 || col == n + 1 || row == n + 1 {
+                    false
+                } else {
+                    let bit = (row - 1) * n + (col - 1);
+                    (code >> (n * n - 1 - bit)) & 1 == 1
+                };
+                image[y * width + x] = if white { 230 } else { 20 };
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_homography() {
+        let corners = [(10.0, 20.0), (50.0, 22.0), (48.0, 70.0), (12.0, 60.0)];
+        let h = Homography::from_square(&corners).unwrap();
+        let (x, y) = h.project(1.0, 1.0);
+        assert!((x - 48.0).abs() < 1e-9 && (y - 70.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_detect_axis_aligned() {
+        let family = TagFamily::tag16h5();
+        let image = render(&family, family.codes[3], 160, 120, (40.0, 30.0), 10.0, 0.0);
+        let mut detector = Detector::new(DetectorParams::default(), family);
+        let mut detections = Vec::new();
+        detector.detect(&image, 160, 120, &mut detections);
+        assert_eq!(detections.len(), 1);
+        let detection = &detections[0];
+        assert_eq!((detection.id, detection.hamming), (3, 0));
+        let expected = [(40.0, 30.0), (100.0, 30.0), (100.0, 90.0), (40.0, 90.0)];
+        for (corner, expected) in detection.corners.iter().zip(expected) {
+            assert!(distance(*corner, expected) < 1.5, "{:?}", detection.corners);
+        }
+    }
+
+    #[test]
+    fn test_detect_rotated() {
+        let family = TagFamily::tag16h5();
+        let mut detector = Detector::new(DetectorParams::default(), family.clone());
+        let mut detections = Vec::new();
+        for angle in [0.3, 1.9, 3.5, 5.0] {
+            let image = render(
+                &family,
+                family.codes[12],
+                200,
+                200,
+                (100.0, 100.0),
+                10.0,
+                angle,
+            );
+            detector.detect(&image, 200, 200, &mut detections);
+            assert_eq!(detections.len(), 1, "angle {}", angle);
+            assert_eq!(detections[0].id, 12);
+            // the top left corner of the tag is where it was drawn from.
+            assert!(
+                distance(detections[0].corners[0], (100.0, 100.0)) < 2.0,
+                "angle {}: {:?}",
+                angle,
+                detections[0].corners
+            );
+        }
+    }
+
+    #[test]
+    fn test_no_false_positive() {
+        let family = TagFamily::tag16h5();
+        // a plain black square is not a tag.
+        let image = render(&family, 0, 100, 100, (20.0, 20.0), 10.0, 0.0);
+        let mut detector = Detector::new(DetectorParams::default(), family);
+        let mut detections = Vec::new();
+        detector.detect(&image, 100, 100, &mut detections);
+        assert!(detections.is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+//! Tag families: the codes of the tags and their matching.
+
+use cu29::CuResult;
+use cu29_traits::CuError;
+
+/// The codes of a family of square tags.
+/// A code has dim * dim bits, row by row from the top left corner of the tag, most significant bit first.
+/// A bit is 1 for a white cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFamily {
+    pub name: String,
+    /// Number of data cells on a side, the black border is not included.
+    pub dim: usize,
+    pub codes: Vec<u64>,
+}
+
+/// The AprilTag 16h5 family.
+const TAG16H5: [u64; 30] = [
+    0x27c8, 0x31b6, 0x3859, 0x569c, 0x6c76, 0x7ddb, 0xaf09, 0xf5a1, 0xfb8b, 0x1cb9, 0x28ca, 0xe8dc,
+    0x1426, 0x5770, 0x9253, 0xb702, 0x063a, 0x8f34, 0xb4c0, 0x51ec, 0xe6f0, 0x5fa4, 0xdd43, 0x1aaa,
+    0xe62f, 0x6dbc, 0xb6eb, 0xde10, 0x154d, 0xb57a,
+];
+
+impl TagFamily {
+    pub fn tag16h5() -> Self {
+        TagFamily {
+            name: "tag16h5".to_string(),
+            dim: 4,
+            codes: TAG16H5.to_vec(),
+        }
+    }
+
+    pub fn custom(name: &str, dim: usize, codes: Vec<u64>) -> CuResult<Self> {
+        if dim == 0 || dim * dim > 64 {
+            return Err(format!(
+                "Invalid tag dimension {}, the codes must fit in 64 bits.",
+                dim
+            )
+            .into());
+        }
+        Ok(TagFamily {
+            name: name.to_string(),
+            dim,
+            codes,
+        })
+    }
+
+    /// Parses a list of hexadecimal codes like "0x27c8, 0x31b6".
+    pub fn parse_codes(codes: &str) -> CuResult<Vec<u64>> {
+        codes
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(|code| {
+                u64::from_str_radix(code.trim_start_matches("0x"), 16).map_err(|e| {
+                    CuError::new_with_cause(&format!("Invalid tag code '{}'", code), e)
+                })
+            })
+            .collect()
+    }
+
+    /// The code of the tag seen rotated by 90 degrees clockwise.
+    pub fn rotate(&self, code: u64) -> u64 {
+        let n = self.dim;
+        let mut rotated = 0;
+        for row in 0..n {
+            for col in 0..n {
+                let bit = (code >> (n * n - 1 - ((n - 1 - col) * n + row))) & 1;
+                rotated |= bit << (n * n - 1 - (row * n + col));
+            }
+        }
+        rotated
+    }
+
+    /// Finds the closest code within max_hamming bits in any of the 4 orientations.
+    /// Returns the id, the number of bits corrected and the number of clockwise rotations applied to the
+    /// observed code to match it.
+    pub fn decode(&self, code: u64, max_hamming: u32) -> Option<(u32, u32, usize)> {
+        let mut best: Option<(u32, u32, usize)> = None;
+        let mut rotated = code;
+        for rotation in 0..4 {
+            for (id, family_code) in self.codes.iter().enumerate() {
+                let hamming = (rotated ^ family_code).count_ones();
+                if hamming <= max_hamming && best.is_none_or(|(_, h, _)| hamming < h) {
+                    best = Some((id as u32, hamming, rotation));
+                }
+            }
+            rotated = self.rotate(rotated);
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_and_decode() {
+        let family = TagFamily::tag16h5();
+        // 1000       0001
+        // 0000  cw   0000
+        // 0000  ->   0000
+        // 0001       1000
+        assert_eq!(family.rotate(0x8001), 0x1008);
+        let code = family.codes[7];
+        let rotated = family.rotate(family.rotate(family.rotate(code)));
+        assert_eq!(family.rotate(rotated), code);
+        assert_eq!(family.decode(rotated, 0), Some((7, 0, 1)));
+        assert_eq!(family.decode(code ^ 0x10, 1), Some((7, 1, 0)));
+        assert_eq!(family.decode(code ^ 0x11, 1), None);
+    }
+
+    #[test]
+    fn test_parse_codes() {
+        assert_eq!(
+            TagFamily::parse_codes("0x27c8, 31b6,").unwrap(),
+            vec![0x27c8, 0x31b6]
+        );
+        assert!(TagFamily::parse_codes("0xzz").is_err());
+        assert!(TagFamily::custom("big", 9, vec![]).is_err());
+    }
+}
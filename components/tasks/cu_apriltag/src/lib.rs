@@ -0,0 +1,287 @@
+mod detector;
+mod family;
+
+pub use detector::{Detector, DetectorParams, Homography, RawDetection};
+pub use family::TagFamily;
+
+use bincode::{Decode, Encode};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_traits::CuError;
+use cu_sensor_payloads::Image;
+use cu_spatial_payloads::Pose;
+use serde::{Deserialize, Serialize};
+
+/// A tag detected in an image.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct TagDetection {
+    pub id: u32,
+    /// Number of bits corrected to match the code, the higher the less reliable.
+    pub hamming: u32,
+    /// Outer corners of the black border in pixels, from the top left corner of the tag, clockwise.
+    pub corners: [(f32, f32); 4],
+    /// Pose of the tag in the frame of the camera (x right, y down, z forward). In the frame of the tag,
+    /// x goes right, y down and z into the tag, as seen when facing it.
+    pub pose: Pose,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct TagDetections {
+    pub tags: Vec<TagDetection>,
+}
+
+/// The pinhole parameters of the camera, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct CameraIntrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+}
+
+impl CameraIntrinsics {
+    /// Reads the intrinsics from the config: either inline "fx", "fy", "cx", "cy" or from a RON file
+    /// `(fx: .., fy: .., cx: .., cy: ..)` referenced by "intrinsics_file".
+    pub fn from_config(config: &ComponentConfig) -> CuResult<Self> {
+        if let Some(path) = config.get::<String>("intrinsics_file") {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| CuError::new_with_cause(&format!("Could not read {}", path), e))?;
+            return ron::from_str(&content).map_err(|e| {
+                CuError::new_with_cause(&format!("Invalid intrinsics in {}", path), e)
+            });
+        }
+        match (
+            config.get::<f64>("fx"),
+            config.get::<f64>("fy"),
+            config.get::<f64>("cx"),
+            config.get::<f64>("cy"),
+        ) {
+            (Some(fx), Some(fy), Some(cx), Some(cy)) => Ok(CameraIntrinsics { fx, fy, cx, cy }),
+            _ => Err(
+                "The camera intrinsics need an 'intrinsics_file' or 'fx', 'fy', 'cx' and 'cy'."
+                    .into(),
+            ),
+        }
+    }
+
+    /// Pose of a square tag of side tag_size meters from the homography mapping its [-1, 1] square to the image.
+    pub fn tag_pose(&self, homography: &Homography, tag_size: f64) -> Pose {
+        let h = &homography.0;
+        // M = K^-1 H
+        let mut m = [0.0; 9];
+        for col in 0..3 {
+            m[col] = (h[col] - self.cx * h[6 + col]) / self.fx;
+            m[3 + col] = (h[3 + col] - self.cy * h[6 + col]) / self.fy;
+            m[6 + col] = h[6 + col];
+        }
+        let column = |c: usize| [m[c], m[3 + c], m[6 + c]];
+        let norm = |v: [f64; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        let (m1, m2, m3) = (column(0), column(1), column(2));
+        // The tag is in front of the camera.
+        let sign = if m3[2] < 0.0 { -1.0 } else { 1.0 };
+        let scale = sign * (norm(m1) + norm(m2)) / 2.0;
+        let t = m3.map(|v| v / scale * tag_size / 2.0);
+
+        // Orthonormalize the rotation with Gram-Schmidt.
+        let r1 = m1.map(|v| v / scale);
+        let r1 = r1.map(|v| v / norm(r1));
+        let r2 = m2.map(|v| v / scale);
+        let dot = r1[0] * r2[0] + r1[1] * r2[1] + r1[2] * r2[2];
+        let r2 = [
+            r2[0] - dot * r1[0],
+            r2[1] - dot * r1[1],
+            r2[2] - dot * r1[2],
+        ];
+        let r2 = r2.map(|v| v / norm(r2));
+        let r3 = [
+            r1[1] * r2[2] - r1[2] * r2[1],
+            r1[2] * r2[0] - r1[0] * r2[2],
+            r1[0] * r2[1] - r1[1] * r2[0],
+        ];
+        // R = [r1 r2 r3] as columns, to roll pitch yaw (ZYX).
+        Pose {
+            x: t[0],
+            y: t[1],
+            z: t[2],
+            roll: r2[2].atan2(r3[2]),
+            pitch: (-r1[2]).clamp(-1.0, 1.0).asin(),
+            yaw: r1[1].atan2(r1[0]),
+        }
+    }
+}
+
+/// Detects tags in the images and estimates their pose from the camera intrinsics and the tag size.
+pub struct AprilTagTask {
+    detector: Detector,
+    intrinsics: CameraIntrinsics,
+    tag_size: f64,
+    gray: Vec<u8>,
+    detections: Vec<RawDetection>,
+}
+
+impl Freezable for AprilTagTask {}
+
+impl CuTaskLifecycle for AprilTagTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("AprilTagTask needs a config.")?;
+        let family = match config
+            .get::<String>("family")
+            .unwrap_or("tag16h5".to_string())
+            .as_str()
+        {
+            "tag16h5" => TagFamily::tag16h5(),
+            "custom" => TagFamily::custom(
+                "custom",
+                config
+                    .get::<u32>("dim")
+                    .ok_or("A custom tag family needs a 'dim'.")? as usize,
+                TagFamily::parse_codes(
+                    &config
+                        .get::<String>("codes")
+                        .ok_or("A custom tag family needs its 'codes'.")?,
+                )?,
+            )?,
+            other => {
+                return Err(format!(
+                    "Unknown tag family '{}', expected 'tag16h5' or 'custom'.",
+                    other
+                )
+                .into())
+            }
+        };
+        let defaults = DetectorParams::default();
+        let params = DetectorParams {
+            threshold_radius: config
+                .get::<u32>("threshold_radius")
+                .map_or(defaults.threshold_radius, |r| r as usize),
+            threshold_offset: config
+                .get::<u8>("threshold_offset")
+                .unwrap_or(defaults.threshold_offset),
+            min_side: config.get::<f64>("min_side").unwrap_or(defaults.min_side),
+            min_contrast: config
+                .get::<u8>("min_contrast")
+                .unwrap_or(defaults.min_contrast),
+            max_hamming: config
+                .get::<u32>("max_hamming")
+                .unwrap_or(defaults.max_hamming),
+        };
+        Ok(AprilTagTask {
+            detector: Detector::new(params, family),
+            intrinsics: CameraIntrinsics::from_config(config)?,
+            tag_size: config
+                .get::<f64>("tag_size")
+                .ok_or("AprilTagTask needs the 'tag_size' in meters.")?,
+            gray: Vec::new(),
+            detections: Vec::new(),
+        })
+    }
+}
+
+impl<'cl> CuTask<'cl> for AprilTagTask {
+    type Input = input_msg!('cl, Image);
+    type Output = output_msg!('cl, TagDetections);
+
+    fn process(
+        &mut self,
+        _ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let Some(image) = input.payload() else {
+            output.clear_payload();
+            return Ok(());
+        };
+        if image.is_on_device() {
+            return Err("AprilTagDetector needs the images in the memory of the CPU.".into());
+        }
+        let (width, height) = (image.width as usize, image.height as usize);
+        self.gray.resize(width * height, 0);
+        image.to_luma(&mut self.gray);
+        self.detector
+            .detect(&self.gray, width, height, &mut self.detections);
+
+        let tags = self
+            .detections
+            .iter()
+            .map(|detection| TagDetection {
+                id: detection.id,
+                hamming: detection.hamming,
+                corners: detection.corners.map(|(x, y)| (x as f32, y as f32)),
+                pose: self
+                    .intrinsics
+                    .tag_pose(&detection.homography, self.tag_size),
+            })
+            .collect();
+        output.set_payload(TagDetections { tags });
+        // The detections are valid at the time the image was taken.
+        output.metadata.tov = input.metadata.tov;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+    use cu29::pool::CuHandle;
+    use cu_sensor_payloads::ImageFormat;
+
+    #[test]
+    fn test_tag_pose() {
+        let mut config = ComponentConfig::new();
+        config.set("tag_size", 0.12);
+        for (key, value) in [("fx", 400.0), ("fy", 400.0), ("cx", 100.0), ("cy", 80.0)] {
+            config.set(key, value);
+        }
+        let mut task = AprilTagTask::new(Some(&config)).unwrap();
+
+        // A 60 pixels wide tag, its center 20 pixels right of the optical center: 0.12m at 0.8m.
+        let family = TagFamily::tag16h5();
+        let pixels =
+            detector::tests::render(&family, family.codes[5], 200, 160, (90.0, 50.0), 10.0, 0.0);
+        let image = CuMsg::new(Some(Image {
+            width: 200,
+            height: 160,
+            stride: 200,
+            format: ImageFormat::Mono8,
+            data: CuHandle::new_detached(pixels),
+            device: None,
+        }));
+        let mut output = CuMsg::new(None);
+        task.process(
+            &CuContext::from(&RobotClock::default()),
+            &image,
+            &mut output,
+        )
+        .unwrap();
+        let tags = &output.payload().unwrap().tags;
+        assert_eq!(tags.len(), 1);
+        let pose = &tags[0].pose;
+        assert_eq!(tags[0].id, 5);
+        assert!((pose.z - 0.8).abs() < 0.03, "{:?}", pose);
+        assert!((pose.x - 0.04).abs() < 0.005, "{:?}", pose);
+        assert!(pose.y.abs() < 0.005, "{:?}", pose);
+        assert!(pose.roll.abs() < 0.05 && pose.pitch.abs() < 0.05 && pose.yaw.abs() < 0.05);
+    }
+
+    #[test]
+    fn test_config() {
+        let mut config = ComponentConfig::new();
+        config.set("tag_size", 0.12);
+        assert!(AprilTagTask::new(Some(&config)).is_err());
+        for (key, value) in [("fx", 400.0), ("fy", 400.0), ("cx", 100.0), ("cy", 80.0)] {
+            config.set(key, value);
+        }
+        config.set("family", "custom".to_string());
+        config.set("dim", 3u32);
+        config.set("codes", "0x1ff, 0x0aa".to_string());
+        let task = AprilTagTask::new(Some(&config)).unwrap();
+        assert_eq!(task.detector.family().codes, vec![0x1ff, 0xaa]);
+    }
+}
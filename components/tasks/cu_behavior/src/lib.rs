@@ -0,0 +1,260 @@
+mod tree;
+
+pub use tree::{BehaviorTree, Blackboard, BtNode, BtStatus, NodeState};
+
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use cu29::clock::{CuDuration, CuTime, OptionCuTime};
+use cu29::config::{ComponentConfig, BASE_PERIOD_NS_KEY};
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+
+/// Updates of the blackboard sent to the behavior tree by the rest of the graph.
+/// Only the entries present are updated, the others keep their last value.
+#[derive(Debug, Default, Clone, Encode, Decode)]
+pub struct BtBlackboardUpdate {
+    pub conditions: Vec<(String, bool)>,
+    /// Outcome of the actions requested by the tree, Running while they are executing.
+    pub actions: Vec<(String, BtStatus)>,
+}
+
+impl BtBlackboardUpdate {
+    pub fn set_condition(&mut self, key: &str, value: bool) {
+        self.conditions.push((key.to_string(), value));
+    }
+
+    pub fn set_action_status(&mut self, key: &str, status: BtStatus) {
+        self.actions.push((key.to_string(), status));
+    }
+}
+
+/// Result of one tick of the tree.
+#[derive(Debug, Default, Clone, Encode, Decode)]
+pub struct BtTick {
+    pub tick: u64,
+    pub status: BtStatus,
+    /// The actions the tree wants to be executing, the executors should act on them and report back.
+    pub active_actions: Vec<String>,
+}
+
+impl BtTick {
+    pub fn is_active(&self, action: &str) -> bool {
+        self.active_actions.iter().any(|a| a == action)
+    }
+}
+
+/// A task ticking a behavior tree defined in the config.
+/// The tree ticks at the base period of the node if it has one, at every iteration otherwise.
+pub struct BehaviorTreeTask {
+    tree: BehaviorTree,
+    blackboard: Blackboard,
+    period: Option<CuDuration>,
+    last_tick: Option<CuTime>,
+    tick: u64,
+    last_status: Option<BtStatus>,
+}
+
+impl BehaviorTreeTask {
+    pub fn new_with_tree(root: &BtNode, period: Option<CuDuration>) -> Self {
+        BehaviorTreeTask {
+            tree: BehaviorTree::new(root),
+            blackboard: Blackboard::default(),
+            period,
+            last_tick: None,
+            tick: 0,
+            last_status: None,
+        }
+    }
+}
+
+impl CuTaskLifecycle for BehaviorTreeTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config =
+            config.ok_or("BehaviorTreeTask needs a config with a 'tree' or a 'tree_file'.")?;
+        let definition = match (
+            config.get::<String>("tree"),
+            config.get::<String>("tree_file"),
+        ) {
+            (Some(tree), _) => tree,
+            (None, Some(path)) => std::fs::read_to_string(&path)
+                .map_err(|e| CuError::new_with_cause(&format!("Could not read {}", path), e))?,
+            (None, None) => {
+                return Err(
+                    "BehaviorTreeTask needs a 'tree' or a 'tree_file' in its config.".into(),
+                )
+            }
+        };
+        let root = BtNode::parse(&definition)?;
+        let period = config
+            .try_get::<u64>(BASE_PERIOD_NS_KEY)?
+            .map(CuDuration::from);
+        Ok(Self::new_with_tree(&root, period))
+    }
+}
+
+impl<'cl> CuTask<'cl> for BehaviorTreeTask {
+    type Input = input_msg!('cl, BtBlackboardUpdate);
+    type Output = output_msg!('cl, BtTick);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        if let Some(update) = input.payload() {
+            for (key, value) in &update.conditions {
+                self.blackboard.conditions.insert(key.clone(), *value);
+            }
+            for (key, status) in &update.actions {
+                self.blackboard.actions.insert(key.clone(), *status);
+            }
+        }
+
+        let now = ctx.now();
+        if let (Some(period), Some(last_tick)) = (self.period, self.last_tick) {
+            if CuDuration(now.0.saturating_sub(last_tick.0)) < period {
+                output.clear_payload();
+                return Ok(());
+            }
+        }
+        self.last_tick = Some(now);
+        self.tick += 1;
+
+        let mut active_actions = Vec::new();
+        let status = self.tree.tick(&mut self.blackboard, &mut active_actions);
+        if self.last_status != Some(status) {
+            debug!(
+                "Behavior tree: tick {} -> {}",
+                self.tick,
+                format!("{:?}", status)
+            );
+            self.last_status = Some(status);
+        }
+        output.metadata.tov = now.into();
+        output.metadata.set_status(format!("{:?}", status));
+        output.set_payload(BtTick {
+            tick: self.tick,
+            status,
+            active_actions,
+        });
+        Ok(())
+    }
+}
+
+/// The state of the tree and of the blackboard are frozen so a replay resumes exactly where it was.
+impl Freezable for BehaviorTreeTask {
+    fn freeze<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        Encode::encode(&self.tree.states().to_vec(), encoder)?;
+        Encode::encode(&self.blackboard.conditions, encoder)?;
+        Encode::encode(&self.blackboard.actions, encoder)?;
+        Encode::encode(&OptionCuTime::from(self.last_tick), encoder)?;
+        Encode::encode(&self.tick, encoder)?;
+        Ok(())
+    }
+
+    fn thaw<D: Decoder>(&mut self, decoder: &mut D) -> Result<(), DecodeError> {
+        let states: Vec<NodeState> = Decode::decode(decoder)?;
+        self.tree
+            .set_states(states)
+            .map_err(|e| DecodeError::OtherString(e.to_string()))?;
+        self.blackboard.conditions = Decode::decode(decoder)?;
+        self.blackboard.actions = Decode::decode(decoder)?;
+        let last_tick: OptionCuTime = Decode::decode(decoder)?;
+        self.last_tick = last_tick.into();
+        self.tick = Decode::decode(decoder)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::config::standard;
+    use bincode::de::read::SliceReader;
+    use bincode::de::DecoderImpl;
+    use bincode::enc::write::SliceWriter;
+    use bincode::enc::EncoderImpl;
+    use cu29::clock::RobotClock;
+    use std::time::Duration;
+
+    const TREE: &str = r#"Sequence([Action("undock"), Action("patrol")])"#;
+
+    #[test]
+    fn test_task_ticks_at_base_period() {
+        let mut config = ComponentConfig::new();
+        config.set("tree", TREE.to_string());
+        config.set(BASE_PERIOD_NS_KEY, 10_000_000u64);
+        let mut task = BehaviorTreeTask::new(Some(&config)).unwrap();
+        let (clock, mock) = RobotClock::mock();
+        let mut input = CuMsg::new(None);
+        let mut output = CuMsg::new(None);
+
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert!(output.payload().unwrap().is_active("undock"));
+
+        let mut update = BtBlackboardUpdate::default();
+        update.set_action_status("undock", BtStatus::Success);
+        input.set_payload(update);
+        mock.increment(Duration::from_millis(5));
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert!(output.payload().is_none());
+
+        input.clear_payload();
+        mock.increment(Duration::from_millis(5));
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        let tick = output.payload().unwrap();
+        assert_eq!(tick.tick, 2);
+        assert_eq!(tick.active_actions, vec!["patrol".to_string()]);
+        // The clock going back does not tick.
+        mock.decrement(Duration::from_millis(1));
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert!(output.payload().is_none());
+
+        config.set(BASE_PERIOD_NS_KEY, -1);
+        assert!(BehaviorTreeTask::new(Some(&config)).is_err());
+    }
+
+    #[test]
+    fn test_freeze_thaw() {
+        let root = BtNode::parse(TREE).unwrap();
+        let mut task = BehaviorTreeTask::new_with_tree(&root, None);
+        let clock = RobotClock::default();
+        let mut input = CuMsg::new(None);
+        let mut output = CuMsg::new(None);
+        let mut update = BtBlackboardUpdate::default();
+        update.set_action_status("undock", BtStatus::Success);
+        input.set_payload(update);
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+
+        let mut buffer = [0u8; 1024];
+        let mut encoder = EncoderImpl::new(SliceWriter::new(&mut buffer), standard());
+        task.freeze(&mut encoder).unwrap();
+        let size = encoder.into_writer().bytes_written();
+
+        let mut restored = BehaviorTreeTask::new_with_tree(&root, None);
+        let mut decoder = DecoderImpl::new(SliceReader::new(&buffer[..size]), standard());
+        restored.thaw(&mut decoder).unwrap();
+        input.clear_payload();
+        restored
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        let tick = output.payload().unwrap();
+        assert_eq!(tick.tick, 2);
+        assert_eq!(tick.active_actions, vec!["patrol".to_string()]);
+    }
+}
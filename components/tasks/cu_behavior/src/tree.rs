@@ -0,0 +1,326 @@
+//! The behavior tree definition and its execution engine.
+
+use bincode::{Decode, Encode};
+use cu29::CuResult;
+use cu29_traits::CuError;
+use ron::extensions::Extensions;
+use ron::Options;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Result of the tick of a node.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum BtStatus {
+    #[default]
+    Running,
+    Success,
+    Failure,
+}
+
+/// The definition of a tree as written in the config, for example:
+/// ```ron
+/// Fallback([
+///     Condition("docked"),
+///     Sequence([Condition("dock_visible"), Action("go_dock")]),
+///     Action("explore"),
+/// ])
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BtNode {
+    /// Ticks the children in order until one fails, resumes at the running child.
+    Sequence(Vec<BtNode>),
+    /// Ticks the children in order until one succeeds, resumes at the running child.
+    Fallback(Vec<BtNode>),
+    /// Ticks all its unfinished children at every tick, succeeds once this number of them succeeded.
+    Parallel(u32, Vec<BtNode>),
+    /// Swaps Success and Failure of its child.
+    Inverter(Box<BtNode>),
+    /// Success if the condition is true on the blackboard, Failure otherwise.
+    Condition(String),
+    /// Requests an action and waits for its outcome to be reported on the blackboard.
+    Action(String),
+}
+
+impl BtNode {
+    pub fn parse(ron: &str) -> CuResult<Self> {
+        Options::default()
+            .with_default_extension(Extensions::UNWRAP_VARIANT_NEWTYPES)
+            .from_str(ron)
+            .map_err(|e| CuError::new_with_cause("Invalid behavior tree", e))
+    }
+}
+
+/// What the leaves of the tree read: the conditions and the outcomes of the actions,
+/// fed by the other tasks of the graph.
+#[derive(Debug, Default)]
+pub struct Blackboard {
+    pub conditions: HashMap<String, bool>,
+    pub actions: HashMap<String, BtStatus>,
+}
+
+enum FlatKind {
+    Sequence,
+    Fallback,
+    Parallel(u32),
+    Inverter,
+    Condition(String),
+    Action(String),
+}
+
+struct FlatNode {
+    kind: FlatKind,
+    children: Vec<usize>,
+}
+
+/// The running state of a node.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
+pub struct NodeState {
+    /// For Sequences and Fallbacks, the index of the child to resume from.
+    cursor: u32,
+    /// For the children of a Parallel, their outcome if they are already done.
+    outcome: Option<BtStatus>,
+}
+
+/// An executable behavior tree: the definition flattened in pre-order with the running
+/// state of every node next to it.
+pub struct BehaviorTree {
+    nodes: Vec<FlatNode>,
+    states: Vec<NodeState>,
+}
+
+impl BehaviorTree {
+    pub fn new(root: &BtNode) -> Self {
+        let mut tree = BehaviorTree {
+            nodes: Vec::new(),
+            states: Vec::new(),
+        };
+        tree.flatten(root);
+        tree.states = vec![NodeState::default(); tree.nodes.len()];
+        tree
+    }
+
+    fn flatten(&mut self, node: &BtNode) -> usize {
+        let index = self.nodes.len();
+        let (kind, children): (FlatKind, &[BtNode]) = match node {
+            BtNode::Sequence(children) => (FlatKind::Sequence, children),
+            BtNode::Fallback(children) => (FlatKind::Fallback, children),
+            BtNode::Parallel(threshold, children) => (FlatKind::Parallel(*threshold), children),
+            BtNode::Inverter(child) => (FlatKind::Inverter, std::slice::from_ref(child.as_ref())),
+            BtNode::Condition(key) => (FlatKind::Condition(key.clone()), &[]),
+            BtNode::Action(key) => (FlatKind::Action(key.clone()), &[]),
+        };
+        self.nodes.push(FlatNode {
+            kind,
+            children: Vec::new(),
+        });
+        let children = children.iter().map(|child| self.flatten(child)).collect();
+        self.nodes[index].children = children;
+        index
+    }
+
+    /// The running state of the tree, this is what needs to be frozen for replay.
+    pub fn states(&self) -> &[NodeState] {
+        &self.states
+    }
+
+    pub fn set_states(&mut self, states: Vec<NodeState>) -> CuResult<()> {
+        if states.len() != self.nodes.len() {
+            return Err("The behavior tree state does not match the tree.".into());
+        }
+        self.states = states;
+        Ok(())
+    }
+
+    /// Ticks the whole tree, the actions requested during this tick are pushed to active_actions.
+    pub fn tick(
+        &mut self,
+        blackboard: &mut Blackboard,
+        active_actions: &mut Vec<String>,
+    ) -> BtStatus {
+        self.tick_node(0, blackboard, active_actions)
+    }
+
+    fn tick_node(
+        &mut self,
+        index: usize,
+        blackboard: &mut Blackboard,
+        active_actions: &mut Vec<String>,
+    ) -> BtStatus {
+        let children = self.nodes[index].children.clone();
+        match &self.nodes[index].kind {
+            FlatKind::Sequence | FlatKind::Fallback => {
+                // a Sequence continues on Success, a Fallback on Failure.
+                let continue_on = if matches!(self.nodes[index].kind, FlatKind::Sequence) {
+                    BtStatus::Success
+                } else {
+                    BtStatus::Failure
+                };
+                while (self.states[index].cursor as usize) < children.len() {
+                    let child = children[self.states[index].cursor as usize];
+                    match self.tick_node(child, blackboard, active_actions) {
+                        BtStatus::Running => return BtStatus::Running,
+                        status if status == continue_on => self.states[index].cursor += 1,
+                        status => {
+                            self.reset(index);
+                            return status;
+                        }
+                    }
+                }
+                self.reset(index);
+                continue_on
+            }
+            FlatKind::Parallel(threshold) => {
+                let threshold = *threshold as usize;
+                let (mut successes, mut failures) = (0, 0);
+                for child in &children {
+                    // the children already done keep their outcome until the Parallel completes.
+                    let outcome = match self.states[*child].outcome {
+                        Some(outcome) => outcome,
+                        None => self.tick_node(*child, blackboard, active_actions),
+                    };
+                    match outcome {
+                        BtStatus::Success => successes += 1,
+                        BtStatus::Failure => failures += 1,
+                        BtStatus::Running => {}
+                    }
+                    if outcome != BtStatus::Running {
+                        self.states[*child].outcome = Some(outcome);
+                    }
+                }
+                if successes >= threshold {
+                    self.reset(index);
+                    BtStatus::Success
+                } else if failures > children.len().saturating_sub(threshold) {
+                    self.reset(index);
+                    BtStatus::Failure
+                } else {
+                    BtStatus::Running
+                }
+            }
+            FlatKind::Inverter => match self.tick_node(children[0], blackboard, active_actions) {
+                BtStatus::Success => BtStatus::Failure,
+                BtStatus::Failure => BtStatus::Success,
+                BtStatus::Running => BtStatus::Running,
+            },
+            FlatKind::Condition(key) => {
+                if blackboard.conditions.get(key).copied().unwrap_or(false) {
+                    BtStatus::Success
+                } else {
+                    BtStatus::Failure
+                }
+            }
+            FlatKind::Action(key) => match blackboard.actions.get(key).copied() {
+                Some(status @ (BtStatus::Success | BtStatus::Failure)) => {
+                    // The outcome is consumed so a later request of the same action waits for a new one.
+                    blackboard.actions.remove(key);
+                    status
+                }
+                _ => {
+                    active_actions.push(key.clone());
+                    BtStatus::Running
+                }
+            },
+        }
+    }
+
+    fn reset(&mut self, index: usize) {
+        self.states[index] = NodeState::default();
+        for child in self.nodes[index].children.clone() {
+            self.reset(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(tree: &mut BehaviorTree, blackboard: &mut Blackboard) -> (BtStatus, Vec<String>) {
+        let mut active = Vec::new();
+        let status = tree.tick(blackboard, &mut active);
+        (status, active)
+    }
+
+    #[test]
+    fn test_parse() {
+        let tree = BtNode::parse(
+            r#"Fallback([Condition("docked"), Sequence([Inverter(Condition("low")), Action("dock")]), Parallel(1, [Action("a"), Action("b")])])"#,
+        )
+        .unwrap();
+        assert_eq!(BehaviorTree::new(&tree).states().len(), 9);
+        assert!(BtNode::parse("Sequence(").is_err());
+    }
+
+    #[test]
+    fn test_sequence_resumes_on_running_child() {
+        let root = BtNode::parse(r#"Sequence([Action("a"), Action("b")])"#).unwrap();
+        let mut tree = BehaviorTree::new(&root);
+        let mut blackboard = Blackboard::default();
+
+        assert_eq!(
+            tick(&mut tree, &mut blackboard),
+            (BtStatus::Running, vec!["a".to_string()])
+        );
+        blackboard
+            .actions
+            .insert("a".to_string(), BtStatus::Success);
+        // "a" is done, the sequence moves on to "b" in the same tick.
+        assert_eq!(
+            tick(&mut tree, &mut blackboard),
+            (BtStatus::Running, vec!["b".to_string()])
+        );
+        // "a" is not requested again while "b" runs.
+        assert_eq!(
+            tick(&mut tree, &mut blackboard),
+            (BtStatus::Running, vec!["b".to_string()])
+        );
+        blackboard
+            .actions
+            .insert("b".to_string(), BtStatus::Success);
+        assert_eq!(tick(&mut tree, &mut blackboard).0, BtStatus::Success);
+        // the tree restarts from the beginning.
+        assert_eq!(
+            tick(&mut tree, &mut blackboard),
+            (BtStatus::Running, vec!["a".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_fallback_and_conditions() {
+        let root = BtNode::parse(r#"Fallback([Condition("docked"), Action("dock")])"#).unwrap();
+        let mut tree = BehaviorTree::new(&root);
+        let mut blackboard = Blackboard::default();
+        assert_eq!(
+            tick(&mut tree, &mut blackboard),
+            (BtStatus::Running, vec!["dock".to_string()])
+        );
+        blackboard
+            .actions
+            .insert("dock".to_string(), BtStatus::Failure);
+        assert_eq!(tick(&mut tree, &mut blackboard).0, BtStatus::Failure);
+        blackboard.conditions.insert("docked".to_string(), true);
+        assert_eq!(
+            tick(&mut tree, &mut blackboard),
+            (BtStatus::Success, vec![])
+        );
+    }
+
+    #[test]
+    fn test_parallel() {
+        let root =
+            BtNode::parse(r#"Parallel(2, [Action("a"), Action("b"), Action("c")])"#).unwrap();
+        let mut tree = BehaviorTree::new(&root);
+        let mut blackboard = Blackboard::default();
+        blackboard
+            .actions
+            .insert("a".to_string(), BtStatus::Failure);
+        blackboard
+            .actions
+            .insert("b".to_string(), BtStatus::Success);
+        assert_eq!(tick(&mut tree, &mut blackboard).0, BtStatus::Running);
+        blackboard
+            .actions
+            .insert("c".to_string(), BtStatus::Failure);
+        assert_eq!(tick(&mut tree, &mut blackboard).0, BtStatus::Failure);
+    }
+}
@@ -0,0 +1,284 @@
+//! A thermal derating policy: when the robot heats up, the rates of the configured tasks are lowered through the
+//! parameters of the tasks (see cu29::tuning), and restored once it cooled down.
+
+use cu29::config::{ComponentConfig, Value};
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuMsgPayload, CuSinkTask, CuTaskLifecycle, Freezable};
+use cu29::monitoring::CuHealth;
+use cu29::{input_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use std::marker::PhantomData;
+
+/// A temperature the policy can read.
+pub trait Temperature {
+    fn celsius(&self) -> f32;
+}
+
+impl Temperature for f32 {
+    fn celsius(&self) -> f32 {
+        *self
+    }
+}
+
+impl Temperature for f64 {
+    fn celsius(&self) -> f32 {
+        *self as f32
+    }
+}
+
+/// The hottest of the sensors, ie. the zones of the SoC.
+impl<T: Temperature, const N: usize> Temperature for [T; N] {
+    fn celsius(&self) -> f32 {
+        self.iter()
+            .map(Temperature::celsius)
+            .fold(f32::NEG_INFINITY, f32::max)
+    }
+}
+
+/// A parameter changed with the level, its value for every level from the nominal one.
+#[derive(Debug, Clone, PartialEq)]
+struct DeratingAction {
+    task: String,
+    key: String,
+    values: Vec<Value>,
+}
+
+/// Lowers the rates of the tasks when the temperature of its input crosses its thresholds.
+///
+/// The config takes:
+/// - `thresholds`: the temperatures in °C starting the levels of derating, increasing, ie. "70, 85".
+/// - `hysteresis`: how far in °C below a threshold the temperature goes back to leave its level, 5 by default.
+/// - `actions`: the parameters changed, separated by ';', with their values from the nominal level to the last one
+///   separated by '/', ie. "camera.fps=30/15/5; planner.base_period_ns=100000000/200000000/400000000".
+///
+/// The changes are requested when the level changes and applied by the runtime at the end of the copper list, which
+/// logs them. The task reports itself Degraded while derating.
+pub struct CuDerating<T> {
+    thresholds: Vec<f32>,
+    hysteresis: f32,
+    actions: Vec<DeratingAction>,
+    /// 0 when nominal, n above the nth threshold.
+    level: usize,
+    _temperature: PhantomData<T>,
+}
+
+impl<T> Freezable for CuDerating<T> {}
+
+impl<T> CuTaskLifecycle for CuDerating<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("The CuDerating needs 'thresholds' and 'actions'.")?;
+        let thresholds = parse_thresholds(
+            &config
+                .get::<String>("thresholds")
+                .ok_or("The CuDerating needs 'thresholds'.")?,
+        )?;
+        let actions = parse_actions(
+            &config
+                .get::<String>("actions")
+                .ok_or("The CuDerating needs 'actions'.")?,
+            thresholds.len() + 1,
+        )?;
+        let hysteresis = config.get::<f64>("hysteresis").unwrap_or(5.0) as f32;
+        if hysteresis.is_nan() || hysteresis < 0.0 {
+            return Err("The 'hysteresis' of the CuDerating must be positive.".into());
+        }
+        Ok(CuDerating {
+            thresholds,
+            hysteresis,
+            actions,
+            level: 0,
+            _temperature: PhantomData,
+        })
+    }
+}
+
+impl<T> CuDerating<T> {
+    /// The level at this temperature, leaving a level only below its threshold by the hysteresis.
+    fn level_at(&self, celsius: f32) -> usize {
+        let above = |offset: f32| {
+            self.thresholds
+                .iter()
+                .filter(|threshold| celsius >= *threshold - offset)
+                .count()
+        };
+        let rising = above(0.0);
+        if rising >= self.level {
+            rising
+        } else {
+            above(self.hysteresis).min(self.level)
+        }
+    }
+}
+
+impl<'cl, T> CuSinkTask<'cl> for CuDerating<T>
+where
+    T: CuMsgPayload + Temperature + 'cl,
+{
+    type Input = input_msg!('cl, T);
+
+    fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+        let Some(celsius) = input.payload().map(Temperature::celsius) else {
+            return Ok(());
+        };
+        // An unreadable sensor is considered hot.
+        let level = if celsius.is_nan() {
+            self.thresholds.len()
+        } else {
+            self.level_at(celsius)
+        };
+        if level != self.level {
+            let (from, to) = (self.level as u8, level as u8);
+            debug!("CuDerating: {}°C, level {} -> {}.", celsius, from, to);
+            for action in &self.actions {
+                let value = &action.values[level];
+                let ron = value.to_ron()?;
+                debug!(
+                    "CuDerating: {}.{} set to {}.",
+                    action.task.as_str(),
+                    action.key.as_str(),
+                    ron.as_str()
+                );
+                ctx.request_param(&action.task, &action.key, value.clone())?;
+            }
+            self.level = level;
+        }
+        if self.level > 0 {
+            ctx.report_health(CuHealth::Degraded);
+        }
+        Ok(())
+    }
+}
+
+/// Parses increasing temperatures like "70, 85".
+fn parse_thresholds(text: &str) -> CuResult<Vec<f32>> {
+    let thresholds = text
+        .split(',')
+        .map(|threshold| {
+            threshold.trim().parse::<f32>().map_err(|e| {
+                CuError::new_with_cause(&format!("Invalid threshold '{}'", threshold), e)
+            })
+        })
+        .collect::<CuResult<Vec<f32>>>()?;
+    if thresholds.is_empty() || thresholds.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(CuError::from(format!(
+            "The thresholds of the CuDerating must be increasing, got '{}'.",
+            text
+        )));
+    }
+    Ok(thresholds)
+}
+
+/// Parses "task.key=v0/v1/...; ..." with a RON value for each of the levels.
+fn parse_actions(text: &str, levels: usize) -> CuResult<Vec<DeratingAction>> {
+    text.split(';')
+        .map(str::trim)
+        .filter(|action| !action.is_empty())
+        .map(|action| {
+            let invalid = || {
+                CuError::from(format!(
+                    "Invalid action '{}', expected task.key=v0/v1.",
+                    action
+                ))
+            };
+            let (param, values) = action.split_once('=').ok_or_else(invalid)?;
+            let (task, key) = param.trim().split_once('.').ok_or_else(invalid)?;
+            let values = values
+                .split('/')
+                .map(|value| Value::from_ron(value.trim()))
+                .collect::<CuResult<Vec<Value>>>()?;
+            if values.len() != levels {
+                return Err(CuError::from(format!(
+                    "The action '{}' has {} values, expected one per level: {}.",
+                    action,
+                    values.len(),
+                    levels
+                )));
+            }
+            Ok(DeratingAction {
+                task: task.trim().to_string(),
+                key: key.trim().to_string(),
+                values,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+    use cu29::tuning::CuParamRequests;
+
+    fn derating() -> CuDerating<f32> {
+        let mut config = ComponentConfig::new();
+        config.set("thresholds", "70, 85".to_string());
+        config.set(
+            "actions",
+            "camera.fps=30/15/5; planner.base_period_ns=100000000/200000000/400000000".to_string(),
+        );
+        CuDerating::new(Some(&config)).unwrap()
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut derating = derating();
+        let mut level = |celsius: f32| {
+            derating.level = derating.level_at(celsius);
+            derating.level
+        };
+        assert_eq!(level(60.0), 0);
+        assert_eq!(level(72.0), 1);
+        // Within the hysteresis.
+        assert_eq!(level(67.0), 1);
+        assert_eq!(level(90.0), 2);
+        assert_eq!(level(81.0), 2);
+        assert_eq!(level(79.0), 1);
+        assert_eq!(level(64.0), 0);
+        assert_eq!([60.0f32, 86.0, 71.0].celsius(), 86.0);
+    }
+
+    #[test]
+    fn test_requests() {
+        let clock = RobotClock::default();
+        let requests = CuParamRequests::default();
+        let ctx = CuContext::from(&clock)
+            .with_node(3, "derating", None)
+            .with_param_requests(&requests);
+        let mut derating = derating();
+        derating.process(&ctx, &CuMsg::new(Some(60.0))).unwrap();
+        assert!(requests.take().is_empty());
+        assert_eq!(ctx.reported_health(), None);
+
+        derating.process(&ctx, &CuMsg::new(Some(90.0))).unwrap();
+        let requested: Vec<(String, String, Value)> = requests
+            .take()
+            .into_iter()
+            .map(|request| (request.task, request.key, request.value))
+            .collect();
+        assert_eq!(
+            requested,
+            vec![
+                ("camera".to_string(), "fps".to_string(), Value::from(5u32)),
+                (
+                    "planner".to_string(),
+                    "base_period_ns".to_string(),
+                    Value::from(400000000u64)
+                ),
+            ]
+        );
+        assert_eq!(derating.level, 2);
+        assert_eq!(ctx.reported_health(), Some(CuHealth::Degraded));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(parse_thresholds("85, 70").is_err());
+        assert!(parse_actions("camera.fps=30/15", 3).is_err());
+        assert!(parse_actions("fps=30/15/5", 3).is_err());
+        assert_eq!(parse_actions("camera.fps=30/15/5;", 3).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,166 @@
+use cu29::config::Value;
+use serde::{Deserialize, Serialize};
+
+/// A command sent by the fleet to the robot, handed over to the application once it has been accepted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum FleetCommand {
+    /// Run another mission.
+    SwitchMission { mission: String },
+    /// Send the last iterations to the fleet, see FleetLink::send_dump.
+    DumpFlightRecorder,
+    /// Change the parameter key of the task.
+    SetParam {
+        task: String,
+        key: String,
+        value: Value,
+    },
+}
+
+impl FleetCommand {
+    /// The name of the mutation in the permissions of the node: switch_mission, dump_flight_recorder or task.key.
+    pub fn mutation(&self) -> String {
+        match self {
+            FleetCommand::SwitchMission { .. } => "switch_mission".to_string(),
+            FleetCommand::DumpFlightRecorder => "dump_flight_recorder".to_string(),
+            FleetCommand::SetParam { task, key, .. } => format!("{}.{}", task, key),
+        }
+    }
+
+    /// A short description for the logs.
+    pub fn describe(&self) -> String {
+        match self {
+            FleetCommand::SwitchMission { mission } => format!("switch_mission {}", mission),
+            FleetCommand::DumpFlightRecorder => "dump_flight_recorder".to_string(),
+            FleetCommand::SetParam { task, key, value } => {
+                format!("set_param {}.{} = {:?}", task, key, value)
+            }
+        }
+    }
+}
+
+/// The answer of the robot to a command, sent with the next heartbeat.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CommandAck {
+    pub(crate) id: String,
+    pub(crate) accepted: bool,
+    pub(crate) reason: Option<String>,
+}
+
+/// The commands the robot accepts, everything else is rejected.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CommandPolicy {
+    /// The missions that can be switched to.
+    pub(crate) missions: Vec<String>,
+    /// The parameters that can be changed, as task.key.
+    pub(crate) params: Vec<String>,
+    pub(crate) allow_dump: bool,
+}
+
+impl CommandPolicy {
+    pub(crate) fn check(&self, command: &FleetCommand) -> Result<(), String> {
+        match command {
+            FleetCommand::SwitchMission { mission } => {
+                if !self.missions.contains(mission) {
+                    return Err(format!("mission '{}' is not allowed", mission));
+                }
+            }
+            FleetCommand::DumpFlightRecorder => {
+                if !self.allow_dump {
+                    return Err("flight recorder dumps are not allowed".to_string());
+                }
+            }
+            FleetCommand::SetParam { .. } => {
+                let param = command.mutation();
+                if !self.params.contains(&param) {
+                    return Err(format!("parameter '{}' is not allowed", param));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A command received with its id, or why it could not be understood.
+pub(crate) type ReceivedCommand = (String, Result<FleetCommand, String>);
+
+/// Parses the commands of the answer to a heartbeat: `{"commands": [{"id": "...", "command": "...", ...}]}`.
+/// A command that cannot be understood is returned as an error to be rejected without dropping the others.
+pub(crate) fn parse_commands(body: &str) -> Result<Vec<ReceivedCommand>, String> {
+    if body.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let body: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| format!("invalid answer: {}", e))?;
+    let Some(commands) = body.get("commands") else {
+        return Ok(Vec::new());
+    };
+    let commands = commands
+        .as_array()
+        .ok_or("invalid answer: 'commands' is not a list")?;
+    Ok(commands
+        .iter()
+        .map(|command| {
+            let id = command
+                .get("id")
+                .and_then(|id| id.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let command = serde_json::from_value::<FleetCommand>(command.clone())
+                .map_err(|e| format!("invalid command: {}", e));
+            (id, command)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_check() {
+        let body = r#"{"commands": [
+            {"id": "1", "command": "switch_mission", "mission": "dock"},
+            {"id": "2", "command": "dump_flight_recorder"},
+            {"id": "3", "command": "set_param", "task": "planner", "key": "max_speed", "value": 1.5},
+            {"id": "4", "command": "self_destruct"},
+            {"id": "5", "command": "switch_mission", "mission": "sprint"}
+        ]}"#;
+        let commands = parse_commands(body).unwrap();
+        assert_eq!(commands.len(), 5);
+        assert_eq!(
+            commands[0],
+            (
+                "1".to_string(),
+                Ok(FleetCommand::SwitchMission {
+                    mission: "dock".to_string()
+                })
+            )
+        );
+        assert_eq!(
+            commands[2].1,
+            Ok(FleetCommand::SetParam {
+                task: "planner".to_string(),
+                key: "max_speed".to_string(),
+                value: 1.5.into(),
+            })
+        );
+        assert!(commands[3].1.is_err());
+
+        let policy = CommandPolicy {
+            missions: vec!["patrol".to_string(), "dock".to_string()],
+            params: vec!["planner.max_speed".to_string()],
+            allow_dump: false,
+        };
+        let checks: Vec<_> = commands
+            .iter()
+            .filter_map(|(_, command)| command.as_ref().ok())
+            .map(|command| policy.check(command).is_ok())
+            .collect();
+        assert_eq!(checks, vec![true, false, true, false]);
+
+        assert!(parse_commands("").unwrap().is_empty());
+        assert!(parse_commands("{}").unwrap().is_empty());
+        assert!(parse_commands("not json").is_err());
+    }
+}
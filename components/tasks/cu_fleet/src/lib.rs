@@ -0,0 +1,156 @@
+mod command;
+mod link;
+mod report;
+
+pub use command::FleetCommand;
+pub use link::FleetLink;
+pub use report::HealthSummary;
+
+use bincode::{Decode, Encode};
+use command::CommandPolicy;
+use cu29::clock::RobotClock;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
+use cu29::lineage::CuRunId;
+use cu29::permissions::CuGuard;
+use cu29::threads::task_thread_name;
+use cu29::{output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use link::{FleetAgent, FleetShared};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const DEFAULT_HEARTBEAT_PERIOD_S: u32 = 5;
+
+/// The state of the link with the fleet, sent at every iteration.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+pub struct FleetStatus {
+    /// The last heartbeat went through.
+    pub connected: bool,
+    pub heartbeats: u64,
+    pub failures: u64,
+    pub commands_accepted: u64,
+    pub commands_rejected: u64,
+    /// The accepted commands the application has not taken yet.
+    pub pending_commands: u32,
+}
+
+/// Reports the robot to a fleet endpoint and receives its commands from a background thread so the copper
+/// loop is never blocked by the network. The task itself only reports the status of the link.
+pub struct CuFleet {
+    agent: Option<FleetAgent>,
+    worker: Option<JoinHandle<()>>,
+    link: FleetLink,
+}
+
+impl CuFleet {
+    /// The link to report the health and mission and to take the commands while the application runs.
+    pub fn link(&self) -> FleetLink {
+        self.link.clone()
+    }
+}
+
+impl Freezable for CuFleet {
+    // Nothing to keep, the fleet sees the robot again at the next heartbeat.
+}
+
+/// "a,b,c" to a list, empty for an absent or empty parameter.
+fn list_param(config: &ComponentConfig, key: &str) -> Vec<String> {
+    config
+        .get::<String>(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+impl CuTaskLifecycle for CuFleet {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("CuFleet needs a config with a 'robot_id' and an 'endpoint'.")?;
+        let robot_id: String = config
+            .get::<String>("robot_id")
+            .ok_or("'robot_id' not found in the CuFleet config")?;
+        let endpoint: String = config
+            .get::<String>("endpoint")
+            .ok_or("'endpoint' not found in the CuFleet config")?;
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            return Err(format!(
+                "Unsupported fleet endpoint '{}', it must start with http:// or https://.",
+                endpoint
+            )
+            .into());
+        }
+        let period = Duration::from_secs(
+            config
+                .get::<u32>("heartbeat_period_s")
+                .unwrap_or(DEFAULT_HEARTBEAT_PERIOD_S)
+                .max(1) as u64,
+        );
+        let policy = CommandPolicy {
+            missions: list_param(config, "missions"),
+            params: list_param(config, "params"),
+            allow_dump: config.get::<bool>("allow_dump").unwrap_or(true),
+        };
+        debug!("Fleet: reporting {} to {}", &robot_id, &endpoint);
+
+        let shared = Arc::new(FleetShared {
+            mission_task: config.get::<String>("mission_task"),
+            ..Default::default()
+        });
+        Ok(CuFleet {
+            agent: Some(FleetAgent::new(
+                &endpoint,
+                &robot_id,
+                CuRunId::from_task_config(Some(config)),
+                period,
+                policy,
+                CuGuard::from_config("fleet", Some(config))?,
+                shared.clone(),
+            )),
+            worker: None,
+            link: FleetLink { shared },
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        let agent = self
+            .agent
+            .take()
+            .ok_or("The fleet link cannot be started twice.")?;
+        let worker = std::thread::Builder::new()
+            .name(task_thread_name("fleet"))
+            .spawn(move || agent.run())
+            .map_err(|e| CuError::new_with_cause("Could not start the fleet thread", e))?;
+        let _ = self.link.shared.worker.set(worker.thread().clone());
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        if let Some(worker) = self.worker.take() {
+            self.link.shared.stop.store(true, Ordering::Relaxed);
+            self.link.shared.wake_worker();
+            worker
+                .join()
+                .map_err(|_| CuError::from("The fleet thread panicked."))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'cl> CuSrcTask<'cl> for CuFleet {
+    type Output = output_msg!('cl, FleetStatus);
+
+    fn process(&mut self, _ctx: &CuContext, output: Self::Output) -> CuResult<()> {
+        output.set_payload(self.link.status());
+        Ok(())
+    }
+}
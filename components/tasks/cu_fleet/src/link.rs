@@ -0,0 +1,348 @@
+use crate::command::{parse_commands, CommandAck, CommandPolicy, FleetCommand};
+use crate::report::{HealthSummary, Heartbeat};
+use crate::FleetStatus;
+use cu29::copperlist::CuListDump;
+use cu29::introspection::CuGraphInfo;
+use cu29::lineage::CuRunId;
+use cu29::permissions::{CuGuard, CuRoleToken};
+use cu29_log_derive::debug;
+use cu29_traits::{CuError, CuResult};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::thread::Thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use ureq::{Agent, AgentBuilder};
+
+/// The commands accepted but not taken by the application yet, the oldest are dropped beyond this.
+const MAX_PENDING_COMMANDS: usize = 64;
+
+/// What the application reports, sent with the next heartbeat.
+#[derive(Default)]
+pub(crate) struct Report {
+    health: HealthSummary,
+    mission: Option<String>,
+    acks: Vec<CommandAck>,
+    dumps: Vec<String>,
+}
+
+/// The state shared by the task, its heartbeat thread and the links given to the application.
+#[derive(Default)]
+pub(crate) struct FleetShared {
+    pub(crate) report: Mutex<Report>,
+    pub(crate) commands: Mutex<VecDeque<FleetCommand>>,
+    /// The role the remote commands are executed with, none are accepted without it.
+    pub(crate) role: Mutex<Option<CuRoleToken>>,
+    /// The task giving the mission state in its state, see CuGraphInfo.
+    pub(crate) mission_task: Option<String>,
+    pub(crate) stop: AtomicBool,
+    pub(crate) connected: AtomicBool,
+    pub(crate) heartbeats: AtomicU64,
+    pub(crate) failures: AtomicU64,
+    pub(crate) accepted: AtomicU64,
+    pub(crate) rejected: AtomicU64,
+    /// The heartbeat thread, to wake it up.
+    pub(crate) worker: OnceLock<Thread>,
+}
+
+impl FleetShared {
+    pub(crate) fn wake_worker(&self) {
+        if let Some(worker) = self.worker.get() {
+            worker.unpark();
+        }
+    }
+}
+
+/// The link between the application and the fleet: it reports the health and mission of the robot
+/// and hands over the remote commands. Get it from the task before running the application.
+#[derive(Clone)]
+pub struct FleetLink {
+    pub(crate) shared: Arc<FleetShared>,
+}
+
+impl FleetLink {
+    /// Reports the health of the tasks, and the mission state if a mission_task is configured.
+    /// Call it from time to time with `application.introspect()`.
+    pub fn report(&self, info: &CuGraphInfo) {
+        let health = HealthSummary::from_graph(info);
+        let mission = self
+            .shared
+            .mission_task
+            .as_deref()
+            .and_then(|task| info.get_node(task))
+            .and_then(|node| node.state.clone());
+        let mut report = self.shared.report.lock().unwrap();
+        report.health = health;
+        if mission.is_some() {
+            report.mission = mission;
+        }
+    }
+
+    /// Sets the role of the fleet: the remote commands are accepted if the permissions of the node allow it.
+    /// The application gives it once it has authenticated the fleet, without it every command is rejected.
+    pub fn grant(&self, token: CuRoleToken) {
+        *self.shared.role.lock().unwrap() = Some(token);
+    }
+
+    /// Sets the mission state sent to the fleet.
+    pub fn set_mission(&self, mission: &str) {
+        self.shared.report.lock().unwrap().mission = Some(mission.to_string());
+    }
+
+    /// The next command accepted from the fleet, oldest first. The application is in charge of executing it.
+    pub fn next_command(&self) -> Option<FleetCommand> {
+        self.shared.commands.lock().unwrap().pop_front()
+    }
+
+    /// Sends a dump of the last iteration to the fleet, typically to answer DumpFlightRecorder.
+    /// It is sent by the heartbeat thread right away.
+    pub fn send_dump(&self, dump: &CuListDump) -> CuResult<()> {
+        let json = dump.to_json()?;
+        self.shared.report.lock().unwrap().dumps.push(json);
+        self.shared.wake_worker();
+        Ok(())
+    }
+
+    pub fn status(&self) -> FleetStatus {
+        let shared = &self.shared;
+        FleetStatus {
+            connected: shared.connected.load(Ordering::Relaxed),
+            heartbeats: shared.heartbeats.load(Ordering::Relaxed),
+            failures: shared.failures.load(Ordering::Relaxed),
+            commands_accepted: shared.accepted.load(Ordering::Relaxed),
+            commands_rejected: shared.rejected.load(Ordering::Relaxed),
+            pending_commands: shared.commands.lock().unwrap().len() as u32,
+        }
+    }
+}
+
+/// Sends the heartbeats from its own thread and receives the commands in their answers.
+/// `POST <endpoint>/<robot_id>/heartbeat` and `POST <endpoint>/<robot_id>/dump`.
+pub(crate) struct FleetAgent {
+    pub(crate) endpoint: String,
+    pub(crate) robot_id: String,
+    /// The run of the application, reported so the fleet stitches the history of the robot across its restarts.
+    pub(crate) run: Option<CuRunId>,
+    pub(crate) period: Duration,
+    pub(crate) policy: CommandPolicy,
+    pub(crate) guard: CuGuard,
+    pub(crate) shared: Arc<FleetShared>,
+    pub(crate) agent: Agent,
+}
+
+impl FleetAgent {
+    pub(crate) fn new(
+        endpoint: &str,
+        robot_id: &str,
+        run: Option<CuRunId>,
+        period: Duration,
+        policy: CommandPolicy,
+        guard: CuGuard,
+        shared: Arc<FleetShared>,
+    ) -> Self {
+        FleetAgent {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            robot_id: robot_id.to_string(),
+            run,
+            period,
+            policy,
+            guard,
+            shared,
+            agent: AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(5))
+                .timeout(Duration::from_secs(10))
+                .build(),
+        }
+    }
+
+    /// Sends a heartbeat every period until stopped, unparking the thread sends the pending dumps.
+    pub(crate) fn run(self) {
+        let started = Instant::now();
+        let mut sequence = 0u64;
+        let mut next_heartbeat = Instant::now();
+        while !self.shared.stop.load(Ordering::Relaxed) {
+            if let Err(e) = self.send_dumps() {
+                debug!("Fleet: could not send a dump: {}", e.to_string());
+            }
+            if Instant::now() >= next_heartbeat {
+                let result = self.heartbeat(sequence, started.elapsed());
+                self.shared
+                    .connected
+                    .store(result.is_ok(), Ordering::Relaxed);
+                match result {
+                    Ok(()) => {
+                        self.shared.heartbeats.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        self.shared.failures.fetch_add(1, Ordering::Relaxed);
+                        debug!("Fleet: heartbeat failed: {}", e.to_string());
+                    }
+                }
+                sequence += 1;
+                next_heartbeat = Instant::now() + self.period;
+            }
+            thread::park_timeout(next_heartbeat.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    fn url(&self, what: &str) -> String {
+        format!("{}/{}/{}", self.endpoint, self.robot_id, what)
+    }
+
+    fn post(&self, what: &str, body: &str) -> CuResult<String> {
+        self.agent
+            .post(&self.url(what))
+            .set("Content-Type", "application/json")
+            .send_string(body)
+            .map_err(|e| CuError::new_with_cause(&format!("POST {} failed", what), e))?
+            .into_string()
+            .map_err(|e| {
+                CuError::new_with_cause(&format!("Could not read the answer to {}", what), e)
+            })
+    }
+
+    fn send_dumps(&self) -> CuResult<()> {
+        let dumps = std::mem::take(&mut self.shared.report.lock().unwrap().dumps);
+        for dump in dumps {
+            self.post("dump", &dump)?;
+            debug!("Fleet: flight recorder dump sent");
+        }
+        Ok(())
+    }
+
+    fn heartbeat(&self, sequence: u64, uptime: Duration) -> CuResult<()> {
+        // The acks are taken out so they are sent again on a failure.
+        let (body, acks) = {
+            let mut report = self.shared.report.lock().unwrap();
+            let acks = std::mem::take(&mut report.acks);
+            let heartbeat = Heartbeat {
+                robot_id: &self.robot_id,
+                run: self.run.as_ref(),
+                sequence,
+                timestamp_ms: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+                uptime_s: uptime.as_secs(),
+                health: &report.health,
+                mission: report.mission.as_deref(),
+                acks: &acks,
+            };
+            let body = serde_json::to_string(&heartbeat)
+                .map_err(|e| CuError::new_with_cause("Could not serialize the heartbeat", e))?;
+            (body, acks)
+        };
+        let answer = match self.post("heartbeat", &body) {
+            Ok(answer) => answer,
+            Err(e) => {
+                let mut report = self.shared.report.lock().unwrap();
+                let newer = std::mem::replace(&mut report.acks, acks);
+                report.acks.extend(newer);
+                return Err(e);
+            }
+        };
+        let commands = parse_commands(&answer).map_err(CuError::from)?;
+        for (id, command) in commands {
+            self.receive(id, command);
+        }
+        Ok(())
+    }
+
+    /// Logs the command and queues it for the application if the policy and the role of the fleet allow it.
+    fn receive(&self, id: String, command: Result<FleetCommand, String>) {
+        let checked = command.and_then(|command| {
+            self.policy.check(&command)?;
+            let role = self.shared.role.lock().unwrap();
+            self.guard
+                .authorize(role.as_ref(), &command.mutation())
+                .map_err(|e| e.to_string())?;
+            Ok(command)
+        });
+        let ack = match checked {
+            Ok(command) => {
+                debug!("Fleet: accepted command {}: {}", &id, command.describe());
+                self.shared.accepted.fetch_add(1, Ordering::Relaxed);
+                let mut commands = self.shared.commands.lock().unwrap();
+                if commands.len() >= MAX_PENDING_COMMANDS {
+                    if let Some(dropped) = commands.pop_front() {
+                        debug!(
+                            "Fleet: dropped the command {} never taken by the application",
+                            dropped.describe()
+                        );
+                    }
+                }
+                commands.push_back(command);
+                CommandAck {
+                    id,
+                    accepted: true,
+                    reason: None,
+                }
+            }
+            Err(reason) => {
+                debug!("Fleet: rejected command {}: {}", &id, &reason);
+                self.shared.rejected.fetch_add(1, Ordering::Relaxed);
+                CommandAck {
+                    id,
+                    accepted: false,
+                    reason: Some(reason),
+                }
+            }
+        };
+        self.shared.report.lock().unwrap().acks.push(ack);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::permissions::CuRole;
+
+    #[test]
+    fn test_receive() {
+        let shared = Arc::new(FleetShared::default());
+        let policy = CommandPolicy {
+            missions: vec!["dock".to_string()],
+            params: Vec::new(),
+            allow_dump: true,
+        };
+        let guard = CuGuard::new("fleet").with_level("switch_mission", CuRole::Operator);
+        let agent = FleetAgent::new(
+            "http://localhost:1/",
+            "r1",
+            None,
+            Duration::from_secs(1),
+            policy,
+            guard,
+            shared.clone(),
+        );
+        let link = FleetLink {
+            shared: shared.clone(),
+        };
+        assert_eq!(agent.url("heartbeat"), "http://localhost:1/r1/heartbeat");
+        let dock = FleetCommand::SwitchMission {
+            mission: "dock".to_string(),
+        };
+        // Nothing is accepted before the application grants a role to the fleet.
+        agent.receive("0".to_string(), Ok(dock.clone()));
+        link.grant(CuRoleToken::new(CuRole::Operator, "fleet"));
+        agent.receive("1".to_string(), Ok(dock.clone()));
+        agent.receive(
+            "2".to_string(),
+            Ok(FleetCommand::SwitchMission {
+                mission: "race".to_string(),
+            }),
+        );
+        agent.receive("3".to_string(), Err("invalid command".to_string()));
+
+        let status = link.status();
+        assert_eq!(status.commands_accepted, 1);
+        assert_eq!(status.commands_rejected, 3);
+        assert_eq!(status.pending_commands, 1);
+        assert_eq!(link.next_command(), Some(dock));
+        assert_eq!(link.next_command(), None);
+        let acks = &link.shared.report.lock().unwrap().acks;
+        let accepted: Vec<_> = acks.iter().map(|ack| ack.accepted).collect();
+        assert_eq!(accepted, vec![false, true, false, false]);
+    }
+}
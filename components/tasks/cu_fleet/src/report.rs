@@ -0,0 +1,81 @@
+use crate::command::CommandAck;
+use cu29::introspection::CuGraphInfo;
+use cu29::lineage::CuRunId;
+use cu29::monitoring::CuHealth;
+use serde::Serialize;
+
+/// The health of the whole application: the worst health of its tasks and the tasks that are not nominal.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HealthSummary {
+    pub overall: CuHealth,
+    pub degraded: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl HealthSummary {
+    pub fn from_graph(info: &CuGraphInfo) -> Self {
+        let mut summary = HealthSummary::default();
+        for node in &info.nodes {
+            match node.health {
+                CuHealth::Degraded => summary.degraded.push(node.id.clone()),
+                CuHealth::Failed => summary.failed.push(node.id.clone()),
+                _ => {}
+            }
+        }
+        let all_unknown = info.nodes.iter().all(|n| n.health == CuHealth::Unknown);
+        summary.overall = if !summary.failed.is_empty() {
+            CuHealth::Failed
+        } else if !summary.degraded.is_empty() {
+            CuHealth::Degraded
+        } else if all_unknown {
+            CuHealth::Unknown
+        } else {
+            CuHealth::Nominal
+        };
+        summary
+    }
+}
+
+/// What the robot posts to the fleet endpoint at every heartbeat.
+#[derive(Debug, Serialize)]
+pub(crate) struct Heartbeat<'a> {
+    pub(crate) robot_id: &'a str,
+    pub(crate) run: Option<&'a CuRunId>,
+    pub(crate) sequence: u64,
+    /// Milliseconds since the Unix epoch on the robot.
+    pub(crate) timestamp_ms: u64,
+    pub(crate) uptime_s: u64,
+    pub(crate) health: &'a HealthSummary,
+    pub(crate) mission: Option<&'a str>,
+    /// The answers to the commands received since the previous heartbeat.
+    pub(crate) acks: &'a [CommandAck],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::config::{CuConfig, Node};
+
+    #[test]
+    fn test_health_summary() {
+        let mut config = CuConfig::default();
+        let src = config.add_node(Node::new("src", "tasks::Src"));
+        let ctrl = config.add_node(Node::new("ctrl", "tasks::Ctrl"));
+        let sink = config.add_node(Node::new("sink", "tasks::Sink"));
+        config.connect(src, ctrl, "i32");
+        config.connect(ctrl, sink, "i32");
+        let mut info = CuGraphInfo::from_config(&config);
+        assert_eq!(HealthSummary::from_graph(&info).overall, CuHealth::Unknown);
+
+        info.nodes[0].health = CuHealth::Nominal;
+        info.nodes[1].health = CuHealth::Degraded;
+        let summary = HealthSummary::from_graph(&info);
+        assert_eq!(summary.overall, CuHealth::Degraded);
+        assert_eq!(summary.degraded, vec!["ctrl".to_string()]);
+
+        info.nodes[2].health = CuHealth::Failed;
+        let summary = HealthSummary::from_graph(&info);
+        assert_eq!(summary.overall, CuHealth::Failed);
+        assert_eq!(summary.failed, vec!["sink".to_string()]);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::joint_names;
+use cu29::clock::{CuDuration, CuTime, RobotClock};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_traits::CuError;
+use cu_spatial_payloads::{JointState, JointStates};
+
+/// Aggregates the JointState of N joints, its inputs in the order of the "joints" of its config, into one
+/// JointStates.
+///
+/// A joint without a new message keeps its last state. Nothing is published until every joint reported, nor when
+/// the last state of a joint is older than timeout_ms if set, the output status names the missing joints. The
+/// velocities and the efforts are only published when all the joints have them. From 2 to 8 joints.
+pub struct JointStatesAggregator<const N: usize> {
+    joints: Vec<String>,
+    timeout: Option<CuDuration>,
+    /// The last state of each joint and when it was received.
+    last: [Option<(JointState, CuTime)>; N],
+}
+
+impl<const N: usize> Freezable for JointStatesAggregator<N> {}
+
+impl<const N: usize> CuTaskLifecycle for JointStatesAggregator<N> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("JointStatesAggregator needs a config with its joints.")?;
+        let joints = joint_names(config, "JointStatesAggregator")?;
+        if joints.len() != N {
+            return Err(CuError::from(format!(
+                "JointStatesAggregator<{}> has {} joints in its config.",
+                N,
+                joints.len()
+            )));
+        }
+        Ok(JointStatesAggregator {
+            joints,
+            timeout: config
+                .get::<u32>("timeout_ms")
+                .map(|ms| CuDuration::from(ms as u64 * 1_000_000)),
+            last: [None; N],
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.last = [None; N];
+        Ok(())
+    }
+}
+
+impl<const N: usize> JointStatesAggregator<N> {
+    fn aggregate(
+        &mut self,
+        now: CuTime,
+        inputs: [&CuMsg<JointState>; N],
+        output: &mut CuMsg<JointStates>,
+    ) {
+        for (last, input) in self.last.iter_mut().zip(inputs.iter()) {
+            if let Some(state) = input.payload() {
+                *last = Some((*state, now));
+            }
+        }
+        let missing: Vec<&str> = self
+            .last
+            .iter()
+            .zip(&self.joints)
+            .filter(|(last, _)| match (last, self.timeout) {
+                (None, _) => true,
+                (Some((_, received)), Some(timeout)) => now - *received > timeout,
+                (Some(_), None) => false,
+            })
+            .map(|(_, name)| name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            output.clear_payload();
+            output
+                .metadata
+                .set_status(format!("missing {}", missing.join(", ")));
+            return;
+        }
+
+        let states = self.last.iter().flatten().map(|(state, _)| state);
+        let all = |values: Vec<Option<f64>>| -> Vec<f64> {
+            values
+                .into_iter()
+                .collect::<Option<_>>()
+                .unwrap_or_default()
+        };
+        output.set_payload(JointStates {
+            positions: states.clone().map(|state| state.position).collect(),
+            velocities: all(states.clone().map(|state| state.velocity).collect()),
+            efforts: all(states.map(|state| state.effort).collect()),
+        });
+        // The time of the most recent feedback.
+        output.metadata.tov = inputs
+            .iter()
+            .filter_map(|input| -> Option<CuTime> { input.metadata.tov.into() })
+            .max()
+            .into();
+    }
+}
+
+/// The CuTask of the aggregators of 2 to 8 joints, whose inputs are tuples.
+macro_rules! impl_joint_states_aggregator {
+    ($($n:literal: ($($input:ident),+)),+) => {
+        $(
+            impl<'cl> CuTask<'cl> for JointStatesAggregator<$n> {
+                type Input = input_msg!('cl, $(impl_joint_states_aggregator!(@state $input)),+);
+                type Output = output_msg!('cl, JointStates);
+
+                fn process(
+                    &mut self,
+                    ctx: &CuContext,
+                    input: Self::Input,
+                    output: Self::Output,
+                ) -> CuResult<()> {
+                    let ($($input,)+) = input;
+                    self.aggregate(ctx.now(), [$($input),+], output);
+                    Ok(())
+                }
+            }
+        )+
+    };
+    (@state $input:ident) => {
+        JointState
+    };
+}
+
+impl_joint_states_aggregator! {
+    2: (j1, j2),
+    3: (j1, j2, j3),
+    4: (j1, j2, j3, j4),
+    5: (j1, j2, j3, j4, j5),
+    6: (j1, j2, j3, j4, j5, j6),
+    7: (j1, j2, j3, j4, j5, j6, j7),
+    8: (j1, j2, j3, j4, j5, j6, j7, j8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn state(position: f64, velocity: Option<f64>) -> CuMsg<JointState> {
+        CuMsg::new(Some(JointState {
+            position,
+            velocity,
+            effort: None,
+        }))
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut config = ComponentConfig::new();
+        config.set("joints", "shoulder, elbow, wrist".to_string());
+        config.set("timeout_ms", 100u32);
+        let mut aggregator = JointStatesAggregator::<3>::new(Some(&config)).unwrap();
+        let (clock, mock) = RobotClock::mock();
+        let mut output = CuMsg::new(None);
+
+        let none = CuMsg::new(None);
+        let (shoulder, elbow) = (state(0.1, Some(1.0)), state(0.2, Some(2.0)));
+        aggregator
+            .process(
+                &CuContext::from(&clock),
+                (&shoulder, &elbow, &none),
+                &mut output,
+            )
+            .unwrap();
+        assert!(output.payload().is_none());
+        assert_eq!(output.metadata.status_txt.0.as_str(), "missing wrist");
+
+        // The shoulder and the elbow keep their state.
+        mock.increment(Duration::from_millis(50));
+        let wrist = state(0.3, Some(3.0));
+        aggregator
+            .process(
+                &CuContext::from(&clock),
+                (&none, &none, &wrist),
+                &mut output,
+            )
+            .unwrap();
+        let states = output.payload().unwrap();
+        assert_eq!(states.positions, [0.1, 0.2, 0.3]);
+        assert_eq!(states.velocities, [1.0, 2.0, 3.0]);
+        assert!(states.efforts.is_empty());
+
+        // The shoulder and the elbow are now too old.
+        mock.increment(Duration::from_millis(60));
+        let wrist = state(0.4, None);
+        aggregator
+            .process(
+                &CuContext::from(&clock),
+                (&none, &none, &wrist),
+                &mut output,
+            )
+            .unwrap();
+        assert!(output.payload().is_none());
+        assert_eq!(
+            output.metadata.status_txt.0.as_str(),
+            "missing shoulder, elbow"
+        );
+
+        let (shoulder, elbow) = (state(0.1, Some(1.0)), state(0.2, Some(2.0)));
+        aggregator
+            .process(
+                &CuContext::from(&clock),
+                (&shoulder, &elbow, &none),
+                &mut output,
+            )
+            .unwrap();
+        let states = output.payload().unwrap();
+        assert_eq!(states.positions, [0.1, 0.2, 0.4]);
+        // The wrist has no velocity anymore.
+        assert!(states.velocities.is_empty());
+
+        assert!(JointStatesAggregator::<2>::new(Some(&config)).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Generic glue for the arms: the feedback of the joints, one message per joint, aggregated into one JointStates,
+//! and a JointCommands demultiplexed into one JointCommand per joint. Both are driven by the list of the names of
+//! the joints in their config, which gives the order of the joints in the arrays:
+//!
+//! ```ron
+//! (id: "arm_state", type: "cu_joints::JointStatesAggregator<3>", config: {"joints": "shoulder, elbow, wrist"}),
+//! (id: "elbow_cmd", type: "cu_joints::JointCommandSelect", config: {"joints": "shoulder, elbow, wrist", "joint": "elbow"}),
+//! ```
+
+mod aggregate;
+mod select;
+
+pub use aggregate::JointStatesAggregator;
+pub use select::JointCommandSelect;
+
+use cu29::config::ComponentConfig;
+use cu29::CuResult;
+use cu29_traits::CuError;
+
+/// The names of the joints from the "joints" key of the config, ie. "shoulder, elbow, wrist".
+pub(crate) fn joint_names(config: &ComponentConfig, task: &str) -> CuResult<Vec<String>> {
+    let joints = config
+        .get::<String>("joints")
+        .ok_or_else(|| format!("'joints' not found in the {} config", task))?;
+    let names: Vec<String> = joints
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .collect();
+    if names.iter().any(|name| name.is_empty()) {
+        return Err(CuError::from(format!(
+            "An empty joint name in '{}' in the {} config.",
+            joints, task
+        )));
+    }
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(CuError::from(format!(
+                "The joint '{}' is listed twice in the {} config.",
+                name, task
+            )));
+        }
+    }
+    Ok(names)
+}
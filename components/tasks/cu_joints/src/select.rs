@@ -0,0 +1,126 @@
+use crate::joint_names;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_traits::CuError;
+use cu_spatial_payloads::{JointCommand, JointCommands};
+
+/// Selects the JointCommand of one joint, its "joint" in the "joints" of the config, from a JointCommands. One
+/// per joint connects the JointCommands to the sinks driving the joints.
+pub struct JointCommandSelect {
+    joints: usize,
+    index: usize,
+}
+
+impl Freezable for JointCommandSelect {}
+
+impl CuTaskLifecycle for JointCommandSelect {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("JointCommandSelect needs a config with its joints.")?;
+        let joints = joint_names(config, "JointCommandSelect")?;
+        let joint = config
+            .get::<String>("joint")
+            .ok_or("'joint' not found in the JointCommandSelect config")?;
+        let index = joints
+            .iter()
+            .position(|name| *name == joint)
+            .ok_or_else(|| {
+                CuError::from(format!(
+                    "The joint '{}' is not in the joints {:?} of the JointCommandSelect config.",
+                    joint, joints
+                ))
+            })?;
+        Ok(JointCommandSelect {
+            joints: joints.len(),
+            index,
+        })
+    }
+}
+
+impl JointCommandSelect {
+    /// The setpoint of the joint in one of the vectors, None if the vector is not controlled.
+    fn setpoint(&self, values: &[f64], what: &str) -> CuResult<Option<f64>> {
+        match values.len() {
+            0 => Ok(None),
+            len if len == self.joints => Ok(Some(values[self.index])),
+            len => Err(CuError::from(format!(
+                "Expected {} joint {}, got {}.",
+                self.joints, what, len
+            ))),
+        }
+    }
+}
+
+impl<'cl> CuTask<'cl> for JointCommandSelect {
+    type Input = input_msg!('cl, JointCommands);
+    type Output = output_msg!('cl, JointCommand);
+
+    fn process(
+        &mut self,
+        _ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let Some(commands) = input.payload() else {
+            output.clear_payload();
+            return Ok(());
+        };
+        output.set_payload(JointCommand {
+            position: self.setpoint(&commands.positions, "positions")?,
+            velocity: self.setpoint(&commands.velocities, "velocities")?,
+            effort: self.setpoint(&commands.efforts, "efforts")?,
+        });
+        output.metadata.tov = input.metadata.tov;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+
+    #[test]
+    fn test_select() {
+        let mut config = ComponentConfig::new();
+        config.set("joints", "shoulder, elbow, wrist".to_string());
+        config.set("joint", "elbow".to_string());
+        let mut select = JointCommandSelect::new(Some(&config)).unwrap();
+        let clock = RobotClock::default();
+        let mut output = CuMsg::new(None);
+
+        let mut input = CuMsg::new(Some(JointCommands {
+            positions: vec![0.1, 0.2, 0.3],
+            velocities: vec![],
+            efforts: vec![1.0, 2.0, 3.0],
+        }));
+        select
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert_eq!(
+            output.payload(),
+            Some(&JointCommand {
+                position: Some(0.2),
+                velocity: None,
+                effort: Some(2.0),
+            })
+        );
+
+        input.set_payload(JointCommands {
+            positions: vec![0.1, 0.2],
+            ..Default::default()
+        });
+        assert!(select
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .is_err());
+
+        config.set("joint", "gripper".to_string());
+        assert!(JointCommandSelect::new(Some(&config)).is_err());
+        config.set("joints", "shoulder, shoulder".to_string());
+        assert!(JointCommandSelect::new(Some(&config)).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{parse_list, publish, LimitKind, LimitsStatus, Margin, ViolationFilter};
+use cu29::clock::CuTime;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_traits::CuError;
+use cu_spatial_payloads::Pose;
+
+/// A workspace polygon in the x, y plane.
+pub struct Geofence {
+    vertices: Vec<(f64, f64)>,
+}
+
+impl Geofence {
+    pub fn new(vertices: Vec<(f64, f64)>) -> CuResult<Self> {
+        if vertices.len() < 3 {
+            return Err("A geofence needs at least 3 vertices.".into());
+        }
+        Ok(Geofence { vertices })
+    }
+
+    /// Parses "x,y; x,y; ...".
+    pub fn parse(s: &str) -> CuResult<Self> {
+        let vertices = s
+            .split(';')
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| match parse_list(v)?.as_slice() {
+                [x, y] => Ok((*x, *y)),
+                _ => Err(CuError::from(format!(
+                    "Invalid vertex '{}', expected 'x,y'",
+                    v
+                ))),
+            })
+            .collect::<CuResult<Vec<_>>>()?;
+        Self::new(vertices)
+    }
+
+    fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+    }
+
+    /// Ray casting point in polygon.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.edges()
+            .filter(|((x1, y1), (x2, y2))| {
+                (y1 > &y) != (y2 > &y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1
+            })
+            .count()
+            % 2
+            == 1
+    }
+
+    /// Distance to the border, positive inside and negative outside.
+    pub fn signed_distance(&self, x: f64, y: f64) -> f64 {
+        let distance = self
+            .edges()
+            .map(|((x1, y1), (x2, y2))| {
+                let (dx, dy) = (x2 - x1, y2 - y1);
+                let len2 = dx * dx + dy * dy;
+                let t = if len2 > 0.0 {
+                    (((x - x1) * dx + (y - y1) * dy) / len2).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                ((x - (x1 + t * dx)).powi(2) + (y - (y1 + t * dy)).powi(2)).sqrt()
+            })
+            .fold(f64::INFINITY, f64::min);
+        if self.contains(x, y) {
+            distance
+        } else {
+            -distance
+        }
+    }
+}
+
+/// Checks a Pose against a workspace polygon and a maximum speed.
+pub struct GeofenceTask {
+    geofence: Option<Geofence>,
+    hysteresis: f64,
+    max_speed: Option<f64>,
+    speed_hysteresis: f64,
+    filter: ViolationFilter,
+    last: Option<(Pose, CuTime)>,
+    /// The checks of the last input, kept to not allocate them on every process.
+    margins: Vec<Margin>,
+}
+
+impl Freezable for GeofenceTask {}
+
+impl CuTaskLifecycle for GeofenceTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("GeofenceTask needs a config.")?;
+        let geofence = config
+            .get::<String>("polygon")
+            .map(|p| Geofence::parse(&p))
+            .transpose()?;
+        let max_speed = config.get::<f64>("max_speed");
+        if geofence.is_none() && max_speed.is_none() {
+            return Err("GeofenceTask needs at least a 'polygon' or a 'max_speed'.".into());
+        }
+        Ok(GeofenceTask {
+            geofence,
+            hysteresis: config.get::<f64>("hysteresis").unwrap_or(0.0),
+            max_speed,
+            speed_hysteresis: config.get::<f64>("speed_hysteresis").unwrap_or(0.0),
+            filter: ViolationFilter::from_config(config),
+            last: None,
+            margins: Vec::new(),
+        })
+    }
+}
+
+impl<'cl> CuTask<'cl> for GeofenceTask {
+    type Input = input_msg!('cl, Pose);
+    type Output = output_msg!('cl, LimitsStatus);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        // Without a new pose we keep on publishing the last known status.
+        if let Some(pose) = input.payload() {
+            let tov: Option<CuTime> = input.metadata.tov.into();
+            let now = tov.unwrap_or(ctx.now());
+            self.margins.clear();
+            if let Some(geofence) = &self.geofence {
+                self.margins.push(Margin {
+                    kind: LimitKind::Geofence,
+                    margin: geofence.signed_distance(pose.x, pose.y),
+                    hysteresis: self.hysteresis,
+                });
+            }
+            if let (Some(max_speed), Some((last_pose, last_time))) = (self.max_speed, self.last) {
+                if now > last_time {
+                    let dt = now.0.saturating_sub(last_time.0) as f64 / 1e9;
+                    self.margins.push(Margin {
+                        kind: LimitKind::Speed,
+                        margin: max_speed - pose.distance(&last_pose) / dt,
+                        hysteresis: self.speed_hysteresis,
+                    });
+                }
+            }
+            self.last = Some((*pose, now));
+            self.filter.update(now, &self.margins);
+        }
+        publish(self.filter.status(), output);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+
+    #[test]
+    fn test_signed_distance() {
+        let fence = Geofence::parse("0,0; 10,0; 10,5; 0,5").unwrap();
+        assert!(fence.contains(1.0, 1.0));
+        assert!(!fence.contains(11.0, 1.0));
+        assert!((fence.signed_distance(1.0, 2.0) - 1.0).abs() < 1e-9);
+        assert!((fence.signed_distance(12.0, 2.0) + 2.0).abs() < 1e-9);
+        assert!(Geofence::parse("0,0; 1,1").is_err());
+    }
+
+    #[test]
+    fn test_geofence_task() {
+        let mut config = ComponentConfig::new();
+        config.set("polygon", "0,0; 10,0; 10,5; 0,5".to_string());
+        config.set("max_speed", 2.0);
+        let mut task = GeofenceTask::new(Some(&config)).unwrap();
+        let clock = RobotClock::default();
+        let mut input = CuMsg::new(Some(Pose::new_2d(1.0, 1.0, 0.0)));
+        let mut output = CuMsg::new(None);
+
+        input.metadata.tov = CuTime::from(0).into();
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert!(!output.payload().unwrap().violated);
+
+        // 1m in 1s, still ok.
+        input.set_payload(Pose::new_2d(2.0, 1.0, 0.0));
+        input.metadata.tov = CuTime::from(1_000_000_000).into();
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert!(!output.payload().unwrap().violated);
+
+        // 10m in 1s and out of the fence.
+        input.set_payload(Pose::new_2d(12.0, 1.0, 0.0));
+        input.metadata.tov = CuTime::from(2_000_000_000).into();
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        let status = output.payload().unwrap();
+        assert!(status.violated);
+        assert_eq!(status.crossed, vec![LimitKind::Geofence, LimitKind::Speed]);
+    }
+}
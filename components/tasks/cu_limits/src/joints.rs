@@ -0,0 +1,171 @@
+use crate::{parse_list, publish, LimitKind, LimitsStatus, Margin, ViolationFilter};
+use cu29::clock::CuTime;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu_spatial_payloads::JointStates;
+
+/// Checks JointStates against position ranges and maximum velocities.
+pub struct JointLimitsTask {
+    min_positions: Vec<f64>,
+    max_positions: Vec<f64>,
+    max_velocities: Option<Vec<f64>>,
+    hysteresis: f64,
+    speed_hysteresis: f64,
+    filter: ViolationFilter,
+    /// The checks of the last input, kept to not allocate them on every process.
+    margins: Vec<Margin>,
+}
+
+impl Freezable for JointLimitsTask {}
+
+impl CuTaskLifecycle for JointLimitsTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("JointLimitsTask needs a config.")?;
+        let min_positions = parse_list(
+            &config
+                .get::<String>("min_positions")
+                .ok_or("'min_positions' not found in the JointLimitsTask config")?,
+        )?;
+        let max_positions = parse_list(
+            &config
+                .get::<String>("max_positions")
+                .ok_or("'max_positions' not found in the JointLimitsTask config")?,
+        )?;
+        if min_positions.len() != max_positions.len() {
+            return Err("'min_positions' and 'max_positions' need to have the same length.".into());
+        }
+        let max_velocities = config
+            .get::<String>("max_velocities")
+            .map(|v| parse_list(&v))
+            .transpose()?;
+        if let Some(max_velocities) = &max_velocities {
+            if max_velocities.len() != min_positions.len() {
+                return Err("'max_velocities' needs one value per joint.".into());
+            }
+        }
+        Ok(JointLimitsTask {
+            min_positions,
+            max_positions,
+            max_velocities,
+            hysteresis: config.get::<f64>("hysteresis").unwrap_or(0.0),
+            speed_hysteresis: config.get::<f64>("speed_hysteresis").unwrap_or(0.0),
+            filter: ViolationFilter::from_config(config),
+            margins: Vec::new(),
+        })
+    }
+}
+
+impl<'cl> CuTask<'cl> for JointLimitsTask {
+    type Input = input_msg!('cl, JointStates);
+    type Output = output_msg!('cl, LimitsStatus);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        // Without new joint states we keep on publishing the last known status.
+        if let Some(joints) = input.payload() {
+            if joints.positions.len() != self.min_positions.len() {
+                return Err(format!(
+                    "Expected {} joints, got {}.",
+                    self.min_positions.len(),
+                    joints.positions.len()
+                )
+                .into());
+            }
+            let tov: Option<CuTime> = input.metadata.tov.into();
+            let now = tov.unwrap_or(ctx.now());
+            self.margins.clear();
+            for (i, position) in joints.positions.iter().enumerate() {
+                self.margins.push(Margin {
+                    kind: LimitKind::JointPosition(i as u32),
+                    margin: (position - self.min_positions[i])
+                        .min(self.max_positions[i] - position),
+                    hysteresis: self.hysteresis,
+                });
+            }
+            if let Some(max_velocities) = &self.max_velocities {
+                for (i, velocity) in joints
+                    .velocities
+                    .iter()
+                    .enumerate()
+                    .take(max_velocities.len())
+                {
+                    self.margins.push(Margin {
+                        kind: LimitKind::JointVelocity(i as u32),
+                        margin: max_velocities[i] - velocity.abs(),
+                        hysteresis: self.speed_hysteresis,
+                    });
+                }
+            }
+            self.filter.update(now, &self.margins);
+        }
+        publish(self.filter.status(), output);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+
+    #[test]
+    fn test_joint_limits() {
+        let mut config = ComponentConfig::new();
+        config.set("min_positions", "-1.0, -2.0".to_string());
+        config.set("max_positions", "1.0, 2.0".to_string());
+        config.set("max_velocities", "0.5, 0.5".to_string());
+        config.set("hysteresis", 0.1);
+        let mut task = JointLimitsTask::new(Some(&config)).unwrap();
+        let clock = RobotClock::default();
+        let mut output = CuMsg::new(None);
+
+        let mut input = CuMsg::new(Some(JointStates {
+            positions: vec![0.0, 1.5],
+            velocities: vec![0.1, 0.8],
+            efforts: vec![],
+        }));
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        let status = output.payload().unwrap();
+        assert!(status.violated);
+        assert_eq!(status.crossed, vec![LimitKind::JointVelocity(1)]);
+
+        // back within the limits but not the hysteresis.
+        input.set_payload(JointStates {
+            positions: vec![0.95, 0.0],
+            velocities: vec![0.0, 0.0],
+            efforts: vec![],
+        });
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert!(output.payload().unwrap().violated);
+
+        input.set_payload(JointStates {
+            positions: vec![0.5, 0.0],
+            velocities: vec![0.0, 0.0],
+            efforts: vec![],
+        });
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert!(!output.payload().unwrap().violated);
+
+        input.set_payload(JointStates {
+            positions: vec![0.5],
+            velocities: vec![],
+            efforts: vec![],
+        });
+        assert!(task
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .is_err());
+    }
+}
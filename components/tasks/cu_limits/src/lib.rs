@@ -0,0 +1,174 @@
+mod geofence;
+mod joints;
+
+pub use geofence::{Geofence, GeofenceTask};
+pub use joints::JointLimitsTask;
+
+use bincode::{Decode, Encode};
+use cu29::clock::{CuDuration, CuTime};
+use cu29::config::ComponentConfig;
+use cu29::cutask::CuMsg;
+use cu29::CuResult;
+use cu29_traits::CuError;
+
+/// The limit that has been crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+pub enum LimitKind {
+    /// The robot is outside of the workspace polygon.
+    Geofence,
+    /// The robot goes faster than the maximum speed.
+    Speed,
+    /// The joint at this index is outside of its range.
+    JointPosition(u32),
+    /// The joint at this index moves faster than its maximum velocity.
+    JointVelocity(u32),
+}
+
+/// Published by the limits tasks, the actuators should stop the robot when violated is true.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+pub struct LimitsStatus {
+    /// Debounced state, this is what should gate the commands.
+    pub violated: bool,
+    /// The limits crossed at the last check, for diagnostics.
+    pub crossed: Vec<LimitKind>,
+}
+
+/// One check of a limit: how far we are inside of the limit (negative if outside) and
+/// the hysteresis to apply to it.
+pub(crate) struct Margin {
+    kind: LimitKind,
+    margin: f64,
+    hysteresis: f64,
+}
+
+/// Turns the raw checks into a stable violation state with hysteresis and grace periods.
+pub(crate) struct ViolationFilter {
+    grace_period: CuDuration,
+    clear_period: CuDuration,
+    violated: bool,
+    /// Since when the checks disagree with the current state.
+    pending_since: Option<CuTime>,
+    status: LimitsStatus,
+}
+
+impl ViolationFilter {
+    pub(crate) fn from_config(config: &ComponentConfig) -> Self {
+        let ms =
+            |key: &str| CuDuration::from(config.get::<u32>(key).unwrap_or(0) as u64 * 1_000_000u64);
+        ViolationFilter {
+            grace_period: ms("grace_period_ms"),
+            clear_period: ms("clear_period_ms"),
+            violated: false,
+            pending_since: None,
+            status: LimitsStatus::default(),
+        }
+    }
+
+    /// Updates the state with the margins checked at time now.
+    pub(crate) fn update(&mut self, now: CuTime, margins: &[Margin]) -> &LimitsStatus {
+        let crossed = &mut self.status.crossed;
+        crossed.clear();
+        crossed.extend(margins.iter().filter(|m| m.margin < 0.0).map(|m| m.kind));
+
+        // Once violated, we need to be back inside by the hysteresis to be considered ok.
+        let bad = if self.violated {
+            margins.iter().any(|m| m.margin < m.hysteresis)
+        } else {
+            !crossed.is_empty()
+        };
+
+        if bad == self.violated {
+            self.pending_since = None;
+        } else {
+            let since = *self.pending_since.get_or_insert(now);
+            let period = if self.violated {
+                self.clear_period
+            } else {
+                self.grace_period
+            };
+            // The time can go back, ie. from the tov of a replayed message.
+            if CuDuration(now.0.saturating_sub(since.0)) >= period {
+                self.violated = bad;
+                self.pending_since = None;
+            }
+        }
+
+        self.status.violated = self.violated;
+        &self.status
+    }
+
+    /// The status of the last update.
+    pub(crate) fn status(&self) -> &LimitsStatus {
+        &self.status
+    }
+}
+
+/// Publishes the status, reusing the payload already in the output.
+pub(crate) fn publish(status: &LimitsStatus, output: &mut CuMsg<LimitsStatus>) {
+    if status.violated {
+        output
+            .metadata
+            .set_status(format!("VIOLATED {:?}", status.crossed));
+    }
+    match output.payload_mut() {
+        Some(payload) => payload.clone_from(status),
+        None => output.set_payload(status.clone()),
+    }
+}
+
+/// Parses a list of numbers like "1.0, 2, -3.5".
+pub(crate) fn parse_list(s: &str) -> CuResult<Vec<f64>> {
+    s.split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|e| CuError::new_with_cause(&format!("Invalid number '{}'", v), e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn margin(margin: f64) -> Margin {
+        Margin {
+            kind: LimitKind::Speed,
+            margin,
+            hysteresis: 0.5,
+        }
+    }
+
+    #[test]
+    fn test_grace_and_hysteresis() {
+        let mut config = ComponentConfig::new();
+        config.set("grace_period_ms", 10u32);
+        config.set("clear_period_ms", 20u32);
+        let mut filter = ViolationFilter::from_config(&config);
+        let ms = |t: u64| CuTime::from(t * 1_000_000);
+
+        assert!(!filter.update(ms(0), &[margin(1.0)]).violated);
+        // short glitch within the grace period.
+        assert!(!filter.update(ms(1), &[margin(-1.0)]).violated);
+        assert!(!filter.update(ms(5), &[margin(1.0)]).violated);
+        // real violation.
+        assert!(!filter.update(ms(6), &[margin(-1.0)]).violated);
+        let status = filter.update(ms(16), &[margin(-1.0)]);
+        assert!(status.violated);
+        assert_eq!(status.crossed, vec![LimitKind::Speed]);
+        // back inside but within the hysteresis: still violated.
+        assert!(filter.update(ms(50), &[margin(0.2)]).violated);
+        // well inside but not long enough.
+        assert!(filter.update(ms(60), &[margin(1.0)]).violated);
+        assert!(!filter.update(ms(80), &[margin(1.0)]).violated);
+        // going back in time.
+        assert!(!filter.update(ms(90), &[margin(-1.0)]).violated);
+        assert!(!filter.update(ms(85), &[margin(-1.0)]).violated);
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(parse_list("1, -2.5,3").unwrap(), vec![1.0, -2.5, 3.0]);
+        assert!(parse_list("1, a").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! The log odds occupancy grid and the integration of the scans into it.
+
+use cu_sensor_payloads::LaserScan;
+use cu_spatial_payloads::Pose;
+
+/// Value of a cell never observed in the published grid.
+pub const UNKNOWN: i8 = -1;
+
+/// Where the grid is in the world and how it is updated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridParams {
+    pub width: usize,
+    pub height: usize,
+    /// Size of a cell in meters.
+    pub resolution: f64,
+    /// Position of the corner of the cell (0, 0) in the world.
+    pub origin_x: f64,
+    pub origin_y: f64,
+    /// Log odds added to a cell where a scan ends.
+    pub hit: f32,
+    /// Log odds added to a cell a scan goes through (negative).
+    pub miss: f32,
+    /// The log odds are clamped to [-clamp, clamp] so the map can still change.
+    pub clamp: f32,
+}
+
+/// The grid as log odds: 0 is unknown, positive is occupied, negative is free.
+/// The cells are stored row by row starting from the origin.
+pub struct LogOddsGrid {
+    params: GridParams,
+    cells: Vec<f32>,
+}
+
+impl LogOddsGrid {
+    pub fn new(params: GridParams) -> Self {
+        LogOddsGrid {
+            params,
+            cells: vec![0.0; params.width * params.height],
+        }
+    }
+
+    pub fn params(&self) -> &GridParams {
+        &self.params
+    }
+
+    pub fn cells(&self) -> &[f32] {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [f32] {
+        &mut self.cells
+    }
+
+    /// The cell containing the point of the world (x, y), if it is in the grid.
+    pub fn cell_at(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let (cx, cy) = self.to_cell(x, y);
+        if cx >= 0
+            && cy >= 0
+            && (cx as usize) < self.params.width
+            && (cy as usize) < self.params.height
+        {
+            Some((cx as usize, cy as usize))
+        } else {
+            None
+        }
+    }
+
+    pub fn log_odds(&self, cx: usize, cy: usize) -> f32 {
+        self.cells[cy * self.params.width + cx]
+    }
+
+    fn to_cell(&self, x: f64, y: f64) -> (i64, i64) {
+        (
+            ((x - self.params.origin_x) / self.params.resolution).floor() as i64,
+            ((y - self.params.origin_y) / self.params.resolution).floor() as i64,
+        )
+    }
+
+    fn update(&mut self, cx: i64, cy: i64, delta: f32) {
+        if cx < 0 || cy < 0 || cx as usize >= self.params.width || cy as usize >= self.params.height
+        {
+            return;
+        }
+        let cell = &mut self.cells[cy as usize * self.params.width + cx as usize];
+        *cell = (*cell + delta).clamp(-self.params.clamp, self.params.clamp);
+    }
+
+    /// Integrates a scan taken from pose: the cells crossed by the beams become freer, the cells where they
+    /// end more occupied. The measurements beyond range_max only clear the space up to range_max.
+    pub fn integrate(&mut self, pose: &Pose, scan: &LaserScan) {
+        let start = self.to_cell(pose.x, pose.y);
+        for (i, range) in scan.ranges.iter().enumerate() {
+            let (range, hit) = if *range >= scan.range_min && *range <= scan.range_max {
+                (*range, true)
+            } else if *range > scan.range_max && range.is_finite() {
+                (scan.range_max, false)
+            } else {
+                continue;
+            };
+            let angle = pose.yaw + (scan.angle_min + i as f32 * scan.angle_increment) as f64;
+            let end = self.to_cell(
+                pose.x + range as f64 * angle.cos(),
+                pose.y + range as f64 * angle.sin(),
+            );
+            self.trace(start, end);
+            if hit {
+                self.update(end.0, end.1, self.params.hit);
+            }
+        }
+    }
+
+    /// Bresenham from start to end, end excluded.
+    fn trace(&mut self, start: (i64, i64), end: (i64, i64)) {
+        let (dx, dy) = ((end.0 - start.0).abs(), -(end.1 - start.1).abs());
+        let (sx, sy) = ((end.0 - start.0).signum(), (end.1 - start.1).signum());
+        let (mut x, mut y) = start;
+        let mut error = dx + dy;
+        while (x, y) != end {
+            self.update(x, y, self.params.miss);
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Writes the occupancy probabilities in percent, or UNKNOWN, to out.
+    pub fn write_occupancy(&self, out: &mut [i8]) {
+        for (out, log_odds) in out.iter_mut().zip(&self.cells) {
+            *out = if *log_odds == 0.0 {
+                UNKNOWN
+            } else {
+                (100.0 - 100.0 / (1.0 + log_odds.exp())).round() as i8
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> LogOddsGrid {
+        LogOddsGrid::new(GridParams {
+            width: 20,
+            height: 20,
+            resolution: 0.5,
+            origin_x: -5.0,
+            origin_y: -5.0,
+            hit: 0.85,
+            miss: -0.4,
+            clamp: 5.0,
+        })
+    }
+
+    #[test]
+    fn test_integrate_scan() {
+        let mut grid = grid();
+        let scan = LaserScan {
+            angle_min: 0.0,
+            angle_increment: std::f32::consts::FRAC_PI_2,
+            range_min: 0.1,
+            range_max: 4.0,
+            // a wall at 3m ahead, nothing on the left.
+            ranges: vec![3.0, 10.0],
+        };
+        grid.integrate(&Pose::new_2d(0.1, 0.1, 0.0), &scan);
+
+        let (wx, wy) = grid.cell_at(3.1, 0.1).unwrap();
+        assert!(grid.log_odds(wx, wy) > 0.0);
+        let (fx, fy) = grid.cell_at(1.6, 0.1).unwrap();
+        assert!(grid.log_odds(fx, fy) < 0.0);
+        // free up to range_max on the left, but no obstacle.
+        let (lx, ly) = grid.cell_at(0.1, 3.6).unwrap();
+        assert!(grid.log_odds(lx, ly) < 0.0);
+        let (ux, uy) = grid.cell_at(0.1, 4.6).unwrap();
+        assert_eq!(grid.log_odds(ux, uy), 0.0);
+
+        let mut occupancy = vec![0i8; 400];
+        grid.write_occupancy(&mut occupancy);
+        assert!(occupancy[wy * 20 + wx] > 50);
+        assert!(occupancy[fy * 20 + fx] < 50);
+        assert_eq!(occupancy[uy * 20 + ux], UNKNOWN);
+    }
+
+    #[test]
+    fn test_out_of_grid() {
+        let mut grid = grid();
+        let scan = LaserScan {
+            angle_min: 0.0,
+            angle_increment: 0.0,
+            range_min: 0.1,
+            range_max: 100.0,
+            ranges: vec![50.0],
+        };
+        grid.integrate(&Pose::new_2d(-20.0, 0.0, 0.0), &scan);
+        assert!(grid.cell_at(-20.0, 0.0).is_none());
+        assert!(grid.cells().iter().any(|c| *c < 0.0));
+        assert!(grid.cells().iter().all(|c| *c <= 0.0));
+    }
+}
@@ -0,0 +1,260 @@
+mod grid;
+
+pub use grid::{GridParams, LogOddsGrid, UNKNOWN};
+
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use cu29::clock::{CuDuration, CuTime, OptionCuTime};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::pool::{CuHandle, CuPool};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu_sensor_payloads::LaserScan;
+use cu_spatial_payloads::Pose;
+
+/// An occupancy grid: for every cell the probability of it being occupied in percent, or UNKNOWN.
+/// The cells are stored row by row starting from the origin, in a buffer of the pool of the mapping task.
+#[derive(Debug, Default, Clone, Encode, Decode)]
+pub struct OccupancyGrid {
+    pub width: u32,
+    pub height: u32,
+    pub resolution: f64,
+    pub origin_x: f64,
+    pub origin_y: f64,
+    pub cells: CuHandle<i8>,
+}
+
+impl OccupancyGrid {
+    pub fn get(&self, cx: u32, cy: u32) -> i8 {
+        self.cells[(cy * self.width + cx) as usize]
+    }
+}
+
+/// Integrates the laser scans at the last received pose into an occupancy grid and publishes it periodically.
+pub struct OccupancyGridTask {
+    grid: LogOddsGrid,
+    pool: CuPool<i8>,
+    pose: Option<Pose>,
+    publish_period: CuDuration,
+    last_publish: Option<CuTime>,
+}
+
+impl CuTaskLifecycle for OccupancyGridTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("OccupancyGridTask needs a config.")?;
+        let width = config
+            .get::<u32>("width")
+            .ok_or("OccupancyGridTask needs a 'width' in cells.")? as usize;
+        let height = config
+            .get::<u32>("height")
+            .ok_or("OccupancyGridTask needs a 'height' in cells.")? as usize;
+        let resolution = config
+            .get::<f64>("resolution")
+            .ok_or("OccupancyGridTask needs a 'resolution' in meters per cell.")?;
+        if width == 0 || height == 0 || resolution <= 0.0 {
+            return Err("OccupancyGridTask: the grid size and resolution must be positive.".into());
+        }
+        // By default the grid is centered on the start of the robot.
+        let params = GridParams {
+            width,
+            height,
+            resolution,
+            origin_x: config
+                .get::<f64>("origin_x")
+                .unwrap_or(-(width as f64) * resolution / 2.0),
+            origin_y: config
+                .get::<f64>("origin_y")
+                .unwrap_or(-(height as f64) * resolution / 2.0),
+            hit: config.get::<f64>("hit_log_odds").unwrap_or(0.85) as f32,
+            miss: config.get::<f64>("miss_log_odds").unwrap_or(-0.4) as f32,
+            clamp: config.get::<f64>("clamp_log_odds").unwrap_or(5.0) as f32,
+        };
+        let pool_size = config.get::<u32>("pool_size").unwrap_or(3) as usize;
+        let publish_period_ms = config.get::<u32>("publish_period_ms").unwrap_or(1000);
+        Ok(OccupancyGridTask {
+            grid: LogOddsGrid::new(params),
+            pool: CuPool::new("occupancy_grid", pool_size, width * height),
+            pose: None,
+            publish_period: CuDuration::from(publish_period_ms as u64 * 1_000_000),
+            last_publish: None,
+        })
+    }
+}
+
+impl<'cl> CuTask<'cl> for OccupancyGridTask {
+    type Input = input_msg!('cl, LaserScan, Pose);
+    type Output = output_msg!('cl, OccupancyGrid);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let (scan, pose) = input;
+        if let Some(pose) = pose.payload() {
+            self.pose = Some(*pose);
+        }
+        if let (Some(scan), Some(pose)) = (scan.payload(), &self.pose) {
+            self.grid.integrate(pose, scan);
+        }
+
+        let now = ctx.now();
+        if let Some(last_publish) = self.last_publish {
+            if now - last_publish < self.publish_period {
+                output.clear_payload();
+                return Ok(());
+            }
+        }
+        // If all the buffers are still held downstream, skip this publication and retry at the next iteration.
+        let Some(mut cells) = self.pool.acquire() else {
+            debug!("OccupancyGridTask: the grid pool is exhausted, skipping a publication.");
+            output.clear_payload();
+            output.metadata.set_status("pool exhausted");
+            return Ok(());
+        };
+        self.grid.write_occupancy(
+            cells
+                .get_mut()
+                .expect("A buffer fresh from the pool is not shared"),
+        );
+        self.last_publish = Some(now);
+        let params = self.grid.params();
+        output.set_payload(OccupancyGrid {
+            width: params.width as u32,
+            height: params.height as u32,
+            resolution: params.resolution,
+            origin_x: params.origin_x,
+            origin_y: params.origin_y,
+            cells,
+        });
+        output.metadata.tov = now.into();
+        Ok(())
+    }
+}
+
+/// The whole grid is frozen so a replay rebuilds exactly the same map.
+impl Freezable for OccupancyGridTask {
+    fn freeze<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        Encode::encode(self.grid.cells(), encoder)?;
+        Encode::encode(&self.pose, encoder)?;
+        Encode::encode(&OptionCuTime::from(self.last_publish), encoder)?;
+        Ok(())
+    }
+
+    fn thaw<D: Decoder>(&mut self, decoder: &mut D) -> Result<(), DecodeError> {
+        let cells: Vec<f32> = Decode::decode(decoder)?;
+        if cells.len() != self.grid.cells().len() {
+            return Err(DecodeError::OtherString(format!(
+                "The frozen grid has {} cells, the configured one {}.",
+                cells.len(),
+                self.grid.cells().len()
+            )));
+        }
+        self.grid.cells_mut().copy_from_slice(&cells);
+        self.pose = Decode::decode(decoder)?;
+        let last_publish: OptionCuTime = Decode::decode(decoder)?;
+        self.last_publish = last_publish.into();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::config::standard;
+    use bincode::de::read::SliceReader;
+    use bincode::de::DecoderImpl;
+    use bincode::enc::write::SliceWriter;
+    use bincode::enc::EncoderImpl;
+    use cu29::clock::RobotClock;
+    use std::time::Duration;
+
+    fn config() -> ComponentConfig {
+        let mut config = ComponentConfig::new();
+        config.set("width", 40u32);
+        config.set("height", 40u32);
+        config.set("resolution", 0.25);
+        config.set("publish_period_ms", 100u32);
+        config.set("pool_size", 1u32);
+        config
+    }
+
+    fn scan() -> LaserScan {
+        LaserScan {
+            angle_min: 0.0,
+            angle_increment: 0.0,
+            range_min: 0.1,
+            range_max: 4.0,
+            ranges: vec![2.0],
+        }
+    }
+
+    #[test]
+    fn test_publishes_periodically_from_pool() {
+        let mut task = OccupancyGridTask::new(Some(&config())).unwrap();
+        let (clock, mock) = RobotClock::mock();
+        let scan = CuMsg::new(Some(scan()));
+        let pose = CuMsg::new(Some(Pose::new_2d(0.1, 0.1, 0.0)));
+        let mut output = CuMsg::new(None);
+
+        task.process(&CuContext::from(&clock), (&scan, &pose), &mut output)
+            .unwrap();
+        let grid = output.payload().unwrap().clone();
+        assert!(grid.cells.is_pooled());
+        assert!(grid.get(28, 20) > 50);
+        assert!(grid.get(24, 20) < 50);
+        assert_eq!(grid.get(0, 0), UNKNOWN);
+
+        mock.increment(Duration::from_millis(50));
+        task.process(&CuContext::from(&clock), (&scan, &pose), &mut output)
+            .unwrap();
+        assert!(output.payload().is_none());
+
+        // The only buffer is still held by grid.
+        mock.increment(Duration::from_millis(50));
+        task.process(&CuContext::from(&clock), (&scan, &pose), &mut output)
+            .unwrap();
+        assert!(output.payload().is_none());
+        drop(grid);
+        task.process(&CuContext::from(&clock), (&scan, &pose), &mut output)
+            .unwrap();
+        assert!(output.payload().is_some());
+    }
+
+    #[test]
+    fn test_freeze_thaw() {
+        let mut task = OccupancyGridTask::new(Some(&config())).unwrap();
+        let clock = RobotClock::default();
+        let scan = CuMsg::new(Some(scan()));
+        let pose = CuMsg::new(Some(Pose::new_2d(0.1, 0.1, 0.0)));
+        let mut output = CuMsg::new(None);
+        task.process(&CuContext::from(&clock), (&scan, &pose), &mut output)
+            .unwrap();
+
+        let mut buffer = vec![0u8; 16 * 1024];
+        let mut encoder = EncoderImpl::new(SliceWriter::new(&mut buffer), standard());
+        task.freeze(&mut encoder).unwrap();
+        let size = encoder.into_writer().bytes_written();
+
+        let mut restored = OccupancyGridTask::new(Some(&config())).unwrap();
+        let mut decoder = DecoderImpl::new(SliceReader::new(&buffer[..size]), standard());
+        restored.thaw(&mut decoder).unwrap();
+        assert_eq!(restored.grid.cells(), task.grid.cells());
+        assert_eq!(restored.pose, task.pose);
+
+        let mut small = config();
+        small.set("width", 10u32);
+        let mut mismatched = OccupancyGridTask::new(Some(&small)).unwrap();
+        let mut decoder = DecoderImpl::new(SliceReader::new(&buffer[..size]), standard());
+        assert!(mismatched.thaw(&mut decoder).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Wheel odometry of a differential drive, with a covariance adapted to the slip of the wheels detected from an IMU,
+//! for a filter fusing it with the other sensors:
+//!
+//! ```ron
+//! (id: "odometry", type: "cu_odometry::WheelOdometry<cu_rp_encoder::EncoderPayload>", config: {
+//!     "wheel_radius": 0.05, "ticks_per_rev": 1024, "wheel_base": 0.3,
+//! }),
+//! ```
+
+mod model;
+
+pub use model::{OdometryParams, WheelOdometryModel};
+
+use cu29::clock::{CuTime, RobotClock};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuMsgPayload, CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use cu_sensor_payloads::Imu;
+use cu_spatial_payloads::PoseWithCovariance;
+use std::f64::consts::PI;
+use std::marker::PhantomData;
+
+/// The odometry from the cumulative ticks of the left and right encoders, its first 2 inputs, and the yaw rate of
+/// an IMU, its third input. The pose starts at the origin on start and is published with its covariance every time
+/// both encoders report.
+///
+/// The parameters of the model are in the config: wheel_radius (m), ticks_per_rev and wheel_base (m) for the
+/// geometry, wheel_noise (m²/m, 0.001 by default) for the noise of the wheels, slip_threshold (rad/s, 0.2 by
+/// default) and slip_gain (10 by default) for the slip. The status of the output is "slipping" while the wheels
+/// slip.
+pub struct WheelOdometry<E>
+where
+    f32: for<'a> From<&'a E>,
+{
+    model: WheelOdometryModel,
+    /// The ticks of the encoders at the last update and when they were received.
+    last: Option<((f64, f64), CuTime)>,
+    slipping: bool,
+    _marker: PhantomData<E>,
+}
+
+impl<E> Freezable for WheelOdometry<E> where f32: for<'a> From<&'a E> {}
+
+fn positive(config: &ComponentConfig, key: &str, default: Option<f64>) -> CuResult<f64> {
+    let value = config
+        .get::<f64>(key)
+        .or(default)
+        .ok_or_else(|| format!("WheelOdometry needs a '{}' in its config.", key))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(CuError::from(format!(
+            "Invalid {} {} in the WheelOdometry config.",
+            key, value
+        )));
+    }
+    Ok(value)
+}
+
+impl<E> CuTaskLifecycle for WheelOdometry<E>
+where
+    f32: for<'a> From<&'a E>,
+{
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config =
+            config.ok_or("WheelOdometry needs a config with the geometry of the robot.")?;
+        let wheel_radius = positive(config, "wheel_radius", None)?;
+        let ticks_per_rev = config
+            .get::<u32>("ticks_per_rev")
+            .filter(|ticks| *ticks > 0)
+            .ok_or("WheelOdometry needs a non zero 'ticks_per_rev' in its config.")?;
+        let wheel_base = positive(config, "wheel_base", None)?;
+        if wheel_base == 0.0 {
+            return Err("The wheel_base of WheelOdometry cannot be 0.".into());
+        }
+        Ok(WheelOdometry {
+            model: WheelOdometryModel::new(OdometryParams {
+                meters_per_tick: 2.0 * PI * wheel_radius / ticks_per_rev as f64,
+                wheel_base,
+                wheel_noise: positive(config, "wheel_noise", Some(0.001))?,
+                slip_threshold: positive(config, "slip_threshold", Some(0.2))?,
+                slip_gain: positive(config, "slip_gain", Some(10.0))?,
+            }),
+            last: None,
+            slipping: false,
+            _marker: PhantomData,
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.model.reset();
+        self.last = None;
+        self.slipping = false;
+        Ok(())
+    }
+}
+
+impl<'cl, E> CuTask<'cl> for WheelOdometry<E>
+where
+    f32: for<'a> From<&'a E>,
+    E: CuMsgPayload + 'cl,
+{
+    type Input = input_msg!('cl, E, E, Imu);
+    type Output = output_msg!('cl, PoseWithCovariance);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let (left, right, imu) = input;
+        let (Some(left_ticks), Some(right_ticks)) = (left.payload(), right.payload()) else {
+            output.clear_payload();
+            return Ok(());
+        };
+        let ticks = (f32::from(left_ticks) as f64, f32::from(right_ticks) as f64);
+        let now = ctx.now();
+        // The first ticks are the reference of the origin.
+        if let Some((last_ticks, last_time)) = self.last.replace((ticks, now)) {
+            let dt = (now - last_time).0 as f64 / 1e9;
+            let slip = self.model.update(
+                ticks.0 - last_ticks.0,
+                ticks.1 - last_ticks.1,
+                dt,
+                imu.payload().map(|imu| imu.angular_velocity[2]),
+            );
+            if (slip > 0.0) != self.slipping {
+                self.slipping = slip > 0.0;
+                debug!("Odometry: the wheels slip: {}.", self.slipping);
+            }
+        }
+        output.set_payload(PoseWithCovariance {
+            pose: self.model.pose(),
+            covariance: self.model.covariance(),
+        });
+        if self.slipping {
+            output.metadata.set_status("slipping");
+        }
+        output.metadata.tov = [left, right]
+            .iter()
+            .filter_map(|input| -> Option<CuTime> { input.metadata.tov.into() })
+            .max()
+            .into();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::{Decode, Encode};
+    use std::time::Duration;
+
+    /// The cumulative ticks of an encoder.
+    #[derive(Default, Encode, Decode)]
+    struct Ticks(i32);
+
+    impl From<&Ticks> for f32 {
+        fn from(ticks: &Ticks) -> f32 {
+            ticks.0 as f32
+        }
+    }
+
+    #[test]
+    fn test_wheel_odometry() {
+        let mut config = ComponentConfig::new();
+        config.set("wheel_radius", 0.5 / PI);
+        config.set("ticks_per_rev", 1000u32);
+        config.set("wheel_base", 0.5);
+        config.set("slip_threshold", 0.1);
+        let mut odometry = WheelOdometry::<Ticks>::new(Some(&config)).unwrap();
+        let (clock, mock) = RobotClock::mock();
+        odometry.start(&clock).unwrap();
+        let mut output = CuMsg::new(None);
+        let imu = CuMsg::new(Some(Imu::default()));
+
+        let (left, right) = (CuMsg::new(Some(Ticks(500))), CuMsg::new(Some(Ticks(500))));
+        odometry
+            .process(&CuContext::from(&clock), (&left, &right, &imu), &mut output)
+            .unwrap();
+        assert_eq!(output.payload().unwrap().pose.x, 0.0);
+
+        // 1ms per tick, 1m forward.
+        mock.increment(Duration::from_secs(1));
+        let (left, right) = (CuMsg::new(Some(Ticks(1500))), CuMsg::new(Some(Ticks(1500))));
+        odometry
+            .process(&CuContext::from(&clock), (&left, &right, &imu), &mut output)
+            .unwrap();
+        let straight = *output.payload().unwrap();
+        assert!((straight.pose.x - 1.0).abs() < 1e-6);
+        assert_eq!(output.metadata.status_txt.0.as_str(), "");
+
+        // The encoders turn, not the IMU.
+        mock.increment(Duration::from_secs(1));
+        let (left, right) = (CuMsg::new(Some(Ticks(1400))), CuMsg::new(Some(Ticks(1600))));
+        odometry
+            .process(&CuContext::from(&clock), (&left, &right, &imu), &mut output)
+            .unwrap();
+        assert_eq!(output.metadata.status_txt.0.as_str(), "slipping");
+        assert!(output.payload().unwrap().covariance[8] > straight.covariance[8]);
+
+        let none = CuMsg::new(None);
+        odometry
+            .process(&CuContext::from(&clock), (&left, &none, &imu), &mut output)
+            .unwrap();
+        assert!(output.payload().is_none());
+
+        config.set("wheel_base", 0.0);
+        assert!(WheelOdometry::<Ticks>::new(Some(&config)).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use cu_spatial_payloads::Pose;
+
+/// The parameters of the odometry of a differential drive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OdometryParams {
+    /// The distance traveled by a wheel per tick of its encoder, in m.
+    pub meters_per_tick: f64,
+    /// The distance between the wheels, in m.
+    pub wheel_base: f64,
+    /// The variance of the distance traveled by a wheel per meter traveled, in m²/m.
+    pub wheel_noise: f64,
+    /// The mismatch between the yaw rates of the encoders and of the IMU above which the wheels slip, in rad/s.
+    pub slip_threshold: f64,
+    /// How much the wheel noise is multiplied by per rad/s of mismatch above slip_threshold.
+    pub slip_gain: f64,
+}
+
+/// Integrates the distances traveled by the wheels into a pose and propagates its covariance.
+///
+/// The noise of every wheel grows with the distance it traveled. When the yaw rate measured by the encoders does not
+/// match the one of the IMU, a wheel slips and the noise is inflated by the mismatch, so a filter fusing the
+/// odometry trusts it less while it is wrong.
+pub struct WheelOdometryModel {
+    params: OdometryParams,
+    pose: Pose,
+    covariance: [[f64; 3]; 3],
+}
+
+type Matrix = [[f64; 3]; 3];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = a[j][i];
+        }
+    }
+    m
+}
+
+impl WheelOdometryModel {
+    pub fn new(params: OdometryParams) -> Self {
+        WheelOdometryModel {
+            params,
+            pose: Pose::default(),
+            covariance: [[0.0; 3]; 3],
+        }
+    }
+
+    /// Back to the origin, with a null covariance.
+    pub fn reset(&mut self) {
+        self.pose = Pose::default();
+        self.covariance = [[0.0; 3]; 3];
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// The covariance of x, y and yaw, row major.
+    pub fn covariance(&self) -> [f64; 9] {
+        let mut covariance = [0.0; 9];
+        for (i, row) in self.covariance.iter().enumerate() {
+            covariance[i * 3..i * 3 + 3].copy_from_slice(row);
+        }
+        covariance
+    }
+
+    /// Integrates the ticks of the left and right encoders during dt, in s. imu_yaw_rate is the yaw rate of the IMU
+    /// over the same period if there is one. Returns the mismatch of the yaw rates above slip_threshold, 0 when the
+    /// wheels do not slip.
+    pub fn update(
+        &mut self,
+        left_ticks: f64,
+        right_ticks: f64,
+        dt: f64,
+        imu_yaw_rate: Option<f64>,
+    ) -> f64 {
+        let OdometryParams {
+            meters_per_tick,
+            wheel_base,
+            wheel_noise,
+            slip_threshold,
+            slip_gain,
+        } = self.params;
+        let left = left_ticks * meters_per_tick;
+        let right = right_ticks * meters_per_tick;
+        let distance = (left + right) / 2.0;
+        let rotation = (right - left) / wheel_base;
+
+        let slip = match imu_yaw_rate {
+            Some(imu_yaw_rate) if dt > 0.0 => {
+                ((rotation / dt - imu_yaw_rate).abs() - slip_threshold).max(0.0)
+            }
+            _ => 0.0,
+        };
+        let noise = wheel_noise * (1.0 + slip_gain * slip);
+
+        // The heading in the middle of the motion.
+        let heading = self.pose.yaw + rotation / 2.0;
+        let (sin, cos) = heading.sin_cos();
+
+        // The jacobians of the motion by the pose and by the distances of the wheels.
+        let by_pose = [
+            [1.0, 0.0, -distance * sin],
+            [0.0, 1.0, distance * cos],
+            [0.0, 0.0, 1.0],
+        ];
+        let half_turn = distance / (2.0 * wheel_base);
+        let by_wheels = [
+            [cos / 2.0 + half_turn * sin, cos / 2.0 - half_turn * sin],
+            [sin / 2.0 - half_turn * cos, sin / 2.0 + half_turn * cos],
+            [-1.0 / wheel_base, 1.0 / wheel_base],
+        ];
+        let wheels_variance = [noise * left.abs(), noise * right.abs()];
+
+        let mut covariance = mul(&mul(&by_pose, &self.covariance), &transpose(&by_pose));
+        for (i, row) in covariance.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value += (0..2)
+                    .map(|k| by_wheels[i][k] * wheels_variance[k] * by_wheels[j][k])
+                    .sum::<f64>();
+            }
+        }
+        self.covariance = covariance;
+
+        self.pose.x += distance * cos;
+        self.pose.y += distance * sin;
+        self.pose.yaw += rotation;
+        slip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const PARAMS: OdometryParams = OdometryParams {
+        meters_per_tick: 0.001,
+        wheel_base: 0.5,
+        wheel_noise: 0.01,
+        slip_threshold: 0.1,
+        slip_gain: 10.0,
+    };
+
+    #[test]
+    fn test_straight_and_turn() {
+        let mut odometry = WheelOdometryModel::new(PARAMS);
+        assert_eq!(odometry.update(1000.0, 1000.0, 1.0, Some(0.0)), 0.0);
+        let pose = odometry.pose();
+        assert!((pose.x - 1.0).abs() < 1e-9);
+        assert!(pose.y.abs() < 1e-9);
+        let covariance = odometry.covariance();
+        // Along x: the mean of 2 wheels of variance 0.01.
+        assert!((covariance[0] - 0.005).abs() < 1e-9);
+        // Across: the difference of the wheels turns the robot.
+        assert!((covariance[4] - 0.02).abs() < 1e-9);
+        assert!((covariance[8] - 0.08).abs() < 1e-9);
+
+        // An eighth of a turn in place, then 1m forward.
+        let ticks = PI / 4.0 * PARAMS.wheel_base / PARAMS.meters_per_tick;
+        odometry.update(-ticks / 2.0, ticks / 2.0, 1.0, None);
+        odometry.update(1000.0, 1000.0, 1.0, None);
+        let pose = odometry.pose();
+        assert!((pose.yaw - PI / 4.0).abs() < 1e-9);
+        assert!((pose.x - 1.0 - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((pose.y - 0.5f64.sqrt()).abs() < 1e-9);
+        // The error of the heading grows the error of the position.
+        assert!(odometry.covariance()[4] > 0.06);
+    }
+
+    #[test]
+    fn test_slip() {
+        let mut grip = WheelOdometryModel::new(PARAMS);
+        let mut slipping = WheelOdometryModel::new(PARAMS);
+        // The encoders turn at 0.4 rad/s.
+        assert_eq!(grip.update(900.0, 1100.0, 1.0, Some(0.4)), 0.0);
+        // The IMU does not, the wheels spin.
+        let slip = slipping.update(900.0, 1100.0, 1.0, Some(0.0));
+        assert!((slip - 0.3).abs() < 1e-9);
+        assert_eq!(grip.pose(), slipping.pose());
+        for (grip, slipping) in grip.covariance().iter().zip(slipping.covariance()) {
+            assert!((slipping - grip * 4.0).abs() < 1e-9);
+        }
+        grip.reset();
+        assert_eq!(grip.covariance(), [0.0; 9]);
+    }
+}
@@ -0,0 +1,352 @@
+mod model;
+mod postprocess;
+mod preprocess;
+
+pub use model::{resolve_shape, ExecutionProvider, ModelConfig, OnnxModel};
+pub use postprocess::{
+    decode_detections, iou, l2_normalize, non_max_suppression, DetectionFormat, DetectionParams,
+};
+pub use preprocess::{image_to_tensor, InputInfo, InputSpec, Layout};
+
+use bincode::{Decode, Encode};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuMsgPayload};
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::pool::CuHandle;
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use cu_sensor_payloads::Image;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// An object detected by a model.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct Detection {
+    pub class_id: u32,
+    pub score: f32,
+    /// x1, y1, x2, y2 in pixels of the input image.
+    pub bbox: [f32; 4],
+}
+
+/// The detections of a model, best first.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct Detections {
+    pub detections: Vec<Detection>,
+}
+
+/// A feature vector computed by a model, for example to recognize places or objects.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct Embedding {
+    pub values: Vec<f32>,
+}
+
+/// A raw tensor, row major.
+#[derive(Debug, Default, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct FloatTensor {
+    pub shape: Vec<u32>,
+    pub data: CuHandle<f32>,
+}
+
+/// How the output of the model is turned into a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSpec {
+    pub detection: DetectionParams,
+    /// Scales the embeddings to a unit length.
+    pub normalize: bool,
+}
+
+/// A message that can be fed to a model.
+pub trait OnnxInput {
+    /// Writes the message to the preallocated input tensor of the model.
+    fn fill(&self, spec: &InputSpec, tensor: &mut [f32]) -> CuResult<InputInfo>;
+}
+
+/// A message decoded from an output of a model.
+pub trait OnnxOutput {
+    /// Decodes the output in place to reuse the allocations of the previous message.
+    fn decode(
+        &mut self,
+        shape: &[i64],
+        data: &[f32],
+        spec: &OutputSpec,
+        info: &InputInfo,
+    ) -> CuResult<()>;
+}
+
+impl OnnxInput for Image {
+    fn fill(&self, spec: &InputSpec, tensor: &mut [f32]) -> CuResult<InputInfo> {
+        image_to_tensor(self, spec, tensor)
+    }
+}
+
+impl OnnxInput for FloatTensor {
+    fn fill(&self, spec: &InputSpec, tensor: &mut [f32]) -> CuResult<InputInfo> {
+        if self.data.len() != tensor.len() {
+            return Err(format!(
+                "Got a tensor of {} values for an input of shape {:?}.",
+                self.data.len(),
+                spec.shape
+            )
+            .into());
+        }
+        tensor.copy_from_slice(&self.data);
+        Ok(InputInfo::default())
+    }
+}
+
+impl OnnxOutput for Detections {
+    fn decode(
+        &mut self,
+        shape: &[i64],
+        data: &[f32],
+        spec: &OutputSpec,
+        info: &InputInfo,
+    ) -> CuResult<()> {
+        decode_detections(shape, data, &spec.detection, info, &mut self.detections)
+    }
+}
+
+impl OnnxOutput for Embedding {
+    fn decode(
+        &mut self,
+        _shape: &[i64],
+        data: &[f32],
+        spec: &OutputSpec,
+        _info: &InputInfo,
+    ) -> CuResult<()> {
+        self.values.clear();
+        self.values.extend_from_slice(data);
+        if spec.normalize {
+            l2_normalize(&mut self.values);
+        }
+        Ok(())
+    }
+}
+
+impl OnnxOutput for FloatTensor {
+    fn decode(
+        &mut self,
+        shape: &[i64],
+        data: &[f32],
+        _spec: &OutputSpec,
+        _info: &InputInfo,
+    ) -> CuResult<()> {
+        self.shape.clear();
+        self.shape.extend(shape.iter().map(|dim| *dim as u32));
+        // Copied in place unless the previous tensor is still shared downstream.
+        match self.data.get_mut() {
+            Some(buffer) if buffer.len() == data.len() => buffer.copy_from_slice(data),
+            _ => self.data = CuHandle::new_detached(data.to_vec()),
+        }
+        Ok(())
+    }
+}
+
+/// Runs an ONNX model on the input messages of type I and decodes its output as messages of type O.
+/// The input tensor is allocated once when the model is loaded.
+pub struct OnnxTask<I, O> {
+    model: OnnxModel,
+    input_spec: InputSpec,
+    output_spec: OutputSpec,
+    _marker: PhantomData<(I, O)>,
+}
+
+/// Detects objects in images.
+pub type OnnxDetector = OnnxTask<Image, Detections>;
+/// Computes a feature vector of images.
+pub type OnnxImageEmbedder = OnnxTask<Image, Embedding>;
+/// Runs a model on raw tensors.
+pub type OnnxTensorTask = OnnxTask<FloatTensor, FloatTensor>;
+
+/// Parses 3 comma separated values like "0.485, 0.456, 0.406".
+fn get_triple(config: &ComponentConfig, key: &str, default: [f32; 3]) -> CuResult<[f32; 3]> {
+    let Some(value) = config.get::<String>(key) else {
+        return Ok(default);
+    };
+    let values = value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CuError::new_with_cause(&format!("Invalid '{}'", key), e))?;
+    values
+        .try_into()
+        .map_err(|_| format!("'{}' needs 3 values, got '{}'.", key, value).into())
+}
+
+fn specs_from_config(config: &ComponentConfig) -> CuResult<(InputSpec, OutputSpec)> {
+    let layout = match config
+        .get::<String>("layout")
+        .unwrap_or("nchw".to_string())
+        .as_str()
+    {
+        "nchw" => Layout::Nchw,
+        "nhwc" => Layout::Nhwc,
+        other => {
+            return Err(format!("Unknown layout '{}', expected 'nchw' or 'nhwc'.", other).into())
+        }
+    };
+    let bgr = match config
+        .get::<String>("channel_order")
+        .unwrap_or("rgb".to_string())
+        .as_str()
+    {
+        "rgb" => false,
+        "bgr" => true,
+        other => {
+            return Err(format!(
+                "Unknown channel_order '{}', expected 'rgb' or 'bgr'.",
+                other
+            )
+            .into())
+        }
+    };
+    let format = match config
+        .get::<String>("detection_format")
+        .unwrap_or("boxes".to_string())
+        .as_str()
+    {
+        "boxes" => DetectionFormat::Boxes,
+        "yolo" => DetectionFormat::Yolo,
+        "yolo_transposed" => DetectionFormat::YoloTransposed,
+        other => {
+            return Err(format!(
+                "Unknown detection_format '{}', expected 'boxes', 'yolo' or 'yolo_transposed'.",
+                other
+            )
+            .into())
+        }
+    };
+    let defaults = DetectionParams::default();
+    let input_spec = InputSpec {
+        shape: Vec::new(),
+        layout,
+        bgr,
+        mean: get_triple(config, "mean", [0.0; 3])?,
+        std: get_triple(config, "std", [1.0; 3])?,
+    };
+    let output_spec = OutputSpec {
+        detection: DetectionParams {
+            format,
+            score_threshold: config
+                .get::<f64>("score_threshold")
+                .map_or(defaults.score_threshold, |t| t as f32),
+            iou_threshold: config
+                .get::<f64>("iou_threshold")
+                .map_or(defaults.iou_threshold, |t| t as f32),
+            max_detections: config
+                .get::<u32>("max_detections")
+                .map_or(defaults.max_detections, |m| m as usize),
+        },
+        normalize: config.get::<bool>("normalize").unwrap_or(false),
+    };
+    Ok((input_spec, output_spec))
+}
+
+impl<I, O> Freezable for OnnxTask<I, O> {}
+
+impl<I, O> CuTaskLifecycle for OnnxTask<I, O> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("OnnxTask needs a config.")?;
+        let model_config = ModelConfig::from_config(config)?;
+        let (mut input_spec, output_spec) = specs_from_config(config)?;
+        let model = OnnxModel::load(&model_config)?;
+        input_spec.shape = model.input_shape().to_vec();
+        let shape = format!("{:?}", input_spec.shape);
+        let providers = format!("{:?}", model_config.execution_providers);
+        debug!(
+            "OnnxTask: loaded {} with the input shape {} on {}.",
+            model_config.path.clone(),
+            shape,
+            providers
+        );
+        Ok(OnnxTask {
+            model,
+            input_spec,
+            output_spec,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<'cl, I, O> CuTask<'cl> for OnnxTask<I, O>
+where
+    I: OnnxInput + CuMsgPayload + 'cl,
+    O: OnnxOutput + CuMsgPayload + 'cl,
+{
+    type Input = input_msg!('cl, I);
+    type Output = output_msg!('cl, O);
+
+    fn process(
+        &mut self,
+        _ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let Some(payload) = input.payload() else {
+            output.clear_payload();
+            return Ok(());
+        };
+        let info = payload.fill(&self.input_spec, self.model.input_mut())?;
+        let decoded = output.payload_mut().get_or_insert_with(O::default);
+        let spec = &self.output_spec;
+        self.model
+            .run(|shape, data| decoded.decode(shape, data, spec, &info))?;
+        // The outputs are valid at the time of the input.
+        output.metadata.tov = input.metadata.tov;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_specs_from_config() {
+        let mut config = ComponentConfig::new();
+        config.set("layout", "nhwc".to_string());
+        config.set("mean", "0.485, 0.456, 0.406".to_string());
+        config.set("detection_format", "yolo".to_string());
+        config.set("score_threshold", 0.25);
+        let (input, output) = specs_from_config(&config).unwrap();
+        assert_eq!(input.layout, Layout::Nhwc);
+        assert_eq!(input.mean, [0.485, 0.456, 0.406]);
+        assert_eq!(input.std, [1.0; 3]);
+        assert_eq!(output.detection.format, DetectionFormat::Yolo);
+        assert_eq!(output.detection.score_threshold, 0.25);
+
+        config.set("std", "0.2, 0.2".to_string());
+        assert!(specs_from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_tensor_output_reuses_its_buffer() {
+        let spec = OutputSpec {
+            detection: DetectionParams::default(),
+            normalize: false,
+        };
+        let mut tensor = FloatTensor::default();
+        tensor
+            .decode(&[1, 2], &[1.0, 2.0], &spec, &InputInfo::default())
+            .unwrap();
+        let shared = tensor.data.clone();
+        tensor
+            .decode(&[1, 2], &[3.0, 4.0], &spec, &InputInfo::default())
+            .unwrap();
+        // The first buffer was still shared, a new one was allocated.
+        assert_eq!(&shared[..], &[1.0, 2.0]);
+        drop(shared);
+        let buffer = tensor.data.as_ptr();
+        tensor
+            .decode(&[1, 2], &[5.0, 6.0], &spec, &InputInfo::default())
+            .unwrap();
+        assert_eq!(tensor.data.as_ptr(), buffer);
+        assert_eq!(tensor.shape, vec![1, 2]);
+        assert_eq!(&tensor.data[..], &[5.0, 6.0]);
+    }
+}
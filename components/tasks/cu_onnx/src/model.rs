@@ -0,0 +1,270 @@
+//! Loading of the models and inference with ONNX Runtime.
+
+use cu29::config::ComponentConfig;
+use cu29::CuResult;
+use cu29_traits::CuError;
+use ort::execution_providers::{
+    CPUExecutionProvider, CUDAExecutionProvider, ExecutionProviderDispatch,
+    TensorRTExecutionProvider,
+};
+use ort::session::{Session, SessionInputValue};
+use ort::value::Tensor;
+
+/// Where the model runs. When several are given, ONNX Runtime assigns the nodes to the first one supporting them
+/// and falls back to the next ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionProvider {
+    Cpu,
+    Cuda,
+    TensorRt,
+}
+
+impl ExecutionProvider {
+    /// Parses a list like "tensorrt, cuda, cpu".
+    pub fn parse_list(list: &str) -> CuResult<Vec<Self>> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|provider| !provider.is_empty())
+            .map(|provider| match provider {
+                "cpu" => Ok(ExecutionProvider::Cpu),
+                "cuda" => Ok(ExecutionProvider::Cuda),
+                "tensorrt" => Ok(ExecutionProvider::TensorRt),
+                other => Err(format!(
+                    "Unknown execution provider '{}', expected 'cpu', 'cuda' or 'tensorrt'.",
+                    other
+                )
+                .into()),
+            })
+            .collect()
+    }
+
+    fn dispatch(&self, device_id: i32) -> ExecutionProviderDispatch {
+        match self {
+            ExecutionProvider::Cpu => CPUExecutionProvider::default().build(),
+            ExecutionProvider::Cuda => CUDAExecutionProvider::default()
+                .with_device_id(device_id)
+                .build(),
+            ExecutionProvider::TensorRt => TensorRTExecutionProvider::default()
+                .with_device_id(device_id)
+                .build(),
+        }
+    }
+}
+
+/// The model and how to run it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelConfig {
+    pub path: String,
+    pub execution_providers: Vec<ExecutionProvider>,
+    pub device_id: i32,
+    /// Number of threads of the CPU provider, ONNX Runtime decides if not set.
+    pub intra_threads: Option<usize>,
+    /// Path of the ONNX Runtime shared library, ORT_DYLIB_PATH or the system one if not set.
+    pub dylib_path: Option<String>,
+    /// Replaces the dynamic dimensions of the input of the model.
+    pub input_shape: Option<Vec<usize>>,
+    /// Name of the output to decode, the first one if not set.
+    pub output: Option<String>,
+}
+
+impl ModelConfig {
+    pub fn from_config(config: &ComponentConfig) -> CuResult<Self> {
+        let input_shape = config
+            .get::<String>("input_shape")
+            .map(|shape| {
+                shape
+                    .split(',')
+                    .map(|dim| {
+                        dim.trim().parse::<usize>().map_err(|e| {
+                            CuError::new_with_cause(&format!("Invalid input_shape '{}'", shape), e)
+                        })
+                    })
+                    .collect::<CuResult<Vec<_>>>()
+            })
+            .transpose()?;
+        Ok(ModelConfig {
+            path: config
+                .get::<String>("model")
+                .ok_or("The ONNX task needs the path of its 'model'.")?,
+            execution_providers: ExecutionProvider::parse_list(
+                &config
+                    .get::<String>("execution_providers")
+                    .unwrap_or("cpu".to_string()),
+            )?,
+            device_id: config.get::<i32>("device_id").unwrap_or(0),
+            intra_threads: config.get::<u32>("intra_threads").map(|n| n as usize),
+            dylib_path: config.get::<String>("dylib_path"),
+            input_shape,
+            output: config.get::<String>("output"),
+        })
+    }
+}
+
+/// Resolves the shape of the input tensor from the dimensions declared by the model (-1 for a dynamic one) and the
+/// optional shape from the config. A dynamic batch dimension defaults to 1.
+pub fn resolve_shape(model: &[i64], config: Option<&[usize]>) -> CuResult<Vec<usize>> {
+    if let Some(shape) = config {
+        let compatible = shape.len() == model.len()
+            && model
+                .iter()
+                .zip(shape)
+                .all(|(m, s)| *m < 0 || *m as usize == *s);
+        if !compatible {
+            return Err(format!(
+                "The input_shape {:?} does not match the input {:?} of the model.",
+                shape, model
+            )
+            .into());
+        }
+        return Ok(shape.to_vec());
+    }
+    model
+        .iter()
+        .enumerate()
+        .map(|(i, dim)| match *dim {
+            dim if dim >= 0 => Ok(dim as usize),
+            _ if i == 0 => Ok(1),
+            _ => Err(format!(
+                "The input {:?} of the model has dynamic dimensions, set its 'input_shape'.",
+                model
+            )
+            .into()),
+        })
+        .collect()
+}
+
+/// A loaded model with its preallocated input tensor.
+pub struct OnnxModel {
+    session: Session,
+    input: Tensor<f32>,
+    input_shape: Vec<usize>,
+    output_index: usize,
+}
+
+impl OnnxModel {
+    pub fn load(config: &ModelConfig) -> CuResult<Self> {
+        let environment = match &config.dylib_path {
+            Some(path) => ort::init_from(path),
+            None => ort::init(),
+        };
+        environment
+            .with_name("copper")
+            .commit()
+            .map_err(|e| CuError::new_with_cause("Could not load ONNX Runtime", e))?;
+
+        let mut builder = Session::builder()
+            .and_then(|builder| {
+                builder.with_execution_providers(
+                    config
+                        .execution_providers
+                        .iter()
+                        .map(|provider| provider.dispatch(config.device_id)),
+                )
+            })
+            .map_err(|e| CuError::new_with_cause("Could not set the execution providers", e))?;
+        if let Some(threads) = config.intra_threads {
+            builder = builder
+                .with_intra_threads(threads)
+                .map_err(|e| CuError::new_with_cause("Could not set the intra_threads", e))?;
+        }
+        let session = builder.commit_from_file(&config.path).map_err(|e| {
+            CuError::new_with_cause(&format!("Could not load the model {}", config.path), e)
+        })?;
+
+        let [input] = &session.inputs[..] else {
+            return Err(format!(
+                "The model {} has {} inputs, only single input models are supported.",
+                config.path,
+                session.inputs.len()
+            )
+            .into());
+        };
+        let dimensions = input
+            .input_type
+            .tensor_dimensions()
+            .ok_or_else(|| CuError::from(format!("The input '{}' is not a tensor.", input.name)))?;
+        let input_shape = resolve_shape(dimensions, config.input_shape.as_deref())?;
+        let len = input_shape.iter().product::<usize>();
+        let input = Tensor::from_array((input_shape.clone(), vec![0.0f32; len]))
+            .map_err(|e| CuError::new_with_cause("Could not allocate the input tensor", e))?;
+
+        let output_index = match &config.output {
+            Some(name) => session
+                .outputs
+                .iter()
+                .position(|output| &output.name == name)
+                .ok_or_else(|| CuError::from(format!("The model has no output '{}'.", name)))?,
+            None => 0,
+        };
+        Ok(OnnxModel {
+            session,
+            input,
+            input_shape,
+            output_index,
+        })
+    }
+
+    pub fn input_shape(&self) -> &[usize] {
+        &self.input_shape
+    }
+
+    /// The input tensor, to be filled before [OnnxModel::run].
+    pub fn input_mut(&mut self) -> &mut [f32] {
+        self.input.extract_raw_tensor_mut().1
+    }
+
+    /// Runs the model and hands the shape and the values of the selected output to decode.
+    pub fn run<R>(&mut self, decode: impl FnOnce(&[i64], &[f32]) -> CuResult<R>) -> CuResult<R> {
+        let outputs = self
+            .session
+            .run([SessionInputValue::from(self.input.view())])
+            .map_err(|e| CuError::new_with_cause("Inference failed", e))?;
+        let (shape, data) = outputs[self.output_index]
+            .try_extract_raw_tensor::<f32>()
+            .map_err(|e| CuError::new_with_cause("The output is not a f32 tensor", e))?;
+        decode(shape, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_shape() {
+        assert_eq!(
+            resolve_shape(&[-1, 3, 224, 224], None).unwrap(),
+            vec![1, 3, 224, 224]
+        );
+        assert!(resolve_shape(&[1, 3, -1, -1], None).is_err());
+        assert_eq!(
+            resolve_shape(&[1, 3, -1, -1], Some(&[1, 3, 480, 640])).unwrap(),
+            vec![1, 3, 480, 640]
+        );
+        assert!(resolve_shape(&[1, 3, -1, -1], Some(&[1, 1, 480, 640])).is_err());
+        assert!(resolve_shape(&[1, 3, -1, -1], Some(&[3, 480, 640])).is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        let mut config = ComponentConfig::new();
+        assert!(ModelConfig::from_config(&config).is_err());
+        config.set("model", "yolo.onnx".to_string());
+        config.set("execution_providers", "tensorrt, cuda,cpu".to_string());
+        config.set("input_shape", "1, 3, 640, 640".to_string());
+        let model = ModelConfig::from_config(&config).unwrap();
+        assert_eq!(
+            model.execution_providers,
+            vec![
+                ExecutionProvider::TensorRt,
+                ExecutionProvider::Cuda,
+                ExecutionProvider::Cpu
+            ]
+        );
+        assert_eq!(model.input_shape, Some(vec![1, 3, 640, 640]));
+        assert_eq!(model.device_id, 0);
+
+        config.set("execution_providers", "tpu".to_string());
+        assert!(ModelConfig::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Decoding of the output tensors of the models.
+
+use crate::preprocess::InputInfo;
+use crate::Detection;
+use cu29::CuResult;
+
+/// Layout of the output tensor of a detection model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionFormat {
+    /// [1, N, 6]: rows of x1, y1, x2, y2, score, class, as output by the models exported with their NMS.
+    Boxes,
+    /// [1, 4 + classes, N]: columns of center x, center y, width, height and the score of each class, as output by
+    /// the YOLOv8 style models.
+    Yolo,
+    /// [1, N, 4 + classes]: the transposed [DetectionFormat::Yolo].
+    YoloTransposed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionParams {
+    pub format: DetectionFormat,
+    pub score_threshold: f32,
+    /// Boxes of the same class overlapping a better one by more than this intersection over union are dropped.
+    pub iou_threshold: f32,
+    pub max_detections: usize,
+}
+
+impl Default for DetectionParams {
+    fn default() -> Self {
+        DetectionParams {
+            format: DetectionFormat::Boxes,
+            score_threshold: 0.5,
+            iou_threshold: 0.45,
+            max_detections: 100,
+        }
+    }
+}
+
+/// Decodes the detections in the coordinates of the input message, best first.
+pub fn decode_detections(
+    shape: &[i64],
+    data: &[f32],
+    params: &DetectionParams,
+    info: &InputInfo,
+    out: &mut Vec<Detection>,
+) -> CuResult<()> {
+    out.clear();
+    let &[1, a, b] = shape else {
+        return Err(format!("Expected a [1, _, _] detection output, got {:?}.", shape).into());
+    };
+    let (a, b) = (a as usize, b as usize);
+    let scale = |bbox: [f32; 4]| {
+        [
+            bbox[0] * info.scale_x,
+            bbox[1] * info.scale_y,
+            bbox[2] * info.scale_x,
+            bbox[3] * info.scale_y,
+        ]
+    };
+    match params.format {
+        DetectionFormat::Boxes => {
+            if b < 6 {
+                return Err(format!("Expected rows of 6 values, got {:?}.", shape).into());
+            }
+            for row in data.chunks_exact(b) {
+                if row[4] >= params.score_threshold {
+                    out.push(Detection {
+                        class_id: row[5] as u32,
+                        score: row[4],
+                        bbox: scale([row[0], row[1], row[2], row[3]]),
+                    });
+                }
+            }
+        }
+        DetectionFormat::Yolo | DetectionFormat::YoloTransposed => {
+            let transposed = params.format == DetectionFormat::YoloTransposed;
+            let (attributes, candidates) = if transposed { (b, a) } else { (a, b) };
+            if attributes < 5 {
+                return Err(format!("Expected at least 5 attributes, got {:?}.", shape).into());
+            }
+            let at = |attribute: usize, candidate: usize| {
+                if transposed {
+                    data[candidate * attributes + attribute]
+                } else {
+                    data[attribute * candidates + candidate]
+                }
+            };
+            for candidate in 0..candidates {
+                let (class_id, score) = (4..attributes)
+                    .map(|attribute| (attribute - 4, at(attribute, candidate)))
+                    .fold((0, f32::MIN), |best, c| if c.1 > best.1 { c } else { best });
+                if score < params.score_threshold {
+                    continue;
+                }
+                let (cx, cy) = (at(0, candidate), at(1, candidate));
+                let (w, h) = (at(2, candidate), at(3, candidate));
+                out.push(Detection {
+                    class_id: class_id as u32,
+                    score,
+                    bbox: scale([cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0]),
+                });
+            }
+        }
+    }
+    non_max_suppression(out, params.iou_threshold);
+    out.truncate(params.max_detections);
+    Ok(())
+}
+
+/// Sorts the detections by decreasing score and drops the ones overlapping a better one of the same class.
+pub fn non_max_suppression(detections: &mut Vec<Detection>, iou_threshold: f32) {
+    detections.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept = 0;
+    for i in 0..detections.len() {
+        let candidate = &detections[i];
+        let overlaps = detections[..kept].iter().any(|better| {
+            better.class_id == candidate.class_id
+                && iou(&better.bbox, &candidate.bbox) > iou_threshold
+        });
+        if !overlaps {
+            detections.swap(kept, i);
+            kept += 1;
+        }
+    }
+    detections.truncate(kept);
+}
+
+/// Intersection over union of two boxes x1, y1, x2, y2.
+pub fn iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let width = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let height = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let intersection = width * height;
+    let area = |r: &[f32; 4]| (r[2] - r[0]) * (r[3] - r[1]);
+    let union = area(a) + area(b) - intersection;
+    if union <= 0.0 {
+        0.0
+    } else {
+        intersection / union
+    }
+}
+
+/// Scales the vector to a unit length, if it is not null.
+pub fn l2_normalize(values: &mut [f32]) {
+    let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        values.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boxes() {
+        let data = [
+            10.0, 10.0, 20.0, 20.0, 0.9, 1.0, // kept
+            11.0, 11.0, 21.0, 21.0, 0.8, 1.0, // overlaps the first one
+            11.0, 11.0, 21.0, 21.0, 0.7, 2.0, // another class
+            50.0, 50.0, 60.0, 60.0, 0.2, 1.0, // below the threshold
+        ];
+        let info = InputInfo {
+            scale_x: 2.0,
+            scale_y: 1.0,
+        };
+        let mut out = Vec::new();
+        decode_detections(
+            &[1, 4, 6],
+            &data,
+            &DetectionParams::default(),
+            &info,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            vec![
+                Detection {
+                    class_id: 1,
+                    score: 0.9,
+                    bbox: [20.0, 10.0, 40.0, 20.0]
+                },
+                Detection {
+                    class_id: 2,
+                    score: 0.7,
+                    bbox: [22.0, 11.0, 42.0, 21.0]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_yolo() {
+        // 2 classes, 3 candidates, one column per candidate.
+        let data = [
+            10.0, 100.0, 10.5, // cx
+            10.0, 100.0, 10.5, // cy
+            4.0, 8.0, 4.0, // w
+            2.0, 8.0, 2.0, // h
+            0.1, 0.3, 0.6, // class 0
+            0.9, 0.1, 0.2, // class 1
+        ];
+        let params = DetectionParams {
+            format: DetectionFormat::Yolo,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        decode_detections(&[1, 6, 3], &data, &params, &InputInfo::default(), &mut out).unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].class_id, 1);
+        assert_eq!(out[0].bbox, [8.0, 9.0, 12.0, 11.0]);
+        assert_eq!(out[1].class_id, 0);
+
+        // Same candidates, one row per candidate.
+        let mut transposed = [0.0; 18];
+        for (i, value) in data.iter().enumerate() {
+            transposed[(i % 3) * 6 + i / 3] = *value;
+        }
+        let params = DetectionParams {
+            format: DetectionFormat::YoloTransposed,
+            ..Default::default()
+        };
+        let mut out_transposed = Vec::new();
+        decode_detections(
+            &[1, 3, 6],
+            &transposed,
+            &params,
+            &InputInfo::default(),
+            &mut out_transposed,
+        )
+        .unwrap();
+        assert_eq!(out, out_transposed);
+    }
+
+    #[test]
+    fn test_iou() {
+        assert_eq!(iou(&[0.0, 0.0, 2.0, 2.0], &[1.0, 0.0, 3.0, 2.0]), 1.0 / 3.0);
+        assert_eq!(iou(&[0.0, 0.0, 1.0, 1.0], &[2.0, 2.0, 3.0, 3.0]), 0.0);
+    }
+}
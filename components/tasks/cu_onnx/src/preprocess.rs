@@ -0,0 +1,212 @@
+//! Conversion of the input messages to the input tensor of the model.
+
+use cu29::CuResult;
+use cu_sensor_payloads::Image;
+
+/// Order of the dimensions of an image tensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Batch, channels, height, width: the usual layout of the vision models.
+    Nchw,
+    /// Batch, height, width, channels.
+    Nhwc,
+}
+
+/// How the input messages are written to the input tensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSpec {
+    /// Shape of the input tensor, batch dimension included.
+    pub shape: Vec<usize>,
+    pub layout: Layout,
+    /// Feeds the channels as blue, green, red instead of red, green, blue.
+    pub bgr: bool,
+    /// A pixel component c is fed as (c / 255 - mean) / std, per channel.
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+}
+
+impl Default for InputSpec {
+    fn default() -> Self {
+        InputSpec {
+            shape: Vec::new(),
+            layout: Layout::Nchw,
+            bgr: false,
+            mean: [0.0; 3],
+            std: [1.0; 3],
+        }
+    }
+}
+
+impl InputSpec {
+    /// The number of elements of the input tensor.
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The channels, height and width of the images expected by the model.
+    pub fn image_dims(&self) -> CuResult<(usize, usize, usize)> {
+        let [_, a, b, c] = self.shape[..] else {
+            return Err(format!(
+                "An image input needs a 4 dimensions tensor, the model expects {:?}.",
+                self.shape
+            )
+            .into());
+        };
+        let (channels, height, width) = match self.layout {
+            Layout::Nchw => (a, b, c),
+            Layout::Nhwc => (c, a, b),
+        };
+        if channels != 1 && channels != 3 {
+            return Err(format!(
+                "An image input needs 1 or 3 channels, the model expects {:?}.",
+                self.shape
+            )
+            .into());
+        }
+        Ok((channels, height, width))
+    }
+}
+
+/// What the decoding of the outputs needs to know about the input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputInfo {
+    /// Factors from the coordinates in the input tensor to the coordinates in the input message.
+    pub scale_x: f32,
+    pub scale_y: f32,
+}
+
+impl Default for InputInfo {
+    fn default() -> Self {
+        InputInfo {
+            scale_x: 1.0,
+            scale_y: 1.0,
+        }
+    }
+}
+
+/// Resizes the image to the size expected by the model with a bilinear interpolation, normalizes it and writes it
+/// to the tensor of the first batch.
+pub fn image_to_tensor(image: &Image, spec: &InputSpec, tensor: &mut [f32]) -> CuResult<InputInfo> {
+    let (channels, height, width) = spec.image_dims()?;
+    if image.width == 0 || image.height == 0 {
+        return Err("Empty image.".into());
+    }
+    if image.is_on_device() {
+        return Err(
+            "The image is in the memory of a GPU, the CPU preprocessing cannot read it.".into(),
+        );
+    }
+    let scale_x = image.width as f32 / width as f32;
+    let scale_y = image.height as f32 / height as f32;
+    let plane = width * height;
+
+    for ty in 0..height {
+        // Pixel centers are aligned, as in most resizing libraries.
+        let sy = ((ty as f32 + 0.5) * scale_y - 0.5).clamp(0.0, (image.height - 1) as f32);
+        let (y0, fy) = (sy as u32, sy.fract());
+        let y1 = (y0 + 1).min(image.height - 1);
+        for tx in 0..width {
+            let sx = ((tx as f32 + 0.5) * scale_x - 0.5).clamp(0.0, (image.width - 1) as f32);
+            let (x0, fx) = (sx as u32, sx.fract());
+            let x1 = (x0 + 1).min(image.width - 1);
+            let (p00, p10) = (image.rgb(x0, y0), image.rgb(x1, y0));
+            let (p01, p11) = (image.rgb(x0, y1), image.rgb(x1, y1));
+            let mut rgb = [0.0f32; 3];
+            for c in 0..3 {
+                let top = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+                let bottom = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+                rgb[c] = top * (1.0 - fy) + bottom * fy;
+            }
+            if spec.bgr {
+                rgb.swap(0, 2);
+            }
+            let pixel = ty * width + tx;
+            if channels == 1 {
+                let luma = (rgb[0] * 77.0 + rgb[1] * 150.0 + rgb[2] * 29.0) / 256.0;
+                tensor[pixel] = (luma / 255.0 - spec.mean[0]) / spec.std[0];
+                continue;
+            }
+            for (c, value) in rgb.iter().enumerate() {
+                let value = (value / 255.0 - spec.mean[c]) / spec.std[c];
+                match spec.layout {
+                    Layout::Nchw => tensor[c * plane + pixel] = value,
+                    Layout::Nhwc => tensor[pixel * 3 + c] = value,
+                }
+            }
+        }
+    }
+    Ok(InputInfo { scale_x, scale_y })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::pool::CuHandle;
+    use cu_sensor_payloads::ImageFormat;
+
+    fn rgb_image() -> Image {
+        // 4x2, the left half red, the right half blue.
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            for x in 0..4 {
+                data.extend_from_slice(if x < 2 { &[255, 0, 0] } else { &[0, 0, 255] });
+            }
+        }
+        Image {
+            width: 4,
+            height: 2,
+            stride: 12,
+            format: ImageFormat::Rgb8,
+            data: CuHandle::new_detached(data),
+            device: None,
+        }
+    }
+
+    #[test]
+    fn test_nchw_downscale() {
+        let spec = InputSpec {
+            shape: vec![1, 3, 1, 2],
+            ..Default::default()
+        };
+        let mut tensor = vec![0.0; spec.len()];
+        let info = image_to_tensor(&rgb_image(), &spec, &mut tensor).unwrap();
+        assert_eq!(
+            info,
+            InputInfo {
+                scale_x: 2.0,
+                scale_y: 2.0
+            }
+        );
+        // r r, g g, b b
+        assert_eq!(tensor, vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_nhwc_bgr_normalized() {
+        let spec = InputSpec {
+            shape: vec![1, 2, 4, 3],
+            layout: Layout::Nhwc,
+            bgr: true,
+            mean: [0.5; 3],
+            std: [0.5; 3],
+        };
+        let mut tensor = vec![0.0; spec.len()];
+        image_to_tensor(&rgb_image(), &spec, &mut tensor).unwrap();
+        assert_eq!(&tensor[..3], &[-1.0, -1.0, 1.0]);
+        assert_eq!(&tensor[9..12], &[1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn test_invalid_shape() {
+        let spec = InputSpec {
+            shape: vec![1, 4, 2, 2],
+            ..Default::default()
+        };
+        let mut tensor = vec![0.0; spec.len()];
+        assert!(image_to_tensor(&rgb_image(), &spec, &mut tensor).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! The steering laws, independent from Copper so they can be tested and reused easily.
+
+use cu_spatial_payloads::{Pose, Twist};
+use std::f64::consts::PI;
+
+/// Which steering law to use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SteeringLaw {
+    /// Pure pursuit: steers toward a point of the path at the lookahead distance.
+    PurePursuit,
+    /// Stanley: corrects the heading error and the cross track error relative to the path.
+    Stanley { gain: f64, wheelbase: f64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FollowerParams {
+    pub law: SteeringLaw,
+    /// Distance of the point to steer to for pure pursuit, also how close a waypoint needs
+    /// to be for the follower to move on to the next one.
+    pub lookahead: f64,
+    pub max_speed: f64,
+    pub max_angular_speed: f64,
+    /// Distance to the last waypoint under which the goal is considered reached.
+    pub goal_tolerance: f64,
+    /// Distance to the last waypoint under which the robot slows down linearly.
+    pub slowdown_distance: f64,
+}
+
+/// Follows a list of waypoints, keeping track of the progress along them.
+pub struct PathFollower {
+    params: FollowerParams,
+    path: Vec<Pose>,
+    /// Index of the segment [target - 1, target] the robot is on.
+    target: usize,
+    arrived: bool,
+}
+
+fn normalize_angle(angle: f64) -> f64 {
+    let mut angle = angle % (2.0 * PI);
+    if angle > PI {
+        angle -= 2.0 * PI;
+    } else if angle < -PI {
+        angle += 2.0 * PI;
+    }
+    angle
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+impl PathFollower {
+    pub fn new(params: FollowerParams) -> Self {
+        PathFollower {
+            params,
+            path: Vec::new(),
+            target: 0,
+            arrived: false,
+        }
+    }
+
+    /// Starts following a new path from its beginning.
+    pub fn set_path(&mut self, path: Vec<Pose>) {
+        self.arrived = path.is_empty();
+        self.path = path;
+        self.target = 0;
+    }
+
+    pub fn path(&self) -> &[Pose] {
+        &self.path
+    }
+
+    pub fn target(&self) -> usize {
+        self.target
+    }
+
+    pub fn arrived(&self) -> bool {
+        self.arrived
+    }
+
+    /// Computes the velocity command for the robot at pose.
+    pub fn command(&mut self, pose: &Pose) -> Twist {
+        if self.arrived || self.path.is_empty() {
+            return Twist::default();
+        }
+        let position = (pose.x, pose.y);
+        let last = self.path.len() - 1;
+        let goal = (self.path[last].x, self.path[last].y);
+        let to_goal = distance(position, goal);
+        if to_goal < self.params.goal_tolerance {
+            self.arrived = true;
+            return Twist::default();
+        }
+
+        // Move on to the next waypoints as we get close to them or once they are behind us.
+        while self.target < last
+            && (self.passed(position)
+                || distance(
+                    position,
+                    (self.path[self.target].x, self.path[self.target].y),
+                ) < self.params.lookahead)
+        {
+            self.target += 1;
+        }
+
+        let speed = if self.params.slowdown_distance > 0.0 {
+            self.params.max_speed * (to_goal / self.params.slowdown_distance).min(1.0)
+        } else {
+            self.params.max_speed
+        };
+
+        let angular = match self.params.law {
+            SteeringLaw::PurePursuit => self.pure_pursuit(pose, speed),
+            SteeringLaw::Stanley { gain, wheelbase } => self.stanley(pose, speed, gain, wheelbase),
+        };
+
+        // Saturate the rotation keeping the curvature so the robot stays on its arc.
+        let max_angular = self.params.max_angular_speed;
+        if angular.abs() > max_angular && max_angular > 0.0 {
+            let scale = max_angular / angular.abs();
+            Twist::new_2d(speed * scale, angular * scale)
+        } else {
+            Twist::new_2d(speed, angular)
+        }
+    }
+
+    /// True if the robot is further than the target waypoint along the next segment.
+    fn passed(&self, position: (f64, f64)) -> bool {
+        let (a, b) = (&self.path[self.target], &self.path[self.target + 1]);
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        (position.0 - a.x) * dx + (position.1 - a.y) * dy > 0.0
+    }
+
+    /// Start of the current segment, the first waypoint is reached from where the robot is.
+    fn segment_start(&self, pose: &Pose) -> (f64, f64) {
+        if self.target == 0 {
+            (pose.x, pose.y)
+        } else {
+            let p = &self.path[self.target - 1];
+            (p.x, p.y)
+        }
+    }
+
+    /// Point of the path at the lookahead distance of the robot.
+    fn lookahead_point(&self, pose: &Pose) -> (f64, f64) {
+        let (px, py) = (pose.x, pose.y);
+        let r = self.params.lookahead;
+        let mut start = self.segment_start(pose);
+        for waypoint in &self.path[self.target..] {
+            let end = (waypoint.x, waypoint.y);
+            // Furthest intersection of the lookahead circle with the segment.
+            let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+            let (fx, fy) = (start.0 - px, start.1 - py);
+            let a = dx * dx + dy * dy;
+            let b = 2.0 * (fx * dx + fy * dy);
+            let c = fx * fx + fy * fy - r * r;
+            let discriminant = b * b - 4.0 * a * c;
+            if a > 0.0 && discriminant >= 0.0 {
+                let t = (-b + discriminant.sqrt()) / (2.0 * a);
+                if (0.0..=1.0).contains(&t) {
+                    return (start.0 + t * dx, start.1 + t * dy);
+                }
+            }
+            start = end;
+        }
+        let waypoint = &self.path[self.target];
+        (waypoint.x, waypoint.y)
+    }
+
+    fn pure_pursuit(&self, pose: &Pose, speed: f64) -> f64 {
+        let (gx, gy) = self.lookahead_point(pose);
+        let (dx, dy) = (gx - pose.x, gy - pose.y);
+        // lateral offset of the goal in the frame of the robot.
+        let lateral = -pose.yaw.sin() * dx + pose.yaw.cos() * dy;
+        let l2 = dx * dx + dy * dy;
+        if l2 == 0.0 {
+            return 0.0;
+        }
+        let curvature = 2.0 * lateral / l2;
+        speed * curvature
+    }
+
+    fn stanley(&self, pose: &Pose, speed: f64, gain: f64, wheelbase: f64) -> f64 {
+        let start = self.segment_start(pose);
+        let end = (self.path[self.target].x, self.path[self.target].y);
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return 0.0;
+        }
+        let path_heading = dy.atan2(dx);
+        let heading_error = normalize_angle(path_heading - pose.yaw);
+        // positive when the robot is on the right of the path.
+        let cross_track = (dx * (start.1 - pose.y) - dy * (start.0 - pose.x)) / length;
+        let steering = heading_error + (gain * cross_track).atan2(speed.max(0.1));
+        speed * steering.tan() / wheelbase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(law: SteeringLaw) -> FollowerParams {
+        FollowerParams {
+            law,
+            lookahead: 1.0,
+            max_speed: 1.0,
+            max_angular_speed: 2.0,
+            goal_tolerance: 0.1,
+            slowdown_distance: 0.0,
+        }
+    }
+
+    fn straight_path() -> Vec<Pose> {
+        (1..=10).map(|i| Pose::new_2d(i as f64, 0.0, 0.0)).collect()
+    }
+
+    #[test]
+    fn test_pure_pursuit_straight_and_turning() {
+        let mut follower = PathFollower::new(params(SteeringLaw::PurePursuit));
+        follower.set_path(straight_path());
+        let twist = follower.command(&Pose::new_2d(0.0, 0.0, 0.0));
+        assert_eq!(twist.vx, 1.0);
+        assert!(twist.wz.abs() < 1e-9);
+
+        // The path is on the left of the robot: turn left.
+        let twist = follower.command(&Pose::new_2d(2.0, -0.5, 0.0));
+        assert!(twist.wz > 0.0);
+        // On the right: turn right.
+        let twist = follower.command(&Pose::new_2d(2.0, 0.5, 0.0));
+        assert!(twist.wz < 0.0);
+    }
+
+    #[test]
+    fn test_stanley() {
+        let mut follower = PathFollower::new(params(SteeringLaw::Stanley {
+            gain: 1.0,
+            wheelbase: 0.5,
+        }));
+        follower.set_path(straight_path());
+        assert!(follower.command(&Pose::new_2d(0.0, 0.0, 0.0)).wz.abs() < 1e-9);
+        assert!(follower.command(&Pose::new_2d(2.0, -0.5, 0.0)).wz > 0.0);
+        // heading off to the left of the path.
+        assert!(follower.command(&Pose::new_2d(2.0, 0.0, 0.3)).wz < 0.0);
+    }
+
+    #[test]
+    fn test_limits_and_arrival() {
+        let mut p = params(SteeringLaw::PurePursuit);
+        p.slowdown_distance = 2.0;
+        p.max_angular_speed = 0.5;
+        let mut follower = PathFollower::new(p);
+        follower.set_path(vec![Pose::new_2d(1.0, 0.0, 0.0)]);
+        let twist = follower.command(&Pose::new_2d(0.0, 0.0, 0.0));
+        assert!((twist.vx - 0.5).abs() < 1e-9);
+
+        // sharp turn: the angular speed is saturated and the linear one reduced with it.
+        follower.set_path(vec![Pose::new_2d(0.0, 5.0, 0.0)]);
+        let twist = follower.command(&Pose::new_2d(0.0, 0.0, 0.0));
+        assert!((twist.wz - 0.5).abs() < 1e-9);
+        assert!(twist.vx < 1.0);
+
+        let twist = follower.command(&Pose::new_2d(0.0, 4.95, 0.0));
+        assert_eq!(twist, Twist::default());
+        assert!(follower.arrived());
+    }
+}
@@ -0,0 +1,161 @@
+mod follower;
+
+pub use follower::{FollowerParams, PathFollower, SteeringLaw};
+
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu_spatial_payloads::{Pose, Twist, Waypoints};
+
+/// Follows the last received waypoints from the odometry of the robot and outputs velocity commands.
+/// The follower restarts from the first waypoint every time a new list is received.
+pub struct PathFollowerTask {
+    follower: PathFollower,
+}
+
+impl Freezable for PathFollowerTask {}
+
+impl CuTaskLifecycle for PathFollowerTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("PathFollowerTask needs a config.")?;
+        let algorithm = config
+            .get::<String>("algorithm")
+            .unwrap_or("pure_pursuit".to_string());
+        let law = match algorithm.as_str() {
+            "pure_pursuit" => SteeringLaw::PurePursuit,
+            "stanley" => SteeringLaw::Stanley {
+                gain: config.get::<f64>("stanley_gain").unwrap_or(1.0),
+                wheelbase: config
+                    .get::<f64>("wheelbase")
+                    .ok_or("PathFollowerTask needs a 'wheelbase' with the stanley algorithm.")?,
+            },
+            other => {
+                return Err(format!(
+                    "Unknown algorithm '{}', expected 'pure_pursuit' or 'stanley'.",
+                    other
+                )
+                .into())
+            }
+        };
+        let params = FollowerParams {
+            law,
+            lookahead: config.get::<f64>("lookahead").unwrap_or(1.0),
+            max_speed: config
+                .get::<f64>("max_speed")
+                .ok_or("PathFollowerTask needs a 'max_speed'.")?,
+            max_angular_speed: config
+                .get::<f64>("max_angular_speed")
+                .unwrap_or(f64::INFINITY),
+            goal_tolerance: config.get::<f64>("goal_tolerance").unwrap_or(0.1),
+            slowdown_distance: config.get::<f64>("slowdown_distance").unwrap_or(0.0),
+        };
+        Ok(PathFollowerTask {
+            follower: PathFollower::new(params),
+        })
+    }
+}
+
+impl<'cl> CuTask<'cl> for PathFollowerTask {
+    type Input = input_msg!('cl, Pose, Waypoints);
+    type Output = output_msg!('cl, Twist);
+
+    fn process(
+        &mut self,
+        _ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let (odometry, waypoints) = input;
+        if let Some(waypoints) = waypoints.payload() {
+            debug!(
+                "Path follower: new path of {} waypoints.",
+                waypoints.poses.len()
+            );
+            self.follower.set_path(waypoints.poses.clone());
+        }
+        let Some(pose) = odometry.payload() else {
+            // No idea where we are, don't move.
+            output.set_payload(Twist::default());
+            return Ok(());
+        };
+        let was_arrived = self.follower.arrived();
+        output.set_payload(self.follower.command(pose));
+        if self.follower.arrived() && !was_arrived {
+            debug!("Path follower: goal reached.");
+        }
+        output.metadata.tov = odometry.metadata.tov;
+        if !self.follower.arrived() {
+            output.metadata.set_status(format!(
+                "wp {}/{}",
+                self.follower.target() + 1,
+                self.follower.path().len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+
+    #[test]
+    fn test_follows_new_path() {
+        let mut config = ComponentConfig::new();
+        config.set("max_speed", 1.0);
+        config.set("lookahead", 0.5);
+        let mut task = PathFollowerTask::new(Some(&config)).unwrap();
+        let clock = RobotClock::default();
+        let odometry = CuMsg::new(Some(Pose::new_2d(0.0, 0.0, 0.0)));
+        let mut waypoints = CuMsg::new(Some(Waypoints {
+            poses: vec![Pose::new_2d(0.0, 2.0, 0.0), Pose::new_2d(0.0, 4.0, 0.0)],
+        }));
+        let mut output = CuMsg::new(None);
+
+        task.process(
+            &CuContext::from(&clock),
+            (&odometry, &waypoints),
+            &mut output,
+        )
+        .unwrap();
+        assert!(output.payload().unwrap().wz > 0.0);
+
+        // the path is kept when no new waypoints are received.
+        waypoints.clear_payload();
+        task.process(
+            &CuContext::from(&clock),
+            (&odometry, &waypoints),
+            &mut output,
+        )
+        .unwrap();
+        assert!(output.payload().unwrap().wz > 0.0);
+
+        waypoints.set_payload(Waypoints { poses: vec![] });
+        task.process(
+            &CuContext::from(&clock),
+            (&odometry, &waypoints),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output.payload().unwrap(), &Twist::default());
+    }
+
+    #[test]
+    fn test_config() {
+        let mut config = ComponentConfig::new();
+        config.set("max_speed", 1.0);
+        config.set("algorithm", "stanley".to_string());
+        assert!(PathFollowerTask::new(Some(&config)).is_err());
+        config.set("wheelbase", 0.3);
+        assert!(PathFollowerTask::new(Some(&config)).is_ok());
+        config.set("algorithm", "dwa".to_string());
+        assert!(PathFollowerTask::new(Some(&config)).is_err());
+    }
+}
@@ -4,6 +4,7 @@ use bincode::error::{DecodeError, EncodeError};
 use bincode::{Decode, Encode};
 use cu29::clock::{CuDuration, CuTime, RobotClock};
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::CuMsg;
 use cu29::cutask::{CuMsgPayload, CuTask, CuTaskLifecycle, Freezable};
 use cu29::{input_msg, output_msg, CuResult};
@@ -219,7 +220,7 @@ where
 
     fn process(
         &mut self,
-        _clock: &RobotClock,
+        _ctx: &CuContext,
         input: Self::Input,
         output: Self::Output,
     ) -> CuResult<()> {
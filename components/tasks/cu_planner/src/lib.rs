@@ -0,0 +1,190 @@
+mod search;
+
+pub use search::{Planner, PlannerKind, PlannerParams, SearchStatus};
+
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu_occupancy_grid::OccupancyGrid;
+use cu_spatial_payloads::{Pose, Waypoints};
+
+/// Plans a path from the odometry pose to the last received goal over the last received occupancy grid.
+/// The search expands at most `expansions_per_iteration` states per iteration so a long search is spread over
+/// several iterations instead of stalling the loop. The path is published once, when it is found.
+pub struct PlannerTask {
+    planner: Planner,
+    expansions_per_iteration: u32,
+    grid: Option<OccupancyGrid>,
+    odometry: Option<Pose>,
+    /// A goal received before the grid or the odometry, planned as soon as they are known.
+    pending_goal: Option<Pose>,
+}
+
+impl Freezable for PlannerTask {}
+
+impl CuTaskLifecycle for PlannerTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("PlannerTask needs a config.")?;
+        let algorithm = config
+            .get::<String>("algorithm")
+            .unwrap_or("astar".to_string());
+        let kind = match algorithm.as_str() {
+            "astar" => PlannerKind::Grid,
+            "hybrid_astar" => PlannerKind::Hybrid {
+                headings: config.get::<u32>("headings").unwrap_or(16) as usize,
+                turning_radius: config
+                    .get::<f64>("turning_radius")
+                    .ok_or("PlannerTask needs a 'turning_radius' with hybrid_astar.")?,
+            },
+            other => {
+                return Err(format!(
+                    "Unknown algorithm '{}', expected 'astar' or 'hybrid_astar'.",
+                    other
+                )
+                .into())
+            }
+        };
+        let params = PlannerParams {
+            kind,
+            occupied_threshold: config.get::<u8>("occupied_threshold").unwrap_or(50) as i8,
+            allow_unknown: config.get::<bool>("allow_unknown").unwrap_or(false),
+            robot_radius: config.get::<f64>("robot_radius").unwrap_or(0.0),
+            goal_tolerance: config.get::<f64>("goal_tolerance").unwrap_or(0.25),
+            max_expansions: config.get::<u32>("max_expansions").unwrap_or(1_000_000),
+        };
+        Ok(PlannerTask {
+            planner: Planner::new(params),
+            expansions_per_iteration: config
+                .get::<u32>("expansions_per_iteration")
+                .unwrap_or(2000),
+            grid: None,
+            odometry: None,
+            pending_goal: None,
+        })
+    }
+}
+
+impl<'cl> CuTask<'cl> for PlannerTask {
+    type Input = input_msg!('cl, OccupancyGrid, Pose, Pose);
+    type Output = output_msg!('cl, Waypoints);
+
+    fn process(
+        &mut self,
+        _ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let (grid, odometry, goal) = input;
+        if let Some(grid) = grid.payload() {
+            self.grid = Some(grid.clone());
+        }
+        if let Some(odometry) = odometry.payload() {
+            self.odometry = Some(*odometry);
+        }
+        if let Some(goal) = goal.payload() {
+            self.pending_goal = Some(*goal);
+        }
+        if let (Some(grid), Some(start), Some(goal)) =
+            (&self.grid, &self.odometry, &self.pending_goal)
+        {
+            self.planner.start(grid, start, goal);
+            self.pending_goal = None;
+        }
+
+        output.clear_payload();
+        match self.planner.status() {
+            SearchStatus::Idle => {}
+            SearchStatus::Searching => match self.planner.step(self.expansions_per_iteration) {
+                SearchStatus::Found => {
+                    let poses = self.planner.path().unwrap_or_default();
+                    debug!(
+                        "Planner: found a path of {} waypoints after {} expansions.",
+                        poses.len(),
+                        self.planner.expansions()
+                    );
+                    output.set_payload(Waypoints { poses });
+                }
+                SearchStatus::Failed => {
+                    debug!(
+                        "Planner: no path found after {} expansions.",
+                        self.planner.expansions()
+                    );
+                    output.metadata.set_status("no path");
+                }
+                _ => output
+                    .metadata
+                    .set_status(format!("searching {}", self.planner.expansions())),
+            },
+            SearchStatus::Found => {}
+            SearchStatus::Failed => output.metadata.set_status("no path"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+    use cu29::pool::CuHandle;
+
+    #[test]
+    fn test_plans_over_several_iterations() {
+        let mut config = ComponentConfig::new();
+        config.set("expansions_per_iteration", 5u32);
+        let mut task = PlannerTask::new(Some(&config)).unwrap();
+        let clock = RobotClock::default();
+        let grid = CuMsg::new(Some(OccupancyGrid {
+            width: 10,
+            height: 10,
+            resolution: 1.0,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            cells: CuHandle::new_detached(vec![0; 100]),
+        }));
+        let mut odometry = CuMsg::new(None);
+        let mut goal = CuMsg::new(Some(Pose::new_2d(8.5, 0.5, 0.0)));
+        let mut output = CuMsg::new(None);
+
+        // No odometry yet, the goal is kept for later.
+        task.process(
+            &CuContext::from(&clock),
+            (&grid, &odometry, &goal),
+            &mut output,
+        )
+        .unwrap();
+        assert!(output.payload().is_none());
+
+        odometry.set_payload(Pose::new_2d(0.5, 0.5, 0.0));
+        goal.clear_payload();
+        let mut iterations = 1;
+        while iterations < 100 {
+            task.process(
+                &CuContext::from(&clock),
+                (&grid, &odometry, &goal),
+                &mut output,
+            )
+            .unwrap();
+            if output.payload().is_some() {
+                break;
+            }
+            iterations += 1;
+        }
+        assert!(iterations > 1);
+        assert_eq!(output.payload().unwrap().poses.len(), 8);
+        // Published once.
+        task.process(
+            &CuContext::from(&clock),
+            (&grid, &odometry, &goal),
+            &mut output,
+        )
+        .unwrap();
+        assert!(output.payload().is_none());
+    }
+}
@@ -0,0 +1,471 @@
+//! The A* and hybrid A* searches, both resumable so they can be spread over several iterations.
+
+use cu_occupancy_grid::{OccupancyGrid, UNKNOWN};
+use cu_spatial_payloads::Pose;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlannerKind {
+    /// A* on the cells of the grid, 8-connected.
+    Grid,
+    /// Hybrid A*: the robot moves along arcs so the path respects a minimum turning radius.
+    /// The search is done over the cells and `headings` discretized orientations.
+    Hybrid {
+        headings: usize,
+        turning_radius: f64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlannerParams {
+    pub kind: PlannerKind,
+    /// A cell at this occupancy (in percent) or more is an obstacle.
+    pub occupied_threshold: i8,
+    /// If false the unknown cells are obstacles.
+    pub allow_unknown: bool,
+    /// The obstacles are inflated by this radius.
+    pub robot_radius: f64,
+    /// Distance to the goal under which the goal is reached (hybrid A* only, A* goes to the goal cell).
+    pub goal_tolerance: f64,
+    /// The search gives up after this number of expanded states.
+    pub max_expansions: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchStatus {
+    Idle,
+    Searching,
+    Found,
+    Failed,
+}
+
+#[derive(PartialEq)]
+struct OpenEntry {
+    f: f64,
+    index: u32,
+}
+
+impl Eq for OpenEntry {}
+
+// Reversed so the BinaryHeap pops the lowest f first.
+impl Ord for OpenEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.total_cmp(&self.f)
+    }
+}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+const NO_PARENT: u32 = u32::MAX;
+
+/// A path planner over an occupancy grid.
+/// The buffers of the search are allocated for the size of the grid and reused from one search to the next.
+pub struct Planner {
+    params: PlannerParams,
+    grid: Option<OccupancyGrid>,
+    /// Cells covered by the robot around its center.
+    footprint: Vec<(i64, i64)>,
+    open: BinaryHeap<OpenEntry>,
+    cost: Vec<f64>,
+    parent: Vec<u32>,
+    closed: Vec<bool>,
+    /// The continuous pose reached in every state.
+    poses: Vec<(f64, f64, f64)>,
+    goal: Pose,
+    expansions: u32,
+    found: Option<u32>,
+    status: SearchStatus,
+}
+
+fn normalize_angle(angle: f64) -> f64 {
+    angle.rem_euclid(2.0 * PI)
+}
+
+impl Planner {
+    pub fn new(params: PlannerParams) -> Self {
+        Planner {
+            params,
+            grid: None,
+            footprint: Vec::new(),
+            open: BinaryHeap::new(),
+            cost: Vec::new(),
+            parent: Vec::new(),
+            closed: Vec::new(),
+            poses: Vec::new(),
+            goal: Pose::default(),
+            expansions: 0,
+            found: None,
+            status: SearchStatus::Idle,
+        }
+    }
+
+    pub fn status(&self) -> SearchStatus {
+        self.status
+    }
+
+    /// Number of states expanded by the current search.
+    pub fn expansions(&self) -> u32 {
+        self.expansions
+    }
+
+    fn headings(&self) -> usize {
+        match self.params.kind {
+            PlannerKind::Grid => 1,
+            PlannerKind::Hybrid { headings, .. } => headings,
+        }
+    }
+
+    /// Starts a new search from start to goal on grid, the previous one is dropped.
+    pub fn start(&mut self, grid: &OccupancyGrid, start: &Pose, goal: &Pose) {
+        let states = grid.width as usize * grid.height as usize * self.headings();
+        self.open.clear();
+        self.cost.clear();
+        self.cost.resize(states, f64::INFINITY);
+        self.parent.clear();
+        self.parent.resize(states, NO_PARENT);
+        self.closed.clear();
+        self.closed.resize(states, false);
+        self.poses.resize(states, (0.0, 0.0, 0.0));
+        self.expansions = 0;
+        self.found = None;
+        self.goal = *goal;
+
+        let cells = (self.params.robot_radius / grid.resolution).ceil() as i64;
+        self.footprint = (-cells..=cells)
+            .flat_map(|dx| (-cells..=cells).map(move |dy| (dx, dy)))
+            .filter(|(dx, dy)| ((dx * dx + dy * dy) as f64).sqrt() <= cells as f64)
+            .collect();
+        self.grid = Some(grid.clone());
+
+        let goal_cell = self.cell_of(goal.x, goal.y);
+        match (self.state_of(start.x, start.y, start.yaw), goal_cell) {
+            (Some(index), Some((gx, gy))) if !self.blocked(gx, gy) => {
+                self.cost[index as usize] = 0.0;
+                self.poses[index as usize] = match self.params.kind {
+                    PlannerKind::Grid => self.cell_center(index),
+                    PlannerKind::Hybrid { .. } => (start.x, start.y, start.yaw),
+                };
+                self.open.push(OpenEntry {
+                    f: self.heuristic(index),
+                    index,
+                });
+                self.status = SearchStatus::Searching;
+            }
+            _ => self.status = SearchStatus::Failed,
+        }
+    }
+
+    /// Expands at most budget states of the current search.
+    pub fn step(&mut self, budget: u32) -> SearchStatus {
+        if self.status != SearchStatus::Searching {
+            return self.status;
+        }
+        for _ in 0..budget {
+            let Some(OpenEntry { index, .. }) = self.open.pop() else {
+                self.status = SearchStatus::Failed;
+                break;
+            };
+            if self.closed[index as usize] {
+                continue;
+            }
+            self.closed[index as usize] = true;
+            self.expansions += 1;
+            if self.is_goal(index) {
+                self.found = Some(index);
+                self.status = SearchStatus::Found;
+                break;
+            }
+            if self.expansions >= self.params.max_expansions {
+                self.status = SearchStatus::Failed;
+                break;
+            }
+            self.expand(index);
+        }
+        self.status
+    }
+
+    /// The path found, from the first waypoint after the start to the goal.
+    pub fn path(&self) -> Option<Vec<Pose>> {
+        let mut index = self.found?;
+        let mut path = Vec::new();
+        while self.parent[index as usize] != NO_PARENT {
+            let (x, y, yaw) = self.poses[index as usize];
+            path.push(Pose::new_2d(x, y, yaw));
+            index = self.parent[index as usize];
+        }
+        path.reverse();
+        // The grid search ends in the center of the goal cell, finish on the exact goal.
+        if let (PlannerKind::Grid, Some(last)) = (self.params.kind, path.last_mut()) {
+            *last = self.goal;
+        }
+        Some(path)
+    }
+
+    fn grid(&self) -> &OccupancyGrid {
+        self.grid.as_ref().expect("The search has been started")
+    }
+
+    fn cell_of(&self, x: f64, y: f64) -> Option<(i64, i64)> {
+        let grid = self.grid();
+        let cx = ((x - grid.origin_x) / grid.resolution).floor() as i64;
+        let cy = ((y - grid.origin_y) / grid.resolution).floor() as i64;
+        if cx >= 0 && cy >= 0 && cx < grid.width as i64 && cy < grid.height as i64 {
+            Some((cx, cy))
+        } else {
+            None
+        }
+    }
+
+    fn state_of(&self, x: f64, y: f64, yaw: f64) -> Option<u32> {
+        let (cx, cy) = self.cell_of(x, y)?;
+        let headings = self.headings();
+        let heading =
+            ((normalize_angle(yaw) / (2.0 * PI) * headings as f64).round() as usize) % headings;
+        Some(((cy as usize * self.grid().width as usize + cx as usize) * headings + heading) as u32)
+    }
+
+    fn cell_center(&self, index: u32) -> (f64, f64, f64) {
+        let grid = self.grid();
+        let cell = index as usize / self.headings();
+        let (cx, cy) = (cell % grid.width as usize, cell / grid.width as usize);
+        (
+            grid.origin_x + (cx as f64 + 0.5) * grid.resolution,
+            grid.origin_y + (cy as f64 + 0.5) * grid.resolution,
+            0.0,
+        )
+    }
+
+    fn blocked(&self, cx: i64, cy: i64) -> bool {
+        let grid = self.grid();
+        self.footprint.iter().any(|(dx, dy)| {
+            let (x, y) = (cx + dx, cy + dy);
+            if x < 0 || y < 0 || x >= grid.width as i64 || y >= grid.height as i64 {
+                return true;
+            }
+            let occupancy = grid.get(x as u32, y as u32);
+            if occupancy == UNKNOWN {
+                !self.params.allow_unknown
+            } else {
+                occupancy >= self.params.occupied_threshold
+            }
+        })
+    }
+
+    fn heuristic(&self, index: u32) -> f64 {
+        let (x, y, _) = match self.params.kind {
+            PlannerKind::Grid => self.cell_center(index),
+            PlannerKind::Hybrid { .. } => self.poses[index as usize],
+        };
+        ((x - self.goal.x).powi(2) + (y - self.goal.y).powi(2)).sqrt()
+    }
+
+    fn is_goal(&self, index: u32) -> bool {
+        match self.params.kind {
+            PlannerKind::Grid => {
+                self.cell_of(self.goal.x, self.goal.y)
+                    == self.cell_of(self.poses[index as usize].0, self.poses[index as usize].1)
+            }
+            PlannerKind::Hybrid { .. } => self.heuristic(index) <= self.params.goal_tolerance,
+        }
+    }
+
+    fn relax(&mut self, from: u32, to: u32, cost: f64, pose: (f64, f64, f64)) {
+        let to_cost = self.cost[from as usize] + cost;
+        if self.closed[to as usize] || to_cost >= self.cost[to as usize] {
+            return;
+        }
+        self.cost[to as usize] = to_cost;
+        self.parent[to as usize] = from;
+        self.poses[to as usize] = pose;
+        self.open.push(OpenEntry {
+            f: to_cost + self.heuristic(to),
+            index: to,
+        });
+    }
+
+    /// Checks the arc every half cell so the step cannot jump over a thin obstacle.
+    fn arc_free(&self, (x, y, yaw): (f64, f64, f64), curvature: f64, length: f64) -> bool {
+        let samples = (2.0 * length / self.grid().resolution).ceil() as usize;
+        (1..=samples).all(|i| {
+            let s = length * i as f64 / samples as f64;
+            let (px, py) = if curvature == 0.0 {
+                (x + s * yaw.cos(), y + s * yaw.sin())
+            } else {
+                let pyaw = yaw + s * curvature;
+                (
+                    x + (pyaw.sin() - yaw.sin()) / curvature,
+                    y - (pyaw.cos() - yaw.cos()) / curvature,
+                )
+            };
+            self.cell_of(px, py)
+                .is_some_and(|(cx, cy)| !self.blocked(cx, cy))
+        })
+    }
+
+    fn expand(&mut self, index: u32) {
+        let (x, y, yaw) = self.poses[index as usize];
+        let resolution = self.grid().resolution;
+        match self.params.kind {
+            PlannerKind::Grid => {
+                let Some((cx, cy)) = self.cell_of(x, y) else {
+                    return;
+                };
+                for (dx, dy) in [
+                    (1, 0),
+                    (-1, 0),
+                    (0, 1),
+                    (0, -1),
+                    (1, 1),
+                    (1, -1),
+                    (-1, 1),
+                    (-1, -1),
+                ] {
+                    let (nx, ny) = (cx + dx, cy + dy);
+                    // no corner cutting on the diagonals.
+                    if self.blocked(nx, ny)
+                        || self.blocked(cx + dx, cy)
+                        || self.blocked(cx, cy + dy)
+                    {
+                        continue;
+                    }
+                    let (px, py) = (x + dx as f64 * resolution, y + dy as f64 * resolution);
+                    let yaw = (dy as f64).atan2(dx as f64);
+                    if let Some(to) = self.state_of(px, py, 0.0) {
+                        let cost = ((dx * dx + dy * dy) as f64).sqrt() * resolution;
+                        self.relax(index, to, cost, (px, py, yaw));
+                    }
+                }
+            }
+            PlannerKind::Hybrid { turning_radius, .. } => {
+                // Long enough to always leave the current cell.
+                let step = 1.5 * resolution;
+                for curvature in [0.0, 1.0 / turning_radius, -1.0 / turning_radius] {
+                    let (px, py, pyaw) = if curvature == 0.0 {
+                        (x + step * yaw.cos(), y + step * yaw.sin(), yaw)
+                    } else {
+                        let pyaw = yaw + step * curvature;
+                        (
+                            x + (pyaw.sin() - yaw.sin()) / curvature,
+                            y - (pyaw.cos() - yaw.cos()) / curvature,
+                            pyaw,
+                        )
+                    };
+                    if !self.arc_free((x, y, yaw), curvature, step) {
+                        continue;
+                    }
+                    if let Some(to) = self.state_of(px, py, pyaw) {
+                        // Turning is slightly penalized to favor straight paths.
+                        let cost = if curvature == 0.0 { step } else { step * 1.1 };
+                        self.relax(index, to, cost, (px, py, normalize_angle(pyaw)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::pool::CuHandle;
+
+    /// A 20x20 grid of 0.5m cells with a wall at x = 5m with a gap at the top.
+    fn grid() -> OccupancyGrid {
+        let mut cells = vec![0i8; 400];
+        for cy in 0..16 {
+            cells[cy * 20 + 10] = 100;
+        }
+        OccupancyGrid {
+            width: 20,
+            height: 20,
+            resolution: 0.5,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            cells: CuHandle::new_detached(cells),
+        }
+    }
+
+    fn params(kind: PlannerKind) -> PlannerParams {
+        PlannerParams {
+            kind,
+            occupied_threshold: 50,
+            allow_unknown: false,
+            robot_radius: 0.0,
+            goal_tolerance: 0.5,
+            max_expansions: 100_000,
+        }
+    }
+
+    fn goes_through_gap(path: &[Pose]) -> bool {
+        let in_wall = |p: &Pose| (5.0..5.5).contains(&p.x) && p.y < 8.0;
+        !path.iter().any(in_wall) && path.iter().any(|p| p.y >= 7.5)
+    }
+
+    #[test]
+    fn test_astar_with_budget() {
+        let mut planner = Planner::new(params(PlannerKind::Grid));
+        let goal = Pose::new_2d(9.0, 1.0, 0.0);
+        planner.start(&grid(), &Pose::new_2d(1.0, 1.0, 0.0), &goal);
+        let mut iterations = 0;
+        while planner.step(10) == SearchStatus::Searching {
+            iterations += 1;
+        }
+        assert!(iterations > 1);
+        assert_eq!(planner.status(), SearchStatus::Found);
+        let path = planner.path().unwrap();
+        assert_eq!(path.last(), Some(&goal));
+        assert!(goes_through_gap(&path));
+    }
+
+    #[test]
+    fn test_hybrid_astar() {
+        let mut planner = Planner::new(params(PlannerKind::Hybrid {
+            headings: 16,
+            turning_radius: 1.0,
+        }));
+        planner.start(
+            &grid(),
+            &Pose::new_2d(1.0, 1.0, 0.0),
+            &Pose::new_2d(9.0, 1.0, 0.0),
+        );
+        assert_eq!(planner.step(u32::MAX), SearchStatus::Found);
+        let path = planner.path().unwrap();
+        assert!(goes_through_gap(&path));
+        // consecutive poses never turn faster than the turning radius allows.
+        for pair in path.windows(2) {
+            let turn = (pair[1].yaw - pair[0].yaw).rem_euclid(2.0 * PI);
+            assert!(turn.min(2.0 * PI - turn) <= 0.75 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_unreachable_goal() {
+        let mut planner = Planner::new(params(PlannerKind::Grid));
+        // in the wall
+        planner.start(
+            &grid(),
+            &Pose::new_2d(1.0, 1.0, 0.0),
+            &Pose::new_2d(5.25, 1.0, 0.0),
+        );
+        assert_eq!(planner.status(), SearchStatus::Failed);
+
+        // the inflated wall closes the gap.
+        let mut p = params(PlannerKind::Grid);
+        p.robot_radius = 1.5;
+        let mut planner = Planner::new(p);
+        planner.start(
+            &grid(),
+            &Pose::new_2d(2.5, 2.5, 0.0),
+            &Pose::new_2d(7.5, 2.5, 0.0),
+        );
+        assert_eq!(planner.status(), SearchStatus::Searching);
+        assert_eq!(planner.step(u32::MAX), SearchStatus::Failed);
+        assert!(planner.path().is_none());
+    }
+}
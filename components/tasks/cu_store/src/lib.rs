@@ -0,0 +1,221 @@
+mod store;
+
+pub use store::{FileStore, KvBackend};
+
+#[cfg(feature = "sled")]
+pub use store::SledStore;
+
+use bincode::config::standard;
+use bincode::{decode_from_slice, encode_to_vec, Decode, Encode};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::CuMsg;
+use cu29::cutask::{CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use std::path::PathBuf;
+
+const DEFAULT_COMPACT_AFTER: usize = 1024;
+
+/// One operation on the store.
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub enum StoreOp {
+    Put { key: String, value: Vec<u8> },
+    Delete { key: String },
+    Get { key: String },
+}
+
+/// Message sent to the store task: a batch of operations.
+/// All the Put and Delete of a request are applied atomically, then the Gets are answered
+/// so they see the state after the batch.
+#[derive(Debug, Default, Clone, Encode, Decode)]
+pub struct StoreRequest {
+    pub ops: Vec<StoreOp>,
+}
+
+impl StoreRequest {
+    /// Stores any bincode encodable value under key.
+    pub fn put<T: Encode>(&mut self, key: &str, value: &T) -> CuResult<()> {
+        let value = encode_to_vec(value, standard())
+            .map_err(|e| CuError::new_with_cause("Could not encode the value to store", e))?;
+        self.put_raw(key, value);
+        Ok(())
+    }
+
+    pub fn put_raw(&mut self, key: &str, value: Vec<u8>) {
+        self.ops.push(StoreOp::Put {
+            key: key.to_string(),
+            value,
+        });
+    }
+
+    pub fn delete(&mut self, key: &str) {
+        self.ops.push(StoreOp::Delete {
+            key: key.to_string(),
+        });
+    }
+
+    /// Asks for the value of key in the reply.
+    pub fn get(&mut self, key: &str) {
+        self.ops.push(StoreOp::Get {
+            key: key.to_string(),
+        });
+    }
+}
+
+/// Message sent back by the store task with the values asked for by the Gets of the request.
+#[derive(Debug, Default, Clone, Encode, Decode)]
+pub struct StoreReply {
+    pub values: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl StoreReply {
+    /// Decodes the value of key, None if the key was not asked for or is not in the store.
+    pub fn get<T: Decode>(&self, key: &str) -> CuResult<Option<T>> {
+        match self.get_raw(key) {
+            Some(value) => {
+                let (value, _) = decode_from_slice(value, standard())
+                    .map_err(|e| CuError::new_with_cause("Could not decode the stored value", e))?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_raw(&self, key: &str) -> Option<&[u8]> {
+        self.values
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, value)| value.as_deref())
+    }
+}
+
+/// The task exposing the store to the rest of the graph.
+pub struct CuStoreTask {
+    backend: Box<dyn KvBackend>,
+}
+
+impl CuStoreTask {
+    pub fn with_backend(backend: Box<dyn KvBackend>) -> Self {
+        CuStoreTask { backend }
+    }
+}
+
+impl Freezable for CuStoreTask {
+    // The state lives on disk.
+}
+
+impl CuTaskLifecycle for CuStoreTask {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config = config.ok_or("CuStoreTask needs a config with at least a 'path'.")?;
+        let path: String = config
+            .get::<String>("path")
+            .ok_or("'path' not found in the CuStoreTask config")?;
+        let path = PathBuf::from(path);
+        let backend_name = config
+            .get::<String>("backend")
+            .unwrap_or("file".to_string());
+        debug!("Opening the {} store at {}", &backend_name, path.to_str());
+
+        let backend: Box<dyn KvBackend> = match backend_name.as_str() {
+            "file" => {
+                let compact_after = config
+                    .get::<u32>("compact_after")
+                    .map(|c| c as usize)
+                    .unwrap_or(DEFAULT_COMPACT_AFTER);
+                Box::new(FileStore::open(&path, compact_after)?)
+            }
+            #[cfg(feature = "sled")]
+            "sled" => Box::new(SledStore::open(&path)?),
+            other => {
+                return Err(format!(
+                    "Unknown store backend '{}', this crate supports: {}.",
+                    other,
+                    if cfg!(feature = "sled") {
+                        "file, sled"
+                    } else {
+                        "file"
+                    }
+                )
+                .into())
+            }
+        };
+        Ok(Self::with_backend(backend))
+    }
+}
+
+impl<'cl> CuTask<'cl> for CuStoreTask {
+    type Input = input_msg!('cl, StoreRequest);
+    type Output = output_msg!('cl, StoreReply);
+
+    fn process(
+        &mut self,
+        _ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let Some(request) = input.payload() else {
+            output.clear_payload();
+            return Ok(());
+        };
+        self.backend.apply(&request.ops)?;
+
+        let mut reply = StoreReply::default();
+        for op in &request.ops {
+            if let StoreOp::Get { key } = op {
+                reply.values.push((key.clone(), self.backend.get(key)?));
+            }
+        }
+        output.set_payload(reply);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_request_reply() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("mission.store");
+        let mut config = ComponentConfig::default();
+        config.set("path", path.to_str().unwrap().to_string());
+
+        let clock = RobotClock::default();
+        let mut input = CuMsg::new(None);
+        let mut output = CuMsg::new(None);
+        {
+            let mut task = CuStoreTask::new(Some(&config)).unwrap();
+            let mut request = StoreRequest::default();
+            request.put("odometry_total", &1234.5f64).unwrap();
+            request.put("errors", &3u32).unwrap();
+            request.get("errors");
+            input.set_payload(request);
+            task.process(&CuContext::from(&clock), &input, &mut output)
+                .unwrap();
+            assert_eq!(
+                output.payload().unwrap().get::<u32>("errors").unwrap(),
+                Some(3)
+            );
+        }
+
+        // After a restart.
+        let mut task = CuStoreTask::new(Some(&config)).unwrap();
+        let mut request = StoreRequest::default();
+        request.get("odometry_total");
+        request.get("last_dock");
+        input.set_payload(request);
+        task.process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        let reply = output.payload().unwrap();
+        assert_eq!(reply.get::<f64>("odometry_total").unwrap(), Some(1234.5));
+        assert_eq!(reply.get::<f64>("last_dock").unwrap(), None);
+    }
+}
@@ -0,0 +1,350 @@
+//! Storage backends of the key-value store.
+
+use crate::StoreOp;
+use bincode::config::standard;
+use bincode::{decode_from_slice, encode_to_vec};
+use cu29::CuResult;
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use std::collections::HashMap;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// What a storage backend needs to provide to the store task.
+pub trait KvBackend: Send {
+    fn get(&self, key: &str) -> CuResult<Option<Vec<u8>>>;
+
+    /// Applies all the Put and Delete operations of the batch atomically: after a crash either all
+    /// of them are visible or none of them. Get operations are ignored.
+    fn apply(&mut self, ops: &[StoreOp]) -> CuResult<()>;
+}
+
+/// Size of the header of a record: payload length + crc32 of the payload.
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// Append-only file backend.
+/// Every batch is one record `[len: u32 LE][crc32: u32 LE][bincode Vec<StoreOp>]` synced to disk.
+/// The whole content is kept in memory, this is meant for small amounts of state.
+pub struct FileStore {
+    path: PathBuf,
+    file: File,
+    /// The length of the complete records in the file, anything after it is a failed write.
+    len: u64,
+    index: HashMap<String, Vec<u8>>,
+    /// Number of records or entries in the file that have been superseded since the last compaction.
+    obsolete: usize,
+    compact_after: usize,
+}
+
+impl FileStore {
+    /// Opens or creates the store at path, replaying its content.
+    /// A truncated or corrupted record at the end of the file is discarded.
+    pub fn open(path: &Path, compact_after: usize) -> CuResult<Self> {
+        let mut content = Vec::new();
+        if path.exists() {
+            File::open(path)
+                .and_then(|mut f| f.read_to_end(&mut content))
+                .map_err(|e| CuError::new_with_cause("Could not read the store", e))?;
+        }
+
+        let mut index = HashMap::new();
+        let mut obsolete = 0;
+        let mut offset = 0;
+        while let Some((ops, next_offset)) = read_record(&content, offset) {
+            for op in ops {
+                if apply_to_index(&mut index, op) {
+                    obsolete += 1;
+                }
+            }
+            offset = next_offset;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| CuError::new_with_cause("Could not open the store", e))?;
+
+        if offset < content.len() {
+            debug!(
+                "Store: discarding {} bytes of an interrupted write.",
+                content.len() - offset
+            );
+            file.set_len(offset as u64)
+                .and_then(|_| file.sync_all())
+                .map_err(|e| CuError::new_with_cause("Could not truncate the store", e))?;
+        }
+
+        Ok(FileStore {
+            path: path.to_path_buf(),
+            file,
+            len: offset as u64,
+            index,
+            obsolete,
+            compact_after,
+        })
+    }
+
+    /// Rewrites the file with only the live entries.
+    /// The new content is written next to the old file and renamed over it so it is atomic.
+    pub fn compact(&mut self) -> CuResult<()> {
+        let snapshot: Vec<StoreOp> = self
+            .index
+            .iter()
+            .map(|(key, value)| StoreOp::Put {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect();
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let record = encode_record(&snapshot)?;
+        {
+            let mut tmp = File::create(&tmp_path)
+                .map_err(|e| CuError::new_with_cause("Could not create the compacted store", e))?;
+            tmp.write_all(&record)
+                .and_then(|_| tmp.sync_all())
+                .map_err(|e| CuError::new_with_cause("Could not write the compacted store", e))?;
+        }
+        // The rename is only durable once the directory holding the store is synced.
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        rename(&tmp_path, &self.path)
+            .and_then(|_| File::open(dir))
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| CuError::new_with_cause("Could not replace the store", e))?;
+
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| CuError::new_with_cause("Could not reopen the store", e))?;
+        self.len = record.len() as u64;
+        self.obsolete = 0;
+        Ok(())
+    }
+}
+
+impl KvBackend for FileStore {
+    fn get(&self, key: &str) -> CuResult<Option<Vec<u8>>> {
+        Ok(self.index.get(key).cloned())
+    }
+
+    fn apply(&mut self, ops: &[StoreOp]) -> CuResult<()> {
+        let mutations: Vec<StoreOp> = ops
+            .iter()
+            .filter(|op| !matches!(op, StoreOp::Get { .. }))
+            .cloned()
+            .collect();
+        if mutations.is_empty() {
+            return Ok(());
+        }
+
+        // One write for the whole batch: a crash in the middle leaves a record failing its crc. A write that
+        // failed is cut off so the next batches are not appended after it, where the reopening would drop them.
+        let record = encode_record(&mutations)?;
+        let written = self
+            .file
+            .metadata()
+            .and_then(|metadata| {
+                if metadata.len() != self.len {
+                    self.file.set_len(self.len)?;
+                }
+                self.file.write_all(&record)
+            })
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = written {
+            let _ = self.file.set_len(self.len);
+            return Err(CuError::new_with_cause("Could not write to the store", e));
+        }
+        self.len += record.len() as u64;
+
+        for op in mutations {
+            if apply_to_index(&mut self.index, op) {
+                self.obsolete += 1;
+            }
+        }
+
+        if self.obsolete >= self.compact_after {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies one operation to the in memory index, returns true if it superseded something on disk.
+fn apply_to_index(index: &mut HashMap<String, Vec<u8>>, op: StoreOp) -> bool {
+    match op {
+        StoreOp::Put { key, value } => index.insert(key, value).is_some(),
+        StoreOp::Delete { key } => {
+            index.remove(&key);
+            // the delete record itself is obsolete after a compaction.
+            true
+        }
+        StoreOp::Get { .. } => false,
+    }
+}
+
+fn encode_record(ops: &[StoreOp]) -> CuResult<Vec<u8>> {
+    let payload = encode_to_vec(ops, standard())
+        .map_err(|e| CuError::new_with_cause("Could not encode the store record", e))?;
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    record.extend_from_slice(&payload);
+    Ok(record)
+}
+
+/// Reads the record at offset, returns None if it is incomplete or corrupted.
+fn read_record(content: &[u8], offset: usize) -> Option<(Vec<StoreOp>, usize)> {
+    let header = content.get(offset..offset + RECORD_HEADER_SIZE)?;
+    let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let start = offset + RECORD_HEADER_SIZE;
+    let payload = content.get(start..start + len)?;
+    if crc32fast::hash(payload) != crc {
+        return None;
+    }
+    let (ops, _) = decode_from_slice(payload, standard()).ok()?;
+    Some((ops, start + len))
+}
+
+/// sled backend, sled takes care of the atomicity and of the compaction.
+#[cfg(feature = "sled")]
+pub struct SledStore {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled")]
+impl SledStore {
+    pub fn open(path: &Path) -> CuResult<Self> {
+        let db = sled::open(path).map_err(|e| CuError::new_with_cause("Could not open sled", e))?;
+        Ok(SledStore { db })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl KvBackend for SledStore {
+    fn get(&self, key: &str) -> CuResult<Option<Vec<u8>>> {
+        self.db
+            .get(key)
+            .map(|value| value.map(|v| v.to_vec()))
+            .map_err(|e| CuError::new_with_cause("Could not read from sled", e))
+    }
+
+    fn apply(&mut self, ops: &[StoreOp]) -> CuResult<()> {
+        let mut batch = sled::Batch::default();
+        for op in ops {
+            match op {
+                StoreOp::Put { key, value } => batch.insert(key.as_str(), value.as_slice()),
+                StoreOp::Delete { key } => batch.remove(key.as_str()),
+                StoreOp::Get { .. } => {}
+            }
+        }
+        self.db
+            .apply_batch(batch)
+            .and_then(|_| self.db.flush().map(|_| ()))
+            .map_err(|e| CuError::new_with_cause("Could not write to sled", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn put(key: &str, value: &[u8]) -> StoreOp {
+        StoreOp::Put {
+            key: key.to_string(),
+            value: value.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.store");
+        {
+            let mut store = FileStore::open(&path, 1024).unwrap();
+            store.apply(&[put("a", &[1]), put("b", &[2])]).unwrap();
+            store
+                .apply(&[StoreOp::Delete {
+                    key: "a".to_string(),
+                }])
+                .unwrap();
+        }
+        let store = FileStore::open(&path, 1024).unwrap();
+        assert_eq!(store.get("a").unwrap(), None);
+        assert_eq!(store.get("b").unwrap(), Some(vec![2]));
+    }
+
+    #[test]
+    fn test_interrupted_write() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.store");
+        {
+            let mut store = FileStore::open(&path, 1024).unwrap();
+            store.apply(&[put("a", &[1])]).unwrap();
+            store.apply(&[put("a", &[2]), put("b", &[3])]).unwrap();
+        }
+        // Simulate a power loss in the middle of the last record.
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 2)
+            .unwrap();
+
+        let mut store = FileStore::open(&path, 1024).unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(vec![1]));
+        assert_eq!(store.get("b").unwrap(), None);
+
+        // the store is still usable after the recovery.
+        store.apply(&[put("c", &[4])]).unwrap();
+        let store = FileStore::open(&path, 1024).unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(vec![1]));
+        assert_eq!(store.get("c").unwrap(), Some(vec![4]));
+    }
+
+    #[test]
+    fn test_failed_write() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.store");
+        let mut store = FileStore::open(&path, 1024).unwrap();
+        store.apply(&[put("a", &[1])]).unwrap();
+        // A write that failed halfway, with the store still open.
+        let record = encode_record(&[put("b", &[2])]).unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&record[..record.len() - 2])
+            .unwrap();
+        store.apply(&[put("c", &[3])]).unwrap();
+
+        let store = FileStore::open(&path, 1024).unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(vec![1]));
+        assert_eq!(store.get("b").unwrap(), None);
+        assert_eq!(store.get("c").unwrap(), Some(vec![3]));
+    }
+
+    #[test]
+    fn test_compaction() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.store");
+        {
+            let mut store = FileStore::open(&path, 4).unwrap();
+            for i in 0..10u8 {
+                store.apply(&[put("counter", &[i])]).unwrap();
+            }
+        }
+        let store = FileStore::open(&path, 4).unwrap();
+        assert_eq!(store.get("counter").unwrap(), Some(vec![9]));
+        assert!(store.obsolete < 4);
+    }
+}
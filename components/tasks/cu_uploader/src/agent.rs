@@ -0,0 +1,191 @@
+use crate::control::UploadShared;
+use crate::target::UploadTarget;
+use cu29_log_derive::debug;
+use cu29_traits::{CuError, CuResult};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// The longest wait before retrying after consecutive failures.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The files with the extension in dir that are done being written, oldest first.
+/// A slab of the unified logger (name_3.copper) is done once the next one (name_4.copper) exists,
+/// any file is done once it has not been modified for settle.
+pub(crate) fn ready_files(dir: &Path, extension: &str, settle: Duration) -> CuResult<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| CuError::new_with_cause("Could not list the watched directory", e))?;
+    let now = SystemTime::now();
+    let mut ready = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(extension) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata.modified().unwrap_or(now);
+        let settled = now
+            .duration_since(modified)
+            .is_ok_and(|idle| idle >= settle);
+        if settled || next_slab(&path).is_some_and(|next| next.exists()) {
+            ready.push((modified, path));
+        }
+    }
+    ready.sort();
+    Ok(ready.into_iter().map(|(_, path)| path).collect())
+}
+
+/// name_4.copper for name_3.copper.
+fn next_slab(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let (base, index) = stem.rsplit_once('_')?;
+    let index: usize = index.parse().ok()?;
+    let extension = path.extension()?.to_str()?;
+    Some(path.with_file_name(format!("{}_{}.{}", base, index + 1, extension)))
+}
+
+pub(crate) struct UploadAgent {
+    pub(crate) dir: PathBuf,
+    pub(crate) extension: String,
+    pub(crate) settle: Duration,
+    pub(crate) scan_period: Duration,
+    pub(crate) chunk_size: usize,
+    pub(crate) delete_after_upload: bool,
+    pub(crate) target: Box<dyn UploadTarget>,
+    pub(crate) shared: Arc<UploadShared>,
+}
+
+impl UploadAgent {
+    /// Uploads the files as they are ready until stopped, unparking the thread wakes it up.
+    pub(crate) fn run(mut self) {
+        let mut consecutive_failures = 0u32;
+        while !self.shared.stop.load(Ordering::Relaxed) {
+            let wait = match self.upload_ready_files() {
+                Ok(()) => {
+                    consecutive_failures = 0;
+                    self.scan_period
+                }
+                Err(e) if self.shared.interrupted() => {
+                    debug!("Uploader: interrupted: {}", e.to_string());
+                    self.scan_period
+                }
+                Err(e) => {
+                    self.shared.failures.fetch_add(1, Ordering::Relaxed);
+                    consecutive_failures += 1;
+                    let backoff = Duration::from_secs(1 << consecutive_failures.min(6));
+                    let backoff = backoff.min(MAX_BACKOFF);
+                    debug!(
+                        "Uploader: failed, retrying in {}s: {}",
+                        backoff.as_secs(),
+                        e.to_string()
+                    );
+                    backoff
+                }
+            };
+            self.shared.uploading.store(false, Ordering::Relaxed);
+            thread::park_timeout(wait);
+        }
+    }
+
+    fn upload_ready_files(&mut self) -> CuResult<()> {
+        if self.shared.interrupted() {
+            return Ok(());
+        }
+        let files = ready_files(&self.dir, &self.extension, self.settle)?;
+        self.shared
+            .pending
+            .store(files.len() as u32, Ordering::Relaxed);
+        for path in files {
+            if self.shared.interrupted() {
+                return Ok(());
+            }
+            self.shared.uploading.store(true, Ordering::Relaxed);
+            self.upload(&path)?;
+            self.shared.pending.fetch_sub(1, Ordering::Relaxed);
+            self.shared.uploaded_files.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Uploads a file from where the endpoint is, then deletes it or marks it as uploaded.
+    fn upload(&mut self, path: &Path) -> CuResult<()> {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or("Invalid file name")?
+            .to_string();
+        let mut file =
+            File::open(path).map_err(|e| CuError::new_with_cause("Could not open a log", e))?;
+        let size = file
+            .metadata()
+            .map_err(|e| CuError::new_with_cause("Could not read the size of a log", e))?
+            .len();
+
+        let mut offset = self.target.uploaded(&name, size)?;
+        if offset > 0 {
+            debug!("Uploader: resuming {} at {}/{}", &name, offset, size);
+        }
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        while offset < size {
+            if self.shared.interrupted() {
+                return Err("The uploads are paused".into());
+            }
+            let len = (size - offset).min(self.chunk_size as u64);
+            chunk.resize(len as usize, 0);
+            file.seek(SeekFrom::Start(offset))
+                .and_then(|_| file.read_exact(&mut chunk))
+                .map_err(|e| CuError::new_with_cause("Could not read a log", e))?;
+            let confirmed = self
+                .target
+                .send(&name, offset, size, &chunk, &self.shared)?;
+            if confirmed > offset {
+                self.shared
+                    .uploaded_bytes
+                    .fetch_add(confirmed - offset, Ordering::Relaxed);
+            }
+            offset = confirmed;
+        }
+        self.target.finish(&name, size)?;
+
+        if self.delete_after_upload {
+            std::fs::remove_file(path)
+        } else {
+            let mut uploaded = path.as_os_str().to_owned();
+            uploaded.push(".uploaded");
+            std::fs::rename(path, uploaded)
+        }
+        .map_err(|e| CuError::new_with_cause("Could not remove an uploaded log", e))?;
+        debug!("Uploader: {} uploaded ({} bytes)", &name, size);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_ready_files() {
+        let dir = tempdir().unwrap();
+        for name in ["run_0.copper", "run_1.copper", "other.copper", "notes.txt"] {
+            std::fs::write(dir.path().join(name), b"log").unwrap();
+        }
+        let ready = ready_files(dir.path(), "copper", Duration::from_secs(3600)).unwrap();
+        // run_1 and other may still be written to.
+        assert_eq!(ready, vec![dir.path().join("run_0.copper")]);
+
+        let mut ready = ready_files(dir.path(), "copper", Duration::ZERO).unwrap();
+        ready.sort();
+        assert_eq!(ready.len(), 3);
+    }
+}
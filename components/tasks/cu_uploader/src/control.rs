@@ -0,0 +1,166 @@
+use crate::UploadStatus;
+use cu29::permissions::{CuGuard, CuRoleToken};
+use cu29_traits::CuResult;
+use std::io;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::thread::Thread;
+use std::time::{Duration, Instant};
+
+/// The largest block sent at once, so a change of the bandwidth limit or a pause is seen quickly.
+const BLOCK_SIZE: usize = 16 * 1024;
+
+/// The longest sleep of the bandwidth limiting before checking for a pause again.
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+/// The state shared by the task, its upload thread and the controls given to the application.
+#[derive(Default)]
+pub(crate) struct UploadShared {
+    pub(crate) paused: AtomicBool,
+    pub(crate) max_bytes_per_s: AtomicU64,
+    pub(crate) stop: AtomicBool,
+    pub(crate) pending: AtomicU32,
+    pub(crate) uploading: AtomicBool,
+    pub(crate) uploaded_files: AtomicU64,
+    pub(crate) uploaded_bytes: AtomicU64,
+    pub(crate) failures: AtomicU64,
+    /// The upload thread, to wake it up.
+    pub(crate) worker: OnceLock<Thread>,
+}
+
+impl UploadShared {
+    /// True if the transfers have to stop, for a pause or for good.
+    pub(crate) fn interrupted(&self) -> bool {
+        self.paused.load(Ordering::Relaxed) || self.stop.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn wake_worker(&self) {
+        if let Some(worker) = self.worker.get() {
+            worker.unpark();
+        }
+    }
+}
+
+/// Controls the uploads while the application runs, for example to pause them over a metered link.
+/// Get it from the task before running the application: `application.task::<CuUploader>(NodeIds::uploader)`.
+/// The changes need the roles set in the permissions of the node: "pause", "resume" and "bandwidth_limit".
+#[derive(Clone)]
+pub struct UploadControl {
+    pub(crate) shared: Arc<UploadShared>,
+    pub(crate) guard: Arc<CuGuard>,
+}
+
+impl UploadControl {
+    /// Stops the transfers as soon as possible, they resume from where they stopped.
+    pub fn pause(&self, token: &CuRoleToken) -> CuResult<()> {
+        self.guard.authorize(Some(token), "pause")?;
+        self.shared.paused.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn resume(&self, token: &CuRoleToken) -> CuResult<()> {
+        self.guard.authorize(Some(token), "resume")?;
+        self.shared.paused.store(false, Ordering::Relaxed);
+        self.shared.wake_worker();
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::Relaxed)
+    }
+
+    /// Limits the upload rate, 0 for no limit. It applies to the transfer in progress too.
+    pub fn set_bandwidth_limit(&self, token: &CuRoleToken, bytes_per_s: u64) -> CuResult<()> {
+        self.guard.authorize(Some(token), "bandwidth_limit")?;
+        self.shared
+            .max_bytes_per_s
+            .store(bytes_per_s, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn bandwidth_limit(&self) -> u64 {
+        self.shared.max_bytes_per_s.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> UploadStatus {
+        let shared = &self.shared;
+        UploadStatus {
+            paused: shared.paused.load(Ordering::Relaxed),
+            uploading: shared.uploading.load(Ordering::Relaxed),
+            pending_files: shared.pending.load(Ordering::Relaxed),
+            uploaded_files: shared.uploaded_files.load(Ordering::Relaxed),
+            uploaded_bytes: shared.uploaded_bytes.load(Ordering::Relaxed),
+            failures: shared.failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The body of a request, sent at the bandwidth limit. It fails if the uploads are paused or stopped
+/// so the request is dropped and resumed later from the last offset confirmed by the endpoint.
+pub(crate) struct Throttled<'a> {
+    data: &'a [u8],
+    shared: &'a UploadShared,
+    next: Instant,
+}
+
+impl<'a> Throttled<'a> {
+    pub(crate) fn new(data: &'a [u8], shared: &'a UploadShared) -> Self {
+        Throttled {
+            data,
+            shared,
+            next: Instant::now(),
+        }
+    }
+}
+
+impl Read for Throttled<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.shared.interrupted() {
+                return Err(io::Error::other("The uploads are paused"));
+            }
+            let now = Instant::now();
+            if now >= self.next {
+                break;
+            }
+            thread::sleep((self.next - now).min(MAX_WAIT));
+        }
+        let len = buf.len().min(BLOCK_SIZE).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        let limit = self.shared.max_bytes_per_s.load(Ordering::Relaxed);
+        if limit > 0 {
+            let send_time = Duration::from_secs_f64(len as f64 / limit as f64);
+            self.next = self.next.max(Instant::now()) + send_time;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttled() {
+        let shared = UploadShared::default();
+        shared.max_bytes_per_s.store(100_000, Ordering::Relaxed);
+        let data = vec![7u8; 50_000];
+        let start = Instant::now();
+        let mut sent = Vec::new();
+        Throttled::new(&data, &shared)
+            .read_to_end(&mut sent)
+            .unwrap();
+        assert_eq!(sent, data);
+        // The last block goes without waiting.
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        shared.paused.store(true, Ordering::Relaxed);
+        let mut sent = Vec::new();
+        assert!(Throttled::new(&data, &shared)
+            .read_to_end(&mut sent)
+            .is_err());
+    }
+}
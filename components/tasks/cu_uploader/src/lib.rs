@@ -0,0 +1,183 @@
+mod agent;
+mod control;
+mod s3;
+mod target;
+
+pub use control::UploadControl;
+
+use agent::UploadAgent;
+use bincode::{Decode, Encode};
+use control::UploadShared;
+use cu29::clock::RobotClock;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuSrcTask, CuTaskLifecycle, Freezable};
+use cu29::permissions::CuGuard;
+use cu29::threads::task_thread_name;
+use cu29::{output_msg, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::CuError;
+use s3::{Credentials, S3Target, MIN_PART_SIZE};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use target::{HttpTarget, UploadTarget};
+
+const DEFAULT_EXTENSION: &str = "copper";
+const DEFAULT_SETTLE_S: u32 = 30;
+const DEFAULT_SCAN_PERIOD_S: u32 = 10;
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+const DEFAULT_S3_REGION: &str = "us-east-1";
+
+/// The state of the uploads, sent at every iteration.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+pub struct UploadStatus {
+    pub paused: bool,
+    /// A file is being uploaded.
+    pub uploading: bool,
+    /// The files ready to be uploaded at the last scan of the directory, not uploaded yet.
+    pub pending_files: u32,
+    pub uploaded_files: u64,
+    pub uploaded_bytes: u64,
+    /// The number of failed attempts, each of them is retried.
+    pub failures: u64,
+}
+
+/// Uploads the completed log files of a directory from a background thread so the copper loop is never
+/// blocked by the network. The task itself only reports the status of the uploads.
+pub struct CuUploader {
+    agent: Option<UploadAgent>,
+    worker: Option<JoinHandle<()>>,
+    control: UploadControl,
+}
+
+impl CuUploader {
+    /// The handle to pause, resume and limit the uploads while the application runs.
+    pub fn control(&self) -> UploadControl {
+        self.control.clone()
+    }
+}
+
+impl Freezable for CuUploader {
+    // The progress of the transfers is kept by the endpoints.
+}
+
+impl CuTaskLifecycle for CuUploader {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let config =
+            config.ok_or("CuUploader needs a config with a 'watch_dir' and an 'endpoint'.")?;
+        let dir = PathBuf::from(
+            config
+                .get::<String>("watch_dir")
+                .ok_or("'watch_dir' not found in the CuUploader config")?,
+        );
+        let endpoint: String = config
+            .get::<String>("endpoint")
+            .ok_or("'endpoint' not found in the CuUploader config")?;
+        let mut chunk_size = config
+            .get::<u32>("chunk_size")
+            .map(|c| c as usize)
+            .unwrap_or(DEFAULT_CHUNK_SIZE);
+
+        let target: Box<dyn UploadTarget> = if endpoint.starts_with("s3://") {
+            chunk_size = chunk_size.max(MIN_PART_SIZE);
+            let region = config
+                .get::<String>("s3_region")
+                .unwrap_or(DEFAULT_S3_REGION.to_string());
+            let s3_endpoint = config.get::<String>("s3_endpoint");
+            Box::new(S3Target::new(
+                &endpoint,
+                &region,
+                s3_endpoint.as_deref(),
+                Credentials::from_env()?,
+                dir.clone(),
+            )?)
+        } else if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+            Box::new(HttpTarget::new(&endpoint))
+        } else {
+            return Err(format!(
+                "Unsupported upload endpoint '{}', it must start with http://, https:// or s3://.",
+                endpoint
+            )
+            .into());
+        };
+
+        let shared = Arc::new(UploadShared::default());
+        shared.paused.store(
+            config.get::<bool>("paused").unwrap_or(false),
+            Ordering::Relaxed,
+        );
+        shared.max_bytes_per_s.store(
+            config.get::<u32>("max_bytes_per_s").unwrap_or(0) as u64,
+            Ordering::Relaxed,
+        );
+        let control = UploadControl {
+            shared: shared.clone(),
+            guard: Arc::new(CuGuard::from_config("uploader", Some(config))?),
+        };
+        debug!("Uploader: uploading {} to {}", dir.to_str(), &endpoint);
+
+        let agent = UploadAgent {
+            dir,
+            extension: config
+                .get::<String>("extension")
+                .unwrap_or(DEFAULT_EXTENSION.to_string()),
+            settle: Duration::from_secs(
+                config.get::<u32>("settle_s").unwrap_or(DEFAULT_SETTLE_S) as u64
+            ),
+            scan_period: Duration::from_secs(
+                config
+                    .get::<u32>("scan_period_s")
+                    .unwrap_or(DEFAULT_SCAN_PERIOD_S) as u64,
+            ),
+            chunk_size,
+            delete_after_upload: config.get::<bool>("delete_after_upload").unwrap_or(true),
+            target,
+            shared,
+        };
+        Ok(CuUploader {
+            agent: Some(agent),
+            worker: None,
+            control,
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        let agent = self
+            .agent
+            .take()
+            .ok_or("The uploader cannot be started twice.")?;
+        let worker = std::thread::Builder::new()
+            .name(task_thread_name("uploader"))
+            .spawn(move || agent.run())
+            .map_err(|e| CuError::new_with_cause("Could not start the upload thread", e))?;
+        let _ = self.control.shared.worker.set(worker.thread().clone());
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        if let Some(worker) = self.worker.take() {
+            self.control.shared.stop.store(true, Ordering::Relaxed);
+            self.control.shared.wake_worker();
+            worker
+                .join()
+                .map_err(|_| CuError::from("The upload thread panicked."))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'cl> CuSrcTask<'cl> for CuUploader {
+    type Output = output_msg!('cl, UploadStatus);
+
+    fn process(&mut self, _ctx: &CuContext, output: Self::Output) -> CuResult<()> {
+        output.set_payload(self.control.status());
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use crate::control::{Throttled, UploadShared};
+use cu29_traits::{CuError, CuResult};
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, Error, Response};
+
+/// Where the files are uploaded. A transfer is made of chunks sent in order, any of them can fail:
+/// it is resumed from the offset the endpoint confirms.
+pub(crate) trait UploadTarget: Send {
+    /// The number of bytes of the file the endpoint already has.
+    fn uploaded(&mut self, name: &str, size: u64) -> CuResult<u64>;
+
+    /// Sends the chunk of the file starting at offset and returns the offset confirmed by the endpoint.
+    fn send(
+        &mut self,
+        name: &str,
+        offset: u64,
+        size: u64,
+        chunk: &[u8],
+        shared: &UploadShared,
+    ) -> CuResult<u64>;
+
+    /// Checks the whole file is stored, it can be deleted once this returns Ok.
+    fn finish(&mut self, name: &str, size: u64) -> CuResult<()>;
+}
+
+pub(crate) fn agent() -> Agent {
+    AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(10))
+        .timeout_read(Duration::from_secs(60))
+        .build()
+}
+
+pub(crate) fn request_error(what: &str, error: Error) -> CuError {
+    match error {
+        Error::Status(code, response) => format!(
+            "{} failed with HTTP {}: {}",
+            what,
+            code,
+            response.into_string().unwrap_or_default()
+        )
+        .into(),
+        Error::Transport(transport) => CuError::new_with_cause(what, transport),
+    }
+}
+
+/// Uploads to an HTTP endpoint with a resumable protocol: `HEAD <url>/<name>` answers the offset already
+/// stored in an `Upload-Offset` header (404 if none), and `PATCH <url>/<name>` appends the body at the
+/// `Upload-Offset` of the request and answers the new offset.
+pub(crate) struct HttpTarget {
+    url: String,
+    agent: Agent,
+}
+
+impl HttpTarget {
+    pub(crate) fn new(url: &str) -> Self {
+        HttpTarget {
+            url: url.trim_end_matches('/').to_string(),
+            agent: agent(),
+        }
+    }
+
+    fn file_url(&self, name: &str) -> String {
+        format!("{}/{}", self.url, name)
+    }
+}
+
+fn upload_offset(response: &Response) -> Option<u64> {
+    response.header("Upload-Offset")?.trim().parse().ok()
+}
+
+impl UploadTarget for HttpTarget {
+    fn uploaded(&mut self, name: &str, _size: u64) -> CuResult<u64> {
+        match self.agent.head(&self.file_url(name)).call() {
+            Ok(response) => Ok(upload_offset(&response).unwrap_or(0)),
+            Err(Error::Status(404, _)) => Ok(0),
+            Err(e) => Err(request_error("Querying the upload offset", e)),
+        }
+    }
+
+    fn send(
+        &mut self,
+        name: &str,
+        offset: u64,
+        size: u64,
+        chunk: &[u8],
+        shared: &UploadShared,
+    ) -> CuResult<u64> {
+        let result = self
+            .agent
+            .request("PATCH", &self.file_url(name))
+            .set("Content-Type", "application/offset+octet-stream")
+            .set("Content-Length", &chunk.len().to_string())
+            .set("Upload-Offset", &offset.to_string())
+            .set("Upload-Length", &size.to_string())
+            .send(Throttled::new(chunk, shared));
+        match result {
+            Ok(response) => Ok(upload_offset(&response).unwrap_or(offset + chunk.len() as u64)),
+            // The endpoint has another offset, start again from it.
+            Err(Error::Status(409, _)) => self.uploaded(name, size),
+            Err(e) => Err(request_error("Uploading a chunk", e)),
+        }
+    }
+
+    fn finish(&mut self, name: &str, size: u64) -> CuResult<()> {
+        let stored = self.uploaded(name, size)?;
+        if stored != size {
+            return Err(format!(
+                "The endpoint stored {} bytes of {} instead of {}.",
+                stored, name, size
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+//! A dual channel voter: the same command computed by 2 independent chains of tasks is only forwarded when both
+//! agree, otherwise the safe state is commanded.
+
+use bincode::{Decode, Encode};
+use cu29::clock::{CuDuration, CuTime, RobotClock};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuMsgPayload, CuTask, CuTaskLifecycle, Freezable};
+use cu29::monitoring::CuHealth;
+use cu29::{input_msg, output_msg, CuResult};
+use cu29_log_derive::debug;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A command the voter can compare between the 2 channels.
+pub trait Votable {
+    /// How far apart the commands of the 2 channels are, compared with the tolerance of the voter.
+    fn distance(&self, other: &Self) -> f64;
+
+    /// The command putting the actuators in their safe state, ie. a zero velocity.
+    fn safe_state() -> Self;
+}
+
+impl Votable for f64 {
+    fn distance(&self, other: &Self) -> f64 {
+        (self - other).abs()
+    }
+
+    fn safe_state() -> Self {
+        0.0
+    }
+}
+
+impl Votable for f32 {
+    fn distance(&self, other: &Self) -> f64 {
+        (self - other).abs() as f64
+    }
+
+    fn safe_state() -> Self {
+        0.0
+    }
+}
+
+/// The largest distance of the elements, ie. for the commands of the joints of an arm.
+impl<T: Votable, const N: usize> Votable for [T; N] {
+    fn distance(&self, other: &Self) -> f64 {
+        self.iter()
+            .zip(other.iter())
+            .map(|(a, b)| a.distance(b))
+            .fold(0.0, f64::max)
+    }
+
+    fn safe_state() -> Self {
+        std::array::from_fn(|_| T::safe_state())
+    }
+}
+
+/// Why the voter commanded the safe state.
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+pub enum VoteFault {
+    /// The channel at this index produced no command while the other one did.
+    Missing(u8),
+    /// The commands are further apart than the tolerance.
+    Disagreement,
+    /// The commands were computed for times further apart than max_skew_ms.
+    Skew,
+}
+
+impl fmt::Display for VoteFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoteFault::Missing(channel) => write!(f, "channel {} missing", channel),
+            VoteFault::Disagreement => write!(f, "disagreement"),
+            VoteFault::Skew => write!(f, "skew"),
+        }
+    }
+}
+
+/// Votes between the commands of its 2 inputs, the 2 channels, and forwards the first one when they agree within
+/// `tolerance`. Otherwise it commands the safe state of `T` and reports the task as Degraded.
+///
+/// The config takes:
+/// - `tolerance`: the largest distance between the 2 commands, 0 by default.
+/// - `max_skew_ms`: optional, the largest difference between the times of validity of the 2 commands.
+/// - `latch`: true by default, the safe state is kept after a fault until the task is restarted.
+///
+/// When neither channel produced a command, nothing is forwarded.
+pub struct CuVoter<T> {
+    tolerance: f64,
+    max_skew: Option<CuDuration>,
+    latch: bool,
+    fault: Option<VoteFault>,
+    agreed: u64,
+    faults: u64,
+    _command: PhantomData<T>,
+}
+
+impl<T> Freezable for CuVoter<T> {}
+
+impl<T> CuTaskLifecycle for CuVoter<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let tolerance = config
+            .and_then(|config| config.get::<f64>("tolerance"))
+            .unwrap_or(0.0);
+        if tolerance.is_nan() || tolerance < 0.0 {
+            return Err("The 'tolerance' of the CuVoter must be positive.".into());
+        }
+        Ok(CuVoter {
+            tolerance,
+            max_skew: config
+                .and_then(|config| config.get::<u32>("max_skew_ms"))
+                .map(|ms| CuDuration::from(ms as u64 * 1_000_000)),
+            latch: config
+                .and_then(|config| config.get::<bool>("latch"))
+                .unwrap_or(true),
+            fault: None,
+            agreed: 0,
+            faults: 0,
+            _command: PhantomData,
+        })
+    }
+
+    /// A latched fault is cleared by a restart.
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.fault = None;
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        let (agreed, faults) = (self.agreed, self.faults);
+        debug!("CuVoter: {} commands agreed, {} faults.", agreed, faults);
+        Ok(())
+    }
+}
+
+impl<T: CuMsgPayload + Votable> CuVoter<T> {
+    /// The command both channels agree on, None if neither produced one.
+    fn vote<'a>(&self, a: &'a CuMsg<T>, b: &CuMsg<T>) -> Result<Option<&'a T>, VoteFault> {
+        let (command_a, command_b) = match (a.payload(), b.payload()) {
+            (None, None) => return Ok(None),
+            (None, Some(_)) => return Err(VoteFault::Missing(0)),
+            (Some(_), None) => return Err(VoteFault::Missing(1)),
+            (Some(command_a), Some(command_b)) => (command_a, command_b),
+        };
+        let tovs: (Option<CuTime>, Option<CuTime>) = (a.metadata.tov.into(), b.metadata.tov.into());
+        if let (Some(max_skew), (Some(tov_a), Some(tov_b))) = (self.max_skew, tovs) {
+            if tov_a.0.abs_diff(tov_b.0) > max_skew.0 {
+                return Err(VoteFault::Skew);
+            }
+        }
+        // A NaN distance is a disagreement.
+        let distance = command_a.distance(command_b);
+        if distance.is_nan() || distance > self.tolerance {
+            return Err(VoteFault::Disagreement);
+        }
+        Ok(Some(command_a))
+    }
+}
+
+impl<'cl, T> CuTask<'cl> for CuVoter<T>
+where
+    T: CuMsgPayload + Votable + Clone + 'cl,
+{
+    type Input = input_msg!('cl, T, T);
+    type Output = output_msg!('cl, T);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let (a, b) = input;
+        let vote = self.vote(a, b);
+        let fault = match (self.fault, vote) {
+            (Some(latched), _) if self.latch => Some(latched),
+            (_, Err(fault)) => Some(fault),
+            (_, Ok(_)) => None,
+        };
+        if let Some(new_fault) = fault.filter(|_| fault != self.fault) {
+            self.faults += 1;
+            let description = new_fault.to_string();
+            debug!("CuVoter: safe state commanded, {}.", description);
+        }
+        self.fault = fault;
+
+        match (fault, vote) {
+            (Some(fault), _) => {
+                output.set_payload(T::safe_state());
+                output.metadata.tov = ctx.now().into();
+                output.metadata.set_status(format!("SAFE {}", fault));
+                ctx.report_health(CuHealth::Degraded);
+            }
+            (None, Ok(Some(command))) => {
+                self.agreed += 1;
+                output.set_payload(command.clone());
+                output.metadata.tov = a.metadata.tov;
+            }
+            (None, _) => output.clear_payload(),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voter(tolerance: f64, latch: bool) -> CuVoter<f64> {
+        let mut config = ComponentConfig::new();
+        config.set("tolerance", tolerance);
+        config.set("latch", latch);
+        CuVoter::new(Some(&config)).unwrap()
+    }
+
+    fn vote(
+        voter: &mut CuVoter<f64>,
+        clock: &RobotClock,
+        a: Option<f64>,
+        b: Option<f64>,
+    ) -> (Option<f64>, Option<CuHealth>) {
+        let (a, b) = (CuMsg::new(a), CuMsg::new(b));
+        let mut output = CuMsg::new(None);
+        let ctx = CuContext::from(clock);
+        voter.process(&ctx, (&a, &b), &mut output).unwrap();
+        (output.payload().copied(), ctx.reported_health())
+    }
+
+    #[test]
+    fn test_latched_voter() {
+        let clock = RobotClock::default();
+        let mut voter = voter(0.1, true);
+        assert_eq!(
+            vote(&mut voter, &clock, Some(1.0), Some(1.05)),
+            (Some(1.0), None)
+        );
+        assert_eq!(vote(&mut voter, &clock, None, None), (None, None));
+        assert_eq!(
+            vote(&mut voter, &clock, Some(1.0), Some(1.5)),
+            (Some(0.0), Some(CuHealth::Degraded))
+        );
+        assert_eq!(voter.fault, Some(VoteFault::Disagreement));
+        // Latched until the restart.
+        assert_eq!(
+            vote(&mut voter, &clock, Some(1.0), Some(1.0)),
+            (Some(0.0), Some(CuHealth::Degraded))
+        );
+        voter.start(&clock).unwrap();
+        assert_eq!(
+            vote(&mut voter, &clock, Some(1.0), Some(1.0)),
+            (Some(1.0), None)
+        );
+        assert_eq!(
+            vote(&mut voter, &clock, Some(f64::NAN), Some(f64::NAN)),
+            (Some(0.0), Some(CuHealth::Degraded))
+        );
+        assert_eq!((voter.agreed, voter.faults), (2, 2));
+    }
+
+    #[test]
+    fn test_unlatched_voter() {
+        let clock = RobotClock::default();
+        let mut voter = voter(0.0, false);
+        assert_eq!(
+            vote(&mut voter, &clock, None, Some(1.0)),
+            (Some(0.0), Some(CuHealth::Degraded))
+        );
+        assert_eq!(voter.fault, Some(VoteFault::Missing(0)));
+        assert_eq!(
+            vote(&mut voter, &clock, Some(2.0), Some(2.0)),
+            (Some(2.0), None)
+        );
+        assert_eq!(voter.fault, None);
+    }
+
+    #[test]
+    fn test_skew() {
+        let mut config = ComponentConfig::new();
+        config.set("max_skew_ms", 5u32);
+        let voter = CuVoter::<f64>::new(Some(&config)).unwrap();
+        let mut a = CuMsg::new(Some(1.0));
+        let mut b = CuMsg::new(Some(1.0));
+        a.metadata.tov = CuTime::from(0u64).into();
+        b.metadata.tov = CuTime::from(10_000_000u64).into();
+        assert_eq!(voter.vote(&a, &b), Err(VoteFault::Skew));
+        b.metadata.tov = CuTime::from(4_000_000u64).into();
+        assert_eq!(voter.vote(&a, &b), Ok(Some(&1.0)));
+    }
+
+    #[test]
+    fn test_array_commands() {
+        assert_eq!([1.0, 2.0].distance(&[1.5, 2.0]), 0.5);
+        assert_eq!(<[f32; 3]>::safe_state(), [0.0; 3]);
+    }
+}
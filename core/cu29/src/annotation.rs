@@ -0,0 +1,84 @@
+//! Markers written in the log to flag interesting moments of a run, ie. "picked up object" during a test drive.
+//! They are written right away in their own section of the log and read back with cu29_export.
+
+use crate::clock::{CuTime, RobotClock};
+use crate::{CuError, CuResult};
+use bincode::{Decode, Encode};
+use cu29_log_derive::debug;
+use cu29_traits::{wire_config, UnifiedLogType, WriteStream};
+use cu29_unifiedlog::{stream_write, UnifiedLoggerWrite};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// How much attention an annotation deserves.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CuSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl CuSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CuSeverity::Info => "info",
+            CuSeverity::Warning => "warning",
+            CuSeverity::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for CuSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A named marker at a time of the robot clock.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct CuAnnotation {
+    pub time: CuTime,
+    pub severity: CuSeverity,
+    pub label: String,
+}
+
+impl fmt::Display for CuAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}] {}", self.time, self.severity, self.label)
+    }
+}
+
+/// Writes annotations in the log of a running application, from any thread, ie. from an operator's button.
+/// Get it with `application.annotator()`.
+#[derive(Clone)]
+pub struct CuAnnotator {
+    clock: RobotClock,
+    logger: Arc<Mutex<UnifiedLoggerWrite>>,
+}
+
+impl CuAnnotator {
+    pub fn new(clock: RobotClock, logger: Arc<Mutex<UnifiedLoggerWrite>>) -> Self {
+        CuAnnotator { clock, logger }
+    }
+
+    /// Marks the current time in the log.
+    pub fn annotate(&self, label: &str, severity: CuSeverity) -> CuResult<()> {
+        let annotation = CuAnnotation {
+            time: self.clock.now(),
+            severity,
+            label: label.to_string(),
+        };
+        let size = bincode::encode_to_vec(&annotation, wire_config())
+            .map_err(|e| CuError::new_with_cause("Could not encode the annotation", e))?
+            .len();
+        // Every annotation has its own section so it is on disk even if the application does not exit cleanly.
+        let mut stream = stream_write(self.logger.clone(), UnifiedLogType::Annotation, size + 64);
+        stream.log(&annotation)?;
+        debug!("Annotation: [{}] {}", severity.as_str(), label);
+        Ok(())
+    }
+}
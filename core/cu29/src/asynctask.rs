@@ -0,0 +1,489 @@
+//! Tasks awaiting their I/O, ie. the drivers of network or serial devices, without blocking the copper loop.
+//!
+//! An async task implements CuAsyncSrcTask or CuAsyncTask and takes its place in the graph, next to the sync tasks,
+//! through its adapter:
+//!
+//! ```ron
+//! (id: "lidar", type: "cu29::asynctask::CuAsyncSrc<my_lidar::UdpLidar>"),
+//! (id: "uploader", type: "cu29::asynctask::CuAsync<my_cloud::Uploader>"),
+//! ```
+//!
+//! Between its start and its stop, the async task runs on its own thread, `cu:task:<type>`, where an embedded
+//! executor polls it every time it is woken up. A process call of the adapter never waits for it: it publishes the
+//! last output completed since the previous call, or nothing, and hands its input over to the async task for its
+//! next call, the last input winning when the async task is busy. An error of the async task is returned by the next
+//! process call, for the monitor to decide.
+//!
+//! The thread runs with the priority and the core affinity of the node if it has some, see
+//! cu29::threads::CuThreadScheduling.
+//!
+//! With the `tokio` feature, the threads of the async tasks enter a tokio runtime shared by all of them, so they can
+//! use its sockets and timers.
+
+use crate::clock::{CuTime, RobotClock};
+use crate::config::ComponentConfig;
+use crate::context::CuContext;
+use crate::cutask::{CuMsg, CuMsgPayload, CuSrcTask, CuTask, CuTaskLifecycle, Freezable};
+use crate::threads::{spawn_task_thread, CuThreadScheduling};
+use crate::{input_msg, output_msg};
+use cu29_traits::lockaudit::{self, LockSite};
+use cu29_traits::{CuError, CuResult};
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+
+static SLOTS_LOCK: LockSite = LockSite::new("async_task_slots");
+
+/// A source awaiting its outputs, see the module documentation.
+pub trait CuAsyncSrcTask: CuTaskLifecycle + Send + 'static {
+    type Output: CuMsgPayload + Send + 'static;
+
+    /// Awaits the next output, ie. the next packet of the device.
+    fn next(&mut self) -> impl Future<Output = CuResult<Self::Output>>;
+}
+
+/// A task awaiting the processing of its input, see the module documentation.
+pub trait CuAsyncTask: CuTaskLifecycle + Send + 'static {
+    type Input: CuMsgPayload + Clone + Send + 'static;
+    type Output: CuMsgPayload + Send + 'static;
+
+    /// Processes an input, None when there is nothing to publish for it.
+    fn process(
+        &mut self,
+        input: Self::Input,
+    ) -> impl Future<Output = CuResult<Option<Self::Output>>>;
+}
+
+/// Unparks the thread polling the future.
+struct Unparker(Thread);
+
+impl Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls the future on the current thread, parked while it is pending, until it completes or stop is set.
+fn block_on<F: Future>(future: F, stop: &AtomicBool) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(Unparker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if stop.load(Ordering::Acquire) {
+            return None;
+        }
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Some(output);
+        }
+        thread::park();
+    }
+}
+
+/// The tokio runtime entered by the threads of the async tasks, started by the first of them.
+#[cfg(feature = "tokio")]
+fn tokio_handle() -> CuResult<tokio::runtime::Handle> {
+    use std::sync::OnceLock;
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime.handle().clone());
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name(format!("{}tokio", crate::threads::THREAD_PREFIX))
+        .build()
+        .map_err(|e| CuError::new_with_cause("Could not start the tokio runtime", e))?;
+    Ok(RUNTIME.get_or_init(|| runtime).handle().clone())
+}
+
+/// What the adapter and the thread of the async task exchange.
+struct Slots<I, O> {
+    stop: AtomicBool,
+    /// The last input not taken by the async task yet.
+    input: Mutex<Option<I>>,
+    /// The last output completed and not published yet, with the time it completed.
+    output: Mutex<Option<(CuResult<O>, CuTime)>>,
+}
+
+impl<I, O> Default for Slots<I, O> {
+    fn default() -> Self {
+        Slots {
+            stop: AtomicBool::new(false),
+            input: Mutex::new(None),
+            output: Mutex::new(None),
+        }
+    }
+}
+
+impl<I, O> Slots<I, O> {
+    fn take_input(&self) -> Option<I> {
+        lockaudit::lock(&SLOTS_LOCK, &self.input).unwrap().take()
+    }
+
+    fn put_output(&self, output: CuResult<O>, clock: &RobotClock) {
+        *lockaudit::lock(&SLOTS_LOCK, &self.output).unwrap() = Some((output, clock.now()));
+    }
+}
+
+/// The async task, on its thread while it is started.
+struct AsyncDriver<T, I, O> {
+    task: Option<T>,
+    slots: Arc<Slots<I, O>>,
+    thread: Option<JoinHandle<T>>,
+    /// The priority and the core affinity of the node, applied to the thread.
+    scheduling: Option<CuThreadScheduling>,
+}
+
+impl<T, I, O> AsyncDriver<T, I, O>
+where
+    T: CuTaskLifecycle + Send + 'static,
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self> {
+        Ok(AsyncDriver {
+            task: Some(T::new(config)?),
+            slots: Arc::new(Slots::default()),
+            thread: None,
+            scheduling: CuThreadScheduling::from_config(config)?,
+        })
+    }
+
+    /// Starts the task and runs body on its thread until the stop.
+    fn start<B>(&mut self, clock: &RobotClock, body: B) -> CuResult<()>
+    where
+        B: FnOnce(&mut T, &Slots<I, O>, &RobotClock) + Send + 'static,
+    {
+        let mut task = self
+            .task
+            .take()
+            .ok_or("The async task is already started.")?;
+        if let Err(e) = task.start(clock) {
+            self.task = Some(task);
+            return Err(e);
+        }
+        self.slots = Arc::new(Slots::default());
+        let slots = self.slots.clone();
+        let clock = clock.clone();
+        #[cfg(feature = "tokio")]
+        let handle = tokio_handle()?;
+        // The name of the type of the task without its path nor its generics.
+        let type_name = std::any::type_name::<T>();
+        let type_name = type_name.split('<').next().unwrap_or(type_name);
+        let name = type_name.rsplit("::").next().unwrap_or(type_name);
+        let scheduling = self.scheduling.clone();
+        self.thread = Some(spawn_task_thread(name, move || {
+            #[cfg(feature = "tokio")]
+            let _runtime = handle.enter();
+            if let Some(Err(e)) = scheduling.map(|scheduling| scheduling.apply()) {
+                slots.put_output(Err(e), &clock);
+            }
+            body(&mut task, &slots, &clock);
+            task
+        })?);
+        Ok(())
+    }
+
+    /// Hands the input over to the async task, replacing the one it did not take yet.
+    fn put_input(&self, input: I) {
+        *lockaudit::lock(&SLOTS_LOCK, &self.slots.input).unwrap() = Some(input);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+
+    fn take_output(&self) -> Option<(CuResult<O>, CuTime)> {
+        lockaudit::lock(&SLOTS_LOCK, &self.slots.output)
+            .unwrap()
+            .take()
+    }
+
+    /// Cancels what the async task awaits and stops it.
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        if let Some(thread) = self.thread.take() {
+            self.slots.stop.store(true, Ordering::Release);
+            thread.thread().unpark();
+            let task = thread
+                .join()
+                .map_err(|_| CuError::from("The thread of the async task panicked."))?;
+            self.task = Some(task);
+        }
+        match self.task.as_mut() {
+            Some(task) => task.stop(clock),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The adapter of a CuAsyncSrcTask in the graph.
+pub struct CuAsyncSrc<T: CuAsyncSrcTask> {
+    driver: AsyncDriver<T, (), T::Output>,
+}
+
+impl<T: CuAsyncSrcTask> Freezable for CuAsyncSrc<T> {}
+
+impl<T: CuAsyncSrcTask> CuTaskLifecycle for CuAsyncSrc<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuAsyncSrc {
+            driver: AsyncDriver::new(config)?,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.driver.start(clock, |task, slots, clock| {
+            while let Some(output) = block_on(task.next(), &slots.stop) {
+                slots.put_output(output, clock);
+            }
+        })
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.driver.stop(clock)
+    }
+}
+
+impl<'cl, T: CuAsyncSrcTask> CuSrcTask<'cl> for CuAsyncSrc<T> {
+    type Output = output_msg!('cl, T::Output);
+
+    fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+        match self.driver.take_output() {
+            Some((output, completed)) => {
+                new_msg.set_payload(output?);
+                new_msg.metadata.tov = completed.into();
+            }
+            None => new_msg.clear_payload(),
+        }
+        Ok(())
+    }
+}
+
+/// The adapter of a CuAsyncTask in the graph.
+pub struct CuAsync<T: CuAsyncTask> {
+    driver: AsyncDriver<T, T::Input, Option<T::Output>>,
+}
+
+impl<T: CuAsyncTask> Freezable for CuAsync<T> {}
+
+impl<T: CuAsyncTask> CuTaskLifecycle for CuAsync<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuAsync {
+            driver: AsyncDriver::new(config)?,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.driver.start(clock, |task, slots, clock| {
+            // Woken up by the adapter when it puts an input.
+            let next_input = || poll_fn(|_| slots.take_input().map_or(Poll::Pending, Poll::Ready));
+            while let Some(input) = block_on(next_input(), &slots.stop) {
+                match block_on(task.process(input), &slots.stop) {
+                    Some(output) => slots.put_output(output, clock),
+                    None => break,
+                }
+            }
+        })
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.driver.stop(clock)
+    }
+}
+
+impl<'cl, T: CuAsyncTask> CuTask<'cl> for CuAsync<T> {
+    type Input = input_msg!('cl, T::Input);
+    type Output = output_msg!('cl, T::Output);
+
+    fn process(
+        &mut self,
+        _ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let completed = self.driver.take_output();
+        if let Some(input) = input.payload() {
+            self.driver.put_input(input.clone());
+        }
+        match completed {
+            Some((Ok(Some(payload)), completed)) => {
+                output.set_payload(payload);
+                output.metadata.tov = completed.into();
+            }
+            Some((Err(e), _)) => return Err(e),
+            _ => output.clear_payload(),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Pending once, like an I/O not ready yet.
+    async fn yield_once() {
+        let mut yielded = false;
+        poll_fn(|cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Counts from 1, fails on 3.
+    struct Counter {
+        count: u32,
+    }
+
+    impl Freezable for Counter {}
+
+    impl CuTaskLifecycle for Counter {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self> {
+            Ok(Counter { count: 0 })
+        }
+    }
+
+    impl CuAsyncSrcTask for Counter {
+        type Output = u32;
+
+        async fn next(&mut self) -> CuResult<u32> {
+            // Slow enough to read every count.
+            thread::sleep(Duration::from_millis(20));
+            yield_once().await;
+            self.count += 1;
+            if self.count == 3 {
+                return Err("count 3".into());
+            }
+            Ok(self.count)
+        }
+    }
+
+    struct Doubler {}
+
+    impl Freezable for Doubler {}
+
+    impl CuTaskLifecycle for Doubler {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self> {
+            Ok(Doubler {})
+        }
+    }
+
+    impl CuAsyncTask for Doubler {
+        type Input = u32;
+        type Output = u32;
+
+        async fn process(&mut self, input: u32) -> CuResult<Option<u32>> {
+            yield_once().await;
+            Ok(Some(input * 2))
+        }
+    }
+
+    /// Calls process until it publishes something or fails.
+    fn wait_for<F: FnMut() -> CuResult<Option<u32>>>(mut process: F) -> CuResult<u32> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(output) = process()? {
+                return Ok(output);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("The async task did not complete.");
+    }
+
+    #[test]
+    fn test_async_src() {
+        let (clock, _mock) = RobotClock::mock();
+        let mut src = CuAsyncSrc::<Counter>::new(None).unwrap();
+        src.start(&clock).unwrap();
+        let mut output = CuMsg::new(None);
+        let mut next = || {
+            src.process(&CuContext::from(&clock), &mut output)
+                .map(|()| output.payload().copied())
+        };
+        assert_eq!(wait_for(&mut next).unwrap(), 1);
+        assert_eq!(wait_for(&mut next).unwrap(), 2);
+        assert!(wait_for(&mut next).is_err());
+        assert_eq!(wait_for(&mut next).unwrap(), 4);
+        src.stop(&clock).unwrap();
+        assert!(src.driver.task.is_some());
+    }
+
+    #[cfg(feature = "tokio")]
+    struct Ticker {}
+
+    #[cfg(feature = "tokio")]
+    impl Freezable for Ticker {}
+
+    #[cfg(feature = "tokio")]
+    impl CuTaskLifecycle for Ticker {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self> {
+            Ok(Ticker {})
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    impl CuAsyncSrcTask for Ticker {
+        type Output = u32;
+
+        async fn next(&mut self) -> CuResult<u32> {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(1)
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_tokio_timer() {
+        let (clock, _mock) = RobotClock::mock();
+        let mut ticker = CuAsyncSrc::<Ticker>::new(None).unwrap();
+        ticker.start(&clock).unwrap();
+        let mut output = CuMsg::new(None);
+        let tick = wait_for(|| {
+            ticker
+                .process(&CuContext::from(&clock), &mut output)
+                .map(|()| output.payload().copied())
+        });
+        assert_eq!(tick.unwrap(), 1);
+        ticker.stop(&clock).unwrap();
+    }
+
+    #[test]
+    fn test_async_task() {
+        let (clock, _mock) = RobotClock::mock();
+        let mut doubler = CuAsync::<Doubler>::new(None).unwrap();
+        doubler.start(&clock).unwrap();
+        let mut output = CuMsg::new(None);
+        let input = CuMsg::new(Some(21));
+        doubler
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        let none = CuMsg::new(None);
+        let doubled = wait_for(|| {
+            doubler
+                .process(&CuContext::from(&clock), &none, &mut output)
+                .map(|()| output.payload().copied())
+        });
+        assert_eq!(doubled.unwrap(), 42);
+        // The task awaits its next input, the stop cancels it.
+        doubler.stop(&clock).unwrap();
+        doubler.start(&clock).unwrap();
+        doubler.stop(&clock).unwrap();
+    }
+}
@@ -0,0 +1,232 @@
+//! Checks of the robot clock against the wall clock (CLOCK_REALTIME): its drift, and the steps of the wall clock
+//! made by NTP or a suspend and resume of the machine.
+//!
+//! Enabled in the runtime section of the config:
+//! ```ron
+//! runtime: (clock_check: (period_ms: 1000, max_step_ms: 100, max_drift_ppm: 200.0)),
+//! ```
+//! Every step or excessive drift is written in its own section of the log with the copper lists recorded while it
+//! happened, so the analysis tools can tell which copper lists have timestamps to treat carefully. See the
+//! clock-events command of the log reader.
+
+use crate::clock::{CuTime, RobotClock};
+use crate::config::ClockCheckConfig;
+use crate::{CuError, CuResult};
+use bincode::{Decode, Encode};
+use cu29_traits::{wire_config, UnifiedLogType, WriteStream};
+use cu29_unifiedlog::{stream_write, UnifiedLoggerWrite};
+use serde_derive::Serialize;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What happened to the clocks.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub enum CuClockEventKind {
+    /// The offset between the wall clock and the robot clock jumped by this much between 2 comparisons.
+    Step { offset_ns: i64 },
+    /// The robot clock drifted from the wall clock by this much since the last step, positive if it is slower.
+    Drift { ppm: f64 },
+}
+
+/// A discontinuity or a drift of the clocks, with the copper lists recorded while it happened.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub struct CuClockEvent {
+    /// When it was detected on the robot clock.
+    pub time: CuTime,
+    pub kind: CuClockEventKind,
+    pub first_culist: u32,
+    pub last_culist: u32,
+}
+
+impl CuClockEvent {
+    /// If the copper list was recorded while the event happened.
+    pub fn covers(&self, culist: u32) -> bool {
+        (self.first_culist..=self.last_culist).contains(&culist)
+    }
+}
+
+impl fmt::Display for CuClockEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            CuClockEventKind::Step { offset_ns } => write!(
+                f,
+                "{} wall clock step of {:+.3} ms",
+                self.time,
+                offset_ns as f64 / 1_000_000.0
+            )?,
+            CuClockEventKind::Drift { ppm } => {
+                write!(f, "{} robot clock drift of {:+.1} ppm", self.time, ppm)?
+            }
+        }
+        write!(
+            f,
+            ", copper lists {} to {}",
+            self.first_culist, self.last_culist
+        )
+    }
+}
+
+/// A reading of both clocks, the wall clock in ns since the epoch.
+#[derive(Debug, Clone, Copy)]
+struct Reading {
+    robot: CuTime,
+    wall: i64,
+    culist: u32,
+}
+
+impl Reading {
+    /// How much more the wall clock advanced than the robot clock since the earlier reading.
+    fn offset_since(&self, earlier: &Reading) -> i64 {
+        (self.wall - earlier.wall) - (self.robot.0 as i64 - earlier.robot.0 as i64)
+    }
+}
+
+/// Compares the robot clock to the wall clock at the end of the copper lists, see check.
+pub struct CuClockCheck {
+    clock: RobotClock,
+    config: ClockCheckConfig,
+    /// Where the drift is measured from, the first reading or the one after the last step.
+    reference: Option<Reading>,
+    last: Option<Reading>,
+    /// If the drift was already reported, it is reported again once it went back under the limit.
+    drifting: bool,
+}
+
+impl CuClockCheck {
+    pub fn new(clock: RobotClock, config: ClockCheckConfig) -> Self {
+        CuClockCheck {
+            clock,
+            config,
+            reference: None,
+            last: None,
+            drifting: false,
+        }
+    }
+
+    /// Compares the clocks if the period elapsed, culist is the id of the copper list that just ended.
+    pub fn check(&mut self, culist: u32) -> Option<CuClockEvent> {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos() as i64)
+            .unwrap_or_default();
+        self.check_at(self.clock.now(), wall, culist)
+    }
+
+    /// check with explicit readings of the clocks, the wall clock in ns since the epoch.
+    pub fn check_at(&mut self, robot: CuTime, wall: i64, culist: u32) -> Option<CuClockEvent> {
+        let now = Reading {
+            robot,
+            wall,
+            culist,
+        };
+        let (Some(reference), Some(last)) = (self.reference, self.last) else {
+            self.reference = Some(now);
+            self.last = Some(now);
+            return None;
+        };
+        if robot.0.saturating_sub(last.robot.0) < self.config.period_ms * 1_000_000 {
+            return None;
+        }
+        self.last = Some(now);
+        let step = now.offset_since(&last);
+        if step.unsigned_abs() > self.config.max_step_ms * 1_000_000 {
+            self.reference = Some(now);
+            self.drifting = false;
+            return Some(CuClockEvent {
+                time: robot,
+                kind: CuClockEventKind::Step { offset_ns: step },
+                first_culist: last.culist,
+                last_culist: culist,
+            });
+        }
+        let elapsed = robot.0.saturating_sub(reference.robot.0);
+        if elapsed == 0 {
+            return None;
+        }
+        let ppm = now.offset_since(&reference) as f64 * 1_000_000.0 / elapsed as f64;
+        if ppm.abs() <= self.config.max_drift_ppm {
+            self.drifting = false;
+            return None;
+        }
+        if self.drifting {
+            return None;
+        }
+        self.drifting = true;
+        Some(CuClockEvent {
+            time: robot,
+            kind: CuClockEventKind::Drift { ppm },
+            first_culist: reference.culist,
+            last_culist: culist,
+        })
+    }
+}
+
+/// Writes the event in its own section of the log, like the annotations, so it is on disk right away.
+pub fn write_clock_event(
+    logger: &Arc<Mutex<UnifiedLoggerWrite>>,
+    event: &CuClockEvent,
+) -> CuResult<()> {
+    let size = bincode::encode_to_vec(event, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not encode the clock event", e))?
+        .len();
+    let mut stream = stream_write(logger.clone(), UnifiedLogType::ClockEvent, size + 64);
+    stream.log(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn test_clock_check() {
+        let mut check = CuClockCheck::new(RobotClock::default(), ClockCheckConfig::default());
+        let wall = 1_700_000_000_000_000_000i64;
+        assert_eq!(check.check_at(CuTime::from(0), wall, 0), None);
+        // Not compared before the period.
+        assert_eq!(
+            check.check_at(CuTime::from(500 * MS), wall + 900 * MS as i64, 5),
+            None
+        );
+        // 100 ppm is under the limit.
+        assert_eq!(
+            check.check_at(
+                CuTime::from(1000 * MS),
+                wall + 1000 * MS as i64 + 100_000,
+                10
+            ),
+            None
+        );
+
+        // NTP steps the wall clock back 2 s.
+        let event = check.check_at(CuTime::from(2000 * MS), wall, 20).unwrap();
+        assert_eq!(
+            event.kind,
+            CuClockEventKind::Step {
+                offset_ns: -2_000_000_000 - 100_000
+            }
+        );
+        assert!(event.covers(15));
+        assert!(!event.covers(21));
+        assert_eq!(
+            event.to_string(),
+            "2.000 s wall clock step of -2000.100 ms, copper lists 10 to 20"
+        );
+
+        // Then the robot clock runs 1000 ppm slow, reported once.
+        let event = check
+            .check_at(CuTime::from(3000 * MS), wall + 1001 * MS as i64, 30)
+            .unwrap();
+        let CuClockEventKind::Drift { ppm } = event.kind else {
+            panic!("expected a drift");
+        };
+        assert!((ppm - 1000.0).abs() < 1e-6);
+        assert_eq!(event.first_culist, 20);
+        assert_eq!(
+            check.check_at(CuTime::from(4000 * MS), wall + 2002 * MS as i64, 40),
+            None
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Clock domains: subgraphs whose tasks timestamp their messages against another clock than the robot clock, ie.
+//! the hardware clock of a camera or the clock of a remote machine.
+//!
+//! The tasks of such a subgraph are marked with the name of their domain in the config:
+//!
+//! ```ron
+//! (id: "cam", type: "tasks::Camera", clock_domain: "camera"),
+//! (id: "detector", type: "tasks::Detector", clock_domain: "camera"),
+//! ```
+//!
+//! The conversion of the clock of the domain to the robot clock is registered at runtime, usually by the task or the
+//! thread estimating it, and the tasks fusing the data of several domains get the times of their inputs in the robot
+//! clock with `ctx.robot_tov("detector", input.metadata.tov)`.
+
+use crate::config::CuConfig;
+use cu29_clock::{CuTime, OptionCuTime};
+use cu29_traits::lockaudit::{self, LockSite};
+use cu29_traits::{CuError, CuResult};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Converts a time of the clock of a domain into the robot clock.
+pub type CuClockConversion = Arc<dyn Fn(CuTime) -> CuTime + Send + Sync>;
+
+static CONVERSIONS_LOCK: LockSite = LockSite::new("clock_domains");
+
+/// The clock domains of the tasks and the conversions registered for them. The runtime shares it with the tasks
+/// through their context.
+#[derive(Default)]
+pub struct CuClockDomains {
+    /// The domain of every task out of the robot clock, by task name.
+    tasks: HashMap<String, String>,
+    conversions: RwLock<HashMap<String, CuClockConversion>>,
+}
+
+impl CuClockDomains {
+    pub fn from_config(config: &CuConfig) -> Self {
+        let tasks = config
+            .get_all_nodes()
+            .iter()
+            .filter_map(|node| Some((node.get_id(), node.get_clock_domain()?.to_string())))
+            .collect();
+        CuClockDomains {
+            tasks,
+            conversions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The clock domain of the task, None for the robot clock.
+    pub fn domain_of(&self, task: &str) -> Option<&str> {
+        self.tasks.get(task).map(String::as_str)
+    }
+
+    /// Registers the conversion from the clock of the domain to the robot clock, replacing the previous one.
+    pub fn register(
+        &self,
+        domain: &str,
+        conversion: impl Fn(CuTime) -> CuTime + Send + Sync + 'static,
+    ) {
+        lockaudit::write(&CONVERSIONS_LOCK, &self.conversions)
+            .unwrap()
+            .insert(domain.to_string(), Arc::new(conversion));
+    }
+
+    /// Registers a clock of the domain offset_ns behind the robot clock: robot time = domain time + offset_ns.
+    pub fn register_offset(&self, domain: &str, offset_ns: i64) {
+        self.register(domain, move |time| {
+            CuTime::from(time.0.saturating_add_signed(offset_ns))
+        });
+    }
+
+    pub fn is_registered(&self, domain: &str) -> bool {
+        lockaudit::read(&CONVERSIONS_LOCK, &self.conversions)
+            .unwrap()
+            .contains_key(domain)
+    }
+
+    /// Converts a time of the clock of the domain into the robot clock.
+    pub fn to_robot_time(&self, domain: &str, time: CuTime) -> CuResult<CuTime> {
+        let conversion = lockaudit::read(&CONVERSIONS_LOCK, &self.conversions)
+            .unwrap()
+            .get(domain)
+            .cloned()
+            .ok_or_else(|| {
+                CuError::from(format!(
+                    "No conversion of the clock domain '{}' to the robot clock is registered.",
+                    domain
+                ))
+            })?;
+        Ok(conversion(time))
+    }
+
+    /// Converts a time of the clock of the task into the robot clock, the time as is for a task in the robot clock.
+    pub fn task_to_robot_time(&self, task: &str, time: CuTime) -> CuResult<CuTime> {
+        match self.domain_of(task) {
+            Some(domain) => self.to_robot_time(domain, time),
+            None => Ok(time),
+        }
+    }
+
+    /// The time of validity of a message of the task in the robot clock.
+    pub fn robot_tov(&self, task: &str, tov: OptionCuTime) -> CuResult<OptionCuTime> {
+        if tov.is_none() {
+            return Ok(tov);
+        }
+        Ok(self.task_to_robot_time(task, tov.unwrap())?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_domains() {
+        let txt = r#"( tasks: [(id: "cam", type: "a", clock_domain: "camera"), (id: "imu", type: "b")], cnx: [] )"#;
+        let domains = CuClockDomains::from_config(&CuConfig::deserialize_ron(txt));
+        assert_eq!(domains.domain_of("cam"), Some("camera"));
+        assert_eq!(domains.domain_of("imu"), None);
+
+        let time = CuTime::from(1_000_000u64);
+        assert!(domains.task_to_robot_time("cam", time).is_err());
+        assert_eq!(domains.task_to_robot_time("imu", time).unwrap(), time);
+
+        domains.register_offset("camera", -400_000);
+        assert!(domains.is_registered("camera"));
+        assert_eq!(
+            domains.robot_tov("cam", time.into()).unwrap().unwrap().0,
+            600_000
+        );
+        assert!(domains
+            .robot_tov("cam", OptionCuTime::none())
+            .unwrap()
+            .is_none());
+
+        // A drifting clock, 2 ticks per microsecond.
+        domains.register("camera", |ticks| CuTime::from(ticks.0 * 500));
+        assert_eq!(
+            domains.to_robot_time("camera", CuTime::from(3u64)).unwrap(),
+            CuTime::from(1500u64)
+        );
+    }
+}
@@ -3,14 +3,18 @@
 //! The configuration is serialized in the RON format.
 //! The configuration is used to generate the runtime code at compile time.
 
+use crate::permissions::{CuRole, PERMISSION_KEY_PREFIX};
 use crate::{CuError, CuResult};
+use cu29_clock::CuDuration;
+use cu29_log_derive::debug;
 use petgraph::stable_graph::{EdgeIndex, StableDiGraph};
 use petgraph::visit::EdgeRef;
 use ron::extensions::Extensions;
 use ron::value::Value as RonValue;
 use ron::Options;
+use serde::de::Error as _;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::Display;
 use std::fs::read_to_string;
@@ -52,6 +56,19 @@ impl ComponentConfig {
         self.0.get(key).map(|v| T::from(v.clone()))
     }
 
+    /// Like get for the values that can be invalid, ie. a negative integer for a u64.
+    pub fn try_get<T: TryFrom<Value, Error = CuError>>(&self, key: &str) -> CuResult<Option<T>> {
+        self.0
+            .get(key)
+            .map(|v| {
+                T::try_from(v.clone()).map_err(|e| {
+                    CuError::from(format!("Invalid value for the config key '{}'", key))
+                        .add_cause(&e.to_string())
+                })
+            })
+            .transpose()
+    }
+
     #[allow(dead_code)]
     pub fn set<T: Into<Value>>(&mut self, key: &str, value: T) {
         self.0.insert(key.to_string(), value.into());
@@ -99,6 +116,12 @@ impl From<f64> for Value {
     }
 }
 
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value(RonValue::Bool(value))
+    }
+}
+
 impl From<Value> for bool {
     fn from(value: Value) -> Self {
         if let RonValue::Bool(v) = value.0 {
@@ -165,6 +188,26 @@ impl From<Value> for f64 {
     }
 }
 
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value(RonValue::Number(value.into()))
+    }
+}
+
+impl TryFrom<Value> for u64 {
+    type Error = CuError;
+
+    fn try_from(value: Value) -> CuResult<Self> {
+        match &value.0 {
+            RonValue::Number(num) => num
+                .as_i64()
+                .and_then(|i| u64::try_from(i).ok())
+                .ok_or_else(|| format!("Expected a positive integer but got {}", value).into()),
+            _ => Err(format!("Expected a Number but got {}", value).into()),
+        }
+    }
+}
+
 impl From<String> for Value {
     fn from(value: String) -> Self {
         Value(RonValue::String(value))
@@ -181,10 +224,38 @@ impl From<Value> for String {
     }
 }
 
+impl Value {
+    /// Parses a value written in RON, ie. `2.5` or `"fast"`.
+    pub fn from_ron(text: &str) -> CuResult<Self> {
+        let value: RonValue = ron::from_str(text.trim()).map_err(|e| {
+            CuError::from(format!("Invalid RON value '{}'", text)).add_cause(&e.to_string())
+        })?;
+        Ok(Value(value))
+    }
+
+    /// The text of a string value, ie. a quantity written with its unit.
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.0 {
+            RonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value written in RON, parsed back by from_ron.
+    pub fn to_ron(&self) -> CuResult<String> {
+        ron::to_string(&self.0).map_err(|e| {
+            CuError::from("Could not write the value in RON").add_cause(&e.to_string())
+        })
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.0 {
-            RonValue::Number(n) => write!(f, "{}", n.as_i64().unwrap()),
+            RonValue::Number(n) => match n.as_i64() {
+                Some(i) => write!(f, "{}", i),
+                None => write!(f, "{}", n.as_f64().unwrap()),
+            },
             RonValue::String(s) => write!(f, "{}", s),
             RonValue::Bool(b) => write!(f, "{}", b),
             RonValue::Map(m) => write!(f, "{:?}", m),
@@ -196,6 +267,69 @@ impl Display for Value {
     }
 }
 
+/// The candidate closest to name if it is close enough to be a typo.
+pub fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    // Edit distance counting the swap of 2 adjacent characters as 1 edit (optimal string alignment).
+    let distance = |a: &str, b: &str| {
+        let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+        let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in d[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = (a[i - 1] != b[j - 1]) as usize;
+                d[i][j] = (d[i - 1][j] + 1)
+                    .min(d[i][j - 1] + 1)
+                    .min(d[i - 1][j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+                }
+            }
+        }
+        d[a.len()][b.len()]
+    };
+    candidates
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(d, candidate)| *d <= candidate.chars().count().max(name.chars().count()) / 3)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Key under which the runtime gives the base period of a node (if any) to its task config.
+pub const BASE_PERIOD_NS_KEY: &str = "base_period_ns";
+
+/// Keys under which the runtime gives the scheduling of the threads of a node (if any) to its task config, for the
+/// drivers running their own threads. The cores are written as a list, ie. "2,3".
+pub const PRIORITY_KEY: &str = "priority";
+pub const CORE_AFFINITY_KEY: &str = "core_affinity";
+
+/// Key set to true in the config of every task during a dry run, see CuParamOverrides::dry_run.
+pub const DRY_RUN_KEY: &str = "dry_run";
+
+/// Whether the task is created for a dry run: a driver then checks its config without opening its hardware.
+pub fn is_dry_run(config: Option<&ComponentConfig>) -> bool {
+    config
+        .and_then(|config| config.get::<bool>(DRY_RUN_KEY))
+        .unwrap_or(false)
+}
+
+/// What the runtime does when the process of a task fails, see [crate::errorpolicy].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CuErrorPolicy {
+    /// The output of the task is empty for this copper list and the runtime continues.
+    Ignore,
+    /// The process is called again up to this many times in the same copper list.
+    Retry(u32),
+    /// The task is stopped, created again from its config and started, its output is empty for this copper list.
+    Restart,
+    /// The application stops with the error.
+    Abort,
+}
+
 /// A node in the configuration graph.
 /// A node represents a Task in the system Graph.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -205,6 +339,30 @@ pub struct Node {
     type_: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     config: Option<ComponentConfig>,
+    /// The period at which this task is expected to run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_period_ns: Option<u64>,
+    /// The size in bytes of the scratch arena the runtime gives to this task.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scratch_size: Option<usize>,
+    /// The role needed by each runtime mutation of the task, see [crate::permissions].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permissions: Option<BTreeMap<String, CuRole>>,
+    /// The clock the task timestamps its messages against if not the robot clock, see [crate::clockdomain].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock_domain: Option<String>,
+    /// Whether the application can run without this task, if its hardware is missing for example.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optional: Option<bool>,
+    /// The real time priority of the threads running the task, see [crate::threads::CuThreadScheduling].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<i32>,
+    /// The cores the threads running the task are pinned to, see [crate::threads::CuThreadScheduling].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    core_affinity: Option<Vec<usize>>,
+    /// What the runtime does when the process of the task fails, see [crate::errorpolicy].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on_error: Option<CuErrorPolicy>,
 }
 
 impl Node {
@@ -213,11 +371,100 @@ impl Node {
         Node {
             id: id.to_string(),
             type_: Some(ptype.to_string()),
-            // base_period_ns: None,
             config: None,
+            base_period_ns: None,
+            scratch_size: None,
+            permissions: None,
+            clock_domain: None,
+            optional: None,
+            priority: None,
+            core_affinity: None,
+            on_error: None,
         }
     }
 
+    /// The configured period of the task if any.
+    pub fn get_base_period_ns(&self) -> Option<u64> {
+        self.base_period_ns
+    }
+
+    #[allow(dead_code)]
+    pub fn set_base_period_ns(&mut self, period_ns: Option<u64>) {
+        self.base_period_ns = period_ns;
+    }
+
+    /// The size of the scratch arena of the task, 0 if it does not need one.
+    pub fn get_scratch_size(&self) -> usize {
+        self.scratch_size.unwrap_or(0)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_scratch_size(&mut self, size: Option<usize>) {
+        self.scratch_size = size;
+    }
+
+    /// The role needed for the runtime mutation of the task, None if it is not marked.
+    pub fn get_permission(&self, mutation: &str) -> Option<CuRole> {
+        self.permissions.as_ref()?.get(mutation).copied()
+    }
+
+    #[allow(dead_code)]
+    pub fn set_permission(&mut self, mutation: &str, role: CuRole) {
+        self.permissions
+            .get_or_insert_with(BTreeMap::new)
+            .insert(mutation.to_string(), role);
+    }
+
+    /// The clock domain of the task, None for the robot clock.
+    pub fn get_clock_domain(&self) -> Option<&str> {
+        self.clock_domain.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn set_clock_domain(&mut self, domain: Option<String>) {
+        self.clock_domain = domain;
+    }
+
+    /// Whether the runtime prunes the task instead of failing when it cannot be created or started.
+    pub fn is_optional(&self) -> bool {
+        self.optional.unwrap_or(false)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_optional(&mut self, optional: Option<bool>) {
+        self.optional = optional;
+    }
+
+    /// The real time priority of the threads running the task, if any.
+    pub fn get_priority(&self) -> Option<i32> {
+        self.priority
+    }
+
+    #[allow(dead_code)]
+    pub fn set_priority(&mut self, priority: Option<i32>) {
+        self.priority = priority;
+    }
+
+    /// The cores the threads running the task are pinned to, empty if they are not.
+    pub fn get_core_affinity(&self) -> &[usize] {
+        self.core_affinity.as_deref().unwrap_or_default()
+    }
+
+    #[allow(dead_code)]
+    pub fn set_core_affinity(&mut self, cores: Option<Vec<usize>>) {
+        self.core_affinity = cores;
+    }
+
+    /// The error policy of the task, None if the monitor decides.
+    pub fn get_on_error(&self) -> Option<CuErrorPolicy> {
+        self.on_error
+    }
+
+    #[allow(dead_code)]
+    pub fn set_on_error(&mut self, policy: Option<CuErrorPolicy>) {
+        self.on_error = policy;
+    }
+
     #[allow(dead_code)]
     pub fn get_id(&self) -> String {
         self.id.clone()
@@ -238,6 +485,35 @@ impl Node {
         self.config.as_ref()
     }
 
+    /// The config given to the task at creation: the instance config plus the reserved keys
+    /// the runtime provides like the base period and the permissions of the node.
+    pub fn get_task_config(&self) -> Option<ComponentConfig> {
+        let mut config = self.config.clone();
+        if let Some(period) = self.base_period_ns {
+            config
+                .get_or_insert_with(ComponentConfig::new)
+                .set(BASE_PERIOD_NS_KEY, period);
+        }
+        if let Some(priority) = self.priority {
+            config
+                .get_or_insert_with(ComponentConfig::new)
+                .set(PRIORITY_KEY, priority);
+        }
+        if let Some(cores) = &self.core_affinity {
+            let cores: Vec<String> = cores.iter().map(usize::to_string).collect();
+            config
+                .get_or_insert_with(ComponentConfig::new)
+                .set(CORE_AFFINITY_KEY, cores.join(","));
+        }
+        for (mutation, role) in self.permissions.iter().flatten() {
+            config.get_or_insert_with(ComponentConfig::new).set(
+                &format!("{}{}", PERMISSION_KEY_PREFIX, mutation),
+                role.as_str().to_string(),
+            );
+        }
+        config
+    }
+
     #[allow(dead_code)]
     pub fn get_param<T: From<Value>>(&self, key: &str) -> Option<T> {
         let pc = self.config.as_ref()?;
@@ -277,6 +553,159 @@ pub struct Cnx {
 
     /// Tells Copper if it needs to log the messages.
     pub store: Option<bool>,
+
+    /// Optional declarative mapping applied to the messages flowing on this connection.
+    /// If set, Copper inserts a generated adapter task between src and dst.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transform: Option<CnxTransform>,
+
+    /// The data on this connection is stale when the source produced nothing for longer than this.
+    /// A stale connection has its health flipped to Degraded, see CuEdgeStats.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_ns: Option<u64>,
+
+    /// The data delivered to dst must be younger than this, see deadline_policy for what happens when it is not.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline_ms: Option<u64>,
+
+    /// What the runtime does when the data delivered to dst is older than deadline_ms, Warn by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline_policy: Option<DeadlinePolicy>,
+
+    /// How hard the transport tries to deliver the messages, Reliable by default.
+    /// The tasks of a process always get every message, it is for the bridges to map onto their transport.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reliability: Option<Reliability>,
+
+    /// The last message of the source is retained for the observers joining later, like a latched ROS topic,
+    /// ie. for a map or a calibration. See cu29::latch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latched: Option<bool>,
+}
+
+impl Cnx {
+    /// Friendly name of the source task.
+    pub fn get_src(&self) -> &str {
+        &self.src
+    }
+
+    /// Friendly name of the destination task.
+    pub fn get_dst(&self) -> &str {
+        &self.dst
+    }
+
+    /// The deadline of the data on this connection.
+    pub fn get_deadline(&self) -> Option<CuDuration> {
+        self.deadline_ms.map(|ms| CuDuration(ms * 1_000_000))
+    }
+}
+
+/// What the runtime does when the data delivered on a connection is older than its deadline.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlinePolicy {
+    /// Logs the miss and delivers the data anyway.
+    #[default]
+    Warn,
+    /// Does not deliver the data: the process call of the destination errors out and the monitor decides.
+    Trip,
+}
+
+/// The delivery guarantee of a connection, mapped by the bridges onto the QoS of their transport.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reliability {
+    /// Every message is delivered, retransmitted if needed.
+    #[default]
+    Reliable,
+    /// A message can be lost, a newer one is better than a late one.
+    BestEffort,
+}
+
+/// Prefix of the type names of the adapter tasks generated for the connection transforms.
+pub const ADAPTER_TYPE_PREFIX: &str = "CuAdapter_";
+
+/// A simple declarative mapping between 2 numeric messages, specified on a connection.
+/// The output value is computed as: `output = (input.field * unit_factor * scale) + offset`.
+/// ie. for converting a wheel RPM to rad/s: `transform: (unit: ("rpm", "rad/s"))`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CnxTransform {
+    /// Path of the field to select from the source payload (ie. "rpm" or "pose.x").
+    /// If None, the payload itself is used as a value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+
+    /// Multiplicative factor applied to the value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+
+    /// Offset added to the value after the scaling.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+
+    /// Unit conversion as (from, to), for example ("rpm", "rad/s").
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<(String, String)>,
+
+    /// Message type received by the destination task. If None, it is the same as the source message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+}
+
+impl CnxTransform {
+    /// The global multiplicative factor (unit conversion and scale) for this transform.
+    pub fn factor(&self) -> CuResult<f64> {
+        let unit_factor = match &self.unit {
+            Some((from, to)) => unit_conversion_factor(from, to)?,
+            None => 1.0,
+        };
+        Ok(unit_factor * self.scale.unwrap_or(1.0))
+    }
+}
+
+// Known units as (name, dimension, factor to the SI unit of this dimension).
+const KNOWN_UNITS: &[(&str, &str, f64)] = &[
+    ("m", "length", 1.0),
+    ("cm", "length", 0.01),
+    ("mm", "length", 0.001),
+    ("km", "length", 1000.0),
+    ("in", "length", 0.0254),
+    ("ft", "length", 0.3048),
+    ("rad", "angle", 1.0),
+    ("deg", "angle", std::f64::consts::PI / 180.0),
+    ("rev", "angle", 2.0 * std::f64::consts::PI),
+    ("s", "time", 1.0),
+    ("ms", "time", 1e-3),
+    ("us", "time", 1e-6),
+    ("ns", "time", 1e-9),
+    ("rad/s", "angular_velocity", 1.0),
+    ("deg/s", "angular_velocity", std::f64::consts::PI / 180.0),
+    ("rpm", "angular_velocity", 2.0 * std::f64::consts::PI / 60.0),
+    ("rps", "angular_velocity", 2.0 * std::f64::consts::PI),
+    ("m/s", "velocity", 1.0),
+    ("km/h", "velocity", 1.0 / 3.6),
+    ("mph", "velocity", 0.44704),
+    ("m/s^2", "acceleration", 1.0),
+    ("g", "acceleration", 9.80665),
+    ("Hz", "frequency", 1.0),
+    ("kHz", "frequency", 1e3),
+];
+
+/// Computes the multiplicative factor to convert a value from one unit to another of the same dimension.
+pub fn unit_conversion_factor(from: &str, to: &str) -> CuResult<f64> {
+    let lookup = |name: &str| {
+        KNOWN_UNITS
+            .iter()
+            .find(|(n, _, _)| *n == name)
+            .ok_or_else(|| CuError::from(format!("Unknown unit \"{}\"", name)))
+    };
+    let (_, from_dim, from_factor) = lookup(from)?;
+    let (_, to_dim, to_factor) = lookup(to)?;
+    if from_dim != to_dim {
+        return Err(CuError::from(format!(
+            "Cannot convert \"{}\" ({}) to \"{}\" ({})",
+            from, from_dim, to, to_dim
+        )));
+    }
+    Ok(from_factor / to_factor)
 }
 
 /// CuConfig is the programmatic representation of the configuration graph.
@@ -286,6 +715,11 @@ pub struct CuConfig {
     // This is not what is directly serialized, see the custom serialization below.
     pub graph: StableDiGraph<Node, Cnx, NodeId>,
     monitor: Option<MonitorConfig>,
+    runtime: Option<RuntimeConfig>,
+    faults: Option<FaultsConfig>,
+    logging: Option<LoggingConfig>,
+    missions: Vec<MissionConfig>,
+    tests: Vec<ConfigAssertion>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -308,12 +742,263 @@ impl MonitorConfig {
     }
 }
 
+/// How the runtime waits for the start of the next iteration of the copper loop.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepStrategy {
+    /// Spins on the clock: the lowest jitter but it burns a whole core.
+    BusyWait,
+    /// Sleeps until an absolute deadline on the monotonic clock (clock_nanosleep with TIMER_ABSTIME).
+    #[default]
+    ClockNanosleep,
+    /// Blocks on a periodic timerfd, Linux only.
+    TimerFd,
+}
+
+/// What the runtime does when an iteration takes so long that it misses the deadlines of the next ones.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverrunPolicy {
+    /// Skips the missed iterations, the loop resumes on its original schedule.
+    #[default]
+    Skip,
+    /// Runs the missed iterations back to back, up to max_iterations, and skips the others.
+    CatchUp { max_iterations: u32 },
+    /// Shifts the schedule by the delay, the next iteration starts a period after the late one.
+    Stretch,
+}
+
+/// What the runtime does when all its copper lists are in flight, ie. after iterations abandoned on errors.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExhaustionPolicy {
+    /// The iteration returns an error.
+    #[default]
+    Error,
+    /// The oldest copper list is discarded without being logged and its slot reused.
+    DropOldest,
+    /// The oldest copper list is written to the log as it is, right away in the iteration, and its slot reused.
+    LogOldest,
+    /// Waiting for a copper list to be released, rejected when the config is read: the log is written on the thread
+    /// of the runtime at the end of the iterations, so nothing would release a copper list during the wait.
+    Block,
+}
+
+/// The number of copper lists of the runtime if the runtime section does not set it.
+pub const DEFAULT_COPPERLISTS: usize = 10;
+
+/// The timing of the copper loop.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct RuntimeConfig {
+    /// Target period of an iteration of the loop. If not set, the loop runs as fast as it can.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period_ns: Option<u64>,
+    #[serde(default)]
+    pub sleep_strategy: SleepStrategy,
+    #[serde(default)]
+    pub overrun_policy: OverrunPolicy,
+    #[serde(default)]
+    pub exhaustion_policy: ExhaustionPolicy,
+    /// The number of copper lists the runtime allocates, DEFAULT_COPPERLISTS if not set: more for the pipelines
+    /// with a high rate or a slow log storage, fewer for the small targets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copperlists: Option<usize>,
+    /// If set, the runtime logs the jitter statistics of the loop with this period.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_period_s: Option<u64>,
+    /// Size in bytes of each of the 2 buffers the copper lists are staged in before being written to the log.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_staging_size: Option<usize>,
+    /// If set, the runtime publishes the timing of the tasks and the usage of the pools in this file, ie. in
+    /// /dev/shm, for cu29-top and the external profilers. See cu29::metrics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics_path: Option<String>,
+    /// If true, run() runs the self tests of the tasks after starting them and does not enter the loop if one of
+    /// them fails. See cu29::selftest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_test: Option<bool>,
+    /// If set, the runtime compares the robot clock to the wall clock and logs its drifts and steps, see
+    /// cu29::clockcheck.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_check: Option<ClockCheckConfig>,
+    /// A gap between 2 iterations larger than this is a suspend of the machine: run() restarts the tasks before
+    /// resuming the loop. 5 s if not set, see cu29::suspend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspend_threshold_ms: Option<u64>,
+    /// If true, the application watches its config file and applies the parameters, the pacing and the logging
+    /// section on save, for development. See cu29::watch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watch_config: Option<bool>,
+    /// If true, the memory allocated by the tasks and the pool handles they hold are charged to them, in their
+    /// statistics, the metrics and the summary of the log. See cu29::memory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_attribution: Option<bool>,
+    /// The robot the application runs on in a fleet: it prefixes the channels of its logs and live streams, see
+    /// cu29::namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub robot_id: Option<String>,
+    /// The file persisting the last run, so the next one knows its counter and its previous run, see
+    /// cu29::lineage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_file: Option<String>,
+    /// The number of worker threads running the independent branches of the graph in parallel, the tasks run one
+    /// after the other on the thread of the runtime if not set or 0. See cu29::parallel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_workers: Option<usize>,
+    /// The setup of the process for hard deadlines on a PREEMPT_RT kernel, see cu29::realtime.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub realtime: Option<RealtimeConfig>,
+    /// The time a config installed in the field has to start all the tasks before the previous one is restored,
+    /// 60 s if not set. See cu29::upgrade.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upgrade_timeout_s: Option<u64>,
+}
+
+/// The scheduling policy of the real time thread of the runtime.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RealtimePolicy {
+    /// SCHED_FIFO: the thread runs until it blocks or a thread of a higher priority is ready.
+    #[default]
+    Fifo,
+    /// SCHED_RR: like Fifo, but the threads of the same priority share the processor in time slices.
+    RoundRobin,
+}
+
+/// The real time setup of the process, the missing fields take their default values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RealtimeConfig {
+    /// The real time priority of the thread of the runtime, from 1 to 99.
+    pub priority: i32,
+    pub policy: RealtimePolicy,
+    /// Locks the memory of the process, present and future, so it is never paged out.
+    pub lock_memory: bool,
+    /// Faults in the space preallocated for the log so writing it does not fault.
+    pub prefault_log: bool,
+}
+
+impl Default for RealtimeConfig {
+    fn default() -> Self {
+        RealtimeConfig {
+            priority: 80,
+            policy: RealtimePolicy::Fifo,
+            lock_memory: true,
+            prefault_log: true,
+        }
+    }
+}
+
+/// The comparison of the robot clock to the wall clock, the missing fields take their default values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClockCheckConfig {
+    /// How often the clocks are compared.
+    pub period_ms: u64,
+    /// A change of the offset between the clocks larger than this between 2 comparisons is a step, ie. an NTP
+    /// adjustment or a suspend of the machine.
+    pub max_step_ms: u64,
+    /// The largest drift of the robot clock from the wall clock, in parts per million, before it is reported.
+    pub max_drift_ppm: f64,
+}
+
+impl Default for ClockCheckConfig {
+    fn default() -> Self {
+        ClockCheckConfig {
+            period_ms: 1000,
+            max_step_ms: 100,
+            max_drift_ppm: 200.0,
+        }
+    }
+}
+
+/// Faults the runtime injects to test the error policies and the safety behaviors, see cu29::faults.
+/// The probabilities are between 0 and 1, the same seed injects the same faults on the same run.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct FaultsConfig {
+    #[serde(default)]
+    pub seed: u64,
+    /// Messages from src not delivered to dst.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drops: Vec<DropFault>,
+    /// Process calls of a task delayed, ie. to make the loop overrun.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delays: Vec<DelayFault>,
+    /// Process calls of a task replaced by an error, ie. a driver failing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorFault>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct DropFault {
+    pub src: String,
+    pub dst: String,
+    pub probability: f64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct DelayFault {
+    pub task: String,
+    pub probability: f64,
+    pub delay_us: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct ErrorFault {
+    pub task: String,
+    pub probability: f64,
+}
+
+/// What the logger writes besides the copper lists.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct LoggingConfig {
+    /// Downsampled copies of the outputs of tasks, see cu29::derived.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub derived: Vec<DerivedChannelConfig>,
+}
+
+/// A copy of the output of a task logged at a lower rate, ie. a 10 Hz copy of a 1 kHz IMU for a dashboard.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct DerivedChannelConfig {
+    pub task: String,
+    pub rate_hz: f64,
+    /// The name the exports select the channel with, ie. "telemetry".
+    pub tag: String,
+}
+
+/// A mode of the application running a subset of the tasks of the graph, ie. "teleop" or "autonomous", see
+/// cu29::mission.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct MissionConfig {
+    pub id: String,
+    /// The tasks processed in this mission, the tasks in no mission are processed in all of them.
+    pub tasks: Vec<String>,
+}
+
+/// An assertion on the structure of the config, checked by `cu29-validate` and when the runtime is generated,
+/// see cu29::lint::check_assertions. Every field set is checked.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct ConfigAssertion {
+    /// The tasks are connected in this order, directly or through other tasks, ie. ["cam", "detector", "planner"].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<Vec<String>>,
+    /// The task runs at least this often, ie. ("control", "1ms"), with a unit among ns, us, ms and s.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_period: Option<(String, String)>,
+}
+
 /// The config is a list of tasks and their connections.
 #[derive(Serialize, Deserialize, Default)]
 struct CuConfigRepresentation {
     tasks: Vec<Node>,
     cnx: Vec<Cnx>,
     monitor: Option<MonitorConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runtime: Option<RuntimeConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    faults: Option<FaultsConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logging: Option<LoggingConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    missions: Vec<MissionConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tests: Vec<ConfigAssertion>,
 }
 
 impl<'de> Deserialize<'de> for CuConfig {
@@ -322,34 +1007,86 @@ impl<'de> Deserialize<'de> for CuConfig {
     where
         D: Deserializer<'de>,
     {
-        let representation = CuConfigRepresentation::deserialize(deserializer)
-            .expect("Failed to deserialize config");
+        let representation = CuConfigRepresentation::deserialize(deserializer)?;
 
         let mut cuconfig = CuConfig::default();
         for task in representation.tasks {
+            if cuconfig.graph.node_weights().any(|node| node.id == task.id) {
+                return Err(D::Error::custom(format!(
+                    "Duplicate task id '{}'.",
+                    task.id
+                )));
+            }
             cuconfig.add_node(task);
         }
 
+        let ids: Vec<String> = cuconfig
+            .graph
+            .node_weights()
+            .map(|n| n.id.clone())
+            .collect();
+        // No node is removed while building the graph: the node ids are the positions in the list.
+        let find = |id: &str, end: &str| {
+            ids.iter().position(|node_id| node_id == id).ok_or_else(|| {
+                let suggestion = closest_match(id, ids.iter().map(String::as_str))
+                    .map(|closest| format!(" Did you mean '{}'?", closest))
+                    .unwrap_or_default();
+                D::Error::custom(format!(
+                    "The {} '{}' of a connection is not a declared task.{}",
+                    end, id, suggestion
+                ))
+            })
+        };
         for c in representation.cnx {
-            let src = cuconfig
-                .graph
-                .node_indices()
-                .find(|i| cuconfig.graph[*i].id == c.src)
-                .expect("Source node not found");
-            let dst = cuconfig
-                .graph
-                .node_indices()
-                .find(|i| cuconfig.graph[*i].id == c.dst)
-                .expect(format!("Destination {} node not found", c.dst).as_str());
-            cuconfig.connect_ext(
-                src.index() as NodeId,
-                dst.index() as NodeId,
-                &c.msg,
-                c.batch,
-                c.store,
-            );
+            let src = find(&c.src, "source")?;
+            let dst = find(&c.dst, "destination")?;
+            cuconfig.connect_ext(src as NodeId, dst as NodeId, &c.msg, c.batch, c.store);
+            let edge = cuconfig.graph.edge_indices().next_back().unwrap();
+            cuconfig.graph[edge].transform = c.transform;
+            cuconfig.graph[edge].max_age_ns = c.max_age_ns;
+            cuconfig.graph[edge].deadline_ms = c.deadline_ms;
+            cuconfig.graph[edge].deadline_policy = c.deadline_policy;
+            cuconfig.graph[edge].reliability = c.reliability;
+            cuconfig.graph[edge].latched = c.latched;
+        }
+        for (index, mission) in representation.missions.iter().enumerate() {
+            if representation.missions[..index]
+                .iter()
+                .any(|other| other.id == mission.id)
+            {
+                return Err(D::Error::custom(format!(
+                    "Duplicate mission id '{}'.",
+                    mission.id
+                )));
+            }
+            for task in &mission.tasks {
+                if !ids.contains(task) {
+                    let suggestion = closest_match(task, ids.iter().map(String::as_str))
+                        .map(|closest| format!(" Did you mean '{}'?", closest))
+                        .unwrap_or_default();
+                    return Err(D::Error::custom(format!(
+                        "The task '{}' of the mission '{}' is not a declared task.{}",
+                        task, mission.id, suggestion
+                    )));
+                }
+            }
+        }
+        if representation
+            .runtime
+            .as_ref()
+            .is_some_and(|runtime| runtime.exhaustion_policy == ExhaustionPolicy::Block)
+        {
+            return Err(D::Error::custom(
+                "The Block exhaustion_policy is not supported: the copper lists are logged on the thread of the \
+                 runtime, nothing would release one while it waits. Use Error, DropOldest or LogOldest.",
+            ));
         }
         cuconfig.monitor = representation.monitor;
+        cuconfig.runtime = representation.runtime;
+        cuconfig.faults = representation.faults;
+        cuconfig.logging = representation.logging;
+        cuconfig.missions = representation.missions;
+        cuconfig.tests = representation.tests;
         Ok(cuconfig)
     }
 }
@@ -376,6 +1113,11 @@ impl Serialize for CuConfig {
             tasks,
             cnx,
             monitor: self.monitor.clone(),
+            runtime: self.runtime.clone(),
+            faults: self.faults.clone(),
+            logging: self.logging.clone(),
+            missions: self.missions.clone(),
+            tests: self.tests.clone(),
         }
         .serialize(serializer)
     }
@@ -386,6 +1128,11 @@ impl Default for CuConfig {
         CuConfig {
             graph: StableDiGraph::new(),
             monitor: None,
+            runtime: None,
+            faults: None,
+            logging: None,
+            missions: Vec::new(),
+            tests: Vec::new(),
         }
     }
 }
@@ -463,6 +1210,12 @@ impl CuConfig {
                 msg: msg_type.to_string(),
                 batch,
                 store,
+                transform: None,
+                max_age_ns: None,
+                deadline_ms: None,
+                deadline_policy: None,
+                reliability: None,
+                latched: None,
             },
         );
     }
@@ -489,9 +1242,14 @@ impl CuConfig {
     }
 
     pub fn deserialize_ron(ron: &str) -> Self {
+        Self::try_deserialize_ron(ron).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Parses a configuration, the error gives the position of the problem in the RON.
+    pub fn try_deserialize_ron(ron: &str) -> CuResult<Self> {
         Self::get_options()
             .from_str(ron)
-            .expect("Syntax Error in config")
+            .map_err(|e| format!("Error in config at {}", e).into())
     }
 
     /// Render the configuration graph in the dot format.
@@ -570,6 +1328,245 @@ impl CuConfig {
     pub fn get_monitor_config(&self) -> Option<&MonitorConfig> {
         self.monitor.as_ref()
     }
+
+    /// The timing of the copper loop if any.
+    pub fn get_runtime_config(&self) -> Option<&RuntimeConfig> {
+        self.runtime.as_ref()
+    }
+
+    /// The robot the application runs on in a fleet, see cu29::namespace.
+    pub fn robot_id(&self) -> Option<&str> {
+        self.runtime.as_ref()?.robot_id.as_deref()
+    }
+
+    pub fn set_robot_id(&mut self, robot_id: &str) {
+        self.runtime
+            .get_or_insert_with(RuntimeConfig::default)
+            .robot_id = Some(robot_id.to_string());
+    }
+
+    /// The faults to inject if any, see cu29::faults.
+    pub fn get_faults_config(&self) -> Option<&FaultsConfig> {
+        self.faults.as_ref()
+    }
+
+    /// The missions of the application, the first one is the mission it starts in, see cu29::mission.
+    pub fn get_missions(&self) -> &[MissionConfig] {
+        &self.missions
+    }
+
+    /// The assertions of the tests section on the structure of the config.
+    pub fn get_assertions(&self) -> &[ConfigAssertion] {
+        &self.tests
+    }
+
+    /// What the logger writes besides the copper lists if any.
+    pub fn get_logging_config(&self) -> Option<&LoggingConfig> {
+        self.logging.as_ref()
+    }
+
+    /// Replaces every connection declaring a transform by an adapter task and 2 plain connections.
+    /// The adapters are added after all the declared tasks so the declared task indices are unchanged.
+    /// The adapter type is generated by the copper_runtime macro, see ADAPTER_TYPE_PREFIX.
+    pub fn expand_transforms(&mut self) -> CuResult<()> {
+        let edges: Vec<EdgeIndex<NodeId>> = self
+            .graph
+            .edge_indices()
+            .filter(|e| self.graph[*e].transform.is_some())
+            .collect();
+
+        for edge in edges {
+            let (src, dst) = self.graph.edge_endpoints(edge).unwrap();
+            let cnx = self.graph.remove_edge(edge).unwrap();
+            let transform = cnx.transform.clone().unwrap();
+            let factor = transform.factor().map_err(|e| {
+                e.add_cause(format!("Invalid transform from {} to {}", cnx.src, cnx.dst).as_str())
+            })?;
+            let output_msg = transform.msg.clone().unwrap_or_else(|| cnx.msg.clone());
+            // The adapter converts the value with `as` casts through a f64.
+            let casts =
+                std::iter::once(&output_msg).chain(transform.field.is_none().then_some(&cnx.msg));
+            for msg in casts {
+                if !is_numeric_msg(msg) {
+                    return Err(format!(
+                        "Invalid transform from {} to {}: the message type {} is not a numeric primitive type.",
+                        cnx.src, cnx.dst, msg
+                    )
+                    .into());
+                }
+            }
+
+            let id = adapter_id(edge.index(), &cnx.src, &cnx.dst);
+            if self.get_all_nodes().iter().any(|node| node.get_id() == id) {
+                return Err(format!(
+                    "The id {} of the adapter of the transform from {} to {} is already used by a task.",
+                    id, cnx.src, cnx.dst
+                )
+                .into());
+            }
+            let mut adapter = Node::new(
+                id.as_str(),
+                adapter_type_name(edge.index(), &cnx.src, &cnx.dst).as_str(),
+            );
+            adapter.set_param::<Value>("factor", factor.into());
+            adapter.set_param::<Value>("offset", transform.offset.unwrap_or(0.0).into());
+            adapter.set_param::<Value>("input_msg", cnx.msg.clone().into());
+            adapter.set_param::<Value>("output_msg", output_msg.clone().into());
+            if let Some(field) = transform.field {
+                adapter.set_param::<Value>("field", field.into());
+            }
+            let adapter_id = self.add_node(adapter);
+
+            self.connect_ext(
+                src.index() as NodeId,
+                adapter_id,
+                &cnx.msg,
+                cnx.batch,
+                cnx.store,
+            );
+            // The message retained is the one of the source.
+            let edge = self.graph.find_edge(src, adapter_id.into()).unwrap();
+            self.graph[edge].latched = cnx.latched;
+            self.connect_ext(
+                adapter_id,
+                dst.index() as NodeId,
+                &output_msg,
+                cnx.batch,
+                cnx.store,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The id of the adapter task of the transform of the connection cnx_id between src and dst.
+pub fn adapter_id(cnx_id: usize, src: &str, dst: &str) -> String {
+    format!("{}_to_{}_{}", src, dst, cnx_id)
+}
+
+// The message types the adapters can convert with an `as` cast.
+fn is_numeric_msg(msg: &str) -> bool {
+    const NUMERIC: &[&str] = &[
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+        "f32", "f64",
+    ];
+    NUMERIC.contains(&msg.trim())
+}
+
+/// The name of the adapter task type generated for the transform of the connection cnx_id between src and dst,
+/// unique even with several connections between the same tasks.
+pub fn adapter_type_name(cnx_id: usize, src: &str, dst: &str) -> String {
+    let sanitize = |id: &str| {
+        id.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>()
+    };
+    format!(
+        "{}{}_{}_{}",
+        ADAPTER_TYPE_PREFIX,
+        cnx_id,
+        sanitize(src),
+        sanitize(dst)
+    )
+}
+
+/// Parameters of the nodes replacing the ones of the config file, for example to try other gains of a
+/// controller without editing the config ("what-if" runs). Only the parameters can change: the structure of
+/// the task graph is compiled in the application.
+/// It also gives the robot id of an application deployed with the same config on every robot of a fleet, and
+/// whether the application is created for a dry run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CuParamOverrides(Vec<(String, String, Value)>, Option<String>, bool);
+
+impl CuParamOverrides {
+    pub fn new() -> Self {
+        CuParamOverrides(Vec::new(), None, false)
+    }
+
+    /// Creates the tasks for a dry run, ie. `--dry-run` on a CI or a bench machine: every task finds DRY_RUN_KEY
+    /// set to true in its config, the drivers check their config without opening their hardware.
+    pub fn dry_run(mut self) -> Self {
+        self.2 = true;
+        self
+    }
+
+    /// Replaces robot_id of the runtime section, see cu29::namespace.
+    pub fn robot_id(mut self, robot_id: &str) -> Self {
+        self.1 = Some(robot_id.to_string());
+        self
+    }
+
+    /// Overrides the parameter key of the node with the friendly name node.
+    pub fn set<T: Into<Value>>(mut self, node: &str, key: &str, value: T) -> Self {
+        self.0
+            .push((node.to_string(), key.to_string(), value.into()));
+        self
+    }
+
+    /// Parses an override from the command line: `node.key=value` where value is in RON, ie. `pid.kp=2.5`.
+    pub fn parse(&mut self, param: &str) -> CuResult<()> {
+        let (path, value) = param.split_once('=').ok_or_else(|| {
+            CuError::from(format!(
+                "Invalid override '{}', expected node.key=value.",
+                param
+            ))
+        })?;
+        let (node, key) = path.trim().split_once('.').ok_or_else(|| {
+            CuError::from(format!(
+                "Invalid override '{}', expected node.key=value.",
+                param
+            ))
+        })?;
+        let value: RonValue = ron::from_str(value.trim()).map_err(|e| {
+            CuError::from(format!("Invalid value in the override '{}'", param))
+                .add_cause(&e.to_string())
+        })?;
+        self.0
+            .push((node.to_string(), key.to_string(), Value(value)));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty() && self.1.is_none() && !self.2
+    }
+
+    /// Sets the overridden parameters in the config, the nodes must exist.
+    pub fn apply(&self, config: &mut CuConfig) -> CuResult<()> {
+        for (node_id, key, value) in &self.0 {
+            let index = config
+                .graph
+                .node_indices()
+                .find(|index| config.graph[*index].id == *node_id)
+                .ok_or_else(|| {
+                    let ids: Vec<String> =
+                        config.graph.node_weights().map(|n| n.get_id()).collect();
+                    let hint = closest_match(node_id, ids.iter().map(String::as_str))
+                        .map(|m| format!(" Did you mean '{}'?", m))
+                        .unwrap_or_default();
+                    CuError::from(format!(
+                        "Cannot override {}.{}: there is no node '{}' in the config.{}",
+                        node_id, key, node_id, hint
+                    ))
+                })?;
+            debug!(
+                "Config: {}.{} overridden to {}.",
+                node_id.as_str(),
+                key.as_str(),
+                value.to_string()
+            );
+            config.graph[index].set_param(key, value.clone());
+        }
+        if let Some(robot_id) = &self.1 {
+            config.set_robot_id(robot_id);
+        }
+        if self.2 {
+            debug!("Config: dry run, the drivers do not open their hardware.");
+            for node in config.graph.node_weights_mut() {
+                node.set_param(DRY_RUN_KEY, true);
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Read a copper configuration from a file.
@@ -581,7 +1578,14 @@ pub fn read_configuration(config_filename: &str) -> CuResult<CuConfig> {
         ))
         .add_cause(e.to_string().as_str())
     })?;
-    Ok(CuConfig::deserialize_ron(&config_content))
+    read_configuration_str(&config_content)
+}
+
+/// Same as read_configuration for a config written in RON, ie. inline in a test.
+pub fn read_configuration_str(config_content: &str) -> CuResult<CuConfig> {
+    let mut config = CuConfig::try_deserialize_ron(config_content)?;
+    config.expand_transforms()?;
+    Ok(config)
 }
 
 // tests
@@ -621,6 +1625,232 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_optional_node() {
+        let txt =
+            r#"( tasks: [(id: "a", type: "b", optional: true), (id: "c", type: "d")], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        assert!(config.get_node(0).unwrap().is_optional());
+        assert!(!config.get_node(1).unwrap().is_optional());
+    }
+
+    #[test]
+    fn test_thread_scheduling_in_task_config() {
+        let txt =
+            r#"( tasks: [(id: "pid", type: "b", priority: 80, core_affinity: [2, 3])], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        let node = config.get_node(0).unwrap();
+        assert_eq!(node.get_priority(), Some(80));
+        assert_eq!(node.get_core_affinity(), &[2, 3]);
+        let task_config = node.get_task_config().unwrap();
+        assert_eq!(task_config.get::<i32>(PRIORITY_KEY), Some(80));
+        assert_eq!(
+            task_config.get::<String>(CORE_AFFINITY_KEY),
+            Some("2,3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_on_error() {
+        let txt = r#"( tasks: [(id: "a", type: "b", on_error: Retry(3)), (id: "c", type: "d", on_error: Restart), (id: "e", type: "f")], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        assert_eq!(
+            config.get_node(0).unwrap().get_on_error(),
+            Some(CuErrorPolicy::Retry(3))
+        );
+        assert_eq!(
+            config.get_node(1).unwrap().get_on_error(),
+            Some(CuErrorPolicy::Restart)
+        );
+        assert_eq!(config.get_node(2).unwrap().get_on_error(), None);
+    }
+
+    #[test]
+    fn test_base_period_in_task_config() {
+        let txt = r#"( tasks: [(id: "a", type: "b", base_period_ns: 10000000)], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        let node = config.get_node(0).unwrap();
+        assert_eq!(node.get_base_period_ns(), Some(10_000_000));
+        assert!(node.get_instance_config().is_none());
+        let task_config = node.get_task_config().unwrap();
+        assert_eq!(
+            task_config.try_get::<u64>(BASE_PERIOD_NS_KEY).unwrap(),
+            Some(10_000_000)
+        );
+
+        let mut config = ComponentConfig::new();
+        config.set(BASE_PERIOD_NS_KEY, -1);
+        let error = config.try_get::<u64>(BASE_PERIOD_NS_KEY).unwrap_err();
+        assert!(error.to_string().contains(BASE_PERIOD_NS_KEY));
+    }
+
+    #[test]
+    fn test_permissions_in_task_config() {
+        let txt = r#"( tasks: [(id: "a", type: "b", permissions: {"pause": operator, "gain": engineer})], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        let node = config.get_node(0).unwrap();
+        assert_eq!(node.get_permission("pause"), Some(CuRole::Operator));
+        assert_eq!(node.get_permission("reset"), None);
+        let task_config = node.get_task_config().unwrap();
+        assert_eq!(
+            task_config.get::<String>("permission.gain"),
+            Some("engineer".to_string())
+        );
+        let deserialized = CuConfig::deserialize_ron(&config.serialize_ron());
+        assert_eq!(
+            deserialized.get_node(0).unwrap().get_permission("gain"),
+            Some(CuRole::Engineer)
+        );
+    }
+
+    #[test]
+    fn test_param_overrides() {
+        let txt = r#"( tasks: [(id: "pid", type: "b", config: {"kp": 1.0, "ki": 0.1})], cnx: [] )"#;
+        let mut config = CuConfig::deserialize_ron(txt);
+        let mut overrides = CuParamOverrides::new().set("pid", "ki", 0.2);
+        overrides.parse("pid.kp=2.5").unwrap();
+        overrides.parse("pid.mode=\"fast\"").unwrap();
+        overrides.apply(&mut config).unwrap();
+        let node = config.get_node(0).unwrap();
+        assert_eq!(node.get_param::<f64>("kp"), Some(2.5));
+        assert_eq!(node.get_param::<f64>("ki"), Some(0.2));
+        assert_eq!(node.get_param::<String>("mode"), Some("fast".to_string()));
+
+        assert!(overrides.parse("pid.kp").is_err());
+        assert!(overrides.parse("kp=1.0").is_err());
+        assert!(overrides.parse("pid.kp=[1.0").is_err());
+        let error = CuParamOverrides::new()
+            .set("pdi", "kp", 1.0)
+            .apply(&mut config)
+            .unwrap_err();
+        assert!(error.to_string().contains("Did you mean 'pid'?"));
+
+        assert_eq!(config.robot_id(), None);
+        CuParamOverrides::new()
+            .robot_id("r2")
+            .apply(&mut config)
+            .unwrap();
+        assert_eq!(config.robot_id(), Some("r2"));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let txt = r#"( tasks: [(id: "imu", type: "b"), (id: "pid", type: "d", config: {"kp": 1.0})], cnx: [] )"#;
+        let mut config = CuConfig::deserialize_ron(txt);
+        assert!(!is_dry_run(
+            config.get_node(1).unwrap().get_task_config().as_ref()
+        ));
+        CuParamOverrides::new()
+            .dry_run()
+            .apply(&mut config)
+            .unwrap();
+        for node in config.get_all_nodes() {
+            assert!(is_dry_run(node.get_task_config().as_ref()));
+        }
+        assert_eq!(
+            config.get_node(1).unwrap().get_param::<f64>("kp"),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_scratch_size() {
+        let txt = r#"( tasks: [(id: "a", type: "b", scratch_size: 65536), (id: "c", type: "d")], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        assert_eq!(config.get_node(0).unwrap().get_scratch_size(), 65536);
+        assert_eq!(config.get_node(1).unwrap().get_scratch_size(), 0);
+    }
+
+    #[test]
+    fn test_clock_domain() {
+        let txt = r#"( tasks: [(id: "a", type: "b", clock_domain: "camera"), (id: "c", type: "d")], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        assert_eq!(
+            config.get_node(0).unwrap().get_clock_domain(),
+            Some("camera")
+        );
+        assert_eq!(config.get_node(1).unwrap().get_clock_domain(), None);
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        let factor = unit_conversion_factor("rpm", "rad/s").unwrap();
+        assert!((factor - 0.10471975511965977).abs() < 1e-12);
+        assert_eq!(unit_conversion_factor("km", "m").unwrap(), 1000.0);
+        assert!(unit_conversion_factor("m", "rad").is_err());
+        assert!(unit_conversion_factor("furlong", "m").is_err());
+    }
+
+    #[test]
+    fn test_expand_transforms() {
+        let txt = r#"(
+            tasks: [(id: "enc", type: "Encoder"), (id: "pid", type: "Pid")],
+            cnx: [(src: "enc", dst: "pid", msg: "i32", transform: (field: "rpm", scale: 2.0, offset: 1.0, unit: ("rpm", "rps"), msg: "f32"))],
+        )"#;
+        let mut config = CuConfig::deserialize_ron(txt);
+        assert_eq!(config.graph.edge_count(), 1);
+        config.expand_transforms().unwrap();
+        assert_eq!(config.graph.node_count(), 3);
+        assert_eq!(config.graph.edge_count(), 2);
+
+        let adapter = config.get_node(2).unwrap();
+        assert_eq!(adapter.get_id(), "enc_to_pid_0");
+        assert_eq!(adapter.get_type(), "CuAdapter_0_enc_pid");
+        assert!((adapter.get_param::<f64>("factor").unwrap() - 2.0 / 60.0).abs() < 1e-12);
+        assert_eq!(adapter.get_param::<f64>("offset").unwrap(), 1.0);
+        assert_eq!(adapter.get_param::<String>("field").unwrap(), "rpm");
+
+        let input = config.get_edge_weight(config.get_dst_edges(2)[0]).unwrap();
+        assert_eq!(input.msg, "i32");
+        let output = config.get_edge_weight(config.get_src_edges(2)[0]).unwrap();
+        assert_eq!(output.msg, "f32");
+        assert!(output.transform.is_none());
+    }
+
+    #[test]
+    fn test_expand_transforms_between_same_tasks() {
+        let txt = r#"(
+            tasks: [(id: "enc", type: "Encoder"), (id: "pid", type: "Pid")],
+            cnx: [
+                (src: "enc", dst: "pid", msg: "i32", transform: (scale: 2.0)),
+                (src: "enc", dst: "pid", msg: "u8", transform: (scale: 3.0)),
+            ],
+        )"#;
+        let mut config = CuConfig::deserialize_ron(txt);
+        config.expand_transforms().unwrap();
+        let ids: Vec<String> = config.get_all_nodes().iter().map(|n| n.get_id()).collect();
+        assert_eq!(ids, vec!["enc", "pid", "enc_to_pid_0", "enc_to_pid_1"]);
+    }
+
+    #[test]
+    fn test_expand_transforms_invalid() {
+        let expand = |cnx: &str| {
+            let txt = format!(
+                r#"( tasks: [(id: "enc", type: "Encoder"), (id: "pid", type: "Pid")], cnx: [{}] )"#,
+                cnx
+            );
+            CuConfig::deserialize_ron(&txt).expand_transforms()
+        };
+        assert!(
+            expand(r#"(src: "enc", dst: "pid", msg: "i32", transform: (unit: ("rpm", "m")))"#)
+                .is_err()
+        );
+        assert!(
+            expand(r#"(src: "enc", dst: "pid", msg: "String", transform: (scale: 2.0))"#).is_err()
+        );
+        assert!(
+            expand(r#"(src: "enc", dst: "pid", msg: "i32", transform: (msg: "bool"))"#).is_err()
+        );
+        // The type of a field is unknown here, only the output is checked.
+        assert!(expand(
+            r#"(src: "enc", dst: "pid", msg: "Odom", transform: (field: "x", msg: "f32"))"#
+        )
+        .is_ok());
+        assert!(
+            expand(r#"(src: "enc", dst: "pid", msg: "Odom", transform: (field: "x"))"#).is_err()
+        );
+    }
+
     #[test]
     fn test_monitor() {
         let txt = r#"( tasks: [], cnx: [], monitor: (type: "ExampleMonitor", ) ) "#;
@@ -635,4 +1865,162 @@ mod tests {
             4.into()
         );
     }
+
+    #[test]
+    fn test_runtime() {
+        let txt = r#"( tasks: [], cnx: [] ) "#;
+        let config = CuConfig::deserialize_ron(txt);
+        assert!(config.get_runtime_config().is_none());
+
+        let txt =
+            r#"( tasks: [], cnx: [], runtime: (period_ns: 1000000, sleep_strategy: TimerFd) ) "#;
+        let config = CuConfig::deserialize_ron(txt);
+        let runtime = config.get_runtime_config().unwrap();
+        assert_eq!(runtime.period_ns, Some(1_000_000));
+        assert_eq!(runtime.sleep_strategy, SleepStrategy::TimerFd);
+        assert_eq!(runtime.overrun_policy, OverrunPolicy::Skip);
+        assert_eq!(runtime.exhaustion_policy, ExhaustionPolicy::Error);
+        assert_eq!(runtime.copperlists, None);
+        assert_eq!(runtime.report_period_s, None);
+
+        let txt = r#"( tasks: [], cnx: [], runtime: (period_ns: 1000000, overrun_policy: CatchUp(max_iterations: 3), exhaustion_policy: DropOldest, copperlists: 32) ) "#;
+        let config = CuConfig::deserialize_ron(txt);
+        assert_eq!(
+            config.get_runtime_config().unwrap().overrun_policy,
+            OverrunPolicy::CatchUp { max_iterations: 3 }
+        );
+        assert_eq!(
+            config.get_runtime_config().unwrap().exhaustion_policy,
+            ExhaustionPolicy::DropOldest
+        );
+        assert_eq!(config.get_runtime_config().unwrap().copperlists, Some(32));
+
+        let config = CuConfig::deserialize_ron(&config.serialize_ron());
+        assert_eq!(
+            config.get_runtime_config().unwrap().period_ns,
+            Some(1_000_000)
+        );
+        let error = CuConfig::try_deserialize_ron(
+            r#"( tasks: [], cnx: [], runtime: (exhaustion_policy: Block) ) "#,
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Block exhaustion_policy is not supported"));
+    }
+
+    #[test]
+    fn test_realtime_config() {
+        let txt =
+            r#"( tasks: [], cnx: [], runtime: (realtime: (priority: 90, policy: RoundRobin)) ) "#;
+        let config = CuConfig::deserialize_ron(txt);
+        let realtime = config
+            .get_runtime_config()
+            .unwrap()
+            .realtime
+            .clone()
+            .unwrap();
+        assert_eq!(realtime.priority, 90);
+        assert_eq!(realtime.policy, RealtimePolicy::RoundRobin);
+        assert!(realtime.lock_memory && realtime.prefault_log);
+        let config = CuConfig::deserialize_ron(&config.serialize_ron());
+        assert_eq!(
+            config.get_runtime_config().unwrap().realtime,
+            Some(realtime)
+        );
+    }
+
+    #[test]
+    fn test_clock_check_config() {
+        let txt = r#"( tasks: [], cnx: [], runtime: (clock_check: (max_step_ms: 50)) ) "#;
+        let config = CuConfig::deserialize_ron(txt);
+        let clock_check = config
+            .get_runtime_config()
+            .unwrap()
+            .clock_check
+            .clone()
+            .unwrap();
+        assert_eq!(clock_check.max_step_ms, 50);
+        assert_eq!(clock_check.period_ms, 1000);
+    }
+
+    #[test]
+    fn test_missions() {
+        let txt = r#"(
+            tasks: [(id: "joystick", type: "a"), (id: "planner", type: "b"), (id: "motors", type: "c")],
+            cnx: [],
+            missions: [(id: "teleop", tasks: ["joystick"]), (id: "autonomous", tasks: ["planner"])],
+        )"#;
+        let config = read_configuration_str(txt).unwrap();
+        assert_eq!(config.get_missions().len(), 2);
+        assert_eq!(config.get_missions()[1].tasks, vec!["planner".to_string()]);
+        let config = read_configuration_str(&config.serialize_ron()).unwrap();
+        assert_eq!(config.get_missions()[0].id, "teleop");
+
+        let error = read_configuration_str(&txt.replace("[\"planner\"]", "[\"planer\"]"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Did you mean 'planner'?"));
+        assert!(read_configuration_str(&txt.replace("autonomous", "teleop")).is_err());
+    }
+
+    #[test]
+    fn test_faults_config() {
+        let txt = r#"( tasks: [], cnx: [], faults: (seed: 7, delays: [(task: "a", probability: 0.5, delay_us: 100)]) ) "#;
+        let config = CuConfig::deserialize_ron(txt);
+        let faults = config.get_faults_config().unwrap();
+        assert_eq!(faults.seed, 7);
+        assert!(faults.drops.is_empty());
+        assert_eq!(faults.delays[0].delay_us, 100);
+
+        let config = CuConfig::deserialize_ron(&config.serialize_ron());
+        assert_eq!(config.get_faults_config().unwrap().delays.len(), 1);
+        assert!(CuConfig::default().get_faults_config().is_none());
+    }
+
+    #[test]
+    fn test_qos() {
+        let txt = r#"( tasks: [(id: "a", type: "a::A"), (id: "b", type: "a::B"), (id: "c", type: "a::C")],
+                       cnx: [(src: "a", dst: "b", msg: "u32", deadline_ms: 20, deadline_policy: Trip, reliability: BestEffort),
+                             (src: "a", dst: "c", msg: "u32")] ) "#;
+        let config = CuConfig::deserialize_ron(txt);
+        let fast = &config.graph[EdgeIndex::new(0)];
+        assert_eq!(fast.get_deadline(), Some(CuDuration(20_000_000)));
+        assert_eq!(fast.deadline_policy, Some(DeadlinePolicy::Trip));
+        assert_eq!(fast.reliability, Some(Reliability::BestEffort));
+        let plain = &config.graph[EdgeIndex::new(1)];
+        assert_eq!(plain.get_deadline(), None);
+        assert_eq!(plain.reliability.unwrap_or_default(), Reliability::Reliable);
+
+        let config = CuConfig::deserialize_ron(&config.serialize_ron());
+        assert_eq!(config.graph[EdgeIndex::new(0)].deadline_ms, Some(20));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let txt = r#"( tasks: [(id: "src", type: "a::Src"), (id: "sink", type: "a::Sink")],
+                      cnx: [(src: "scr", dst: "sink", msg: "i32")] )"#;
+        let error = CuConfig::try_deserialize_ron(txt).err().unwrap();
+        assert!(error.to_string().contains("'scr'"));
+        assert!(error.to_string().contains("Did you mean 'src'?"));
+
+        let txt =
+            r#"( tasks: [(id: "src", type: "a::Src"), (id: "src", type: "a::Sink")], cnx: [] )"#;
+        let error = CuConfig::try_deserialize_ron(txt).err().unwrap();
+        assert!(error.to_string().contains("Duplicate task id 'src'"));
+
+        let txt = r#"( tasks: [(id: "src" type: "a::Src")], cnx: [] )"#;
+        let error = CuConfig::try_deserialize_ron(txt).err().unwrap();
+        assert!(error.to_string().contains("1:"));
+    }
+
+    #[test]
+    fn test_closest_match() {
+        let ids = ["imu", "motor_left", "motor_right"];
+        assert_eq!(
+            closest_match("motor_lft", ids.into_iter()),
+            Some("motor_left")
+        );
+        assert_eq!(closest_match("camera", ids.into_iter()), None);
+    }
 }
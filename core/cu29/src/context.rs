@@ -0,0 +1,301 @@
+//! The context the runtime gives a task when it processes a copper list.
+//!
+
+use crate::clockdomain::CuClockDomains;
+use crate::config::{ComponentConfig, NodeId, Value};
+use crate::monitoring::{CuEdgeStats, CuHealth};
+use crate::pool::{CuPool, CuPools};
+use crate::scratch::CuScratch;
+use crate::tuning::{CuParamRequest, CuParamRequests};
+use cu29_clock::{CuDuration, OptionCuTime, RobotClock};
+use cu29_traits::{CuError, CuResult};
+use std::cell::Cell;
+use std::ops::Deref;
+
+/// What a task gets from the runtime on every process call.
+/// It dereferences to the clock so `ctx.now()` reads the time of the robot.
+///
+/// New features of the runtime are given to the tasks through here so the signatures of the task
+/// traits do not have to change every time.
+pub struct CuContext<'a> {
+    /// The clock of the runtime.
+    pub clock: &'a RobotClock,
+    /// The time measured since the previous process call of this task, None on the first one.
+    /// Use it rather than the configured period of the loop: the runtime does not guarantee it.
+    pub dt: Option<CuDuration>,
+    /// The id of the node of the task in the config.
+    pub node_id: NodeId,
+    /// The name of the node of the task in the config.
+    pub node_name: &'a str,
+    config: Option<&'a ComponentConfig>,
+    pools: Option<&'a CuPools>,
+    scratch: Option<&'a CuScratch>,
+    edges: &'a [CuEdgeStats],
+    clock_domains: Option<&'a CuClockDomains>,
+    param_requests: Option<&'a CuParamRequests>,
+    health: Cell<Option<CuHealth>>,
+}
+
+impl<'a> CuContext<'a> {
+    /// A context outside of any node, see with_node and with_pools to complete it.
+    pub fn new(clock: &'a RobotClock, dt: Option<CuDuration>) -> Self {
+        CuContext {
+            clock,
+            dt,
+            node_id: 0,
+            node_name: "",
+            config: None,
+            pools: None,
+            scratch: None,
+            edges: &[],
+            clock_domains: None,
+            param_requests: None,
+            health: Cell::new(None),
+        }
+    }
+
+    /// Sets the node the task is running as and the config it got from it.
+    pub fn with_node(
+        mut self,
+        node_id: NodeId,
+        node_name: &'a str,
+        config: Option<&'a ComponentConfig>,
+    ) -> Self {
+        self.node_id = node_id;
+        self.node_name = node_name;
+        self.config = config;
+        self
+    }
+
+    /// Sets where the pools of the tasks are allocated.
+    pub fn with_pools(mut self, pools: &'a CuPools) -> Self {
+        self.pools = Some(pools);
+        self
+    }
+
+    /// Sets the scratch arena of the task.
+    pub fn with_scratch(mut self, scratch: &'a CuScratch) -> Self {
+        self.scratch = Some(scratch);
+        self
+    }
+
+    /// Sets the liveliness of the connections of the graph, see input_age.
+    pub fn with_edges(mut self, edges: &'a [CuEdgeStats]) -> Self {
+        self.edges = edges;
+        self
+    }
+
+    /// Sets the clock domains of the tasks, see robot_tov.
+    pub fn with_clock_domains(mut self, clock_domains: &'a CuClockDomains) -> Self {
+        self.clock_domains = Some(clock_domains);
+        self
+    }
+
+    /// Sets where the changes of parameters requested by the task go, see request_param.
+    pub fn with_param_requests(mut self, param_requests: &'a CuParamRequests) -> Self {
+        self.param_requests = Some(param_requests);
+        self
+    }
+
+    /// The clock domains of the tasks, to register the conversion of a clock at runtime.
+    pub fn clock_domains(&self) -> Option<&'a CuClockDomains> {
+        self.clock_domains
+    }
+
+    /// The time of validity of a message from the task src in the robot clock, whatever the clock domain of src.
+    /// It errors if the conversion of the domain of src is not registered yet.
+    pub fn robot_tov(&self, src: &str, tov: OptionCuTime) -> CuResult<OptionCuTime> {
+        match self.clock_domains {
+            Some(clock_domains) => clock_domains.robot_tov(src, tov),
+            None => Ok(tov),
+        }
+    }
+
+    /// How old the data coming from the task src is: the time since it last produced a message.
+    /// None if src is not connected to this task or did not run yet.
+    pub fn input_age(&self, src: &str) -> Option<CuDuration> {
+        self.input(src)?.age(self.clock.now())
+    }
+
+    /// Whether the data coming from the task src is older than the max_age_ns of the connection.
+    pub fn is_input_stale(&self, src: &str) -> bool {
+        self.input(src)
+            .is_some_and(|edge| edge.is_stale(self.clock.now()))
+    }
+
+    fn input(&self, src: &str) -> Option<&CuEdgeStats> {
+        self.edges
+            .iter()
+            .find(|edge| edge.src == src && edge.dst == self.node_name)
+    }
+
+    /// The time since the previous process call, or the given default on the first one.
+    pub fn dt_or(&self, default: CuDuration) -> CuDuration {
+        self.dt.unwrap_or(default)
+    }
+
+    /// A parameter from the config of the node.
+    pub fn param<T: From<Value>>(&self, key: &str) -> Option<T> {
+        self.config?.get(key)
+    }
+
+    /// Requests a change of the parameter key of the task, applied and logged by the runtime at the end of the
+    /// copper list, see cu29::tuning.
+    pub fn request_param(&self, task: &str, key: &str, value: impl Into<Value>) -> CuResult<()> {
+        let param_requests = self.param_requests.ok_or_else(|| {
+            CuError::from(format!(
+                "The task '{}' cannot change the parameters of the other tasks.",
+                self.node_name
+            ))
+        })?;
+        param_requests.push(CuParamRequest {
+            origin: self.node_name.to_string(),
+            task: task.to_string(),
+            key: key.to_string(),
+            value: value.into(),
+        });
+        Ok(())
+    }
+
+    /// The pool `name` of this task, allocated by the runtime the first time it is asked for with `capacity`
+    /// buffers of `buffer_len` elements. The next calls give the same pool back without allocating.
+    pub fn pool<T: Clone + Default + Send + 'static>(
+        &self,
+        name: &str,
+        capacity: usize,
+        buffer_len: usize,
+    ) -> CuResult<CuPool<T>> {
+        let pools = self.pools.ok_or_else(|| {
+            CuError::from(format!(
+                "No pool allocator is available to the task '{}'.",
+                self.node_name
+            ))
+        })?;
+        pools.get_or_create(self.node_id, self.node_name, name, capacity, buffer_len)
+    }
+
+    /// The scratch arena of the task, empty at the start of every process call.
+    /// Its size is set by scratch_size in the node of the task in the config.
+    pub fn scratch(&self) -> CuResult<&'a CuScratch> {
+        self.scratch.ok_or_else(|| {
+            CuError::from(format!(
+                "No scratch arena is available to the task '{}'.",
+                self.node_name
+            ))
+        })
+    }
+
+    /// Reports the health of the task for this process call, for example Degraded when it works on stale inputs.
+    /// Without a report a successful call is Nominal, an error is always Failed.
+    pub fn report_health(&self, health: CuHealth) {
+        self.health.set(Some(health));
+    }
+
+    /// The health reported by the task during this process call, if any.
+    pub fn reported_health(&self) -> Option<CuHealth> {
+        self.health.get()
+    }
+}
+
+impl<'a> From<&'a RobotClock> for CuContext<'a> {
+    /// A context without a previous process call, for example to call a task from a test.
+    fn from(clock: &'a RobotClock) -> Self {
+        CuContext::new(clock, None)
+    }
+}
+
+impl Deref for CuContext<'_> {
+    type Target = RobotClock;
+
+    fn deref(&self) -> &RobotClock {
+        self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29_clock::CuTime;
+
+    #[test]
+    fn test_context() {
+        let (clock, mock) = RobotClock::mock();
+        mock.increment(std::time::Duration::from_nanos(42));
+        let ctx = CuContext::from(&clock);
+        assert_eq!(ctx.now(), CuDuration(42));
+        assert_eq!(ctx.dt_or(CuDuration(10)), CuDuration(10));
+        let ctx = CuContext::new(&clock, Some(CuDuration(5)));
+        assert_eq!(ctx.dt_or(CuDuration(10)), CuDuration(5));
+    }
+
+    #[test]
+    fn test_node_context() {
+        let clock = RobotClock::default();
+        let mut config = ComponentConfig::new();
+        config.set("gain", 2.5);
+        let pools = CuPools::new();
+        let ctx = CuContext::new(&clock, None)
+            .with_node(3, "ctrl", Some(&config))
+            .with_pools(&pools);
+        assert_eq!(ctx.param::<f64>("gain"), Some(2.5));
+        assert_eq!(ctx.param::<f64>("missing"), None);
+        let pool = ctx.pool::<f32>("scan", 2, 8).unwrap();
+        assert_eq!(pool.stats().id, "ctrl.scan");
+        assert!(CuContext::from(&clock).pool::<f32>("scan", 2, 8).is_err());
+        assert!(ctx.scratch().is_err());
+        let scratch = CuScratch::new(64);
+        let ctx = ctx.with_scratch(&scratch);
+        assert_eq!(
+            ctx.scratch().unwrap().alloc_slice(4, 0u16).unwrap().len(),
+            4
+        );
+
+        assert_eq!(ctx.reported_health(), None);
+        ctx.report_health(CuHealth::Degraded);
+        assert_eq!(ctx.reported_health(), Some(CuHealth::Degraded));
+
+        assert!(ctx.request_param("camera", "fps", 15u32).is_err());
+        let requests = CuParamRequests::default();
+        let ctx = ctx.with_param_requests(&requests);
+        ctx.request_param("camera", "fps", 15u32).unwrap();
+        let requested = requests.take();
+        assert_eq!(requested[0].origin, "ctrl");
+        assert_eq!(requested[0].value, Value::from(15u32));
+    }
+
+    #[test]
+    fn test_input_age() {
+        let (clock, mock) = RobotClock::mock();
+        let mut edges = vec![CuEdgeStats::new("imu", "fusion", Some(CuDuration(100)))];
+        edges[0].record_output(CuTime::from(0), true);
+        mock.increment(std::time::Duration::from_nanos(150));
+        let ctx = CuContext::new(&clock, None)
+            .with_node(1, "fusion", None)
+            .with_edges(&edges);
+        assert_eq!(ctx.input_age("imu"), Some(CuDuration(150)));
+        assert!(ctx.is_input_stale("imu"));
+        assert_eq!(ctx.input_age("gps"), None);
+        assert!(!ctx.is_input_stale("gps"));
+    }
+
+    #[test]
+    fn test_robot_tov() {
+        let clock = RobotClock::default();
+        let tov: OptionCuTime = CuTime::from(1000u64).into();
+        let ctx = CuContext::new(&clock, None);
+        assert_eq!(ctx.robot_tov("cam", tov).unwrap(), tov);
+
+        let mut config = crate::config::CuConfig::default();
+        let mut cam = crate::config::Node::new("cam", "tasks::Camera");
+        cam.set_clock_domain(Some("camera".to_string()));
+        config.add_node(cam);
+        let domains = CuClockDomains::from_config(&config);
+        let ctx = ctx.with_clock_domains(&domains);
+        assert!(ctx.robot_tov("cam", tov).is_err());
+        ctx.clock_domains().unwrap().register_offset("camera", 500);
+        assert_eq!(
+            ctx.robot_tov("cam", tov).unwrap(),
+            CuTime::from(1500u64).into()
+        );
+    }
+}
@@ -5,6 +5,12 @@ extern crate alloc;
 use bincode::{Decode, Encode};
 use std::fmt;
 
+use crate::cutask::{CuMsg, CuMsgPayload};
+use crate::dynmsg::DynCuMsg;
+use crate::summary::CuChannelStats;
+use crate::{CuError, CuResult};
+use cu29_clock::CuTime;
+use cu29_log_derive::debug;
 use cu29_traits::CopperListTuple;
 use serde_derive::Serialize;
 use std::fmt::Display;
@@ -69,6 +75,92 @@ impl<P: CopperListTuple> CopperList<P> {
     }
 }
 
+/// A human readable snapshot of one message of a copper list.
+#[derive(Debug, Clone, Serialize)]
+pub struct CuMsgDump {
+    /// Friendly name of the task that produced this message.
+    pub task: String,
+    /// Rust type of the payload.
+    pub msg_type: String,
+    /// Debug representation of the payload, None if the task did not produce anything.
+    pub payload: Option<String>,
+    pub before_process: Option<CuTime>,
+    pub after_process: Option<CuTime>,
+    pub tov: Option<CuTime>,
+    pub status: String,
+    /// The positions in the copper list of the messages this one was computed from, see cu29::provenance.
+    pub provenance: Vec<u16>,
+}
+
+impl CuMsgDump {
+    pub fn from_msg<T: CuMsgPayload + fmt::Debug>(
+        task: &str,
+        msg_type: &str,
+        msg: &CuMsg<T>,
+    ) -> Self {
+        CuMsgDump {
+            task: task.to_string(),
+            msg_type: msg_type.to_string(),
+            payload: msg.payload().map(|payload| format!("{:?}", payload)),
+            before_process: msg.metadata.before_process.into(),
+            after_process: msg.metadata.after_process.into(),
+            tov: msg.metadata.tov.into(),
+            // the status can be padded with zeros coming from the preallocated copper lists.
+            status: msg.metadata.status_txt.0.trim_end_matches('\0').to_string(),
+            provenance: msg.metadata.provenance.slots().to_vec(),
+        }
+    }
+}
+
+/// A human readable snapshot of a full copper list, ie. of everything computed during one iteration.
+#[derive(Debug, Clone, Serialize)]
+pub struct CuListDump {
+    pub id: u32,
+    pub msgs: Vec<CuMsgDump>,
+}
+
+impl CuListDump {
+    pub fn to_json(&self) -> CuResult<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| CuError::new_with_cause("Could not serialize the copper list", e))
+    }
+}
+
+/// Implemented by the generated copper list payloads to be able to dump their content.
+pub trait CuListDumper {
+    /// The layout of the copper list, "task:msg_type" for every message in order, see cu29::wire.
+    const SCHEMA: &'static str;
+
+    fn dump_msgs(&self) -> Vec<CuMsgDump>;
+
+    /// The earliest time a task started processing in this copper list, ie. when its iteration started.
+    fn start_time(&self) -> Option<CuTime>;
+
+    /// Records every message in the statistics of its channel, in the order of dump_msgs.
+    fn record_stats(&self, channels: &mut [CuChannelStats]);
+
+    /// The untyped view of the messages whose payload type is registered, with the task that produced them.
+    fn dyn_msgs(&self) -> Vec<(&'static str, DynCuMsg)>;
+
+    /// The payload of the message at index, in the order of dump_msgs, in the wire format.
+    /// None if there is no payload.
+    fn encode_payload(&self, index: usize) -> Option<Vec<u8>>;
+}
+
+impl<P: CopperListTuple + CuListDumper> CopperList<P> {
+    pub fn dump(&self) -> CuListDump {
+        CuListDump {
+            id: self.id,
+            msgs: self.msgs.dump_msgs(),
+        }
+    }
+
+    /// When the iteration of this copper list started, None if no task ran.
+    pub fn start_time(&self) -> Option<CuTime> {
+        self.msgs.start_time()
+    }
+}
+
 /// This structure maintains the entire memory needed by Copper for one loop for the inter tasks communication within a process.
 /// P or Payload is typically a Tuple of various types of messages that are exchanged between tasks.
 /// N is the maximum number of in flight Copper List the runtime can support.
@@ -77,6 +169,10 @@ pub struct CuListsManager<P: CopperListTuple, const N: usize> {
     length: usize,
     insertion_index: usize,
     current_cl_id: u32,
+    /// Slot of the last copper list popped as long as it has not been reused.
+    last_popped: Option<usize>,
+    /// Slot of the last copper list created, the messages of the tasks not processed are carried over from it.
+    last_created: Option<usize>,
 }
 
 impl<P: CopperListTuple + fmt::Debug, const N: usize> fmt::Debug for CuListsManager<P, N> {
@@ -113,6 +209,8 @@ impl<P: CopperListTuple, const N: usize> CuListsManager<P, N> {
             length: 0,
             insertion_index: 0,
             current_cl_id: 0,
+            last_popped: None,
+            last_created: None,
         }
     }
 
@@ -145,12 +243,24 @@ impl<P: CopperListTuple, const N: usize> CuListsManager<P, N> {
         self.length = 0;
     }
 
+    /// Takes the next slot of the ring with the messages of the last copper list created: the messages of the tasks
+    /// not processed in the iteration are carried over this way, see cu29::multirate. After a pop, it is the slot of
+    /// the copper list popped which already holds them, otherwise they are copied, ie. after iterations abandoned on
+    /// errors or a copper list dropped by the exhaustion_policy.
     #[inline]
     pub fn create(&mut self) -> Option<&mut CopperList<P>> {
         if self.is_full() {
             return None;
         }
-        let result = &mut self.data[self.insertion_index];
+        let index = self.insertion_index;
+        if self.last_popped == Some(index) {
+            self.last_popped = None;
+        }
+        if let Some(last) = self.last_created.filter(|&last| last != index) {
+            self.carry_over(last, index);
+        }
+        self.last_created = Some(index);
+        let result = &mut self.data[index];
         self.insertion_index = (self.insertion_index + 1) % N;
         self.length += 1;
 
@@ -161,6 +271,23 @@ impl<P: CopperListTuple, const N: usize> CuListsManager<P, N> {
         Some(result)
     }
 
+    /// Copies the messages of the slot from into the slot to, through their encoding as the messages are not Clone.
+    fn carry_over(&mut self, from: usize, to: usize) {
+        let config = bincode::config::standard();
+        let copied = bincode::encode_to_vec(&self.data[from].msgs, config)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                bincode::decode_from_slice::<P, _>(&bytes, config).map_err(|e| e.to_string())
+            });
+        match copied {
+            Ok((msgs, _)) => self.data[to].msgs = msgs,
+            Err(e) => debug!(
+                "Copper lists: the messages of CL {} are not carried over: {}",
+                self.data[from].id, e
+            ),
+        }
+    }
+
     /// Peeks at the last element in the queue.
     #[inline]
     pub fn peek(&self) -> Option<&CopperList<P>> {
@@ -200,9 +327,28 @@ impl<P: CopperListTuple, const N: usize> CuListsManager<P, N> {
             self.insertion_index -= 1;
         }
         self.length -= 1;
+        self.last_popped = Some(self.insertion_index);
         Some(&mut self.data[self.insertion_index])
     }
 
+    /// Removes the oldest copper list of the queue, its slot is reused by the next create.
+    #[inline]
+    pub fn pop_oldest(&mut self) -> Option<&mut CopperList<P>> {
+        if self.length == 0 {
+            return None;
+        }
+        let index = (self.insertion_index + N - self.length) % N;
+        self.length -= 1;
+        Some(&mut self.data[index])
+    }
+
+    /// Returns the last copper list popped from the queue if its slot has not been reused yet.
+    /// This is the last copper list the runtime has completely processed.
+    #[inline]
+    pub fn last_popped(&self) -> Option<&CopperList<P>> {
+        self.last_popped.map(|index| &self.data[index])
+    }
+
     /// Returns an iterator over the queue's contents.
     ///
     /// The iterator goes from the most recently pushed items to the oldest ones.
@@ -415,4 +561,102 @@ mod tests {
         let res: Vec<_> = q.iter().map(|x| x.msgs).collect();
         assert_eq!(res, [5, 4, 3, 2, 1]);
     }
+
+    #[test]
+    fn test_last_popped() {
+        let mut q = CuListsManager::<i32, 5>::new();
+        assert!(q.last_popped().is_none());
+        q.create().unwrap().msgs = 1;
+        q.pop();
+        assert_eq!(q.last_popped().unwrap().msgs, 1);
+        q.create().unwrap().msgs = 2;
+        assert!(q.last_popped().is_none());
+    }
+
+    #[test]
+    fn test_recycled_slot() {
+        let mut q = CuListsManager::<i32, 5>::new();
+        q.create().unwrap().msgs = 1;
+        q.pop();
+        let cl = q.create().unwrap();
+        assert_eq!(cl.id, 1);
+        assert_eq!(cl.msgs, 1);
+    }
+
+    #[test]
+    fn test_carried_over_after_an_abandoned_iteration() {
+        let mut q = CuListsManager::<i32, 3>::new();
+        q.create().unwrap().msgs = 1;
+        q.pop();
+        // Abandoned, it stays in flight and the next one takes another slot.
+        q.create().unwrap().msgs = 2;
+        assert_eq!(q.create().unwrap().msgs, 2);
+    }
+
+    #[test]
+    fn test_pop_oldest() {
+        let mut q = CuListsManager::<i32, 3>::new();
+        for i in 0..3 {
+            q.create().unwrap().msgs = i;
+        }
+        assert!(q.create().is_none());
+        assert_eq!(q.pop_oldest().unwrap().msgs, 0);
+        // The slot of the oldest one is reused with the messages of the last one.
+        let cl = q.create().unwrap();
+        assert_eq!(cl.msgs, 2);
+        cl.msgs = 3;
+        let in_flight: Vec<i32> = q.iter().map(|cl| cl.msgs).collect();
+        assert_eq!(in_flight, vec![3, 2, 1]);
+        assert_eq!(q.pop_oldest().unwrap().msgs, 1);
+        assert_eq!(q.pop().unwrap().msgs, 3);
+        assert_eq!(q.pop().unwrap().msgs, 2);
+        assert!(q.pop_oldest().is_none());
+    }
+
+    impl CuListDumper for (CuMsg<i32>,) {
+        const SCHEMA: &'static str = "src:i32";
+
+        fn dump_msgs(&self) -> Vec<CuMsgDump> {
+            vec![CuMsgDump::from_msg("src", "i32", &self.0)]
+        }
+
+        fn start_time(&self) -> Option<CuTime> {
+            self.0.metadata.before_process.into()
+        }
+
+        fn record_stats(&self, channels: &mut [CuChannelStats]) {
+            channels[0].record(&self.0);
+        }
+
+        fn dyn_msgs(&self) -> Vec<(&'static str, DynCuMsg)> {
+            DynCuMsg::from_msg(&self.0)
+                .into_iter()
+                .map(|msg| ("src", msg))
+                .collect()
+        }
+
+        fn encode_payload(&self, _index: usize) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut msg = CuMsg::new(Some(42));
+        msg.metadata.before_process = CuTime::from(10).into();
+        let cl = CopperList::new(3, (msg,));
+        let dump = cl.dump();
+        assert_eq!(dump.id, 3);
+        assert_eq!(dump.msgs[0].payload.as_deref(), Some("42"));
+        assert_eq!(dump.msgs[0].before_process, Some(CuTime::from(10)));
+        assert_eq!(dump.msgs[0].after_process, None);
+        assert_eq!(cl.start_time(), Some(CuTime::from(10)));
+        assert!(dump.to_json().unwrap().contains("\"src\""));
+
+        assert!(cl.msgs.dyn_msgs().is_empty());
+        crate::registry::register_json_msg_type::<i32>("i32").unwrap();
+        let msgs = cl.msgs.dyn_msgs();
+        assert_eq!(msgs[0].0, "src");
+        assert_eq!(msgs[0].1.to_json(), "42");
+    }
 }
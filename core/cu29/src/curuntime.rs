@@ -2,16 +2,54 @@
 //! It is exposed to the user via the `copper_runtime` macro injecting it as a field in their application struct.
 //!
 
-use crate::clock::{ClockProvider, RobotClock};
-use crate::config::{Cnx, CuConfig, NodeId};
-use crate::config::{ComponentConfig, Node};
-use crate::copperlist::{CopperList, CopperListState, CuListsManager};
-use crate::monitoring::CuMonitor;
-use crate::CuResult;
-use cu29_traits::CopperListTuple;
+use crate::annotation::{CuAnnotator, CuSeverity};
+use crate::clock::{ClockProvider, CuDuration, CuTime, OptionCuTime, RobotClock, RobotClockMock};
+use crate::clockcheck::{write_clock_event, CuClockCheck};
+use crate::clockdomain::CuClockDomains;
+use crate::config::{closest_match, Cnx, CuConfig, CuParamOverrides, ExhaustionPolicy, NodeId};
+use crate::config::{ComponentConfig, Node, Value, BASE_PERIOD_NS_KEY};
+use crate::copperlist::{CopperList, CopperListState, CuListDump, CuListDumper, CuListsManager};
+use crate::derived::CuDerivedLogger;
+use crate::faults::CuFaultInjector;
+use crate::introspection::CuGraphInfo;
+use crate::latch::{CuLatchedMsg, CuLatches};
+use crate::lineage::CuRunId;
+use crate::memory::{enable_memory_attribution, memory_attribution_enabled, task_memory};
+use crate::metrics::CuMetricsWriter;
+use crate::mission::CuMissions;
+use crate::monitoring::{
+    record_edges_delivery, record_edges_output, CuEdgeStats, CuHealth, CuMonitor, CuTaskStats,
+};
+use crate::multirate::CuRateScheduler;
+use crate::namespace::check_robot_id;
+use crate::pacing::{LoopPacer, LoopStats, Overrun};
+use crate::parallel::CuWorkers;
+use crate::permissions::{CuGuard, CuRoleToken, PERMISSION_KEY_PREFIX};
+use crate::pool::{pools_stats, register_stats_source, CuPools};
+use crate::realtime::setup_realtime;
+use crate::scratch::CuScratch;
+use crate::summary::{write_summary, CuSummaryCollector};
+use crate::suspend::{CuSuspendDetector, DEFAULT_SUSPEND_THRESHOLD};
+use crate::threads::{
+    describe_current_thread, install_panic_hook, name_current_thread, CuThreadScheduling,
+};
+use crate::timefactor::{CuTimeFactor, CuTimePacer};
+use crate::tuning::{log_param_change, CuParamChange, CuParamRequests};
+use crate::upgrade::CuConfigUpgrade;
+use crate::watch::{CuConfigDiff, CuConfigWatcher};
+use crate::{CuError, CuResult};
+use cu29_log_derive::debug;
+use cu29_traits::lockaudit;
 use cu29_traits::WriteStream;
+use cu29_traits::{CopperListTuple, UnifiedLogType};
+use cu29_unifiedlog::{
+    stream_write_staged, LogHealth, LogState, StagedStream, UnifiedLoggerWrite, LOGGER_LOCK,
+};
 use petgraph::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 /// This is the main structure that will be injected as a member of the Application struct.
 /// CT is the tuple of all the tasks in order of execution.
@@ -28,6 +66,129 @@ pub struct CuRuntime<CT, P: CopperListTuple, M: CuMonitor, const NBCL: usize> {
     /// The base clock the runtime will be using to record time.
     pub clock: RobotClock, // TODO: remove public at some point
 
+    /// Statistics maintained by the runtime for every task, indexed by node id.
+    pub tasks_stats: Vec<CuTaskStats>,
+
+    /// The liveliness of every connection, in the order of the edges of the graph.
+    pub edges_stats: Vec<CuEdgeStats>,
+
+    /// The connections going out of every task, indexed by node id, as indices in edges_stats.
+    pub edges_by_src: Vec<Vec<usize>>,
+
+    /// The connections going in every task, indexed by node id, as indices in edges_stats.
+    pub edges_by_dst: Vec<Vec<usize>>,
+
+    /// The config of every task, indexed by node id, for the parameters the tasks read from their context.
+    pub tasks_configs: Vec<Option<ComponentConfig>>,
+
+    /// The pools allocated for the tasks through their context.
+    pub pools: CuPools,
+
+    /// The scratch arena of every task, indexed by node id, reset before each of its process calls.
+    pub scratches: Vec<CuScratch>,
+
+    /// The clock domains of the tasks and the conversions of their clocks to the robot clock.
+    pub clock_domains: Arc<CuClockDomains>,
+
+    /// The changes of parameters requested by the tasks, applied at the end of every copper list.
+    pub param_requests: CuParamRequests,
+
+    /// The faults injected in the tasks and connections, only with a faults section in the config.
+    pub faults: Option<CuFaultInjector>,
+
+    /// The tasks replaced by their recorded outputs during a replay, indexed by node id.
+    /// They are neither started nor processed, see set_stubbed_tasks.
+    pub stubbed: Vec<bool>,
+
+    /// The optional tasks the runtime runs without, indexed by node id, see cu29::optional.
+    /// They are neither started nor processed.
+    pub pruned: Vec<bool>,
+
+    /// The tasks skipped, indexed by node id: the ones switched off with disable_task and the ones out of the
+    /// current mission.
+    pub disabled: Vec<bool>,
+
+    /// The tasks switched off with disable_task, indexed by node id.
+    switched_off: Vec<bool>,
+
+    /// The missions of the config and the current one, see cu29::mission.
+    pub missions: CuMissions,
+
+    /// Which tasks are marked optional in the config, indexed by node id.
+    optional: Vec<bool>,
+
+    /// Drives the robot clock from the replayed copper lists, see set_replay_mode.
+    replay_clock: Option<RobotClockMock>,
+
+    /// Paces the replayed or simulated time against the wall clock, see pace_with.
+    time_pacer: Option<CuTimePacer>,
+
+    /// The statistics of the logged copper lists, written in the log as a summary when the runtime is dropped.
+    pub summary: CuSummaryCollector,
+
+    /// The downsampled copies of the outputs of tasks declared in the logging section of the config.
+    pub derived: CuDerivedLogger,
+
+    /// The last messages of the sources of the latched connections, for the observers joining later.
+    pub latches: CuLatches,
+
+    /// The worker threads running the independent tasks in parallel if parallel_workers is set, see cu29::parallel.
+    pub workers: Option<CuWorkers>,
+
+    /// When the tasks with a base_period_ns are due, see cu29::multirate.
+    pub rates: CuRateScheduler,
+
+    /// The run of the application, written in the header of its log, see cu29::lineage.
+    pub run: Option<CuRunId>,
+
+    /// The config installed in the field the application boots with, until it started all the tasks, see
+    /// cu29::upgrade.
+    pub config_upgrade: Option<CuConfigUpgrade>,
+
+    /// The priority and the core affinity of the threads processing the tasks, indexed by node id, see
+    /// cu29::threads::CuThreadScheduling.
+    pub schedulings: Vec<Option<CuThreadScheduling>>,
+
+    /// The log the summary and the annotations are written to, see set_unified_logger.
+    unified_logger: Option<Arc<Mutex<UnifiedLoggerWrite>>>,
+
+    /// How full the log is, reported when it changes, see cu29_unifiedlog::LogFullPolicy.
+    log_health: Option<Arc<LogHealth>>,
+    log_state: LogState,
+
+    /// Static part of the introspection, built from the config.
+    graph_info: CuGraphInfo,
+
+    /// Paces the loop if a period is configured.
+    pacer: Option<LoopPacer>,
+
+    /// What to do when all the copper lists are in flight, see reserve_copper_list.
+    exhaustion_policy: ExhaustionPolicy,
+
+    /// How many times all the copper lists were in flight when an iteration started.
+    pub copperlists_exhausted: u64,
+
+    /// What happened to the schedule of the loop before the current iteration, None if it was on time.
+    last_overrun: Option<Overrun>,
+
+    /// Publishes the metrics of the tasks and the pools if a metrics_path is configured.
+    metrics: Option<CuMetricsWriter>,
+
+    /// When the statistics of the pools were last published.
+    last_pools_publication: OptionCuTime,
+
+    /// Compares the robot clock to the wall clock if a clock_check is configured.
+    clock_check: Option<CuClockCheck>,
+
+    /// Detects the suspends of the machine between 2 iterations of run().
+    suspend: CuSuspendDetector,
+
+    /// If the self tests of the tasks have to pass before entering the loop.
+    self_test_required: bool,
+
+    /// Watches the config file during development if watch_config is set, see watch_config.
+    config_watcher: Option<CuConfigWatcher>,
+
     /// Logger
     logger: Box<dyn WriteStream<CopperList<P>>>,
 }
@@ -41,6 +202,42 @@ impl<CT, P: CopperListTuple, M: CuMonitor, const NBCL: usize> ClockProvider
     }
 }
 
+/// How often the statistics of the pools are published in the metrics file.
+const POOLS_PUBLICATION_PERIOD: CuDuration = CuDuration(100_000_000);
+
+/// Default size of each of the 2 buffers the copper lists are staged in before being written to the log.
+const DEFAULT_LOG_STAGING_SIZE: usize = 1024 * 1024;
+
+/// The stream the runtime logs the copper lists to. A copper list is encoded in a staging buffer while another
+/// thread writes the previous buffer to the log, so a slow flush of the log does not hold up the recycling of the
+/// copper lists. The staging buffers show up in the statistics of the pools.
+pub fn copperlist_stream(
+    logger: Arc<Mutex<UnifiedLoggerWrite>>,
+    config: &CuConfig,
+) -> StagedStream {
+    let staging_size = config
+        .get_runtime_config()
+        .and_then(|runtime_config| runtime_config.log_staging_size)
+        .unwrap_or(DEFAULT_LOG_STAGING_SIZE);
+    let stream = stream_write_staged(
+        logger,
+        UnifiedLogType::CopperList,
+        60 * 1024, // FIXME: make this a config
+        staging_size,
+    )
+    .on_saturation(|snapshot| {
+        let used = snapshot.used;
+        let capacity = snapshot.capacity;
+        debug!(
+            "Logger: the staging buffer of the copper lists is {}/{} bytes full while the previous one is still being written, the log storage is not keeping up.",
+            used,
+            capacity
+        );
+    });
+    register_stats_source(stream.stats());
+    stream
+}
+
 impl<CT, P: CopperListTuple + 'static, M: CuMonitor, const NBCL: usize> CuRuntime<CT, P, M, NBCL> {
     pub fn new(
         clock: RobotClock,
@@ -49,12 +246,16 @@ impl<CT, P: CopperListTuple + 'static, M: CuMonitor, const NBCL: usize> CuRuntim
         monitor_instanciator: impl Fn(Option<&ComponentConfig>) -> M,
         logger: impl WriteStream<CopperList<P>> + 'static,
     ) -> CuResult<Self> {
-        let all_instances_configs: Vec<Option<&ComponentConfig>> = config
+        name_current_thread("cu:main");
+        install_panic_hook();
+        lockaudit::set_attribution(describe_current_thread);
+
+        let all_tasks_configs: Vec<Option<ComponentConfig>> = config
             .get_all_nodes()
             .iter()
-            .map(|node_config| node_config.get_instance_config())
+            .map(|node_config| node_config.get_task_config())
             .collect();
-        let tasks = tasks_instanciator(all_instances_configs)?;
+        let tasks = tasks_instanciator(all_tasks_configs.iter().map(Option::as_ref).collect())?;
 
         let monitor = if let Some(monitor_section) = config.get_monitor_config() {
             monitor_instanciator(monitor_section.get_config())
@@ -62,21 +263,656 @@ impl<CT, P: CopperListTuple + 'static, M: CuMonitor, const NBCL: usize> CuRuntim
             monitor_instanciator(None)
         };
 
+        let graph_info = CuGraphInfo::from_config(config);
+        let tasks_stats = vec![CuTaskStats::default(); graph_info.nodes.len()];
+        let mut edges_stats = Vec::new();
+        let mut edges_by_src = vec![Vec::new(); graph_info.nodes.len()];
+        let mut edges_by_dst = vec![Vec::new(); graph_info.nodes.len()];
+        for edge in config.graph.edge_indices() {
+            let cnx = &config.graph[edge];
+            let (src, dst) = config.graph.edge_endpoints(edge).unwrap();
+            edges_by_src[src.index()].push(edges_stats.len());
+            edges_by_dst[dst.index()].push(edges_stats.len());
+            edges_stats.push(
+                CuEdgeStats::new(cnx.get_src(), cnx.get_dst(), cnx.max_age_ns.map(CuDuration))
+                    .with_deadline(cnx.get_deadline(), cnx.deadline_policy.unwrap_or_default()),
+            );
+        }
+        let scratches = config
+            .get_all_nodes()
+            .iter()
+            .map(|node| CuScratch::new(node.get_scratch_size()))
+            .collect();
+        let schedulings = config
+            .get_all_nodes()
+            .iter()
+            .map(|node| CuThreadScheduling::from_node(node))
+            .collect::<CuResult<Vec<_>>>()?;
+        let pacer = match config.get_runtime_config() {
+            Some(runtime_config) => LoopPacer::from_config(runtime_config)?,
+            None => None,
+        };
+
+        let metrics = match config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.metrics_path.as_ref())
+        {
+            Some(path) => {
+                let names: Vec<&str> = graph_info.nodes.iter().map(|n| n.id.as_str()).collect();
+                Some(CuMetricsWriter::create(Path::new(path), &names)?)
+            }
+            None => None,
+        };
+
+        if let Some(robot_id) = config.robot_id() {
+            check_robot_id(robot_id)?;
+        }
+
+        let faults = CuFaultInjector::from_config(config)?;
+        if faults.is_some() {
+            debug!(
+                "Warning: this config injects faults in the tasks, do not use it on a real robot."
+            );
+        }
+
+        let clock_check = config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.clock_check.clone())
+            .map(|check_config| CuClockCheck::new(clock.clone(), check_config));
+
+        let suspend = CuSuspendDetector::new(
+            config
+                .get_runtime_config()
+                .and_then(|runtime_config| runtime_config.suspend_threshold_ms)
+                .map(|ms| CuDuration(ms * 1_000_000))
+                .unwrap_or(DEFAULT_SUSPEND_THRESHOLD),
+        );
+
+        let missions = CuMissions::from_config(config);
+
+        if config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.memory_attribution)
+            .unwrap_or(false)
+        {
+            enable_memory_attribution();
+        }
+
+        let workers = match config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.parallel_workers)
+        {
+            Some(workers) if workers > 0 => {
+                let mut workers = CuWorkers::new(workers)?;
+                let nodes = config.get_all_nodes();
+                for (node_id, (node, scheduling)) in nodes.iter().zip(&schedulings).enumerate() {
+                    if let Some(scheduling) = scheduling {
+                        workers
+                            .dedicate(node_id, &node.get_id(), scheduling)
+                            .map_err(|e| {
+                                CuError::new_with_cause(
+                                    &format!("Cannot schedule the task '{}'", node.get_id()),
+                                    e,
+                                )
+                            })?;
+                    }
+                }
+                Some(workers)
+            }
+            _ => {
+                if schedulings.iter().any(Option::is_some) {
+                    debug!("Threads: without parallel_workers the tasks run on cu:main, their priority and core_affinity only apply to the threads of their drivers.");
+                }
+                None
+            }
+        };
+
+        let self_test_required = config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.self_test)
+            .unwrap_or(false);
+
         let runtime = Self {
             tasks,
             monitor,
             copper_lists_manager: CuListsManager::new(), // placeholder
             clock,
+            tasks_stats,
+            edges_stats,
+            edges_by_src,
+            edges_by_dst,
+            tasks_configs: all_tasks_configs,
+            pools: CuPools::new(),
+            scratches,
+            clock_domains: Arc::new(CuClockDomains::from_config(config)),
+            param_requests: CuParamRequests::default(),
+            faults,
+            stubbed: vec![false; graph_info.nodes.len()],
+            pruned: vec![false; graph_info.nodes.len()],
+            disabled: (0..graph_info.nodes.len())
+                .map(|node_id| !missions.runs(node_id))
+                .collect(),
+            switched_off: vec![false; graph_info.nodes.len()],
+            missions,
+            optional: config
+                .get_all_nodes()
+                .iter()
+                .map(|node| node.is_optional())
+                .collect(),
+            replay_clock: None,
+            time_pacer: None,
+            summary: CuSummaryCollector::new(
+                graph_info.nodes.iter().map(|n| n.id.clone()).collect(),
+            ),
+            derived: CuDerivedLogger::new(config)?,
+            latches: CuLatches::from_config(config),
+            workers,
+            rates: CuRateScheduler::from_config(config),
+            schedulings,
+            run: None,
+            config_upgrade: None,
+            unified_logger: None,
+            log_health: None,
+            log_state: LogState::Nominal,
+            graph_info,
+            pacer,
+            exhaustion_policy: config
+                .get_runtime_config()
+                .map(|runtime_config| runtime_config.exhaustion_policy)
+                .unwrap_or_default(),
+            copperlists_exhausted: 0,
+            last_overrun: None,
+            metrics,
+            last_pools_publication: OptionCuTime::none(),
+            clock_check,
+            suspend,
+            self_test_required,
+            config_watcher: None,
             logger: Box::new(logger),
         };
 
         Ok(runtime)
     }
 
+    /// Selects the tasks, by their friendly name, to replace by their recorded outputs during a replay.
+    /// The other tasks run as usual on the recorded outputs of the stubbed ones.
+    pub fn set_stubbed_tasks(&mut self, task_ids: &[&str]) -> CuResult<()> {
+        let mut stubbed = vec![false; self.graph_info.nodes.len()];
+        for task_id in task_ids {
+            let node = self.graph_info.get_node(task_id).ok_or_else(|| {
+                let hint =
+                    closest_match(task_id, self.graph_info.nodes.iter().map(|n| n.id.as_str()))
+                        .map(|m| format!(" Did you mean '{}'?", m))
+                        .unwrap_or_default();
+                CuError::from(format!(
+                    "Cannot stub '{}': there is no such task.{}",
+                    task_id, hint
+                ))
+            })?;
+            stubbed[node.node_id as usize] = true;
+            debug!(
+                "Replay: task '{}' replaced by its recorded outputs.",
+                *task_id
+            );
+        }
+        self.stubbed = stubbed;
+        Ok(())
+    }
+
+    /// Runs without an optional task that could not be created or started, see cu29::optional. The optional tasks
+    /// left with only pruned tasks upstream are pruned too.
+    pub fn prune_task(&mut self, node_id: usize, error: &CuError) {
+        self.pruned[node_id] = true;
+        let stats = &mut self.tasks_stats[node_id];
+        stats.health = CuHealth::Failed;
+        stats.last_error = Some(error.to_string());
+        let task_id = self.graph_info.nodes[node_id].id.as_str();
+        debug!("Optional task '{}' pruned: {}", task_id, error.to_string());
+
+        let src_pruned = |edge: usize| {
+            self.edges_by_src
+                .iter()
+                .position(|edges| edges.contains(&edge))
+                .is_some_and(|src| self.pruned[src])
+        };
+        let starved: Vec<usize> = (0..self.pruned.len())
+            .filter(|&node| self.optional[node] && !self.pruned[node])
+            .filter(|&node| {
+                let inputs = &self.edges_by_dst[node];
+                !inputs.is_empty() && inputs.iter().all(|&edge| src_pruned(edge))
+            })
+            .collect();
+        for node in starved {
+            if !self.pruned[node] {
+                self.prune_task(node, &"All its inputs come from pruned tasks.".into());
+            }
+        }
+    }
+
+    /// Switches off a task, ie. a camera pipeline to save power: it is not processed anymore and the tasks
+    /// downstream keep seeing its last message, like for a task not due (see cu29::multirate). The task is not
+    /// stopped, it resumes where it was with enable_task.
+    pub fn disable_task(&mut self, node_id: usize) -> CuResult<()> {
+        self.set_task_enabled(node_id, false)
+    }
+
+    /// Processes again a task switched off with disable_task.
+    pub fn enable_task(&mut self, node_id: usize) -> CuResult<()> {
+        self.set_task_enabled(node_id, true)
+    }
+
+    fn set_task_enabled(&mut self, node_id: usize, enabled: bool) -> CuResult<()> {
+        let node = self.graph_info.nodes.get(node_id).ok_or_else(|| {
+            CuError::from(format!("There is no task with the node id {}.", node_id))
+        })?;
+        if self.switched_off[node_id] == enabled {
+            let state = if enabled { "enabled" } else { "disabled" };
+            debug!("Task '{}' {}.", node.id.as_str(), state);
+        }
+        self.switched_off[node_id] = !enabled;
+        self.disabled[node_id] = !enabled || !self.missions.runs(node_id);
+        Ok(())
+    }
+
+    /// Switches to another mission of the config, see cu29::mission: from the next iteration, only the tasks of
+    /// this mission and the ones in no mission are processed.
+    pub fn switch_mission(&mut self, mission: &str) -> CuResult<()> {
+        let previous = self.missions.current().unwrap_or_default().to_string();
+        self.missions.switch(mission)?;
+        self.disabled = (0..self.disabled.len())
+            .map(|node_id| self.switched_off[node_id] || !self.missions.runs(node_id))
+            .collect();
+        debug!("Mission: {} -> {}.", previous, mission);
+        Ok(())
+    }
+
+    /// Degraded when the runtime runs without some optional tasks, see cu29::optional.
+    pub fn health(&self) -> CuHealth {
+        if self.pruned.contains(&true) {
+            CuHealth::Degraded
+        } else {
+            CuHealth::Nominal
+        }
+    }
+
+    /// Puts the runtime in replay mode: the sources are replaced by their recorded outputs and the robot clock,
+    /// driven by clock_mock, follows the time of the replayed copper lists. The other tasks see the same messages
+    /// at the same time as when they were recorded.
+    pub fn set_replay_mode(&mut self, clock_mock: RobotClockMock) {
+        self.stubbed = self
+            .graph_info
+            .nodes
+            .iter()
+            .map(|node| node.task_type == CuTaskType::Source)
+            .collect();
+        debug!("Replay: the sources are replaced by their recorded outputs.");
+        self.replay_clock = Some(clock_mock);
+    }
+
+    /// Moves the robot clock to the start of a replayed copper list, in replay mode. With pace_with, it first
+    /// waits for the time of the copper list at the time factor.
+    pub fn set_replay_time(&mut self, time: Option<CuTime>) {
+        if let (Some(clock_mock), Some(time)) = (&self.replay_clock, time) {
+            if let Some(pacer) = &mut self.time_pacer {
+                pacer.wait_until(time);
+            }
+            clock_mock.set_value(time.0);
+        }
+    }
+
+    /// Replays or simulates at the pace of the wall clock multiplied by factor, changed live from any thread, see
+    /// cu29::timefactor. Without it, the copper lists are replayed and the steps simulated as fast as possible.
+    pub fn pace_with(&mut self, factor: CuTimeFactor) {
+        debug!("Time factor: {}.", factor.get());
+        self.time_pacer = Some(CuTimePacer::new(factor));
+    }
+
+    /// The factor given to pace_with, None if the time is not paced.
+    pub fn time_factor(&self) -> Option<&CuTimeFactor> {
+        self.time_pacer.as_ref().map(CuTimePacer::factor)
+    }
+
+    /// Waits until the simulated time can reach time at the time factor, if paced, ie. before a step of a
+    /// simulation.
+    pub fn pace_time(&mut self, time: CuTime) {
+        if let Some(pacer) = &mut self.time_pacer {
+            pacer.wait_until(time);
+        }
+    }
+
+    /// The log the annotations are written to, and the summary of the run when the runtime is dropped.
+    pub fn set_unified_logger(&mut self, logger: Arc<Mutex<UnifiedLoggerWrite>>) {
+        self.log_health = Some(lockaudit::lock(&LOGGER_LOCK, &logger).unwrap().health());
+        self.derived.set_logger(logger.clone());
+        self.unified_logger = Some(logger);
+    }
+
+    /// Keeps the config installed in the field once all the tasks are started, see cu29::upgrade.
+    pub fn confirm_config_upgrade(&mut self) -> CuResult<()> {
+        match self.config_upgrade.take() {
+            Some(upgrade) => upgrade.confirm(),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets up the process and the current thread for hard deadlines if the runtime section has a realtime
+    /// section, see cu29::realtime. Called once the tasks are created and the log is set.
+    pub fn setup_realtime(&self, config: &CuConfig) -> CuResult<()> {
+        match config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.realtime.as_ref())
+        {
+            Some(realtime) => setup_realtime(realtime, self.unified_logger.as_ref()),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes annotations in the log from any thread, None if the runtime has no log.
+    pub fn annotator(&self) -> Option<CuAnnotator> {
+        self.unified_logger
+            .as_ref()
+            .map(|logger| CuAnnotator::new(self.clock.clone(), logger.clone()))
+    }
+
+    /// Marks the current time in the log, see cu29::annotation.
+    pub fn annotate(&self, label: &str, severity: CuSeverity) -> CuResult<()> {
+        self.annotator()
+            .ok_or("Cannot annotate, the runtime has no log.")?
+            .annotate(label, severity)
+    }
+
+    /// Changes a parameter of a task while the application runs, the task reads it with ctx.param on its next
+    /// process call. The key is the mutation checked against the permissions of the task, the holder of the token
+    /// is recorded as the origin of the change in the log, see cu29::tuning.
+    pub fn set_param(
+        &mut self,
+        task_id: &str,
+        key: &str,
+        value: Value,
+        token: Option<&CuRoleToken>,
+    ) -> CuResult<CuParamChange> {
+        let node_id = self.tuned_node_id(task_id, key)?;
+        CuGuard::from_config(task_id, self.tasks_configs[node_id].as_ref())?
+            .authorize(token, key)?;
+        let origin = token
+            .map(|token| token.holder().to_string())
+            .unwrap_or_default();
+        let change = self.param_change(node_id, task_id, key, &value, origin)?;
+        self.apply_param_change(&change)?;
+        Ok(change)
+    }
+
+    fn param_change(
+        &self,
+        node_id: usize,
+        task_id: &str,
+        key: &str,
+        value: &Value,
+        origin: String,
+    ) -> CuResult<CuParamChange> {
+        let old = self.tasks_configs[node_id]
+            .as_ref()
+            .and_then(|config| config.0.get(key))
+            .map(Value::to_ron)
+            .transpose()?;
+        Ok(CuParamChange {
+            time: self.clock.now(),
+            task: task_id.to_string(),
+            key: key.to_string(),
+            old,
+            new: Some(value.to_ron()?),
+            origin,
+        })
+    }
+
+    /// Applies the changes requested by the tasks during the copper list, see CuContext::request_param. A request
+    /// that cannot be applied is logged and dropped, it does not stop the application.
+    fn apply_param_requests(&mut self) {
+        for request in self.param_requests.take() {
+            let outcome = self
+                .tuned_node_id(&request.task, &request.key)
+                .and_then(|node_id| {
+                    self.param_change(
+                        node_id,
+                        &request.task,
+                        &request.key,
+                        &request.value,
+                        request.origin.clone(),
+                    )
+                })
+                .and_then(|change| self.apply_param_change(&change));
+            if let Err(e) = outcome {
+                debug!(
+                    "Tuning: the change of {}.{} requested by {} failed: {}",
+                    request.task.as_str(),
+                    request.key.as_str(),
+                    request.origin.as_str(),
+                    e.to_string()
+                );
+            }
+        }
+    }
+
+    /// Applies a recorded change of a parameter without checking the permissions, ie. during a replay,
+    /// and records it in the log again.
+    pub fn apply_param_change(&mut self, change: &CuParamChange) -> CuResult<()> {
+        let node_id = self.tuned_node_id(&change.task, &change.key)?;
+        let config = self.tasks_configs[node_id].get_or_insert_with(ComponentConfig::new);
+        match change.new_value()? {
+            Some(value) => config.0.insert(change.key.clone(), value),
+            None => config.0.remove(&change.key),
+        };
+        if change.key == BASE_PERIOD_NS_KEY {
+            let period = change.new.as_deref().and_then(|new| new.parse().ok());
+            self.rates.set_period(node_id, period.map(CuDuration));
+        }
+        if let Some(logger) = &self.unified_logger {
+            log_param_change(logger, change)?;
+        }
+        Ok(())
+    }
+
+    fn tuned_node_id(&self, task_id: &str, key: &str) -> CuResult<usize> {
+        if key.starts_with(PERMISSION_KEY_PREFIX) {
+            return Err(CuError::from(format!(
+                "Cannot change {}.{}: the permissions are not parameters.",
+                task_id, key
+            )));
+        }
+        let node = self.graph_info.get_node(task_id).ok_or_else(|| {
+            let hint = closest_match(task_id, self.graph_info.nodes.iter().map(|n| n.id.as_str()))
+                .map(|m| format!(" Did you mean '{}'?", m))
+                .unwrap_or_default();
+            CuError::from(format!(
+                "Cannot change {}.{}: there is no such task.{}",
+                task_id, key, hint
+            ))
+        })?;
+        Ok(node.node_id as usize)
+    }
+
+    /// Watches the config file the application was created from if watch_config is set in its runtime section,
+    /// the overrides are applied again on every save. See cu29::watch.
+    pub fn watch_config(&mut self, path: &str, config: &CuConfig, overrides: &CuParamOverrides) {
+        let enabled = config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.watch_config)
+            .unwrap_or(false);
+        if enabled {
+            debug!("Config: watching {} for changes.", path);
+            self.config_watcher = Some(CuConfigWatcher::new(path, config, overrides));
+        }
+    }
+
+    /// Applies the changes of the config which do not need a restart: the parameters of the tasks, the pacing of
+    /// the loop and the logging section of the saved config.
+    pub fn apply_config_diff(&mut self, diff: &CuConfigDiff, config: &CuConfig) -> CuResult<()> {
+        for change in &diff.params {
+            self.apply_param_change(&CuParamChange {
+                time: self.clock.now(),
+                ..change.clone()
+            })?;
+        }
+        if !diff.pacing.is_empty() {
+            self.pacer = match config.get_runtime_config() {
+                Some(runtime_config) => LoopPacer::from_config(runtime_config)?,
+                None => None,
+            };
+            self.reset_pacing();
+        }
+        if diff.logging {
+            self.derived = CuDerivedLogger::new(config)?;
+            if let Some(logger) = &self.unified_logger {
+                self.derived.set_logger(logger.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the changes of the config file if it was saved, and logs them with the ones needing a restart.
+    fn check_config_watch(&mut self) {
+        let now = self.clock.now();
+        let Some(watcher) = &mut self.config_watcher else {
+            return;
+        };
+        let result = match watcher.poll(now) {
+            Some(Ok(diff)) if diff.is_empty() => return,
+            Some(Ok(diff)) => {
+                let config = watcher.current().clone();
+                debug!("Config: the config changed: {}", diff.to_string());
+                self.apply_config_diff(&diff, &config)
+            }
+            Some(Err(e)) => Err(e),
+            None => return,
+        };
+        if let Err(e) = result {
+            debug!("Config: could not apply the config: {}.", e.to_string());
+        }
+    }
+
+    /// Returns the structure of the task graph with the current rates and health of the tasks.
+    pub fn introspect(&self) -> CuGraphInfo {
+        let mut info = self.graph_info.clone();
+        info.update(&self.tasks_stats);
+        info.update_edges(&self.edges_stats, self.clock.now());
+        info
+    }
+
+    /// Records a process call of a task at time on the connections going out of it, see record_edges_output.
+    pub fn record_output(&mut self, node_id: usize, time: CuTime, produced: bool) {
+        record_edges_output(
+            &mut self.edges_stats,
+            &self.edges_by_src[node_id],
+            time,
+            produced,
+        );
+    }
+
+    /// Records the delivery at time of the messages going in a task, see record_edges_delivery.
+    pub fn record_delivery(&mut self, node_id: usize, time: CuTime) -> CuResult<()> {
+        record_edges_delivery(&mut self.edges_stats, &self.edges_by_dst[node_id], time)
+    }
+
+    /// What a new observer is sent first: the last message of the sources of the latched connections, see
+    /// cu29::latch.
+    pub fn latched_msgs(&self) -> Vec<CuLatchedMsg> {
+        self.latches.snapshot()
+    }
+
+    /// Returns a structured snapshot of every message computed during the last complete iteration.
+    pub fn dump_last_iteration(&self) -> Option<CuListDump>
+    where
+        P: CuListDumper,
+    {
+        self.copper_lists_manager.last_popped().map(|cl| cl.dump())
+    }
+
+    /// Waits for the start of the next iteration of the loop if a period is configured.
+    pub fn wait_next_iteration(&mut self) {
+        if let Some(pacer) = &mut self.pacer {
+            self.last_overrun = pacer.wait();
+        }
+    }
+
+    /// Restarts the pacing of the loop, the next iteration starts right away.
+    pub fn reset_pacing(&mut self) {
+        if let Some(pacer) = &mut self.pacer {
+            pacer.reset();
+        }
+        self.last_overrun = None;
+        self.suspend.rearm(self.clock.now());
+    }
+
+    /// How long the machine was suspended since the previous iteration, None if it was not, see cu29::suspend.
+    pub fn check_suspend(&mut self) -> Option<CuDuration> {
+        self.suspend.check(self.clock.now())
+    }
+
+    /// Resumes the loop after a suspend once the tasks are restarted: the gap is logged, the next process calls
+    /// get no dt and the next iteration starts right away.
+    pub fn resume_after_suspend(&mut self, gap: CuDuration) {
+        debug!(
+            "Suspend: the machine was suspended for {}, the tasks were restarted.",
+            gap
+        );
+        if let Some(annotator) = self.annotator() {
+            let label = format!("resumed after a suspend of {}", gap);
+            if let Err(e) = annotator.annotate(&label, CuSeverity::Warning) {
+                debug!("Logger: could not annotate the suspend: {}", e.to_string());
+            }
+        }
+        for stats in &mut self.tasks_stats {
+            stats.last_process = OptionCuTime::none();
+        }
+        self.reset_pacing();
+    }
+
+    /// If the self tests of the tasks have to pass before entering the loop, self_test in the runtime config.
+    pub fn self_test_required(&self) -> bool {
+        self.self_test_required
+    }
+
+    /// What happened to the schedule of the loop before the current iteration, None if it started on time.
+    pub fn last_overrun(&self) -> Option<Overrun> {
+        self.last_overrun
+    }
+
+    /// The timing statistics of the loop if a period is configured.
+    pub fn loop_stats(&self) -> Option<&LoopStats> {
+        self.pacer.as_ref().map(LoopPacer::stats)
+    }
+
     pub fn available_copper_lists(&self) -> usize {
         NBCL - self.copper_lists_manager.len()
     }
 
+    /// Makes sure the next iteration gets a copper list. They are all in flight only when iterations were abandoned
+    /// on errors, the oldest one is then freed following the exhaustion_policy of the runtime section.
+    pub fn reserve_copper_list(&mut self) -> CuResult<()> {
+        if !self.copper_lists_manager.is_full() {
+            return Ok(());
+        }
+        self.copperlists_exhausted += 1;
+        let policy = self.exhaustion_policy;
+        // Block is rejected when the config is read.
+        if matches!(policy, ExhaustionPolicy::Error | ExhaustionPolicy::Block) {
+            return Err(format!("All the {} copper lists are in flight.", NBCL).into());
+        }
+        let oldest = self.copper_lists_manager.pop_oldest().unwrap();
+        let id = oldest.id;
+        if policy == ExhaustionPolicy::LogOldest {
+            oldest.change_state(CopperListState::BeingSerialized);
+            if let Err(e) = self.logger.log(oldest) {
+                debug!("Logger: could not log a copper list: {}", e.to_string());
+            }
+            debug!("Copper lists: all in flight, CL {} logged as it is.", id);
+        } else {
+            debug!("Copper lists: all in flight, CL {} dropped.", id);
+        }
+        oldest.change_state(CopperListState::Free);
+        Ok(())
+    }
+
     pub fn end_of_processing(&mut self, culistid: u32) {
         let mut is_top = true;
         let mut nb_done = 0;
@@ -88,7 +924,9 @@ impl<CT, P: CopperListTuple + 'static, M: CuMonitor, const NBCL: usize> CuRuntim
             // serialize them all and Free them.
             if is_top && cl.get_state() == CopperListState::DoneProcessing {
                 cl.change_state(CopperListState::BeingSerialized);
-                self.logger.log(cl).unwrap();
+                if let Err(e) = self.logger.log(cl) {
+                    debug!("Logger: could not log a copper list: {}", e.to_string());
+                }
                 cl.change_state(CopperListState::Free);
                 nb_done += 1;
             } else {
@@ -98,6 +936,131 @@ impl<CT, P: CopperListTuple + 'static, M: CuMonitor, const NBCL: usize> CuRuntim
         for _ in 0..nb_done {
             let _ = self.copper_lists_manager.pop();
         }
+        self.check_log_health();
+        self.check_clock(culistid);
+        self.check_config_watch();
+        self.apply_param_requests();
+        self.record_memory();
+        self.publish_metrics();
+    }
+
+    /// Copies the memory charged to every task in its statistics, see cu29::memory.
+    fn record_memory(&mut self) {
+        if !memory_attribution_enabled() {
+            return;
+        }
+        for (task, stats) in self.tasks_stats.iter_mut().enumerate() {
+            stats.record_memory(task_memory(task));
+        }
+    }
+
+    /// Logs the steps and the drifts of the robot clock against the wall clock, see cu29::clockcheck.
+    fn check_clock(&mut self, culistid: u32) {
+        let Some(event) = self
+            .clock_check
+            .as_mut()
+            .and_then(|check| check.check(culistid))
+        else {
+            return;
+        };
+        let description = event.to_string();
+        debug!("Clock: {}.", description);
+        if let Some(logger) = &self.unified_logger {
+            if let Err(e) = write_clock_event(logger, &event) {
+                debug!("Logger: could not log a clock event: {}", e.to_string());
+            }
+        }
+    }
+
+    /// Reports when the log gets full, what is still logged depends on its LogFullPolicy.
+    fn check_log_health(&mut self) {
+        let Some(health) = &self.log_health else {
+            return;
+        };
+        let state = health.state();
+        if state == self.log_state {
+            return;
+        }
+        self.log_state = state;
+        let snapshot = health.snapshot();
+        let description = state.to_string();
+        let reason = snapshot.reason.unwrap_or_default();
+        debug!("Logger: the log is {} ({}).", description, reason);
+    }
+
+    /// Publishes the statistics of the tasks, and the ones of the pools every POOLS_PUBLICATION_PERIOD.
+    fn publish_metrics(&mut self) {
+        let Some(metrics) = &self.metrics else {
+            return;
+        };
+        let now = self.clock.now();
+        self.summary.update_task_stats(&mut self.tasks_stats);
+        metrics.publish_tasks(now, &self.tasks_stats);
+        metrics.publish_log_errors(cu29_log_runtime::error_counts());
+        let last: Option<CuTime> = self.last_pools_publication.into();
+        if last.is_none_or(|last| now - last >= POOLS_PUBLICATION_PERIOD) {
+            // Collecting the statistics of the pools allocates, they change slowly anyway.
+            metrics.publish_pools(&pools_stats());
+            self.last_pools_publication = now.into();
+        }
+    }
+}
+
+impl<CT, P: CopperListTuple, M: CuMonitor, const NBCL: usize> Drop for CuRuntime<CT, P, M, NBCL> {
+    fn drop(&mut self) {
+        // Only with the lock_audit feature.
+        for stats in lockaudit::lock_stats() {
+            let (name, acquisitions, contentions) =
+                (stats.name, stats.acquisitions, stats.contentions);
+            let max_wait = CuDuration::from(stats.max_wait.as_nanos() as u64);
+            let max_hold = CuDuration::from(stats.max_hold.as_nanos() as u64);
+            debug!(
+                "Lock audit: '{}' taken {} times, {} contended, waited at most {}, held at most {}.",
+                name, acquisitions, contentions, max_wait, max_hold
+            );
+        }
+        if let Some(faults) = &self.faults {
+            let (dropped, delayed, errors) = (
+                faults.stats.dropped,
+                faults.stats.delayed,
+                faults.stats.errors,
+            );
+            debug!(
+                "Faults: {} messages dropped, {} process calls delayed, {} errors injected.",
+                dropped, delayed, errors
+            );
+        }
+        if self.copperlists_exhausted > 0 {
+            debug!(
+                "Copper lists: all in flight {} times.",
+                self.copperlists_exhausted
+            );
+        }
+        if let Some(health) = &self.log_health {
+            let dropped = health.snapshot().dropped;
+            if dropped > 0 {
+                debug!(
+                    "Logger: {} entries were dropped because the log was full.",
+                    dropped
+                );
+            }
+        }
+        let Some(logger) = self.unified_logger.take() else {
+            return;
+        };
+        if self.summary.is_empty() {
+            return;
+        }
+        let mut summary = self.summary.summary();
+        if memory_attribution_enabled() {
+            summary.memory = self.summary.memory(&self.tasks_stats);
+        }
+        if let Err(e) = write_summary(logger, &summary) {
+            debug!(
+                "Logger: could not write the summary of the run: {}",
+                e.to_string()
+            );
+        }
     }
 }
 
@@ -105,7 +1068,7 @@ impl<CT, P: CopperListTuple + 'static, M: CuMonitor, const NBCL: usize> CuRuntim
 /// - Source: only producing output messages (usually used for drivers)
 /// - Regular: processing input messages and producing output messages, more like compute nodes.
 /// - Sink: only consuming input messages (usually used for actuators)
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum CuTaskType {
     Source,
     Regular,
@@ -179,7 +1142,7 @@ impl Debug for CuExecutionLoop {
 /// This structure represents a step in the execution plan.
 #[derive(Debug)]
 pub enum CuExecutionUnit {
-    Step(CuExecutionStep),
+    Step(Box<CuExecutionStep>),
     Loop(CuExecutionLoop),
 }
 
@@ -204,7 +1167,10 @@ fn find_output_index_type_from_nodeid(
     None
 }
 
-fn find_task_type_for_id(graph: &StableDiGraph<Node, Cnx, NodeId>, node_id: NodeId) -> CuTaskType {
+pub(crate) fn find_task_type_for_id(
+    graph: &StableDiGraph<Node, Cnx, NodeId>,
+    node_id: NodeId,
+) -> CuTaskType {
     if graph.neighbors_directed(node_id.into(), Incoming).count() == 0 {
         CuTaskType::Source
     } else if graph.neighbors_directed(node_id.into(), Outgoing).count() == 0 {
@@ -326,7 +1292,7 @@ fn plan_tasks_tree_branch(
                 input_msg_indices_types,
                 output_msg_index_type,
             };
-            plan.push(CuExecutionUnit::Step(step));
+            plan.push(CuExecutionUnit::Step(Box::new(step)));
         }
     }
     next_culist_output_index
@@ -335,6 +1301,21 @@ fn plan_tasks_tree_branch(
 /// This is the main heuristics to compute an execution plan at compilation time.
 /// TODO: Make that heuristic plugable.
 pub fn compute_runtime_plan(config: &CuConfig) -> CuResult<CuExecutionLoop> {
+    // A task without any connection has nothing to produce or consume in the copper list.
+    if let Some(node_id) = config
+        .graph
+        .node_indices()
+        .find(|node_id| config.graph.neighbors_undirected(*node_id).next().is_none())
+    {
+        let node = &config.graph[node_id];
+        return Err(format!(
+            "Task '{}' ({}) is not connected to any other task.",
+            node.get_id(),
+            node.get_type()
+        )
+        .into());
+    }
+
     // find all the sources.
     let nodes_to_visit = config
         .graph
@@ -369,11 +1350,14 @@ pub fn compute_runtime_plan(config: &CuConfig) -> CuResult<CuExecutionLoop> {
 mod tests {
     use super::*;
     use crate::clock::RobotClock;
-    use crate::config::Node;
+    use crate::config::{read_configuration_str, Node};
+    use crate::context::CuContext;
     use crate::cutask::{CuSinkTask, CuTaskLifecycle};
     use crate::cutask::{CuSrcTask, Freezable};
-    use crate::monitoring::NoMonitor;
+    use crate::monitoring::{CuHealth, NoMonitor};
+    use crate::permissions::CuRole;
     use bincode::Encode;
+    use cu29_clock::CuDuration;
 
     pub struct TestSource {}
 
@@ -390,7 +1374,7 @@ mod tests {
 
     impl CuSrcTask<'_> for TestSource {
         type Output = ();
-        fn process(&mut self, _clock: &RobotClock, _empty_msg: Self::Output) -> CuResult<()> {
+        fn process(&mut self, _ctx: &CuContext, _empty_msg: Self::Output) -> CuResult<()> {
             Ok(())
         }
     }
@@ -411,7 +1395,7 @@ mod tests {
     impl CuSinkTask<'_> for TestSink {
         type Input = ();
 
-        fn process(&mut self, _clock: &RobotClock, _input: Self::Input) -> CuResult<()> {
+        fn process(&mut self, _ctx: &CuContext, _input: Self::Input) -> CuResult<()> {
             Ok(())
         }
     }
@@ -456,6 +1440,396 @@ mod tests {
         assert!(runtime.is_ok());
     }
 
+    #[test]
+    fn test_thread_schedulings() {
+        let mut config = CuConfig::default();
+        let mut source = Node::new("a", "TestSource");
+        source.set_core_affinity(Some(vec![0]));
+        config.add_node(source);
+        config.add_node(Node::new("b", "TestSink"));
+        config.connect(0, 1, "()");
+        let runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        assert_eq!(
+            runtime.schedulings[0].as_ref().unwrap().core_affinity,
+            vec![0]
+        );
+        assert!(runtime.schedulings[1].is_none());
+
+        config.graph[NodeIndex::new(1)].set_priority(Some(0));
+        assert!(CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_runtime_introspection() {
+        let mut config = CuConfig::default();
+        config.add_node(Node::new("a", "TestSource"));
+        let mut sink = Node::new("b", "TestSink");
+        sink.set_base_period_ns(Some(10_000_000));
+        config.add_node(sink);
+        config.connect(0, 1, "()");
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        runtime.tasks_stats[0].record_process(CuDuration(10).into(), &Ok(()));
+        runtime.tasks_stats[1].record_process(CuDuration(10).into(), &Err("boom".into()));
+
+        let info = runtime.introspect();
+        assert_eq!(info.nodes.len(), 2);
+        assert_eq!(info.edges.len(), 1);
+        let a = info.get_node("a").unwrap();
+        assert_eq!(a.task_type, CuTaskType::Source);
+        assert_eq!(a.health, CuHealth::Nominal);
+        let b = info.get_node("b").unwrap();
+        assert_eq!(b.type_name, "TestSink");
+        assert_eq!(b.configured_rate_hz, Some(100.0));
+        assert_eq!(b.health, CuHealth::Failed);
+        assert_eq!(info.edges[0].src, "a");
+        assert_eq!(info.edges[0].msg, "()");
+
+        let json = info.to_json().unwrap();
+        assert!(json.contains("\"TestSink\""));
+    }
+
+    #[test]
+    fn test_edges_staleness() {
+        let config = CuConfig::deserialize_ron(
+            r#"(
+                tasks: [(id: "imu", type: "TestSource"), (id: "fusion", type: "TestSink")],
+                cnx: [(src: "imu", dst: "fusion", msg: "()", max_age_ns: 1000)],
+            )"#,
+        );
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        assert_eq!(runtime.introspect().edges[0].health, CuHealth::Unknown);
+        runtime.record_output(0, CuTime::from(0), true);
+        assert_eq!(runtime.edges_stats[0].health, CuHealth::Nominal);
+        // The sink has no connection going out of it.
+        runtime.record_output(1, CuTime::from(500), true);
+        runtime.record_output(0, CuTime::from(1500), false);
+        assert_eq!(runtime.edges_stats[0].health, CuHealth::Degraded);
+        let edge = &runtime.introspect().edges[0];
+        assert_eq!(edge.max_age_ns, Some(1000));
+        assert_eq!(edge.health, CuHealth::Degraded);
+        assert!(edge.age.is_some());
+    }
+
+    #[test]
+    fn test_edges_deadline() {
+        let config = CuConfig::deserialize_ron(
+            r#"(
+                tasks: [(id: "imu", type: "TestSource"), (id: "fusion", type: "TestSink")],
+                cnx: [(src: "imu", dst: "fusion", msg: "()", deadline_ms: 1, deadline_policy: Trip)],
+            )"#,
+        );
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        assert_eq!(runtime.edges_by_dst, vec![vec![], vec![0]]);
+        runtime.record_output(0, CuTime::from(0), true);
+        assert!(runtime.record_delivery(1, CuTime::from(500_000)).is_ok());
+        runtime.record_output(0, CuTime::from(1_000_000), true);
+        let error = runtime
+            .record_delivery(1, CuTime::from(3_000_000))
+            .unwrap_err();
+        assert!(error.to_string().contains("'imu' missed its deadline"));
+        let edge = &runtime.introspect().edges[0];
+        assert_eq!(edge.deadline_ms, Some(1));
+        assert_eq!(edge.deadline_misses, 1);
+    }
+
+    #[test]
+    fn test_metrics_publication() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics");
+        let config = CuConfig::deserialize_ron(&format!(
+            r#"(
+                tasks: [(id: "imu", type: "TestSource"), (id: "fusion", type: "TestSink")],
+                cnx: [(src: "imu", dst: "fusion", msg: "()")],
+                runtime: (metrics_path: "{}"),
+            )"#,
+            path.display()
+        ));
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        runtime.tasks_stats[1].process_count = 3;
+        runtime.end_of_processing(0);
+        let snapshot = crate::metrics::CuMetricsReader::open(&path)
+            .unwrap()
+            .snapshot();
+        assert_eq!(snapshot.publications, 1);
+        assert_eq!(snapshot.tasks[1].name, "fusion");
+        assert_eq!(snapshot.tasks[1].process_count, 3);
+    }
+
+    #[test]
+    fn test_stubbed_tasks() {
+        let mut config = CuConfig::default();
+        config.add_node(Node::new("camera", "TestSource"));
+        config.add_node(Node::new("motors", "TestSink"));
+        config.connect(0, 1, "()");
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        assert_eq!(runtime.stubbed, vec![false, false]);
+        runtime.set_stubbed_tasks(&["motors"]).unwrap();
+        assert_eq!(runtime.stubbed, vec![false, true]);
+        let error = runtime.set_stubbed_tasks(&["motor"]).unwrap_err();
+        assert!(error.to_string().contains("Did you mean 'motors'?"));
+        assert_eq!(runtime.stubbed, vec![false, true]);
+        runtime.set_stubbed_tasks(&[]).unwrap();
+        assert_eq!(runtime.stubbed, vec![false, false]);
+    }
+
+    #[test]
+    fn test_disable_task() {
+        let mut config = CuConfig::default();
+        config.add_node(Node::new("camera", "TestSource"));
+        config.add_node(Node::new("motors", "TestSink"));
+        config.connect(0, 1, "()");
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        runtime.disable_task(0).unwrap();
+        runtime.disable_task(0).unwrap();
+        assert_eq!(runtime.disabled, vec![true, false]);
+        assert_eq!(runtime.health(), CuHealth::Nominal);
+        runtime.enable_task(0).unwrap();
+        assert_eq!(runtime.disabled, vec![false, false]);
+        assert!(runtime.disable_task(2).is_err());
+    }
+
+    #[test]
+    fn test_switch_mission() {
+        let config = read_configuration_str(
+            r#"(
+                tasks: [(id: "joystick", type: "TestSource"), (id: "motors", type: "TestSink")],
+                cnx: [(src: "joystick", dst: "motors", msg: "()")],
+                missions: [(id: "teleop", tasks: ["joystick"]), (id: "docked", tasks: [])],
+            )"#,
+        )
+        .unwrap();
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        assert_eq!(runtime.missions.current(), Some("teleop"));
+        assert_eq!(runtime.disabled, vec![false, false]);
+        runtime.switch_mission("docked").unwrap();
+        assert_eq!(runtime.disabled, vec![true, false]);
+        runtime.disable_task(1).unwrap();
+        runtime.switch_mission("teleop").unwrap();
+        assert_eq!(runtime.disabled, vec![false, true]);
+        assert!(runtime.switch_mission("auto").is_err());
+    }
+
+    #[test]
+    fn test_prune_task() {
+        let txt = r#"(
+            tasks: [
+                (id: "camera", type: "TestSource", optional: true),
+                (id: "detector", type: "TestTask", optional: true),
+                (id: "lidar", type: "TestSource", optional: true),
+                (id: "planner", type: "TestSink", optional: true),
+                (id: "motors", type: "TestSink"),
+            ],
+            cnx: [
+                (src: "camera", dst: "detector", msg: "()"),
+                (src: "detector", dst: "planner", msg: "()"),
+                (src: "lidar", dst: "planner", msg: "()"),
+                (src: "detector", dst: "motors", msg: "()"),
+            ],
+        )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        assert_eq!(runtime.health(), CuHealth::Nominal);
+
+        runtime.prune_task(0, &"no camera on /dev/video0".into());
+        // The planner still has the lidar, the motors are not optional.
+        assert_eq!(runtime.pruned, vec![true, true, false, false, false]);
+        assert_eq!(runtime.health(), CuHealth::Degraded);
+        assert_eq!(runtime.tasks_stats[1].health, CuHealth::Failed);
+        assert!(runtime.tasks_stats[0]
+            .last_error
+            .as_ref()
+            .unwrap()
+            .contains("/dev/video0"));
+
+        runtime.prune_task(2, &"no lidar".into());
+        assert_eq!(runtime.pruned, vec![true, true, true, true, false]);
+    }
+
+    #[test]
+    fn test_replay_mode() {
+        let mut config = CuConfig::default();
+        config.add_node(Node::new("camera", "TestSource"));
+        config.add_node(Node::new("motors", "TestSink"));
+        config.connect(0, 1, "()");
+        let (clock, clock_mock) = RobotClock::mock();
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            clock,
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        runtime.set_replay_mode(clock_mock);
+        assert_eq!(runtime.stubbed, vec![true, false]);
+        runtime.set_replay_time(Some(CuTime::from(42_000_000)));
+        assert_eq!(runtime.clock.now(), CuTime::from(42_000_000));
+        // A copper list where no task ran keeps the time.
+        runtime.set_replay_time(None);
+        assert_eq!(runtime.clock.now(), CuTime::from(42_000_000));
+
+        // 40ms of log replayed 2 times faster.
+        let factor = CuTimeFactor::default();
+        factor.set(2.0).unwrap();
+        runtime.pace_with(factor);
+        let started = std::time::Instant::now();
+        runtime.set_replay_time(Some(CuTime::from(60_000_000)));
+        runtime.set_replay_time(Some(CuTime::from(100_000_000)));
+        assert!(started.elapsed() >= std::time::Duration::from_millis(20));
+        assert_eq!(runtime.time_factor().map(CuTimeFactor::get), Some(2.0));
+    }
+
+    #[test]
+    fn test_set_param() {
+        let mut config = CuConfig::default();
+        let mut pid = Node::new("pid", "TestSource");
+        pid.set_param("kp", 1.0);
+        pid.set_permission("kp", CuRole::Engineer);
+        config.add_node(pid);
+        config.add_node(Node::new("motors", "TestSink"));
+        config.connect(0, 1, "()");
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        let operator = CuRoleToken::new(CuRole::Operator, "alice");
+        let engineer = CuRoleToken::new(CuRole::Engineer, "bob");
+        assert!(runtime
+            .set_param("pid", "kp", Value::from(2.0), Some(&operator))
+            .is_err());
+        assert!(runtime
+            .set_param(
+                "pid",
+                "permission.kp",
+                Value::from("operator".to_string()),
+                None
+            )
+            .is_err());
+        let change = runtime
+            .set_param("pid", "kp", Value::from(2.0), Some(&engineer))
+            .unwrap();
+        assert_eq!(change.old_value().unwrap(), Some(Value::from(1.0)));
+        assert_eq!(change.origin, "bob");
+        let param = |runtime: &CuRuntime<Tasks, Msgs, NoMonitor, 2>| {
+            runtime.tasks_configs[0].as_ref().unwrap().get::<f64>("kp")
+        };
+        assert_eq!(param(&runtime), Some(2.0));
+
+        // Replayed backward.
+        runtime.apply_param_change(&change.reverted()).unwrap();
+        assert_eq!(param(&runtime), Some(1.0));
+    }
+
+    #[test]
+    fn test_param_requests() {
+        let mut config = CuConfig::default();
+        let mut camera = Node::new("camera", "TestSource");
+        camera.set_base_period_ns(Some(10_000_000));
+        config.add_node(camera);
+        config.add_node(Node::new("derating", "TestSink"));
+        config.connect(0, 1, "()");
+        let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+            RobotClock::default(),
+            &config,
+            tasks_instanciator,
+            monitor_instanciator,
+            FakeWriter {},
+        )
+        .unwrap();
+        {
+            let ctx = CuContext::new(&runtime.clock, None)
+                .with_node(1, "derating", None)
+                .with_param_requests(&runtime.param_requests);
+            ctx.request_param("camera", "base_period_ns", 50_000_000u64)
+                .unwrap();
+            // Dropped without stopping the application.
+            ctx.request_param("nope", "fps", 5u32).unwrap();
+        }
+        runtime.apply_param_requests();
+        let camera = runtime.tasks_configs[0].as_ref().unwrap();
+        assert_eq!(
+            camera.try_get::<u64>("base_period_ns").unwrap(),
+            Some(50_000_000)
+        );
+        assert!(runtime.rates.is_due(0, CuTime::from(0)));
+        assert!(!runtime.rates.is_due(0, CuTime::from(30_000_000)));
+        assert!(runtime.param_requests.take().is_empty());
+    }
+
     #[test]
     fn test_copperlists_manager_lifecycle() {
         let mut config = CuConfig::default();
@@ -529,4 +1903,60 @@ mod tests {
 
         assert_eq!(runtime.available_copper_lists(), 2);
     }
+
+    #[derive(Debug)]
+    struct CountingWriter(Arc<Mutex<usize>>);
+
+    impl<E: Encode> WriteStream<E> for CountingWriter {
+        fn log(&mut self, _obj: &E) -> CuResult<()> {
+            *self.0.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_copperlists_exhaustion() {
+        for (policy, logged) in [("Error", 0), ("DropOldest", 0), ("LogOldest", 1)] {
+            let config = CuConfig::deserialize_ron(&format!(
+                r#"(
+                    tasks: [(id: "a", type: "TestSource"), (id: "b", type: "TestSink")],
+                    cnx: [(src: "a", dst: "b", msg: "()")],
+                    runtime: (exhaustion_policy: {}),
+                )"#,
+                policy
+            ));
+            let count = Arc::new(Mutex::new(0));
+            let mut runtime = CuRuntime::<Tasks, Msgs, NoMonitor, 2>::new(
+                RobotClock::default(),
+                &config,
+                tasks_instanciator,
+                monitor_instanciator,
+                CountingWriter(count.clone()),
+            )
+            .unwrap();
+            // 2 iterations abandoned on errors, their copper lists stay in flight.
+            for _ in 0..2 {
+                runtime.reserve_copper_list().unwrap();
+                let culist = runtime.copper_lists_manager.create().unwrap();
+                culist.change_state(CopperListState::Processing);
+            }
+            assert_eq!(runtime.copperlists_exhausted, 0);
+
+            let reserved = runtime.reserve_copper_list();
+            assert_eq!(runtime.copperlists_exhausted, 1);
+            assert_eq!(*count.lock().unwrap(), logged);
+            if policy == "Error" {
+                assert!(reserved.is_err());
+                assert_eq!(runtime.available_copper_lists(), 0);
+            } else {
+                assert!(reserved.is_ok());
+                assert_eq!(runtime.available_copper_lists(), 1);
+                let culist = runtime.copper_lists_manager.create().unwrap();
+                assert_eq!(culist.id, 2);
+                culist.change_state(CopperListState::Processing);
+                runtime.end_of_processing(2);
+                assert_eq!(runtime.available_copper_lists(), 1);
+            }
+        }
+    }
 }
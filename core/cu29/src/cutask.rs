@@ -3,6 +3,9 @@
 
 use crate::clock::OptionCuTime;
 use crate::config::ComponentConfig;
+use crate::context::CuContext;
+use crate::provenance::CuProvenance;
+use crate::selftest::CuSelfTest;
 use crate::CuResult;
 use bincode::de::Decoder;
 use bincode::de::{BorrowDecoder, Decode};
@@ -38,9 +41,10 @@ impl<'cl, T: CuMsgPayload> CuMsgPack<'cl> for (&'cl mut CuMsg<T>,) {}
 impl<'cl, T: CuMsgPayload> CuMsgPack<'cl> for &'cl mut CuMsg<T> {}
 impl<'cl> CuMsgPack<'cl> for () {}
 
-// Apply the macro to generate implementations for tuple sizes up to 5
+// Apply the macro to generate implementations for tuple sizes up to 8, ie. the joints of an arm.
 impl_cu_msg_pack! {
-    (T1, T2), (T1, T2, T3), (T1, T2, T3, T4), (T1, T2, T3, T4, T5) // TODO: continue if necessary
+    (T1, T2), (T1, T2, T3), (T1, T2, T3, T4), (T1, T2, T3, T4, T5), (T1, T2, T3, T4, T5, T6),
+    (T1, T2, T3, T4, T5, T6, T7), (T1, T2, T3, T4, T5, T6, T7, T8) // TODO: continue if necessary
 }
 
 // A convience macro to get from a payload or a list of payloads to a proper CuMsg or CuMsgPack
@@ -69,7 +73,7 @@ macro_rules! output_msg {
 // which is the maximum size for inline allocation (no heap)
 const COMPACT_STRING_CAPACITY: usize = size_of::<String>();
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CuCompactString(pub CompactString);
 
 impl Encode for CuCompactString {
@@ -81,7 +85,8 @@ impl Encode for CuCompactString {
 
 impl Decode for CuCompactString {
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
-        let bytes: [u8; COMPACT_STRING_CAPACITY] = Decode::decode(decoder)?;
+        // Encoded as a length and the bytes, see doc/wire_format.md.
+        let bytes: Vec<u8> = Decode::decode(decoder)?;
         let cstr = CompactString::from_utf8(bytes).map_err(|e| DecodeError::Utf8 { inner: e })?;
         Ok(CuCompactString(cstr))
     }
@@ -105,6 +110,8 @@ pub struct CuMsgMetadata {
     /// A small string for real time feedback purposes.
     /// This is usefull for to display on the field when the tasks are operating correctly.
     pub status_txt: CuCompactString,
+    /// The messages of the copper list this one was computed from, see [crate::provenance].
+    pub provenance: CuProvenance,
 }
 
 impl CuMsgMetadata {
@@ -143,6 +150,7 @@ impl Default for CuMsgMetadata {
             after_process: OptionCuTime::none(),
             tov: OptionCuTime::none(),
             status_txt: CuCompactString(CompactString::with_capacity(COMPACT_STRING_CAPACITY)),
+            provenance: CuProvenance::default(),
         }
     }
 }
@@ -196,7 +204,13 @@ pub trait Freezable {
 /// The CuTaskLifecycle trait is the base trait for all tasks in Copper.
 /// It defines the lifecycle of a task.
 /// It provides a default empty implementation as all those execution steps are optional.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a copper task",
+    note = "the types of the tasks in the copper config need to implement CuTaskLifecycle and one of CuSrcTask, CuTask or CuSinkTask"
+)]
 pub trait CuTaskLifecycle: Freezable {
+    /// Creates the task from its config. During a dry run (see [crate::config::is_dry_run]) a driver checks its
+    /// config without opening its hardware.
     fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
     where
         Self: Sized;
@@ -225,21 +239,42 @@ pub trait CuTaskLifecycle: Freezable {
     fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
         Ok(())
     }
+
+    /// Checks the hardware behind the task answers as expected, ie. reads its identification register, see
+    /// [crate::selftest]. Called after start, before the loop and never during it. Return an error if it fails.
+    fn self_test(&mut self, _clock: &RobotClock) -> CuResult<CuSelfTest> {
+        Ok(CuSelfTest::NotImplemented)
+    }
+
+    /// The current state of a modal task (see [crate::fsm]), it is reported by the introspection.
+    /// This is called after every process so it should not allocate.
+    fn current_state(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// A Src Task is a task that only produces messages. For example drivers for sensors are Src Tasks.
 /// They are in push mode from the runtime.
 /// To set the frequency of the pulls and align them to any hw, see the runtime configuration.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a copper source task",
+    note = "the task is connected as a source in the copper config: it has outputs but no inputs"
+)]
 pub trait CuSrcTask<'cl>: CuTaskLifecycle {
     type Output: CuMsgPack<'cl>;
 
     /// Process is the most critical execution of the task.
     /// The goal will be to produce the output message as soon as possible.
     /// Use preprocess to prepare the task to make this method as short as possible.
-    fn process(&mut self, clock: &RobotClock, new_msg: Self::Output) -> CuResult<()>;
+    /// The context gives the clock and the time measured since the previous process call.
+    fn process(&mut self, ctx: &CuContext, new_msg: Self::Output) -> CuResult<()>;
 }
 
 /// This is the most generic Task of copper. It is a "transform" task deriving an output from an input.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a regular copper task",
+    note = "the task is connected with inputs and outputs in the copper config"
+)]
 pub trait CuTask<'cl>: CuTaskLifecycle {
     type Input: CuMsgPack<'cl>;
     type Output: CuMsgPack<'cl>;
@@ -247,20 +282,26 @@ pub trait CuTask<'cl>: CuTaskLifecycle {
     /// Process is the most critical execution of the task.
     /// The goal will be to produce the output message as soon as possible.
     /// Use preprocess to prepare the task to make this method as short as possible.
+    /// The context gives the clock and the time measured since the previous process call.
     fn process(
         &mut self,
-        clock: &RobotClock,
+        ctx: &CuContext,
         input: Self::Input,
         output: Self::Output,
     ) -> CuResult<()>;
 }
 
 /// A Sink Task is a task that only consumes messages. For example drivers for actuators are Sink Tasks.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a copper sink task",
+    note = "the task is connected as a sink in the copper config: it has inputs but no outputs"
+)]
 pub trait CuSinkTask<'cl>: CuTaskLifecycle {
     type Input: CuMsgPack<'cl>;
 
     /// Process is the most critical execution of the task.
     /// The goal will be to produce the output message as soon as possible.
     /// Use preprocess to prepare the task to make this method as short as possible.
-    fn process(&mut self, clock: &RobotClock, input: Self::Input) -> CuResult<()>;
+    /// The context gives the clock and the time measured since the previous process call.
+    fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()>;
 }
@@ -0,0 +1,282 @@
+//! Derived log channels: downsampled copies of the outputs of tasks the runtime writes in their own sections of the
+//! log, ie. a 10 Hz copy of a 1 kHz IMU tagged "telemetry", so the export for a dashboard reads a few MB instead of
+//! going through the full rate copper lists.
+//!
+//! They are declared in the logging section of the config:
+//! ```ron
+//! logging: (derived: [(task: "imu", rate_hz: 10.0, tag: "telemetry")]),
+//! ```
+//! The payloads are kept in the wire format, cu29_export decodes the ones whose type is registered in
+//! cu29::registry.
+
+use crate::clock::{CuDuration, CuTime};
+use crate::config::CuConfig;
+use crate::copperlist::CuListDumper;
+use crate::{CuError, CuResult};
+use bincode::{Decode, Encode};
+use cu29_log_derive::debug;
+use cu29_traits::{UnifiedLogType, WriteStream};
+use cu29_unifiedlog::{stream_write, UnifiedLoggerWrite};
+use serde_derive::Serialize;
+use std::sync::{Arc, Mutex};
+
+/// The size of the sections of the log the derived messages are written in.
+const DERIVED_SECTION_SIZE: usize = 64 * 1024;
+
+/// A message of a derived channel.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub struct CuDerivedMsg {
+    pub tag: String,
+    pub task: String,
+    pub msg_type: String,
+    /// When the iteration that produced the message started.
+    pub time: CuTime,
+    /// The payload in the wire format.
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct DerivedChannel {
+    task: String,
+    tag: String,
+    period: CuDuration,
+    /// The position of the output of the task in the copper lists and its type, found on the first copper list.
+    slot: Option<(usize, String)>,
+    last: Option<CuTime>,
+}
+
+/// Writes the derived channels declared in the config from the copper lists the runtime logs.
+#[derive(Debug, Default)]
+pub struct CuDerivedLogger {
+    channels: Vec<DerivedChannel>,
+    resolved: bool,
+    stream: Option<Box<dyn WriteStream<CuDerivedMsg>>>,
+}
+
+impl CuDerivedLogger {
+    pub fn new(config: &CuConfig) -> CuResult<Self> {
+        let Some(logging) = config.get_logging_config() else {
+            return Ok(Self::default());
+        };
+        let nodes = config.get_all_nodes();
+        let channels = logging
+            .derived
+            .iter()
+            .map(|derived| {
+                if !nodes.iter().any(|node| node.get_id() == derived.task) {
+                    return Err(CuError::from(format!(
+                        "The derived channel '{}' copies the task '{}' which is not declared.",
+                        derived.tag, derived.task
+                    )));
+                }
+                if !(derived.rate_hz.is_finite() && derived.rate_hz > 0.0) {
+                    return Err(CuError::from(format!(
+                        "The rate of the derived channel '{}' has to be positive, not {}.",
+                        derived.tag, derived.rate_hz
+                    )));
+                }
+                Ok(DerivedChannel {
+                    task: derived.task.clone(),
+                    tag: derived.tag.clone(),
+                    period: CuDuration((1_000_000_000.0 / derived.rate_hz) as u64),
+                    slot: None,
+                    last: None,
+                })
+            })
+            .collect::<CuResult<_>>()?;
+        Ok(CuDerivedLogger {
+            channels,
+            ..Default::default()
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// The log the derived messages are written to, nothing is written without one.
+    pub fn set_logger(&mut self, logger: Arc<Mutex<UnifiedLoggerWrite>>) {
+        if !self.is_empty() {
+            self.set_stream(stream_write(
+                logger,
+                UnifiedLogType::DerivedChannel,
+                DERIVED_SECTION_SIZE,
+            ));
+        }
+    }
+
+    pub fn set_stream(&mut self, stream: impl WriteStream<CuDerivedMsg> + 'static) {
+        self.stream = Some(Box::new(stream));
+    }
+
+    /// Writes the outputs of the copper list whose derived channel is due.
+    /// A message without payload is skipped, the next one with a payload is written instead.
+    pub fn record<P: CuListDumper>(&mut self, msgs: &P) {
+        if self.is_empty() {
+            return;
+        }
+        if !self.resolved {
+            self.resolve(P::SCHEMA);
+        }
+        let Some(time) = msgs.start_time() else {
+            return;
+        };
+        for channel in &mut self.channels {
+            let Some((index, msg_type)) = &channel.slot else {
+                continue;
+            };
+            if channel
+                .last
+                .is_some_and(|last| time < last + channel.period)
+            {
+                continue;
+            }
+            let Some(payload) = msgs.encode_payload(*index) else {
+                continue;
+            };
+            channel.last = Some(time);
+            let Some(stream) = &mut self.stream else {
+                continue;
+            };
+            let msg = CuDerivedMsg {
+                tag: channel.tag.clone(),
+                task: channel.task.clone(),
+                msg_type: msg_type.clone(),
+                time,
+                payload,
+            };
+            if let Err(e) = stream.log(&msg) {
+                debug!(
+                    "Logger: could not log the derived channel {}: {}",
+                    channel.tag.as_str(),
+                    e.to_string()
+                );
+            }
+        }
+    }
+
+    /// Finds the output of every task in the layout of the copper lists, see CuListDumper::SCHEMA.
+    fn resolve(&mut self, schema: &str) {
+        let slots: Vec<(&str, &str)> = schema
+            .split(';')
+            .filter_map(|slot| slot.split_once(':'))
+            .collect();
+        for channel in &mut self.channels {
+            channel.slot = slots
+                .iter()
+                .position(|(task, _)| *task == channel.task)
+                .map(|index| (index, slots[index].1.to_string()));
+            if channel.slot.is_none() {
+                debug!(
+                    "Logger: the task {} of the derived channel {} has no output, nothing is logged for it.",
+                    channel.task.as_str(),
+                    channel.tag.as_str()
+                );
+            }
+        }
+        self.resolved = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::copperlist::CuMsgDump;
+    use crate::cutask::CuMsg;
+    use crate::dynmsg::DynCuMsg;
+    use crate::summary::CuChannelStats;
+    use crate::wire::wire_config;
+
+    #[derive(Debug)]
+    struct Imu(CuMsg<u32>);
+
+    impl CuListDumper for Imu {
+        const SCHEMA: &'static str = "imu:u32";
+
+        fn dump_msgs(&self) -> Vec<CuMsgDump> {
+            vec![CuMsgDump::from_msg("imu", "u32", &self.0)]
+        }
+
+        fn start_time(&self) -> Option<CuTime> {
+            self.0.metadata.before_process.into()
+        }
+
+        fn record_stats(&self, _channels: &mut [CuChannelStats]) {}
+
+        fn dyn_msgs(&self) -> Vec<(&'static str, DynCuMsg)> {
+            Vec::new()
+        }
+
+        fn encode_payload(&self, index: usize) -> Option<Vec<u8>> {
+            match index {
+                0 => self
+                    .0
+                    .payload()
+                    .map(|payload| bincode::encode_to_vec(payload, wire_config()).unwrap()),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Collected(Arc<Mutex<Vec<CuDerivedMsg>>>);
+
+    impl WriteStream<CuDerivedMsg> for Collected {
+        fn log(&mut self, obj: &CuDerivedMsg) -> CuResult<()> {
+            self.0.lock().unwrap().push(obj.clone());
+            Ok(())
+        }
+    }
+
+    fn config(logging: &str) -> CuConfig {
+        CuConfig::deserialize_ron(&format!(
+            r#"( tasks: [(id: "imu", type: "Imu")], cnx: [], logging: {} )"#,
+            logging
+        ))
+    }
+
+    #[test]
+    fn test_downsampling() {
+        let mut logger = CuDerivedLogger::new(&config(
+            r#"(derived: [(task: "imu", rate_hz: 10.0, tag: "telemetry")])"#,
+        ))
+        .unwrap();
+        let collected = Collected::default();
+        logger.set_stream(collected.clone());
+        // 1 kHz for 350 ms, without payload from 100 to 110 ms.
+        for i in 0..350u64 {
+            let payload = (!(100..110).contains(&i)).then_some(i as u32);
+            let mut msg = CuMsg::new(payload);
+            msg.metadata.before_process = CuTime::from(i * 1_000_000).into();
+            logger.record(&Imu(msg));
+        }
+        let collected = collected.0.lock().unwrap();
+        let values: Vec<u32> = collected
+            .iter()
+            .map(|msg| {
+                bincode::decode_from_slice(&msg.payload, wire_config())
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        assert_eq!(values, [0, 110, 210, 310]);
+        assert_eq!(collected[0].tag, "telemetry");
+        assert_eq!(collected[0].msg_type, "u32");
+        assert_eq!(collected[1].time, CuTime::from(110_000_000));
+    }
+
+    #[test]
+    fn test_invalid_channels() {
+        assert!(CuDerivedLogger::new(&config(
+            r#"(derived: [(task: "gps", rate_hz: 10.0, tag: "telemetry")])"#
+        ))
+        .is_err());
+        assert!(CuDerivedLogger::new(&config(
+            r#"(derived: [(task: "imu", rate_hz: 0.0, tag: "telemetry")])"#
+        ))
+        .is_err());
+        assert!(CuDerivedLogger::new(&CuConfig::default())
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,369 @@
+//! Buffers in the memory of a GPU or of another device, for the pipelines which never bring their data back to
+//! the CPU, ie. capture → inference → encoding on a Jetson.
+//!
+//! A [CuDeviceHandle] describes the buffer, a CUDA device pointer or a DMABUF file descriptor; copper never touches
+//! its content. The ownership follows the rules of the [crate::pool::CuHandle]:
+//! - the task producing the buffer fills it while it holds the only handle, and synchronizes its device stream
+//!   before setting it in its output,
+//! - from then on the buffer is read-only, a task reading it keeps a clone of the handle as long as it needs it,
+//! - the buffer goes back to its owner, its pool or the release given at its creation, when the last handle is
+//!   dropped.
+//!
+//! A task can write in a buffer it received once it holds the only handle, see [CuDeviceHandle::memory_mut].
+//! The log only records the kind and the size of the buffers: a handle decoded from a log has no memory.
+//!
+//! ```
+//! use cu29::device::{CuDeviceMemory, CuDevicePool};
+//!
+//! // The buffers come from the driver or from cudaMalloc, the release frees them when the pool is gone.
+//! let buffers = vec![CuDeviceMemory::DmaBuf { fd: 10 }, CuDeviceMemory::DmaBuf { fd: 11 }];
+//! let pool = CuDevicePool::new("frames", buffers, 640 * 480 * 2, |_| {});
+//! let mut frame = pool.acquire().unwrap();
+//! assert_eq!(frame.memory_mut(), Some(CuDeviceMemory::DmaBuf { fd: 11 }));
+//! assert_eq!(pool.stats().available, 1);
+//! ```
+
+use crate::pool::{register_stats_source, CuPoolStats, PoolStatsSource, FREE_LOCK};
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use cu29_traits::lockaudit;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum CuDeviceKind {
+    Cuda,
+    DmaBuf,
+}
+
+/// Where a device buffer is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CuDeviceMemory {
+    /// A pointer in the memory of a CUDA device, ie. from cudaMalloc.
+    Cuda { device: u32, ptr: u64 },
+    /// A DMABUF exported by a driver, ie. V4L2 or NvBufSurface.
+    DmaBuf { fd: i32 },
+}
+
+impl CuDeviceMemory {
+    pub fn kind(&self) -> CuDeviceKind {
+        match self {
+            CuDeviceMemory::Cuda { .. } => CuDeviceKind::Cuda,
+            CuDeviceMemory::DmaBuf { .. } => CuDeviceKind::DmaBuf,
+        }
+    }
+}
+
+type Release = Box<dyn FnOnce(CuDeviceMemory) + Send + Sync>;
+
+enum Owner {
+    Pool(Arc<DevicePoolInner>),
+    Release(Release),
+    /// Owned by something outliving the handles, or decoded from a log.
+    None,
+}
+
+struct DeviceInner {
+    kind: CuDeviceKind,
+    memory: Option<CuDeviceMemory>,
+    len: usize,
+    owner: Owner,
+}
+
+impl Drop for DeviceInner {
+    fn drop(&mut self) {
+        let Some(memory) = self.memory else {
+            return;
+        };
+        match std::mem::replace(&mut self.owner, Owner::None) {
+            Owner::Pool(pool) => lockaudit::lock(&FREE_LOCK, &pool.free)
+                .unwrap()
+                .push(memory),
+            Owner::Release(release) => release(memory),
+            Owner::None => {}
+        }
+    }
+}
+
+/// A reference counted handle to a device buffer, usable as a payload or a field of a payload, see the module
+/// documentation for who can write in it. Cloning the handle does not copy the buffer.
+pub struct CuDeviceHandle(Arc<DeviceInner>);
+
+impl CuDeviceHandle {
+    /// A buffer outside of any pool. release gets its memory back when the last handle is dropped, ie. to queue it
+    /// back to its driver.
+    pub fn new_detached(
+        memory: CuDeviceMemory,
+        len: usize,
+        release: impl FnOnce(CuDeviceMemory) + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(memory, len, Owner::Release(Box::new(release)))
+    }
+
+    /// A buffer owned by something which outlives all the handles to it.
+    pub fn new_borrowed(memory: CuDeviceMemory, len: usize) -> Self {
+        Self::new(memory, len, Owner::None)
+    }
+
+    fn new(memory: CuDeviceMemory, len: usize, owner: Owner) -> Self {
+        CuDeviceHandle(Arc::new(DeviceInner {
+            kind: memory.kind(),
+            memory: Some(memory),
+            len,
+            owner,
+        }))
+    }
+
+    pub fn kind(&self) -> CuDeviceKind {
+        self.0.kind
+    }
+
+    /// The size of the buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// The buffer to read from, None for a handle decoded from a log.
+    pub fn memory(&self) -> Option<CuDeviceMemory> {
+        self.0.memory
+    }
+
+    /// The buffer to write to, only while this is the only handle pointing to it.
+    pub fn memory_mut(&mut self) -> Option<CuDeviceMemory> {
+        Arc::get_mut(&mut self.0).and_then(|inner| inner.memory)
+    }
+
+    pub fn is_pooled(&self) -> bool {
+        matches!(self.0.owner, Owner::Pool(_))
+    }
+}
+
+impl Clone for CuDeviceHandle {
+    fn clone(&self) -> Self {
+        CuDeviceHandle(self.0.clone())
+    }
+}
+
+impl Debug for CuDeviceHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CuDeviceHandle({:?}, len: {}", self.0.kind, self.0.len)?;
+        if let Owner::Pool(pool) = &self.0.owner {
+            write!(f, ", pool: {}", pool.id)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// What the log keeps of a device buffer.
+#[derive(Encode, Decode, Serialize, Deserialize)]
+struct LoggedBuffer {
+    kind: CuDeviceKind,
+    len: usize,
+}
+
+impl LoggedBuffer {
+    fn of(handle: &CuDeviceHandle) -> Self {
+        LoggedBuffer {
+            kind: handle.kind(),
+            len: handle.len(),
+        }
+    }
+
+    fn into_handle(self) -> CuDeviceHandle {
+        CuDeviceHandle(Arc::new(DeviceInner {
+            kind: self.kind,
+            memory: None,
+            len: self.len,
+            owner: Owner::None,
+        }))
+    }
+}
+
+impl Encode for CuDeviceHandle {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        LoggedBuffer::of(self).encode(encoder)
+    }
+}
+
+impl Decode for CuDeviceHandle {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(LoggedBuffer::decode(decoder)?.into_handle())
+    }
+}
+
+impl<'de> BorrowDecode<'de> for CuDeviceHandle {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(LoggedBuffer::decode(decoder)?.into_handle())
+    }
+}
+
+impl Serialize for CuDeviceHandle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LoggedBuffer::of(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CuDeviceHandle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(LoggedBuffer::deserialize(deserializer)?.into_handle())
+    }
+}
+
+struct DevicePoolInner {
+    id: String,
+    capacity: usize,
+    buffer_len: usize,
+    free: Mutex<Vec<CuDeviceMemory>>,
+    exhausted: AtomicU64,
+    release: Box<dyn Fn(CuDeviceMemory) + Send + Sync>,
+}
+
+impl PoolStatsSource for DevicePoolInner {
+    fn stats(&self) -> CuPoolStats {
+        CuPoolStats {
+            id: self.id.clone(),
+            capacity: self.capacity,
+            available: lockaudit::lock(&FREE_LOCK, &self.free).unwrap().len(),
+            buffer_len: self.buffer_len,
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+            fill: None,
+        }
+    }
+}
+
+/// All the buffers are back once the pool and its handles are gone.
+impl Drop for DevicePoolInner {
+    fn drop(&mut self) {
+        let free = std::mem::take(self.free.get_mut().unwrap());
+        free.into_iter().for_each(&self.release);
+    }
+}
+
+/// A fixed set of device buffers of the same size, allocated by the caller, recycled like the buffers of a
+/// [crate::pool::CuPool]. Cloning the pool gives another reference to the same buffers.
+pub struct CuDevicePool {
+    inner: Arc<DevicePoolInner>,
+}
+
+impl Clone for CuDevicePool {
+    fn clone(&self) -> Self {
+        CuDevicePool {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl CuDevicePool {
+    /// id is used to identify the pool in the statistics, buffer_len is the size of each buffer in bytes.
+    /// release frees a buffer once the pool and all the handles to it are dropped, ie. with cudaFree.
+    pub fn new(
+        id: &str,
+        buffers: Vec<CuDeviceMemory>,
+        buffer_len: usize,
+        release: impl Fn(CuDeviceMemory) + Send + Sync + 'static,
+    ) -> Self {
+        let inner = Arc::new(DevicePoolInner {
+            id: id.to_string(),
+            capacity: buffers.len(),
+            buffer_len,
+            free: Mutex::new(buffers),
+            exhausted: AtomicU64::new(0),
+            release: Box::new(release),
+        });
+        register_stats_source(inner.clone());
+        CuDevicePool { inner }
+    }
+
+    /// Takes a buffer from the pool, None if they are all in use.
+    pub fn acquire(&self) -> Option<CuDeviceHandle> {
+        let memory = lockaudit::lock(&FREE_LOCK, &self.inner.free).unwrap().pop();
+        match memory {
+            Some(memory) => Some(CuDeviceHandle::new(
+                memory,
+                self.inner.buffer_len,
+                Owner::Pool(self.inner.clone()),
+            )),
+            None => {
+                self.inner.exhausted.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CuPoolStats {
+        self.inner.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cuda(ptr: u64) -> CuDeviceMemory {
+        CuDeviceMemory::Cuda { device: 0, ptr }
+    }
+
+    #[test]
+    fn test_device_pool() {
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let pool = {
+            let released = released.clone();
+            CuDevicePool::new("test_device", vec![cuda(1), cuda(2)], 16, move |memory| {
+                released.lock().unwrap().push(memory)
+            })
+        };
+        let mut a = pool.acquire().unwrap();
+        assert_eq!(a.memory_mut(), Some(cuda(2)));
+        let reader = a.clone();
+        assert_eq!(a.memory_mut(), None);
+        assert_eq!(reader.memory(), Some(cuda(2)));
+        let b = pool.acquire().unwrap();
+        assert!(pool.acquire().is_none());
+        assert_eq!(pool.stats().exhausted, 1);
+
+        drop(a);
+        drop(reader);
+        assert_eq!(pool.stats().available, 1);
+        drop(pool);
+        assert!(released.lock().unwrap().is_empty());
+        // The last handle keeps the pool alive until it gives its buffer back.
+        drop(b);
+        let mut released = released.lock().unwrap().clone();
+        released.sort_by_key(|memory| format!("{:?}", memory));
+        assert_eq!(released, vec![cuda(1), cuda(2)]);
+    }
+
+    #[test]
+    fn test_detached_and_logged() {
+        let released = Arc::new(Mutex::new(None));
+        let handle = {
+            let released = released.clone();
+            CuDeviceHandle::new_detached(CuDeviceMemory::DmaBuf { fd: 7 }, 1024, move |memory| {
+                *released.lock().unwrap() = Some(memory)
+            })
+        };
+        let encoded = bincode::encode_to_vec(&handle, bincode::config::standard()).unwrap();
+        let (decoded, _): (CuDeviceHandle, usize) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        assert_eq!(
+            (decoded.kind(), decoded.len(), decoded.memory()),
+            (CuDeviceKind::DmaBuf, 1024, None)
+        );
+        assert!(!handle.is_pooled());
+
+        drop(handle);
+        drop(decoded);
+        assert_eq!(
+            *released.lock().unwrap(),
+            Some(CuDeviceMemory::DmaBuf { fd: 7 })
+        );
+    }
+}
@@ -0,0 +1,607 @@
+//! A standard format for the diagnostics of the tasks and the drivers, and their aggregation into a tree of the
+//! whole robot.
+//!
+//! A driver publishes a `CuDiagnosticStatus` named after its place in the robot, ie. "sensors/imu", with a level, a
+//! short message and a few key values. A `CuDiagnosticAggregator` folds the statuses of its inputs into a
+//! `CuDiagnosticTree`: every group of the paths ("sensors") takes the worst level of its children, and a status not
+//! updated for stale_ms turns Stale.
+//!
+//! ```ron
+//! (id: "diagnostics", type: "cu29::diagnostics::CuDiagnosticAggregator<2>", config: {"stale_ms": 500}),
+//! ```
+//!
+//! The tree is a message for the bridges. For the monitors, the aggregator reports itself Degraded while the worst
+//! level is not OK and names the worst status in the status of its output.
+//!
+//! The statuses and the trees have a fixed capacity, and the strings up to 24 bytes are stored inline: publishing
+//! them does not allocate.
+
+use crate::clock::{CuDuration, CuTime, RobotClock};
+use crate::config::ComponentConfig;
+use crate::context::CuContext;
+use crate::cutask::{CuCompactString, CuMsg, CuTask, CuTaskLifecycle, Freezable};
+use crate::monitoring::CuHealth;
+use crate::registry::register_json_msg_type;
+use crate::{input_msg, output_msg};
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use compact_str::{format_compact, CompactString, ToCompactString};
+use cu29_log_derive::debug;
+use cu29_traits::CuResult;
+use serde::de::Deserializer;
+use serde::ser::Serializer;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+/// The number of key values of a status.
+pub const MAX_DIAGNOSTIC_VALUES: usize = 8;
+
+/// The number of statuses and groups of a tree.
+pub const MAX_DIAGNOSTIC_ENTRIES: usize = 32;
+
+/// How bad a status is, from the best to the worst.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Encode,
+    Decode,
+    Serialize,
+    Deserialize,
+)]
+pub enum CuDiagnosticLevel {
+    #[default]
+    Ok,
+    Warn,
+    Error,
+    /// Not updated for longer than the stale timeout of the aggregator.
+    Stale,
+}
+
+impl fmt::Display for CuDiagnosticLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CuDiagnosticLevel::Ok => "OK",
+            CuDiagnosticLevel::Warn => "WARN",
+            CuDiagnosticLevel::Error => "ERROR",
+            CuDiagnosticLevel::Stale => "STALE",
+        })
+    }
+}
+
+/// At most N items stored inline, encoded like a Vec of them.
+#[derive(Debug, Clone)]
+struct Bounded<T, const N: usize> {
+    items: [T; N],
+    len: usize,
+}
+
+impl<T: Default, const N: usize> Default for Bounded<T, N> {
+    fn default() -> Self {
+        Bounded {
+            items: std::array::from_fn(|_| T::default()),
+            len: 0,
+        }
+    }
+}
+
+impl<T, const N: usize> Bounded<T, N> {
+    fn as_slice(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.items[..self.len]
+    }
+
+    /// Inserts the item at index, shifting the next ones. False when full.
+    fn insert(&mut self, index: usize, item: T) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.items[self.len] = item;
+        self.items[index..=self.len].rotate_right(1);
+        self.len += 1;
+        true
+    }
+
+    fn push(&mut self, item: T) -> bool {
+        self.insert(self.len, item)
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for Bounded<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for Bounded<T, N> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        (self.len as u64).encode(encoder)?;
+        self.as_slice()
+            .iter()
+            .try_for_each(|item| item.encode(encoder))
+    }
+}
+
+impl<T: Decode + Default, const N: usize> Decode for Bounded<T, N> {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let len = u64::decode(decoder)? as usize;
+        if len > N {
+            return Err(DecodeError::ArrayLengthMismatch {
+                required: N,
+                found: len,
+            });
+        }
+        let mut bounded = Self::default();
+        for item in bounded.items.iter_mut().take(len) {
+            *item = T::decode(decoder)?;
+        }
+        bounded.len = len;
+        Ok(bounded)
+    }
+}
+
+impl<'de, T: Decode + Default, const N: usize> BorrowDecode<'de> for Bounded<T, N> {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Self::decode(decoder)
+    }
+}
+
+impl<T: serde::Serialize, const N: usize> serde::Serialize for Bounded<T, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_slice().serialize(serializer)
+    }
+}
+
+impl<'de, T: serde::Deserialize<'de> + Default, const N: usize> serde::Deserialize<'de>
+    for Bounded<T, N>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        if items.len() > N {
+            return Err(serde::de::Error::invalid_length(
+                items.len(),
+                &format!("at most {} items", N).as_str(),
+            ));
+        }
+        let mut bounded = Self::default();
+        items.into_iter().for_each(|item| {
+            bounded.push(item);
+        });
+        Ok(bounded)
+    }
+}
+
+/// A key and its value, ie. ("temperature", "42.5").
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct CuDiagnosticValue {
+    pub key: CuCompactString,
+    pub value: CuCompactString,
+}
+
+/// The diagnostic of a component of the robot.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct CuDiagnosticStatus {
+    /// The path of the component in the robot, '/' separated, ie. "sensors/imu".
+    pub name: CuCompactString,
+    pub level: CuDiagnosticLevel,
+    /// A short summary for a human.
+    pub message: CuCompactString,
+    values: Bounded<CuDiagnosticValue, MAX_DIAGNOSTIC_VALUES>,
+}
+
+impl CuDiagnosticStatus {
+    pub fn new(
+        name: impl ToCompactString,
+        level: CuDiagnosticLevel,
+        message: impl ToCompactString,
+    ) -> Self {
+        CuDiagnosticStatus {
+            name: CuCompactString(name.to_compact_string()),
+            level,
+            message: CuCompactString(message.to_compact_string()),
+            values: Bounded::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.0.as_str()
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.0.as_str()
+    }
+
+    /// Sets the value of key, ie. `status.set_value("temperature", 42.5)`. False when the status already has
+    /// MAX_DIAGNOSTIC_VALUES other keys.
+    pub fn set_value(&mut self, key: &str, value: impl ToCompactString) -> bool {
+        let value = CuCompactString(value.to_compact_string());
+        if let Some(existing) = self
+            .values
+            .as_mut_slice()
+            .iter_mut()
+            .find(|existing| existing.key.0 == key)
+        {
+            existing.value = value;
+            return true;
+        }
+        self.values.push(CuDiagnosticValue {
+            key: CuCompactString(CompactString::from(key)),
+            value,
+        })
+    }
+
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.values().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// The keys and their values in the order they were set.
+    pub fn values(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .as_slice()
+            .iter()
+            .map(|value| (value.key.0.as_str(), value.value.0.as_str()))
+    }
+}
+
+/// The statuses of the robot and the groups of their paths, in the order of their names: a group comes right before
+/// its children.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct CuDiagnosticTree {
+    entries: Bounded<CuDiagnosticStatus, MAX_DIAGNOSTIC_ENTRIES>,
+}
+
+impl CuDiagnosticTree {
+    pub fn len(&self) -> usize {
+        self.entries.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CuDiagnosticStatus> {
+        self.entries.as_slice().iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CuDiagnosticStatus> {
+        self.find(name).ok().map(|index| &self.entries.items[index])
+    }
+
+    /// The worst level of the robot, OK when it is empty.
+    pub fn level(&self) -> CuDiagnosticLevel {
+        self.iter()
+            .map(|status| status.level)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// The first status at the worst level which is not a group, the one to report.
+    pub fn worst(&self) -> Option<&CuDiagnosticStatus> {
+        let level = self.level();
+        let is_group = |name: &str| {
+            self.iter().any(|other| {
+                other
+                    .name()
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+        };
+        self.iter()
+            .find(|status| status.level == level && !is_group(status.name()))
+    }
+
+    fn find(&self, name: &str) -> Result<usize, usize> {
+        self.entries
+            .as_slice()
+            .binary_search_by(|entry| entry.name().cmp(name))
+    }
+
+    /// Adds a status and folds its level into the groups of its path: a group takes the worst level of its children
+    /// and names the first child at this level in its message. A status with the name of a group keeps the worst
+    /// level of both. False when the tree is full, the status is then missing or only partially folded.
+    pub fn insert(&mut self, status: &CuDiagnosticStatus) -> bool {
+        let name = status.name();
+        let groups = name
+            .match_indices('/')
+            .map(|(end, _)| &name[..end])
+            .filter(|group| !group.is_empty());
+        for group in groups {
+            let index = match self.find(group) {
+                Ok(index) => index,
+                Err(index) => {
+                    if !self.entries.insert(
+                        index,
+                        CuDiagnosticStatus::new(group, CuDiagnosticLevel::Ok, ""),
+                    ) {
+                        return false;
+                    }
+                    index
+                }
+            };
+            let entry = &mut self.entries.items[index];
+            if status.level > entry.level {
+                entry.level = status.level;
+                entry.message = status.name.clone();
+            }
+        }
+        match self.find(name) {
+            Ok(index) => {
+                let entry = &mut self.entries.items[index];
+                let level = entry.level.max(status.level);
+                *entry = status.clone();
+                entry.level = level;
+                true
+            }
+            Err(index) => self.entries.insert(index, status.clone()),
+        }
+    }
+}
+
+/// Registers the diagnostic messages in cu29::registry for the bridges and the exporters.
+pub fn register_msg_types() -> CuResult<()> {
+    register_json_msg_type::<CuDiagnosticStatus>("cu29::diagnostics::CuDiagnosticStatus")?;
+    register_json_msg_type::<CuDiagnosticTree>("cu29::diagnostics::CuDiagnosticTree")
+}
+
+/// Aggregates the CuDiagnosticStatus of its N inputs into a CuDiagnosticTree, see the module documentation.
+///
+/// An input without a new message keeps its last status. With stale_ms in the config, the last status of an input
+/// older than that is published Stale. From 2 to 8 inputs.
+pub struct CuDiagnosticAggregator<const N: usize> {
+    stale: Option<CuDuration>,
+    /// The last status of each input and when it was received.
+    last: [Option<(CuDiagnosticStatus, CuTime)>; N],
+    level: CuDiagnosticLevel,
+}
+
+impl<const N: usize> Freezable for CuDiagnosticAggregator<N> {}
+
+impl<const N: usize> CuTaskLifecycle for CuDiagnosticAggregator<N> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuDiagnosticAggregator {
+            stale: config
+                .and_then(|config| config.get::<u32>("stale_ms"))
+                .map(|ms| CuDuration::from(ms as u64 * 1_000_000)),
+            last: std::array::from_fn(|_| None),
+            level: CuDiagnosticLevel::Ok,
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.last = std::array::from_fn(|_| None);
+        self.level = CuDiagnosticLevel::Ok;
+        Ok(())
+    }
+}
+
+impl<const N: usize> CuDiagnosticAggregator<N> {
+    fn aggregate(
+        &mut self,
+        ctx: &CuContext,
+        inputs: [&CuMsg<CuDiagnosticStatus>; N],
+        output: &mut CuMsg<CuDiagnosticTree>,
+    ) {
+        let now = ctx.now();
+        for (last, input) in self.last.iter_mut().zip(inputs.iter()) {
+            if let Some(status) = input.payload() {
+                match last {
+                    Some((last, received)) => {
+                        last.clone_from(status);
+                        *received = now;
+                    }
+                    None => *last = Some((status.clone(), now)),
+                }
+            }
+        }
+
+        let mut tree = CuDiagnosticTree::default();
+        for (status, received) in self.last.iter().flatten() {
+            let inserted = if self.stale.is_some_and(|stale| now - *received > stale) {
+                let mut stale = status.clone();
+                stale.level = CuDiagnosticLevel::Stale;
+                tree.insert(&stale)
+            } else {
+                tree.insert(status)
+            };
+            if !inserted {
+                debug!("Diagnostics: the tree is full, some statuses are missing.");
+                break;
+            }
+        }
+
+        let level = tree.level();
+        if level != self.level {
+            self.level = level;
+            let worst = tree.worst().map(|worst| worst.name()).unwrap_or_default();
+            debug!(
+                "Diagnostics: the robot is {} ({}).",
+                level.to_string(),
+                worst.to_string()
+            );
+        }
+        if level == CuDiagnosticLevel::Ok {
+            ctx.report_health(CuHealth::Nominal);
+            output.metadata.set_status("");
+        } else {
+            ctx.report_health(CuHealth::Degraded);
+            if let Some(worst) = tree.worst() {
+                output
+                    .metadata
+                    .set_status(format_compact!("{} {}", level, worst.name()));
+            }
+        }
+        output.metadata.tov = inputs
+            .iter()
+            .filter_map(|input| -> Option<CuTime> { input.metadata.tov.into() })
+            .max()
+            .into();
+        output.set_payload(tree);
+    }
+}
+
+/// The CuTask of the aggregators of 2 to 8 inputs, whose inputs are tuples.
+macro_rules! impl_diagnostic_aggregator {
+    ($($n:literal: ($($input:ident),+)),+) => {
+        $(
+            impl<'cl> CuTask<'cl> for CuDiagnosticAggregator<$n> {
+                type Input = input_msg!('cl, $(impl_diagnostic_aggregator!(@status $input)),+);
+                type Output = output_msg!('cl, CuDiagnosticTree);
+
+                fn process(
+                    &mut self,
+                    ctx: &CuContext,
+                    input: Self::Input,
+                    output: Self::Output,
+                ) -> CuResult<()> {
+                    let ($($input,)+) = input;
+                    self.aggregate(ctx, [$($input),+], output);
+                    Ok(())
+                }
+            }
+        )+
+    };
+    (@status $input:ident) => {
+        CuDiagnosticStatus
+    };
+}
+
+impl_diagnostic_aggregator! {
+    2: (s1, s2),
+    3: (s1, s2, s3),
+    4: (s1, s2, s3, s4),
+    5: (s1, s2, s3, s4, s5),
+    6: (s1, s2, s3, s4, s5, s6),
+    7: (s1, s2, s3, s4, s5, s6, s7),
+    8: (s1, s2, s3, s4, s5, s6, s7, s8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::config::standard;
+    use bincode::{decode_from_slice, encode_to_vec};
+    use std::time::Duration;
+
+    fn status(name: &str, level: CuDiagnosticLevel) -> CuDiagnosticStatus {
+        CuDiagnosticStatus::new(name, level, "")
+    }
+
+    #[test]
+    fn test_status() {
+        let mut imu = CuDiagnosticStatus::new("sensors/imu", CuDiagnosticLevel::Warn, "hot");
+        assert!(imu.set_value("temperature", 80.5));
+        assert!(imu.set_value("temperature", 81.5));
+        for i in 1..MAX_DIAGNOSTIC_VALUES {
+            assert!(imu.set_value(&format!("k{}", i), i));
+        }
+        assert!(!imu.set_value("full", 0));
+        assert_eq!(imu.value("temperature"), Some("81.5"));
+        assert_eq!(imu.values().count(), MAX_DIAGNOSTIC_VALUES);
+
+        let bytes = encode_to_vec(&imu, standard()).unwrap();
+        let (decoded, _): (CuDiagnosticStatus, usize) =
+            decode_from_slice(&bytes, standard()).unwrap();
+        assert_eq!(decoded, imu);
+        let json = serde_json::to_string(&imu).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CuDiagnosticStatus>(&json).unwrap(),
+            imu
+        );
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut tree = CuDiagnosticTree::default();
+        assert_eq!(tree.level(), CuDiagnosticLevel::Ok);
+        assert!(tree.insert(&status("sensors/lidar", CuDiagnosticLevel::Ok)));
+        assert!(tree.insert(&status("sensors/imu", CuDiagnosticLevel::Error)));
+        assert!(tree.insert(&status("motors/left", CuDiagnosticLevel::Warn)));
+        let names: Vec<&str> = tree.iter().map(|status| status.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "motors",
+                "motors/left",
+                "sensors",
+                "sensors/imu",
+                "sensors/lidar"
+            ]
+        );
+        let sensors = tree.get("sensors").unwrap();
+        assert_eq!(sensors.level, CuDiagnosticLevel::Error);
+        assert_eq!(sensors.message(), "sensors/imu");
+        assert_eq!(tree.get("motors").unwrap().level, CuDiagnosticLevel::Warn);
+        assert_eq!(tree.level(), CuDiagnosticLevel::Error);
+        assert_eq!(tree.worst().unwrap().name(), "sensors/imu");
+
+        let bytes = encode_to_vec(&tree, standard()).unwrap();
+        let (decoded, _): (CuDiagnosticTree, usize) =
+            decode_from_slice(&bytes, standard()).unwrap();
+        assert_eq!(decoded, tree);
+
+        tree.clear();
+        for i in 0..MAX_DIAGNOSTIC_ENTRIES {
+            assert!(tree.insert(&status(&format!("s{:02}", i), CuDiagnosticLevel::Ok)));
+        }
+        assert!(!tree.insert(&status("full", CuDiagnosticLevel::Ok)));
+    }
+
+    #[test]
+    fn test_aggregator() {
+        let mut config = ComponentConfig::new();
+        config.set("stale_ms", 100u32);
+        let mut aggregator = CuDiagnosticAggregator::<2>::new(Some(&config)).unwrap();
+        let (clock, mock) = RobotClock::mock();
+        let mut output = CuMsg::new(None);
+        let imu = CuMsg::new(Some(status("sensors/imu", CuDiagnosticLevel::Ok)));
+        let lidar = CuMsg::new(Some(status("sensors/lidar", CuDiagnosticLevel::Ok)));
+        let none = CuMsg::new(None);
+
+        let ctx = CuContext::from(&clock);
+        aggregator
+            .process(&ctx, (&imu, &lidar), &mut output)
+            .unwrap();
+        assert_eq!(output.payload().unwrap().len(), 3);
+        assert_eq!(ctx.reported_health(), Some(CuHealth::Nominal));
+
+        // The lidar stops reporting.
+        mock.increment(Duration::from_millis(150));
+        let ctx = CuContext::from(&clock);
+        aggregator
+            .process(&ctx, (&imu, &none), &mut output)
+            .unwrap();
+        let tree = output.payload().unwrap();
+        assert_eq!(
+            tree.get("sensors/lidar").unwrap().level,
+            CuDiagnosticLevel::Stale
+        );
+        assert_eq!(
+            tree.get("sensors/imu").unwrap().level,
+            CuDiagnosticLevel::Ok
+        );
+        assert_eq!(ctx.reported_health(), Some(CuHealth::Degraded));
+        assert_eq!(output.metadata.status_txt.0.as_str(), "STALE sensors/lidar");
+    }
+}
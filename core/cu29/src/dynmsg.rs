@@ -0,0 +1,224 @@
+//! An untyped view of a message, with the names and values of the fields of its payload, so a generic sink
+//! (Foxglove, MQTT, a console...) is written once for every payload type registered in cu29::registry.
+
+use crate::clock::CuTime;
+use crate::cutask::{CuMsg, CuMsgPayload};
+use crate::registry::{msg_type, msg_type_of, CuMsgType};
+use crate::{CuError, CuResult};
+use cu29_log::value::Value;
+use std::sync::Arc;
+
+/// A message of any registered payload type.
+#[derive(Debug, Clone)]
+pub struct DynCuMsg {
+    msg_type: Arc<CuMsgType>,
+    /// The time of validity of the message.
+    pub tov: Option<CuTime>,
+    /// The fields of the payload, None if there is no payload.
+    pub payload: Option<Value>,
+}
+
+impl DynCuMsg {
+    /// The view of a message, its payload type has to be registered with register_json_msg_type.
+    pub fn from_msg<T: CuMsgPayload + 'static>(msg: &CuMsg<T>) -> CuResult<Self> {
+        let msg_type = msg_type_of::<T>().ok_or_else(|| {
+            CuError::from(format!(
+                "The payload type {} is not registered.",
+                std::any::type_name::<T>()
+            ))
+        })?;
+        let payload = msg
+            .payload()
+            .map(|payload| msg_type.to_value(payload))
+            .transpose()?;
+        Ok(DynCuMsg {
+            msg_type,
+            tov: msg.metadata.tov.into(),
+            payload,
+        })
+    }
+
+    /// The view of a payload received as bytes in the wire format, ie. by a bridge.
+    pub fn decode(type_name: &str, bytes: &[u8]) -> CuResult<Self> {
+        let msg_type = msg_type(type_name)
+            .ok_or_else(|| CuError::from(format!("Unknown message type {}.", type_name)))?;
+        let payload = msg_type.to_value(&*msg_type.decode(bytes)?)?;
+        Ok(DynCuMsg {
+            msg_type,
+            tov: None,
+            payload: Some(payload),
+        })
+    }
+
+    /// The registered name of the payload type.
+    pub fn type_name(&self) -> &str {
+        self.msg_type.name()
+    }
+
+    pub fn msg_type(&self) -> &CuMsgType {
+        &self.msg_type
+    }
+
+    /// The leaves of the payload with their path, ie. ("pose.x", 1.0) or ("ranges.3", 2.5), sorted by path.
+    /// A payload that is a single value has the path "value".
+    pub fn fields(&self) -> Vec<(String, &Value)> {
+        let mut fields = Vec::new();
+        if let Some(payload) = &self.payload {
+            collect_fields(String::new(), payload, &mut fields);
+        }
+        fields
+    }
+
+    /// The value at a path of fields(), None if there is no such field.
+    pub fn field(&self, path: &str) -> Option<&Value> {
+        self.fields()
+            .into_iter()
+            .find(|(field, _)| field == path)
+            .map(|(_, value)| value)
+    }
+
+    /// The payload as JSON, null if there is no payload.
+    pub fn to_json(&self) -> String {
+        self.payload
+            .as_ref()
+            .map(to_json)
+            .unwrap_or(serde_json::Value::Null)
+            .to_string()
+    }
+}
+
+fn collect_fields<'a>(path: String, value: &'a Value, fields: &mut Vec<(String, &'a Value)>) {
+    let child = |name: String| {
+        if path.is_empty() {
+            name
+        } else {
+            format!("{}.{}", path, name)
+        }
+    };
+    match value {
+        Value::Map(map) => {
+            for (key, value) in map {
+                let name = match key {
+                    Value::String(name) => name.clone(),
+                    key => key.to_string(),
+                };
+                collect_fields(child(name), value, fields);
+            }
+        }
+        Value::Seq(values) => {
+            for (i, value) in values.iter().enumerate() {
+                collect_fields(child(i.to_string()), value, fields);
+            }
+        }
+        Value::Option(Some(value)) | Value::Newtype(value) => collect_fields(path, value, fields),
+        leaf if path.is_empty() => fields.push(("value".to_string(), leaf)),
+        leaf => fields.push((path, leaf)),
+    }
+}
+
+fn to_json(value: &Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        Value::Bool(v) => Json::from(*v),
+        Value::U8(v) => Json::from(*v),
+        Value::U16(v) => Json::from(*v),
+        Value::U32(v) => Json::from(*v),
+        Value::U64(v) => Json::from(*v),
+        Value::I8(v) => Json::from(*v),
+        Value::I16(v) => Json::from(*v),
+        Value::I32(v) => Json::from(*v),
+        Value::I64(v) => Json::from(*v),
+        Value::F32(v) => Json::from(*v),
+        Value::F64(v) => Json::from(*v),
+        Value::Char(v) => Json::from(v.to_string()),
+        Value::String(v) => Json::from(v.clone()),
+        Value::Unit | Value::Option(None) => Json::Null,
+        Value::Option(Some(v)) | Value::Newtype(v) => to_json(v),
+        Value::Seq(values) => Json::Array(values.iter().map(to_json).collect()),
+        Value::Map(map) => Json::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Value::String(key) => key.clone(),
+                        key => key.to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect(),
+        ),
+        Value::Bytes(bytes) => Json::from(bytes.clone()),
+        Value::CuTime(time) => Json::from(time.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::register_json_msg_type;
+    use bincode::{Decode, Encode};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+    struct Track {
+        id: u32,
+        label: Option<String>,
+        positions: Vec<Position>,
+    }
+
+    #[test]
+    fn test_dyn_msg() {
+        register_json_msg_type::<Track>("test::Track").unwrap();
+        let track = Track {
+            id: 3,
+            label: Some("car".to_string()),
+            positions: vec![Position { x: 1.0, y: 2.0 }],
+        };
+        let mut msg = CuMsg::new(Some(track.clone()));
+        msg.metadata.tov = CuTime::from(42).into();
+        let dyn_msg = DynCuMsg::from_msg(&msg).unwrap();
+        assert_eq!(dyn_msg.type_name(), "test::Track");
+        assert_eq!(dyn_msg.tov, Some(CuTime::from(42)));
+
+        let fields: Vec<_> = dyn_msg
+            .fields()
+            .into_iter()
+            .map(|(path, value)| format!("{}={}", path, value))
+            .collect();
+        assert_eq!(
+            fields,
+            ["id=3", "label=car", "positions.0.x=1", "positions.0.y=2"]
+        );
+        assert_eq!(dyn_msg.field("id"), Some(&Value::U32(3)));
+        assert!(dyn_msg.field("speed").is_none());
+        assert_eq!(
+            dyn_msg.to_json(),
+            r#"{"id":3,"label":"car","positions":[{"x":1.0,"y":2.0}]}"#
+        );
+
+        let bytes = dyn_msg.msg_type().encode(&track).unwrap();
+        let decoded = DynCuMsg::decode("test::Track", &bytes).unwrap();
+        assert_eq!(decoded.payload, dyn_msg.payload);
+        assert!(DynCuMsg::decode("test::Unknown", &bytes).is_err());
+
+        assert_eq!(
+            DynCuMsg::from_msg(&CuMsg::<Track>::new(None))
+                .unwrap()
+                .to_json(),
+            "null"
+        );
+        assert!(DynCuMsg::from_msg(&CuMsg::new(Some(1u64))).is_err());
+    }
+
+    #[test]
+    fn test_single_value() {
+        register_json_msg_type::<f64>("test::f64").unwrap();
+        let dyn_msg = DynCuMsg::from_msg(&CuMsg::new(Some(2.5f64))).unwrap();
+        assert_eq!(dyn_msg.field("value"), Some(&Value::F64(2.5)));
+    }
+}
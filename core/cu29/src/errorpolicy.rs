@@ -0,0 +1,118 @@
+//! What the runtime does when the process of a task fails, set per node so a flaky sensor does not take the whole
+//! robot down:
+//!
+//! ```ron
+//! tasks: [
+//!     (id: "imu", type: "a::Imu", on_error: Retry(3)),
+//!     (id: "camera", type: "a::Camera", on_error: Restart),
+//!     (id: "logger", type: "a::Logger", on_error: Ignore),
+//!     (id: "motors", type: "a::Motors", on_error: Abort),
+//! ],
+//! ```
+//!
+//! Without on_error, the monitor decides as before, see [crate::monitoring::CuMonitor::process_error]. With one, the
+//! policy replaces the decision of the monitor, but for Retry which leaves it to the monitor once the retries failed.
+
+use crate::clock::RobotClock;
+use crate::config::ComponentConfig;
+pub use crate::config::CuErrorPolicy;
+use crate::cutask::CuTaskLifecycle;
+use crate::{CuError, CuResult};
+
+/// Calls process until it succeeds, at most 1 + attempts times.
+pub fn retry_process<F>(attempts: u32, mut process: F) -> CuResult<()>
+where
+    F: FnMut() -> CuResult<()>,
+{
+    let mut outcome = process();
+    for _ in 0..attempts {
+        if outcome.is_ok() {
+            break;
+        }
+        outcome = process();
+    }
+    outcome
+}
+
+/// Replaces the task by a new one: stop, new and start. The task failed already, so an error of its stop does not
+/// prevent its restart.
+pub fn restart_task<T: CuTaskLifecycle>(
+    task: &mut T,
+    config: Option<&ComponentConfig>,
+    clock: &RobotClock,
+) -> CuResult<()> {
+    let _ = task.stop(clock);
+    *task = T::new(config)
+        .map_err(|e| CuError::new_with_cause("Could not create the task again", e))?;
+    task.start(clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cutask::Freezable;
+
+    struct Flaky {
+        generation: u32,
+        started: bool,
+    }
+
+    impl Freezable for Flaky {}
+
+    impl CuTaskLifecycle for Flaky {
+        fn new(config: Option<&ComponentConfig>) -> CuResult<Self> {
+            let generation = config.and_then(|config| config.get::<u32>("generation"));
+            Ok(Flaky {
+                generation: generation.ok_or("no generation")?,
+                started: false,
+            })
+        }
+
+        fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+            self.started = true;
+            Ok(())
+        }
+
+        fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+            Err("the device is gone".into())
+        }
+    }
+
+    #[test]
+    fn test_retry_process() {
+        let mut calls = 0;
+        let outcome = retry_process(3, || {
+            calls += 1;
+            if calls < 3 {
+                Err("timeout".into())
+            } else {
+                Ok(())
+            }
+        });
+        assert!(outcome.is_ok());
+        assert_eq!(calls, 3);
+
+        calls = 0;
+        let outcome = retry_process(2, || {
+            calls += 1;
+            Err("timeout".into())
+        });
+        assert!(outcome.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_restart_task() {
+        let (clock, _) = RobotClock::mock();
+        let mut task = Flaky {
+            generation: 1,
+            started: false,
+        };
+        let mut config = ComponentConfig::new();
+        config.set("generation", 2u32);
+        assert!(restart_task(&mut task, Some(&config), &clock).is_ok());
+        assert_eq!(task.generation, 2);
+        assert!(task.started);
+        assert!(restart_task(&mut task, None, &clock).is_err());
+    }
+}
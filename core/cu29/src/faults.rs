@@ -0,0 +1,272 @@
+//! Fault injection, to test the error policies of the monitor and the safety behaviors of an application in CI.
+//!
+//! The `faults` section of the config drops the messages of some connections, delays the process calls of some
+//! tasks or replaces them by errors, each with a probability. The draws come from a generator seeded by the config,
+//! so a run injects the same faults every time. Keep this section in the config of a test profile only.
+//!
+//! ```ron
+//! faults: (
+//!     seed: 42,
+//!     drops: [(src: "camera", dst: "detector", probability: 0.1)],
+//!     delays: [(task: "planner", probability: 0.05, delay_us: 20000)],
+//!     errors: [(task: "imu", probability: 0.01)],
+//! ),
+//! ```
+
+use crate::config::{closest_match, CuConfig, NodeId};
+use crate::{CuError, CuResult};
+use std::time::Duration;
+
+/// A splitmix64 generator, small and the same on every platform.
+#[derive(Debug, Clone)]
+struct FaultRng(u64);
+
+impl FaultRng {
+    /// Every rule has its own generator so adding a rule does not change the faults of the others.
+    fn new(seed: u64, rule: usize) -> Self {
+        FaultRng(seed ^ (rule as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// True with the given probability.
+    fn draw(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Fault {
+    rng: FaultRng,
+    probability: f64,
+}
+
+/// How many faults were injected.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CuFaultStats {
+    pub dropped: u64,
+    pub delayed: u64,
+    pub errors: u64,
+}
+
+/// Draws the faults of the config, the runtime has one if the config has a faults section.
+#[derive(Debug, Clone)]
+pub struct CuFaultInjector {
+    /// The delays and errors of every task, indexed by node id.
+    delays: Vec<Option<(Fault, Duration)>>,
+    errors: Vec<Option<Fault>>,
+    /// The dropping connections by source and destination node ids.
+    drops: Vec<(NodeId, NodeId, Fault)>,
+    pub stats: CuFaultStats,
+}
+
+impl CuFaultInjector {
+    /// None if the config injects no fault, ie. its faults section is empty.
+    pub fn from_config(config: &CuConfig) -> CuResult<Option<Self>> {
+        let Some(faults) = config.get_faults_config() else {
+            return Ok(None);
+        };
+        let ids: Vec<String> = config.get_all_nodes().iter().map(|n| n.get_id()).collect();
+        let find = |task: &str| {
+            ids.iter().position(|id| id == task).ok_or_else(|| {
+                let hint = closest_match(task, ids.iter().map(String::as_str))
+                    .map(|m| format!(" Did you mean '{}'?", m))
+                    .unwrap_or_default();
+                CuError::from(format!(
+                    "Cannot inject faults in '{}': there is no such task.{}",
+                    task, hint
+                ))
+            })
+        };
+        let mut rule = 0;
+        let mut fault = |probability: f64| {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(CuError::from(format!(
+                    "Invalid fault probability {}, it must be between 0 and 1.",
+                    probability
+                )));
+            }
+            rule += 1;
+            Ok(Fault {
+                rng: FaultRng::new(faults.seed, rule),
+                probability,
+            })
+        };
+
+        let mut injector = CuFaultInjector {
+            delays: vec![None; ids.len()],
+            errors: vec![None; ids.len()],
+            drops: Vec::new(),
+            stats: CuFaultStats::default(),
+        };
+        for drop in &faults.drops {
+            let (src, dst) = (find(&drop.src)?, find(&drop.dst)?);
+            let dst_edges = config.get_dst_edges(dst as NodeId);
+            if !config
+                .get_src_edges(src as NodeId)
+                .iter()
+                .any(|edge| dst_edges.contains(edge))
+            {
+                return Err(format!(
+                    "Cannot drop the messages from '{}' to '{}': they are not connected.",
+                    drop.src, drop.dst
+                )
+                .into());
+            }
+            injector
+                .drops
+                .push((src as NodeId, dst as NodeId, fault(drop.probability)?));
+        }
+        for delay in &faults.delays {
+            let task = find(&delay.task)?;
+            injector.delays[task] = Some((
+                fault(delay.probability)?,
+                Duration::from_micros(delay.delay_us),
+            ));
+        }
+        for error in &faults.errors {
+            let task = find(&error.task)?;
+            injector.errors[task] = Some(fault(error.probability)?);
+        }
+        let empty = injector.drops.is_empty()
+            && (0..ids.len()).all(|task| !injector.injects_before_process(task));
+        Ok((!empty).then_some(injector))
+    }
+
+    /// Whether faults are injected before the process calls of the task.
+    pub fn injects_before_process(&self, task: usize) -> bool {
+        self.delays.get(task).is_some_and(Option::is_some)
+            || self.errors.get(task).is_some_and(Option::is_some)
+    }
+
+    /// Whether messages from src to dst are dropped.
+    pub fn injects_drop(&self, src: NodeId, dst: NodeId) -> bool {
+        self.drops.iter().any(|(s, d, _)| *s == src && *d == dst)
+    }
+
+    /// Delays the process call of the task or replaces it by an error, as drawn.
+    pub fn before_process(&mut self, task: usize) -> CuResult<()> {
+        if let Some((fault, delay)) = &mut self.delays[task] {
+            if fault.rng.draw(fault.probability) {
+                self.stats.delayed += 1;
+                std::thread::sleep(*delay);
+            }
+        }
+        if let Some(fault) = &mut self.errors[task] {
+            if fault.rng.draw(fault.probability) {
+                self.stats.errors += 1;
+                return Err("Injected fault.".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the message from src is dropped before it reaches dst.
+    pub fn drops(&mut self, src: NodeId, dst: NodeId) -> bool {
+        let Some((_, _, fault)) = self
+            .drops
+            .iter_mut()
+            .find(|(s, d, _)| *s == src && *d == dst)
+        else {
+            return false;
+        };
+        let dropped = fault.rng.draw(fault.probability);
+        if dropped {
+            self.stats.dropped += 1;
+        }
+        dropped
+    }
+}
+
+/// The faults injected before a process call of the task, if the runtime injects any.
+#[inline]
+pub fn inject_before_process(faults: &mut Option<CuFaultInjector>, task: usize) -> CuResult<()> {
+    match faults {
+        Some(faults) => faults.before_process(task),
+        None => Ok(()),
+    }
+}
+
+/// Whether the message from src is dropped before it reaches dst, if the runtime injects faults.
+#[inline]
+pub fn inject_drop(faults: &mut Option<CuFaultInjector>, src: NodeId, dst: NodeId) -> bool {
+    match faults {
+        Some(faults) => faults.drops(src, dst),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"(
+        tasks: [(id: "camera", type: "a"), (id: "detector", type: "b")],
+        cnx: [(src: "camera", dst: "detector", msg: "u32")],
+        faults: (
+            seed: 42,
+            drops: [(src: "camera", dst: "detector", probability: 0.25)],
+            errors: [(task: "detector", probability: 1.0)],
+        ),
+    )"#;
+
+    #[test]
+    fn test_deterministic_faults() {
+        let config = CuConfig::deserialize_ron(CONFIG);
+        let draws = || {
+            let mut injector = CuFaultInjector::from_config(&config).unwrap().unwrap();
+            let drops: Vec<bool> = (0..1000).map(|_| injector.drops(0, 1)).collect();
+            (drops, injector.stats)
+        };
+        let (drops, stats) = draws();
+        assert_eq!(draws().0, drops);
+        assert!((200..300).contains(&stats.dropped));
+
+        let mut injector = CuFaultInjector::from_config(&config).unwrap().unwrap();
+        assert!(injector.injects_drop(0, 1) && !injector.injects_drop(1, 0));
+        assert!(injector.injects_before_process(1) && !injector.injects_before_process(0));
+        assert!(!injector.drops(1, 0));
+        assert!(injector.before_process(0).is_ok());
+        assert!(injector.before_process(1).is_err());
+        assert_eq!(injector.stats.errors, 1);
+
+        assert!(CuFaultInjector::from_config(&CuConfig::default())
+            .unwrap()
+            .is_none());
+        let empty = CONFIG
+            .replace(
+                r#"drops: [(src: "camera", dst: "detector", probability: 0.25)],"#,
+                "",
+            )
+            .replace(r#"errors: [(task: "detector", probability: 1.0)],"#, "");
+        assert!(
+            CuFaultInjector::from_config(&CuConfig::deserialize_ron(&empty))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_invalid_faults() {
+        let invalid = |faults: &str| {
+            let txt = CONFIG.replace(r#"errors: [(task: "detector", probability: 1.0)]"#, faults);
+            CuFaultInjector::from_config(&CuConfig::deserialize_ron(&txt)).unwrap_err()
+        };
+        let error = invalid(r#"errors: [(task: "detectr", probability: 1.0)]"#);
+        assert!(error.to_string().contains("Did you mean 'detector'?"));
+        invalid(r#"errors: [(task: "detector", probability: 1.5)]"#);
+        invalid(r#"delays: [(task: "tracker", probability: 0.5, delay_us: 10)]"#);
+        let txt = CONFIG.replace(
+            r#"src: "camera", dst: "detector", probability"#,
+            r#"src: "detector", dst: "camera", probability"#,
+        );
+        let error = CuFaultInjector::from_config(&CuConfig::deserialize_ron(&txt)).unwrap_err();
+        assert!(error.to_string().contains("not connected"));
+    }
+}
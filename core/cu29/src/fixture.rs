@@ -0,0 +1,302 @@
+//! Unit tests of a task reproduced from a log, so fixing a bug found in the field starts with a failing test.
+//!
+//! The fixture command of the log reader extracts the inputs and the output of one task from every copper list where
+//! it ran, the inputs given in the order of the inputs of the task:
+//!
+//! ```text
+//! logreader robot.copper fixture pid --inputs imu,setpoint --tolerance 0.001 > tests/pid.ron
+//! ```
+//!
+//! The test feeds the recorded inputs to the task, decoded from the wire format, and compares its outputs with the
+//! recorded ones in their Debug representation, the numbers within the tolerance:
+//!
+//! ```rust,ignore
+//! let fixture = CuTaskFixture::load("tests/pid.ron")?;
+//! let mut pid = Pid::new(None)?;
+//! fixture.run(|case| {
+//!     let (imu, setpoint) = (case.input::<ImuPayload>(0)?, case.input::<f32>(1)?);
+//!     let mut output = CuMsg::default();
+//!     pid.process(&ctx, (&imu, &setpoint), &mut output)?;
+//!     Ok(output.payload().cloned())
+//! })?;
+//! ```
+//!
+//! Every case is checked, the error lists the copper lists whose output differs.
+
+use crate::clock::CuTime;
+use crate::copperlist::{CopperList, CuListDumper};
+use crate::cutask::{CuMsg, CuMsgPayload};
+use crate::wire::wire_config;
+use crate::{CuError, CuResult};
+use cu29_traits::CopperListTuple;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+/// The recorded inputs and outputs of a task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CuTaskFixture {
+    pub task: String,
+    /// The tasks producing its inputs, in the order of the inputs of the task.
+    pub inputs: Vec<String>,
+    /// How far a number of an output can be from the recorded one.
+    pub tolerance: f64,
+    pub cases: Vec<CuFixtureCase>,
+}
+
+/// A process call of the task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CuFixtureCase {
+    /// The copper list it was recorded in.
+    pub culist: u32,
+    pub inputs: Vec<CuFixtureMsg>,
+    /// The Debug representation of the output, None if the task produced nothing.
+    pub expected: Option<String>,
+}
+
+/// A recorded input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CuFixtureMsg {
+    pub tov: Option<CuTime>,
+    /// The payload in the wire format, None if there was none.
+    pub payload: Option<Vec<u8>>,
+    /// The Debug representation of the payload, for the reader of the fixture.
+    pub debug: Option<String>,
+}
+
+impl CuTaskFixture {
+    pub fn new(task: &str, inputs: &[&str], tolerance: f64) -> Self {
+        CuTaskFixture {
+            task: task.to_string(),
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            tolerance,
+            cases: Vec::new(),
+        }
+    }
+
+    /// Adds the process call of the task in a copper list of the log, if it ran in it.
+    pub fn record<P: CopperListTuple + CuListDumper>(
+        &mut self,
+        culist: &CopperList<P>,
+    ) -> CuResult<()> {
+        let msgs = culist.msgs.dump_msgs();
+        let index = |task: &str| {
+            msgs.iter()
+                .position(|msg| msg.task == task)
+                .ok_or_else(|| CuError::from(format!("No task {} in the copper lists.", task)))
+        };
+        let output = &msgs[index(&self.task)?];
+        if output.before_process.is_none() {
+            return Ok(());
+        }
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                let index = index(input)?;
+                Ok(CuFixtureMsg {
+                    tov: msgs[index].tov,
+                    payload: culist.msgs.encode_payload(index),
+                    debug: msgs[index].payload.clone(),
+                })
+            })
+            .collect::<CuResult<Vec<_>>>()?;
+        self.cases.push(CuFixtureCase {
+            culist: culist.id,
+            inputs,
+            expected: output.payload.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> CuResult<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            CuError::new_with_cause(&format!("Could not read the fixture {}", path.display()), e)
+        })?;
+        Self::from_ron(&text)
+    }
+
+    pub fn from_ron(text: &str) -> CuResult<Self> {
+        ron::from_str(text).map_err(|e| CuError::new_with_cause("Invalid fixture", e))
+    }
+
+    pub fn to_ron(&self) -> CuResult<String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| CuError::new_with_cause("Could not serialize the fixture", e))
+    }
+
+    /// Runs the task on every case, process returning the payload of its output.
+    pub fn run<O, F>(&self, mut process: F) -> CuResult<()>
+    where
+        O: Debug,
+        F: FnMut(&CuFixtureCase) -> CuResult<Option<O>>,
+    {
+        let mismatches: Vec<String> = self
+            .cases
+            .iter()
+            .map(|case| {
+                let output = process(case)?.map(|payload| format!("{:?}", payload));
+                let matching = match (&case.expected, &output) {
+                    (Some(expected), Some(output)) => {
+                        matches_within(expected, output, self.tolerance)
+                    }
+                    (expected, output) => expected == output,
+                };
+                Ok((!matching).then(|| {
+                    format!(
+                        "copper list {}: expected {:?}, got {:?}",
+                        case.culist, case.expected, output
+                    )
+                }))
+            })
+            .filter_map(Result::transpose)
+            .collect::<CuResult<_>>()?;
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(CuError::from(format!(
+                "The task {} differs from the log in {} of {} cases.",
+                self.task,
+                mismatches.len(),
+                self.cases.len()
+            ))
+            .add_cause(&mismatches.join("\n")))
+        }
+    }
+}
+
+impl CuFixtureCase {
+    /// The input at index with its recorded payload and time of validity.
+    pub fn input<T: CuMsgPayload>(&self, index: usize) -> CuResult<CuMsg<T>> {
+        let msg = self.inputs.get(index).ok_or_else(|| {
+            CuError::from(format!(
+                "No input {} in the fixture, it has {}.",
+                index,
+                self.inputs.len()
+            ))
+        })?;
+        let payload = msg
+            .payload
+            .as_ref()
+            .map(|bytes| {
+                bincode::decode_from_slice(bytes, wire_config())
+                    .map(|(payload, _)| payload)
+                    .map_err(|e| CuError::new_with_cause("Could not decode the input", e))
+            })
+            .transpose()?;
+        let mut input = CuMsg::new(payload);
+        input.metadata.tov = msg.tov.into();
+        Ok(input)
+    }
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Number(f64),
+}
+
+/// The numbers and the text in between of a Debug representation.
+fn tokens(text: &str) -> Vec<Token<'_>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    while i < bytes.len() {
+        let signed = bytes[i] == b'-' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+        // Not the digits of an identifier, ie. x1.
+        let in_word = i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        if (bytes[i].is_ascii_digit() || signed) && !in_word {
+            let mut end = i + 1;
+            while end < bytes.len()
+                && (bytes[end].is_ascii_digit()
+                    || matches!(bytes[end], b'.' | b'e' | b'E')
+                    || (matches!(bytes[end], b'-' | b'+') && matches!(bytes[end - 1], b'e' | b'E')))
+            {
+                end += 1;
+            }
+            if let Ok(number) = text[i..end].parse() {
+                if start < i {
+                    tokens.push(Token::Text(&text[start..i]));
+                }
+                tokens.push(Token::Number(number));
+                start = end;
+                i = end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if start < bytes.len() {
+        tokens.push(Token::Text(&text[start..]));
+    }
+    tokens
+}
+
+/// Whether two Debug representations are the same but for their numbers, within the tolerance.
+pub fn matches_within(expected: &str, actual: &str, tolerance: f64) -> bool {
+    let (expected, actual) = (tokens(expected), tokens(actual));
+    expected.len() == actual.len()
+        && expected.iter().zip(&actual).all(|pair| match pair {
+            (Token::Text(expected), Token::Text(actual)) => expected == actual,
+            (Token::Number(expected), Token::Number(actual)) => {
+                (expected - actual).abs() <= tolerance
+            }
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_within() {
+        assert!(matches_within("1.0", "1.0005", 0.001));
+        assert!(!matches_within("1.0", "1.01", 0.001));
+        assert!(matches_within(
+            "Pose { x1: 2.5, y: -1e-3 }",
+            "Pose { x1: 2.5001, y: 0.0 }",
+            0.01
+        ));
+        assert!(!matches_within(
+            "Pose { x1: 2.5 }",
+            "Pose { x2: 2.5 }",
+            0.01
+        ));
+        assert!(!matches_within("[1, 2]", "[1, 2, 3]", 0.01));
+    }
+
+    #[test]
+    fn test_fixture() {
+        let bytes = bincode::encode_to_vec(2.0f32, wire_config()).unwrap();
+        let mut fixture = CuTaskFixture::new("double", &["src"], 0.001);
+        for culist in 0..2 {
+            fixture.cases.push(CuFixtureCase {
+                culist,
+                inputs: vec![CuFixtureMsg {
+                    tov: Some(CuTime::from(10)),
+                    payload: Some(bytes.clone()),
+                    debug: Some("2.0".to_string()),
+                }],
+                expected: Some("4.0".to_string()),
+            });
+        }
+        let fixture = CuTaskFixture::from_ron(&fixture.to_ron().unwrap()).unwrap();
+
+        let input = fixture.cases[0].input::<f32>(0).unwrap();
+        assert_eq!(input.payload(), Some(&2.0));
+        assert_eq!(input.metadata.tov, Some(CuTime::from(10)).into());
+        assert!(fixture.cases[0].input::<f32>(1).is_err());
+
+        let double = |case: &CuFixtureCase| Ok(case.input::<f32>(0)?.payload().map(|x| x * 2.0));
+        assert!(fixture.run(double).is_ok());
+        let mut calls = 0;
+        let buggy = fixture.run(|case| {
+            calls += 1;
+            Ok(case.input::<f32>(0)?.payload().map(|x| x * 3.0))
+        });
+        assert_eq!(calls, 2);
+        assert!(buggy.unwrap_err().to_string().contains("2 of 2 cases"));
+    }
+}
@@ -0,0 +1,206 @@
+//! A lightweight finite state machine helper for the tasks that are naturally modal (docking, arm homing...).
+//! Every transition is logged with the time of the robot clock and a task can report the current state
+//! to the runtime through [crate::cutask::CuTaskLifecycle::current_state] for the introspection.
+//!
+//! ```
+//! use cu29::clock::RobotClock;
+//! use cu29::fsm::CuFsmBuilder;
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq, bincode::Encode, bincode::Decode)]
+//! enum Docking { Idle, Approach, Docked }
+//!
+//! let clock = RobotClock::default();
+//! let mut fsm = CuFsmBuilder::new("docking", Docking::Idle)
+//!     .allow(Docking::Idle, Docking::Approach)
+//!     .allow(Docking::Approach, Docking::Docked)
+//!     .allow(Docking::Approach, Docking::Idle)
+//!     .build();
+//! fsm.transition(&clock, Docking::Approach).unwrap();
+//! assert!(fsm.transition(&clock, Docking::Idle).is_ok());
+//! assert!(fsm.transition(&clock, Docking::Docked).is_err());
+//! ```
+
+use crate::cutask::Freezable;
+use crate::{CuError, CuResult};
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use cu29_clock::{CuDuration, CuTime, RobotClock};
+use cu29_log_derive::debug;
+use std::fmt::Debug;
+
+/// Builds a [CuFsm] declaring its allowed transitions.
+/// If no transition is declared, all the transitions are allowed.
+pub struct CuFsmBuilder<S> {
+    name: &'static str,
+    initial: S,
+    allowed: Vec<(S, S)>,
+}
+
+impl<S: Copy + PartialEq + Debug> CuFsmBuilder<S> {
+    /// name is used to identify the state machine in the logs.
+    pub fn new(name: &'static str, initial: S) -> Self {
+        CuFsmBuilder {
+            name,
+            initial,
+            allowed: Vec::new(),
+        }
+    }
+
+    pub fn allow(mut self, from: S, to: S) -> Self {
+        self.allowed.push((from, to));
+        self
+    }
+
+    pub fn build(self) -> CuFsm<S> {
+        CuFsm {
+            name: self.name,
+            state: self.initial,
+            state_name: format!("{:?}", self.initial),
+            entered_at: CuTime::default(),
+            transitions: 0,
+            allowed: self.allowed,
+        }
+    }
+}
+
+/// A finite state machine logging its transitions.
+pub struct CuFsm<S> {
+    name: &'static str,
+    state: S,
+    /// Cached so it can be reported without allocating.
+    state_name: String,
+    entered_at: CuTime,
+    transitions: u64,
+    allowed: Vec<(S, S)>,
+}
+
+impl<S: Copy + PartialEq + Debug> CuFsm<S> {
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    pub fn state_name(&self) -> &str {
+        &self.state_name
+    }
+
+    /// Number of transitions since the creation of the state machine.
+    pub fn transitions(&self) -> u64 {
+        self.transitions
+    }
+
+    pub fn time_in_state(&self, clock: &RobotClock) -> CuDuration {
+        clock.now() - self.entered_at
+    }
+
+    pub fn can_transition(&self, to: S) -> bool {
+        self.allowed.is_empty() || self.allowed.contains(&(self.state, to))
+    }
+
+    /// Moves to the state `to`, transitioning to the current state is a no-op.
+    /// Errors out if the transition has not been allowed.
+    pub fn transition(&mut self, clock: &RobotClock, to: S) -> CuResult<()> {
+        if to == self.state {
+            return Ok(());
+        }
+        if !self.can_transition(to) {
+            return Err(CuError::from(format!(
+                "State machine {}: transition {:?} -> {:?} is not allowed.",
+                self.name, self.state, to
+            )));
+        }
+        let now = clock.now();
+        let to_name = format!("{:?}", to);
+        debug!(
+            "State machine {}: {} -> {} at {} (after {}).",
+            self.name,
+            &self.state_name,
+            &to_name,
+            now.to_string(),
+            (now - self.entered_at).to_string()
+        );
+        self.state = to;
+        self.state_name = to_name;
+        self.entered_at = now;
+        self.transitions += 1;
+        Ok(())
+    }
+}
+
+/// The state machine is part of the state of the task so it can be frozen and thawed with it.
+impl<S: Copy + PartialEq + Debug + Encode + Decode> Freezable for CuFsm<S> {
+    fn freeze<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        Encode::encode(&self.state, encoder)?;
+        Encode::encode(&self.entered_at, encoder)?;
+        Encode::encode(&self.transitions, encoder)?;
+        Ok(())
+    }
+
+    fn thaw<D: Decoder>(&mut self, decoder: &mut D) -> Result<(), DecodeError> {
+        self.state = Decode::decode(decoder)?;
+        self.state_name = format!("{:?}", self.state);
+        self.entered_at = Decode::decode(decoder)?;
+        self.transitions = Decode::decode(decoder)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+    enum Homing {
+        Idle,
+        Seeking,
+        Homed,
+    }
+
+    #[test]
+    fn test_transitions() {
+        let (clock, mock) = RobotClock::mock();
+        let mut fsm = CuFsmBuilder::new("homing", Homing::Idle)
+            .allow(Homing::Idle, Homing::Seeking)
+            .allow(Homing::Seeking, Homing::Homed)
+            .build();
+        assert_eq!(fsm.state_name(), "Idle");
+        assert!(fsm.transition(&clock, Homing::Homed).is_err());
+
+        mock.increment(Duration::from_millis(10));
+        fsm.transition(&clock, Homing::Seeking).unwrap();
+        mock.increment(Duration::from_millis(5));
+        assert_eq!(fsm.time_in_state(&clock), CuDuration::from(5_000_000));
+
+        fsm.transition(&clock, Homing::Homed).unwrap();
+        fsm.transition(&clock, Homing::Homed).unwrap();
+        assert_eq!(fsm.state(), Homing::Homed);
+        assert_eq!(fsm.state_name(), "Homed");
+        assert_eq!(fsm.transitions(), 2);
+    }
+
+    #[test]
+    fn test_freeze_thaw() {
+        let clock = RobotClock::default();
+        let mut fsm = CuFsmBuilder::new("homing", Homing::Idle).build();
+        fsm.transition(&clock, Homing::Homed).unwrap();
+
+        let mut buffer = [0u8; 64];
+        let mut encoder = bincode::enc::EncoderImpl::new(
+            bincode::enc::write::SliceWriter::new(&mut buffer),
+            bincode::config::standard(),
+        );
+        fsm.freeze(&mut encoder).unwrap();
+
+        let mut restored = CuFsmBuilder::new("homing", Homing::Idle).build();
+        let mut decoder = bincode::de::DecoderImpl::new(
+            bincode::de::read::SliceReader::new(&buffer),
+            bincode::config::standard(),
+        );
+        restored.thaw(&mut decoder).unwrap();
+        assert_eq!(restored.state(), Homing::Homed);
+        assert_eq!(restored.state_name(), "Homed");
+        assert_eq!(restored.transitions(), 1);
+    }
+}
@@ -0,0 +1,155 @@
+//! Introspection of a Copper application: the structure of its task graph with the concrete
+//! Rust types of the tasks and messages, the configured and measured rates and the current health.
+//! Everything here is serializable so it can be exposed as is to external tools and UIs.
+
+use crate::config::{CuConfig, DeadlinePolicy, NodeId, Reliability};
+use crate::curuntime::{find_task_type_for_id, CuTaskType};
+use crate::monitoring::{CuEdgeStats, CuHealth, CuTaskStats};
+use crate::{CuError, CuResult};
+use cu29_clock::{CuDuration, CuTime};
+use serde_derive::{Deserialize, Serialize};
+
+/// Description of a task of the graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CuNodeInfo {
+    /// Index of the task in the graph and in the tasks tuple.
+    pub node_id: NodeId,
+    /// Friendly name of the task from the config.
+    pub id: String,
+    /// Rust type of the task.
+    pub type_name: String,
+    pub task_type: CuTaskType,
+    /// Rate declared in the config if any.
+    pub configured_rate_hz: Option<f64>,
+    /// Rate measured by the runtime.
+    pub measured_rate_hz: Option<f64>,
+    pub health: CuHealth,
+    /// Current state of the task if it is modal.
+    pub state: Option<String>,
+    pub process_count: u64,
+    pub error_count: u64,
+    pub last_error: Option<String>,
+}
+
+/// Description of a connection of the graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CuEdgeInfo {
+    /// Friendly name of the source task.
+    pub src: String,
+    /// Friendly name of the destination task.
+    pub dst: String,
+    /// Rust type of the message exchanged.
+    pub msg: String,
+    pub batch: u32,
+    pub store: bool,
+    /// The age from which the data on the connection is stale.
+    pub max_age_ns: Option<u64>,
+    /// How old the data on the connection is, see CuEdgeStats.
+    pub age: Option<CuDuration>,
+    pub health: CuHealth,
+    /// The age from which the data delivered to the destination is late.
+    pub deadline_ms: Option<u64>,
+    pub deadline_policy: DeadlinePolicy,
+    pub reliability: Reliability,
+    /// If the last message of the source is retained for the late observers.
+    pub latched: bool,
+    /// How many times late data was delivered to the destination.
+    pub deadline_misses: u64,
+}
+
+/// The full picture of the task graph.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CuGraphInfo {
+    pub nodes: Vec<CuNodeInfo>,
+    pub edges: Vec<CuEdgeInfo>,
+}
+
+impl CuGraphInfo {
+    /// Builds the static part of the introspection from the configuration.
+    pub fn from_config(config: &CuConfig) -> Self {
+        let nodes = config
+            .graph
+            .node_indices()
+            .map(|index| {
+                let node_id = index.index() as NodeId;
+                let node = &config.graph[index];
+                CuNodeInfo {
+                    node_id,
+                    id: node.get_id(),
+                    type_name: node.get_type().to_string(),
+                    task_type: find_task_type_for_id(&config.graph, node_id),
+                    configured_rate_hz: node
+                        .get_base_period_ns()
+                        .filter(|p| *p > 0)
+                        .map(|p| 1_000_000_000.0 / p as f64),
+                    measured_rate_hz: None,
+                    health: CuHealth::Unknown,
+                    state: None,
+                    process_count: 0,
+                    error_count: 0,
+                    last_error: None,
+                }
+            })
+            .collect();
+
+        let edges = config
+            .graph
+            .edge_indices()
+            .map(|edge| {
+                let cnx = &config.graph[edge];
+                CuEdgeInfo {
+                    src: cnx.get_src().to_string(),
+                    dst: cnx.get_dst().to_string(),
+                    msg: cnx.msg.clone(),
+                    batch: cnx.batch.unwrap_or(1),
+                    store: cnx.store.unwrap_or(false),
+                    max_age_ns: cnx.max_age_ns,
+                    age: None,
+                    health: CuHealth::Unknown,
+                    deadline_ms: cnx.deadline_ms,
+                    deadline_policy: cnx.deadline_policy.unwrap_or_default(),
+                    reliability: cnx.reliability.unwrap_or_default(),
+                    latched: cnx.latched.unwrap_or(false),
+                    deadline_misses: 0,
+                }
+            })
+            .collect();
+
+        CuGraphInfo { nodes, edges }
+    }
+
+    /// Updates the dynamic part of the introspection from the runtime statistics.
+    /// stats is indexed by node id.
+    pub fn update(&mut self, stats: &[CuTaskStats]) {
+        for node in self.nodes.iter_mut() {
+            if let Some(stats) = stats.get(node.node_id as usize) {
+                node.measured_rate_hz = stats.measured_rate_hz();
+                node.health = stats.health;
+                node.state = stats.state.clone();
+                node.process_count = stats.process_count;
+                node.error_count = stats.error_count;
+                node.last_error = stats.last_error.clone();
+            }
+        }
+    }
+
+    /// Updates the liveliness of the connections at the time now.
+    /// edges is in the order of the edges of the graph.
+    pub fn update_edges(&mut self, edges: &[CuEdgeStats], now: CuTime) {
+        for (edge, stats) in self.edges.iter_mut().zip(edges) {
+            edge.age = stats.age(now);
+            edge.health = stats.health;
+            edge.deadline_misses = stats.deadline_misses;
+        }
+    }
+
+    /// Find a task by its friendly name.
+    pub fn get_node(&self, id: &str) -> Option<&CuNodeInfo> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    pub fn to_json(&self) -> CuResult<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| CuError::new_with_cause("Could not serialize the introspection", e))
+    }
+}
@@ -0,0 +1,215 @@
+//! Latched connections: the runtime retains the last message of their source so an observer joining later, ie. a
+//! bridge or a UI connecting to a running robot, gets it right away, like a latched ROS topic. It is meant for the
+//! data changing slowly or published once, ie. a map or a calibration.
+//!
+//! ```ron
+//! (src: "mapper", dst: "planner", msg: "OccupancyGrid", latched: true),
+//! ```
+//!
+//! The messages are kept in the wire format, `app.latched_msgs()` is what a new observer is sent first.
+
+use crate::clock::CuTime;
+use crate::config::CuConfig;
+use crate::copperlist::CuListDumper;
+use crate::dynmsg::DynCuMsg;
+use crate::namespace::namespaced;
+use crate::CuResult;
+
+/// The last message of the source of a latched connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuLatchedMsg {
+    pub task: String,
+    /// The task in the namespace of the robot, ie. `r2/map`, the topic of a bridge. See cu29::namespace.
+    pub channel: String,
+    pub msg_type: String,
+    /// When the iteration that produced the message started.
+    pub time: CuTime,
+    /// The payload in the wire format.
+    pub payload: Vec<u8>,
+}
+
+impl CuLatchedMsg {
+    /// The untyped view of the message, its payload type has to be registered in cu29::registry.
+    pub fn to_dyn(&self) -> CuResult<DynCuMsg> {
+        let mut msg = DynCuMsg::decode(&self.msg_type, &self.payload)?;
+        msg.tov = Some(self.time);
+        Ok(msg)
+    }
+}
+
+#[derive(Debug)]
+struct Latch {
+    task: String,
+    channel: String,
+    /// The position of the output of the task in the copper lists and its type, found on the first copper list.
+    slot: Option<(usize, String)>,
+    last: Option<CuLatchedMsg>,
+}
+
+/// Retains the last message of the sources of the latched connections from the copper lists of the runtime.
+#[derive(Debug, Default)]
+pub struct CuLatches {
+    latches: Vec<Latch>,
+    resolved: bool,
+}
+
+impl CuLatches {
+    pub fn from_config(config: &CuConfig) -> Self {
+        let mut latches: Vec<Latch> = Vec::new();
+        for edge in config.graph.edge_weights() {
+            let task = edge.get_src();
+            if edge.latched == Some(true) && latches.iter().all(|latch| latch.task != task) {
+                latches.push(Latch {
+                    task: task.to_string(),
+                    channel: namespaced(config.robot_id(), task),
+                    slot: None,
+                    last: None,
+                });
+            }
+        }
+        CuLatches {
+            latches,
+            resolved: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latches.is_empty()
+    }
+
+    /// Retains the outputs of the latched sources the copper list has a payload for.
+    pub fn record<P: CuListDumper>(&mut self, msgs: &P) {
+        if self.is_empty() {
+            return;
+        }
+        if !self.resolved {
+            let slots: Vec<(&str, &str)> = P::SCHEMA
+                .split(';')
+                .filter_map(|slot| slot.split_once(':'))
+                .collect();
+            for latch in &mut self.latches {
+                latch.slot = slots
+                    .iter()
+                    .position(|(task, _)| *task == latch.task)
+                    .map(|index| (index, slots[index].1.to_string()));
+            }
+            self.resolved = true;
+        }
+        let time = msgs.start_time().unwrap_or_default();
+        for latch in &mut self.latches {
+            let Some((index, msg_type)) = &latch.slot else {
+                continue;
+            };
+            if let Some(payload) = msgs.encode_payload(*index) {
+                latch.last = Some(CuLatchedMsg {
+                    task: latch.task.clone(),
+                    channel: latch.channel.clone(),
+                    msg_type: msg_type.clone(),
+                    time,
+                    payload,
+                });
+            }
+        }
+    }
+
+    /// The last message of a latched source, None if it has not produced any yet.
+    pub fn get(&self, task: &str) -> Option<&CuLatchedMsg> {
+        self.latches
+            .iter()
+            .find(|latch| latch.task == task)
+            .and_then(|latch| latch.last.as_ref())
+    }
+
+    /// What a new observer is sent first: the last message of every latched source that produced one.
+    pub fn snapshot(&self) -> Vec<CuLatchedMsg> {
+        self.latches
+            .iter()
+            .filter_map(|latch| latch.last.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::copperlist::CuMsgDump;
+    use crate::cutask::CuMsg;
+    use crate::summary::CuChannelStats;
+    use crate::wire::wire_config;
+
+    #[derive(Debug)]
+    struct MapAndPose(CuMsg<u32>, CuMsg<u8>);
+
+    impl CuListDumper for MapAndPose {
+        const SCHEMA: &'static str = "mapper:u32;localizer:u8";
+
+        fn dump_msgs(&self) -> Vec<CuMsgDump> {
+            vec![
+                CuMsgDump::from_msg("mapper", "u32", &self.0),
+                CuMsgDump::from_msg("localizer", "u8", &self.1),
+            ]
+        }
+
+        fn start_time(&self) -> Option<CuTime> {
+            self.0.metadata.before_process.into()
+        }
+
+        fn record_stats(&self, _channels: &mut [CuChannelStats]) {}
+
+        fn dyn_msgs(&self) -> Vec<(&'static str, DynCuMsg)> {
+            Vec::new()
+        }
+
+        fn encode_payload(&self, index: usize) -> Option<Vec<u8>> {
+            match index {
+                0 => self
+                    .0
+                    .payload()
+                    .map(|payload| bincode::encode_to_vec(payload, wire_config()).unwrap()),
+                1 => self
+                    .1
+                    .payload()
+                    .map(|payload| bincode::encode_to_vec(payload, wire_config()).unwrap()),
+                _ => None,
+            }
+        }
+    }
+
+    fn iteration(ms: u64, map: Option<u32>) -> MapAndPose {
+        let mut map = CuMsg::new(map);
+        map.metadata.before_process = CuTime::from(ms * 1_000_000).into();
+        MapAndPose(map, CuMsg::new(Some(1)))
+    }
+
+    #[test]
+    fn test_latches() {
+        let config = CuConfig::deserialize_ron(
+            r#"( tasks: [(id: "mapper", type: "Mapper"), (id: "localizer", type: "Localizer"),
+                         (id: "planner", type: "Planner")],
+                 cnx: [(src: "mapper", dst: "planner", msg: "u32", latched: true),
+                       (src: "mapper", dst: "localizer", msg: "u32", latched: true),
+                       (src: "localizer", dst: "planner", msg: "u8")] )"#,
+        );
+        let mut latches = CuLatches::from_config(&config);
+        assert!(latches.snapshot().is_empty());
+        // The map is published once, the observer joining later still gets it.
+        latches.record(&iteration(10, Some(42)));
+        latches.record(&iteration(20, None));
+        latches.record(&iteration(30, None));
+        let snapshot = latches.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].task, "mapper");
+        assert_eq!(snapshot[0].channel, "mapper");
+        assert_eq!(snapshot[0].time, CuTime::from(10_000_000));
+        let (map, _): (u32, usize) =
+            bincode::decode_from_slice(&snapshot[0].payload, wire_config()).unwrap();
+        assert_eq!(map, 42);
+        assert!(latches.get("localizer").is_none());
+
+        let mut config = config;
+        config.set_robot_id("r2");
+        let mut latches = CuLatches::from_config(&config);
+        latches.record(&iteration(10, Some(42)));
+        assert_eq!(latches.get("mapper").unwrap().channel, "r2/mapper");
+    }
+}
@@ -1,10 +1,56 @@
 #![doc = include_str!("../README.md")]
 
+pub mod annotation;
+pub mod asynctask;
+pub mod clockcheck;
+pub mod clockdomain;
 pub mod config;
+pub mod context;
 pub mod copperlist;
 pub mod curuntime;
 pub mod cutask;
+pub mod derived;
+pub mod device;
+pub mod diagnostics;
+pub mod dynmsg;
+pub mod errorpolicy;
+pub mod faults;
+pub mod fixture;
+pub mod fsm;
+pub mod introspection;
+pub mod latch;
+pub mod lineage;
+pub mod lint;
+pub mod memory;
+pub mod metrics;
+pub mod mission;
 pub mod monitoring;
+pub mod multirate;
+pub mod namespace;
+pub mod optional;
+pub mod pacing;
+pub mod parallel;
+pub mod permissions;
+pub mod pod;
+pub mod pool;
+pub mod provenance;
+pub mod realtime;
+pub mod reconnect;
+pub mod registry;
+pub mod schedule;
+pub mod scratch;
+pub mod selftest;
+pub mod signal;
+pub mod summary;
+pub mod suspend;
+pub mod testing;
+pub mod threads;
+pub mod timefactor;
+pub mod tuning;
+pub mod units;
+pub mod upgrade;
+pub mod watch;
+pub mod wire;
 
 pub use config::read_configuration;
 pub use cu29_clock as clock;
@@ -0,0 +1,204 @@
+//! The identity of every run of the application, so the fleet tooling can stitch the history of a robot across its
+//! restarts.
+//!
+//! A run gets a random UUID and a counter incremented from the previous run. Both are persisted in the run_file of
+//! the runtime section, which the next run reads to find its previous one:
+//!
+//! ```ron
+//! runtime: (robot_id: "r2", run_file: "/var/lib/copper/run.ron"),
+//! ```
+//!
+//! The run is written in the header of the log and given to every task in its config, see from_task_config, for
+//! the sinks reporting to the fleet. Without a run_file, a run still has a UUID but its counter is 0 and it has no
+//! previous run.
+
+use crate::config::{ComponentConfig, CuConfig};
+use crate::{CuError, CuResult};
+use bincode::{Decode, Encode};
+use cu29_log_derive::debug;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Keys set in the config of every task, see CuRunId::apply.
+pub const RUN_ID_KEY: &str = "run_id";
+pub const RUN_COUNTER_KEY: &str = "run_counter";
+pub const PREVIOUS_RUN_ID_KEY: &str = "previous_run_id";
+
+/// A run of the application.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct CuRunId {
+    pub uuid: String,
+    /// 1 for the first run persisted in the run file, incremented on every run.
+    pub counter: u64,
+    /// The UUID of the previous run.
+    pub previous: Option<String>,
+}
+
+impl CuRunId {
+    /// A new run after the one persisted in run_file, which is replaced by this one.
+    pub fn start(run_file: Option<&Path>) -> CuResult<Self> {
+        let Some(run_file) = run_file else {
+            return Ok(CuRunId {
+                uuid: new_uuid(),
+                counter: 0,
+                previous: None,
+            });
+        };
+        let last = match fs::read_to_string(run_file) {
+            Ok(text) => Some(ron::from_str::<CuRunId>(&text).map_err(|e| {
+                CuError::new_with_cause(&format!("Invalid run file {}", run_file.display()), e)
+            })?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(CuError::new_with_cause(
+                    &format!("Could not read the run file {}", run_file.display()),
+                    e,
+                ))
+            }
+        };
+        let run = CuRunId {
+            uuid: new_uuid(),
+            counter: last.as_ref().map_or(1, |last| last.counter + 1),
+            previous: last.map(|last| last.uuid),
+        };
+        run.persist(run_file)?;
+        Ok(run)
+    }
+
+    /// The run of an application, from the run_file of its runtime section. A run file that cannot be read or
+    /// written does not prevent the robot from starting, the run is then not persisted.
+    pub fn from_config(config: &CuConfig) -> Self {
+        let run_file = config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.run_file.as_deref())
+            .map(Path::new);
+        Self::start(run_file).unwrap_or_else(|e| {
+            debug!("Lineage: {}, this run is not persisted.", e.to_string());
+            Self::start(None).expect("A run without run file cannot fail.")
+        })
+    }
+
+    /// Gives the run to every task in its config.
+    pub fn apply(&self, config: &mut CuConfig) {
+        for node in config.graph.node_weights_mut() {
+            node.set_param(RUN_ID_KEY, self.uuid.clone());
+            node.set_param(RUN_COUNTER_KEY, self.counter);
+            if let Some(previous) = &self.previous {
+                node.set_param(PREVIOUS_RUN_ID_KEY, previous.clone());
+            }
+        }
+    }
+
+    /// The run given to a task, see apply.
+    pub fn from_task_config(config: Option<&ComponentConfig>) -> Option<Self> {
+        let config = config?;
+        Some(CuRunId {
+            uuid: config.get::<String>(RUN_ID_KEY)?,
+            counter: config
+                .try_get::<u64>(RUN_COUNTER_KEY)
+                .ok()
+                .flatten()
+                .unwrap_or_default(),
+            previous: config.get::<String>(PREVIOUS_RUN_ID_KEY),
+        })
+    }
+
+    /// Written to a temporary file first so a crash does not leave a truncated run file.
+    fn persist(&self, run_file: &Path) -> CuResult<()> {
+        let text = ron::to_string(self)
+            .map_err(|e| CuError::new_with_cause("Could not serialize the run", e))?;
+        let mut temporary = run_file.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, text)
+            .and_then(|()| fs::rename(&temporary, run_file))
+            .map_err(|e| {
+                CuError::new_with_cause(
+                    &format!("Could not write the run file {}", run_file.display()),
+                    e,
+                )
+            })
+    }
+}
+
+impl fmt::Display for CuRunId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "run {} ({})", self.counter, self.uuid)?;
+        if let Some(previous) = &self.previous {
+            write!(f, " after {}", previous)?;
+        }
+        Ok(())
+    }
+}
+
+/// A random UUID, version 4. The hashers of the standard library are seeded from the OS.
+fn new_uuid() -> String {
+    let random = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(salt);
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        hasher.write_u32(std::process::id());
+        hasher.finish()
+    };
+    let mut bytes = (((random(0) as u128) << 64) | random(1) as u128).to_be_bytes();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Node;
+
+    #[test]
+    fn test_lineage() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_file = dir.path().join("run.ron");
+        let first = CuRunId::start(Some(&run_file)).unwrap();
+        assert_eq!(first.counter, 1);
+        assert_eq!(first.previous, None);
+        assert_eq!(first.uuid.len(), 36);
+        assert_eq!(&first.uuid[14..15], "4");
+
+        let second = CuRunId::start(Some(&run_file)).unwrap();
+        assert_eq!(second.counter, 2);
+        assert_eq!(second.previous.as_deref(), Some(first.uuid.as_str()));
+        assert_ne!(second.uuid, first.uuid);
+        assert!(second.to_string().starts_with("run 2 ("));
+
+        let unpersisted = CuRunId::start(None).unwrap();
+        assert_eq!(unpersisted.counter, 0);
+        assert_eq!(CuRunId::start(Some(&run_file)).unwrap().counter, 3);
+
+        fs::write(&run_file, "garbage").unwrap();
+        assert!(CuRunId::start(Some(&run_file)).is_err());
+
+        let mut config = CuConfig::default();
+        config.add_node(Node::new("fleet", "cu_fleet::CuFleet"));
+        second.apply(&mut config);
+        let task_config = config.get_all_nodes()[0].get_task_config();
+        assert_eq!(
+            CuRunId::from_task_config(task_config.as_ref()),
+            Some(second)
+        );
+        assert_eq!(CuRunId::from_task_config(None), None);
+    }
+}
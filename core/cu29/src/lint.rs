@@ -0,0 +1,297 @@
+//! The lints of a config: what is valid but most likely a mistake, ie. a task connected to nothing.
+//! They are printed when the runtime is generated and by `cu29-validate my_config.ron`.
+//!
+//! The assertions of the tests section of the config are checked at the same time, a failed one is an error:
+//! ```ron
+//! tests: [(path: ["cam", "detector", "planner"]), (max_period: ("control", "1ms"))],
+//! ```
+
+use crate::config::{unit_conversion_factor, ConfigAssertion, CuConfig, NodeId};
+use petgraph::algo::has_path_connecting;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::visit::{Bfs, NodeIndexable, Walker};
+use petgraph::Direction;
+use std::fmt;
+
+/// The parts of the message types heavy enough to fill a log quickly, ie. an image stream.
+const LARGE_PAYLOAD_HINTS: &[&str] = &[
+    "Image",
+    "Frame",
+    "PointCloud",
+    "Scan",
+    "Audio",
+    "Vec<",
+    "[u8",
+    "CuHandle",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CuLintKind {
+    /// A task with no connection: a source whose output is never consumed or a sink without producer.
+    Unconnected,
+    /// A task in a cycle no source feeds.
+    Unreachable,
+    /// Connected tasks with different periods and no batch between them.
+    RateMismatch,
+    /// A large payload logged with every copper list.
+    LargeLoggedPayload,
+}
+
+/// A problem found in a config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuLint {
+    pub kind: CuLintKind,
+    pub message: String,
+}
+
+impl fmt::Display for CuLint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+/// The lints of the config, in the order of the tasks then of the connections.
+pub fn lint_config(config: &CuConfig) -> Vec<CuLint> {
+    let graph = &config.graph;
+    let mut lints = Vec::new();
+
+    // Every task downstream of a task without input runs, the others wait on each other.
+    let sources: Vec<_> = graph
+        .node_indices()
+        .filter(|node| {
+            graph
+                .neighbors_directed(*node, Direction::Incoming)
+                .next()
+                .is_none()
+        })
+        .collect();
+    let mut reached = vec![false; graph.node_bound()];
+    for source in &sources {
+        for node in Bfs::new(graph, *source).iter(graph) {
+            reached[node.index()] = true;
+        }
+    }
+
+    for node in graph.node_indices() {
+        let id = graph[node].get_id();
+        if graph.neighbors_undirected(node).next().is_none() {
+            lints.push(CuLint {
+                kind: CuLintKind::Unconnected,
+                message: format!(
+                    "Task '{}' is connected to no task: it produces nothing consumed and consumes nothing.",
+                    id
+                ),
+            });
+        } else if !reached[node.index()] {
+            lints.push(CuLint {
+                kind: CuLintKind::Unreachable,
+                message: format!(
+                    "Task '{}' is in a cycle of connections no source feeds, it never gets any input.",
+                    id
+                ),
+            });
+        }
+    }
+
+    for edge in graph.edge_indices() {
+        let (src, dst) = graph.edge_endpoints(edge).unwrap();
+        let cnx = &graph[edge];
+        let periods = (
+            graph[src].get_base_period_ns(),
+            graph[dst].get_base_period_ns(),
+        );
+        if let (Some(src_period), Some(dst_period)) = periods {
+            if src_period != dst_period && cnx.batch.is_none() {
+                lints.push(CuLint {
+                    kind: CuLintKind::RateMismatch,
+                    message: format!(
+                        "'{}' runs every {}ns and '{}' every {}ns, declare a batch on their connection for the \
+                         messages in between.",
+                        cnx.get_src(),
+                        src_period,
+                        cnx.get_dst(),
+                        dst_period
+                    ),
+                });
+            }
+        }
+        if cnx.store.is_none()
+            && LARGE_PAYLOAD_HINTS
+                .iter()
+                .any(|hint| cnx.msg.contains(hint))
+        {
+            lints.push(CuLint {
+                kind: CuLintKind::LargeLoggedPayload,
+                message: format!(
+                    "The {} messages of '{}' look large and are logged with every copper list, decimate them on \
+                     a dedicated connection or set store on '{}' -> '{}' to acknowledge it.",
+                    cnx.msg,
+                    cnx.get_src(),
+                    cnx.get_src(),
+                    cnx.get_dst()
+                ),
+            });
+        }
+    }
+    lints
+}
+
+/// The failures of the assertions of the tests section of the config, empty if they all hold.
+pub fn check_assertions(config: &CuConfig) -> Vec<String> {
+    config
+        .get_assertions()
+        .iter()
+        .flat_map(|assertion| check_assertion(config, assertion))
+        .collect()
+}
+
+fn check_assertion(config: &CuConfig, assertion: &ConfigAssertion) -> Vec<String> {
+    let graph = &config.graph;
+    let find = |id: &str| -> Result<NodeIndex<NodeId>, String> {
+        graph
+            .node_indices()
+            .find(|node| graph[*node].get_id() == id)
+            .ok_or_else(|| format!("the task '{}' of an assertion is not declared", id))
+    };
+    let mut failures = Vec::new();
+    if let Some(path) = &assertion.path {
+        for pair in path.windows(2) {
+            match (find(&pair[0]), find(&pair[1])) {
+                (Ok(from), Ok(to)) => {
+                    if !has_path_connecting(graph, from, to, None) {
+                        failures.push(format!(
+                            "no path from '{}' to '{}' in {:?}",
+                            pair[0], pair[1], path
+                        ));
+                    }
+                }
+                (Err(failure), _) | (_, Err(failure)) => failures.push(failure),
+            }
+        }
+    }
+    if let Some((task, max_period)) = &assertion.max_period {
+        let period = find(task).map(|node| {
+            graph[node].get_base_period_ns().or_else(|| {
+                config
+                    .get_runtime_config()
+                    .and_then(|runtime_config| runtime_config.period_ns)
+            })
+        });
+        match (period, parse_duration_ns(max_period)) {
+            (Err(failure), _) | (_, Err(failure)) => failures.push(failure),
+            (Ok(None), _) => failures.push(format!(
+                "'{}' has no period, declare its base_period_ns or the period_ns of the runtime",
+                task
+            )),
+            (Ok(Some(period)), Ok(max)) if period as f64 > max => failures.push(format!(
+                "'{}' runs every {}ns, more than {}",
+                task, period, max_period
+            )),
+            _ => {}
+        }
+    }
+    failures
+}
+
+/// A duration written with its unit, ie. "1ms", in ns.
+fn parse_duration_ns(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a duration, ie. \"1ms\"", text))?;
+    let factor = unit_conversion_factor(unit.trim(), "ns")
+        .map_err(|_| format!("'{}' is not a duration, ie. \"1ms\"", text))?;
+    Ok(value * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_config() {
+        let txt = r#"(
+            tasks: [
+                (id: "camera", type: "a::Camera", base_period_ns: 33000000),
+                (id: "detector", type: "a::Detector", base_period_ns: 10000000),
+                (id: "orphan", type: "a::Orphan"),
+                (id: "a", type: "a::A"),
+                (id: "b", type: "a::B"),
+            ],
+            cnx: [
+                (src: "camera", dst: "detector", msg: "a::RgbImage"),
+                (src: "a", dst: "b", msg: "u32"),
+                (src: "b", dst: "a", msg: "u32"),
+            ],
+        )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        let kinds: Vec<CuLintKind> = lint_config(&config).iter().map(|l| l.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CuLintKind::Unconnected,
+                CuLintKind::Unreachable,
+                CuLintKind::Unreachable,
+                CuLintKind::RateMismatch,
+                CuLintKind::LargeLoggedPayload,
+            ]
+        );
+
+        let txt = txt
+            .replace(
+                r#"msg: "a::RgbImage""#,
+                r#"msg: "a::RgbImage", batch: 3, store: true"#,
+            )
+            .replace(r#"(src: "b", dst: "a", msg: "u32"),"#, "");
+        let config = CuConfig::deserialize_ron(&txt);
+        let kinds: Vec<CuLintKind> = lint_config(&config).iter().map(|l| l.kind).collect();
+        assert_eq!(kinds, vec![CuLintKind::Unconnected]);
+    }
+
+    #[test]
+    fn test_check_assertions() {
+        let txt = r#"(
+            tasks: [
+                (id: "cam", type: "a::Cam", base_period_ns: 33000000),
+                (id: "detector", type: "a::Detector"),
+                (id: "planner", type: "a::Planner"),
+                (id: "control", type: "a::Control"),
+            ],
+            cnx: [
+                (src: "cam", dst: "detector", msg: "u32"),
+                (src: "detector", dst: "planner", msg: "u32"),
+                (src: "planner", dst: "control", msg: "u32"),
+            ],
+            runtime: (period_ns: 1000000),
+            tests: [
+                (path: ["cam", "planner", "control"]),
+                (max_period: ("control", "1ms")),
+                (max_period: ("cam", "50 ms")),
+            ],
+        )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        assert!(check_assertions(&config).is_empty());
+
+        let txt = txt
+            .replace(
+                r#"["cam", "planner", "control"]"#,
+                r#"["control", "cam", "radar"]"#,
+            )
+            .replace(r#"("cam", "50 ms")"#, r#"("cam", "10ms")"#)
+            .replace(r#"("control", "1ms")"#, r#"("control", "1 parsec")"#);
+        let config = CuConfig::deserialize_ron(&txt);
+        assert_eq!(
+            check_assertions(&config),
+            vec![
+                r#"no path from 'control' to 'cam' in ["control", "cam", "radar"]"#,
+                "the task 'radar' of an assertion is not declared",
+                r#"'1 parsec' is not a duration, ie. "1ms""#,
+                "'cam' runs every 33000000ns, more than 10ms",
+            ]
+        );
+    }
+}
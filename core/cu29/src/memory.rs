@@ -0,0 +1,125 @@
+//! The memory used by every task, to find which one creeps on a long mission.
+//!
+//! With memory_attribution in the runtime section of the config, the counting allocator of copper charges every
+//! allocation and deallocation to the task the runtime is running on the thread, and the pools charge the handles
+//! they give to the task acquiring them until their buffer goes back to the pool. The resident bytes of a task are
+//! what it allocated minus what it freed during its process calls: a payload replaced by the next output of its
+//! task is freed by that task, a buffer freed by another task is charged to the other one.
+//!
+//! The figures are in the statistics of the tasks, the metrics file and the summary of the log.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+/// The tasks past this number are not attributed any memory.
+pub const MAX_ATTRIBUTED_TASKS: usize = 256;
+
+const NO_TASK: usize = usize::MAX;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RESIDENT_BYTES: [AtomicI64; MAX_ATTRIBUTED_TASKS] =
+    [const { AtomicI64::new(0) }; MAX_ATTRIBUTED_TASKS];
+static POOL_HANDLES: [AtomicI64; MAX_ATTRIBUTED_TASKS] =
+    [const { AtomicI64::new(0) }; MAX_ATTRIBUTED_TASKS];
+
+thread_local! {
+    /// The node id of the task running on this thread, read by the allocator so it must not allocate.
+    static ATTRIBUTED_TASK: Cell<usize> = const { Cell::new(NO_TASK) };
+}
+
+/// The memory charged to a task.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CuTaskMemory {
+    /// The bytes it allocated minus the ones it freed, negative if it frees what the others allocate.
+    pub resident_bytes: i64,
+    /// The handles it acquired from the pools whose buffer is not back in its pool yet.
+    pub pool_handles: i64,
+}
+
+/// Starts charging the memory to the tasks, for the rest of the process.
+pub fn enable_memory_attribution() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn memory_attribution_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sets the task the memory allocated on the current thread is charged to, by node id, None between the tasks.
+pub fn set_attributed_task(task: Option<usize>) {
+    ATTRIBUTED_TASK.set(task.unwrap_or(NO_TASK));
+}
+
+/// The task charged on this thread, None if the attribution is disabled.
+fn charged_task() -> Option<usize> {
+    if !memory_attribution_enabled() {
+        return None;
+    }
+    // try_with: the allocator is also called while the thread local is destroyed.
+    ATTRIBUTED_TASK
+        .try_with(Cell::get)
+        .ok()
+        .filter(|task| *task < MAX_ATTRIBUTED_TASKS)
+}
+
+/// Called by the counting allocator, a negative size for a deallocation.
+pub(crate) fn charge_bytes(size: i64) {
+    if let Some(task) = charged_task() {
+        RESIDENT_BYTES[task].fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+/// Charges a handle acquired from a pool, returns the task to give it back to when the buffer is released.
+pub(crate) fn charge_handle() -> Option<usize> {
+    let task = charged_task()?;
+    POOL_HANDLES[task].fetch_add(1, Ordering::Relaxed);
+    Some(task)
+}
+
+/// The buffer of a handle charged with charge_handle went back to its pool.
+pub(crate) fn release_handle(task: usize) {
+    POOL_HANDLES[task].fetch_sub(1, Ordering::Relaxed);
+}
+
+/// The memory charged to the task so far, by node id.
+pub fn task_memory(task: usize) -> CuTaskMemory {
+    if task >= MAX_ATTRIBUTED_TASKS {
+        return CuTaskMemory::default();
+    }
+    CuTaskMemory {
+        resident_bytes: RESIDENT_BYTES[task].load(Ordering::Relaxed),
+        pool_handles: POOL_HANDLES[task].load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::CuPool;
+
+    #[test]
+    fn test_memory_attribution() {
+        // A node id no other test uses, the counters are global.
+        let task = MAX_ATTRIBUTED_TASKS - 1;
+        enable_memory_attribution();
+        let pool = CuPool::<u8>::new("attribution", 2, 16);
+
+        set_attributed_task(Some(task));
+        let kept = vec![0u8; 4096];
+        let freed = vec![0u8; 1024];
+        drop(freed);
+        let handle = pool.acquire().unwrap();
+        set_attributed_task(None);
+
+        let memory = task_memory(task);
+        assert!(memory.resident_bytes >= 4096, "{:?}", memory);
+        assert!(memory.resident_bytes < 4096 + 1024, "{:?}", memory);
+        assert_eq!(memory.pool_handles, 1);
+
+        // Released by another task, still given back to the one which acquired it.
+        drop(handle);
+        assert_eq!(task_memory(task).pool_handles, 0);
+        drop(kept);
+        assert_eq!(task_memory(MAX_ATTRIBUTED_TASKS), CuTaskMemory::default());
+    }
+}
@@ -0,0 +1,483 @@
+//! The timing of the tasks and the usage of the pools published live in a memory mapped file, ie. in /dev/shm,
+//! so an external profiler or cu29-top shows them without attaching a debugger nor adding a network sink.
+//!
+//! The layout is specified in doc/metrics_layout.md. Every field is a 64 bits word written atomically and every
+//! slot is protected by a sequence lock: the runtime never waits for a reader, a reader retries a slot that
+//! changed while it was copying it.
+
+use crate::monitoring::{CuHealth, CuTaskStats};
+use crate::pool::CuPoolStats;
+use cu29_clock::CuTime;
+use cu29_log_runtime::CuErrorCounts;
+use cu29_traits::{CuError, CuResult};
+use memmap2::{Mmap, MmapMut};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// "CUMT" in little-endian.
+pub const METRICS_MAGIC: u32 = 0x544d_5543;
+pub const METRICS_VERSION: u32 = 4;
+pub const METRICS_HEADER_SIZE: usize = 64;
+pub const METRICS_TASK_SLOT_SIZE: usize = 272;
+pub const METRICS_POOL_SLOT_SIZE: usize = 96;
+/// The number of pool slots, the pools past this are not published.
+pub const METRICS_MAX_POOLS: usize = 32;
+
+const TASK_NAME_OFFSET: usize = 80;
+const TASK_ERROR_OFFSET: usize = 144;
+const TASK_MEMORY_OFFSET: usize = 256;
+const POOL_NAME_OFFSET: usize = 40;
+
+/// The words of the memory mapped file.
+struct Words<'a>(&'a [AtomicU64]);
+
+impl<'a> Words<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        // The mapping is page aligned and its length a multiple of 8.
+        let words = unsafe {
+            std::slice::from_raw_parts(bytes.as_ptr() as *const AtomicU64, bytes.len() / 8)
+        };
+        Words(words)
+    }
+
+    fn load(&self, offset: usize) -> u64 {
+        self.0[offset / 8].load(Ordering::Relaxed)
+    }
+
+    fn store(&self, offset: usize, value: u64) {
+        self.0[offset / 8].store(value, Ordering::Relaxed);
+    }
+
+    fn store_name(&self, offset: usize, len: usize, name: &str) {
+        let mut bytes = [0u8; 128];
+        let name = &name.as_bytes()[..name.len().min(len - 1)];
+        bytes[..name.len()].copy_from_slice(name);
+        for (i, chunk) in bytes[..len].chunks(8).enumerate() {
+            self.store(
+                offset + i * 8,
+                u64::from_le_bytes(chunk.try_into().unwrap()),
+            );
+        }
+    }
+
+    fn load_name(&self, offset: usize, len: usize) -> String {
+        let bytes: Vec<u8> = (0..len / 8)
+            .flat_map(|i| self.load(offset + i * 8).to_le_bytes())
+            .take_while(|byte| *byte != 0)
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Writes a slot under its sequence lock, odd while it is being written.
+    fn write_slot(&self, offset: usize, write: impl FnOnce()) {
+        let seq = self.0[offset / 8].fetch_add(1, Ordering::Acquire);
+        std::sync::atomic::fence(Ordering::Release);
+        write();
+        self.0[offset / 8].store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Reads a consistent copy of a slot, retrying while it changes under us.
+    fn read_slot<T>(&self, offset: usize, read: impl Fn() -> T) -> T {
+        loop {
+            let before = self.0[offset / 8].load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let value = read();
+            std::sync::atomic::fence(Ordering::Acquire);
+            if self.0[offset / 8].load(Ordering::Relaxed) == before {
+                return value;
+            }
+        }
+    }
+}
+
+fn task_offset(task: usize) -> usize {
+    METRICS_HEADER_SIZE + task * METRICS_TASK_SLOT_SIZE
+}
+
+fn pool_offset(task_count: usize, pool: usize) -> usize {
+    task_offset(task_count) + pool * METRICS_POOL_SLOT_SIZE
+}
+
+fn health_code(health: CuHealth) -> u64 {
+    match health {
+        CuHealth::Unknown => 0,
+        CuHealth::Nominal => 1,
+        CuHealth::Degraded => 2,
+        CuHealth::Failed => 3,
+    }
+}
+
+fn health_from_code(code: u64) -> CuHealth {
+    match code {
+        1 => CuHealth::Nominal,
+        2 => CuHealth::Degraded,
+        3 => CuHealth::Failed,
+        _ => CuHealth::Unknown,
+    }
+}
+
+/// Publishes the metrics of the runtime, see metrics_path in the runtime section of the config.
+pub struct CuMetricsWriter {
+    map: MmapMut,
+    task_count: usize,
+}
+
+impl CuMetricsWriter {
+    /// Creates the file at path with a slot for every task, named after them.
+    pub fn create(path: &Path, task_names: &[&str]) -> CuResult<Self> {
+        let task_count = task_names.len();
+        // A new file rather than truncating the one of a previous run: a reader still mapping it must not fault.
+        let _ = std::fs::remove_file(path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| CuError::new_with_cause("Could not create the metrics file", e))?;
+        file.set_len(pool_offset(task_count, METRICS_MAX_POOLS) as u64)
+            .map_err(|e| CuError::new_with_cause("Could not size the metrics file", e))?;
+        let map = unsafe { MmapMut::map_mut(&file) }
+            .map_err(|e| CuError::new_with_cause("Could not map the metrics file", e))?;
+        let writer = CuMetricsWriter { map, task_count };
+        let words = writer.words();
+        for (task, name) in task_names.iter().enumerate() {
+            words.store_name(task_offset(task) + TASK_NAME_OFFSET, 64, name);
+        }
+        words.store(8, task_count as u64 | (METRICS_MAX_POOLS as u64) << 32);
+        words.store(
+            16,
+            METRICS_TASK_SLOT_SIZE as u64 | (METRICS_POOL_SLOT_SIZE as u64) << 32,
+        );
+        words.store(24, std::process::id() as u64);
+        // The magic last, a reader does not see a half initialized file.
+        words.store(0, METRICS_MAGIC as u64 | (METRICS_VERSION as u64) << 32);
+        Ok(writer)
+    }
+
+    fn words(&self) -> Words<'_> {
+        Words::new(&self.map)
+    }
+
+    /// Publishes the statistics of the tasks, indexed by node id, at the time now. Does not allocate.
+    pub fn publish_tasks(&self, now: CuTime, stats: &[CuTaskStats]) {
+        let words = self.words();
+        for (task, stats) in stats.iter().enumerate().take(self.task_count) {
+            let offset = task_offset(task);
+            words.write_slot(offset, || {
+                words.store(offset + 8, stats.process_count);
+                words.store(offset + 16, stats.error_count);
+                words.store(offset + 24, stats.busy_time.0);
+                words.store(
+                    offset + 32,
+                    stats.process_duration.map(|d| d.0).unwrap_or_default(),
+                );
+                words.store(
+                    offset + 40,
+                    stats.smoothed_period.map(|p| p.0).unwrap_or_default(),
+                );
+                words.store(offset + 48, health_code(stats.health));
+                words.store(offset + 56, stats.scratch_peak as u64);
+                words.store(offset + 64, stats.max_process_duration.0);
+                words.store(offset + 72, stats.output_bytes);
+                words.store_name(
+                    offset + TASK_ERROR_OFFSET,
+                    112,
+                    stats.last_error.as_deref().unwrap_or_default(),
+                );
+                words.store(
+                    offset + TASK_MEMORY_OFFSET,
+                    stats.memory.resident_bytes as u64,
+                );
+                words.store(
+                    offset + TASK_MEMORY_OFFSET + 8,
+                    stats.memory.pool_handles as u64,
+                );
+            });
+        }
+        words.store(32, now.0);
+        words.store(40, words.load(40) + 1);
+    }
+
+    /// Publishes the number of errors logged with error!, see cu29_log_runtime::error_counts.
+    pub fn publish_log_errors(&self, counts: CuErrorCounts) {
+        let words = self.words();
+        words.store(48, counts.count);
+        words.store(56, counts.suppressed);
+    }
+
+    /// Publishes the statistics of the pools, the ones past METRICS_MAX_POOLS are left out.
+    pub fn publish_pools(&self, pools: &[CuPoolStats]) {
+        let words = self.words();
+        for pool in 0..METRICS_MAX_POOLS {
+            let offset = pool_offset(self.task_count, pool);
+            words.write_slot(offset, || match pools.get(pool) {
+                Some(stats) => {
+                    words.store(offset + 8, stats.capacity as u64);
+                    words.store(offset + 16, stats.available as u64);
+                    words.store(offset + 24, stats.buffer_len as u64);
+                    words.store(offset + 32, stats.exhausted);
+                    words.store_name(offset + POOL_NAME_OFFSET, 56, &stats.id);
+                }
+                None => words.store_name(offset + POOL_NAME_OFFSET, 56, ""),
+            });
+        }
+    }
+}
+
+/// The metrics of a task read from the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuTaskMetrics {
+    pub name: String,
+    pub process_count: u64,
+    pub error_count: u64,
+    /// The total time spent in its process calls.
+    pub busy_ns: u64,
+    /// The duration of its last process call.
+    pub last_duration_ns: u64,
+    /// The smoothed period between 2 process calls, 0 before the second one.
+    pub period_ns: u64,
+    /// The duration of its longest process call.
+    pub max_duration_ns: u64,
+    /// The total number of bytes encoded from its outputs.
+    pub output_bytes: u64,
+    pub health: CuHealth,
+    pub scratch_peak: u64,
+    /// The message of the last error of the task, cut to 111 bytes.
+    pub last_error: Option<String>,
+    /// The memory charged to the task, 0 without memory_attribution. See cu29::memory.
+    pub resident_bytes: i64,
+    pub pool_handles: i64,
+}
+
+impl CuTaskMetrics {
+    /// The mean duration of its process calls.
+    pub fn mean_duration_ns(&self) -> Option<u64> {
+        (self.process_count > 0).then(|| self.busy_ns / self.process_count)
+    }
+
+    /// The rate of the process calls.
+    pub fn rate_hz(&self) -> Option<f64> {
+        (self.period_ns > 0).then(|| 1_000_000_000.0 / self.period_ns as f64)
+    }
+}
+
+/// The metrics of a pool read from the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuPoolMetrics {
+    pub name: String,
+    pub capacity: u64,
+    pub available: u64,
+    pub buffer_len: u64,
+    pub exhausted: u64,
+}
+
+/// A consistent copy of every slot of the file.
+#[derive(Debug, Clone)]
+pub struct CuMetricsSnapshot {
+    pub pid: u32,
+    /// The robot time of the last publication.
+    pub time: CuTime,
+    /// The number of publications, it stops increasing when the runtime is stopped.
+    pub publications: u64,
+    /// The errors logged with error!, the ones suppressed from the log included.
+    pub log_errors: CuErrorCounts,
+    pub tasks: Vec<CuTaskMetrics>,
+    pub pools: Vec<CuPoolMetrics>,
+}
+
+impl CuMetricsSnapshot {
+    /// The share of the time a task spent in its process calls since an earlier snapshot, from 0 to 1.
+    pub fn cpu_load(&self, earlier: &CuMetricsSnapshot, task: usize) -> Option<f64> {
+        let elapsed = self.time.0.checked_sub(earlier.time.0).filter(|e| *e > 0)?;
+        let busy = self.tasks.get(task)?.busy_ns - earlier.tasks.get(task)?.busy_ns;
+        Some(busy as f64 / elapsed as f64)
+    }
+
+    /// The number of bytes per second a task encoded from its outputs since an earlier snapshot.
+    pub fn bandwidth(&self, earlier: &CuMetricsSnapshot, task: usize) -> Option<f64> {
+        let elapsed = self.time.0.checked_sub(earlier.time.0).filter(|e| *e > 0)?;
+        let bytes = self.tasks.get(task)?.output_bytes - earlier.tasks.get(task)?.output_bytes;
+        Some(bytes as f64 * 1_000_000_000.0 / elapsed as f64)
+    }
+}
+
+/// Reads the metrics published by a runtime, from another process.
+pub struct CuMetricsReader {
+    map: Mmap,
+    task_count: usize,
+    pool_count: usize,
+}
+
+impl CuMetricsReader {
+    pub fn open(path: &Path) -> CuResult<Self> {
+        let file = File::open(path)
+            .map_err(|e| CuError::new_with_cause("Could not open the metrics file", e))?;
+        let map = unsafe { Mmap::map(&file) }
+            .map_err(|e| CuError::new_with_cause("Could not map the metrics file", e))?;
+        if map.len() < METRICS_HEADER_SIZE {
+            return Err("The metrics file is too short.".into());
+        }
+        let words = Words::new(&map);
+        let magic = words.load(0);
+        if magic as u32 != METRICS_MAGIC {
+            return Err(
+                "This is not a metrics file of Copper, or it is not initialized yet.".into(),
+            );
+        }
+        if (magic >> 32) as u32 != METRICS_VERSION {
+            return Err(format!(
+                "The metrics file is in version {}, this reader reads version {}.",
+                magic >> 32,
+                METRICS_VERSION
+            )
+            .into());
+        }
+        let counts = words.load(8);
+        let task_count = counts as u32 as usize;
+        let pool_count = (counts >> 32) as usize;
+        if map.len() < pool_offset(task_count, pool_count) {
+            return Err("The metrics file is truncated.".into());
+        }
+        Ok(CuMetricsReader {
+            map,
+            task_count,
+            pool_count,
+        })
+    }
+
+    pub fn snapshot(&self) -> CuMetricsSnapshot {
+        let words = Words::new(&self.map);
+        let tasks = (0..self.task_count)
+            .map(|task| {
+                let offset = task_offset(task);
+                words.read_slot(offset, || CuTaskMetrics {
+                    name: words.load_name(offset + TASK_NAME_OFFSET, 64),
+                    process_count: words.load(offset + 8),
+                    error_count: words.load(offset + 16),
+                    busy_ns: words.load(offset + 24),
+                    last_duration_ns: words.load(offset + 32),
+                    period_ns: words.load(offset + 40),
+                    max_duration_ns: words.load(offset + 64),
+                    output_bytes: words.load(offset + 72),
+                    health: health_from_code(words.load(offset + 48)),
+                    scratch_peak: words.load(offset + 56),
+                    last_error: Some(words.load_name(offset + TASK_ERROR_OFFSET, 112))
+                        .filter(|error| !error.is_empty()),
+                    resident_bytes: words.load(offset + TASK_MEMORY_OFFSET) as i64,
+                    pool_handles: words.load(offset + TASK_MEMORY_OFFSET + 8) as i64,
+                })
+            })
+            .collect();
+        let pools = (0..self.pool_count)
+            .map(|pool| {
+                let offset = pool_offset(self.task_count, pool);
+                words.read_slot(offset, || CuPoolMetrics {
+                    name: words.load_name(offset + POOL_NAME_OFFSET, 56),
+                    capacity: words.load(offset + 8),
+                    available: words.load(offset + 16),
+                    buffer_len: words.load(offset + 24),
+                    exhausted: words.load(offset + 32),
+                })
+            })
+            .filter(|pool| !pool.name.is_empty())
+            .collect();
+        CuMetricsSnapshot {
+            pid: words.load(24) as u32,
+            time: CuTime::from(words.load(32)),
+            publications: words.load(40),
+            log_errors: CuErrorCounts {
+                count: words.load(48),
+                suppressed: words.load(56),
+            },
+            tasks,
+            pools,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::CuTaskMemory;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_metrics() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("metrics");
+        let writer = CuMetricsWriter::create(
+            &path,
+            &[
+                "camera",
+                "a_task_with_a_very_long_name_that_does_not_fit_in_its_slot_at_all",
+            ],
+        )
+        .unwrap();
+        let reader = CuMetricsReader::open(&path).unwrap();
+        let first = reader.snapshot();
+        assert_eq!(first.pid, std::process::id());
+        assert_eq!(first.publications, 0);
+        assert_eq!(first.tasks[0].name, "camera");
+        assert_eq!(first.tasks[1].name.len(), 63);
+        assert!(first.pools.is_empty());
+
+        let mut stats = vec![CuTaskStats::default(); 2];
+        stats[0].record_process(CuTime::from(0).into(), &Ok(()));
+        stats[0].record_process_time(CuTime::from(0), CuTime::from(2_000_000));
+        stats[0].record_process(CuTime::from(10_000_000).into(), &Ok(()));
+        stats[0].record_process_time(CuTime::from(10_000_000), CuTime::from(13_000_000));
+        stats[0].output_bytes = 4096;
+        stats[1].record_process(CuTime::from(0).into(), &Err("no fix".into()));
+        stats[1].record_memory(CuTaskMemory {
+            resident_bytes: -512,
+            pool_handles: 3,
+        });
+        writer.publish_tasks(CuTime::from(10_000_000), &stats);
+        writer.publish_pools(&[CuPoolStats {
+            id: "camera.frames".to_string(),
+            capacity: 4,
+            available: 3,
+            buffer_len: 640,
+            exhausted: 0,
+            fill: None,
+        }]);
+
+        writer.publish_log_errors(CuErrorCounts {
+            count: 12,
+            suppressed: 10,
+        });
+
+        let second = reader.snapshot();
+        assert_eq!(second.publications, 1);
+        assert_eq!(second.log_errors.count, 12);
+        assert_eq!(second.log_errors.suppressed, 10);
+        let camera = &second.tasks[0];
+        assert_eq!(camera.process_count, 2);
+        assert_eq!(camera.busy_ns, 5_000_000);
+        assert_eq!(camera.last_duration_ns, 3_000_000);
+        assert_eq!(camera.rate_hz(), Some(100.0));
+        assert_eq!(camera.max_duration_ns, 3_000_000);
+        assert_eq!(camera.mean_duration_ns(), Some(2_500_000));
+        assert_eq!(camera.health, CuHealth::Nominal);
+        assert_eq!(camera.last_error, None);
+        assert!(second.tasks[1]
+            .last_error
+            .as_ref()
+            .is_some_and(|error| error.starts_with("no fix")));
+        assert_eq!(second.tasks[1].rate_hz(), None);
+        assert_eq!(second.tasks[1].resident_bytes, -512);
+        assert_eq!(second.tasks[1].pool_handles, 3);
+        assert_eq!(second.cpu_load(&first, 0), Some(0.5));
+        // 4KiB in 10ms.
+        assert_eq!(second.bandwidth(&first, 0), Some(409_600.0));
+        assert_eq!(second.pools[0].name, "camera.frames");
+        assert_eq!(second.pools[0].available, 3);
+
+        std::fs::write(dir.path().join("other"), [0u8; 128]).unwrap();
+        assert!(CuMetricsReader::open(&dir.path().join("other")).is_err());
+    }
+}
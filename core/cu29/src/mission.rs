@@ -0,0 +1,125 @@
+//! The missions of an application, the modes running different subsets of its tasks, ie. a robot driven with a
+//! joystick or autonomous:
+//!
+//! ```ron
+//! tasks: [
+//!     (id: "joystick", type: "a::Joystick"),
+//!     (id: "camera", type: "a::Camera"),
+//!     (id: "planner", type: "a::Planner"),
+//!     (id: "motors", type: "a::Motors"),
+//! ],
+//! missions: [
+//!     (id: "teleop", tasks: ["joystick"]),
+//!     (id: "autonomous", tasks: ["camera", "planner"]),
+//! ],
+//! ```
+//!
+//! The graph holds the tasks of all the missions and the tasks in no mission, here the motors, run in all of them
+//! with the same instance, keeping their state across the switches. The application starts in the first mission and
+//! CuRuntime::switch_mission changes it between two iterations. The tasks out of the current mission are skipped like
+//! the disabled ones (see CuRuntime::disable_task): the tasks downstream keep seeing their last message. All the
+//! tasks are started and stopped with the application, whatever the mission.
+
+use crate::config::{closest_match, CuConfig};
+use crate::{CuError, CuResult};
+
+/// The missions of the config and the current one.
+pub struct CuMissions {
+    ids: Vec<String>,
+    /// The tasks processed in every mission, by node id.
+    runs: Vec<Vec<bool>>,
+    current: usize,
+}
+
+impl CuMissions {
+    pub fn from_config(config: &CuConfig) -> Self {
+        let nodes = config.get_all_nodes();
+        let in_a_mission = |id: &String| {
+            config
+                .get_missions()
+                .iter()
+                .any(|mission| mission.tasks.contains(id))
+        };
+        let runs = config
+            .get_missions()
+            .iter()
+            .map(|mission| {
+                nodes
+                    .iter()
+                    .map(|node| {
+                        let id = node.get_id();
+                        mission.tasks.contains(&id) || !in_a_mission(&id)
+                    })
+                    .collect()
+            })
+            .collect();
+        CuMissions {
+            ids: config
+                .get_missions()
+                .iter()
+                .map(|mission| mission.id.clone())
+                .collect(),
+            runs,
+            current: 0,
+        }
+    }
+
+    /// The current mission, None if the config has none.
+    pub fn current(&self) -> Option<&str> {
+        self.ids.get(self.current).map(String::as_str)
+    }
+
+    /// Whether the task is processed in the current mission, always without missions.
+    pub fn runs(&self, node_id: usize) -> bool {
+        self.runs.get(self.current).is_none_or(|runs| runs[node_id])
+    }
+
+    pub fn switch(&mut self, id: &str) -> CuResult<()> {
+        self.current = self
+            .ids
+            .iter()
+            .position(|mission| mission == id)
+            .ok_or_else(|| {
+                let hint = closest_match(id, self.ids.iter().map(String::as_str))
+                    .map(|m| format!(" Did you mean '{}'?", m))
+                    .unwrap_or_default();
+                CuError::from(format!(
+                    "Cannot switch to the mission '{}': there is no such mission.{}",
+                    id, hint
+                ))
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_configuration_str;
+
+    #[test]
+    fn test_missions() {
+        let config = read_configuration_str(
+            r#"(
+                tasks: [(id: "joystick", type: "a"), (id: "planner", type: "b"), (id: "motors", type: "c")],
+                cnx: [],
+                missions: [(id: "teleop", tasks: ["joystick"]), (id: "autonomous", tasks: ["planner"])],
+            )"#,
+        )
+        .unwrap();
+        let mut missions = CuMissions::from_config(&config);
+        assert_eq!(missions.current(), Some("teleop"));
+        let runs =
+            |missions: &CuMissions| (0..3).map(|node| missions.runs(node)).collect::<Vec<_>>();
+        assert_eq!(runs(&missions), vec![true, false, true]);
+        missions.switch("autonomous").unwrap();
+        assert_eq!(runs(&missions), vec![false, true, true]);
+        let error = missions.switch("autonomus").unwrap_err();
+        assert!(error.to_string().contains("Did you mean 'autonomous'?"));
+        assert_eq!(missions.current(), Some("autonomous"));
+
+        let missions = CuMissions::from_config(&CuConfig::default());
+        assert_eq!(missions.current(), None);
+        assert!(missions.runs(0));
+    }
+}
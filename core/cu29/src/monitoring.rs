@@ -1,9 +1,10 @@
 //! Some basic internal monitoring tooling Copper uses to monitor itself and the tasks it is running.
 //!
 
-use crate::config::ComponentConfig;
+use crate::config::{ComponentConfig, DeadlinePolicy};
 use crate::cutask::CuMsgMetadata;
-use cu29_clock::{CuDuration, RobotClock};
+use crate::memory::{charge_bytes, CuTaskMemory};
+use cu29_clock::{CuDuration, CuTime, OptionCuTime, RobotClock};
 use cu29_traits::{CuError, CuResult};
 use std::alloc::{GlobalAlloc, Layout, System};
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -67,6 +68,346 @@ impl CuMonitor for NoMonitor {
     }
 }
 
+/// The health of a task as seen by the runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CuHealth {
+    /// The task has not been executed yet.
+    #[default]
+    Unknown,
+    /// The last execution of the task went fine.
+    Nominal,
+    /// The task is running but something is not quite right.
+    Degraded,
+    /// The last execution of the task errored out.
+    Failed,
+}
+
+/// Statistics the runtime maintains for every task as it executes them.
+/// Updating them does not allocate on the nominal path.
+#[derive(Debug, Clone, Default)]
+pub struct CuTaskStats {
+    /// Number of process calls.
+    pub process_count: u64,
+    /// Number of process calls that returned an error.
+    pub error_count: u64,
+    /// Time at which the last process call started.
+    pub last_process: OptionCuTime,
+    /// Exponentially smoothed period between 2 process calls.
+    pub smoothed_period: Option<CuDuration>,
+    /// The message of the last error reported by the task.
+    pub last_error: Option<String>,
+    /// The current health of the task.
+    pub health: CuHealth,
+    /// The current state reported by a modal task.
+    pub state: Option<String>,
+    /// Number of successful starts of the task, more than 1 if it was restarted.
+    pub start_count: u64,
+    /// Time at which the task was last started.
+    pub last_start: OptionCuTime,
+    /// Duration of the last start call.
+    pub start_duration: Option<CuDuration>,
+    /// Duration of the last preprocess call.
+    pub preprocess_duration: Option<CuDuration>,
+    /// Duration of the last postprocess call.
+    pub postprocess_duration: Option<CuDuration>,
+    /// Duration of the last stop call.
+    pub stop_duration: Option<CuDuration>,
+    /// The largest number of bytes the task used from its scratch arena in a process call.
+    pub scratch_peak: usize,
+    /// Duration of the last process call.
+    pub process_duration: Option<CuDuration>,
+    /// Total time spent in the process calls, for the load of the task.
+    pub busy_time: CuDuration,
+    /// Duration of the longest process call.
+    pub max_process_duration: CuDuration,
+    /// Total number of bytes encoded from the outputs of the task, its share of the log.
+    pub output_bytes: u64,
+    /// The memory charged to the task, with memory_attribution in the runtime section. See cu29::memory.
+    pub memory: CuTaskMemory,
+    /// The most bytes charged to the task at the end of an iteration.
+    pub peak_resident_bytes: i64,
+    /// The most pool handles held by the task at the end of an iteration.
+    pub peak_pool_handles: i64,
+}
+
+impl CuTaskStats {
+    /// Records the outcome of a process call started at `before_process`.
+    pub fn record_process(&mut self, before_process: OptionCuTime, outcome: &CuResult<()>) {
+        self.process_count += 1;
+        if !before_process.is_none() {
+            let now = before_process.unwrap();
+            if !self.last_process.is_none() {
+                let last = self.last_process.unwrap();
+                if now > last {
+                    let period = now - last;
+                    // 1/8 smoothing so we are responsive but not jumpy.
+                    self.smoothed_period = Some(match self.smoothed_period {
+                        Some(smoothed) => CuDuration((smoothed.0 * 7 + period.0) / 8),
+                        None => period,
+                    });
+                }
+            }
+            self.last_process = before_process;
+        }
+        match outcome {
+            Ok(()) => self.health = CuHealth::Nominal,
+            Err(error) => {
+                self.error_count += 1;
+                self.last_error = Some(error.to_string());
+                self.health = CuHealth::Failed;
+            }
+        }
+    }
+
+    /// Records the time the last process call took.
+    pub fn record_process_time(&mut self, before: CuTime, after: CuTime) {
+        let duration = if after > before {
+            after - before
+        } else {
+            CuDuration(0)
+        };
+        self.process_duration = Some(duration);
+        self.max_process_duration = self.max_process_duration.max(duration);
+        self.busy_time += duration;
+    }
+
+    /// Records how much of its scratch arena the task used in its last process call.
+    pub fn record_scratch(&mut self, used: usize) {
+        self.scratch_peak = self.scratch_peak.max(used);
+    }
+
+    /// Records the memory charged to the task at the end of an iteration.
+    pub fn record_memory(&mut self, memory: CuTaskMemory) {
+        self.memory = memory;
+        self.peak_resident_bytes = self.peak_resident_bytes.max(memory.resident_bytes);
+        self.peak_pool_handles = self.peak_pool_handles.max(memory.pool_handles);
+    }
+
+    /// Applies the health the task reported during its last process call, see CuContext::report_health.
+    /// A call that errored out stays Failed whatever the task reported.
+    pub fn record_reported_health(&mut self, reported: Option<CuHealth>) {
+        if let Some(health) = reported {
+            if self.health != CuHealth::Failed {
+                self.health = health;
+            }
+        }
+    }
+
+    /// Records the timing of a lifecycle call (start, preprocess, postprocess or stop) and its outcome.
+    /// The process calls are recorded with record_process.
+    pub fn record_lifecycle(
+        &mut self,
+        step: CuTaskState,
+        before: CuTime,
+        after: CuTime,
+        outcome: &CuResult<()>,
+    ) {
+        let duration = Some(after - before);
+        match step {
+            CuTaskState::Start => {
+                self.start_duration = duration;
+                if outcome.is_ok() {
+                    self.start_count += 1;
+                    self.last_start = before.into();
+                }
+            }
+            CuTaskState::Preprocess => self.preprocess_duration = duration,
+            CuTaskState::Postprocess => self.postprocess_duration = duration,
+            CuTaskState::Stop => self.stop_duration = duration,
+            CuTaskState::Process => {}
+        }
+    }
+
+    /// The time since the start of the last process call, None before the first one.
+    pub fn time_since_last_process(&self, now: CuTime) -> Option<CuDuration> {
+        let last: Option<CuTime> = self.last_process.into();
+        last.and_then(|last| (now >= last).then(|| now - last))
+    }
+
+    /// Records the state reported by the task, only allocates when it changes.
+    pub fn record_state(&mut self, state: Option<&str>) {
+        if self.state.as_deref() != state {
+            self.state = state.map(str::to_string);
+        }
+    }
+
+    /// The measured rate of the process calls in Hz.
+    pub fn measured_rate_hz(&self) -> Option<f64> {
+        self.smoothed_period
+            .filter(|p| p.0 > 0)
+            .map(|p| 1_000_000_000.0 / p.0 as f64)
+    }
+}
+
+/// The liveliness of a connection: how old the data on it is, from the last message its source produced.
+/// Updating it does not allocate.
+#[derive(Debug, Clone)]
+pub struct CuEdgeStats {
+    /// Friendly name of the source task.
+    pub src: String,
+    /// Friendly name of the destination task.
+    pub dst: String,
+    /// The age from which the data is stale, max_age_ns of the connection in the config.
+    pub max_age: Option<CuDuration>,
+    /// Time of the last message with a payload the source produced.
+    pub last_produced: OptionCuTime,
+    /// Time of the first process call of the source, the age of the data until it produces something.
+    pub first_process: OptionCuTime,
+    /// Degraded when the data is stale, Unknown before the source ran.
+    pub health: CuHealth,
+    /// The age from which the data delivered to the destination is late, deadline_ms of the connection.
+    pub deadline: Option<CuDuration>,
+    pub deadline_policy: DeadlinePolicy,
+    /// How many times late data was delivered to the destination.
+    pub deadline_misses: u64,
+    /// Whether the last message of the source is still to be delivered to the destination.
+    undelivered: bool,
+}
+
+impl CuEdgeStats {
+    pub fn new(src: &str, dst: &str, max_age: Option<CuDuration>) -> Self {
+        CuEdgeStats {
+            src: src.to_string(),
+            dst: dst.to_string(),
+            max_age,
+            last_produced: OptionCuTime::none(),
+            first_process: OptionCuTime::none(),
+            health: CuHealth::Unknown,
+            deadline: None,
+            deadline_policy: DeadlinePolicy::Warn,
+            deadline_misses: 0,
+            undelivered: false,
+        }
+    }
+
+    /// Sets the deadline of the data delivered to the destination.
+    pub fn with_deadline(mut self, deadline: Option<CuDuration>, policy: DeadlinePolicy) -> Self {
+        self.deadline = deadline;
+        self.deadline_policy = policy;
+        self
+    }
+
+    /// Records a process call of the source at time, and whether it produced a payload.
+    /// Returns true if the health of the connection changed.
+    pub fn record_output(&mut self, time: CuTime, produced: bool) -> bool {
+        if self.first_process.is_none() {
+            self.first_process = time.into();
+        }
+        if produced {
+            self.last_produced = time.into();
+        }
+        self.undelivered = produced;
+        self.update_health(time)
+    }
+
+    /// Records the delivery of the last message of the source to the destination at time.
+    /// Returns true if it is older than the deadline, nothing is delivered if the source produced nothing.
+    pub fn record_delivery(&mut self, time: CuTime) -> bool {
+        if !std::mem::take(&mut self.undelivered) {
+            return false;
+        }
+        let late = match (self.deadline, self.age(time)) {
+            (Some(deadline), Some(age)) => age > deadline,
+            _ => false,
+        };
+        if late {
+            self.deadline_misses += 1;
+        }
+        late
+    }
+
+    /// How old the data on the connection is, None before the source ran.
+    pub fn age(&self, now: CuTime) -> Option<CuDuration> {
+        let since = if self.last_produced.is_none() {
+            self.first_process
+        } else {
+            self.last_produced
+        };
+        let since: Option<CuTime> = since.into();
+        since.map(|since| {
+            if now > since {
+                now - since
+            } else {
+                CuDuration(0)
+            }
+        })
+    }
+
+    /// Whether the data is older than max_age.
+    pub fn is_stale(&self, now: CuTime) -> bool {
+        match (self.age(now), self.max_age) {
+            (Some(age), Some(max_age)) => age > max_age,
+            _ => false,
+        }
+    }
+
+    /// Flips the health to Degraded when the data gets stale and back to Nominal when it is fresh again.
+    /// Returns true if the health changed.
+    pub fn update_health(&mut self, now: CuTime) -> bool {
+        let health = if self.age(now).is_none() {
+            CuHealth::Unknown
+        } else if self.is_stale(now) {
+            CuHealth::Degraded
+        } else {
+            CuHealth::Nominal
+        };
+        let changed = health != self.health;
+        self.health = health;
+        changed
+    }
+}
+
+/// Records a process call of a task at time on the connections going out of it, outgoing being their indices
+/// in edges, and whether it produced a message. A connection whose data gets stale is logged.
+pub fn record_edges_output(
+    edges: &mut [CuEdgeStats],
+    outgoing: &[usize],
+    time: CuTime,
+    produced: bool,
+) {
+    for &edge in outgoing {
+        let stats = &mut edges[edge];
+        if stats.record_output(time, produced) && stats.health == CuHealth::Degraded {
+            debug!(
+                "Monitoring: the data from '{}' to '{}' is stale, the last message is {} old.",
+                stats.src.as_str(),
+                stats.dst.as_str(),
+                stats.age(time).unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Records the delivery at time of the messages on the connections going in a task, incoming being their indices
+/// in edges. Late data is logged, and is an error if the policy of its connection is Trip.
+pub fn record_edges_delivery(
+    edges: &mut [CuEdgeStats],
+    incoming: &[usize],
+    time: CuTime,
+) -> CuResult<()> {
+    let mut result = Ok(());
+    for &edge in incoming {
+        let stats = &mut edges[edge];
+        if !stats.record_delivery(time) {
+            continue;
+        }
+        let age = stats.age(time).unwrap_or_default();
+        debug!(
+            "Monitoring: the data from '{}' to '{}' missed its deadline, it is {} old.",
+            stats.src.as_str(),
+            stats.dst.as_str(),
+            age
+        );
+        if stats.deadline_policy == DeadlinePolicy::Trip && result.is_ok() {
+            result = Err(CuError::from(format!(
+                "The data from '{}' missed its deadline, it is {} old.",
+                stats.src, age
+            )));
+        }
+    }
+    result
+}
+
 #[global_allocator]
 pub static GLOBAL: CountingAllocator = CountingAllocator::new();
 
@@ -109,6 +450,7 @@ unsafe impl GlobalAlloc for CountingAllocator {
         let ptr = System.alloc(layout);
         if !ptr.is_null() {
             self.allocated.fetch_add(layout.size(), Ordering::SeqCst);
+            charge_bytes(layout.size() as i64);
         }
         ptr
     }
@@ -116,6 +458,7 @@ unsafe impl GlobalAlloc for CountingAllocator {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         System.dealloc(ptr, layout);
         self.deallocated.fetch_add(layout.size(), Ordering::SeqCst);
+        charge_bytes(-(layout.size() as i64));
     }
 }
 
@@ -335,6 +678,102 @@ mod tests {
         assert_eq!(stats.len(), 0);
     }
 
+    #[test]
+    fn test_task_stats() {
+        let mut stats = CuTaskStats::default();
+        assert_eq!(stats.health, CuHealth::Unknown);
+        assert!(stats.time_since_last_process(CuDuration(1)).is_none());
+        stats.record_process(CuDuration(1_000_000).into(), &Ok(()));
+        assert_eq!(stats.health, CuHealth::Nominal);
+        assert!(stats.measured_rate_hz().is_none());
+        assert_eq!(
+            stats.time_since_last_process(CuDuration(2_000_000)),
+            Some(CuDuration(1_000_000))
+        );
+        stats.record_process(CuDuration(2_000_000).into(), &Ok(()));
+        assert_eq!(stats.measured_rate_hz(), Some(1000.0));
+        stats.record_reported_health(Some(CuHealth::Degraded));
+        assert_eq!(stats.health, CuHealth::Degraded);
+        stats.record_process(CuDuration(3_000_000).into(), &Err("boom".into()));
+        stats.record_reported_health(Some(CuHealth::Degraded));
+        assert_eq!(stats.health, CuHealth::Failed);
+        assert_eq!(stats.process_count, 3);
+        assert_eq!(stats.error_count, 1);
+        assert!(stats.last_error.as_ref().unwrap().starts_with("boom"));
+        stats.record_state(Some("Docking"));
+        assert_eq!(stats.state.as_deref(), Some("Docking"));
+        stats.record_state(None);
+        assert!(stats.state.is_none());
+    }
+
+    #[test]
+    fn test_lifecycle_stats() {
+        let mut stats = CuTaskStats::default();
+        let failed: CuResult<()> = Err("no device".into());
+        stats.record_lifecycle(CuTaskState::Start, CuDuration(10), CuDuration(15), &failed);
+        assert_eq!(stats.start_count, 0);
+        assert_eq!(stats.start_duration, Some(CuDuration(5)));
+        stats.record_lifecycle(CuTaskState::Start, CuDuration(20), CuDuration(40), &Ok(()));
+        assert_eq!(stats.start_count, 1);
+        assert_eq!(stats.last_start.unwrap(), CuDuration(20));
+        stats.record_lifecycle(
+            CuTaskState::Preprocess,
+            CuDuration(50),
+            CuDuration(51),
+            &Ok(()),
+        );
+        stats.record_lifecycle(CuTaskState::Stop, CuDuration(60), CuDuration(63), &Ok(()));
+        assert_eq!(stats.preprocess_duration, Some(CuDuration(1)));
+        assert_eq!(stats.postprocess_duration, None);
+        assert_eq!(stats.stop_duration, Some(CuDuration(3)));
+    }
+
+    #[test]
+    fn test_edge_stats() {
+        let mut edge = CuEdgeStats::new("imu", "fusion", Some(CuDuration(100)));
+        assert_eq!(edge.age(CuTime::from(50)), None);
+        assert_eq!(edge.health, CuHealth::Unknown);
+
+        // The source runs without producing anything: the age counts from its first call.
+        assert!(edge.record_output(CuTime::from(1000), false));
+        assert_eq!(edge.health, CuHealth::Nominal);
+        assert!(edge.record_output(CuTime::from(1200), false));
+        assert_eq!(edge.health, CuHealth::Degraded);
+
+        assert!(edge.record_output(CuTime::from(1300), true));
+        assert_eq!(edge.health, CuHealth::Nominal);
+        assert!(!edge.record_output(CuTime::from(1350), false));
+        assert_eq!(edge.age(CuTime::from(1380)), Some(CuDuration(80)));
+        assert!(edge.is_stale(CuTime::from(1401)));
+        assert!(edge.update_health(CuTime::from(1401)));
+        assert_eq!(edge.health, CuHealth::Degraded);
+
+        let mut unbounded = CuEdgeStats::new("imu", "logger", None);
+        unbounded.record_output(CuTime::from(0), true);
+        assert!(!unbounded.is_stale(CuTime::from(u64::MAX / 2)));
+    }
+
+    #[test]
+    fn test_edge_deadline() {
+        let mut edges = vec![
+            CuEdgeStats::new("imu", "fusion", None)
+                .with_deadline(Some(CuDuration(100)), DeadlinePolicy::Warn),
+            CuEdgeStats::new("gps", "fusion", None)
+                .with_deadline(Some(CuDuration(100)), DeadlinePolicy::Trip),
+        ];
+        record_edges_output(&mut edges, &[0, 1], CuTime::from(1000), true);
+        assert!(record_edges_delivery(&mut edges, &[0, 1], CuTime::from(1050)).is_ok());
+        record_edges_output(&mut edges, &[0, 1], CuTime::from(2000), true);
+        assert!(record_edges_delivery(&mut edges, &[0, 1], CuTime::from(2200)).is_err());
+        assert_eq!(edges[0].deadline_misses, 1);
+        assert_eq!(edges[1].deadline_misses, 1);
+
+        // Nothing produced, nothing delivered late.
+        record_edges_output(&mut edges, &[0, 1], CuTime::from(3000), false);
+        assert!(record_edges_delivery(&mut edges, &[0, 1], CuTime::from(3500)).is_ok());
+        assert_eq!(edges[1].deadline_misses, 1);
+    }
+
     #[test]
     fn test_duration_stats() {
         let mut stats = CuDurationStatistics::new(CuDuration(100));
@@ -0,0 +1,137 @@
+//! Runs every task at the rate of its base_period_ns within the loop of the application.
+//!
+//! ```ron
+//! tasks: [
+//!     (id: "imu", type: "a::Imu", base_period_ns: 1000000),
+//!     (id: "camera", type: "a::Camera", base_period_ns: 33333333),
+//!     (id: "fusion", type: "a::Fusion"),
+//! ],
+//! runtime: (period_ns: 1000000),
+//! ```
+//!
+//! The loop runs at the period of the runtime, the fastest rate, and a task with a base_period_ns is only processed
+//! in the iterations where its period elapsed. In the other ones, its last message is carried over in the copper
+//! list: the tasks downstream always receive the latest message of a slower task, recognizable by its time of
+//! validity. The tasks without a base_period_ns run on every iteration.
+//!
+//! The base_period_ns of a task can be changed while the application runs as a parameter of the task, see
+//! cu29::tuning, ie. to lower the rate of a camera when the robot heats up. Only the tasks with a base_period_ns in
+//! the config can change their rate.
+
+use crate::clock::{CuDuration, CuTime};
+use crate::config::CuConfig;
+
+/// When every task is due, by node id.
+pub struct CuRateScheduler {
+    periods: Vec<Option<CuDuration>>,
+    next_due: Vec<Option<CuTime>>,
+    /// How early an iteration can be and still process the tasks due, half the period of the loop.
+    tolerance: CuDuration,
+}
+
+impl CuRateScheduler {
+    pub fn new(periods: Vec<Option<CuDuration>>, tolerance: CuDuration) -> Self {
+        CuRateScheduler {
+            next_due: vec![None; periods.len()],
+            periods,
+            tolerance,
+        }
+    }
+
+    pub fn from_config(config: &CuConfig) -> Self {
+        let periods = config
+            .get_all_nodes()
+            .iter()
+            .map(|node| node.get_base_period_ns().map(CuDuration))
+            .collect();
+        let tolerance = config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.period_ns)
+            .map(|period| CuDuration(period / 2))
+            .unwrap_or_default();
+        Self::new(periods, tolerance)
+    }
+
+    /// Whether the task runs in the iteration starting at now, it is then due a period later. A task late by more
+    /// than a period restarts its schedule from now instead of running on every iteration to catch up.
+    pub fn is_due(&mut self, task: usize, now: CuTime) -> bool {
+        let Some(period) = self.periods[task] else {
+            return true;
+        };
+        match self.next_due[task] {
+            Some(next_due) if now + self.tolerance < next_due => false,
+            Some(next_due) if now < next_due + period => {
+                self.next_due[task] = Some(next_due + period);
+                true
+            }
+            _ => {
+                self.next_due[task] = Some(now + period);
+                true
+            }
+        }
+    }
+
+    /// Changes the period of the task, from its next due iteration.
+    pub fn set_period(&mut self, task: usize, period: Option<CuDuration>) {
+        if let (Some(next_due), Some(old), Some(new)) =
+            (self.next_due[task], self.periods[task], period)
+        {
+            self.next_due[task] = Some(next_due - old + new);
+        }
+        self.periods[task] = period;
+    }
+
+    /// Runs all the tasks on the next iteration, ie. when the tasks restart.
+    pub fn reset(&mut self) {
+        self.next_due
+            .iter_mut()
+            .for_each(|next_due| *next_due = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn test_rates() {
+        let mut rates = CuRateScheduler::new(
+            vec![None, Some(CuDuration(20 * MS)), Some(CuDuration(30 * MS))],
+            CuDuration(5 * MS),
+        );
+        let due: Vec<[bool; 3]> = (1..=7)
+            .map(|i| {
+                // A bit early, within the tolerance.
+                let now = CuDuration(i * 10 * MS - MS);
+                [0, 1, 2].map(|task| rates.is_due(task, now))
+            })
+            .collect();
+        assert_eq!(
+            due,
+            vec![
+                [true, true, true],
+                [true, false, false],
+                [true, true, false],
+                [true, false, true],
+                [true, true, false],
+                [true, false, false],
+                [true, true, true],
+            ]
+        );
+
+        // Late by more than a period.
+        assert!(rates.is_due(1, CuDuration(200 * MS)));
+        assert!(!rates.is_due(1, CuDuration(210 * MS)));
+        assert!(rates.is_due(1, CuDuration(220 * MS)));
+
+        rates.reset();
+        assert!(rates.is_due(2, CuDuration(221 * MS)));
+
+        // Derated to 50ms, due 50ms after its last run.
+        rates.set_period(2, Some(CuDuration(50 * MS)));
+        assert!(!rates.is_due(2, CuDuration(251 * MS)));
+        assert!(rates.is_due(2, CuDuration(271 * MS)));
+    }
+}
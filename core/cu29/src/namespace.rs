@@ -0,0 +1,55 @@
+//! The namespace of a robot in a fleet, so the logs and the live streams of several robots running the same
+//! application can be aggregated without their channels colliding.
+//!
+//! The robot id is robot_id in the runtime section of the config, or given at setup for an application deployed
+//! with the same config on every robot, see CuParamOverrides::robot_id. It is written in the header of the log and
+//! prefixes the channels of the latched messages and of the log readers: `r2/camera` for the task camera of r2.
+
+use crate::{CuError, CuResult};
+
+/// The longest robot id.
+pub const MAX_ROBOT_ID_LEN: usize = 63;
+
+/// Checks a robot id: ASCII letters, digits, '-' and '_', so it can be used as a prefix of a topic, a metadata
+/// value or a file name.
+pub fn check_robot_id(robot_id: &str) -> CuResult<()> {
+    if robot_id.is_empty() || robot_id.len() > MAX_ROBOT_ID_LEN {
+        return Err(CuError::from(format!(
+            "The robot id '{}' must have 1 to {} characters.",
+            robot_id, MAX_ROBOT_ID_LEN
+        )));
+    }
+    if let Some(c) = robot_id
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_')
+    {
+        return Err(CuError::from(format!(
+            "The robot id '{}' contains '{}', only ASCII letters, digits, '-' and '_' are allowed.",
+            robot_id, c
+        )));
+    }
+    Ok(())
+}
+
+/// The name of a channel in the namespace of the robot, the channel itself without robot id.
+pub fn namespaced(robot_id: Option<&str>, channel: &str) -> String {
+    match robot_id {
+        Some(robot_id) => format!("{}/{}", robot_id, channel),
+        None => channel.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace() {
+        assert!(check_robot_id("r2-d2_left").is_ok());
+        assert!(check_robot_id("").is_err());
+        assert!(check_robot_id("fleet/r2").is_err());
+        assert!(check_robot_id(&"r".repeat(64)).is_err());
+        assert_eq!(namespaced(Some("r2"), "camera"), "r2/camera");
+        assert_eq!(namespaced(None, "camera"), "camera");
+    }
+}
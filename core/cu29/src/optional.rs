@@ -0,0 +1,181 @@
+//! Tasks the application can run without, ie. the drivers of hardware that might not be plugged in.
+//!
+//! ```ron
+//! tasks: [
+//!     (id: "camera", type: "a::Camera", optional: true),
+//!     (id: "detector", type: "a::Detector", optional: true),
+//!     (id: "lidar", type: "a::Lidar"),
+//!     (id: "planner", type: "a::Planner"),
+//! ],
+//! ```
+//!
+//! When the creation or the start of an optional task fails, the runtime prunes it instead of failing: the task is
+//! dropped and never processed, its outputs stay empty. The optional tasks depending only on pruned tasks are pruned
+//! with it, here the detector with the camera, while the mandatory ones keep running on what is left. The health of
+//! the runtime is then Degraded, and the pruned tasks are reported as Failed with the reason in their statistics.
+//!
+//! The runtime holds an optional task in a CuOptional, the accessors of the application give the task itself.
+
+use crate::clock::RobotClock;
+use crate::config::ComponentConfig;
+use crate::context::CuContext;
+use crate::cutask::{CuSinkTask, CuSrcTask, CuTask, CuTaskLifecycle, Freezable};
+use crate::selftest::CuSelfTest;
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use cu29_traits::{CuError, CuResult};
+
+/// An optional task in the graph, see the module documentation.
+pub struct CuOptional<T> {
+    task: Option<T>,
+    new_error: Option<CuError>,
+}
+
+impl<T> CuOptional<T> {
+    /// The task, None if it was pruned.
+    pub fn task(&self) -> Option<&T> {
+        self.task.as_ref()
+    }
+
+    pub fn task_mut(&mut self) -> Option<&mut T> {
+        self.task.as_mut()
+    }
+
+    /// Why the task could not be created, if it could not.
+    pub fn new_error(&self) -> Option<&CuError> {
+        self.new_error.as_ref()
+    }
+
+    /// Drops the task, releasing what it holds.
+    pub fn prune(&mut self) {
+        self.task = None;
+    }
+
+    fn present(&mut self) -> CuResult<&mut T> {
+        self.task
+            .as_mut()
+            .ok_or_else(|| CuError::from("The optional task was pruned."))
+    }
+}
+
+impl<T: Freezable> Freezable for CuOptional<T> {
+    fn freeze<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        match &self.task {
+            Some(task) => task.freeze(encoder),
+            None => bincode::Encode::encode(&(), encoder),
+        }
+    }
+
+    fn thaw<D: Decoder>(&mut self, decoder: &mut D) -> Result<(), DecodeError> {
+        match &mut self.task {
+            Some(task) => task.thaw(decoder),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: CuTaskLifecycle> CuTaskLifecycle for CuOptional<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(match T::new(config) {
+            Ok(task) => CuOptional {
+                task: Some(task),
+                new_error: None,
+            },
+            Err(error) => CuOptional {
+                task: None,
+                new_error: Some(error),
+            },
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.present()?.start(clock)
+    }
+
+    fn preprocess(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.present()?.preprocess(clock)
+    }
+
+    fn postprocess(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.present()?.postprocess(clock)
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.present()?.stop(clock)
+    }
+
+    fn self_test(&mut self, clock: &RobotClock) -> CuResult<CuSelfTest> {
+        self.present()?.self_test(clock)
+    }
+
+    fn current_state(&self) -> Option<&str> {
+        self.task.as_ref()?.current_state()
+    }
+}
+
+impl<'cl, T: CuSrcTask<'cl>> CuSrcTask<'cl> for CuOptional<T> {
+    type Output = T::Output;
+
+    fn process(&mut self, ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+        self.present()?.process(ctx, new_msg)
+    }
+}
+
+impl<'cl, T: CuTask<'cl>> CuTask<'cl> for CuOptional<T> {
+    type Input = T::Input;
+    type Output = T::Output;
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        self.present()?.process(ctx, input, output)
+    }
+}
+
+impl<'cl, T: CuSinkTask<'cl>> CuSinkTask<'cl> for CuOptional<T> {
+    type Input = T::Input;
+
+    fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+        self.present()?.process(ctx, input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Camera;
+
+    impl Freezable for Camera {}
+
+    impl CuTaskLifecycle for Camera {
+        fn new(config: Option<&ComponentConfig>) -> CuResult<Self> {
+            match config {
+                Some(_) => Ok(Camera),
+                None => Err("no camera on /dev/video0".into()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_optional() {
+        let (clock, _) = RobotClock::mock();
+        let mut missing = CuOptional::<Camera>::new(None).unwrap();
+        assert!(missing.task().is_none());
+        assert!(missing.new_error().is_some());
+        assert!(missing.start(&clock).is_err());
+
+        let mut camera = CuOptional::<Camera>::new(Some(&ComponentConfig::new())).unwrap();
+        assert!(camera.new_error().is_none());
+        assert!(camera.start(&clock).is_ok());
+        camera.prune();
+        assert!(camera.task_mut().is_none());
+    }
+}
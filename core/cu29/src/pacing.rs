@@ -0,0 +1,485 @@
+//! Pacing of the copper loop at a fixed period with a choice of sleep strategies, and the statistics of its jitter.
+//!
+
+use crate::config::{OverrunPolicy, RuntimeConfig, SleepStrategy};
+use crate::monitoring::CuDurationStatistics;
+use cu29_clock::CuDuration;
+use cu29_log_derive::debug;
+use cu29_traits::CuResult;
+
+/// Lateness above this is recorded as this in the statistics.
+const MAX_RECORDED_LATENESS_NS: u64 = 1_000_000_000;
+
+/// How well the loop kept up with its period.
+#[derive(Debug, Clone)]
+pub struct LoopStats {
+    /// How late the iterations started after their deadline. The jitter of those is the jitter of the loop.
+    pub lateness: CuDurationStatistics,
+    /// Number of deadlines missed because an iteration took longer than the period.
+    pub overruns: u64,
+}
+
+impl Default for LoopStats {
+    fn default() -> Self {
+        LoopStats {
+            lateness: CuDurationStatistics::new(CuDuration(MAX_RECORDED_LATENESS_NS)),
+            overruns: 0,
+        }
+    }
+}
+
+/// What happened to the schedule of the loop when an iteration overran, following the OverrunPolicy.
+/// A controller integrating over time needs it to know the time step of the iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overrun {
+    /// The deadlines missed were skipped, this iteration covers them.
+    Skipped { missed: u64 },
+    /// The deadlines missed run back to back after this iteration, the ones above the cap were skipped.
+    CatchUp { replayed: u64, skipped: u64 },
+    /// This iteration replays a missed deadline, `remaining` more replays follow it.
+    Replay { remaining: u64 },
+    /// The schedule was shifted by the delay of this iteration.
+    Stretched { delay: CuDuration },
+}
+
+/// The time on the monotonic clock of the OS, the one the sleeps are based on.
+#[cfg(target_os = "linux")]
+fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Cannot fail with a valid pointer and CLOCK_MONOTONIC.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(not(target_os = "linux"))]
+fn monotonic_ns() -> u64 {
+    static ORIGIN: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    ORIGIN
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_nanos() as u64
+}
+
+#[cfg(target_os = "linux")]
+fn timespec(ns: u64) -> libc::timespec {
+    libc::timespec {
+        tv_sec: (ns / 1_000_000_000) as libc::time_t,
+        tv_nsec: (ns % 1_000_000_000) as libc::c_long,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn sleep_until(deadline: u64) {
+    let ts = timespec(deadline);
+    // Retry when a signal interrupts the sleep, the deadline is absolute.
+    while unsafe {
+        libc::clock_nanosleep(
+            libc::CLOCK_MONOTONIC,
+            libc::TIMER_ABSTIME,
+            &ts,
+            std::ptr::null_mut(),
+        )
+    } == libc::EINTR
+    {}
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sleep_until(deadline: u64) {
+    let now = monotonic_ns();
+    if deadline > now {
+        std::thread::sleep(std::time::Duration::from_nanos(deadline - now));
+    }
+}
+
+/// A periodic timerfd on the monotonic clock.
+#[cfg(target_os = "linux")]
+struct TimerFd(std::os::fd::OwnedFd);
+
+#[cfg(target_os = "linux")]
+impl TimerFd {
+    fn new() -> CuResult<Self> {
+        use std::os::fd::FromRawFd;
+        let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(cu29_traits::CuError::new_with_cause(
+                "Could not create the timerfd of the loop",
+                std::io::Error::last_os_error(),
+            ));
+        }
+        Ok(TimerFd(unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) }))
+    }
+
+    /// Expires first at the absolute time start, then every period.
+    fn arm(&self, start: u64, period: u64) {
+        use std::os::fd::AsRawFd;
+        let spec = libc::itimerspec {
+            it_interval: timespec(period),
+            it_value: timespec(start),
+        };
+        unsafe {
+            libc::timerfd_settime(
+                self.0.as_raw_fd(),
+                libc::TFD_TIMER_ABSTIME,
+                &spec,
+                std::ptr::null_mut(),
+            )
+        };
+    }
+
+    /// Blocks until the next expiration and returns the number of expirations since the last wait.
+    fn wait(&self) -> u64 {
+        use std::os::fd::AsRawFd;
+        let mut expirations = 0u64;
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.0.as_raw_fd(),
+                    &mut expirations as *mut u64 as *mut libc::c_void,
+                    size_of::<u64>(),
+                )
+            };
+            if read == size_of::<u64>() as isize {
+                return expirations;
+            }
+            if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                return 0;
+            }
+        }
+    }
+}
+
+/// Starts the iterations of the copper loop on a fixed period.
+/// The deadlines are absolute so the loop does not drift. When an iteration overruns, the deadlines missed
+/// are handled following the OverrunPolicy.
+pub struct LoopPacer {
+    period: u64,
+    strategy: SleepStrategy,
+    policy: OverrunPolicy,
+    #[cfg(target_os = "linux")]
+    timerfd: Option<TimerFd>,
+    /// Deadline of the next iteration, None before the first one.
+    next_deadline: Option<u64>,
+    /// Missed iterations still to run back to back.
+    replays: u64,
+    stats: LoopStats,
+    report_period: Option<u64>,
+    last_report: u64,
+}
+
+impl LoopPacer {
+    pub fn new(
+        period: CuDuration,
+        strategy: SleepStrategy,
+        policy: OverrunPolicy,
+        report_period: Option<CuDuration>,
+    ) -> CuResult<Self> {
+        if period.0 == 0 {
+            return Err("The period of the loop cannot be 0.".into());
+        }
+        #[cfg(target_os = "linux")]
+        let timerfd = match strategy {
+            SleepStrategy::TimerFd => Some(TimerFd::new()?),
+            _ => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        if strategy == SleepStrategy::TimerFd {
+            return Err("The TimerFd sleep strategy is only available on Linux.".into());
+        }
+        match policy {
+            OverrunPolicy::Skip => debug!(
+                "Loop paced every {}, skipping the iterations missed on overruns.",
+                period
+            ),
+            OverrunPolicy::CatchUp { max_iterations } => debug!(
+                "Loop paced every {}, catching up to {} iterations missed on overruns.",
+                period, max_iterations
+            ),
+            OverrunPolicy::Stretch => debug!(
+                "Loop paced every {}, stretching the schedule on overruns.",
+                period
+            ),
+        }
+        Ok(LoopPacer {
+            period: period.0,
+            strategy,
+            policy,
+            #[cfg(target_os = "linux")]
+            timerfd,
+            next_deadline: None,
+            replays: 0,
+            stats: LoopStats::default(),
+            report_period: report_period.map(|p| p.0),
+            last_report: 0,
+        })
+    }
+
+    /// The pacer described by the runtime section of the config, None if the loop is not paced.
+    pub fn from_config(config: &RuntimeConfig) -> CuResult<Option<Self>> {
+        config
+            .period_ns
+            .map(|period| {
+                LoopPacer::new(
+                    CuDuration(period),
+                    config.sleep_strategy,
+                    config.overrun_policy,
+                    config
+                        .report_period_s
+                        .map(|s| CuDuration(s * 1_000_000_000)),
+                )
+            })
+            .transpose()
+    }
+
+    /// Starts over: the next wait returns right away and the following ones are a period apart from it.
+    pub fn reset(&mut self) {
+        self.next_deadline = None;
+        self.replays = 0;
+        self.stats = LoopStats::default();
+    }
+
+    /// Waits for the start of the next iteration.
+    /// Returns what happened to the schedule if the previous iteration overran.
+    pub fn wait(&mut self) -> Option<Overrun> {
+        let Some(mut deadline) = self.next_deadline else {
+            let now = monotonic_ns();
+            self.arm_timer(now + self.period);
+            self.next_deadline = Some(now + self.period);
+            self.last_report = now;
+            return None;
+        };
+
+        if self.replays > 0 {
+            // Catching up: the deadline is already in the past, no wait.
+            self.replays -= 1;
+            self.next_deadline = Some(deadline + self.period);
+            return Some(Overrun::Replay {
+                remaining: self.replays,
+            });
+        }
+
+        let (now, missed) = match self.strategy {
+            #[cfg(target_os = "linux")]
+            SleepStrategy::TimerFd => {
+                let expirations = self.timerfd.as_ref().map_or(1, TimerFd::wait).max(1);
+                (monotonic_ns(), expirations - 1)
+            }
+            strategy => {
+                let now = monotonic_ns();
+                let missed = now.saturating_sub(deadline) / self.period;
+                if missed == 0 {
+                    if strategy == SleepStrategy::BusyWait {
+                        while monotonic_ns() < deadline {
+                            std::hint::spin_loop();
+                        }
+                    } else {
+                        sleep_until(deadline);
+                    }
+                }
+                (monotonic_ns(), missed)
+            }
+        };
+
+        let overrun = (missed > 0).then(|| self.handle_overrun(&mut deadline, now, missed));
+
+        let lateness = now.saturating_sub(deadline).min(MAX_RECORDED_LATENESS_NS);
+        self.stats.lateness.record(CuDuration(lateness));
+        self.next_deadline = Some(deadline + self.period);
+
+        if let Some(report_period) = self.report_period {
+            if now - self.last_report >= report_period {
+                self.report();
+                self.stats = LoopStats::default();
+                self.last_report = now;
+            }
+        }
+        overrun
+    }
+
+    /// Moves the deadline of this iteration following the overrun policy.
+    fn handle_overrun(&mut self, deadline: &mut u64, now: u64, missed: u64) -> Overrun {
+        self.stats.overruns += missed;
+        match self.policy {
+            OverrunPolicy::Skip => {
+                *deadline += missed * self.period;
+                debug!("Loop overrun: {} iterations skipped.", missed);
+                Overrun::Skipped { missed }
+            }
+            OverrunPolicy::CatchUp { max_iterations } => {
+                let replayed = missed.min(max_iterations as u64);
+                let skipped = missed - replayed;
+                // This iteration takes the oldest deadline kept, the replays the following ones.
+                *deadline += skipped * self.period;
+                self.replays = replayed;
+                debug!(
+                    "Loop overrun: {} iterations caught up, {} skipped.",
+                    replayed, skipped
+                );
+                Overrun::CatchUp { replayed, skipped }
+            }
+            OverrunPolicy::Stretch => {
+                let delay = CuDuration(now - *deadline);
+                *deadline = now;
+                // The timer keeps its own schedule, shift it too.
+                self.arm_timer(now + self.period);
+                debug!("Loop overrun: schedule stretched by {}.", delay);
+                Overrun::Stretched { delay }
+            }
+        }
+    }
+
+    /// Starts the timerfd, if the strategy uses one, with a first expiration at start.
+    fn arm_timer(&self, start: u64) {
+        #[cfg(target_os = "linux")]
+        if let Some(timerfd) = &self.timerfd {
+            timerfd.arm(start, self.period);
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = start;
+    }
+
+    /// The statistics since the start or the last report.
+    pub fn stats(&self) -> &LoopStats {
+        &self.stats
+    }
+
+    /// Logs the statistics of the loop.
+    pub fn report(&self) {
+        let lateness = &self.stats.lateness;
+        let iterations = lateness.len();
+        let mean = lateness.mean();
+        let p99 = lateness.percentile(0.99);
+        let max = lateness.max();
+        let jitter = lateness.jitter_max();
+        let overruns = self.stats.overruns;
+        debug!(
+            "Loop timing: {} iterations, lateness mean {} p99 {} max {}, jitter max {}, {} overruns.",
+            iterations, mean, p99, max, jitter, overruns
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loose bound on the time the tests are preempted for, the waits are checked against it so a loaded machine
+    /// does not fail them while a pacer waiting for the deadlines it missed still does.
+    const SLACK: u64 = 100_000_000;
+
+    fn run(strategy: SleepStrategy) {
+        let period = 2_000_000;
+        let mut pacer =
+            LoopPacer::new(CuDuration(period), strategy, OverrunPolicy::Skip, None).unwrap();
+        pacer.wait();
+        let start = monotonic_ns();
+        let first_deadline = pacer.next_deadline.unwrap();
+        for _ in 0..10 {
+            pacer.wait();
+        }
+        let elapsed = monotonic_ns() - start;
+        // Absolute deadlines: no drift whatever the time spent between the waits.
+        assert_eq!(pacer.next_deadline, Some(first_deadline + 10 * period));
+        assert!(elapsed >= 10 * period, "{:?}: {}ns", strategy, elapsed);
+        assert!(
+            elapsed < 20 * period + SLACK,
+            "{:?}: {}ns",
+            strategy,
+            elapsed
+        );
+        assert_eq!(pacer.stats().lateness.len(), 10);
+    }
+
+    #[test]
+    fn test_strategies() {
+        run(SleepStrategy::BusyWait);
+        run(SleepStrategy::ClockNanosleep);
+        #[cfg(target_os = "linux")]
+        run(SleepStrategy::TimerFd);
+    }
+
+    #[test]
+    fn test_overrun_skips_the_missed_deadlines() {
+        let period = 10_000_000;
+        let mut pacer = pacer(period, OverrunPolicy::Skip);
+        pacer.wait();
+        let first_deadline = pacer.next_deadline.unwrap();
+        std::thread::sleep(std::time::Duration::from_nanos(3 * period + period / 2));
+        let before = monotonic_ns();
+        // The deadlines 1 and 2 periods after the start are skipped, the third one runs late.
+        assert_eq!(pacer.wait(), Some(Overrun::Skipped { missed: 2 }));
+        assert_eq!(pacer.stats().overruns, 2);
+        assert_eq!(pacer.next_deadline, Some(first_deadline + 3 * period));
+        // The next deadline is less than a period away, not in the past.
+        assert_eq!(pacer.wait(), None);
+        let elapsed = monotonic_ns() - before;
+        assert!(elapsed < period + SLACK, "{}ns", elapsed);
+    }
+
+    fn pacer(period: u64, policy: OverrunPolicy) -> LoopPacer {
+        LoopPacer::new(
+            CuDuration(period),
+            SleepStrategy::ClockNanosleep,
+            policy,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_overrun_catch_up() {
+        let period = 10_000_000;
+        let mut pacer = pacer(period, OverrunPolicy::CatchUp { max_iterations: 1 });
+        pacer.wait();
+        let first_deadline = pacer.next_deadline.unwrap();
+        std::thread::sleep(std::time::Duration::from_nanos(3 * period + period / 2));
+        let before = monotonic_ns();
+        assert_eq!(
+            pacer.wait(),
+            Some(Overrun::CatchUp {
+                replayed: 1,
+                skipped: 1
+            })
+        );
+        // The replay runs right away, then the loop is back on its schedule.
+        assert_eq!(pacer.wait(), Some(Overrun::Replay { remaining: 0 }));
+        let elapsed = monotonic_ns() - before;
+        assert!(elapsed < SLACK, "{}ns", elapsed);
+        assert_eq!(pacer.next_deadline, Some(first_deadline + 3 * period));
+        assert_eq!(pacer.wait(), None);
+        let elapsed = monotonic_ns() - before;
+        assert!(elapsed < period + SLACK, "{}ns", elapsed);
+    }
+
+    #[test]
+    fn test_overrun_stretch() {
+        let period = 10_000_000;
+        let mut pacer = pacer(period, OverrunPolicy::Stretch);
+        pacer.wait();
+        std::thread::sleep(std::time::Duration::from_nanos(3 * period + period / 2));
+        let Some(Overrun::Stretched { delay }) = pacer.wait() else {
+            panic!("The schedule was not stretched");
+        };
+        assert!(delay.0 >= 2 * period + period / 2);
+        // The next iteration is a full period after the late one.
+        let before = monotonic_ns();
+        assert_eq!(pacer.wait(), None);
+        let elapsed = monotonic_ns() - before;
+        assert!(elapsed > period / 2, "{}ns", elapsed);
+        assert!(elapsed < period + SLACK, "{}ns", elapsed);
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert!(LoopPacer::from_config(&RuntimeConfig::default())
+            .unwrap()
+            .is_none());
+        let config = RuntimeConfig {
+            period_ns: Some(0),
+            ..Default::default()
+        };
+        assert!(LoopPacer::from_config(&config).is_err());
+    }
+}
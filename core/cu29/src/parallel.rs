@@ -0,0 +1,262 @@
+//! Runs the independent branches of the task graph on worker threads.
+//!
+//! With `parallel_workers` in the runtime section of the config, the plan is cut in stages: a stage holds the tasks
+//! whose inputs are all produced by the previous stages, so the tasks of a stage do not depend on each other and run
+//! at the same time, ie. a CPU heavy detector next to the driver of an actuator. The runtime joins the workers at
+//! the end of every stage, before the tasks consuming their outputs.
+//!
+//! ```ron
+//! runtime: (period_ns: 10000000, parallel_workers: 2),
+//! ```
+//!
+//! The tasks of a stage hold their messages only, the rest of the runtime is updated on its own thread: the
+//! faults are drawn before the stage, so a seed injects the same faults as in the sequential mode, and the errors
+//! of the tasks go to the monitor after the join, in the order of the plan. An Abort decision skips the next
+//! stages, the other tasks of the stage already ran. The replay stays sequential.
+//!
+//! The stages are level-synchronous: a task waits for the whole previous stage, not only for the tasks producing
+//! its inputs. With unbalanced branches, ie. a slow detector next to a chain of fast control tasks, the next task
+//! of the control chain waits for the detector at the barrier, so the cycle takes the sum over the stages of their
+//! slowest task. Put the slow tasks at the end of their branch, or run them asynchronously (see cu29::asynctask).
+//!
+//! The tasks need to be Send and their messages Sync, which is checked when the application is compiled with
+//! parallel_workers.
+//!
+//! A task with a priority or a core affinity gets a thread of its own, named `cu:task:<id>`, scheduled once when
+//! the runtime starts (see cu29::threads::CuThreadScheduling): the workers keep the default scheduling.
+
+use crate::curuntime::{CuExecutionLoop, CuExecutionUnit};
+use crate::threads::{task_thread_name, CuThreadScheduling, THREAD_PREFIX};
+use crate::{CuError, CuResult};
+use rayon::{Scope, ThreadPool, ThreadPoolBuilder};
+use std::collections::HashMap;
+
+/// Cuts the plan in stages of steps independent from each other, as indices in plan.steps.
+/// The stages are in the order they need to run, their steps in the order of the plan.
+pub fn compute_parallel_stages(plan: &CuExecutionLoop) -> Vec<Vec<usize>> {
+    // The stage of the step producing every message of the copper list.
+    let mut produced_in: HashMap<u32, usize> = HashMap::new();
+    let mut stages: Vec<Vec<usize>> = Vec::new();
+    for (index, unit) in plan.steps.iter().enumerate() {
+        let CuExecutionUnit::Step(step) = unit else {
+            continue;
+        };
+        let stage = step
+            .input_msg_indices_types
+            .iter()
+            .filter_map(|(input, _)| produced_in.get(input))
+            .map(|producer_stage| producer_stage + 1)
+            .max()
+            .unwrap_or(0);
+        if let Some((output, _)) = &step.output_msg_index_type {
+            produced_in.insert(*output, stage);
+        }
+        if stages.len() <= stage {
+            stages.resize(stage + 1, Vec::new());
+        }
+        stages[stage].push(index);
+    }
+    stages
+}
+
+/// The worker threads running the stages, named `cu:worker:<n>`.
+pub struct CuWorkers {
+    pool: ThreadPool,
+    /// The threads of the tasks with a scheduling, indexed by node id.
+    dedicated: Vec<Option<ThreadPool>>,
+}
+
+impl CuWorkers {
+    pub fn new(workers: usize) -> CuResult<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|n| format!("{}worker:{}", THREAD_PREFIX, n))
+            .build()
+            .map_err(|e| CuError::new_with_cause("Could not start the worker threads", e))?;
+        Ok(CuWorkers {
+            pool,
+            dedicated: Vec::new(),
+        })
+    }
+
+    /// Starts the thread of the task, with the scheduling applied for good.
+    pub fn dedicate(
+        &mut self,
+        node_id: usize,
+        task: &str,
+        scheduling: &CuThreadScheduling,
+    ) -> CuResult<()> {
+        let name = task_thread_name(task);
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(1)
+            .thread_name(move |_| name.clone())
+            .build()
+            .map_err(|e| {
+                CuError::new_with_cause(
+                    &format!("Could not start the thread of the task {}", task),
+                    e,
+                )
+            })?;
+        pool.install(|| scheduling.apply())?;
+        if self.dedicated.len() <= node_id {
+            self.dedicated.resize_with(node_id + 1, || None);
+        }
+        self.dedicated[node_id] = Some(pool);
+        Ok(())
+    }
+
+    pub fn workers(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Runs op on the current thread, the tasks it spawns on the scope run on the workers. It returns when all of
+    /// them completed.
+    pub fn in_place_scope<'scope, OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce(&Scope<'scope>) -> R,
+    {
+        self.pool.in_place_scope(op)
+    }
+
+    /// Runs op on the thread of the task if it has one, on the current thread otherwise.
+    pub fn run_on<OP, R>(&self, node_id: usize, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match self.dedicated.get(node_id) {
+            Some(Some(pool)) => pool.install(op),
+            _ => op(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_configuration_str;
+    use crate::curuntime::compute_runtime_plan;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // The stages of the config as the sorted ids of their tasks.
+    fn stage_ids(config: &str) -> Vec<Vec<String>> {
+        let config = read_configuration_str(config).unwrap();
+        let plan = compute_runtime_plan(&config).unwrap();
+        compute_parallel_stages(&plan)
+            .iter()
+            .map(|stage| {
+                let mut ids: Vec<String> = stage
+                    .iter()
+                    .map(|&index| match &plan.steps[index] {
+                        CuExecutionUnit::Step(step) => step.node.get_id(),
+                        CuExecutionUnit::Loop(_) => unreachable!(),
+                    })
+                    .collect();
+                ids.sort();
+                ids
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_stages() {
+        let stages = stage_ids(
+            r#"(
+                tasks: [
+                    (id: "camera", type: "a"),
+                    (id: "detector", type: "b"),
+                    (id: "imu", type: "a"),
+                    (id: "fusion", type: "c"),
+                    (id: "motors", type: "d"),
+                    (id: "leds", type: "d"),
+                ],
+                cnx: [
+                    (src: "camera", dst: "detector", msg: "u32"),
+                    (src: "imu", dst: "fusion", msg: "u32"),
+                    (src: "detector", dst: "fusion", msg: "u32"),
+                    (src: "fusion", dst: "motors", msg: "u32"),
+                    (src: "imu", dst: "leds", msg: "u32"),
+                ],
+            )"#,
+        );
+        assert_eq!(
+            stages,
+            vec![
+                vec!["camera", "imu"],
+                vec!["detector", "leds"],
+                vec!["fusion"],
+                vec!["motors"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parallel_stages_unbalanced_branches() {
+        // A slow detector branch next to a control chain: the stages are cut by depth, so the pid waits for the
+        // detector at the end of the first stage and the motors for the tracker at the end of the second one,
+        // even if the control chain does not depend on them.
+        let stages = stage_ids(
+            r#"(
+                tasks: [
+                    (id: "camera", type: "a"),
+                    (id: "detector", type: "b"),
+                    (id: "tracker", type: "b"),
+                    (id: "encoder", type: "a"),
+                    (id: "pid", type: "c"),
+                    (id: "motors", type: "d"),
+                ],
+                cnx: [
+                    (src: "camera", dst: "detector", msg: "u32"),
+                    (src: "detector", dst: "tracker", msg: "u32"),
+                    (src: "encoder", dst: "pid", msg: "u32"),
+                    (src: "pid", dst: "motors", msg: "u32"),
+                ],
+            )"#,
+        );
+        assert_eq!(
+            stages,
+            vec![
+                vec!["camera", "encoder"],
+                vec!["detector", "pid"],
+                vec!["motors", "tracker"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_workers() {
+        let workers = CuWorkers::new(2).unwrap();
+        assert_eq!(workers.workers(), 2);
+        let ran = AtomicUsize::new(0);
+        workers.in_place_scope(|scope| {
+            scope.spawn(|_| {
+                assert!(std::thread::current()
+                    .name()
+                    .unwrap()
+                    .starts_with("cu:worker:"));
+                ran.fetch_add(1, Ordering::Relaxed);
+            });
+            ran.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(ran.load(Ordering::Relaxed), 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_dedicated_thread() {
+        let mut workers = CuWorkers::new(1).unwrap();
+        let scheduling = CuThreadScheduling {
+            priority: None,
+            core_affinity: vec![0],
+        };
+        workers.dedicate(1, "pid", &scheduling).unwrap();
+        let thread = |node_id| {
+            workers.run_on(node_id, || {
+                std::thread::current().name().map(str::to_string)
+            })
+        };
+        assert_eq!(thread(1).as_deref(), Some("cu:task:pid"));
+        assert_eq!(thread(0), std::thread::current().name().map(str::to_string));
+        assert_eq!(thread(5), std::thread::current().name().map(str::to_string));
+    }
+}
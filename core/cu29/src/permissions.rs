@@ -0,0 +1,225 @@
+//! Guardrails for the APIs mutating a running application (pausing a component, changing a parameter,
+//! executing a remote command...).
+//!
+//! The mutations of a task are marked with the role they need in the `permissions` of its node in the config,
+//! the unmarked ones need the factory role:
+//!
+//! ```ron
+//! (id: "uploader", type: "cu_uploader::CuUploader", permissions: {"pause": operator, "bandwidth_limit": engineer})
+//! ```
+//!
+//! The embedding application authenticates its users its own way and gives their role to the mutating calls
+//! as a [CuRoleToken]. Every accepted or rejected mutation is logged.
+//!
+//! ```
+//! use cu29::permissions::{CuGuard, CuRole, CuRoleToken};
+//!
+//! let guard = CuGuard::new("uploader").with_level("pause", CuRole::Operator);
+//! let operator = CuRoleToken::new(CuRole::Operator, "alice");
+//! assert!(guard.authorize(Some(&operator), "pause").is_ok());
+//! assert!(guard.authorize(Some(&operator), "bandwidth_limit").is_err());
+//! assert!(guard.authorize(None, "pause").is_err());
+//! ```
+
+use crate::config::ComponentConfig;
+use crate::{CuError, CuResult};
+use cu29_log_derive::debug;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Prefix of the keys under which the runtime gives the permissions of a node to its task config.
+pub const PERMISSION_KEY_PREFIX: &str = "permission.";
+
+/// The roles allowed to mutate a running application, each one can do what the previous ones can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CuRole {
+    /// Runs the robot day to day.
+    Operator,
+    /// Tunes the robot.
+    Engineer,
+    /// Everything, including what could damage the robot.
+    Factory,
+}
+
+impl CuRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CuRole::Operator => "operator",
+            CuRole::Engineer => "engineer",
+            CuRole::Factory => "factory",
+        }
+    }
+
+    pub fn parse(role: &str) -> CuResult<Self> {
+        match role {
+            "operator" => Ok(CuRole::Operator),
+            "engineer" => Ok(CuRole::Engineer),
+            "factory" => Ok(CuRole::Factory),
+            _ => Err(format!(
+                "Unknown role '{}', it must be operator, engineer or factory.",
+                role
+            )
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for CuRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The role of whoever asks for a mutation, given by the embedding application once it has authenticated them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuRoleToken {
+    role: CuRole,
+    holder: String,
+}
+
+impl CuRoleToken {
+    /// holder identifies who asks for the mutations in the logs.
+    pub fn new(role: CuRole, holder: &str) -> Self {
+        CuRoleToken {
+            role,
+            holder: holder.to_string(),
+        }
+    }
+
+    pub fn role(&self) -> CuRole {
+        self.role
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+}
+
+/// Checks the mutations of a component against the roles they need.
+#[derive(Debug, Clone)]
+pub struct CuGuard {
+    component: String,
+    levels: HashMap<String, CuRole>,
+}
+
+impl CuGuard {
+    /// A guard where every mutation needs the factory role.
+    /// component identifies the mutated component in the logs.
+    pub fn new(component: &str) -> Self {
+        CuGuard {
+            component: component.to_string(),
+            levels: HashMap::new(),
+        }
+    }
+
+    pub fn with_level(mut self, mutation: &str, role: CuRole) -> Self {
+        self.levels.insert(mutation.to_string(), role);
+        self
+    }
+
+    /// The guard of a task from the permissions the runtime put in its config.
+    pub fn from_config(component: &str, config: Option<&ComponentConfig>) -> CuResult<Self> {
+        let mut guard = CuGuard::new(component);
+        for (key, value) in config.iter().flat_map(|config| config.0.iter()) {
+            if let Some(mutation) = key.strip_prefix(PERMISSION_KEY_PREFIX) {
+                let role = value.as_str().ok_or_else(|| {
+                    CuError::from(format!(
+                        "The permission '{}' of '{}' must be a role name, not {}.",
+                        key, component, value
+                    ))
+                })?;
+                guard
+                    .levels
+                    .insert(mutation.to_string(), CuRole::parse(role)?);
+            }
+        }
+        Ok(guard)
+    }
+
+    /// The role needed for a mutation.
+    pub fn required_role(&self, mutation: &str) -> CuRole {
+        self.levels
+            .get(mutation)
+            .copied()
+            .unwrap_or(CuRole::Factory)
+    }
+
+    /// Errors out if the token does not allow the mutation, logging the decision either way.
+    pub fn authorize(&self, token: Option<&CuRoleToken>, mutation: &str) -> CuResult<()> {
+        let required = self.required_role(mutation);
+        let Some(token) = token else {
+            debug!(
+                "Permissions: {}.{} rejected, it needs the {} role and no role was given.",
+                &self.component,
+                mutation,
+                required.as_str()
+            );
+            return Err(CuError::from(format!(
+                "{}.{} needs the {} role and no role was given.",
+                self.component, mutation, required
+            )));
+        };
+        if token.role < required {
+            debug!(
+                "Permissions: {}.{} rejected for {} as {}, it needs the {} role.",
+                &self.component,
+                mutation,
+                token.holder(),
+                token.role.as_str(),
+                required.as_str()
+            );
+            return Err(CuError::from(format!(
+                "{}.{} needs the {} role, {} is {}.",
+                self.component, mutation, required, token.holder, token.role
+            )));
+        }
+        debug!(
+            "Permissions: {}.{} accepted for {} as {}.",
+            &self.component,
+            mutation,
+            token.holder(),
+            token.role.as_str()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_from_config() {
+        let mut config = ComponentConfig::new();
+        config.set("endpoint", "http://localhost".to_string());
+        config.set("permission.pause", "operator".to_string());
+        config.set("permission.max_speed", "engineer".to_string());
+        let guard = CuGuard::from_config("uploader", Some(&config)).unwrap();
+        assert_eq!(guard.required_role("pause"), CuRole::Operator);
+        assert_eq!(guard.required_role("max_speed"), CuRole::Engineer);
+        assert_eq!(guard.required_role("endpoint"), CuRole::Factory);
+
+        let operator = CuRoleToken::new(CuRole::Operator, "alice");
+        let engineer = CuRoleToken::new(CuRole::Engineer, "bob");
+        let factory = CuRoleToken::new(CuRole::Factory, "line 3");
+        assert!(guard.authorize(Some(&operator), "pause").is_ok());
+        assert!(guard.authorize(Some(&operator), "max_speed").is_err());
+        assert!(guard.authorize(Some(&engineer), "max_speed").is_ok());
+        assert!(guard.authorize(Some(&engineer), "reflash").is_err());
+        assert!(guard.authorize(Some(&factory), "reflash").is_ok());
+
+        config.set("permission.reflash", "admin".to_string());
+        assert!(CuGuard::from_config("uploader", Some(&config)).is_err());
+        config.set("permission.reflash", 3u32);
+        let error = CuGuard::from_config("uploader", Some(&config)).unwrap_err();
+        assert!(error.to_string().contains("permission.reflash"));
+        assert_eq!(
+            CuGuard::from_config("uploader", None)
+                .unwrap()
+                .required_role("pause"),
+            CuRole::Factory
+        );
+    }
+}
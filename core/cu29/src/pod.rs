@@ -0,0 +1,152 @@
+//! A fast path to encode and decode large arrays of plain old data (point clouds, images, audio...).
+//! bincode encodes a slice element by element, which dominates the time of an iteration for large payloads.
+//! When the bytes bincode would produce for an element are its bytes in memory (fixed int encoding or a float,
+//! in the endianness of the machine), the whole slice is copied at once instead.
+//!
+//! The encoding is the same as the one of a `Vec<T>`: the fast path can read what the element by element
+//! encoding wrote and the other way around.
+
+use bincode::config::Config;
+use bincode::de::read::Reader;
+use bincode::de::Decoder;
+use bincode::enc::write::Writer;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use std::mem::{size_of, size_of_val};
+
+/// A type that can be copied as its bytes in memory.
+///
+/// # Safety
+/// The type must have no padding and every bit pattern must be a valid value, like the numeric primitives
+/// and the arrays of them. A `#[repr(C)]` struct of fields of the same primitive type qualifies too.
+pub unsafe trait CuPod: Copy + Encode + Decode + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl CuPod for $t {})*
+    };
+}
+
+impl_pod!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+unsafe impl<T: CuPod, const N: usize> CuPod for [T; N] {}
+
+/// The largest element checked by [is_raw_encoding], bigger elements are always encoded one by one.
+const MAX_PROBE_SIZE: usize = 64;
+
+/// True if bincode encodes T with this config as its bytes in memory, which makes copying the slice
+/// equivalent to encoding it element by element.
+pub fn is_raw_encoding<T: CuPod, C: Config>(config: C) -> bool {
+    let size = size_of::<T>();
+    if size > MAX_PROBE_SIZE {
+        return false;
+    }
+    // Distinct bytes so an endianness or a varint difference shows.
+    let mut pattern = [0u8; MAX_PROBE_SIZE];
+    for (i, byte) in pattern.iter_mut().enumerate() {
+        *byte = 0x81 + i as u8;
+    }
+    // Safety: T is CuPod so any bit pattern is a valid value, the pattern is long enough.
+    let probe: T = unsafe { std::ptr::read_unaligned(pattern.as_ptr() as *const T) };
+    let mut encoded = [0u8; MAX_PROBE_SIZE + 16];
+    match bincode::encode_into_slice(probe, &mut encoded, config) {
+        Ok(len) => encoded[..len] == pattern[..size],
+        Err(_) => false,
+    }
+}
+
+fn as_bytes<T: CuPod>(slice: &[T]) -> &[u8] {
+    // Safety: T is CuPod, it has no padding.
+    unsafe { std::slice::from_raw_parts(slice.as_ptr() as *const u8, size_of_val(slice)) }
+}
+
+fn as_bytes_mut<T: CuPod>(slice: &mut [T]) -> &mut [u8] {
+    // Safety: T is CuPod, any bytes written are a valid value.
+    unsafe { std::slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut u8, size_of_val(slice)) }
+}
+
+/// Encodes the slice like bincode encodes a `[T]`, copying it at once when possible.
+pub fn encode_pod_slice<T: CuPod, E: Encoder>(
+    slice: &[T],
+    encoder: &mut E,
+) -> Result<(), EncodeError> {
+    (slice.len() as u64).encode(encoder)?;
+    if is_raw_encoding::<T, _>(*encoder.config()) {
+        return encoder.writer().write(as_bytes(slice));
+    }
+    for item in slice {
+        item.encode(encoder)?;
+    }
+    Ok(())
+}
+
+/// Decodes what bincode encodes for a `Vec<T>`, reading it at once when possible.
+pub fn decode_pod_vec<T: CuPod, D: Decoder>(decoder: &mut D) -> Result<Vec<T>, DecodeError> {
+    let len = u64::decode(decoder)?;
+    let len: usize = len
+        .try_into()
+        .map_err(|_| DecodeError::OutsideUsizeRange(len))?;
+    decoder.claim_container_read::<T>(len)?;
+    if is_raw_encoding::<T, _>(*decoder.config()) {
+        // Safety: T is CuPod, all zeroes is a valid value. The buffer is allocated as a Vec<T> so it is aligned.
+        let mut vec = vec![unsafe { std::mem::zeroed::<T>() }; len];
+        decoder.reader().read(as_bytes_mut(&mut vec))?;
+        return Ok(vec);
+    }
+    let mut vec = Vec::with_capacity(len);
+    for _ in 0..len {
+        // The container was claimed as a whole.
+        decoder.unclaim_bytes_read(size_of::<T>());
+        vec.push(T::decode(decoder)?);
+    }
+    Ok(vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::config::standard;
+
+    #[test]
+    fn test_raw_encoding() {
+        assert!(is_raw_encoding::<u8, _>(standard()));
+        assert!(is_raw_encoding::<[f32; 3], _>(standard()));
+        // Variable int encoding.
+        assert!(!is_raw_encoding::<u32, _>(standard()));
+        assert!(is_raw_encoding::<u32, _>(
+            standard().with_fixed_int_encoding()
+        ));
+        #[cfg(target_endian = "little")]
+        assert!(!is_raw_encoding::<f64, _>(standard().with_big_endian()));
+    }
+
+    fn roundtrip<T: CuPod + PartialEq + std::fmt::Debug, C: Config>(values: Vec<T>, config: C) {
+        struct Wrapper<T>(Vec<T>);
+        impl<T: CuPod> Encode for Wrapper<T> {
+            fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+                encode_pod_slice(&self.0, encoder)
+            }
+        }
+        impl<T: CuPod> Decode for Wrapper<T> {
+            fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+                Ok(Wrapper(decode_pod_vec(decoder)?))
+            }
+        }
+        let fast = bincode::encode_to_vec(Wrapper(values.clone()), config).unwrap();
+        let slow = bincode::encode_to_vec(&values, config).unwrap();
+        assert_eq!(fast, slow);
+        let (decoded, _): (Wrapper<T>, usize) = bincode::decode_from_slice(&slow, config).unwrap();
+        assert_eq!(decoded.0, values);
+    }
+
+    #[test]
+    fn test_same_as_bincode() {
+        roundtrip(vec![1.5f32, -2.0, f32::MAX, 0.0], standard());
+        roundtrip(vec![[1.0f64, 2.0, 3.0], [4.0, 5.0, 6.0]], standard());
+        roundtrip(vec![300u32, 7, u32::MAX], standard());
+        roundtrip(vec![-3i16, 1000], standard().with_fixed_int_encoding());
+        roundtrip(vec![1.0f32, 2.0], standard().with_big_endian());
+        roundtrip(Vec::<f32>::new(), standard());
+    }
+}
@@ -0,0 +1,438 @@
+//! Pools of preallocated buffers for the large payloads (grids, images, point clouds...).
+//! The buffers are allocated once when the pool is created: a task acquires a [CuHandle] from its pool,
+//! fills it and sets it as its payload. The buffer goes back to the pool when the last handle
+//! pointing to it is dropped, so nothing is allocated in the critical path.
+//! The buffers in the memory of a GPU have their own pools, see [crate::device].
+//!
+//! ```
+//! use cu29::pool::CuPool;
+//!
+//! let pool = CuPool::<u8>::new("frames", 2, 640 * 480);
+//! let mut frame = pool.acquire().unwrap();
+//! frame.get_mut().unwrap()[0] = 42;
+//! assert_eq!(pool.stats().available, 1);
+//! drop(frame);
+//! assert_eq!(pool.stats().available, 2);
+//! ```
+
+use crate::config::NodeId;
+use crate::memory::{charge_handle, release_handle};
+use crate::pod::{decode_pod_vec, encode_pod_slice};
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use cu29_traits::lockaudit::{self, LockSite};
+use cu29_traits::{CuError, CuResult};
+use cu29_unifiedlog::StagingStats;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+/// A snapshot of the usage of a pool.
+#[derive(Debug, Clone, PartialEq, serde_derive::Serialize)]
+pub struct CuPoolStats {
+    pub id: String,
+    /// Number of buffers of the pool.
+    pub capacity: usize,
+    /// Number of buffers ready to be acquired.
+    pub available: usize,
+    /// Number of elements of each buffer.
+    pub buffer_len: usize,
+    /// Number of times a buffer was requested while none was available.
+    pub exhausted: u64,
+    /// How full the buffer being filled is, from 0 to 1, for the buffers filled progressively like the
+    /// staging buffers of the logger.
+    pub fill: Option<f32>,
+}
+
+pub(crate) trait PoolStatsSource: Send + Sync {
+    fn stats(&self) -> CuPoolStats;
+}
+
+/// The staging buffers of a log stream are shown as a pool of 2 buffers, one is available while the other
+/// one is not written to the log yet.
+impl PoolStatsSource for StagingStats {
+    fn stats(&self) -> CuPoolStats {
+        let snapshot = self.snapshot();
+        CuPoolStats {
+            id: snapshot.id,
+            capacity: 2,
+            available: if snapshot.in_flight { 0 } else { 1 },
+            buffer_len: snapshot.capacity,
+            exhausted: snapshot.stalls,
+            fill: Some(snapshot.used as f32 / snapshot.capacity.max(1) as f32),
+        }
+    }
+}
+
+struct PoolInner<T> {
+    id: String,
+    capacity: usize,
+    buffer_len: usize,
+    free: Mutex<Vec<Vec<T>>>,
+    exhausted: AtomicU64,
+}
+
+impl<T: Send> PoolStatsSource for PoolInner<T> {
+    fn stats(&self) -> CuPoolStats {
+        CuPoolStats {
+            id: self.id.clone(),
+            capacity: self.capacity,
+            available: lockaudit::lock(&FREE_LOCK, &self.free).unwrap().len(),
+            buffer_len: self.buffer_len,
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+            fill: None,
+        }
+    }
+}
+
+/// The locks of the free buffers of all the pools, taken by the tasks and by the logger releasing the buffers.
+pub(crate) static FREE_LOCK: LockSite = LockSite::new("pool_free_buffers");
+static REGISTRY_LOCK: LockSite = LockSite::new("pools_registry");
+static POOLS_LOCK: LockSite = LockSite::new("task_pools");
+
+fn registry() -> &'static Mutex<Vec<Weak<dyn PoolStatsSource>>> {
+    static REGISTRY: OnceLock<Mutex<Vec<Weak<dyn PoolStatsSource>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Shows the source in the statistics of the pools as long as it is alive.
+pub(crate) fn register_stats_source(source: Arc<dyn PoolStatsSource>) {
+    lockaudit::lock(&REGISTRY_LOCK, registry())
+        .unwrap()
+        .push(Arc::downgrade(&source));
+}
+
+/// The statistics of all the pools still alive in the process, for monitoring.
+pub fn pools_stats() -> Vec<CuPoolStats> {
+    let mut registry = lockaudit::lock(&REGISTRY_LOCK, registry()).unwrap();
+    registry.retain(|pool| pool.strong_count() > 0);
+    registry
+        .iter()
+        .filter_map(|pool| pool.upgrade())
+        .map(|pool| pool.stats())
+        .collect()
+}
+
+/// A fixed number of buffers of a fixed length, all preallocated at creation.
+/// Cloning the pool gives another reference to the same buffers.
+pub struct CuPool<T> {
+    inner: Arc<PoolInner<T>>,
+}
+
+impl<T> Clone for CuPool<T> {
+    fn clone(&self) -> Self {
+        CuPool {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Default + Send + 'static> CuPool<T> {
+    /// id is used to identify the pool in the statistics.
+    pub fn new(id: &str, capacity: usize, buffer_len: usize) -> Self {
+        let free = (0..capacity)
+            .map(|_| vec![T::default(); buffer_len])
+            .collect();
+        let inner = Arc::new(PoolInner {
+            id: id.to_string(),
+            capacity,
+            buffer_len,
+            free: Mutex::new(free),
+            exhausted: AtomicU64::new(0),
+        });
+        register_stats_source(inner.clone());
+        CuPool { inner }
+    }
+
+    /// Takes a buffer from the pool, None if they are all in use.
+    /// The buffer keeps the content it had when it was released.
+    pub fn acquire(&self) -> Option<CuHandle<T>> {
+        let buffer = lockaudit::lock(&FREE_LOCK, &self.inner.free).unwrap().pop();
+        match buffer {
+            Some(buffer) => Some(CuHandle(Arc::new(HandleInner {
+                buffer,
+                pool: Some(Arc::downgrade(&self.inner)),
+                task: charge_handle(),
+            }))),
+            None => {
+                self.inner.exhausted.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CuPoolStats {
+        self.inner.stats()
+    }
+}
+
+/// A pool of any element type, with the node and the name it was created for.
+type NodePool = (NodeId, String, Box<dyn Any + Send>);
+
+/// The pools the runtime allocates on behalf of the tasks, see [crate::context::CuContext::pool].
+/// A pool is created on the first request and the same one is given back afterwards.
+#[derive(Default)]
+pub struct CuPools {
+    pools: Mutex<Vec<NodePool>>,
+}
+
+impl CuPools {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pool `name` of the node, created with `capacity` buffers of `buffer_len` elements if it does not exist.
+    /// `label` identifies the pool in the statistics as `label.name`.
+    pub fn get_or_create<T: Clone + Default + Send + 'static>(
+        &self,
+        node_id: NodeId,
+        label: &str,
+        name: &str,
+        capacity: usize,
+        buffer_len: usize,
+    ) -> CuResult<CuPool<T>> {
+        let mut pools = lockaudit::lock(&POOLS_LOCK, &self.pools).unwrap();
+        if let Some((_, _, pool)) = pools
+            .iter()
+            .find(|(id, pool_name, _)| *id == node_id && pool_name == name)
+        {
+            let pool = pool.downcast_ref::<CuPool<T>>().ok_or_else(|| {
+                CuError::from(format!(
+                    "The pool {}.{} was created with another type of element.",
+                    label, name
+                ))
+            })?;
+            if pool.inner.capacity != capacity || pool.inner.buffer_len != buffer_len {
+                return Err(format!(
+                    "The pool {}.{} was created with {} buffers of {} elements, not {} of {}.",
+                    label, name, pool.inner.capacity, pool.inner.buffer_len, capacity, buffer_len
+                )
+                .into());
+            }
+            return Ok(pool.clone());
+        }
+        let pool = CuPool::<T>::new(&format!("{}.{}", label, name), capacity, buffer_len);
+        pools.push((node_id, name.to_string(), Box::new(pool.clone())));
+        Ok(pool)
+    }
+}
+
+struct HandleInner<T> {
+    buffer: Vec<T>,
+    pool: Option<Weak<PoolInner<T>>>,
+    /// The task the handle is charged to, see cu29::memory.
+    task: Option<usize>,
+}
+
+impl<T> Drop for HandleInner<T> {
+    fn drop(&mut self) {
+        if let Some(task) = self.task {
+            release_handle(task);
+        }
+        if let Some(pool) = self.pool.as_ref().and_then(|pool| pool.upgrade()) {
+            lockaudit::lock(&FREE_LOCK, &pool.free)
+                .unwrap()
+                .push(std::mem::take(&mut self.buffer));
+        }
+    }
+}
+
+/// A reference counted handle to a buffer, usable as a payload or a field of a payload.
+/// Cloning the handle does not copy the buffer.
+///
+/// A handle can also be detached from any pool: this is what the default value is and what a handle
+/// decoded from a log gives.
+pub struct CuHandle<T>(Arc<HandleInner<T>>);
+
+impl<T> CuHandle<T> {
+    /// A handle owning its buffer, outside of any pool.
+    pub fn new_detached(buffer: Vec<T>) -> Self {
+        CuHandle(Arc::new(HandleInner {
+            buffer,
+            pool: None,
+            task: None,
+        }))
+    }
+
+    /// Mutable access to the buffer, only possible while this is the only handle pointing to it.
+    pub fn get_mut(&mut self) -> Option<&mut [T]> {
+        Arc::get_mut(&mut self.0).map(|inner| inner.buffer.as_mut_slice())
+    }
+
+    pub fn is_pooled(&self) -> bool {
+        self.0.pool.is_some()
+    }
+}
+
+impl<T> Deref for CuHandle<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.0.buffer
+    }
+}
+
+impl<T> Clone for CuHandle<T> {
+    fn clone(&self) -> Self {
+        CuHandle(self.0.clone())
+    }
+}
+
+impl<T> Default for CuHandle<T> {
+    fn default() -> Self {
+        Self::new_detached(Vec::new())
+    }
+}
+
+impl<T> Debug for CuHandle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CuHandle(len: {}", self.len())?;
+        if let Some(pool) = self.0.pool.as_ref().and_then(|pool| pool.upgrade()) {
+            write!(f, ", pool: {}", pool.id)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The buffers of numbers are copied at once by the fast path of [crate::pod], bincode already does it for u8.
+impl<T: Encode + 'static> Encode for CuHandle<T> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        let buffer: &dyn Any = &self.0.buffer;
+        macro_rules! fast_path {
+            ($($t:ty),*) => {
+                $(
+                    if let Some(buffer) = buffer.downcast_ref::<Vec<$t>>() {
+                        return encode_pod_slice(buffer, encoder);
+                    }
+                )*
+            };
+        }
+        fast_path!(i8, u16, i16, u32, i32, u64, i64, f32, f64);
+        Encode::encode(self.deref(), encoder)
+    }
+}
+
+/// Decodes the buffer of a handle, with the fast path of [crate::pod] for the buffers of numbers.
+fn decode_buffer<T: Decode + 'static, D: Decoder>(decoder: &mut D) -> Result<Vec<T>, DecodeError> {
+    let mut buffer: Option<Vec<T>> = None;
+    let slot: &mut dyn Any = &mut buffer;
+    macro_rules! fast_path {
+        ($($t:ty),*) => {
+            $(
+                if let Some(slot) = slot.downcast_mut::<Option<Vec<$t>>>() {
+                    *slot = Some(decode_pod_vec(decoder)?);
+                }
+            )*
+        };
+    }
+    fast_path!(i8, u16, i16, u32, i32, u64, i64, f32, f64);
+    match buffer {
+        Some(buffer) => Ok(buffer),
+        None => Decode::decode(decoder),
+    }
+}
+
+impl<T: Decode + 'static> Decode for CuHandle<T> {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::new_detached(decode_buffer(decoder)?))
+    }
+}
+
+impl<'de, T: Decode + 'static> BorrowDecode<'de> for CuHandle<T> {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::new_detached(decode_buffer(decoder)?))
+    }
+}
+
+impl<T: Serialize> Serialize for CuHandle<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for CuHandle<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new_detached(Vec::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_recycled() {
+        let pool = CuPool::<u32>::new("test_recycle", 2, 4);
+        let mut a = pool.acquire().unwrap();
+        a.get_mut().unwrap().copy_from_slice(&[1, 2, 3, 4]);
+        let shared = a.clone();
+        assert!(a.get_mut().is_none());
+        let b = pool.acquire().unwrap();
+        assert!(pool.acquire().is_none());
+
+        let stats = pool.stats();
+        assert_eq!((stats.available, stats.exhausted), (0, 1));
+
+        drop(a);
+        assert_eq!(pool.stats().available, 0);
+        drop(shared);
+        drop(b);
+        assert_eq!(pool.stats().available, 2);
+        assert!(pools_stats().iter().any(|s| s.id == "test_recycle"));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let pool = CuPool::<u16>::new("test_encode", 1, 3);
+        let mut handle = pool.acquire().unwrap();
+        handle.get_mut().unwrap().copy_from_slice(&[7, 8, 9]);
+        let encoded = bincode::encode_to_vec(&handle, bincode::config::standard()).unwrap();
+        let (decoded, _): (CuHandle<u16>, usize) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        assert_eq!(&decoded[..], &[7, 8, 9]);
+        assert!(!decoded.is_pooled());
+        assert!(handle.is_pooled());
+
+        let samples = CuHandle::new_detached(vec![0.5f32, -1.0, 2.0]);
+        let encoded = bincode::encode_to_vec(&samples, bincode::config::standard()).unwrap();
+        assert_eq!(
+            encoded,
+            bincode::encode_to_vec(&samples[..], bincode::config::standard()).unwrap()
+        );
+        let (decoded, _): (CuHandle<f32>, usize) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        assert_eq!(&decoded[..], &samples[..]);
+    }
+
+    #[test]
+    fn test_pools_by_node() {
+        let pools = CuPools::new();
+        let a = pools.get_or_create::<u8>(0, "src", "frames", 2, 4).unwrap();
+        let again = pools.get_or_create::<u8>(0, "src", "frames", 2, 4).unwrap();
+        let _handle = a.acquire().unwrap();
+        assert_eq!(again.stats().available, 1);
+        assert_eq!(again.stats().id, "src.frames");
+
+        let other = pools
+            .get_or_create::<u8>(1, "sink", "frames", 2, 4)
+            .unwrap();
+        assert_eq!(other.stats().available, 2);
+        assert!(pools.get_or_create::<u8>(0, "src", "frames", 3, 4).is_err());
+        assert!(pools
+            .get_or_create::<u16>(0, "src", "frames", 2, 4)
+            .is_err());
+    }
+
+    #[test]
+    fn test_handle_outliving_pool() {
+        let pool = CuPool::<u8>::new("test_outlive", 1, 1);
+        let handle = pool.acquire().unwrap();
+        drop(pool);
+        assert_eq!(handle.len(), 1);
+    }
+}
@@ -0,0 +1,185 @@
+//! The provenance of the messages: every output records which messages it was computed from, so the analysis of an
+//! incident can trace which camera frame led to which motor command.
+//!
+//! The runtime fills it on every process call with the inputs of the task that had a payload. The inputs of a task
+//! are in the same copper list as its output, so a message is identified by its copper list and its position in
+//! it, and a chain is followed from slot to slot in one copper list. See the provenance command of the log reader.
+
+use crate::copperlist::CuMsgDump;
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// The most inputs recorded for a message, the next ones are not.
+pub const MAX_PROVENANCE_INPUTS: usize = 8;
+
+/// A message of the log: the id of its copper list and its position in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CuMsgId {
+    pub culist: u32,
+    pub slot: u16,
+}
+
+impl fmt::Display for CuMsgId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.culist, self.slot)
+    }
+}
+
+/// The positions in the copper list of the messages an output was computed from, without allocation.
+/// Written as a Vec of u16 in the wire format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CuProvenance {
+    len: u8,
+    slots: [u16; MAX_PROVENANCE_INPUTS],
+}
+
+impl CuProvenance {
+    /// The provenance of the output of a task from its inputs: their slots and if they had a payload.
+    pub fn from_inputs(inputs: &[(u16, bool)]) -> Self {
+        let mut provenance = CuProvenance::default();
+        for (slot, _) in inputs.iter().filter(|(_, produced)| *produced) {
+            provenance.push(*slot);
+        }
+        provenance
+    }
+
+    /// Adds an input, ignored past MAX_PROVENANCE_INPUTS.
+    pub fn push(&mut self, slot: u16) {
+        if (self.len as usize) < MAX_PROVENANCE_INPUTS {
+            self.slots[self.len as usize] = slot;
+            self.len += 1;
+        }
+    }
+
+    pub fn slots(&self) -> &[u16] {
+        &self.slots[..self.len as usize]
+    }
+
+    /// The ids of the inputs for a message of this copper list.
+    pub fn ids(&self, culist: u32) -> impl Iterator<Item = CuMsgId> + '_ {
+        self.slots()
+            .iter()
+            .map(move |&slot| CuMsgId { culist, slot })
+    }
+}
+
+impl Encode for CuProvenance {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        Encode::encode(&self.slots(), encoder)
+    }
+}
+
+impl Decode for CuProvenance {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let slots: Vec<u16> = Decode::decode(decoder)?;
+        if slots.len() > MAX_PROVENANCE_INPUTS {
+            return Err(DecodeError::OtherString(format!(
+                "A provenance of {} inputs, more than {}.",
+                slots.len(),
+                MAX_PROVENANCE_INPUTS
+            )));
+        }
+        let mut provenance = CuProvenance::default();
+        slots.into_iter().for_each(|slot| provenance.push(slot));
+        Ok(provenance)
+    }
+}
+
+impl<'de> BorrowDecode<'de> for CuProvenance {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        CuProvenance::decode(decoder)
+    }
+}
+
+impl Serialize for CuProvenance {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.slots().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CuProvenance {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let slots = Vec::<u16>::deserialize(deserializer)?;
+        let mut provenance = CuProvenance::default();
+        slots.into_iter().for_each(|slot| provenance.push(slot));
+        Ok(provenance)
+    }
+}
+
+/// The messages of a copper list a message was derived from, directly or not, with their depth in the chain:
+/// the message itself at 0, its inputs at 1... Each message appears once, at its first occurrence.
+pub fn trace(msgs: &[CuMsgDump], slot: usize) -> Vec<(usize, usize)> {
+    let mut chain = Vec::new();
+    let mut seen = vec![false; msgs.len()];
+    trace_from(msgs, slot, 0, &mut seen, &mut chain);
+    chain
+}
+
+fn trace_from(
+    msgs: &[CuMsgDump],
+    slot: usize,
+    depth: usize,
+    seen: &mut [bool],
+    chain: &mut Vec<(usize, usize)>,
+) {
+    if slot >= msgs.len() || seen[slot] {
+        return;
+    }
+    seen[slot] = true;
+    chain.push((depth, slot));
+    for &input in &msgs[slot].provenance {
+        trace_from(msgs, input as usize, depth + 1, seen, chain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cutask::CuMsg;
+    use crate::wire::wire_config;
+
+    #[test]
+    fn test_provenance() {
+        let provenance = CuProvenance::from_inputs(&[(0, true), (1, false), (300, true)]);
+        assert_eq!(provenance.slots(), [0, 300]);
+        assert_eq!(
+            provenance
+                .ids(12)
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>(),
+            ["12#0", "12#300"]
+        );
+        let encoded = bincode::encode_to_vec(provenance, wire_config()).unwrap();
+        assert_eq!(encoded, [2, 0, 251, 0x2c, 0x01]);
+        let (decoded, _): (CuProvenance, usize) =
+            bincode::decode_from_slice(&encoded, wire_config()).unwrap();
+        assert_eq!(decoded, provenance);
+
+        let mut full = CuProvenance::default();
+        (0..10).for_each(|slot| full.push(slot));
+        assert_eq!(full.slots().len(), MAX_PROVENANCE_INPUTS);
+    }
+
+    #[test]
+    fn test_trace() {
+        // camera -> detector -> planner <- odometry, planner -> motor.
+        let dump = |task: &str, inputs: &[(u16, bool)]| {
+            let mut msg = CuMsg::new(Some(0u8));
+            msg.metadata.provenance = CuProvenance::from_inputs(inputs);
+            CuMsgDump::from_msg(task, "u8", &msg)
+        };
+        let msgs = [
+            dump("camera", &[]),
+            dump("odometry", &[]),
+            dump("detector", &[(0, true)]),
+            dump("planner", &[(2, true), (1, true)]),
+            dump("motor", &[(3, true)]),
+        ];
+        assert_eq!(trace(&msgs, 4), [(0, 4), (1, 3), (2, 2), (3, 0), (2, 1)]);
+        assert_eq!(trace(&msgs, 1), [(0, 1)]);
+    }
+}
@@ -0,0 +1,76 @@
+//! The setup of the process so the loop of the runtime meets hard deadlines on a PREEMPT_RT kernel, opt in from the
+//! runtime section:
+//!
+//! ```ron
+//! runtime: (period_ns: 1000000, realtime: (priority: 80, policy: Fifo)),
+//! ```
+//!
+//! Once the tasks are created and the log is set, the runtime:
+//! - locks the memory of the process with mlockall, present and future, so it is never paged out,
+//! - faults in the space preallocated for the log, so writing the copper lists does not fault,
+//! - gives the real time policy and priority to its thread, the one which created the application and runs its loop.
+//!
+//! The threads started afterwards, ie. the ones of the async tasks, do not inherit the real time priority: give them
+//! one with the priority of their node, see cu29::threads::CuThreadScheduling. The setup needs CAP_SYS_NICE and
+//! CAP_IPC_LOCK, or the rtprio and memlock limits, the application fails to start without them. Linux only.
+
+use crate::config::{RealtimeConfig, RealtimePolicy};
+use crate::{CuError, CuResult};
+use cu29_unifiedlog::UnifiedLoggerWrite;
+use std::sync::{Arc, Mutex};
+
+/// Sets up the process and the current thread for real time, log being the log of the application.
+pub fn setup_realtime(
+    config: &RealtimeConfig,
+    log: Option<&Arc<Mutex<UnifiedLoggerWrite>>>,
+) -> CuResult<()> {
+    if !(1..=99).contains(&config.priority) {
+        return Err(CuError::from(format!(
+            "Invalid real time priority {}, expected 1 to 99.",
+            config.priority
+        )));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if config.lock_memory
+            && unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0
+        {
+            return Err(CuError::new_with_cause(
+                "Could not lock the memory of the process",
+                std::io::Error::last_os_error(),
+            ));
+        }
+        if config.prefault_log {
+            if let Some(log) = log {
+                log.lock()
+                    .map_err(|_| CuError::from("The log is poisoned."))?
+                    .prefault();
+            }
+        }
+        let policy = match config.policy {
+            RealtimePolicy::Fifo => libc::SCHED_FIFO,
+            RealtimePolicy::RoundRobin => libc::SCHED_RR,
+        };
+        // The threads started afterwards are not real time.
+        crate::threads::sched::set_policy(policy | libc::SCHED_RESET_ON_FORK, config.priority)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = log;
+        Err("The real time setup is only supported on Linux.".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_priority() {
+        let config = RealtimeConfig {
+            priority: 0,
+            ..Default::default()
+        };
+        assert!(setup_realtime(&config, None).is_err());
+    }
+}
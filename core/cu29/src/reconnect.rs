@@ -0,0 +1,357 @@
+//! Reconnection of the drivers of serial and USB devices, so a loose cable or a hub resetting makes a task
+//! Degraded for a while instead of failing forever.
+//!
+//! The device is given in the config of the task either by its path or by the vendor and product ids of its USB
+//! adapter, found in the same sysfs attributes udev matches on, so it is found again whatever name the kernel gives
+//! it after a re-enumeration (ttyUSB0 becoming ttyUSB1):
+//!
+//! ```ron
+//! (id: "imu", type: "MyImu", config: {"usb": "1a86:7523", "usb_serial": "A50285BI"}),
+//! (id: "gps", type: "MyGps", config: {"device": "/dev/ttyACM0"}),
+//! ```
+//!
+//! The driver asks CuReconnect for its device on every process call and reports disconnected on an I/O error:
+//! the device is reopened with an exponential backoff, and the health is Degraded until data comes again.
+//!
+//! ```ignore
+//! fn process(&mut self, ctx: &CuContext, output: &mut CuMsg<Reading>) -> CuResult<()> {
+//!     let now = ctx.clock.now();
+//!     if let Some(port) = self.device.device(now, open_port) {
+//!         match read_reading(port) {
+//!             Ok(reading) => {
+//!                 self.device.data_received();
+//!                 output.set_payload(reading);
+//!             }
+//!             Err(e) => self.device.disconnected(now, &e.to_string()),
+//!         }
+//!     }
+//!     ctx.report_health(self.device.health());
+//!     Ok(())
+//! }
+//! ```
+
+use crate::config::ComponentConfig;
+use crate::monitoring::CuHealth;
+use crate::{CuError, CuResult};
+use cu29_clock::{CuDuration, CuTime};
+use cu29_log_derive::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The delay before the first attempt to reopen a device, doubled after every failed attempt.
+pub const RECONNECT_MIN_BACKOFF: CuDuration = CuDuration(100_000_000);
+/// The longest delay between 2 attempts.
+pub const RECONNECT_MAX_BACKOFF: CuDuration = CuDuration(5_000_000_000);
+
+/// How a device is found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CuDeviceMatch {
+    /// A fixed path, ie. /dev/ttyACM0 or a /dev/serial/by-id link.
+    Path(PathBuf),
+    /// The tty of a USB device, with the serial number of the device to tell apart 2 identical adapters.
+    Usb {
+        vendor_id: u16,
+        product_id: u16,
+        serial: Option<String>,
+    },
+}
+
+impl CuDeviceMatch {
+    /// From "device", or "usb" as VID:PID in hexadecimal with an optional "usb_serial", in the config of a task.
+    pub fn from_config(config: &ComponentConfig) -> CuResult<Self> {
+        if let Some(device) = config.get::<String>("device") {
+            return Ok(CuDeviceMatch::Path(PathBuf::from(device)));
+        }
+        let usb = config.get::<String>("usb").ok_or_else(|| {
+            CuError::from("The config of the task needs a 'device' path or a 'usb' VID:PID.")
+        })?;
+        let (vendor_id, product_id) = parse_usb_ids(&usb)?;
+        Ok(CuDeviceMatch::Usb {
+            vendor_id,
+            product_id,
+            serial: config.get::<String>("usb_serial"),
+        })
+    }
+
+    /// The path of the device if it is plugged in.
+    pub fn resolve(&self) -> Option<PathBuf> {
+        self.resolve_in(Path::new("/sys"), Path::new("/dev"))
+    }
+
+    fn resolve_in(&self, sysfs: &Path, dev: &Path) -> Option<PathBuf> {
+        match self {
+            CuDeviceMatch::Path(path) => path.exists().then(|| path.clone()),
+            CuDeviceMatch::Usb {
+                vendor_id,
+                product_id,
+                serial,
+            } => {
+                let mut ttys: Vec<_> = fs::read_dir(sysfs.join("class/tty"))
+                    .ok()?
+                    .filter_map(|entry| entry.ok())
+                    .collect();
+                // The first one of an adapter with several ports.
+                ttys.sort_by_key(|entry| entry.file_name());
+                ttys.into_iter()
+                    .find(|tty| {
+                        usb_device_dir(sysfs, &tty.path().join("device")).is_some_and(|usb| {
+                            read_id(&usb.join("idVendor")) == Some(*vendor_id)
+                                && read_id(&usb.join("idProduct")) == Some(*product_id)
+                                && serial.as_ref().is_none_or(|serial| {
+                                    read_attribute(&usb.join("serial")).as_ref() == Some(serial)
+                                })
+                        })
+                    })
+                    .map(|tty| dev.join(tty.file_name()))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for CuDeviceMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CuDeviceMatch::Path(path) => write!(f, "{}", path.display()),
+            CuDeviceMatch::Usb {
+                vendor_id,
+                product_id,
+                serial,
+            } => {
+                write!(f, "USB {:04x}:{:04x}", vendor_id, product_id)?;
+                if let Some(serial) = serial {
+                    write!(f, " ({})", serial)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// "1a86:7523" as the vendor and product ids.
+fn parse_usb_ids(usb: &str) -> CuResult<(u16, u16)> {
+    let invalid = || {
+        CuError::from(format!(
+            "Invalid USB id '{}', expected VID:PID ie. 1a86:7523.",
+            usb
+        ))
+    };
+    let (vendor, product) = usb.split_once(':').ok_or_else(invalid)?;
+    let vendor = u16::from_str_radix(vendor.trim(), 16).map_err(|_| invalid())?;
+    let product = u16::from_str_radix(product.trim(), 16).map_err(|_| invalid())?;
+    Ok((vendor, product))
+}
+
+fn read_attribute(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+fn read_id(path: &Path) -> Option<u16> {
+    u16::from_str_radix(&read_attribute(path)?, 16).ok()
+}
+
+/// The directory of the USB device a tty belongs to: the first parent of its device with an idVendor.
+fn usb_device_dir(sysfs: &Path, device: &Path) -> Option<PathBuf> {
+    let device = fs::canonicalize(device).ok()?;
+    let sysfs = fs::canonicalize(sysfs).ok()?;
+    device
+        .ancestors()
+        .take_while(|dir| dir.starts_with(&sysfs) && *dir != sysfs)
+        .find(|dir| dir.join("idVendor").exists())
+        .map(Path::to_path_buf)
+}
+
+/// A device reopened when it disappears, see the module documentation.
+pub struct CuReconnect<D> {
+    matcher: CuDeviceMatch,
+    device: Option<D>,
+    path: Option<PathBuf>,
+    next_attempt: Option<CuTime>,
+    backoff: CuDuration,
+    /// Lost since the last data, the task stays Degraded until data resumes.
+    lost: bool,
+    reconnections: u64,
+}
+
+impl<D> CuReconnect<D> {
+    pub fn new(matcher: CuDeviceMatch) -> Self {
+        CuReconnect {
+            matcher,
+            device: None,
+            path: None,
+            next_attempt: None,
+            backoff: RECONNECT_MIN_BACKOFF,
+            lost: false,
+            reconnections: 0,
+        }
+    }
+
+    pub fn from_config(config: &ComponentConfig) -> CuResult<Self> {
+        Ok(Self::new(CuDeviceMatch::from_config(config)?))
+    }
+
+    /// The device, opened with open if it is not yet and the backoff since the last attempt elapsed.
+    pub fn device<F>(&mut self, now: CuTime, open: F) -> Option<&mut D>
+    where
+        F: FnOnce(&Path) -> CuResult<D>,
+    {
+        if self.device.is_none() && self.next_attempt.is_none_or(|next| now >= next) {
+            self.connect(now, open);
+        }
+        self.device.as_mut()
+    }
+
+    fn connect<F>(&mut self, now: CuTime, open: F)
+    where
+        F: FnOnce(&Path) -> CuResult<D>,
+    {
+        let result = self
+            .matcher
+            .resolve()
+            .ok_or_else(|| CuError::from(format!("{} is not plugged in", self.matcher)))
+            .and_then(|path| open(&path).map(|device| (path, device)));
+        match result {
+            Ok((path, device)) => {
+                if self.lost {
+                    self.reconnections += 1;
+                    debug!("Reconnect: {} reopened.", path.display().to_string());
+                }
+                self.device = Some(device);
+                self.path = Some(path);
+                self.next_attempt = None;
+                self.backoff = RECONNECT_MIN_BACKOFF;
+            }
+            Err(e) => {
+                if !self.lost {
+                    debug!(
+                        "Reconnect: cannot open {}: {}.",
+                        self.matcher.to_string(),
+                        e.to_string()
+                    );
+                }
+                self.lost = true;
+                self.next_attempt = Some(now + self.backoff);
+                self.backoff = CuDuration((self.backoff.0 * 2).min(RECONNECT_MAX_BACKOFF.0));
+            }
+        }
+    }
+
+    /// The device failed, ie. an I/O error or an end of file: it is closed and reopened later.
+    pub fn disconnected(&mut self, now: CuTime, reason: &str) {
+        if self.device.take().is_some() {
+            debug!(
+                "Reconnect: {} lost: {}.",
+                self.matcher.to_string(),
+                reason.to_string()
+            );
+        }
+        self.lost = true;
+        self.next_attempt = Some(now + self.backoff);
+    }
+
+    /// Data came from the device, the connection is healthy again.
+    pub fn data_received(&mut self) {
+        self.lost = false;
+    }
+
+    /// Degraded while the device is lost or has not sent anything since it was reopened.
+    pub fn health(&self) -> CuHealth {
+        if self.lost {
+            CuHealth::Degraded
+        } else {
+            CuHealth::Nominal
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.device.is_some()
+    }
+
+    /// The path the device was last opened at.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The number of times the device was reopened after it was lost.
+    pub fn reconnections(&self) -> u64 {
+        self.reconnections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_usb_match() {
+        let sysfs = tempdir().unwrap();
+        let usb = sysfs.path().join("devices/pci0000:00/usb1/1-2");
+        let interface = usb.join("1-2:1.0");
+        fs::create_dir_all(&interface).unwrap();
+        fs::write(usb.join("idVendor"), "1a86\n").unwrap();
+        fs::write(usb.join("idProduct"), "7523\n").unwrap();
+        fs::write(usb.join("serial"), "A50285BI\n").unwrap();
+        fs::create_dir_all(sysfs.path().join("class/tty/ttyUSB1")).unwrap();
+        fs::create_dir_all(sysfs.path().join("class/tty/ttyS0")).unwrap();
+        std::os::unix::fs::symlink(&interface, sysfs.path().join("class/tty/ttyUSB1/device"))
+            .unwrap();
+
+        let mut config = ComponentConfig::new();
+        config.set("usb", "1A86:7523".to_string());
+        config.set("usb_serial", "A50285BI".to_string());
+        let matcher = CuDeviceMatch::from_config(&config).unwrap();
+        assert_eq!(matcher.to_string(), "USB 1a86:7523 (A50285BI)");
+        assert_eq!(
+            matcher.resolve_in(sysfs.path(), Path::new("/dev")),
+            Some(PathBuf::from("/dev/ttyUSB1"))
+        );
+        config.set("usb_serial", "OTHER".to_string());
+        let other = CuDeviceMatch::from_config(&config).unwrap();
+        assert_eq!(other.resolve_in(sysfs.path(), Path::new("/dev")), None);
+
+        config.set("usb", "1a86".to_string());
+        assert!(CuDeviceMatch::from_config(&config).is_err());
+        assert!(CuDeviceMatch::from_config(&ComponentConfig::new()).is_err());
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("ttyACM0");
+        fs::write(&path, "").unwrap();
+        let mut device = CuReconnect::<u32>::new(CuDeviceMatch::Path(path.clone()));
+        let ms = |ms: u64| CuTime::from(ms * 1_000_000);
+
+        assert_eq!(device.device(ms(0), |_| Ok(1)), Some(&mut 1));
+        assert_eq!(device.health(), CuHealth::Nominal);
+
+        // Unplugged: not reopened before the backoff, then every attempt doubles it.
+        device.disconnected(ms(10), "end of file");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(device.health(), CuHealth::Degraded);
+        assert!(device.device(ms(50), |_| Ok(2)).is_none());
+        assert!(device.device(ms(110), |_| Ok(2)).is_none());
+        assert!(device.device(ms(250), |_| Ok(2)).is_none());
+        assert!(device.device(ms(310), |_| Ok(2)).is_none());
+
+        // Plugged again: Degraded until it sends data.
+        fs::write(&path, "").unwrap();
+        assert!(device.device(ms(400), |_| Ok(2)).is_none());
+        assert_eq!(device.device(ms(710), |_| Ok(2)), Some(&mut 2));
+        assert_eq!(device.path(), Some(path.as_path()));
+        assert_eq!(device.health(), CuHealth::Degraded);
+        device.data_received();
+        assert_eq!(device.health(), CuHealth::Nominal);
+        assert_eq!(device.reconnections(), 1);
+
+        // A device failing to open is retried like a missing one.
+        device.disconnected(ms(1000), "I/O error");
+        assert!(device
+            .device(ms(1100), |_| Err("permission denied".into()))
+            .is_none());
+        assert!(device.device(ms(1150), |_| Ok(3)).is_none());
+        assert_eq!(device.device(ms(1300), |_| Ok(3)), Some(&mut 3));
+    }
+}
@@ -0,0 +1,257 @@
+//! A process wide registry of the payload types, by a stable name, so generic components (bridges, exporters,
+//! injectors...) can handle "a message of type X" without knowing the type at compile time.
+//!
+//! Payload crates register their types at startup:
+//!
+//! ```
+//! use cu29::registry::{msg_type, register_json_msg_type};
+//!
+//! register_json_msg_type::<f32>("f32").unwrap();
+//! let f32_type = msg_type("f32").unwrap();
+//! let bytes = f32_type.encode(&1.5f32).unwrap();
+//! assert_eq!(f32_type.to_json(&*f32_type.decode(&bytes).unwrap()).unwrap(), "1.5");
+//! ```
+
+use crate::cutask::CuMsgPayload;
+use crate::wire::{schema_hash, wire_config, WIRE_FORMAT_VERSION};
+use crate::{CuError, CuResult};
+use cu29_log::value::{to_value, Value};
+use cu29_traits::lockaudit::{self, LockSite};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+type EncodeFn = fn(&dyn Any) -> CuResult<Vec<u8>>;
+type DecodeFn = fn(&[u8]) -> CuResult<Box<dyn Any + Send>>;
+type ToJsonFn = fn(&dyn Any) -> CuResult<String>;
+type FromJsonFn = fn(&str) -> CuResult<Box<dyn Any + Send>>;
+type ToValueFn = fn(&dyn Any) -> CuResult<Value>;
+
+/// A registered payload type and how to (de)serialize it.
+#[derive(Debug)]
+pub struct CuMsgType {
+    name: String,
+    schema_hash: u64,
+    type_id: TypeId,
+    encode: EncodeFn,
+    decode: DecodeFn,
+    to_json: Option<ToJsonFn>,
+    from_json: Option<FromJsonFn>,
+    to_value: Option<ToValueFn>,
+}
+
+impl CuMsgType {
+    /// The stable name the type was registered with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The hash of the name and the version of the wire format, for both ends of a bridge to check they agree.
+    pub fn schema_hash(&self) -> u64 {
+        self.schema_hash
+    }
+
+    /// The TypeId of the payload type.
+    pub fn payload_type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    /// Encodes a payload of this type in the wire format.
+    pub fn encode(&self, payload: &dyn Any) -> CuResult<Vec<u8>> {
+        (self.encode)(payload)
+    }
+
+    /// Decodes a payload of this type from the wire format, downcast it to the type to use it.
+    pub fn decode(&self, bytes: &[u8]) -> CuResult<Box<dyn Any + Send>> {
+        (self.decode)(bytes)
+    }
+
+    /// The JSON of a payload of this type, if it was registered with register_json_msg_type.
+    pub fn to_json(&self, payload: &dyn Any) -> CuResult<String> {
+        let to_json = self.to_json.ok_or_else(|| self.no_json())?;
+        to_json(payload)
+    }
+
+    /// A payload of this type from its JSON, if it was registered with register_json_msg_type.
+    pub fn from_json(&self, json: &str) -> CuResult<Box<dyn Any + Send>> {
+        let from_json = self.from_json.ok_or_else(|| self.no_json())?;
+        from_json(json)
+    }
+
+    /// The fields of a payload of this type as a Value, if it was registered with register_json_msg_type.
+    /// See DynCuMsg for a view of any message.
+    pub fn to_value(&self, payload: &dyn Any) -> CuResult<Value> {
+        let to_value = self.to_value.ok_or_else(|| self.no_json())?;
+        to_value(payload)
+    }
+
+    fn no_json(&self) -> CuError {
+        format!("The message type {} has no JSON support.", self.name).into()
+    }
+}
+
+fn downcast<T: 'static>(payload: &dyn Any) -> CuResult<&T> {
+    payload
+        .downcast_ref::<T>()
+        .ok_or_else(|| format!("The payload is not a {}.", type_name::<T>()).into())
+}
+
+fn encode<T: CuMsgPayload + 'static>(payload: &dyn Any) -> CuResult<Vec<u8>> {
+    bincode::encode_to_vec(downcast::<T>(payload)?, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not encode the payload", e))
+}
+
+fn decode<T: CuMsgPayload + Send + 'static>(bytes: &[u8]) -> CuResult<Box<dyn Any + Send>> {
+    let (payload, _): (T, usize) = bincode::decode_from_slice(bytes, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not decode the payload", e))?;
+    Ok(Box::new(payload))
+}
+
+fn to_json<T: Serialize + 'static>(payload: &dyn Any) -> CuResult<String> {
+    serde_json::to_string(downcast::<T>(payload)?)
+        .map_err(|e| CuError::new_with_cause("Could not serialize the payload to JSON", e))
+}
+
+fn from_json<T: DeserializeOwned + Send + 'static>(json: &str) -> CuResult<Box<dyn Any + Send>> {
+    let payload: T = serde_json::from_str(json)
+        .map_err(|e| CuError::new_with_cause("Could not deserialize the payload from JSON", e))?;
+    Ok(Box::new(payload))
+}
+
+fn to_dyn_value<T: Serialize + 'static>(payload: &dyn Any) -> CuResult<Value> {
+    to_value(downcast::<T>(payload)?)
+        .map_err(|e| CuError::new_with_cause("Could not convert the payload to a Value", e))
+}
+
+static REGISTRY_LOCK: LockSite = LockSite::new("msg_registry");
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<CuMsgType>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Arc<CuMsgType>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn register(msg_type: CuMsgType) -> CuResult<()> {
+    let mut registry = lockaudit::write(&REGISTRY_LOCK, registry()).unwrap();
+    if let Some(existing) = registry.get(&msg_type.name) {
+        if existing.type_id != msg_type.type_id {
+            return Err(format!(
+                "The message type name {} is already registered for another type.",
+                msg_type.name
+            )
+            .into());
+        }
+        // Registering the same type again only adds the JSON support if it was missing.
+        if existing.to_json.is_some() || msg_type.to_json.is_none() {
+            return Ok(());
+        }
+    }
+    registry.insert(msg_type.name.clone(), Arc::new(msg_type));
+    Ok(())
+}
+
+fn new_msg_type<T: CuMsgPayload + Send + 'static>(name: &str) -> CuMsgType {
+    CuMsgType {
+        name: name.to_string(),
+        schema_hash: schema_hash(&format!("{}:{}", WIRE_FORMAT_VERSION, name)),
+        type_id: TypeId::of::<T>(),
+        encode: encode::<T>,
+        decode: decode::<T>,
+        to_json: None,
+        from_json: None,
+        to_value: None,
+    }
+}
+
+/// Registers a payload type under a stable name, ie. "cu_sensor_payloads::Image".
+/// Rename the type (ie. "ImageV2") when its encoding changes so a peer with the old one does not accept it.
+pub fn register_msg_type<T: CuMsgPayload + Send + 'static>(name: &str) -> CuResult<()> {
+    register(new_msg_type::<T>(name))
+}
+
+/// Registers a payload type with its JSON support and the reflection of its fields, see DynCuMsg.
+pub fn register_json_msg_type<T>(name: &str) -> CuResult<()>
+where
+    T: CuMsgPayload + Serialize + DeserializeOwned + Send + 'static,
+{
+    register(CuMsgType {
+        to_json: Some(to_json::<T>),
+        from_json: Some(from_json::<T>),
+        to_value: Some(to_dyn_value::<T>),
+        ..new_msg_type::<T>(name)
+    })
+}
+
+/// The type registered under this name.
+pub fn msg_type(name: &str) -> Option<Arc<CuMsgType>> {
+    lockaudit::read(&REGISTRY_LOCK, registry())
+        .unwrap()
+        .get(name)
+        .cloned()
+}
+
+/// The registered type of T, ie. to find the name to send a payload under.
+pub fn msg_type_of<T: 'static>() -> Option<Arc<CuMsgType>> {
+    lockaudit::read(&REGISTRY_LOCK, registry())
+        .unwrap()
+        .values()
+        .find(|msg_type| msg_type.type_id == TypeId::of::<T>())
+        .cloned()
+}
+
+/// The names of all the registered types.
+pub fn msg_type_names() -> Vec<String> {
+    let mut names: Vec<String> = lockaudit::read(&REGISTRY_LOCK, registry())
+        .unwrap()
+        .keys()
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::{Decode, Encode};
+    use serde_derive::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+    struct Pose {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Debug, Default, Encode, Decode)]
+    struct Raw(Vec<u8>);
+
+    #[test]
+    fn test_registry() {
+        register_msg_type::<Raw>("test::Raw").unwrap();
+        register_json_msg_type::<Pose>("test::Pose").unwrap();
+        register_json_msg_type::<Pose>("test::Pose").unwrap();
+        assert!(register_msg_type::<Raw>("test::Pose").is_err());
+        assert!(msg_type_names().contains(&"test::Pose".to_string()));
+
+        let pose_type = msg_type("test::Pose").unwrap();
+        assert_eq!(pose_type.payload_type_id(), TypeId::of::<Pose>());
+        assert_eq!(
+            msg_type_of::<Pose>().unwrap().schema_hash(),
+            pose_type.schema_hash()
+        );
+        let pose = Pose { x: 1.0, y: -2.0 };
+        let bytes = pose_type.encode(&pose).unwrap();
+        let decoded = pose_type.decode(&bytes).unwrap();
+        assert_eq!(decoded.downcast_ref::<Pose>(), Some(&pose));
+        let json = pose_type.to_json(&pose).unwrap();
+        assert_eq!(json, r#"{"x":1.0,"y":-2.0}"#);
+        let from_json = pose_type.from_json(&json).unwrap();
+        assert_eq!(from_json.downcast_ref::<Pose>(), Some(&pose));
+        assert!(pose_type.encode(&Raw(vec![1])).is_err());
+
+        let raw_type = msg_type("test::Raw").unwrap();
+        assert!(raw_type.to_json(&Raw(vec![1])).is_err());
+        assert!(msg_type("test::Unknown").is_none());
+    }
+}
@@ -1,4 +1,7 @@
+#[allow(dead_code)] // only a subset of the config API is needed to render it
 mod config;
+#[allow(dead_code)]
+mod permissions;
 use clap::Parser;
 use config::read_configuration;
 pub use cu29_traits::*;
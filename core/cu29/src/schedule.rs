@@ -0,0 +1,267 @@
+//! Commands applied at a given time of the robot clock rather than when they are computed.
+//!
+//! The compute time of a planner varies from an iteration to the other, so 2 actuators commanded from 2 branches of
+//! the graph do not move at the same time. The planner stamps its commands with a time in the future covering its
+//! worst latency, a `CuScheduled`, and a `CuCommandScheduler` in front of each actuator delivers them in the first
+//! iteration at or after this time: commands stamped with the same time reach all the actuators together.
+//!
+//! ```ron
+//! (id: "left_delay", type: "cu29::schedule::CuCommandScheduler<WheelCommand>"),
+//! (id: "right_delay", type: "cu29::schedule::CuCommandScheduler<WheelCommand>"),
+//! ```
+//!
+//! A sink driving its hardware from its own thread can use a `CuCommandQueue` directly to apply the commands closer
+//! to their time than the period of the loop.
+
+use crate::clock::{CuDuration, CuTime, RobotClock};
+use crate::config::ComponentConfig;
+use crate::context::CuContext;
+use crate::cutask::{CuMsg, CuMsgPayload, CuTask, CuTaskLifecycle, Freezable};
+use crate::{input_msg, output_msg};
+use bincode::{Decode, Encode};
+use cu29_log_derive::debug;
+use cu29_traits::CuResult;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// A command to apply at a time of the robot clock.
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct CuScheduled<T> {
+    pub apply_at: CuTime,
+    pub command: T,
+}
+
+impl<T> CuScheduled<T> {
+    pub fn new(command: T, apply_at: CuTime) -> Self {
+        CuScheduled { apply_at, command }
+    }
+
+    /// The command to apply delay after now, ie. the worst latency of the pipeline.
+    pub fn after(command: T, now: CuTime, delay: CuDuration) -> Self {
+        Self::new(command, now + delay)
+    }
+}
+
+/// What happened to the commands of a queue.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CuCommandQueueStats {
+    pub applied: u64,
+    /// Commands replaced by a later one due at the same check.
+    pub superseded: u64,
+    /// Commands dropped because the queue was full or they were too late.
+    pub dropped: u64,
+    /// The longest time a command was applied after its time.
+    pub max_delay: CuDuration,
+}
+
+/// The pending commands ordered by their time.
+pub struct CuCommandQueue<T> {
+    pending: VecDeque<CuScheduled<T>>,
+    capacity: usize,
+    max_lateness: Option<CuDuration>,
+    pub stats: CuCommandQueueStats,
+}
+
+impl<T> CuCommandQueue<T> {
+    /// A queue of at most capacity commands. A command due for more than max_lateness is dropped instead of being
+    /// applied, if set.
+    pub fn new(capacity: usize, max_lateness: Option<CuDuration>) -> Self {
+        CuCommandQueue {
+            pending: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            max_lateness,
+            stats: CuCommandQueueStats::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// When the next command is due.
+    pub fn next_apply_time(&self) -> Option<CuTime> {
+        self.pending.front().map(|scheduled| scheduled.apply_at)
+    }
+
+    /// Queues a command after the ones due at the same time. When the queue is full, the command due first is
+    /// dropped and returned.
+    pub fn push(&mut self, scheduled: CuScheduled<T>) -> Option<CuScheduled<T>> {
+        let dropped = if self.pending.len() == self.capacity {
+            self.stats.dropped += 1;
+            self.pending.pop_front()
+        } else {
+            None
+        };
+        let index = self
+            .pending
+            .partition_point(|pending| pending.apply_at <= scheduled.apply_at);
+        self.pending.insert(index, scheduled);
+        dropped
+    }
+
+    /// The last command due at now, the earlier ones due are superseded by it.
+    pub fn pop_due(&mut self, now: CuTime) -> Option<CuScheduled<T>> {
+        let mut due = None;
+        while self
+            .pending
+            .front()
+            .is_some_and(|scheduled| scheduled.apply_at <= now)
+        {
+            let scheduled = self.pending.pop_front().unwrap();
+            let delay = now - scheduled.apply_at;
+            if self.max_lateness.is_some_and(|max| delay > max) {
+                self.stats.dropped += 1;
+                continue;
+            }
+            if due.replace(scheduled).is_some() {
+                self.stats.superseded += 1;
+            }
+        }
+        if let Some(scheduled) = &due {
+            self.stats.applied += 1;
+            self.stats.max_delay = self.stats.max_delay.max(now - scheduled.apply_at);
+        }
+        due
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Delays the commands it receives until their time, see the module documentation.
+///
+/// The config takes an optional `capacity` (16 by default), the number of commands waiting, and an optional
+/// `max_lateness_ms`, the commands late for longer are dropped rather than applied.
+pub struct CuCommandScheduler<T> {
+    queue: CuCommandQueue<T>,
+}
+
+impl<T> Freezable for CuCommandScheduler<T> {}
+
+impl<T> CuTaskLifecycle for CuCommandScheduler<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let capacity = config
+            .and_then(|config| config.get::<u32>("capacity"))
+            .unwrap_or(16);
+        let max_lateness = config
+            .and_then(|config| config.get::<u32>("max_lateness_ms"))
+            .map(|ms| CuDuration::from(ms as u64 * 1_000_000));
+        Ok(CuCommandScheduler {
+            queue: CuCommandQueue::new(capacity as usize, max_lateness),
+        })
+    }
+
+    /// The commands of a previous run are not applied after a restart.
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.queue.clear();
+        let stats = self.queue.stats;
+        let (applied, superseded, dropped) = (stats.applied, stats.superseded, stats.dropped);
+        let max_delay = stats.max_delay;
+        debug!(
+            "CuCommandScheduler: {} commands applied at most {} late, {} superseded, {} dropped.",
+            applied, max_delay, superseded, dropped
+        );
+        Ok(())
+    }
+}
+
+impl<'cl, T> CuTask<'cl> for CuCommandScheduler<T>
+where
+    T: CuMsgPayload + Clone + 'cl,
+{
+    type Input = input_msg!('cl, CuScheduled<T>);
+    type Output = output_msg!('cl, T);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        if let Some(scheduled) = input.payload() {
+            if self.queue.push(scheduled.clone()).is_some() {
+                debug!("CuCommandScheduler: too many commands waiting, dropped the first one.");
+            }
+        }
+        match self.queue.pop_due(ctx.now()) {
+            Some(scheduled) => {
+                output.set_payload(scheduled.command);
+                output.metadata.tov = Some(scheduled.apply_at).into();
+            }
+            None => output.clear_payload(),
+        }
+        output
+            .metadata
+            .set_status(format!("{} waiting", self.queue.len()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> CuTime {
+        CuDuration::from(ms * 1_000_000)
+    }
+
+    #[test]
+    fn test_command_queue() {
+        let mut queue = CuCommandQueue::new(3, Some(ms(50)));
+        queue.push(CuScheduled::new("b", ms(20)));
+        queue.push(CuScheduled::new("a", ms(10)));
+        queue.push(CuScheduled::new("c", ms(20)));
+        assert_eq!(queue.next_apply_time(), Some(ms(10)));
+        assert_eq!(queue.pop_due(ms(5)), None);
+        assert_eq!(queue.pop_due(ms(12)).unwrap().command, "a");
+        // Due together, the last one queued wins.
+        assert_eq!(queue.pop_due(ms(25)).unwrap().command, "c");
+        assert_eq!(queue.stats.superseded, 1);
+        assert_eq!(queue.stats.max_delay, ms(5));
+
+        queue.push(CuScheduled::new("stale", ms(30)));
+        assert_eq!(queue.pop_due(ms(100)), None);
+        assert_eq!(queue.stats.dropped, 1);
+
+        for (i, command) in ["d", "e", "f", "g"].into_iter().enumerate() {
+            queue.push(CuScheduled::after(command, ms(100), ms(i as u64)));
+        }
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.stats.dropped, 2);
+        assert_eq!(queue.next_apply_time(), Some(ms(101)));
+    }
+
+    #[test]
+    fn test_scheduler_task() {
+        let (clock, mock) = RobotClock::mock();
+        let mut scheduler = CuCommandScheduler::<u32>::new(None).unwrap();
+        let mut input = CuMsg::new(Some(CuScheduled::new(42u32, ms(10))));
+        let mut output = CuMsg::<u32>::new(None);
+
+        scheduler
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert_eq!(output.payload(), None);
+
+        input.clear_payload();
+        mock.set_value(ms(10).into());
+        scheduler
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert_eq!(output.payload(), Some(&42));
+        assert_eq!(output.metadata.tov.unwrap(), ms(10));
+
+        scheduler
+            .process(&CuContext::from(&clock), &input, &mut output)
+            .unwrap();
+        assert_eq!(output.payload(), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! A scratch memory arena per task for the temporary buffers of its process call (sorting a scan,
+//! building an image pyramid...). The memory is allocated once when the runtime is created and the
+//! arena is reset before every process call of the task, so nothing is allocated in the critical path.
+//!
+//! ```
+//! use cu29::scratch::CuScratch;
+//!
+//! let mut scratch = CuScratch::new(1024);
+//! let ranges = scratch.alloc_slice_copy(&[3.0f32, 1.0, 2.0]).unwrap();
+//! ranges.sort_by(f32::total_cmp);
+//! assert_eq!(ranges, &[1.0, 2.0, 3.0]);
+//! scratch.reset();
+//! assert_eq!(scratch.used(), 0);
+//! ```
+
+use bumpalo::Bump;
+use cu29_traits::{CuError, CuResult};
+
+/// A bump arena with a fixed capacity: an allocation that does not fit fails instead of growing it.
+/// Only Copy values can be allocated as the values are never dropped, the arena is just rewound.
+pub struct CuScratch {
+    bump: Bump,
+    capacity: usize,
+}
+
+impl CuScratch {
+    /// Preallocates capacity bytes. An arena with a capacity of 0 does not allocate anything.
+    pub fn new(capacity: usize) -> Self {
+        let bump = if capacity > 0 {
+            Bump::with_capacity(capacity)
+        } else {
+            Bump::new()
+        };
+        // Never ask the allocator for more than what was preallocated.
+        bump.set_allocation_limit(Some(bump.allocated_bytes()));
+        CuScratch { bump, capacity }
+    }
+
+    /// The number of bytes the arena was created with.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of bytes allocated since the last reset, padding included.
+    pub fn used(&self) -> usize {
+        self.bump.allocated_bytes() - self.bump.chunk_capacity()
+    }
+
+    /// Frees everything allocated in the arena at once.
+    pub fn reset(&mut self) {
+        self.bump.reset();
+    }
+
+    pub fn alloc<T: Copy>(&self, value: T) -> CuResult<&mut T> {
+        self.bump
+            .try_alloc(value)
+            .map_err(|_| self.exhausted(size_of::<T>()))
+    }
+
+    /// A slice of len elements, all set to value.
+    pub fn alloc_slice<T: Copy>(&self, len: usize, value: T) -> CuResult<&mut [T]> {
+        self.bump
+            .try_alloc_slice_fill_copy(len, value)
+            .map_err(|_| self.exhausted(len * size_of::<T>()))
+    }
+
+    /// A copy of src.
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> CuResult<&mut [T]> {
+        self.bump
+            .try_alloc_slice_copy(src)
+            .map_err(|_| self.exhausted(size_of_val(src)))
+    }
+
+    fn exhausted(&self, requested: usize) -> CuError {
+        format!(
+            "The scratch arena is exhausted: {} bytes requested, {} of {} used.",
+            requested,
+            self.used(),
+            self.capacity
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_capacity() {
+        let mut scratch = CuScratch::new(256);
+        assert_eq!(scratch.capacity(), 256);
+        let a = scratch.alloc_slice(16, 0u64).unwrap();
+        a[0] = 42;
+        assert!(scratch.used() >= 128);
+        assert!(scratch.alloc_slice(64, 0u64).is_err());
+        let b = scratch.alloc(7u32).unwrap();
+        assert_eq!(*b, 7);
+
+        scratch.reset();
+        assert_eq!(scratch.used(), 0);
+        assert!(scratch.alloc_slice(16, 0u64).is_ok());
+    }
+
+    #[test]
+    fn test_empty() {
+        let scratch = CuScratch::new(0);
+        assert!(scratch.alloc(1u8).is_err());
+        assert_eq!(scratch.used(), 0);
+    }
+}
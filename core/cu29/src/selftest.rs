@@ -0,0 +1,188 @@
+//! The self tests of the drivers, a hardware readiness check before the main loop.
+//!
+//! A driver implements CuTaskLifecycle::self_test with the checks it can do without moving the robot: reading an
+//! identification register (WHO_AM_I), a loopback, a scan of its bus... The runtime calls them after starting the
+//! tasks, when the drivers have opened their devices. With `self_test: true` in the runtime section of the config,
+//! run() does not enter the loop if one of them fails, its error holds the report. The `self_test` method of the
+//! application only runs them and returns the report, ie. for a `--self-test` option of the application.
+
+use crate::clock::CuDuration;
+use cu29_traits::{CuError, CuResult};
+use std::fmt::{Display, Formatter};
+
+/// The outcome of a successful self test, a failed one returns an error.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CuSelfTest {
+    /// The task has no self test, the default.
+    NotImplemented,
+    /// The hardware answered as expected, with what was checked, ie. "WHO_AM_I 0x71".
+    Passed(String),
+}
+
+impl CuSelfTest {
+    pub fn passed(details: impl Into<String>) -> Self {
+        CuSelfTest::Passed(details.into())
+    }
+}
+
+/// The result of the self test of a task in the report.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CuSelfTestOutcome {
+    NotImplemented,
+    /// The task is replaced by its recorded outputs, see set_stubbed_tasks.
+    Stubbed,
+    /// The optional task was pruned, see cu29::optional.
+    Pruned,
+    Passed(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CuSelfTestResult {
+    pub task_id: String,
+    pub outcome: CuSelfTestOutcome,
+    pub duration: CuDuration,
+}
+
+/// The self tests of all the tasks, in the order of the plan.
+#[derive(Debug, Clone, Default)]
+pub struct CuSelfTestReport {
+    pub results: Vec<CuSelfTestResult>,
+}
+
+impl CuSelfTestReport {
+    pub fn record(&mut self, task_id: &str, outcome: CuResult<CuSelfTest>, duration: CuDuration) {
+        let outcome = match outcome {
+            Ok(CuSelfTest::NotImplemented) => CuSelfTestOutcome::NotImplemented,
+            Ok(CuSelfTest::Passed(details)) => CuSelfTestOutcome::Passed(details),
+            Err(error) => CuSelfTestOutcome::Failed(error.to_string()),
+        };
+        self.results.push(CuSelfTestResult {
+            task_id: task_id.to_string(),
+            outcome,
+            duration,
+        });
+    }
+
+    pub fn record_stubbed(&mut self, task_id: &str) {
+        self.results.push(CuSelfTestResult {
+            task_id: task_id.to_string(),
+            outcome: CuSelfTestOutcome::Stubbed,
+            duration: CuDuration::default(),
+        });
+    }
+
+    pub fn record_pruned(&mut self, task_id: &str) {
+        self.results.push(CuSelfTestResult {
+            task_id: task_id.to_string(),
+            outcome: CuSelfTestOutcome::Pruned,
+            duration: CuDuration::default(),
+        });
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CuSelfTestResult> {
+        self.results
+            .iter()
+            .filter(|result| matches!(result.outcome, CuSelfTestOutcome::Failed(_)))
+    }
+
+    /// True if no self test failed.
+    pub fn is_ready(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// An error naming the tasks whose self test failed, with the report as its context, if any.
+    pub fn check(&self) -> CuResult<()> {
+        let failed: Vec<&str> = self.failures().map(|r| r.task_id.as_str()).collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(CuError::from(format!(
+                "The self test of {} failed, the hardware is not ready.",
+                failed.join(", ")
+            ))
+            .add_cause(&self.to_string()))
+        }
+    }
+}
+
+impl Display for CuSelfTestReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let count = |wanted: fn(&CuSelfTestOutcome) -> bool| {
+            self.results.iter().filter(|r| wanted(&r.outcome)).count()
+        };
+        writeln!(
+            f,
+            "Hardware {}: {} passed, {} failed, {} without self test.",
+            if self.is_ready() {
+                "READY"
+            } else {
+                "NOT READY"
+            },
+            count(|o| matches!(o, CuSelfTestOutcome::Passed(_))),
+            count(|o| matches!(o, CuSelfTestOutcome::Failed(_))),
+            count(|o| {
+                matches!(
+                    o,
+                    CuSelfTestOutcome::NotImplemented
+                        | CuSelfTestOutcome::Stubbed
+                        | CuSelfTestOutcome::Pruned
+                )
+            }),
+        )?;
+        writeln!(f, "{:<24} {:<8} {:>12}  DETAILS", "TASK", "RESULT", "TIME")?;
+        for result in &self.results {
+            let (status, details) = match &result.outcome {
+                CuSelfTestOutcome::NotImplemented => ("-", "no self test"),
+                CuSelfTestOutcome::Stubbed => ("-", "stubbed"),
+                CuSelfTestOutcome::Pruned => ("-", "pruned"),
+                CuSelfTestOutcome::Passed(details) => ("PASSED", details.as_str()),
+                CuSelfTestOutcome::Failed(error) => ("FAILED", error.lines().next().unwrap_or("")),
+            };
+            writeln!(
+                f,
+                "{:<24} {:<8} {:>12}  {}",
+                result.task_id,
+                status,
+                result.duration.to_string(),
+                details
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test_report() {
+        let mut report = CuSelfTestReport::default();
+        report.record(
+            "imu",
+            Ok(CuSelfTest::passed("WHO_AM_I 0x50")),
+            CuDuration(1_000_000),
+        );
+        report.record("pid", Ok(CuSelfTest::NotImplemented), CuDuration(0));
+        assert!(report.is_ready());
+        assert!(report.check().is_ok());
+
+        report.record(
+            "motor",
+            Err(CuError::from("no answer on the bus")),
+            CuDuration(0),
+        );
+        report.record_stubbed("lidar");
+        report.record_pruned("camera");
+        assert!(!report.is_ready());
+        let error = report.check().unwrap_err().to_string();
+        assert!(error.starts_with("The self test of motor failed"));
+        assert!(error.contains("Hardware NOT READY"));
+
+        let printed = report.to_string();
+        assert!(printed.starts_with("Hardware NOT READY: 1 passed, 1 failed, 3 without self test."));
+        assert!(printed.contains("WHO_AM_I 0x50"));
+        assert!(printed.contains("no answer on the bus"));
+    }
+}
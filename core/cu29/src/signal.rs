@@ -0,0 +1,109 @@
+//! Stopping a copper application cleanly when it is asked to: ctrl-c (SIGINT), `kill` or `systemctl stop` (SIGTERM).
+//!
+
+use cu29_traits::{CuError, CuResult};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::SigId;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag asking the copper loop to stop at the end of the current iteration.
+/// Clones share the same flag so it can be raised from another thread or a signal handler.
+#[derive(Debug, Clone, Default)]
+pub struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the loop to stop.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Raises the flag on SIGINT and SIGTERM until the returned guard is dropped.
+    /// A second signal while the application is stopping terminates it right away, in case a task is stuck.
+    pub fn install_handlers(&self) -> CuResult<SignalHandlers> {
+        self.install_handlers_for(&[SIGINT, SIGTERM])
+    }
+
+    fn install_handlers_for(&self, signals: &[i32]) -> CuResult<SignalHandlers> {
+        let mut handlers = SignalHandlers {
+            ids: Vec::with_capacity(signals.len() * 2),
+            released: Arc::new(AtomicBool::new(false)),
+        };
+        for &signal in signals {
+            // Once the handlers are dropped the signal does what it did before they were installed. This one stays
+            // registered, it is inactive until then.
+            let default =
+                signal_hook::flag::register_conditional_default(signal, handlers.released.clone());
+            // The order matters: the shutdown only triggers if the flag was already raised by a previous signal.
+            let flag = default
+                .and_then(|_| {
+                    signal_hook::flag::register_conditional_shutdown(signal, 1, self.0.clone())
+                })
+                .and_then(|id| {
+                    handlers.ids.push(id);
+                    signal_hook::flag::register(signal, self.0.clone())
+                });
+            match flag {
+                Ok(id) => handlers.ids.push(id),
+                // Dropping the handlers does not leave half of them behind.
+                Err(e) => {
+                    return Err(CuError::new_with_cause(
+                        "Could not install the signal handlers",
+                        e,
+                    ))
+                }
+            }
+        }
+        Ok(handlers)
+    }
+}
+
+/// The signal handlers of a StopFlag, they are removed when this is dropped.
+pub struct SignalHandlers {
+    ids: Vec<SigId>,
+    /// Raised when dropped to give the signals their default action back.
+    released: Arc<AtomicBool>,
+}
+
+impl Drop for SignalHandlers {
+    fn drop(&mut self) {
+        for id in self.ids.drain(..) {
+            signal_hook::low_level::unregister(id);
+        }
+        self.released.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_flag() {
+        let flag = StopFlag::new();
+        let shared = flag.clone();
+        assert!(!flag.is_stopped());
+        shared.stop();
+        assert!(flag.is_stopped());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_raises_the_flag() {
+        use signal_hook::consts::SIGUSR2;
+
+        // A signal nothing else in the tests uses rather than the ctrl-c of the test runner.
+        let flag = StopFlag::new();
+        let _handlers = flag.install_handlers_for(&[SIGUSR2]).unwrap();
+        signal_hook::low_level::raise(SIGUSR2).unwrap();
+        assert!(flag.is_stopped());
+    }
+}
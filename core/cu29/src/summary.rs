@@ -0,0 +1,456 @@
+//! The overview of a run written at the end of its log: what every task produced and how long it took,
+//! so a postmortem can start with it instead of a full scan of the copper lists.
+
+use crate::copperlist::CuListDumper;
+use crate::cutask::{CuMsg, CuMsgPayload};
+use crate::monitoring::{CuDurationStatistics, CuTaskStats};
+use crate::{CuError, CuResult};
+use bincode::enc::write::SizeWriter;
+use bincode::enc::EncoderImpl;
+use bincode::{Decode, Encode};
+use cu29_clock::{CuDuration, CuTime, OptionCuTime};
+use cu29_traits::{wire_config, UnifiedLogType, WriteStream};
+use cu29_unifiedlog::{stream_write, UnifiedLoggerWrite};
+use serde_derive::Serialize;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// The longest process call the timing percentiles can tell apart, longer ones are counted as this.
+const MAX_PROCESS_DURATION: CuDuration = CuDuration(10_000_000_000);
+
+/// What a task produced over a run.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub struct CuChannelSummary {
+    /// The task producing the messages.
+    pub task: String,
+    pub msg_type: String,
+    pub count: u64,
+    /// The messages without a payload, ie. when the task had nothing to send or errored out.
+    pub dropped: u64,
+    /// The sizes of the encoded payloads in bytes.
+    pub min_size: u64,
+    pub mean_size: u64,
+    pub max_size: u64,
+    /// The mean rate of the messages over the run.
+    pub rate_hz: f64,
+    /// The sum of the sizes of the encoded payloads, what the channel costs to the log.
+    pub total_bytes: u64,
+    /// The mean number of bytes encoded per second over the run.
+    pub bytes_per_s: f64,
+}
+
+/// How long the process calls of a task took over a run.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub struct CuTaskTimings {
+    pub task: String,
+    pub count: u64,
+    pub min: CuDuration,
+    pub p50: CuDuration,
+    pub p90: CuDuration,
+    pub p99: CuDuration,
+    pub max: CuDuration,
+}
+
+/// The memory charged to a task over a run, see cu29::memory.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub struct CuTaskMemorySummary {
+    pub task: String,
+    /// At the end of the run.
+    pub resident_bytes: i64,
+    pub peak_resident_bytes: i64,
+    /// At the end of the run.
+    pub pool_handles: i64,
+    pub peak_pool_handles: i64,
+}
+
+/// The overview of a run, written in the log when the application is dropped.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub struct CuLogSummary {
+    pub copper_lists: u64,
+    /// From the start of the first iteration to the start of the last one.
+    pub duration: CuDuration,
+    pub channels: Vec<CuChannelSummary>,
+    pub tasks: Vec<CuTaskTimings>,
+    /// Empty without memory_attribution in the runtime section of the config.
+    pub memory: Vec<CuTaskMemorySummary>,
+}
+
+impl CuLogSummary {
+    pub fn to_json(&self) -> CuResult<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| CuError::new_with_cause("Could not serialize the log summary", e))
+    }
+}
+
+impl fmt::Display for CuLogSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} copper lists over {}.",
+            self.copper_lists, self.duration
+        )?;
+        writeln!(f, "Channels:")?;
+        let total_bytes: u64 = self.channels.iter().map(|c| c.total_bytes).sum();
+        for channel in &self.channels {
+            let share = match total_bytes {
+                0 => 0.0,
+                total => channel.total_bytes as f64 * 100.0 / total as f64,
+            };
+            writeln!(
+                f,
+                "  {} ({}): {} messages at {:.2} Hz, {} dropped, size {}/{}/{} bytes (min/mean/max), \
+                 {:.1} KiB/s ({:.1}% of the bytes)",
+                channel.task,
+                channel.msg_type,
+                channel.count,
+                channel.rate_hz,
+                channel.dropped,
+                channel.min_size,
+                channel.mean_size,
+                channel.max_size,
+                channel.bytes_per_s / 1024.0,
+                share
+            )?;
+        }
+        writeln!(f, "Tasks:")?;
+        for task in &self.tasks {
+            writeln!(
+                f,
+                "  {}: {} calls, min {} p50 {} p90 {} p99 {} max {}",
+                task.task, task.count, task.min, task.p50, task.p90, task.p99, task.max
+            )?;
+        }
+        if !self.memory.is_empty() {
+            writeln!(f, "Memory:")?;
+        }
+        for task in &self.memory {
+            writeln!(
+                f,
+                "  {}: {} bytes resident (peak {}), {} pool handles (peak {})",
+                task.task,
+                task.resident_bytes,
+                task.peak_resident_bytes,
+                task.pool_handles,
+                task.peak_pool_handles
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates the statistics of the messages of one task without allocating.
+#[derive(Debug, Clone)]
+pub struct CuChannelStats {
+    task: String,
+    msg_type: String,
+    count: u64,
+    dropped: u64,
+    min_size: u64,
+    total_size: u64,
+    max_size: u64,
+    first: OptionCuTime,
+    last: OptionCuTime,
+    process: CuDurationStatistics,
+}
+
+impl CuChannelStats {
+    pub fn new(task: &str, msg_type: &str) -> Self {
+        CuChannelStats {
+            task: task.to_string(),
+            msg_type: msg_type.to_string(),
+            count: 0,
+            dropped: 0,
+            min_size: u64::MAX,
+            total_size: 0,
+            max_size: 0,
+            first: OptionCuTime::none(),
+            last: OptionCuTime::none(),
+            process: CuDurationStatistics::new(MAX_PROCESS_DURATION),
+        }
+    }
+
+    pub fn record<T: CuMsgPayload>(&mut self, msg: &CuMsg<T>) {
+        self.count += 1;
+        match msg.payload() {
+            Some(payload) => {
+                let mut encoder = EncoderImpl::new(SizeWriter::default(), wire_config());
+                let size = match payload.encode(&mut encoder) {
+                    Ok(()) => encoder.into_writer().bytes_written as u64,
+                    Err(_) => 0,
+                };
+                self.min_size = self.min_size.min(size);
+                self.total_size += size;
+                self.max_size = self.max_size.max(size);
+            }
+            None => self.dropped += 1,
+        }
+        let before: Option<CuTime> = msg.metadata.before_process.into();
+        let after: Option<CuTime> = msg.metadata.after_process.into();
+        if let Some(before) = before {
+            if self.first.is_none() {
+                self.first = before.into();
+            }
+            self.last = before.into();
+            if let Some(after) = after.filter(|after| *after >= before) {
+                self.process
+                    .record((after - before).min(MAX_PROCESS_DURATION));
+            }
+        }
+    }
+
+    fn summary(&self) -> CuChannelSummary {
+        let sized = self.count - self.dropped;
+        let first: Option<CuTime> = self.first.into();
+        let last: Option<CuTime> = self.last.into();
+        let (rate_hz, bytes_per_s) = match (first, last) {
+            (Some(first), Some(last)) if last > first => {
+                let span_s = (last - first).0 as f64 / 1_000_000_000.0;
+                (
+                    (self.count - 1) as f64 / span_s,
+                    self.total_size as f64 / span_s,
+                )
+            }
+            _ => (0.0, 0.0),
+        };
+        CuChannelSummary {
+            task: self.task.clone(),
+            msg_type: self.msg_type.clone(),
+            count: self.count,
+            dropped: self.dropped,
+            min_size: if sized > 0 { self.min_size } else { 0 },
+            mean_size: self.total_size.checked_div(sized).unwrap_or(0),
+            max_size: self.max_size,
+            rate_hz,
+            total_bytes: self.total_size,
+            bytes_per_s,
+        }
+    }
+
+    fn timings(&self) -> CuTaskTimings {
+        CuTaskTimings {
+            task: self.task.clone(),
+            count: self.process.len(),
+            min: self.process.min(),
+            p50: self.process.percentile(0.5),
+            p90: self.process.percentile(0.9),
+            p99: self.process.percentile(0.99),
+            max: self.process.max(),
+        }
+    }
+}
+
+/// Collects the statistics of every copper list the runtime logs, for the summary written at the end of the log.
+#[derive(Debug, Default)]
+pub struct CuSummaryCollector {
+    copper_lists: u64,
+    first: Option<CuTime>,
+    last: Option<CuTime>,
+    channels: Vec<CuChannelStats>,
+    /// The ids of the tasks by node id, and the node id of the task producing every channel.
+    tasks: Vec<String>,
+    channel_nodes: Vec<Option<usize>>,
+}
+
+impl CuSummaryCollector {
+    /// tasks are the ids of the tasks by node id, to find the task of every channel in update_task_stats.
+    pub fn new(tasks: Vec<String>) -> Self {
+        CuSummaryCollector {
+            tasks,
+            ..Default::default()
+        }
+    }
+
+    pub fn record<P: CuListDumper>(&mut self, msgs: &P) {
+        if self.channels.is_empty() {
+            // Only the first copper list allocates, to get the names of the channels.
+            self.channels = msgs
+                .dump_msgs()
+                .iter()
+                .map(|msg| CuChannelStats::new(&msg.task, &msg.msg_type))
+                .collect();
+            self.channel_nodes = self
+                .channels
+                .iter()
+                .map(|channel| self.tasks.iter().position(|task| *task == channel.task))
+                .collect();
+        }
+        self.copper_lists += 1;
+        if let Some(start) = msgs.start_time() {
+            self.first.get_or_insert(start);
+            self.last = Some(start);
+        }
+        msgs.record_stats(&mut self.channels);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.copper_lists == 0
+    }
+
+    /// Copies the number of bytes encoded from the outputs of every task in their statistics, indexed by node id.
+    pub fn update_task_stats(&self, stats: &mut [CuTaskStats]) {
+        for (channel, node) in self.channels.iter().zip(&self.channel_nodes) {
+            if let Some(stats) = node.and_then(|node| stats.get_mut(node)) {
+                stats.output_bytes = channel.total_size;
+            }
+        }
+    }
+
+    /// The memory charged to every task from their statistics, indexed by node id.
+    pub fn memory(&self, stats: &[CuTaskStats]) -> Vec<CuTaskMemorySummary> {
+        self.tasks
+            .iter()
+            .zip(stats)
+            .map(|(task, stats)| CuTaskMemorySummary {
+                task: task.clone(),
+                resident_bytes: stats.memory.resident_bytes,
+                peak_resident_bytes: stats.peak_resident_bytes,
+                pool_handles: stats.memory.pool_handles,
+                peak_pool_handles: stats.peak_pool_handles,
+            })
+            .collect()
+    }
+
+    pub fn summary(&self) -> CuLogSummary {
+        let duration = match (self.first, self.last) {
+            (Some(first), Some(last)) if last > first => last - first,
+            _ => CuDuration::default(),
+        };
+        CuLogSummary {
+            copper_lists: self.copper_lists,
+            duration,
+            channels: self.channels.iter().map(CuChannelStats::summary).collect(),
+            tasks: self.channels.iter().map(CuChannelStats::timings).collect(),
+            memory: Vec::new(),
+        }
+    }
+}
+
+/// Writes the summary in its own section of the log.
+pub fn write_summary(
+    logger: Arc<Mutex<UnifiedLoggerWrite>>,
+    summary: &CuLogSummary,
+) -> CuResult<()> {
+    let size = bincode::encode_to_vec(summary, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not encode the log summary", e))?
+        .len();
+    // Room for the header of the section.
+    let mut stream = stream_write(logger, UnifiedLogType::Summary, size + 64);
+    stream.log(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::copperlist::CuMsgDump;
+    use crate::dynmsg::DynCuMsg;
+    use crate::memory::CuTaskMemory;
+
+    #[derive(Debug)]
+    struct TwoTasks(CuMsg<u32>, CuMsg<Vec<u8>>);
+
+    impl CuListDumper for TwoTasks {
+        const SCHEMA: &'static str = "src:u32;sink:Vec<u8>";
+
+        fn dump_msgs(&self) -> Vec<CuMsgDump> {
+            vec![
+                CuMsgDump::from_msg("src", "u32", &self.0),
+                CuMsgDump::from_msg("sink", "Vec<u8>", &self.1),
+            ]
+        }
+
+        fn start_time(&self) -> Option<CuTime> {
+            self.0.metadata.before_process.into()
+        }
+
+        fn record_stats(&self, channels: &mut [CuChannelStats]) {
+            channels[0].record(&self.0);
+            channels[1].record(&self.1);
+        }
+
+        fn dyn_msgs(&self) -> Vec<(&'static str, DynCuMsg)> {
+            Vec::new()
+        }
+
+        fn encode_payload(&self, _index: usize) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    fn iteration(i: u64, size: Option<usize>) -> TwoTasks {
+        let start = CuTime::from(i * 10_000_000);
+        let mut src = CuMsg::new(Some(i as u32));
+        src.metadata.before_process = start.into();
+        src.metadata.after_process = (start + CuDuration(1000 * (i + 1))).into();
+        let mut sink = CuMsg::new(size.map(|size| vec![0u8; size]));
+        sink.metadata.before_process = (start + CuDuration(20_000)).into();
+        sink.metadata.after_process = (start + CuDuration(25_000)).into();
+        TwoTasks(src, sink)
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut collector = CuSummaryCollector::default();
+        assert!(collector.is_empty());
+        collector.record(&iteration(0, Some(10)));
+        collector.record(&iteration(1, None));
+        collector.record(&iteration(2, Some(200)));
+        let summary = collector.summary();
+
+        assert_eq!(summary.copper_lists, 3);
+        assert_eq!(summary.duration, CuDuration(20_000_000));
+        let sink = &summary.channels[1];
+        assert_eq!(sink.task, "sink");
+        assert_eq!((sink.count, sink.dropped), (3, 1));
+        // A Vec is encoded with its length first.
+        assert_eq!((sink.min_size, sink.max_size), (11, 201));
+        assert_eq!(sink.mean_size, (11 + 201) / 2);
+        assert!((summary.channels[0].rate_hz - 100.0).abs() < 0.01);
+        assert_eq!(sink.total_bytes, 212);
+        // 212 bytes over the 20ms between the first and last process calls.
+        assert!((sink.bytes_per_s - 10_600.0).abs() < 0.01);
+
+        let src = &summary.tasks[0];
+        assert_eq!(src.count, 3);
+        assert_eq!(src.min, CuDuration(1000));
+        // The histogram has 3 significant digits.
+        assert!(src.max.0.abs_diff(3000) <= 3);
+        assert!(summary.to_string().contains("sink (Vec<u8>): 3 messages"));
+        // Encoded in 1 byte each.
+        assert!(summary.to_string().contains("(98.6% of the bytes)"));
+
+        let mut collector = CuSummaryCollector::new(vec!["sink".to_string(), "src".to_string()]);
+        collector.record(&iteration(0, Some(10)));
+        let mut stats = vec![CuTaskStats::default(); 2];
+        collector.update_task_stats(&mut stats);
+        assert_eq!((stats[0].output_bytes, stats[1].output_bytes), (11, 1));
+        stats[1].record_memory(CuTaskMemory {
+            resident_bytes: 4096,
+            pool_handles: 2,
+        });
+        stats[1].record_memory(CuTaskMemory {
+            resident_bytes: 1024,
+            pool_handles: 1,
+        });
+        let mut with_memory = collector.summary();
+        with_memory.memory = collector.memory(&stats);
+        assert_eq!(
+            with_memory.memory[1],
+            CuTaskMemorySummary {
+                task: "src".to_string(),
+                resident_bytes: 1024,
+                peak_resident_bytes: 4096,
+                pool_handles: 1,
+                peak_pool_handles: 2,
+            }
+        );
+        assert!(with_memory
+            .to_string()
+            .contains("src: 1024 bytes resident (peak 4096), 1 pool handles (peak 2)"));
+        assert!(!summary.to_string().contains("Memory:"));
+
+        let encoded = bincode::encode_to_vec(&summary, wire_config()).unwrap();
+        let (decoded, _): (CuLogSummary, usize) =
+            bincode::decode_from_slice(&encoded, wire_config()).unwrap();
+        assert_eq!(decoded, summary);
+    }
+}
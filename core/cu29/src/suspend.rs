@@ -0,0 +1,100 @@
+//! Detection of the suspends of the machine between 2 iterations of the loop, ie. the lid of the laptop of a dev
+//! robot closed for lunch.
+//!
+//! Without it the first iteration after the resume would give every task a dt of several minutes. Instead `run`
+//! stops all the tasks, starts them again so the drivers reopen their devices, logs the gap with an annotation and
+//! resumes the loop as if it just started: the tasks get no dt on their next process call.
+//!
+//! A suspend is a jump of the robot clock between 2 iterations, or on Linux an increase of the time the machine
+//! spent suspended (CLOCK_BOOTTIME - CLOCK_MONOTONIC), larger than suspend_threshold_ms in the runtime section of
+//! the config (5 s by default).
+
+use crate::clock::{CuDuration, CuTime};
+
+/// The gap above which an iteration is considered as resumed from a suspend, if not set in the config.
+pub const DEFAULT_SUSPEND_THRESHOLD: CuDuration = CuDuration(5_000_000_000);
+
+/// The time the machine spent suspended since it booted.
+#[cfg(target_os = "linux")]
+fn suspended_ns() -> u64 {
+    let read = |clock| {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // Cannot fail with a valid pointer and those clocks.
+        unsafe { libc::clock_gettime(clock, &mut ts) };
+        ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+    };
+    read(libc::CLOCK_BOOTTIME).saturating_sub(read(libc::CLOCK_MONOTONIC))
+}
+
+/// Only the jumps of the robot clock are detected on the other platforms.
+#[cfg(not(target_os = "linux"))]
+fn suspended_ns() -> u64 {
+    0
+}
+
+/// Compares every iteration with the previous one, see check.
+#[derive(Debug)]
+pub struct CuSuspendDetector {
+    threshold: CuDuration,
+    /// The robot time and the time suspended at the previous iteration.
+    last: Option<(CuTime, u64)>,
+}
+
+impl CuSuspendDetector {
+    pub fn new(threshold: CuDuration) -> Self {
+        CuSuspendDetector {
+            threshold,
+            last: None,
+        }
+    }
+
+    /// How long the machine was suspended since the previous call, None if it was not.
+    pub fn check(&mut self, now: CuTime) -> Option<CuDuration> {
+        self.check_at(now, suspended_ns())
+    }
+
+    /// check with an explicit time suspended since the boot.
+    pub fn check_at(&mut self, now: CuTime, suspended: u64) -> Option<CuDuration> {
+        let last = self.last.replace((now, suspended));
+        let (last_time, last_suspended) = last?;
+        // The robot clock may or may not count the suspended time depending on its source.
+        let gap = now
+            .0
+            .saturating_sub(last_time.0)
+            .max(suspended.saturating_sub(last_suspended));
+        (gap > self.threshold.0).then_some(CuDuration(gap))
+    }
+
+    /// Starts over from now, ie. once the tasks are restarted.
+    pub fn rearm(&mut self, now: CuTime) {
+        self.last = Some((now, suspended_ns()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_suspend_detection() {
+        let mut detector = CuSuspendDetector::new(DEFAULT_SUSPEND_THRESHOLD);
+        assert_eq!(detector.check_at(CuTime::from(0), 10 * S), None);
+        assert_eq!(detector.check_at(CuTime::from(S), 10 * S), None);
+        // The monotonic clock stopped during the suspend, only the time suspended shows it.
+        assert_eq!(
+            detector.check_at(CuTime::from(2 * S), 70 * S),
+            Some(CuDuration(60 * S))
+        );
+        // The robot clock counted it.
+        assert_eq!(
+            detector.check_at(CuTime::from(300 * S), 70 * S),
+            Some(CuDuration(298 * S))
+        );
+        assert_eq!(detector.check_at(CuTime::from(304 * S), 70 * S), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! The support of the `#[copper_test]` harness of cu29_derive: a temporary log for the runtime under test and the
+//! messages of every iteration it ran, for the assertions of the test.
+
+use crate::copperlist::{CuListDump, CuMsgDump};
+use crate::{CuError, CuResult};
+use cu29_unifiedlog::{UnifiedLogger, UnifiedLoggerBuilder, UnifiedLoggerWrite};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// Small enough for the many runtimes of a test suite.
+const TEST_LOG_SIZE: usize = 4 * 1024 * 1024;
+
+/// A log in a temporary directory, removed when it is dropped.
+pub struct CuTestLog {
+    pub logger: Arc<Mutex<UnifiedLoggerWrite>>,
+    _dir: TempDir,
+}
+
+impl CuTestLog {
+    pub fn new(name: &str) -> CuResult<Self> {
+        let dir = tempfile::tempdir().map_err(|e| {
+            CuError::new_with_cause("Could not create the directory of the test log", e)
+        })?;
+        let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+            .write(true)
+            .create(true)
+            .file_base_name(&dir.path().join(format!("{}.copper", name)))
+            .preallocated_size(TEST_LOG_SIZE)
+            .build()
+            .map_err(|e| CuError::new_with_cause("Could not create the test log", e))?
+        else {
+            return Err("Could not create the test log.".into());
+        };
+        Ok(CuTestLog {
+            logger: Arc::new(Mutex::new(logger)),
+            _dir: dir,
+        })
+    }
+}
+
+/// The messages of every iteration a test ran, in order.
+#[derive(Debug, Default)]
+pub struct CuTestRun {
+    pub iterations: Vec<CuListDump>,
+}
+
+impl CuTestRun {
+    /// Records the messages of the last iteration, see dump_last_iteration of the application.
+    pub fn record(&mut self, iteration: Option<CuListDump>) {
+        self.iterations.extend(iteration);
+    }
+
+    pub fn len(&self) -> usize {
+        self.iterations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iterations.is_empty()
+    }
+
+    /// The message of a task at every iteration.
+    pub fn messages(&self, task: &str) -> Vec<&CuMsgDump> {
+        self.iterations
+            .iter()
+            .filter_map(|iteration| iteration.msgs.iter().find(|msg| msg.task == task))
+            .collect()
+    }
+
+    /// The payloads of a task at every iteration, in their Debug representation, None when it produced nothing.
+    pub fn outputs(&self, task: &str) -> Vec<Option<&str>> {
+        self.messages(task)
+            .into_iter()
+            .map(|msg| msg.payload.as_deref())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iteration(id: u32, payload: Option<&str>) -> CuListDump {
+        CuListDump {
+            id,
+            msgs: vec![CuMsgDump {
+                task: "imu".to_string(),
+                msg_type: "f32".to_string(),
+                payload: payload.map(str::to_string),
+                before_process: None,
+                after_process: None,
+                tov: None,
+                status: String::new(),
+                provenance: Vec::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let log = CuTestLog::new("test_run").unwrap();
+        assert!(log.logger.lock().is_ok());
+
+        let mut run = CuTestRun::default();
+        run.record(Some(iteration(0, Some("1.0"))));
+        run.record(None);
+        run.record(Some(iteration(1, None)));
+        assert_eq!(run.len(), 2);
+        assert_eq!(run.outputs("imu"), vec![Some("1.0"), None]);
+        assert!(run.outputs("gps").is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+//! The names of the threads of copper and what happens when one of them panics.
+//!
+//! Every thread of copper is named `cu:...`: `cu:main` for the thread running the runtime, `cu:logger` for the
+//! writer of the log, `cu:task:<name>` for the threads of the drivers. The panic hook installed by the runtime writes
+//! the name of the panicking thread, the task being processed and the backtrace in the structured log, so a crash in
+//! the field is diagnosed from the log alone. A panic in a spawned `cu:` thread then aborts the process: the robot
+//! does not keep on running with a dead driver.
+
+use crate::config::{ComponentConfig, Node, CORE_AFFINITY_KEY, PRIORITY_KEY};
+use cu29_log_derive::debug;
+use cu29_traits::{CuError, CuResult};
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::panic::PanicHookInfo;
+use std::sync::Once;
+use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
+
+/// The prefix of the names of the threads of copper.
+pub const THREAD_PREFIX: &str = "cu:";
+
+thread_local! {
+    /// The name given with name_current_thread, the std name of a thread cannot be changed after its start.
+    static THREAD_NAME: Cell<Option<&'static str>> = const { Cell::new(None) };
+    /// The task the runtime is running on this thread.
+    static CURRENT_TASK: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// The name of the thread of a driver of the task, ie. `cu:task:imu`.
+pub fn task_thread_name(task: &str) -> String {
+    format!("{}task:{}", THREAD_PREFIX, task)
+}
+
+/// Spawns a thread of a driver of the task, named with task_thread_name.
+pub fn spawn_task_thread<F, T>(task: &str, f: F) -> CuResult<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new()
+        .name(task_thread_name(task))
+        .spawn(f)
+        .map_err(|e| {
+            CuError::new_with_cause(
+                &format!("Could not start the thread of the task {}", task),
+                e,
+            )
+        })
+}
+
+/// Names the current thread, ie. the main thread which was not spawned by copper.
+/// The name is also given to the OS on Linux, cut to 15 bytes, for ps and the debuggers.
+pub fn name_current_thread(name: &'static str) {
+    THREAD_NAME.set(Some(name));
+    #[cfg(target_os = "linux")]
+    {
+        let mut os_name = [0u8; 16];
+        let len = name.len().min(15);
+        os_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        unsafe {
+            libc::prctl(
+                libc::PR_SET_NAME,
+                os_name.as_ptr() as libc::c_ulong,
+                0,
+                0,
+                0,
+            );
+        }
+    }
+}
+
+/// The name of the current thread, see name_current_thread.
+pub fn current_thread_name() -> String {
+    THREAD_NAME
+        .get()
+        .map(str::to_string)
+        .or_else(|| std::thread::current().name().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", std::thread::current().id()))
+}
+
+/// The current thread and the task it is running, ie. `cu:main (imu)`, for the diagnostics.
+pub fn describe_current_thread() -> String {
+    match current_task() {
+        Some(task) => format!("{} ({})", current_thread_name(), task),
+        None => current_thread_name(),
+    }
+}
+
+/// Sets the task the runtime is running on the current thread, None between the tasks.
+pub fn set_current_task(task: Option<&'static str>) {
+    CURRENT_TASK.set(task);
+}
+
+/// The task the runtime is running on the current thread.
+pub fn current_task() -> Option<&'static str> {
+    CURRENT_TASK.get()
+}
+
+/// How the threads running a task are scheduled, from the priority and the core_affinity of its node:
+///
+/// ```ron
+/// (id: "pid", type: "a::Pid", priority: 80, core_affinity: [3]),
+/// ```
+///
+/// With parallel_workers, the runtime processes the task on a thread of its own scheduled once at startup, see
+/// cu29::parallel. Without, the tasks run on cu:main which keeps its scheduling. A driver running its own thread,
+/// like an async task, applies it to its thread with from_config. The priority is a real time one (SCHED_FIFO, 1 to 99) so the control loops are not
+/// preempted by the logger or the perception: it needs CAP_SYS_NICE or an rtprio limit, the runtime fails to start
+/// if it cannot set it. Linux only.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CuThreadScheduling {
+    pub priority: Option<i32>,
+    pub core_affinity: Vec<usize>,
+}
+
+impl CuThreadScheduling {
+    /// The scheduling of the task of the node, None if it has neither a priority nor a core affinity.
+    pub fn from_node(node: &Node) -> CuResult<Option<Self>> {
+        Self::new(node.get_priority(), node.get_core_affinity().to_vec())
+    }
+
+    /// The scheduling given by the runtime in the config of a task, see PRIORITY_KEY and CORE_AFFINITY_KEY.
+    pub fn from_config(config: Option<&ComponentConfig>) -> CuResult<Option<Self>> {
+        let Some(config) = config else {
+            return Ok(None);
+        };
+        let core_affinity = match config.get::<String>(CORE_AFFINITY_KEY) {
+            Some(cores) => cores
+                .split(',')
+                .map(|core| {
+                    core.trim().parse::<usize>().map_err(|e| {
+                        CuError::new_with_cause(&format!("Invalid core '{}'", core), e)
+                    })
+                })
+                .collect::<CuResult<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        Self::new(config.get::<i32>(PRIORITY_KEY), core_affinity)
+    }
+
+    fn new(priority: Option<i32>, core_affinity: Vec<usize>) -> CuResult<Option<Self>> {
+        if priority.is_none() && core_affinity.is_empty() {
+            return Ok(None);
+        }
+        if let Some(priority) = priority.filter(|priority| !(1..=99).contains(priority)) {
+            return Err(CuError::from(format!(
+                "Invalid priority {}, expected a real time priority from 1 to 99.",
+                priority
+            )));
+        }
+        Ok(Some(CuThreadScheduling {
+            priority,
+            core_affinity,
+        }))
+    }
+
+    /// Applies the scheduling to the current thread until the guard is dropped.
+    pub fn enter(&self) -> CuResult<CuSchedulingGuard> {
+        let mut guard = CuSchedulingGuard::default();
+        #[cfg(target_os = "linux")]
+        {
+            if !self.core_affinity.is_empty() {
+                let previous = sched::affinity()?;
+                sched::set_affinity(&sched::cores(&self.core_affinity)?)?;
+                guard.affinity = Some(previous);
+            }
+            if let Some(priority) = self.priority {
+                let previous = sched::policy()?;
+                sched::set_policy(libc::SCHED_FIFO, priority)?;
+                guard.policy = Some(previous);
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err("Thread priority and core affinity are only supported on Linux.".into());
+        #[allow(unreachable_code)]
+        Ok(guard)
+    }
+
+    /// Applies the scheduling to the current thread for good, ie. at the start of the thread of a driver.
+    pub fn apply(&self) -> CuResult<()> {
+        self.enter()?.keep();
+        Ok(())
+    }
+}
+
+/// Restores the scheduling of the thread when dropped, see CuThreadScheduling::enter.
+#[derive(Default)]
+pub struct CuSchedulingGuard {
+    #[cfg(target_os = "linux")]
+    affinity: Option<libc::cpu_set_t>,
+    #[cfg(target_os = "linux")]
+    policy: Option<(i32, i32)>,
+}
+
+impl CuSchedulingGuard {
+    fn keep(mut self) {
+        #[cfg(target_os = "linux")]
+        {
+            self.affinity = None;
+            self.policy = None;
+        }
+    }
+}
+
+impl Drop for CuSchedulingGuard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some((policy, priority)) = self.policy.take() {
+                let _ = sched::set_policy(policy, priority);
+            }
+            if let Some(affinity) = self.affinity.take() {
+                let _ = sched::set_affinity(&affinity);
+            }
+        }
+    }
+}
+
+/// The scheduling of the calling thread, the pid 0 of the sched_* calls.
+#[cfg(target_os = "linux")]
+pub(crate) mod sched {
+    use cu29_traits::{CuError, CuResult};
+    use std::mem::{size_of, zeroed};
+
+    fn os_error(what: &str) -> CuError {
+        CuError::new_with_cause(
+            &format!("Could not {} of the thread", what),
+            std::io::Error::last_os_error(),
+        )
+    }
+
+    pub fn cores(cores: &[usize]) -> CuResult<libc::cpu_set_t> {
+        let mut set: libc::cpu_set_t = unsafe { zeroed() };
+        for &core in cores {
+            if core >= libc::CPU_SETSIZE as usize {
+                return Err(CuError::from(format!("Invalid core {}.", core)));
+            }
+            unsafe { libc::CPU_SET(core, &mut set) };
+        }
+        Ok(set)
+    }
+
+    pub fn affinity() -> CuResult<libc::cpu_set_t> {
+        let mut set: libc::cpu_set_t = unsafe { zeroed() };
+        if unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set) } != 0 {
+            return Err(os_error("read the core affinity"));
+        }
+        Ok(set)
+    }
+
+    pub fn set_affinity(set: &libc::cpu_set_t) -> CuResult<()> {
+        if unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), set) } != 0 {
+            return Err(os_error("set the core affinity"));
+        }
+        Ok(())
+    }
+
+    /// The policy and the priority.
+    pub fn policy() -> CuResult<(i32, i32)> {
+        let policy = unsafe { libc::sched_getscheduler(0) };
+        let mut param: libc::sched_param = unsafe { zeroed() };
+        if policy < 0 || unsafe { libc::sched_getparam(0, &mut param) } != 0 {
+            return Err(os_error("read the scheduling policy"));
+        }
+        Ok((policy, param.sched_priority))
+    }
+
+    pub fn set_policy(policy: i32, priority: i32) -> CuResult<()> {
+        let param = libc::sched_param {
+            sched_priority: priority,
+        };
+        if unsafe { libc::sched_setscheduler(0, policy, &param) } != 0 {
+            return Err(os_error("set the priority"));
+        }
+        Ok(())
+    }
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown".to_string());
+    match info.location() {
+        Some(location) => format!("{} at {}", payload, location),
+        None => payload,
+    }
+}
+
+/// Installs the panic hook of copper, once per process. The previous hook still runs after it.
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let thread = current_thread_name();
+            // Only the threads of copper, the ones of the application are left to their own panic handling.
+            let in_copper = THREAD_NAME.get().is_some()
+                || std::thread::current()
+                    .name()
+                    .is_some_and(|name| name.starts_with(THREAD_PREFIX));
+            if in_copper {
+                log_panic(&thread, info);
+            }
+            previous(info);
+            let spawned = THREAD_NAME.get().is_none();
+            if in_copper && spawned {
+                eprintln!("The copper thread {} panicked, aborting.", thread);
+                std::process::abort();
+            }
+        }));
+    });
+}
+
+fn log_panic(thread: &str, info: &PanicHookInfo) {
+    // The panicking thread may hold the log writer, it cannot log then.
+    if !cu29_log_runtime::writer_available(Duration::from_millis(100)) {
+        return;
+    }
+    let message = panic_message(info);
+    let task = current_task().unwrap_or("none");
+    let backtrace = Backtrace::force_capture().to_string();
+    debug!(
+        "Panic: the thread '{}' panicked while running the task '{}': {}\n{}",
+        thread, task, message, backtrace
+    );
+    cu29_log_runtime::flush_log();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_names() {
+        assert_eq!(task_thread_name("imu"), "cu:task:imu");
+        let handle = spawn_task_thread("imu", || {
+            set_current_task(Some("imu"));
+            (current_thread_name(), current_task())
+        })
+        .unwrap();
+        assert_eq!(
+            handle.join().unwrap(),
+            ("cu:task:imu".to_string(), Some("imu"))
+        );
+        assert_eq!(current_task(), None);
+        set_current_task(Some("pid"));
+        assert!(describe_current_thread().ends_with(" (pid)"));
+        set_current_task(None);
+
+        std::thread::spawn(|| {
+            name_current_thread("cu:main");
+            assert_eq!(current_thread_name(), "cu:main");
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_thread_scheduling() {
+        let mut config = ComponentConfig::new();
+        assert_eq!(
+            CuThreadScheduling::from_config(Some(&config)).unwrap(),
+            None
+        );
+        config.set(CORE_AFFINITY_KEY, "0".to_string());
+        let scheduling = CuThreadScheduling::from_config(Some(&config))
+            .unwrap()
+            .unwrap();
+        assert_eq!(scheduling.core_affinity, vec![0]);
+        config.set(PRIORITY_KEY, 100);
+        assert!(CuThreadScheduling::from_config(Some(&config)).is_err());
+
+        #[cfg(target_os = "linux")]
+        std::thread::spawn(move || {
+            let all_cores = sched::affinity().unwrap();
+            {
+                let _guard = scheduling.enter().unwrap();
+                let pinned = sched::affinity().unwrap();
+                assert!(unsafe { libc::CPU_ISSET(0, &pinned) });
+                assert_eq!(unsafe { libc::CPU_COUNT(&pinned) }, 1);
+            }
+            let restored = sched::affinity().unwrap();
+            assert_eq!(unsafe { libc::CPU_COUNT(&restored) }, unsafe {
+                libc::CPU_COUNT(&all_cores)
+            });
+        })
+        .join()
+        .unwrap();
+    }
+}
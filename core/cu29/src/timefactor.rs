@@ -0,0 +1,182 @@
+//! The speed of the simulated and replayed time against the wall clock, changed while they run: 1 follows the wall
+//! clock, 0.25 is 4 times slower to examine a section of a log, 4 is 4 times faster to skim it and 0 pauses.
+//!
+//! ```ignore
+//! let factor = CuTimeFactor::default();
+//! application.copper_runtime.pace_with(factor.clone());
+//! // From a UI or a remote command, on another thread.
+//! factor.set(0.25)?;
+//! ```
+//!
+//! The runtime holds back the robot clock of a replay (see CuRuntime::set_replay_time) and of a simulation stepped
+//! by the host application (step in library mode) to advance it at the factor of the wall clock. The tasks read
+//! their time from the robot clock and their rates follow it (see cu29::multirate), so they all slow down, speed up
+//! or pause together. A change applies from the time reached, it does not shift the time already played.
+
+use crate::clock::CuTime;
+use crate::{CuError, CuResult};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The fastest the time can go, 100 times the wall clock.
+pub const MAX_TIME_FACTOR: f64 = 100.0;
+
+/// How often a wait checks for a change of the factor, ie. the end of a pause.
+const POLL_PERIOD: Duration = Duration::from_millis(10);
+
+/// The factor applied to the wall clock, shared between the thread driving the time and the ones changing it.
+#[derive(Debug, Clone)]
+pub struct CuTimeFactor(Arc<AtomicU64>);
+
+impl Default for CuTimeFactor {
+    fn default() -> Self {
+        CuTimeFactor(Arc::new(AtomicU64::new(1.0f64.to_bits())))
+    }
+}
+
+impl CuTimeFactor {
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Sets the factor, from 0 (paused) to MAX_TIME_FACTOR.
+    pub fn set(&self, factor: f64) -> CuResult<()> {
+        if !(0.0..=MAX_TIME_FACTOR).contains(&factor) {
+            return Err(CuError::from(format!(
+                "Invalid time factor {}, it must be between 0 (paused) and {}.",
+                factor, MAX_TIME_FACTOR
+            )));
+        }
+        self.0.store(factor.to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn pause(&self) {
+        self.0.store(0.0f64.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.get() == 0.0
+    }
+}
+
+/// Holds back the simulated or replayed time so it advances at the factor of the wall clock.
+pub struct CuTimePacer {
+    factor: CuTimeFactor,
+    /// The instant on the wall clock and the simulated time when the factor last changed, with the factor since.
+    anchor: Option<(Instant, CuTime, f64)>,
+    /// The simulated time of the last wait.
+    reached: Option<CuTime>,
+}
+
+impl CuTimePacer {
+    pub fn new(factor: CuTimeFactor) -> Self {
+        CuTimePacer {
+            factor,
+            anchor: None,
+            reached: None,
+        }
+    }
+
+    pub fn factor(&self) -> &CuTimeFactor {
+        &self.factor
+    }
+
+    /// Starts again from the next wait, ie. after seeking in a log.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+        self.reached = None;
+    }
+
+    /// Waits until the simulated time can reach time. The first wait, and the first one after the time went back,
+    /// returns right away and starts the pacing.
+    pub fn wait_until(&mut self, time: CuTime) {
+        if self.reached.is_none_or(|reached| time < reached) {
+            self.reset();
+            self.reached = Some(time);
+        }
+        loop {
+            let factor = self.factor.get();
+            let now = Instant::now();
+            match self.anchor {
+                Some((wall, simulated, anchored)) if anchored == factor && factor > 0.0 => {
+                    let offset = time.0.saturating_sub(simulated.0) as f64 / factor;
+                    let due = wall + Duration::from_nanos(offset as u64);
+                    if due <= now {
+                        break;
+                    }
+                    thread::sleep((due - now).min(POLL_PERIOD));
+                }
+                Some((_, _, anchored)) if anchored == factor => thread::sleep(POLL_PERIOD),
+                anchor => {
+                    // The time advanced at the previous factor up to now.
+                    let simulated = match anchor {
+                        Some((wall, simulated, anchored)) => {
+                            let elapsed = now.duration_since(wall).as_nanos() as f64 * anchored;
+                            CuTime::from(simulated.0 + elapsed as u64).min(time)
+                        }
+                        None => self.reached.unwrap_or(time),
+                    };
+                    self.anchor = Some((now, simulated, factor));
+                }
+            }
+        }
+        self.reached = Some(time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> CuTime {
+        CuTime::from(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn test_time_factor() {
+        let factor = CuTimeFactor::default();
+        assert_eq!(factor.get(), 1.0);
+        assert!(factor.set(-1.0).is_err());
+        assert!(factor.set(f64::NAN).is_err());
+        assert!(factor.set(1000.0).is_err());
+        factor.set(0.25).unwrap();
+        assert_eq!(factor.clone().get(), 0.25);
+        factor.pause();
+        assert!(factor.is_paused());
+    }
+
+    #[test]
+    fn test_pacing() {
+        let factor = CuTimeFactor::default();
+        factor.set(10.0).unwrap();
+        let mut pacer = CuTimePacer::new(factor.clone());
+        // The first time anchors the pacing and returns right away, it would be 1s late otherwise.
+        let started = Instant::now();
+        pacer.wait_until(ms(10_000));
+        assert!(started.elapsed() < Duration::from_millis(500));
+        // 200ms 10 times faster.
+        pacer.wait_until(ms(10_200));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        // Paused for 50ms, then 4 times faster.
+        factor.pause();
+        let resume = factor.clone();
+        let resumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            resume.set(4.0).unwrap();
+        });
+        let paused = Instant::now();
+        pacer.wait_until(ms(10_300));
+        assert!(paused.elapsed() >= Duration::from_millis(70));
+        resumer.join().unwrap();
+
+        // Back in time, ie. a seek, starts again from there: 40ms 4 times faster.
+        let rewound = Instant::now();
+        pacer.wait_until(ms(0));
+        pacer.wait_until(ms(40));
+        assert!(rewound.elapsed() >= Duration::from_millis(10));
+    }
+}
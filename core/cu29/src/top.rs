@@ -0,0 +1,220 @@
+use clap::Parser;
+use cu29::metrics::{CuMetricsReader, CuMetricsSnapshot};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The metrics file of the application, metrics_path in the runtime section of its config
+    #[clap(value_parser)]
+    metrics: PathBuf,
+    /// Refresh period in milliseconds
+    #[clap(long, default_value_t = 1000)]
+    interval_ms: u64,
+    /// Print the metrics once and exit
+    #[clap(long)]
+    once: bool,
+}
+
+fn micros(ns: u64) -> String {
+    format!("{:.1}", ns as f64 / 1000.0)
+}
+
+fn render(snapshot: &CuMetricsSnapshot, earlier: &CuMetricsSnapshot) -> String {
+    let mut out = String::new();
+    let running = snapshot.publications != earlier.publications;
+    writeln!(
+        out,
+        "pid {}, {} iterations, robot time {}{}",
+        snapshot.pid,
+        snapshot.publications,
+        snapshot.time,
+        if running { "" } else { ", not running" }
+    )
+    .unwrap();
+    if snapshot.log_errors.count > 0 {
+        writeln!(
+            out,
+            "{} errors logged, {} of them suppressed as repeats",
+            snapshot.log_errors.count, snapshot.log_errors.suppressed
+        )
+        .unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "{:<24} {:>9} {:>6} {:>9} {:>9} {:>9} {:>9} {:>10} {:>7} {:>9}",
+        "TASK",
+        "RATE (Hz)",
+        "CPU %",
+        "LAST (us)",
+        "MEAN (us)",
+        "MAX (us)",
+        "KiB/s",
+        "CALLS",
+        "ERRORS",
+        "HEALTH"
+    )
+    .unwrap();
+    for (i, task) in snapshot.tasks.iter().enumerate() {
+        let rate = task
+            .rate_hz()
+            .map(|rate| format!("{:.1}", rate))
+            .unwrap_or_else(|| "-".to_string());
+        let load = snapshot
+            .cpu_load(earlier, i)
+            .map(|load| format!("{:.1}", load * 100.0))
+            .unwrap_or_else(|| "-".to_string());
+        let bandwidth = snapshot
+            .bandwidth(earlier, i)
+            .map(|bandwidth| format!("{:.1}", bandwidth / 1024.0))
+            .unwrap_or_else(|| "-".to_string());
+        let mean = task
+            .mean_duration_ns()
+            .map(micros)
+            .unwrap_or_else(|| "-".to_string());
+        writeln!(
+            out,
+            "{:<24} {:>9} {:>6} {:>9} {:>9} {:>9} {:>9} {:>10} {:>7} {:>9}",
+            task.name,
+            rate,
+            load,
+            micros(task.last_duration_ns),
+            mean,
+            micros(task.max_duration_ns),
+            bandwidth,
+            task.process_count,
+            task.error_count,
+            format!("{:?}", task.health)
+        )
+        .unwrap();
+    }
+
+    let errors: Vec<_> = snapshot
+        .tasks
+        .iter()
+        .filter_map(|task| Some((&task.name, task.last_error.as_ref()?)))
+        .collect();
+    if !errors.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "{:<24} LAST ERROR", "TASK").unwrap();
+        for (name, error) in errors {
+            writeln!(out, "{:<24} {}", name, error).unwrap();
+        }
+    }
+
+    // Only with memory_attribution in the runtime section of the config.
+    if snapshot
+        .tasks
+        .iter()
+        .any(|task| task.resident_bytes != 0 || task.pool_handles != 0)
+    {
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "{:<24} {:>12} {:>12}",
+            "TASK", "RESIDENT KiB", "POOL HANDLES"
+        )
+        .unwrap();
+        for task in &snapshot.tasks {
+            writeln!(
+                out,
+                "{:<24} {:>12.1} {:>12}",
+                task.name,
+                task.resident_bytes as f64 / 1024.0,
+                task.pool_handles
+            )
+            .unwrap();
+        }
+    }
+
+    if !snapshot.pools.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "{:<32} {:>10} {:>10} {:>10} {:>10}",
+            "POOL", "AVAILABLE", "CAPACITY", "BUF LEN", "EXHAUSTED"
+        )
+        .unwrap();
+        for pool in &snapshot.pools {
+            writeln!(
+                out,
+                "{:<32} {:>10} {:>10} {:>10} {:>10}",
+                pool.name, pool.available, pool.capacity, pool.buffer_len, pool.exhausted
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+
+/// Draws over the previous screen instead of clearing it first, so it does not flicker.
+fn redraw(screen: &str) {
+    let mut out = String::from("\x1b[H");
+    for line in screen.lines() {
+        out.push_str(line);
+        out.push_str("\x1b[K\n");
+    }
+    out.push_str("\x1b[J");
+    print!("{}", out);
+}
+
+/// Waits for the application to create its metrics file.
+fn attach(path: &Path, interval: Duration, once: bool) -> CuMetricsReader {
+    let mut waiting = false;
+    loop {
+        match CuMetricsReader::open(path) {
+            Ok(reader) => return reader,
+            Err(error) if once => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+            Err(error) => {
+                if !waiting {
+                    eprintln!("{}, waiting for the application...", error);
+                    waiting = true;
+                }
+                sleep(interval);
+            }
+        }
+    }
+}
+
+/// Shows the live rates, execution times and health of the tasks of a running application from its metrics file,
+/// refreshed in place like top.
+fn main() {
+    let args = Args::parse();
+    let interval = Duration::from_millis(args.interval_ms);
+    let mut reader = attach(&args.metrics, interval, args.once);
+    let mut earlier = reader.snapshot();
+    if !args.once {
+        print!("\x1b[2J");
+    }
+    loop {
+        sleep(interval);
+        let mut snapshot = reader.snapshot();
+        if snapshot.publications == earlier.publications {
+            // The application may have been restarted with a new file.
+            if let Ok(restarted) = CuMetricsReader::open(&args.metrics) {
+                let restarted_snapshot = restarted.snapshot();
+                if restarted_snapshot.pid != snapshot.pid
+                    || restarted_snapshot.publications != snapshot.publications
+                {
+                    reader = restarted;
+                    earlier = restarted_snapshot.clone();
+                    snapshot = restarted_snapshot;
+                }
+            }
+        }
+        let screen = render(&snapshot, &earlier);
+        if args.once {
+            print!("{}", screen);
+            return;
+        }
+        redraw(&screen);
+        earlier = snapshot;
+    }
+}
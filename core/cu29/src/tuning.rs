@@ -0,0 +1,169 @@
+//! Live tuning: the parameters of the tasks changed while the application runs, ie. the gains of a controller
+//! tuned by an engineer during a test drive.
+//!
+//! Every change is recorded in its own section of the log with its time, the old and new values and who made it,
+//! so a replay of the log applies them again at the same time of the robot clock, see `CuReplay` in cu29_export.
+//!
+//! The tasks read the new value with `ctx.param` on their next process call. A change is a mutation of the task
+//! named by the key of the parameter, so it is checked against the permissions of the task, see
+//! [crate::permissions].
+//!
+//! A task can request a change itself with `ctx.request_param`, ie. a derating policy lowering the rate of a camera
+//! when the robot heats up. The runtime applies the requests at the end of the copper list and records them like the
+//! other changes with the requesting task as their origin. They are trusted like the config and not checked against
+//! the permissions.
+
+use crate::clock::CuTime;
+use crate::config::Value;
+use crate::{CuError, CuResult};
+use bincode::{Decode, Encode};
+use cu29_log_derive::debug;
+use cu29_traits::{wire_config, UnifiedLogType, WriteStream};
+use cu29_unifiedlog::{stream_write, UnifiedLoggerWrite};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A change of a parameter of a task, the values are written in RON.
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct CuParamChange {
+    pub time: CuTime,
+    pub task: String,
+    pub key: String,
+    /// None if the parameter was not set.
+    pub old: Option<String>,
+    /// None if the parameter is removed.
+    pub new: Option<String>,
+    /// Who made the change, ie. the holder of the role token.
+    pub origin: String,
+}
+
+impl CuParamChange {
+    pub fn old_value(&self) -> CuResult<Option<Value>> {
+        self.old.as_deref().map(Value::from_ron).transpose()
+    }
+
+    pub fn new_value(&self) -> CuResult<Option<Value>> {
+        self.new.as_deref().map(Value::from_ron).transpose()
+    }
+
+    /// The change undoing this one, to replay the log backward.
+    pub fn reverted(&self) -> Self {
+        CuParamChange {
+            old: self.new.clone(),
+            new: self.old.clone(),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for CuParamChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}.{}: {} -> {} by {}",
+            self.time,
+            self.task,
+            self.key,
+            self.old.as_deref().unwrap_or("unset"),
+            self.new.as_deref().unwrap_or("unset"),
+            self.origin
+        )
+    }
+}
+
+/// A change of a parameter requested by a task, see CuContext::request_param.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuParamRequest {
+    /// The task making the request.
+    pub origin: String,
+    pub task: String,
+    pub key: String,
+    pub value: Value,
+}
+
+/// The changes requested by the tasks during a copper list, applied by the runtime at its end.
+#[derive(Debug, Default)]
+pub struct CuParamRequests(Mutex<Vec<CuParamRequest>>);
+
+impl CuParamRequests {
+    pub fn push(&self, request: CuParamRequest) {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(request);
+    }
+
+    /// The requests in the order they were made, emptying the queue.
+    pub fn take(&self) -> Vec<CuParamRequest> {
+        std::mem::take(
+            &mut *self
+                .0
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+    }
+}
+
+/// Writes the change in its own section of the log, like the annotations, so it is on disk right away.
+pub fn log_param_change(
+    logger: &Arc<Mutex<UnifiedLoggerWrite>>,
+    change: &CuParamChange,
+) -> CuResult<()> {
+    let size = bincode::encode_to_vec(change, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not encode the parameter change", e))?
+        .len();
+    let mut stream = stream_write(logger.clone(), UnifiedLogType::ParamChange, size + 64);
+    stream.log(change)?;
+    debug!(
+        "Tuning: {}.{} changed to {} by {}.",
+        change.task.as_str(),
+        change.key.as_str(),
+        change.new.as_deref().unwrap_or("unset"),
+        change.origin.as_str()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param_change() {
+        let change = CuParamChange {
+            time: CuTime::from(1_000_000_000u64),
+            task: "pid".to_string(),
+            key: "kp".to_string(),
+            old: None,
+            new: Some(Value::from(2.5).to_ron().unwrap()),
+            origin: "alice".to_string(),
+        };
+        assert_eq!(change.old_value().unwrap(), None);
+        assert_eq!(change.new_value().unwrap(), Some(Value::from(2.5)));
+        assert_eq!(change.to_string(), "1.000 s pid.kp: unset -> 2.5 by alice");
+        let reverted = change.reverted();
+        assert_eq!(reverted.old_value().unwrap(), Some(Value::from(2.5)));
+        assert_eq!(reverted.new_value().unwrap(), None);
+
+        let encoded = bincode::encode_to_vec(&change, wire_config()).unwrap();
+        let (decoded, _): (CuParamChange, usize) =
+            bincode::decode_from_slice(&encoded, wire_config()).unwrap();
+        assert_eq!(decoded, change);
+    }
+
+    #[test]
+    fn test_param_requests() {
+        let requests = CuParamRequests::default();
+        let request = |value: u32| CuParamRequest {
+            origin: "derating".to_string(),
+            task: "camera".to_string(),
+            key: "fps".to_string(),
+            value: Value::from(value),
+        };
+        requests.push(request(15));
+        requests.push(request(5));
+        assert_eq!(requests.take(), vec![request(15), request(5)]);
+        assert!(requests.take().is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+//! Physical values serialized with their unit, `{"value": 9.81, "unit": "m/s²"}`, so whoever reads the JSON
+//! of a payload (a dashboard, a telemetry sink...) does not have to guess whether a number is in g or in m/s².
+//!
+//! The Serialize implementation of a payload holding uom quantities gives them with unit_value!:
+//!
+//! ```
+//! use cu29::unit_value;
+//! use cu29::units::CuUnitValue;
+//! use uom::si::acceleration::meter_per_second_squared;
+//! use uom::si::f32::Acceleration;
+//!
+//! let acc = Acceleration::new::<meter_per_second_squared>(9.81);
+//! let value: CuUnitValue = unit_value!(acc, meter_per_second_squared);
+//! assert_eq!(value.unit, "m/s²");
+//! ```
+//!
+//! The other way around, the durations, frequencies, lengths and velocities of a task config are written with their
+//! unit, `config: {"period": "10ms", "max_speed": "1.5 m/s"}`, and read as uom quantities:
+//!
+//! ```
+//! use cu29::config::ComponentConfig;
+//! use cu29::units::uom::si::f64::Velocity;
+//! use cu29::units::uom::si::velocity::meter_per_second;
+//!
+//! let mut config = ComponentConfig::new();
+//! config.set("max_speed", "5.4 km/h".to_string());
+//! let max_speed: Velocity = config.get("max_speed").unwrap();
+//! assert!((max_speed.get::<meter_per_second>() - 1.5).abs() < 1e-9);
+//! ```
+
+use crate::config::Value;
+use crate::{CuError, CuResult};
+use cu29_clock::CuDuration;
+use serde_derive::{Deserialize, Serialize};
+use uom::si::f64::{Frequency, Length, Time, Velocity};
+use uom::si::frequency::hertz;
+use uom::si::length::meter;
+use uom::si::time::second;
+use uom::si::velocity::meter_per_second;
+
+pub use uom;
+
+/// A value with the abbreviation of its unit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CuUnitValue {
+    pub value: f64,
+    pub unit: String,
+}
+
+impl CuUnitValue {
+    pub fn new(value: f64, unit: &str) -> Self {
+        CuUnitValue {
+            value,
+            unit: unit.to_string(),
+        }
+    }
+
+    /// The value if it is in the expected unit.
+    pub fn value_in(&self, unit: &str) -> CuResult<f64> {
+        if self.unit != unit {
+            return Err(format!(
+                "Expected a value in {}, got {} {}.",
+                unit, self.value, self.unit
+            )
+            .into());
+        }
+        Ok(self.value)
+    }
+}
+
+/// The units a duration can be written with in a config, with their factor to seconds.
+pub const TIME_UNITS: &[(&str, f64)] = &[
+    ("ns", 1e-9),
+    ("us", 1e-6),
+    ("µs", 1e-6),
+    ("ms", 1e-3),
+    ("s", 1.0),
+    ("min", 60.0),
+    ("h", 3600.0),
+];
+
+/// The units a frequency can be written with in a config, with their factor to hertz.
+pub const FREQUENCY_UNITS: &[(&str, f64)] = &[("Hz", 1.0), ("kHz", 1e3), ("MHz", 1e6)];
+
+/// The units a length can be written with in a config, with their factor to meters.
+pub const LENGTH_UNITS: &[(&str, f64)] = &[
+    ("mm", 1e-3),
+    ("cm", 1e-2),
+    ("m", 1.0),
+    ("km", 1e3),
+    ("in", 0.0254),
+    ("ft", 0.3048),
+];
+
+/// The units a velocity can be written with in a config, with their factor to meters per second.
+pub const VELOCITY_UNITS: &[(&str, f64)] = &[
+    ("mm/s", 1e-3),
+    ("cm/s", 1e-2),
+    ("m/s", 1.0),
+    ("km/h", 1.0 / 3.6),
+];
+
+impl CuUnitValue {
+    /// Parses a value written with its unit, ie. "10ms" or "1.5 m/s".
+    pub fn parse(text: &str) -> CuResult<Self> {
+        let text = text.trim();
+        // The longest prefix that is a number, so an exponent ("1e-3s") is not taken for the unit.
+        (1..=text.len())
+            .rev()
+            .filter(|end| text.is_char_boundary(*end))
+            .find_map(|end| {
+                let value = text[..end].trim_end().parse::<f64>().ok()?;
+                Some(CuUnitValue::new(value, text[end..].trim()))
+            })
+            .filter(|value| value.value.is_finite() && !value.unit.is_empty())
+            .ok_or_else(|| {
+                format!("Invalid value '{}', expected a number and its unit.", text).into()
+            })
+    }
+
+    /// The value in the SI unit of the quantity, given the factors of the units it can be written with.
+    pub fn to_si(&self, quantity: &str, units: &[(&str, f64)]) -> CuResult<f64> {
+        match units.iter().find(|(unit, _)| *unit == self.unit) {
+            Some((_, factor)) => Ok(self.value * factor),
+            None => {
+                let known: Vec<&str> = units.iter().map(|(unit, _)| *unit).collect();
+                Err(format!(
+                    "Invalid unit '{}' for a {}, expected one of {}.",
+                    self.unit,
+                    quantity,
+                    known.join(", ")
+                )
+                .into())
+            }
+        }
+    }
+}
+
+/// The value in the SI unit of a quantity written with its unit in a config.
+pub fn config_quantity(value: &Value, quantity: &str, units: &[(&str, f64)]) -> CuResult<f64> {
+    let text = value.as_str().ok_or_else(|| {
+        CuError::from(format!(
+            "Expected a {} with its unit, ie. \"{}{}\", got {}.",
+            quantity, 10, units[0].0, value
+        ))
+    })?;
+    CuUnitValue::parse(text)?.to_si(quantity, units)
+}
+
+// Like the other types read from a config, an invalid value panics when the task reads it.
+macro_rules! quantity_from_config {
+    ($quantity:ty, $name:expr, $units:expr, $si_unit:ty) => {
+        impl From<Value> for $quantity {
+            fn from(value: Value) -> Self {
+                match config_quantity(&value, $name, $units) {
+                    Ok(si) => <$quantity>::new::<$si_unit>(si),
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+    };
+}
+
+quantity_from_config!(Time, "duration", TIME_UNITS, second);
+quantity_from_config!(Frequency, "frequency", FREQUENCY_UNITS, hertz);
+quantity_from_config!(Length, "length", LENGTH_UNITS, meter);
+quantity_from_config!(Velocity, "velocity", VELOCITY_UNITS, meter_per_second);
+
+/// A duration of the config, ie. "10ms", as a duration of the robot clock.
+impl From<Value> for CuDuration {
+    fn from(value: Value) -> Self {
+        match config_quantity(&value, "duration", TIME_UNITS) {
+            Ok(s) if s >= 0.0 => CuDuration((s * 1e9).round() as u64),
+            Ok(_) => panic!("Expected a positive duration, got {}.", value),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+/// The value of a uom quantity in the given unit, with the abbreviation of the unit.
+#[macro_export]
+macro_rules! unit_value {
+    ($quantity:expr, $unit:ty) => {
+        $crate::units::CuUnitValue::new(
+            $quantity.get::<$unit>() as f64,
+            <$unit as $crate::units::uom::si::Unit>::abbreviation(),
+        )
+    };
+}
+
+/// The JSON of a payload for telemetry, with the units its Serialize implementation gives.
+pub fn to_telemetry_json<T: serde::Serialize>(payload: &T) -> CuResult<String> {
+    serde_json::to_string(payload)
+        .map_err(|e| CuError::new_with_cause("Could not serialize the payload to JSON", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uom::si::acceleration::{meter_per_second_squared, standard_gravity};
+    use uom::si::f32::Acceleration;
+
+    #[derive(Serialize)]
+    struct Imu {
+        acc_z: CuUnitValue,
+    }
+
+    #[test]
+    fn test_unit_value() {
+        let acc = Acceleration::new::<standard_gravity>(1.0);
+        let imu = Imu {
+            acc_z: unit_value!(acc, meter_per_second_squared),
+        };
+        assert!((imu.acc_z.value - 9.80665).abs() < 1e-4);
+        assert_eq!(
+            to_telemetry_json(&imu).unwrap(),
+            format!(
+                r#"{{"acc_z":{{"value":{},"unit":"m/s²"}}}}"#,
+                imu.acc_z.value
+            )
+        );
+        assert!(imu.acc_z.value_in("m/s²").is_ok());
+        assert!(imu.acc_z.value_in("g").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            CuUnitValue::parse("10ms").unwrap(),
+            CuUnitValue::new(10.0, "ms")
+        );
+        assert_eq!(
+            CuUnitValue::parse(" 1.5 m/s ").unwrap(),
+            CuUnitValue::new(1.5, "m/s")
+        );
+        assert_eq!(
+            CuUnitValue::parse("1e-3s").unwrap(),
+            CuUnitValue::new(1e-3, "s")
+        );
+        assert!(CuUnitValue::parse("10").is_err());
+        assert!(CuUnitValue::parse("ms").is_err());
+        let error = CuUnitValue::new(3.0, "m/h")
+            .to_si("velocity", VELOCITY_UNITS)
+            .unwrap_err();
+        assert!(error.to_string().contains("mm/s, cm/s, m/s, km/h"));
+    }
+
+    #[test]
+    fn test_config_quantities() {
+        use crate::config::CuConfig;
+        use uom::si::frequency::kilohertz;
+        use uom::si::length::millimeter;
+        use uom::si::time::millisecond;
+
+        let txt = r#"( tasks: [(id: "base", type: "a::Base", config: {
+            "period": "20 ms", "rate": "1.5kHz", "wheel": "65mm", "speed": "36 km/h", "count": 3,
+        })], cnx: [] )"#;
+        let config = CuConfig::deserialize_ron(txt);
+        let config = config.get_all_nodes()[0].get_instance_config().unwrap();
+        let period: Time = config.get("period").unwrap();
+        assert!((period.get::<millisecond>() - 20.0).abs() < 1e-9);
+        assert_eq!(
+            config.get::<CuDuration>("period"),
+            Some(CuDuration(20_000_000))
+        );
+        let rate: Frequency = config.get("rate").unwrap();
+        assert!((rate.get::<kilohertz>() - 1.5).abs() < 1e-9);
+        let wheel: Length = config.get("wheel").unwrap();
+        assert!((wheel.get::<millimeter>() - 65.0).abs() < 1e-9);
+        let speed: Velocity = config.get("speed").unwrap();
+        assert!((speed.get::<meter_per_second>() - 10.0).abs() < 1e-9);
+
+        let error = config_quantity(&config.0["count"], "duration", TIME_UNITS).unwrap_err();
+        assert!(error.to_string().contains("\"10ns\""));
+        assert!(config_quantity(&config.0["wheel"], "duration", TIME_UNITS).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+//! Field upgrades of the config, for the remote pushes to the deployed robots: a new config is installed in place of
+//! the config file of the application and the application boots with it on its next start, the previous config is
+//! kept to be restored if the new one does not work.
+//!
+//! ```ignore
+//! application.install_config(&pushed_config)?; // then restart the application
+//! ```
+//!
+//! The new config is validated first: it must parse, pass its assertions and keep the graph of the application
+//! (the same tasks of the same types and the same connections), as the graph is compiled in. The previous config is
+//! kept next to the config file, in <config>.rollback.
+//!
+//! The first boot with the new config has upgrade_timeout_s (in the runtime section, 60 s by default) to start all
+//! its tasks. If it does not, the previous config is restored on the config file and the next start of the
+//! application, ie. by its supervisor, boots with it. A boot that failed before the timeout, the application having
+//! crashed or exited on an error, is detected on the next start which boots the previous config as well. The
+//! rollbacks are in the structured log, and their reason is returned: by CuConfigUpgrade::boot for a failed boot, by
+//! the start of the tasks which then fails for a timeout.
+
+use crate::config::{read_configuration, read_configuration_str, CuConfig};
+use crate::lint::check_assertions;
+use crate::{CuError, CuResult};
+use cu29_log_derive::debug;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The time the new config has to start all the tasks if the runtime section does not set upgrade_timeout_s.
+pub const DEFAULT_UPGRADE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The states of an installed config in <config>.pending, removed once it started all the tasks.
+const INSTALLED: &str = "installed";
+const BOOTING: &str = "booting";
+
+/// Validates the config and installs it in place of the config file of the application at path, the current one
+/// becoming its rollback.
+pub fn install_config(path: impl AsRef<Path>, config: &str) -> CuResult<()> {
+    let path = path.as_ref();
+    let new = read_configuration_str(config)
+        .map_err(|e| CuError::new_with_cause("The new config is invalid", e))?;
+    let failures = check_assertions(&new);
+    if !failures.is_empty() {
+        return Err(CuError::from(format!(
+            "The new config fails its assertions: {}.",
+            failures.join(", ")
+        )));
+    }
+    let current = read_configuration(&path.to_string_lossy())?;
+    let changes = graph_changes(&current, &new);
+    if !changes.is_empty() {
+        return Err(CuError::from(format!(
+            "The new config changes the graph of the application, it needs a new build: {}.",
+            changes.join(", ")
+        )));
+    }
+
+    let write = |target: &Path, text: &str| {
+        let mut temporary = target.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, text)
+            .and_then(|()| fs::rename(&temporary, target))
+            .map_err(|e| {
+                CuError::new_with_cause(&format!("Could not write {}", target.display()), e)
+            })
+    };
+    // A config installed but not booted yet is replaced, it keeps the rollback of the one which booted.
+    if !sibling(path, "pending").exists() {
+        let current = fs::read_to_string(path).map_err(|e| {
+            CuError::new_with_cause(&format!("Could not read {}", path.display()), e)
+        })?;
+        write(&sibling(path, "rollback"), &current)?;
+    }
+    write(&sibling(path, "pending"), INSTALLED)?;
+    write(path, config)?;
+    debug!(
+        "Upgrade: a new config is installed in {}.",
+        path.display().to_string()
+    );
+    Ok(())
+}
+
+/// What the start of the application found of an upgrade of its config.
+#[derive(Debug, Clone)]
+pub enum CuUpgradeBoot {
+    /// No config was installed, the application boots its config as usual.
+    None,
+    /// The first boot of a config just installed.
+    Upgrade(CuConfigUpgrade),
+    /// The previous boot of the installed config failed, the previous config is restored and booted, with why.
+    RolledBack(String),
+}
+
+/// How the upgrade ended, see CuConfigUpgrade::confirm.
+#[derive(Debug, Clone)]
+enum Settlement {
+    Pending,
+    Confirmed,
+    RolledBack(String),
+}
+
+/// A config installed in the field, from its first boot until it started all the tasks.
+#[derive(Debug, Clone)]
+pub struct CuConfigUpgrade {
+    path: PathBuf,
+    /// Once confirmed or rolled back, the other cannot happen.
+    settled: Arc<Mutex<Settlement>>,
+}
+
+impl CuConfigUpgrade {
+    /// Called before the config at path is read: the boot of a config just installed, or the rollback of one that
+    /// did not start all its tasks on its first boot.
+    pub fn boot(path: impl AsRef<Path>) -> CuResult<CuUpgradeBoot> {
+        let path = path.as_ref();
+        let pending = sibling(path, "pending");
+        let state = match fs::read_to_string(&pending) {
+            Ok(state) => state,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(CuUpgradeBoot::None),
+            Err(e) => {
+                return Err(CuError::new_with_cause(
+                    &format!("Could not read {}", pending.display()),
+                    e,
+                ))
+            }
+        };
+        if state.trim() != INSTALLED {
+            let reason = format!(
+                "The first boot of the new config {} did not start all the tasks",
+                path.display()
+            );
+            rollback(path, &reason)?;
+            return Ok(CuUpgradeBoot::RolledBack(reason));
+        }
+        fs::write(&pending, BOOTING).map_err(|e| {
+            CuError::new_with_cause(&format!("Could not write {}", pending.display()), e)
+        })?;
+        debug!(
+            "Upgrade: booting the new config {}.",
+            path.display().to_string()
+        );
+        Ok(CuUpgradeBoot::Upgrade(CuConfigUpgrade {
+            path: path.to_path_buf(),
+            settled: Arc::new(Mutex::new(Settlement::Pending)),
+        }))
+    }
+
+    /// Restores the previous config if the tasks are not all started within the upgrade_timeout_s of config.
+    pub fn watch(&self, config: &CuConfig) {
+        let timeout = config
+            .get_runtime_config()
+            .and_then(|runtime_config| runtime_config.upgrade_timeout_s)
+            .map_or(DEFAULT_UPGRADE_TIMEOUT, Duration::from_secs);
+        let upgrade = self.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            let reason = format!(
+                "The new config {} did not start all the tasks within {}s",
+                upgrade.path.display(),
+                timeout.as_secs()
+            );
+            if let Err(e) = upgrade.roll_back(reason) {
+                debug!("Upgrade: {}", e.to_string());
+            }
+        });
+    }
+
+    /// The new config started all the tasks, it is kept. An error with the reason if it was rolled back meanwhile,
+    /// the application then needs to restart to boot the previous config.
+    pub fn confirm(&self) -> CuResult<()> {
+        let mut settled = self.lock()?;
+        match &*settled {
+            Settlement::Pending => {
+                fs::remove_file(sibling(&self.path, "pending"))
+                    .map_err(|e| CuError::new_with_cause("Could not confirm the new config", e))?;
+                *settled = Settlement::Confirmed;
+                debug!(
+                    "Upgrade: the new config {} started all the tasks.",
+                    self.path.display().to_string()
+                );
+                Ok(())
+            }
+            Settlement::Confirmed => Ok(()),
+            Settlement::RolledBack(reason) => Err(CuError::from(format!(
+                "{}, the previous config is restored for the next start.",
+                reason
+            ))),
+        }
+    }
+
+    /// Restores the previous config, unless the new one was confirmed.
+    fn roll_back(&self, reason: String) -> CuResult<()> {
+        let mut settled = self.lock()?;
+        if let Settlement::Pending = *settled {
+            rollback(&self.path, &reason)?;
+            *settled = Settlement::RolledBack(reason);
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> CuResult<std::sync::MutexGuard<'_, Settlement>> {
+        self.settled
+            .lock()
+            .map_err(|_| CuError::from("The config upgrade is poisoned."))
+    }
+}
+
+/// Restores the previous config for the next start of the application.
+fn rollback(path: &Path, reason: &str) -> CuResult<()> {
+    fs::rename(sibling(path, "rollback"), path)
+        .and_then(|()| fs::remove_file(sibling(path, "pending")))
+        .map_err(|e| {
+            CuError::new_with_cause(
+                &format!(
+                    "Could not restore the previous config of {}",
+                    path.display()
+                ),
+                e,
+            )
+        })?;
+    debug!(
+        "Upgrade: {}, the previous config {} is restored.",
+        reason.to_string(),
+        path.display().to_string()
+    );
+    Ok(())
+}
+
+/// <config>.<extension>, next to the config file.
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(extension);
+    PathBuf::from(sibling)
+}
+
+/// What differs in the graph compiled in the application: the tasks, their types and the connections.
+fn graph_changes(old: &CuConfig, new: &CuConfig) -> Vec<String> {
+    let tasks = |config: &CuConfig| -> BTreeSet<(String, String)> {
+        config
+            .get_all_nodes()
+            .iter()
+            .map(|node| (node.get_id(), node.get_type().to_string()))
+            .collect()
+    };
+    let cnx = |config: &CuConfig| -> BTreeSet<(String, String, String)> {
+        config
+            .graph
+            .edge_weights()
+            .map(|cnx| {
+                (
+                    cnx.get_src().to_string(),
+                    cnx.get_dst().to_string(),
+                    cnx.msg.clone(),
+                )
+            })
+            .collect()
+    };
+    let (old_tasks, new_tasks) = (tasks(old), tasks(new));
+    let (old_cnx, new_cnx) = (cnx(old), cnx(new));
+    old_tasks
+        .symmetric_difference(&new_tasks)
+        .map(|(id, task_type)| format!("task {} ({})", id, task_type))
+        .chain(
+            old_cnx
+                .symmetric_difference(&new_cnx)
+                .map(|(src, dst, msg)| format!("connection {} -> {} ({})", src, dst, msg)),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"(
+        tasks: [(id: "src", type: "a::Src", config: {"rate": 10}), (id: "sink", type: "a::Sink")],
+        cnx: [(src: "src", dst: "sink", msg: "u32")],
+    )"#;
+
+    #[test]
+    fn test_install_and_confirm() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("copperconfig.ron");
+        fs::write(&path, CONFIG).unwrap();
+        assert!(matches!(
+            CuConfigUpgrade::boot(&path).unwrap(),
+            CuUpgradeBoot::None
+        ));
+
+        assert!(install_config(&path, "garbage").is_err());
+        let other_graph = CONFIG.replace("a::Sink", "a::Motor");
+        assert!(install_config(&path, &other_graph)
+            .unwrap_err()
+            .to_string()
+            .contains("task sink (a::Motor)"));
+
+        let new = CONFIG.replace("10", "5");
+        install_config(&path, &new).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), new);
+        assert_eq!(
+            fs::read_to_string(sibling(&path, "rollback")).unwrap(),
+            CONFIG
+        );
+        let CuUpgradeBoot::Upgrade(upgrade) = CuConfigUpgrade::boot(&path).unwrap() else {
+            panic!("The new config is not booted.");
+        };
+        upgrade.confirm().unwrap();
+        assert!(!sibling(&path, "pending").exists());
+        // Settled, a late timeout does not roll back.
+        upgrade.roll_back("late".to_string()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), new);
+        assert!(upgrade.confirm().is_ok());
+        assert!(matches!(
+            CuConfigUpgrade::boot(&path).unwrap(),
+            CuUpgradeBoot::None
+        ));
+    }
+
+    #[test]
+    fn test_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("copperconfig.ron");
+        fs::write(&path, CONFIG).unwrap();
+        let new = CONFIG.replace("10", "5");
+
+        // The first boot crashed before starting all the tasks.
+        install_config(&path, &new).unwrap();
+        assert!(matches!(
+            CuConfigUpgrade::boot(&path).unwrap(),
+            CuUpgradeBoot::Upgrade(_)
+        ));
+        let CuUpgradeBoot::RolledBack(reason) = CuConfigUpgrade::boot(&path).unwrap() else {
+            panic!("The new config is not rolled back.");
+        };
+        assert!(reason.contains("did not start all the tasks"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG);
+
+        // The first boot did not start all the tasks in time.
+        install_config(&path, &new).unwrap();
+        let CuUpgradeBoot::Upgrade(upgrade) = CuConfigUpgrade::boot(&path).unwrap() else {
+            panic!("The new config is not booted.");
+        };
+        let config =
+            read_configuration_str(&new.replace("cnx:", "runtime: (upgrade_timeout_s: 0), cnx:"))
+                .unwrap();
+        upgrade.watch(&config);
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG);
+        let error = upgrade.confirm().unwrap_err().to_string();
+        assert!(error.contains("within 0s"), "{}", error);
+        assert!(matches!(
+            CuConfigUpgrade::boot(&path).unwrap(),
+            CuUpgradeBoot::None
+        ));
+    }
+}
@@ -0,0 +1,50 @@
+#[allow(dead_code)] // only a subset of the config API is needed to lint it
+mod config;
+mod lint;
+#[allow(dead_code)]
+mod permissions;
+use clap::Parser;
+use config::read_configuration;
+pub use cu29_traits::*;
+use lint::{check_assertions, lint_config};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Config file name
+    #[clap(value_parser)]
+    config: PathBuf,
+    /// Exit with an error if the config has any lint
+    #[clap(long)]
+    deny_warnings: bool,
+}
+
+/// Reads the configuration file and prints its lints and the failures of its assertions, see cu29::lint.
+fn main() {
+    let args = Args::parse();
+
+    let config = match read_configuration(args.config.to_str().unwrap()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid config: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let lints = lint_config(&config);
+    for lint in &lints {
+        println!("warning: {}", lint);
+    }
+    println!("{} lints.", lints.len());
+    let failures = check_assertions(&config);
+    for failure in &failures {
+        println!("error: {}", failure);
+    }
+    if !failures.is_empty() {
+        println!("{} failed assertions.", failures.len());
+        std::process::exit(1);
+    }
+    if args.deny_warnings && !lints.is_empty() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,349 @@
+//! Watch mode for development: the application watches its config file and applies the changes it can on save,
+//! ie. to tune the gains of a controller or the rate of the loop without restarting it.
+//!
+//! Enabled in the runtime section of the config:
+//! ```ron
+//! runtime: (watch_config: true),
+//! ```
+//! The parameters of the tasks (with their base period), the pacing of the loop and the logging section are
+//! applied on the next iteration, the changes of the parameters are logged like the ones of the live tuning (see
+//! [crate::tuning]) so a replay applies them again. The other changes need a restart of the application. Both are
+//! written to the structured log on every save.
+
+use crate::clock::{CuDuration, CuTime, OptionCuTime};
+use crate::config::{read_configuration, ComponentConfig, CuConfig, CuParamOverrides};
+use crate::permissions::PERMISSION_KEY_PREFIX;
+use crate::tuning::CuParamChange;
+use crate::CuResult;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// How often the modification time of the config file is checked.
+const WATCH_PERIOD: CuDuration = CuDuration(500_000_000);
+
+/// The fields of the runtime section applied by rebuilding the pacer of the loop.
+const PACING_FIELDS: [&str; 4] = [
+    "period_ns",
+    "sleep_strategy",
+    "overrun_policy",
+    "report_period_s",
+];
+
+/// The origin of the changes of the parameters made by saving the config file, see CuParamChange.
+pub const CONFIG_FILE_ORIGIN: &str = "config file";
+
+/// What changed between the running config and the saved one.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CuConfigDiff {
+    /// The parameters of the tasks, time is set when they are applied.
+    pub params: Vec<CuParamChange>,
+    /// The fields of the runtime section pacing the loop, with their old and new values.
+    pub pacing: Vec<(String, String, String)>,
+    /// If the logging section changed.
+    pub logging: bool,
+    /// What cannot change without a restart of the application.
+    pub restart: Vec<String>,
+}
+
+impl CuConfigDiff {
+    /// The differences between the config the application runs with and the one saved.
+    pub fn between(old: &CuConfig, new: &CuConfig) -> Self {
+        let mut diff = CuConfigDiff::default();
+        let old_nodes = old.get_all_nodes();
+        let new_nodes = new.get_all_nodes();
+        for old_node in &old_nodes {
+            let id = old_node.get_id();
+            let Some(new_node) = new_nodes.iter().find(|node| node.get_id() == id) else {
+                diff.restart.push(format!("task {} removed", id));
+                continue;
+            };
+            if old_node.get_type() != new_node.get_type() {
+                diff.restart.push(format!(
+                    "type of {}: {} -> {}",
+                    id,
+                    old_node.get_type(),
+                    new_node.get_type()
+                ));
+            }
+            if old_node.get_scratch_size() != new_node.get_scratch_size() {
+                diff.restart.push(format!("scratch_size of {}", id));
+            }
+            if old_node.get_clock_domain() != new_node.get_clock_domain() {
+                diff.restart.push(format!("clock_domain of {}", id));
+            }
+            diff.diff_params(&id, old_node.get_task_config(), new_node.get_task_config());
+        }
+        for new_node in &new_nodes {
+            if !old_nodes
+                .iter()
+                .any(|node| node.get_id() == new_node.get_id())
+            {
+                diff.restart
+                    .push(format!("task {} added", new_node.get_id()));
+            }
+        }
+
+        let cnx = |config: &CuConfig| -> BTreeSet<(String, String, String)> {
+            config
+                .graph
+                .edge_weights()
+                .map(|cnx| {
+                    (
+                        cnx.get_src().to_string(),
+                        cnx.get_dst().to_string(),
+                        to_ron(cnx),
+                    )
+                })
+                .collect()
+        };
+        let (old_cnx, new_cnx) = (cnx(old), cnx(new));
+        for (src, dst, _) in old_cnx.symmetric_difference(&new_cnx) {
+            let change = format!("connection {} -> {}", src, dst);
+            if !diff.restart.contains(&change) {
+                diff.restart.push(change);
+            }
+        }
+
+        if to_ron(&old.get_monitor_config()) != to_ron(&new.get_monitor_config()) {
+            diff.restart.push("monitor section".to_string());
+        }
+        if to_ron(&old.get_faults_config()) != to_ron(&new.get_faults_config()) {
+            diff.restart.push("faults section".to_string());
+        }
+        diff.logging = to_ron(&old.get_logging_config()) != to_ron(&new.get_logging_config());
+        for (field, old_value, new_value) in
+            fields_diff(&old.get_runtime_config(), &new.get_runtime_config())
+        {
+            if PACING_FIELDS.contains(&field.as_str()) {
+                diff.pacing.push((field, old_value, new_value));
+            } else {
+                diff.restart.push(format!("runtime.{}", field));
+            }
+        }
+        diff
+    }
+
+    fn diff_params(
+        &mut self,
+        task: &str,
+        old: Option<ComponentConfig>,
+        new: Option<ComponentConfig>,
+    ) {
+        let (old, new) = (old.unwrap_or_default(), new.unwrap_or_default());
+        let keys: BTreeSet<&String> = old.0.keys().chain(new.0.keys()).collect();
+        for key in keys {
+            let (old_value, new_value) = (old.0.get(key), new.0.get(key));
+            if old_value == new_value {
+                continue;
+            }
+            if key.starts_with(PERMISSION_KEY_PREFIX) {
+                self.restart.push(format!("permissions of {}", task));
+                continue;
+            }
+            self.params.push(CuParamChange {
+                time: CuTime::default(),
+                task: task.to_string(),
+                key: key.clone(),
+                old: old_value.and_then(|value| value.to_ron().ok()),
+                new: new_value.and_then(|value| value.to_ron().ok()),
+                origin: CONFIG_FILE_ORIGIN.to_string(),
+            });
+        }
+        self.restart.dedup();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty() && self.pacing.is_empty() && !self.logging && self.restart.is_empty()
+    }
+}
+
+impl fmt::Display for CuConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.params {
+            writeln!(
+                f,
+                "  applied: {}.{}: {} -> {}",
+                change.task,
+                change.key,
+                change.old.as_deref().unwrap_or("unset"),
+                change.new.as_deref().unwrap_or("unset")
+            )?;
+        }
+        for (field, old, new) in &self.pacing {
+            writeln!(f, "  applied: runtime.{}: {} -> {}", field, old, new)?;
+        }
+        if self.logging {
+            writeln!(f, "  applied: logging section")?;
+        }
+        for change in &self.restart {
+            writeln!(f, "  needs a restart: {}", change)?;
+        }
+        Ok(())
+    }
+}
+
+fn to_ron<T: Serialize>(value: &T) -> String {
+    ron::to_string(value).unwrap_or_default()
+}
+
+/// The fields of 2 sections of the config which differ, with their old and new values.
+fn fields_diff<T: Serialize>(old: &T, new: &T) -> Vec<(String, String, String)> {
+    let fields = |value: &T| match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let (old, new) = (fields(old), fields(new));
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let show = |value: Option<&serde_json::Value>| {
+        value.map_or("unset".to_string(), |value| value.to_string())
+    };
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| (key.clone(), show(old.get(key)), show(new.get(key))))
+        .collect()
+}
+
+/// Watches the config file of the application, see poll.
+#[derive(Debug)]
+pub struct CuConfigWatcher {
+    path: PathBuf,
+    /// The overrides of the command line, applied again on every save so they keep precedence over the file.
+    overrides: CuParamOverrides,
+    /// The config as last saved, with the overrides.
+    current: CuConfig,
+    modified: Option<SystemTime>,
+    last_check: OptionCuTime,
+}
+
+impl CuConfigWatcher {
+    pub fn new(path: impl Into<PathBuf>, current: &CuConfig, overrides: &CuParamOverrides) -> Self {
+        let path = path.into();
+        let modified = modified(&path);
+        CuConfigWatcher {
+            path,
+            overrides: overrides.clone(),
+            current: current.clone(),
+            modified,
+            last_check: OptionCuTime::none(),
+        }
+    }
+
+    /// Checks the file every WATCH_PERIOD, returns what changed if it was saved since the last check.
+    /// A file which does not parse is an error, the next save is checked again.
+    pub fn poll(&mut self, now: CuTime) -> Option<CuResult<CuConfigDiff>> {
+        let last: Option<CuTime> = self.last_check.into();
+        if last.is_some_and(|last| now - last < WATCH_PERIOD) {
+            return None;
+        }
+        self.last_check = now.into();
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(self.reload())
+    }
+
+    fn reload(&mut self) -> CuResult<CuConfigDiff> {
+        let mut config = read_configuration(&self.path.to_string_lossy())?;
+        self.overrides.apply(&mut config)?;
+        let diff = CuConfigDiff::between(&self.current, &config);
+        self.current = config;
+        Ok(diff)
+    }
+
+    /// The config as last saved.
+    pub fn current(&self) -> &CuConfig {
+        &self.current
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_configuration_str;
+
+    const CONFIG: &str = r#"(
+        tasks: [(id: "imu", type: "Imu", config: {"port": "/dev/ttyUSB0"}),
+                (id: "pid", type: "Pid", config: {"kp": 1.0, "ki": 0.1})],
+        cnx: [(src: "imu", dst: "pid", msg: "f32")],
+        runtime: (period_ns: 1000000, watch_config: true),
+    )"#;
+
+    #[test]
+    fn test_config_diff() {
+        let old = read_configuration_str(CONFIG).unwrap();
+        assert!(CuConfigDiff::between(&old, &old).is_empty());
+        let new = read_configuration_str(
+            &CONFIG
+                .replace(r#""kp": 1.0, "ki": 0.1"#, r#""kp": 2.0"#)
+                .replace(
+                    "period_ns: 1000000",
+                    "period_ns: 2000000, metrics_path: \"/dev/shm/m\"",
+                )
+                .replace(r#""Imu""#, r#""Imu2""#)
+                .replace(r#"msg: "f32""#, r#"msg: "f64""#),
+        )
+        .unwrap();
+        let diff = CuConfigDiff::between(&old, &new);
+        let params: Vec<_> = diff
+            .params
+            .iter()
+            .map(|change| (change.key.as_str(), change.new.as_deref()))
+            .collect();
+        assert_eq!(params, [("ki", None), ("kp", Some("2.0"))]);
+        assert_eq!(diff.params[0].origin, CONFIG_FILE_ORIGIN);
+        assert!(!diff.logging);
+        assert_eq!(
+            diff.to_string(),
+            "  applied: pid.ki: 0.1 -> unset\n  applied: pid.kp: 1.0 -> 2.0\n\
+             \x20 applied: runtime.period_ns: 1000000 -> 2000000\n\
+             \x20 needs a restart: type of imu: Imu -> Imu2\n\
+             \x20 needs a restart: connection imu -> pid\n\
+             \x20 needs a restart: runtime.metrics_path\n"
+        );
+    }
+
+    #[test]
+    fn test_watcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("copperconfig.ron");
+        fs::write(&path, CONFIG).unwrap();
+        let mut config = read_configuration(&path.to_string_lossy()).unwrap();
+        let overrides = CuParamOverrides::new().set("pid", "ki", 0.5);
+        overrides.apply(&mut config).unwrap();
+        let mut watcher = CuConfigWatcher::new(&path, &config, &overrides);
+        assert!(watcher.poll(CuTime::from(0)).is_none());
+
+        fs::write(&path, CONFIG.replace("0.1", "0.2").replace("1.0", "3.0")).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(1))
+            .unwrap();
+        // Not checked again before the period.
+        assert!(watcher.poll(CuTime::from(100_000_000)).is_none());
+        let diff = watcher.poll(CuTime::from(600_000_000)).unwrap().unwrap();
+        // The override of ki keeps precedence over the file.
+        let keys: Vec<_> = diff
+            .params
+            .iter()
+            .map(|change| change.key.as_str())
+            .collect();
+        assert_eq!(keys, ["kp"]);
+        assert!(watcher.poll(CuTime::from(1_200_000_000)).is_none());
+
+        fs::write(&path, "(tasks: [").unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(2))
+            .unwrap();
+        assert!(watcher.poll(CuTime::from(1_800_000_000)).unwrap().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! The wire format of the log, specified in doc/wire_format.md: little-endian, variable length integers,
+//! fields in declaration order, regardless of the architecture of the robot or of the defaults of bincode.
+//!
+//! The application writes a CuWireHeader at the start of its log with the version of the format and the layout of
+//! its copper lists, so a reader built for another version or another configuration refuses the log instead of
+//! decoding garbage.
+
+use crate::lineage::CuRunId;
+use crate::{CuError, CuResult};
+use bincode::{Decode, Encode};
+use cu29_traits::{UnifiedLogType, WriteStream};
+use cu29_unifiedlog::{stream_write, UnifiedLoggerWrite};
+use std::sync::{Arc, Mutex};
+
+pub use cu29_traits::{wire_config, WireConfig, WIRE_FORMAT_VERSION};
+
+/// The FNV-1a hash of a schema, stable across architectures and compilers.
+pub fn schema_hash(schema: &str) -> u64 {
+    schema.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The content of the Schema section of a log.
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct CuWireHeader {
+    pub version: u16,
+    pub schema_hash: u64,
+    /// The layout of the copper lists, see CuListDumper::SCHEMA.
+    pub schema: String,
+    /// The robot which wrote the log, see cu29::namespace.
+    pub robot_id: Option<String>,
+    /// The run of the application which wrote the log, see cu29::lineage.
+    pub run: Option<CuRunId>,
+}
+
+impl CuWireHeader {
+    pub fn new(schema: &str, robot_id: Option<&str>, run: Option<&CuRunId>) -> Self {
+        CuWireHeader {
+            version: WIRE_FORMAT_VERSION,
+            schema_hash: schema_hash(schema),
+            schema: schema.to_string(),
+            robot_id: robot_id.map(str::to_string),
+            run: run.cloned(),
+        }
+    }
+
+    /// Checks that a log with this header can be decoded with this version of copper and this schema.
+    pub fn check(&self, schema: &str) -> CuResult<()> {
+        if self.version != WIRE_FORMAT_VERSION {
+            return Err(format!(
+                "The log is in version {} of the wire format, this reader reads version {}.",
+                self.version, WIRE_FORMAT_VERSION
+            )
+            .into());
+        }
+        if self.schema_hash != schema_hash(schema) {
+            return Err(format!(
+                "The copper lists of the log do not match the ones of this reader.\n  log: {}\n  reader: {}",
+                self.schema, schema
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Writes the header of the wire format in its own section of the log.
+pub fn write_wire_header(
+    logger: Arc<Mutex<UnifiedLoggerWrite>>,
+    schema: &str,
+    robot_id: Option<&str>,
+    run: Option<&CuRunId>,
+) -> CuResult<()> {
+    let header = CuWireHeader::new(schema, robot_id, run);
+    let size = bincode::encode_to_vec(&header, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not encode the wire header", e))?
+        .len();
+    // Room for the header of the section.
+    let mut stream = stream_write(logger, UnifiedLogType::Schema, size + 64);
+    stream.log(&header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cutask::CuMsg;
+
+    #[test]
+    fn test_wire_header() {
+        let header = CuWireHeader::new("src:u32;sink:Vec<u8>", Some("r2"), None);
+        assert!(header.check("src:u32;sink:Vec<u8>").is_ok());
+        assert!(header.check("src:u64;sink:Vec<u8>").is_err());
+        let old = CuWireHeader {
+            version: 0,
+            ..header.clone()
+        };
+        assert!(old.check("src:u32;sink:Vec<u8>").is_err());
+        let encoded = bincode::encode_to_vec(&header, wire_config()).unwrap();
+        // The robot id as an Option of a string, then the run.
+        assert!(encoded.ends_with(&[1, 2, b'r', b'2', 0]));
+        let run = CuRunId {
+            uuid: "u".to_string(),
+            counter: 300,
+            previous: None,
+        };
+        let header = CuWireHeader::new("src:u32", None, Some(&run));
+        let encoded = bincode::encode_to_vec(&header, wire_config()).unwrap();
+        assert!(encoded.ends_with(&[0, 1, 1, b'u', 251, 0x2c, 0x01, 0]));
+        // The reference values of FNV-1a.
+        assert_eq!(schema_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(schema_hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_wire_layout() {
+        // Spelled out in doc/wire_format.md, a change here is a new version of the format.
+        assert_eq!(
+            bincode::encode_to_vec(250u32, wire_config()).unwrap(),
+            [250]
+        );
+        assert_eq!(
+            bincode::encode_to_vec(0x1234u32, wire_config()).unwrap(),
+            [251, 0x34, 0x12]
+        );
+        assert_eq!(bincode::encode_to_vec(-2i32, wire_config()).unwrap(), [3]);
+        assert_eq!(
+            bincode::encode_to_vec(1.0f32, wire_config()).unwrap(),
+            [0, 0, 0x80, 0x3f]
+        );
+        assert_eq!(
+            bincode::encode_to_vec("hi", wire_config()).unwrap(),
+            [2, b'h', b'i']
+        );
+
+        let mut msg = CuMsg::new(Some(7u8));
+        msg.metadata.before_process = crate::clock::CuTime::from(300).into();
+        let encoded = bincode::encode_to_vec(&msg, wire_config()).unwrap();
+        // Some payload, before_process, after_process (none), tov (none), empty status, no provenance.
+        let none = [253, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let mut expected = vec![1, 7, 251, 0x2c, 0x01];
+        expected.extend_from_slice(&none);
+        expected.extend_from_slice(&none);
+        expected.extend_from_slice(&[0, 0]);
+        assert_eq!(encoded, expected);
+
+        msg.metadata.set_status("ok");
+        let encoded = bincode::encode_to_vec(&msg, wire_config()).unwrap();
+        let (decoded, size): (CuMsg<u8>, usize) =
+            bincode::decode_from_slice(&encoded, wire_config()).unwrap();
+        assert_eq!(size, encoded.len());
+        assert_eq!(decoded.metadata.status_txt.0, "ok");
+    }
+}
@@ -0,0 +1,176 @@
+//! Measures the rebuild time of an application after its config is touched, with the code generated by
+//! copper_runtime taken from the cache or generated again, for graphs of increasing sizes.
+//!
+//! cargo bench -p cu29-derive --bench expansion
+//!
+//! The first build of the generated application compiles all the copper crates, it is not measured.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 3] = [10, 50, 100];
+const RUNS: usize = 3;
+
+const TASKS: &str = r#"
+use cu29::clock::RobotClock;
+use cu29::context::CuContext;
+use cu29::config::ComponentConfig;
+use cu29::cutask::{CuMsg, CuSinkTask, CuSrcTask, CuTask, CuTaskLifecycle, Freezable};
+use cu29::{input_msg, output_msg};
+use cu29_traits::CuResult;
+
+macro_rules! lifecycle {
+    ($name:ident) => {
+        pub struct $name {}
+        impl Freezable for $name {}
+        impl CuTaskLifecycle for $name {
+            fn new(_config: Option<&ComponentConfig>) -> CuResult<Self> {
+                Ok(Self {})
+            }
+        }
+    };
+}
+
+lifecycle!(Src);
+lifecycle!(Pass);
+lifecycle!(Sink);
+
+impl<'cl> CuSrcTask<'cl> for Src {
+    type Output = output_msg!('cl, u64);
+    fn process(&mut self, ctx: &CuContext, output: Self::Output) -> CuResult<()> {
+        output.set_payload(ctx.now().into());
+        Ok(())
+    }
+}
+
+impl<'cl> CuTask<'cl> for Pass {
+    type Input = input_msg!('cl, u64);
+    type Output = output_msg!('cl, u64);
+    fn process(&mut self, _ctx: &CuContext, input: Self::Input, output: Self::Output) -> CuResult<()> {
+        if let Some(value) = input.payload() {
+            output.set_payload(*value + 1);
+        }
+        Ok(())
+    }
+}
+
+impl<'cl> CuSinkTask<'cl> for Sink {
+    type Input = input_msg!('cl, u64);
+    fn process(&mut self, _ctx: &CuContext, _input: Self::Input) -> CuResult<()> {
+        Ok(())
+    }
+}
+"#;
+
+const MAIN: &str = r#"
+#![allow(dead_code)]
+use cu29_derive::copper_runtime;
+use cu29_log_derive::debug;
+
+mod tasks;
+
+#[copper_runtime(config = "copperconfig.ron")]
+struct App {}
+
+fn main() {}
+"#;
+
+const BUILD: &str = r#"
+fn main() {
+    println!("cargo:rustc-env=LOG_INDEX_DIR={}", std::env::var("OUT_DIR").unwrap());
+}
+"#;
+
+/// A chain of size tasks: a source, size - 2 regular tasks and a sink.
+fn config(size: usize) -> String {
+    let mut tasks = vec![r#"(id: "t0", type: "tasks::Src")"#.to_string()];
+    tasks.extend((1..size - 1).map(|i| format!(r#"(id: "t{}", type: "tasks::Pass")"#, i)));
+    tasks.push(format!(r#"(id: "t{}", type: "tasks::Sink")"#, size - 1));
+    let cnx: Vec<String> = (1..size)
+        .map(|i| format!(r#"(src: "t{}", dst: "t{}", msg: "u64")"#, i - 1, i))
+        .collect();
+    format!(
+        "(\n    tasks: [\n        {}\n    ],\n    cnx: [\n        {}\n    ],\n)\n",
+        tasks.join(",\n        "),
+        cnx.join(",\n        ")
+    )
+}
+
+fn create_app(dir: &Path) {
+    let core = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let deps: String = [
+        "cu29",
+        "cu29-derive",
+        "cu29-log",
+        "cu29-log-derive",
+        "cu29-log-runtime",
+        "cu29-traits",
+        "cu29-unifiedlog",
+    ]
+    .iter()
+    .map(|name| {
+        let path = core.join(name.replace('-', "_"));
+        format!("{} = {{ path = {:?} }}\n", name, path)
+    })
+    .collect();
+    let manifest = format!(
+        "[package]\nname = \"expansion-bench-app\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+         [workspace]\n\n[dependencies]\n{}bincode = {{ version = \"2.0.0-rc.3\", features = [\"derive\"] }}\n",
+        deps
+    );
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+    std::fs::write(dir.join("build.rs"), BUILD).unwrap();
+    std::fs::write(dir.join("src/main.rs"), MAIN).unwrap();
+    std::fs::write(dir.join("src/tasks.rs"), TASKS).unwrap();
+    // Build with the same versions as the workspace.
+    let lock = core.parent().unwrap().join("Cargo.lock");
+    if lock.exists() {
+        std::fs::copy(lock, dir.join("Cargo.lock")).unwrap();
+    }
+}
+
+/// Rewrites the config with the same content, which makes cargo build the application again, and times it.
+fn rebuild(dir: &Path, size: usize, cached: bool) -> Duration {
+    std::fs::write(dir.join("copperconfig.ron"), config(size)).unwrap();
+    let mut cargo = Command::new(std::env::var("CARGO").unwrap_or("cargo".to_string()));
+    cargo.current_dir(dir).args(["build", "--quiet"]);
+    if !cached {
+        cargo.env("CU29_NO_CODEGEN_CACHE", "1");
+    }
+    let start = Instant::now();
+    // The macros are verbose on stderr, only show it on failure.
+    let output = cargo.output().expect("Could not run cargo");
+    let elapsed = start.elapsed();
+    assert!(
+        output.status.success(),
+        "The bench application failed to build:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    elapsed
+}
+
+fn main() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("expansion_bench");
+    create_app(&dir);
+    println!("nodes   generated   cached");
+    for size in SIZES {
+        // Warms up the dependencies and the cache for this size.
+        rebuild(&dir, size, true);
+        let best = |cached| {
+            (0..RUNS)
+                .map(|_| rebuild(&dir, size, cached))
+                .min()
+                .unwrap()
+        };
+        let generated = best(false);
+        let cached = best(true);
+        println!(
+            "{:>5} {:>9.2}s {:>7.2}s",
+            size,
+            generated.as_secs_f64(),
+            cached.as_secs_f64()
+        );
+    }
+}
@@ -1,4 +1,8 @@
 use cargo_metadata::{MetadataCommand, Package};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use walkdir::WalkDir;
 
 fn main() {
     let metadata = MetadataCommand::new()
@@ -8,6 +12,8 @@ fn main() {
     for package in metadata.packages {
         check_metadata(&package);
     }
+
+    emit_build_id();
 }
 
 fn check_metadata(package: &Package) {
@@ -20,3 +26,33 @@ fn check_metadata(package: &Package) {
         }
     }
 }
+
+/// Identifies the code generation for its cache: the sources of this crate and, in the copper workspace,
+/// the ones of cu29 it plans the runtime with. A published cu29 is identified by its version.
+fn emit_build_id() {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    for dir in ["src", "../cu29/src"] {
+        if !Path::new(dir).exists() {
+            continue;
+        }
+        println!("cargo:rerun-if-changed={}", dir);
+        let mut files: Vec<_> = WalkDir::new(dir)
+            .into_iter()
+            .flatten()
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect();
+        files.sort();
+        for file in files {
+            file.hash(&mut hasher);
+            std::fs::read(&file).unwrap_or_default().hash(&mut hasher);
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!(
+        "cargo:rustc-env=CU29_DERIVE_BUILD_ID={:016x}",
+        hasher.finish()
+    );
+}
@@ -0,0 +1,134 @@
+//! A cache of the code generated by copper_runtime.
+//! Cargo builds the crate again every time its config is touched, this avoids planning and generating
+//! a large graph again when the config, the macro invocation and the macro itself did not change.
+//! An entry holds the warnings printed while generating the code so a cache hit prints them again.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+/// Identifies the build of this macro, see build.rs.
+const BUILD_ID: &str = env!("CU29_DERIVE_BUILD_ID");
+
+/// Set this environment variable to always generate the code.
+const DISABLE_VAR: &str = "CU29_NO_CODEGEN_CACHE";
+
+/// The environment variables the code generation reads, the crate name locates the config. The compiler and the
+/// cfg of the target (all the CARGO_CFG_ ones) are in as well, a change of toolchain or target generates it again.
+const ENV_INPUTS: &[&str] = &["CARGO_PKG_NAME", "RUSTC"];
+const ENV_INPUTS_PREFIX: &str = "CARGO_CFG_";
+
+/// The warnings are the first lines of an entry, as comments the code is parsed without.
+const WARNING_PREFIX: &str = "// ";
+
+/// The cache entry of one invocation of the macro.
+pub(crate) struct CodegenCache {
+    dir: PathBuf,
+    /// All the entries of this invocation start with this, only the last one is kept.
+    prefix: String,
+    path: PathBuf,
+}
+
+impl CodegenCache {
+    /// The entry for this content of the invocation, the files the code generation reads included.
+    /// None if the crate has no OUT_DIR to store it (it needs a build.rs) or if the cache is disabled.
+    pub(crate) fn new(name: &str, inputs: &[&[u8]]) -> Option<Self> {
+        if std::env::var_os(DISABLE_VAR).is_some() {
+            return None;
+        }
+        let dir = PathBuf::from(std::env::var_os("OUT_DIR")?).join("cu29_codegen");
+        let mut hasher = DefaultHasher::new();
+        BUILD_ID.hash(&mut hasher);
+        inputs.hash(&mut hasher);
+        for var in ENV_INPUTS {
+            std::env::var_os(var).hash(&mut hasher);
+        }
+        let cfgs: BTreeMap<OsString, OsString> = std::env::vars_os()
+            .filter(|(var, _)| var.to_string_lossy().starts_with(ENV_INPUTS_PREFIX))
+            .collect();
+        cfgs.hash(&mut hasher);
+        let prefix = format!("{}.", name);
+        let path = dir.join(format!("{}{:016x}.rs", prefix, hasher.finish()));
+        Some(CodegenCache { dir, prefix, path })
+    }
+
+    /// The code generated the last time for the same content and its warnings, if any.
+    pub(crate) fn load(&self) -> Option<(proc_macro::TokenStream, Vec<String>)> {
+        let entry = std::fs::read_to_string(&self.path).ok()?;
+        let warnings = entry
+            .lines()
+            .map_while(|line| line.strip_prefix(WARNING_PREFIX))
+            .map(str::to_string)
+            .collect();
+        Some((entry.parse().ok()?, warnings))
+    }
+
+    /// Saves the generated code with its warnings and removes the entries of the previous contents.
+    /// A failure only means the next build will generate the code again. Code holding a compile error is not saved:
+    /// what caused it may not be in the inputs of the entry, ie. a file the config refers to.
+    pub(crate) fn store(&self, code: &proc_macro::TokenStream, warnings: &[String]) {
+        if has_compile_error(code.clone().into()) {
+            return;
+        }
+        let code = code.to_string();
+        if std::fs::create_dir_all(&self.dir).is_err() {
+            return;
+        }
+        if let Ok(entries) = std::fs::read_dir(&self.dir) {
+            for entry in entries.flatten() {
+                if entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(&self.prefix)
+                {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        // Written aside first so a concurrent build never reads a partial entry.
+        let mut entry = String::new();
+        for warning in warnings {
+            entry.push_str(WARNING_PREFIX);
+            entry.push_str(&warning.replace('\n', " "));
+            entry.push('\n');
+        }
+        entry.push_str(&code);
+        let partial = self.path.with_extension("partial");
+        if std::fs::write(&partial, entry).is_ok() {
+            let _ = std::fs::rename(&partial, &self.path);
+        }
+    }
+}
+
+/// Whether the code invokes compile_error!, as the errors of the macro do.
+fn has_compile_error(code: proc_macro2::TokenStream) -> bool {
+    let mut previous_is_compile_error = false;
+    code.into_iter().any(|token| {
+        let found = match &token {
+            proc_macro2::TokenTree::Punct(punct) => {
+                previous_is_compile_error && punct.as_char() == '!'
+            }
+            proc_macro2::TokenTree::Group(group) => has_compile_error(group.stream()),
+            _ => false,
+        };
+        previous_is_compile_error =
+            matches!(&token, proc_macro2::TokenTree::Ident(ident) if ident == "compile_error");
+        found
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+
+    #[test]
+    fn test_has_compile_error() {
+        assert!(!has_compile_error(quote! { fn compile_error() {} }));
+        assert!(has_compile_error(quote! {
+            mod app { ::core::compile_error! { "invalid config" } }
+        }));
+    }
+}
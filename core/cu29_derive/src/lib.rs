@@ -1,40 +1,426 @@
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
+use std::collections::HashMap;
+use std::fmt::Display;
 
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::meta::parser;
 use syn::Fields::{Named, Unnamed};
 use syn::{
-    parse_macro_input, parse_quote, parse_str, Field, ItemImpl, ItemStruct, LitStr, Type, TypeTuple,
+    parse_macro_input, parse_quote, parse_str, Field, ItemImpl, ItemStruct, LitBool, LitStr, Type,
+    TypeTuple,
 };
 
+use cache::CodegenCache;
 use cu29::config::read_configuration;
 use cu29::config::CuConfig;
-use cu29::curuntime::{compute_runtime_plan, CuExecutionLoop, CuExecutionUnit, CuTaskType};
+use cu29::config::ADAPTER_TYPE_PREFIX;
+use cu29::config::DEFAULT_COPPERLISTS;
+use cu29::curuntime::{
+    compute_runtime_plan, CuExecutionLoop, CuExecutionStep, CuExecutionUnit, CuTaskType,
+};
+use cu29::errorpolicy::CuErrorPolicy;
+use cu29::faults::CuFaultInjector;
+use cu29::lint::{check_assertions, lint_config};
+use cu29::parallel::compute_parallel_stages;
 use format::{highlight_rust_code, rustfmt_generated_code};
 
+mod cache;
 mod format;
 mod utils;
 
-// TODO: this needs to be determined when the runtime is sizing itself.
-const DEFAULT_CLNB: usize = 10;
-
 #[inline]
 fn int2sliceindex(i: u32) -> syn::Index {
     syn::Index::from(i as usize)
 }
 
+/// The inputs of a task as a tuple of references, an input the fault injection drops being replaced by an
+/// empty message. The first token stream declares these empty messages so they outlive the tuple.
+/// producers maps the copper list slots to the node ids of the tasks producing them.
+fn gen_task_inputs(
+    step: &CuExecutionStep,
+    producers: &HashMap<u32, u32>,
+    faults: Option<&CuFaultInjector>,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let dst = step.node_id;
+    let (declarations, inputs): (Vec<_>, Vec<_>) = step
+        .input_msg_indices_types
+        .iter()
+        .enumerate()
+        .map(|(i, (index, msg_type))| {
+            let culist_index = int2sliceindex(*index);
+            let src = producers[index];
+            if !faults.is_some_and(|faults| faults.injects_drop(src, dst)) {
+                return (quote! {}, quote! { &msgs.#culist_index });
+            }
+            let dropped = format_ident!("dropped_input_{}_{}", dst, i);
+            let msg_type: Type = parse_str(msg_type).unwrap();
+            (
+                quote! { let #dropped; },
+                quote! {
+                    if _inject_drop(&mut self.copper_runtime.faults, #src, #dst) {
+                        #dropped = _CuMsg::<#msg_type>::new(None);
+                        &#dropped
+                    } else {
+                        &msgs.#culist_index
+                    }
+                },
+            )
+        })
+        .unzip();
+    (quote! { #(#declarations)* }, quote! { (#(#inputs),*) })
+}
+
+/// The faults injected before the process call of the task, Ok if the config injects none in it.
+fn gen_inject_before_process(
+    step: &CuExecutionStep,
+    faults: Option<&CuFaultInjector>,
+) -> proc_macro2::TokenStream {
+    let tid = step.node_id as usize;
+    if faults.is_some_and(|faults| faults.injects_before_process(tid)) {
+        quote! { _inject_before_process(&mut self.copper_runtime.faults, #tid) }
+    } else {
+        quote! { _CuResult::Ok(()) }
+    }
+}
+
+/// The provenance of the output of a task: the slots of its inputs holding a payload, read before the output is
+/// borrowed.
+fn gen_task_provenance(step: &CuExecutionStep) -> proc_macro2::TokenStream {
+    let inputs = step.input_msg_indices_types.iter().map(|(index, _)| {
+        let culist_index = int2sliceindex(*index);
+        let slot = *index as u16;
+        quote! { (#slot, msgs.#culist_index.payload().is_some()) }
+    });
+    quote! { _CuProvenance::from_inputs(&[#(#inputs),*]) }
+}
+
+/// The decision of the monitor on the error of a process call, maybe_error, applied to the output of the task,
+/// cumsg_output.
+fn gen_error_decision(step: &CuExecutionStep) -> proc_macro2::TokenStream {
+    let tid = step.node_id as usize;
+    let node_index = int2sliceindex(step.node_id);
+    // The error policy of the node replaces the decision of the monitor, see cu29::errorpolicy.
+    match step.node.get_on_error() {
+        Some(CuErrorPolicy::Ignore) => {
+            return quote! {
+                if maybe_error.is_err() {
+                    debug!("Process: task '{}' errored out during process, ignored by its error policy.", TASKS_IDS[#tid]);
+                    cumsg_output.clear_payload();
+                }
+            }
+        }
+        Some(CuErrorPolicy::Restart) => {
+            return quote! {
+                if let Err(error) = maybe_error {
+                    debug!("Process: task '{}' errored out during process, restarted by its error policy.", TASKS_IDS[#tid]);
+                    cumsg_output.clear_payload();
+                    let before = self.copper_runtime.clock.now();
+                    let outcome = _restart_task(&mut self.copper_runtime.tasks.#node_index, self.copper_runtime.tasks_configs[#tid].as_ref(), &self.copper_runtime.clock);
+                    let after = self.copper_runtime.clock.now();
+                    self.copper_runtime.tasks_stats[#tid].record_lifecycle(_CuTaskState::Start, before, after, &outcome);
+                    if let Err(restart_error) = outcome {
+                        return Err(_CuError::new_with_cause("Task could not be restarted after an error during process.", restart_error)
+                            .add_cause(&error.to_string()));
+                    }
+                }
+            }
+        }
+        Some(CuErrorPolicy::Abort) => {
+            return quote! {
+                if let Err(error) = maybe_error {
+                    debug!("Process: task '{}' errored out during process, aborting the application as its error policy.", TASKS_IDS[#tid]);
+                    return Err(_CuError::new_with_cause("Task errored out during process.", error));
+                }
+            }
+        }
+        Some(CuErrorPolicy::Retry(_)) | None => {}
+    }
+    quote! {
+        if let Err(error) = maybe_error {
+            let decision = self.copper_runtime.monitor.process_error(#tid, _CuTaskState::Process, &error);
+            match decision {
+                _Decision::Abort => {
+                    debug!("Process: ABORT decision from monitoring. Task '{}' errored out \
+                    during process. Skipping the processing of CL {}.", TASKS_IDS[#tid], id);
+                    self.copper_runtime.monitor.process_copperlist(&collect_metadata(&culist))?;
+                    self.copper_runtime.end_of_processing(id);
+                    return Ok(()); // this returns early from the one iteration call.
+
+                }
+                _Decision::Ignore => {
+                    debug!("Process: IGNORE decision from monitoring. Task '{}' errored out \
+                    during process. The runtime will continue with a forced empty message.", TASKS_IDS[#tid]);
+                    cumsg_output.clear_payload();
+                }
+                _Decision::Shutdown => {
+                    debug!("Process: SHUTDOWN decision from monitoring. Task '{}' errored out \
+                    during process. The runtime cannot continue.", TASKS_IDS[#tid]);
+                    return Err(_CuError::new_with_cause("Task errored out during process.", error));
+                }
+            }
+        }
+    }
+}
+
+/// The process call of the task, called again on error for a Retry error policy, see cu29::errorpolicy.
+fn gen_retry(
+    step: &CuExecutionStep,
+    process: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    match step.node.get_on_error() {
+        Some(CuErrorPolicy::Retry(attempts)) => quote! { _retry_process(#attempts, || #process) },
+        _ => process,
+    }
+}
+
+/// Whether the task might not run in an iteration: it has a base_period_ns (see cu29::multirate) or it is optional
+/// and can be pruned (see cu29::optional).
+fn is_conditional(step: &CuExecutionStep) -> bool {
+    step.node.get_base_period_ns().is_some() || step.node.is_optional()
+}
+
+/// Whether the task runs in the current iteration: due_<node id> for the conditional tasks, unless disabled for the
+/// others (see CuRuntime::disable_task).
+fn gen_task_due(step: &CuExecutionStep) -> proc_macro2::TokenStream {
+    if is_conditional(step) {
+        let due = format_ident!("due_{}", step.node_id);
+        quote! { #due }
+    } else {
+        let tid = step.node_id as usize;
+        quote! { !self.copper_runtime.disabled[#tid] }
+    }
+}
+
+/// Whether the task runs with a priority or a core affinity, see cu29::threads::CuThreadScheduling.
+fn has_scheduling(step: &CuExecutionStep) -> bool {
+    step.node.get_priority().is_some() || !step.node.get_core_affinity().is_empty()
+}
+
+/// A stage of the parallel mode (see cu29::parallel): the steps independent from each other run on the workers,
+/// the bookkeeping of the runtime is done before and after them on the thread of the runtime, in plan order.
+fn gen_parallel_stage(
+    steps: &[&CuExecutionStep],
+    producers: &HashMap<u32, u32>,
+    faults: Option<&CuFaultInjector>,
+) -> proc_macro2::TokenStream {
+    let ident = |name: &str, step: &CuExecutionStep| format_ident!("{}_{}", name, step.node_id);
+    let output_index = |step: &CuExecutionStep| {
+        let (index, _) = step
+            .output_msg_index_type
+            .as_ref()
+            .expect("Every task has an output message index.");
+        int2sliceindex(*index)
+    };
+
+    // The inputs, the faults and the lateness of the connections of the tasks due, before the stage.
+    let prepare = steps.iter().map(|step| {
+        let tid = step.node_id as usize;
+        let due = gen_task_due(step);
+        let pending = ident("pending", step);
+        let inject = gen_inject_before_process(step, faults);
+        match step.task_type {
+            CuTaskType::Source => quote! {
+                let #pending = if #due {
+                    let prepared = #inject;
+                    Some((_CuProvenance::default(), (), prepared))
+                } else {
+                    None
+                };
+            },
+            _ => {
+                let (dropped_inputs, inputs) = gen_task_inputs(step, producers, faults);
+                let task_provenance = gen_task_provenance(step);
+                quote! {
+                    #dropped_inputs
+                    let #pending = if #due {
+                        let provenance = #task_provenance;
+                        let cumsg_input = #inputs;
+                        let prepared = _record_edges_delivery(&mut self.copper_runtime.edges_stats, &self.copper_runtime.edges_by_dst[#tid], stage_start)
+                            .and_then(|()| #inject);
+                        Some((provenance, cumsg_input, prepared))
+                    } else {
+                        None
+                    };
+                }
+            }
+        }
+    });
+
+    let tids: Vec<usize> = steps.iter().map(|step| step.node_id as usize).collect();
+    let scratches: Vec<_> = steps.iter().map(|step| ident("scratch", step)).collect();
+    let stats: Vec<_> = steps.iter().map(|step| ident("stats", step)).collect();
+    let outcomes: Vec<_> = steps.iter().map(|step| ident("outcome", step)).collect();
+    let borrows = steps.iter().map(|step| {
+        let node_index = int2sliceindex(step.node_id);
+        let task = ident("task", step);
+        let cumsg_output = ident("cumsg_output", step);
+        let output_culist_index = output_index(step);
+        quote! {
+            let #task = &mut self.copper_runtime.tasks.#node_index;
+            let #cumsg_output = &mut msgs.#output_culist_index;
+        }
+    });
+
+    // The process calls of the tasks due, on the workers but the last one.
+    let bodies: Vec<_> = steps
+        .iter()
+        .map(|step| {
+            let tid = step.node_id as usize;
+            let node_id = step.node_id;
+            let comment_str = format!(
+                "/// {} ({:?}) Id:{}",
+                step.node.get_id(),
+                step.task_type,
+                step.node_id
+            );
+            let comment_tokens: proc_macro2::TokenStream = parse_str(&comment_str).unwrap();
+            let task = ident("task", step);
+            let cumsg_input = ident("cumsg_input", step);
+            let cumsg_output = ident("cumsg_output", step);
+            let provenance = ident("provenance", step);
+            let prepared = ident("prepared", step);
+            let pending = ident("pending", step);
+            let scratch = ident("scratch", step);
+            let stats = ident("stats", step);
+            let input_binding = match step.task_type {
+                CuTaskType::Source => quote! { _ },
+                _ => quote! { #cumsg_input },
+            };
+            let process = match step.task_type {
+                CuTaskType::Source => quote! { #task.process(&ctx, #cumsg_output) },
+                CuTaskType::Sink => quote! { #task.process(&ctx, #cumsg_input) },
+                CuTaskType::Regular => quote! { #task.process(&ctx, #cumsg_input, #cumsg_output) },
+            };
+            let process = gen_retry(step, process);
+            let body = quote! {
+                if let Some((#provenance, #input_binding, #prepared)) = #pending {
+                    #comment_tokens
+                    let before_process = clock.now();
+                    #cumsg_output.metadata.before_process = before_process.into();
+                    #cumsg_output.metadata.provenance = #provenance;
+                    let dt = #stats.time_since_last_process(before_process);
+                    #scratch.reset();
+                    let ctx = _CuContext::new(clock, dt)
+                        .with_node(#node_id, TASKS_IDS[#tid], tasks_configs[#tid].as_ref())
+                        .with_pools(pools)
+                        .with_scratch(#scratch)
+                        .with_edges(edges_stats)
+                        .with_clock_domains(clock_domains)
+                        .with_param_requests(param_requests);
+                    _set_current_task(Some(TASKS_IDS[#tid]));
+                    _set_attributed_task(Some(#tid));
+                    let maybe_error = #prepared.and_then(|()| #process);
+                    _set_current_task(None);
+                    _set_attributed_task(None);
+                    let after_process = clock.now();
+                    #cumsg_output.metadata.after_process = after_process.into();
+                    #stats.record_process_time(before_process, after_process);
+                    #stats.record_process(#cumsg_output.metadata.before_process, &maybe_error);
+                    #stats.record_reported_health(ctx.reported_health());
+                    #stats.record_scratch(#scratch.used());
+                    #stats.record_state(#task.current_state());
+                    Some((maybe_error, before_process))
+                } else {
+                    None
+                }
+            };
+            // On the thread of the task if it has a priority or a core affinity, see cu29::parallel.
+            if has_scheduling(step) {
+                quote! { workers.run_on(#tid, move || #body) }
+            } else {
+                body
+            }
+        })
+        .collect();
+    let (last_body, spawned_bodies) = bodies.split_last().expect("A stage has steps.");
+    let (last_outcome, spawned_outcomes) = outcomes.split_last().expect("A stage has steps.");
+
+    // The connections and the errors, after the stage.
+    let conclude = steps.iter().map(|step| {
+        let tid = step.node_id as usize;
+        let outcome = ident("outcome", step);
+        let output_culist_index = output_index(step);
+        let error_decision = gen_error_decision(step);
+        quote! {
+            if let Some((maybe_error, before_process)) = #outcome {
+                let cumsg_output = &mut msgs.#output_culist_index;
+                _record_edges_output(&mut self.copper_runtime.edges_stats, &self.copper_runtime.edges_by_src[#tid], before_process, maybe_error.is_ok() && cumsg_output.payload().is_some());
+                #error_decision
+            }
+        }
+    });
+
+    quote! {
+        {
+            let stage_start = self.copper_runtime.clock.now();
+            #(#prepare)*
+            let [#(#scratches),*] = self.copper_runtime.scratches.get_disjoint_mut([#(#tids),*]).expect("The tasks of a stage are distinct.");
+            let [#(#stats),*] = self.copper_runtime.tasks_stats.get_disjoint_mut([#(#tids),*]).expect("The tasks of a stage are distinct.");
+            #(let mut #outcomes = None;)*
+            {
+                let clock = &self.copper_runtime.clock;
+                let tasks_configs = &self.copper_runtime.tasks_configs;
+                let pools = &self.copper_runtime.pools;
+                let edges_stats = &self.copper_runtime.edges_stats;
+                let clock_domains = &self.copper_runtime.clock_domains;
+                let param_requests = &self.copper_runtime.param_requests;
+                #(#borrows)*
+                let workers = self.copper_runtime.workers.as_ref().expect("The parallel mode has workers.");
+                workers.in_place_scope(|scope| {
+                    #(
+                        let #spawned_outcomes = &mut #spawned_outcomes;
+                        scope.spawn(move |_| *#spawned_outcomes = #spawned_bodies);
+                    )*
+                    #last_outcome = #last_body;
+                });
+            }
+            #(#conclude)*
+        }
+    }
+}
+
+/// Reports a problem in the config as a compile error pointing at its path in the macro invocation.
+fn config_error(config_lit: &LitStr, message: impl Display) -> TokenStream {
+    syn::Error::new(
+        config_lit.span(),
+        format!("in copper config {}: {}", config_label(config_lit), message),
+    )
+    .to_compile_error()
+    .into()
+}
+
+/// The path of the config in the messages, or "inline" for a config written in the attribute itself.
+fn config_label(config_lit: &LitStr) -> String {
+    let config = config_lit.value();
+    if config.trim_start().starts_with('(') {
+        "inline".to_string()
+    } else {
+        format!("{:?}", config)
+    }
+}
+
+/// Unwraps the result or returns its error as a compile error on the config path.
+macro_rules! try_config {
+    ($config_lit:expr, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(error) => return config_error(&$config_lit, error),
+        }
+    };
+}
+
 /// Generates the CopperList content type from a config.
 /// gen_cumsgs!("path/to/config.toml")
 /// It will create a new type called CuMsgs you can pass to the log reader for decoding:
 #[proc_macro]
 pub fn gen_cumsgs(config_path_lit: TokenStream) -> TokenStream {
-    let config = parse_macro_input!(config_path_lit as LitStr).value();
+    let config_lit = parse_macro_input!(config_path_lit as LitStr);
+    let config = config_lit.value();
     eprintln!("[gen culist support with {:?}]", config);
-    let cuconfig = read_config(&config);
-    let runtime_plan: CuExecutionLoop =
-        compute_runtime_plan(&cuconfig).expect("Could not compute runtime plan");
+    let cuconfig = try_config!(config_lit, read_config(&config));
+    let runtime_plan: CuExecutionLoop = try_config!(config_lit, compute_runtime_plan(&cuconfig));
 
     // All accesses are linear on the culist but the id of the tasks is random (determined by the Ron declaration order).
     // This records the task ids in call order.
@@ -47,7 +433,7 @@ pub fn gen_cumsgs(config_path_lit: TokenStream) -> TokenStream {
         })
         .collect();
 
-    let support = gen_culist_support(&runtime_plan, &taskid_order);
+    let support = try_config!(config_lit, gen_culist_support(&runtime_plan, &taskid_order));
 
     let with_uses = quote! {
         use bincode::Encode as _Encode;
@@ -57,6 +443,11 @@ pub fn gen_cumsgs(config_path_lit: TokenStream) -> TokenStream {
         use bincode::de::Decoder as _Decoder;
         use bincode::error::DecodeError as _DecodeError;
         use cu29::copperlist::CopperList as _CopperList;
+        use cu29::copperlist::CuListDumper as _CuListDumper;
+        use cu29::copperlist::CuMsgDump as _CuMsgDump;
+        use cu29::clock::CuTime as _CuTime;
+        use cu29::summary::CuChannelStats as _CuChannelStats;
+        use cu29::dynmsg::DynCuMsg as _DynCuMsg;
         use cu29::cutask::CuMsgMetadata as _CuMsgMetadata;
         #support
     };
@@ -67,9 +458,9 @@ pub fn gen_cumsgs(config_path_lit: TokenStream) -> TokenStream {
 fn gen_culist_support(
     runtime_plan: &CuExecutionLoop,
     taskid_call_order: &Vec<usize>,
-) -> proc_macro2::TokenStream {
+) -> Result<proc_macro2::TokenStream, String> {
     eprintln!("[Extract msgs types]");
-    let all_msgs_types_in_culist_order = extract_msg_types(runtime_plan);
+    let all_msgs_types_in_culist_order = extract_msg_types(runtime_plan)?;
 
     let culist_size = all_msgs_types_in_culist_order.len();
     let task_indices: Vec<_> = taskid_call_order
@@ -87,6 +478,9 @@ fn gen_culist_support(
     eprintln!("[build the copperlist tuple debug support]");
     let msgs_types_tuple_debug = build_culist_tuple_debug(&all_msgs_types_in_culist_order);
 
+    eprintln!("[build the copperlist tuple dump support]");
+    let msgs_types_tuple_dump = build_culist_tuple_dump(runtime_plan)?;
+
     let collect_metadata_function = quote! {
         pub fn collect_metadata<'a>(culist: &'a CuList) -> [&'a _CuMsgMetadata; #culist_size] {
             [#( &culist.msgs.0.#task_indices.metadata, )*]
@@ -94,7 +488,7 @@ fn gen_culist_support(
     };
 
     // This generates a way to get the metadata of every single message of a culist at low cost
-    quote! {
+    Ok(quote! {
         #collect_metadata_function
 
         pub struct CuMsgs(#msgs_types_tuple);
@@ -106,21 +500,42 @@ fn gen_culist_support(
 
         // Adds the debug support
         #msgs_types_tuple_debug
-    }
+
+        // Adds the structured dump support
+        #msgs_types_tuple_dump
+    })
 }
 
 /// Adds #[copper_runtime(config = "path")] to your application struct to generate the runtime.
 /// This will add a "runtime" field to your struct and implement the "new" and "run" methods.
+///
+/// With #[copper_runtime(config = "path", library = true)], the runtime is meant to be embedded in another
+/// application stepping it (a GUI, a game engine tick, another scheduler): "new" takes a mock clock the
+/// runtime advances on every "step(dt)", and there is no "run" loop.
+///
+/// Next to the struct it generates names for the tasks of the config: for a task "imu", `ImuTask` is the type of
+/// the task and `ImuOutput` the type of the message it sends, `NodeIds::imu` its id,
+/// and `application.task_mut::<ImuTask>(NodeIds::imu)` gives access to the task itself. A connection from "imu" to
+/// "fusion" is `EdgeIds::imu_to_fusion`, with its statistics in `application.edge_stats(EdgeIds::imu_to_fusion)`.
+///
+/// The generated code is cached in the OUT_DIR of the crate and reused as long as the config, the attribute and
+/// the struct do not change. Set CU29_NO_CODEGEN_CACHE in the environment of the build to disable it.
 #[proc_macro_attribute]
 pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
     eprintln!("[entry]");
-    let mut item_struct = parse_macro_input!(input as ItemStruct);
+    let start = std::time::Instant::now();
+    let (args_src, input_src) = (args.to_string(), input.to_string());
+    let item_struct = parse_macro_input!(input as ItemStruct);
 
     let mut config_file: Option<LitStr> = None;
+    let mut library = false;
     let attribute_config_parser = parser(|meta| {
         if meta.path.is_ident("config") {
             config_file = Some(meta.value()?.parse()?);
             Ok(())
+        } else if meta.path.is_ident("library") {
+            library = meta.value()?.parse::<LitBool>()?.value;
+            Ok(())
         } else {
             Err(meta.error("unsupported property"))
         }
@@ -128,32 +543,339 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
 
     eprintln!("[parse]");
     parse_macro_input!(args with attribute_config_parser);
-    let config_file = config_file
-        .expect("Expected config file attribute like #[CopperRuntime(config = \"path\")]")
-        .value();
-    let copper_config = read_config(&config_file);
+    let Some(config_lit) = config_file else {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "Expected a config file attribute like #[copper_runtime(config = \"path\")]",
+        )
+        .to_compile_error()
+        .into();
+    };
+    let config_file = config_lit.value();
+    let config_path = try_config!(config_lit, config_full_path(&config_file));
+    // An unreadable config is not cached, it is reported when reading the configuration below.
+    let cache = std::fs::read(&config_path).ok().and_then(|content| {
+        CodegenCache::new(
+            &item_struct.ident.to_string(),
+            &[
+                args_src.as_bytes(),
+                input_src.as_bytes(),
+                config_path.as_bytes(),
+                &content,
+            ],
+        )
+    });
+    if let Some((tokens, warnings)) = cache.as_ref().and_then(CodegenCache::load) {
+        for warning in warnings {
+            eprintln!("{}", warning);
+        }
+        eprintln!("[codegen cache hit in {:?}]", start.elapsed());
+        return tokens;
+    }
+    let copper_config = try_config!(
+        config_lit,
+        read_configuration(&config_path).map_err(|e| e.to_string())
+    );
+    let warnings = config_warnings(&config_lit, &copper_config);
+    for warning in &warnings {
+        eprintln!("{}", warning);
+    }
+    let read_config = quote! { _read_configuration(#config_file)? };
+    let tokens = gen_runtime(
+        item_struct,
+        &config_lit,
+        &copper_config,
+        read_config,
+        Some(&config_file),
+        library,
+    );
+    eprintln!("[generated in {:?}]", start.elapsed());
+    if let Some(cache) = &cache {
+        cache.store(&tokens, &warnings);
+    }
+
+    // Print and format the generated code using rustfmt
+    // println!("Generated tokens: {}", tokens);
+    let formatted_code = rustfmt_generated_code(tokens.to_string());
+    eprintln!("\n     ===    Gen. Runtime ===\n");
+    eprintln!("{}", highlight_rust_code(formatted_code));
+    eprintln!("\n     === === === === === ===\n");
+
+    tokens
+}
+
+/// Runs a miniature copper application in a test:
+///
+/// ```ignore
+/// #[copper_test(config = r#"( tasks: [...], cnx: [...] )"#, mocks(src = MockSrc), iterations = 5, period = "10ms")]
+/// fn sink_receives_everything(app: &mut CopperTestApp, run: &CuTestRun) {
+///     assert_eq!(run.outputs("src").len(), 5);
+///     assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 5);
+/// }
+/// ```
+///
+/// The config is inline, the tasks listed in mocks are replaced by the given types. The test steps the runtime the
+/// number of iterations (10 by default) on a mock clock advanced by the period (the one of the runtime section of the
+/// config or 1ms by default), records the messages of every iteration then calls the body with the stopped
+/// application. The runtime is generated in a module named after the test, with the task names of copper_runtime.
+#[proc_macro_attribute]
+pub fn copper_test(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut body = parse_macro_input!(input as syn::ItemFn);
+    let name = body.sig.ident.clone();
+
+    let mut config_lit: Option<LitStr> = None;
+    let mut mocks: Vec<(syn::Ident, syn::Path)> = Vec::new();
+    let mut iterations: u64 = 10;
+    let mut period_lit: Option<LitStr> = None;
+    let attribute_parser = parser(|meta| {
+        if meta.path.is_ident("config") {
+            config_lit = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("mocks") {
+            meta.parse_nested_meta(|mock| {
+                let task = mock.path.require_ident()?.clone();
+                mocks.push((task, mock.value()?.parse()?));
+                Ok(())
+            })
+        } else if meta.path.is_ident("iterations") {
+            iterations = meta.value()?.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(())
+        } else if meta.path.is_ident("period") {
+            period_lit = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported property"))
+        }
+    });
+    parse_macro_input!(args with attribute_parser);
+    let Some(config_lit) = config_lit else {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "Expected an inline config like #[copper_test(config = r#\"( tasks: [...], cnx: [...] )\"#)]",
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    let mut raw_config = try_config!(
+        config_lit,
+        CuConfig::try_deserialize_ron(&config_lit.value())
+    );
+    for (task, mock) in &mocks {
+        let Some(node) = raw_config
+            .graph
+            .node_weights_mut()
+            .find(|node| *task == node.get_id())
+        else {
+            return syn::Error::new(task.span(), format!("there is no task '{}' to mock", task))
+                .to_compile_error()
+                .into();
+        };
+        let mock_type = quote! { #mock }.to_string().replace(' ', "");
+        *node = node.clone().set_type(Some(mock_type));
+    }
+    let config_text = raw_config.serialize_ron();
+    let copper_config = try_config!(
+        config_lit,
+        cu29::config::read_configuration_str(&config_text).map_err(|e| e.to_string())
+    );
+
+    let period_ns = match &period_lit {
+        Some(period_lit) => {
+            let period = cu29::units::CuUnitValue::parse(&period_lit.value())
+                .and_then(|period| period.to_si("duration", cu29::units::TIME_UNITS));
+            match period {
+                Ok(s) if s > 0.0 => (s * 1e9).round() as u64,
+                Ok(_) => {
+                    return syn::Error::new(period_lit.span(), "the period must be positive")
+                        .to_compile_error()
+                        .into()
+                }
+                Err(e) => {
+                    return syn::Error::new(period_lit.span(), e.to_string())
+                        .to_compile_error()
+                        .into()
+                }
+            }
+        }
+        None => copper_config
+            .get_runtime_config()
+            .and_then(|runtime| runtime.period_ns)
+            .unwrap_or(1_000_000),
+    };
+
+    for warning in config_warnings(&config_lit, &copper_config) {
+        eprintln!("{}", warning);
+    }
+    let app_struct: ItemStruct = parse_quote! { struct CopperTestApp {} };
+    let read_config = quote! { cu29::config::read_configuration_str(#config_text)? };
+    let runtime: proc_macro2::TokenStream = gen_runtime(
+        app_struct,
+        &config_lit,
+        &copper_config,
+        read_config,
+        None,
+        true,
+    )
+    .into();
+
+    body.vis = parse_quote! { pub(super) };
+    let log_name = name.to_string();
+    quote! {
+        mod #name {
+            use super::*;
+
+            #runtime
+
+            #body
+        }
+
+        #[test]
+        fn #name() {
+            // Declared first so the log outlives the runtime writing to it.
+            let log = cu29::testing::CuTestLog::new(#log_name).expect("Failed to create the test log.");
+            let (clock, clock_mock) = cu29::clock::RobotClock::mock();
+            let mut app = #name::CopperTestApp::new(clock, clock_mock, log.logger.clone())
+                .expect("Failed to create the test application.");
+            app.start_all_tasks().expect("Failed to start the tasks.");
+            let mut run = cu29::testing::CuTestRun::default();
+            for _ in 0..#iterations {
+                app.step(cu29::clock::CuDuration(#period_ns)).expect("Failed to step the test application.");
+                run.record(app.dump_last_iteration());
+            }
+            app.stop_all_tasks().expect("Failed to stop the tasks.");
+            #name::#name(&mut app, &run);
+        }
+    }
+    .into()
+}
+
+/// The lints of the config, as printed while building.
+fn config_warnings(config_lit: &LitStr, copper_config: &CuConfig) -> Vec<String> {
+    lint_config(copper_config)
+        .into_iter()
+        .map(|lint| {
+            format!(
+                "warning: in copper config {}: {}",
+                config_label(config_lit),
+                lint
+            )
+        })
+        .collect()
+}
+
+/// Generates the runtime of the application struct for the config, read at run time by read_config.
+fn gen_runtime(
+    mut item_struct: ItemStruct,
+    config_lit: &LitStr,
+    copper_config: &CuConfig,
+    read_config: proc_macro2::TokenStream,
+    watched_file: Option<&str>,
+    library: bool,
+) -> TokenStream {
+    let failures = check_assertions(copper_config);
+    if !failures.is_empty() {
+        return config_error(
+            config_lit,
+            format!("failed assertions: {}", failures.join("; ")),
+        );
+    }
+    // The tasks and connections the faults are injected in are checked at build time rather than at start up.
+    let faults = match CuFaultInjector::from_config(copper_config) {
+        Ok(faults) => faults,
+        Err(error) => return config_error(config_lit, error),
+    };
+
+    // The number of copper lists is a parameter of the type of the runtime.
+    let copperlists = copper_config
+        .get_runtime_config()
+        .and_then(|runtime| runtime.copperlists)
+        .unwrap_or(DEFAULT_COPPERLISTS);
+    if copperlists == 0 {
+        return config_error(
+            config_lit,
+            "copperlists in the runtime section cannot be 0, every iteration needs a copper list.",
+        );
+    }
 
     eprintln!("[runtime plan]");
     let runtime_plan: CuExecutionLoop =
-        compute_runtime_plan(&copper_config).expect("Could not compute runtime plan");
+        try_config!(config_lit, compute_runtime_plan(copper_config));
     eprintln!("{:?}", runtime_plan);
 
     eprintln!("[extract tasks ids & types]");
     let (all_tasks_ids, all_tasks_types_names, all_tasks_types) =
-        extract_tasks_types(&copper_config);
+        try_config!(config_lit, extract_tasks_types(copper_config));
     eprintln!("tasks types: {:?}", all_tasks_types_names);
 
+    eprintln!("[build type aliases and node ids]");
+    let (type_aliases, node_ids, task_accessors) = try_config!(
+        config_lit,
+        gen_task_names(
+            copper_config,
+            &runtime_plan,
+            &all_tasks_ids,
+            &all_tasks_types
+        )
+    );
+
+    // The runtime holds the optional tasks in a CuOptional, see cu29::optional.
+    let all_tasks_optional: Vec<bool> = copper_config
+        .get_all_nodes()
+        .iter()
+        .map(|node| node.is_optional())
+        .collect();
+    let all_tasks_held_types: Vec<Type> = all_tasks_types
+        .iter()
+        .zip(&all_tasks_optional)
+        .map(|(ty, optional)| {
+            if *optional {
+                parse_quote! { _CuOptional<#ty> }
+            } else {
+                ty.clone()
+            }
+        })
+        .collect();
+
     eprintln!("[build task tuples]");
     // Build the tuple of all those types
     // note the extraneous , at the end is to make the tuple work even if this is only one element
     let task_types_tuple: TypeTuple = parse_quote! {
-        (#(#all_tasks_types),*,)
+        (#(#all_tasks_held_types),*,)
     };
 
+    eprintln!("[build task trait checks]");
+    // Checks up front that each task implements the trait of its place in the graph,
+    // so a wrong type in the config gets the diagnostic of the trait instead of a wall of missing methods.
+    let task_trait_checks: Vec<proc_macro2::TokenStream> = runtime_plan
+        .steps
+        .iter()
+        .filter_map(|unit| match unit {
+            CuExecutionUnit::Step(step) => Some(step),
+            CuExecutionUnit::Loop(_) => None,
+        })
+        .map(|step| {
+            let task_type = &all_tasks_types[step.node_id as usize];
+            match step.task_type {
+                CuTaskType::Source => quote! { _is_src_task::<#task_type>(); },
+                CuTaskType::Regular => quote! { _is_task::<#task_type>(); },
+                CuTaskType::Sink => quote! { _is_sink_task::<#task_type>(); },
+            }
+        })
+        .collect();
+
+    eprintln!("[build transform adapters]");
+    let adapters_code = try_config!(config_lit, gen_transform_adapters(copper_config));
+
     eprintln!("[build monitor type]");
     let monitor_type = if let Some(monitor_config) = copper_config.get_monitor_config() {
-        let monitor_type = parse_str::<Type>(monitor_config.get_type())
-            .expect("Could not transform the monitor type name into a Rust type.");
+        let monitor_type = try_config!(
+            config_lit,
+            parse_str::<Type>(monitor_config.get_type()).map_err(|_| format!(
+                "the monitor type '{}' is not a valid Rust type.",
+                monitor_config.get_type()
+            ))
+        );
         quote! { #monitor_type }
     } else {
         quote! { _NoMonitor }
@@ -162,18 +884,26 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
     eprintln!("[build runtime field]");
     // add that to a new field
     let runtime_field: Field = parse_quote! {
-        copper_runtime: _CuRuntime<CuTasks, CuMsgs, #monitor_type, #DEFAULT_CLNB>
+        copper_runtime: _CuRuntime<CuTasks, CuMsgs, #monitor_type, #copperlists>
     };
 
     let name = &item_struct.ident;
 
+    // In library mode, the runtime also keeps the control of the clock to advance it on every step.
+    let mut added_fields = vec![runtime_field];
+    if library {
+        added_fields.push(parse_quote! {
+            copper_clock_mock: _RobotClockMock
+        });
+    }
+
     eprintln!("[match struct anonymity]");
     match &mut item_struct.fields {
         Named(fields_named) => {
-            fields_named.named.push(runtime_field);
+            fields_named.named.extend(added_fields);
         }
         Unnamed(fields_unnamed) => {
-            fields_unnamed.unnamed.push(runtime_field);
+            fields_unnamed.unnamed.extend(added_fields);
         }
         _ => (),
     };
@@ -181,11 +911,13 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
     eprintln!("[gen instances]");
     // Generate the code to create instances of the nodes
     // It maps the types to their index
+    // The lifecycle methods of the tasks stubbed by a replay are not called.
     let (task_instances_init_code,
         start_calls,
         stop_calls,
         preprocess_calls,
-        postprocess_calls): (Vec<_>, Vec<_>, Vec<_>, Vec<_>, Vec<_>) = itertools::multiunzip(all_tasks_types
+        postprocess_calls,
+        self_test_calls): (Vec<_>, Vec<_>, Vec<_>, Vec<_>, Vec<_>, Vec<_>) = itertools::multiunzip(all_tasks_held_types
         .iter()
         .enumerate()
         .map(|(index, ty)| {
@@ -194,14 +926,34 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                 "Failed to get create instance for {}, instance index {}.",
                 all_tasks_types_names[index], index
             );
+            // An optional task that fails to start is pruned, see cu29::optional.
+            let prune_on_start_error = all_tasks_optional[index].then(|| quote! {
+                let outcome = match outcome {
+                    Err(error) => {
+                        self.copper_runtime.prune_task(#index, &error);
+                        Ok(())
+                    }
+                    ok => ok,
+                };
+            });
             (
                 quote! {
-                    #ty::new(all_instances_configs[#index]).map_err(|e| e.add_cause(#additional_error_info))?
+                    <#ty as _CuTaskLifecycle>::new(all_instances_configs[#index]).map_err(|e| e.add_cause(#additional_error_info))?
                 },
                 quote! {
-                    {
+                    if !self.copper_runtime.stubbed[#index] && !self.copper_runtime.pruned[#index] {
                         let task = &mut self.copper_runtime.tasks.#task_index;
-                        if let Err(error) = task.start(&self.copper_runtime.clock) {
+                        let before = self.copper_runtime.clock.now();
+                        let outcome = task.start(&self.copper_runtime.clock);
+                        let after = self.copper_runtime.clock.now();
+                        self.copper_runtime.tasks_stats[#index].record_lifecycle(_CuTaskState::Start, before, after, &outcome);
+                        if outcome.is_ok() {
+                            let duration = after - before;
+                            let count = self.copper_runtime.tasks_stats[#index].start_count;
+                            debug!("Lifecycle: task '{}' started (#{}) in {}.", TASKS_IDS[#index], count, duration);
+                        }
+                        #prune_on_start_error
+                        if let Err(error) = outcome {
                             let decision = self.copper_runtime.monitor.process_error(#index, _CuTaskState::Start, &error);
                             match decision {
                                 _Decision::Abort => {
@@ -224,9 +976,17 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                     }
                 },
                 quote! {
-                    {
+                    if !self.copper_runtime.stubbed[#index] && !self.copper_runtime.pruned[#index] {
                         let task = &mut self.copper_runtime.tasks.#task_index;
-                        if let Err(error) = task.stop(&self.copper_runtime.clock) {
+                        let before = self.copper_runtime.clock.now();
+                        let outcome = task.stop(&self.copper_runtime.clock);
+                        let after = self.copper_runtime.clock.now();
+                        self.copper_runtime.tasks_stats[#index].record_lifecycle(_CuTaskState::Stop, before, after, &outcome);
+                        if outcome.is_ok() {
+                            let duration = after - before;
+                            debug!("Lifecycle: task '{}' stopped in {}.", TASKS_IDS[#index], duration);
+                        }
+                        if let Err(error) = outcome {
                             let decision = self.copper_runtime.monitor.process_error(#index, _CuTaskState::Stop, &error);
                             match decision {
                                 _Decision::Abort => {
@@ -249,9 +1009,13 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                     }
                 },
                 quote! {
-                    {
+                    if !self.copper_runtime.stubbed[#index] && !self.copper_runtime.pruned[#index] {
                         let task = &mut self.copper_runtime.tasks.#task_index;
-                        if let Err(error) = task.preprocess(&self.copper_runtime.clock) {
+                        let before = self.copper_runtime.clock.now();
+                        let outcome = task.preprocess(&self.copper_runtime.clock);
+                        let after = self.copper_runtime.clock.now();
+                        self.copper_runtime.tasks_stats[#index].record_lifecycle(_CuTaskState::Preprocess, before, after, &outcome);
+                        if let Err(error) = outcome {
                             let decision = self.copper_runtime.monitor.process_error(#index, _CuTaskState::Preprocess, &error);
                             match decision {
                                 _Decision::Abort => {
@@ -274,9 +1038,13 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                     }
                 },
                 quote! {
-                    {
+                    if !self.copper_runtime.stubbed[#index] && !self.copper_runtime.pruned[#index] {
                         let task = &mut self.copper_runtime.tasks.#task_index;
-                        if let Err(error) = task.postprocess(&self.copper_runtime.clock) {
+                        let before = self.copper_runtime.clock.now();
+                        let outcome = task.postprocess(&self.copper_runtime.clock);
+                        let after = self.copper_runtime.clock.now();
+                        self.copper_runtime.tasks_stats[#index].record_lifecycle(_CuTaskState::Postprocess, before, after, &outcome);
+                        if let Err(error) = outcome {
                             let decision = self.copper_runtime.monitor.process_error(#index, _CuTaskState::Postprocess, &error);
                             match decision {
                                 _Decision::Abort => {
@@ -297,15 +1065,70 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                             }
                         }
                     }
+                },
+                quote! {
+                    if self.copper_runtime.stubbed[#index] {
+                        report.record_stubbed(TASKS_IDS[#index]);
+                    } else if self.copper_runtime.pruned[#index] {
+                        report.record_pruned(TASKS_IDS[#index]);
+                    } else {
+                        let task = &mut self.copper_runtime.tasks.#task_index;
+                        let before = self.copper_runtime.clock.now();
+                        let outcome = task.self_test(&self.copper_runtime.clock);
+                        let after = self.copper_runtime.clock.now();
+                        if let Err(error) = &outcome {
+                            let error = error.to_string();
+                            debug!("Self test: task '{}' failed: {}", TASKS_IDS[#index], error);
+                        }
+                        report.record(TASKS_IDS[#index], outcome, after - before);
+                    }
                 }
             )
         })
     );
 
+    // The lifecycle calls follow the order of the plan, like the process calls, not the order of declaration.
+    let mut lifecycle_order: Vec<usize> = runtime_plan
+        .steps
+        .iter()
+        .filter_map(|unit| match unit {
+            CuExecutionUnit::Step(step) => Some(step.node_id as usize),
+            CuExecutionUnit::Loop(_) => None,
+        })
+        .collect();
+    let unplanned: Vec<usize> = (0..all_tasks_types.len())
+        .filter(|i| !lifecycle_order.contains(i))
+        .collect();
+    lifecycle_order.extend(unplanned);
+    let in_plan_order = |calls: Vec<proc_macro2::TokenStream>| -> Vec<proc_macro2::TokenStream> {
+        lifecycle_order.iter().map(|&i| calls[i].clone()).collect()
+    };
+    let start_calls = in_plan_order(start_calls);
+    let stop_calls = in_plan_order(stop_calls);
+    let preprocess_calls = in_plan_order(preprocess_calls);
+    let postprocess_calls = in_plan_order(postprocess_calls);
+    let self_test_calls = in_plan_order(self_test_calls);
+
     // All accesses are linear on the culist but the id of the tasks is random (determined by the Ron declaration order).
     // This records the task ids in call order.
     let mut taskid_call_order: Vec<usize> = Vec::new();
 
+    // The tasks producing the messages of the copper list, for the fault injection on the connections.
+    let producers: HashMap<u32, u32> = runtime_plan
+        .steps
+        .iter()
+        .filter_map(|unit| match unit {
+            CuExecutionUnit::Step(step) => step
+                .output_msg_index_type
+                .as_ref()
+                .map(|(index, _)| (*index, step.node_id)),
+            CuExecutionUnit::Loop(_) => None,
+        })
+        .collect();
+
+    // The process call of every step of the plan, by index in the plan, for the parallel mode.
+    let mut process_calls: Vec<proc_macro2::TokenStream> = Vec::new();
+
     let runtime_plan_code: Vec<proc_macro2::TokenStream> = runtime_plan.steps
         .iter()
         .map(|unit| {
@@ -333,42 +1156,46 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                     );
                     let comment_tokens: proc_macro2::TokenStream = parse_str(&comment_str).unwrap();
                     let tid = step.node_id as usize;
+                    let inject = gen_inject_before_process(step, faults.as_ref());
+                    let node_id = step.node_id;
+                    let error_decision = gen_error_decision(step);
                     taskid_call_order.push(tid);
 
                     let process_call = match step.task_type {
                         CuTaskType::Source => {
+                            let process = gen_retry(step, quote! { #task_instance.process(&ctx, cumsg_output) });
                             if let Some((index, _)) = &step.output_msg_index_type {
                                 let output_culist_index = int2sliceindex(*index);
                                 quote! {
                                     {
                                         #comment_tokens
                                         let cumsg_output = &mut msgs.#output_culist_index;
-                                        cumsg_output.metadata.before_process = self.copper_runtime.clock.now().into();
-                                        let maybe_error = #task_instance.process(&self.copper_runtime.clock, cumsg_output);
-                                        cumsg_output.metadata.after_process = self.copper_runtime.clock.now().into();
-                                        if let Err(error) = maybe_error {
-                                            let decision = self.copper_runtime.monitor.process_error(#tid, _CuTaskState::Process, &error);
-                                            match decision {
-                                                _Decision::Abort => {
-                                                    debug!("Process: ABORT decision from monitoring. Task '{}' errored out \
-                                                    during process. Skipping the processing of CL {}.", TASKS_IDS[#tid], id);
-                                                    self.copper_runtime.monitor.process_copperlist(&collect_metadata(&culist))?;
-                                                    self.copper_runtime.end_of_processing(id);
-                                                    return Ok(()); // this returns early from the one iteration call.
-
-                                                }
-                                                _Decision::Ignore => {
-                                                    debug!("Process: IGNORE decision from monitoring. Task '{}' errored out \
-                                                    during process. The runtime will continue with a forced empty message.", TASKS_IDS[#tid]);
-                                                    cumsg_output.clear_payload();
-                                                }
-                                                _Decision::Shutdown => {
-                                                    debug!("Process: SHUTDOWN decision from monitoring. Task '{}' errored out \
-                                                    during process. The runtime cannot continue.", TASKS_IDS[#tid]);
-                                                    return Err(_CuError::new_with_cause("Task errored out during process.", error));
-                                                }
-                                            }
-                                        }
+                                        let before_process = self.copper_runtime.clock.now();
+                                        cumsg_output.metadata.before_process = before_process.into();
+                                        cumsg_output.metadata.provenance = _CuProvenance::default();
+                                        let dt = self.copper_runtime.tasks_stats[#tid].time_since_last_process(before_process);
+                                        self.copper_runtime.scratches[#tid].reset();
+                                        let ctx = _CuContext::new(&self.copper_runtime.clock, dt)
+                                            .with_node(#node_id, TASKS_IDS[#tid], self.copper_runtime.tasks_configs[#tid].as_ref())
+                                            .with_pools(&self.copper_runtime.pools)
+                                            .with_scratch(&self.copper_runtime.scratches[#tid])
+                                            .with_edges(&self.copper_runtime.edges_stats)
+                                            .with_clock_domains(&self.copper_runtime.clock_domains)
+                                            .with_param_requests(&self.copper_runtime.param_requests);
+                                        _set_current_task(Some(TASKS_IDS[#tid]));
+                                        _set_attributed_task(Some(#tid));
+                                        let maybe_error = #inject.and_then(|()| #process);
+                                        _set_current_task(None);
+                                        _set_attributed_task(None);
+                                        let after_process = self.copper_runtime.clock.now();
+                                        cumsg_output.metadata.after_process = after_process.into();
+                                        self.copper_runtime.tasks_stats[#tid].record_process_time(before_process, after_process);
+                                        self.copper_runtime.tasks_stats[#tid].record_process(cumsg_output.metadata.before_process, &maybe_error);
+                                        self.copper_runtime.tasks_stats[#tid].record_reported_health(ctx.reported_health());
+                                        self.copper_runtime.tasks_stats[#tid].record_scratch(self.copper_runtime.scratches[#tid].used());
+                                        self.copper_runtime.tasks_stats[#tid].record_state(#task_instance.current_state());
+                                        _record_edges_output(&mut self.copper_runtime.edges_stats, &self.copper_runtime.edges_by_src[#tid], before_process, maybe_error.is_ok() && cumsg_output.payload().is_some());
+                                        #error_decision
                                     }
                                 }
                             } else {
@@ -376,42 +1203,49 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                             }
                         }
                         CuTaskType::Sink => {
+                            let process = gen_retry(step, quote! { #task_instance.process(&ctx, cumsg_input) });
                             // collect the indices
-                            let indices = step.input_msg_indices_types.iter().map(|(index, _)| int2sliceindex(*index));
+                            let (dropped_inputs, inputs) = gen_task_inputs(step, &producers, faults.as_ref());
+                            let provenance = gen_task_provenance(step);
                             if let Some((output_index, _)) = &step.output_msg_index_type {
                                 let output_culist_index = int2sliceindex(*output_index);
                                 quote! {
                                     {
                                         #comment_tokens
-                                        let cumsg_input = (#(&msgs.#indices),*);
+                                        #dropped_inputs
+                                        let provenance = #provenance;
+                                        let cumsg_input = #inputs;
                                         // This is the virtual output for the sink
                                         let cumsg_output = &mut msgs.#output_culist_index;
-                                        cumsg_output.metadata.before_process = self.copper_runtime.clock.now().into();
-                                        let maybe_error = #task_instance.process(&self.copper_runtime.clock, cumsg_input);
-                                        cumsg_output.metadata.after_process = self.copper_runtime.clock.now().into();
-                                        if let Err(error) = maybe_error {
-                                            let decision = self.copper_runtime.monitor.process_error(#tid, _CuTaskState::Process, &error);
-                                            match decision {
-                                                _Decision::Abort => {
-                                                    debug!("Process: ABORT decision from monitoring. Task '{}' errored out \
-                                                    during process. Skipping the processing of CL {}.", TASKS_IDS[#tid], id);
-                                                    self.copper_runtime.monitor.process_copperlist(&collect_metadata(&culist))?;
-                                                    self.copper_runtime.end_of_processing(id);
-                                                    return Ok(()); // this returns early from the one iteration call.
-
-                                                }
-                                                _Decision::Ignore => {
-                                                    debug!("Process: IGNORE decision from monitoring. Task '{}' errored out \
-                                                    during process. The runtime will continue with a forced empty message.", TASKS_IDS[#tid]);
-                                                    cumsg_output.clear_payload();
-                                                }
-                                                _Decision::Shutdown => {
-                                                    debug!("Process: SHUTDOWN decision from monitoring. Task '{}' errored out \
-                                                    during process. The runtime cannot continue.", TASKS_IDS[#tid]);
-                                                    return Err(_CuError::new_with_cause("Task errored out during process.", error));
-                                                }
-                                            }
-                                        }
+                                        let before_process = self.copper_runtime.clock.now();
+                                        cumsg_output.metadata.before_process = before_process.into();
+                                        cumsg_output.metadata.provenance = provenance;
+                                        let dt = self.copper_runtime.tasks_stats[#tid].time_since_last_process(before_process);
+                                        self.copper_runtime.scratches[#tid].reset();
+                                        let maybe_late = _record_edges_delivery(&mut self.copper_runtime.edges_stats, &self.copper_runtime.edges_by_dst[#tid], before_process);
+                                        let ctx = _CuContext::new(&self.copper_runtime.clock, dt)
+                                            .with_node(#node_id, TASKS_IDS[#tid], self.copper_runtime.tasks_configs[#tid].as_ref())
+                                            .with_pools(&self.copper_runtime.pools)
+                                            .with_scratch(&self.copper_runtime.scratches[#tid])
+                                            .with_edges(&self.copper_runtime.edges_stats)
+                                            .with_clock_domains(&self.copper_runtime.clock_domains)
+                                            .with_param_requests(&self.copper_runtime.param_requests);
+                                        _set_current_task(Some(TASKS_IDS[#tid]));
+                                        _set_attributed_task(Some(#tid));
+                                        let maybe_error = maybe_late
+                                            .and_then(|()| #inject)
+                                            .and_then(|()| #process);
+                                        _set_current_task(None);
+                                        _set_attributed_task(None);
+                                        let after_process = self.copper_runtime.clock.now();
+                                        cumsg_output.metadata.after_process = after_process.into();
+                                        self.copper_runtime.tasks_stats[#tid].record_process_time(before_process, after_process);
+                                        self.copper_runtime.tasks_stats[#tid].record_process(cumsg_output.metadata.before_process, &maybe_error);
+                                        self.copper_runtime.tasks_stats[#tid].record_reported_health(ctx.reported_health());
+                                        self.copper_runtime.tasks_stats[#tid].record_scratch(self.copper_runtime.scratches[#tid].used());
+                                        self.copper_runtime.tasks_stats[#tid].record_state(#task_instance.current_state());
+                                        _record_edges_output(&mut self.copper_runtime.edges_stats, &self.copper_runtime.edges_by_src[#tid], before_process, maybe_error.is_ok() && cumsg_output.payload().is_some());
+                                        #error_decision
                                     }
                                 }
                             } else {
@@ -419,40 +1253,47 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                             }
                         }
                         CuTaskType::Regular => {
-                            let indices = step.input_msg_indices_types.iter().map(|(index, _)| int2sliceindex(*index));
+                            let process = gen_retry(step, quote! { #task_instance.process(&ctx, cumsg_input, cumsg_output) });
+                            let (dropped_inputs, inputs) = gen_task_inputs(step, &producers, faults.as_ref());
+                            let provenance = gen_task_provenance(step);
                             if let Some((output_index, _)) = &step.output_msg_index_type {
                                 let output_culist_index = int2sliceindex(*output_index);
                                 quote! {
                                     {
                                         #comment_tokens
-                                        let cumsg_input = (#(&msgs.#indices),*);
+                                        #dropped_inputs
+                                        let provenance = #provenance;
+                                        let cumsg_input = #inputs;
                                         let cumsg_output = &mut msgs.#output_culist_index;
-                                        cumsg_output.metadata.before_process = self.copper_runtime.clock.now().into();
-                                        let maybe_error = #task_instance.process(&self.copper_runtime.clock, cumsg_input, cumsg_output);
-                                        cumsg_output.metadata.after_process = self.copper_runtime.clock.now().into();
-                                        if let Err(error) = maybe_error {
-                                            let decision = self.copper_runtime.monitor.process_error(#tid, _CuTaskState::Process, &error);
-                                            match decision {
-                                                _Decision::Abort => {
-                                                    debug!("Process: ABORT decision from monitoring. Task '{}' errored out \
-                                                    during process. Skipping the processing of CL {}.", TASKS_IDS[#tid], id);
-                                                    self.copper_runtime.monitor.process_copperlist(&collect_metadata(&culist))?;
-                                                    self.copper_runtime.end_of_processing(id);
-                                                    return Ok(()); // this returns early from the one iteration call.
-
-                                                }
-                                                _Decision::Ignore => {
-                                                    debug!("Process: IGNORE decision from monitoring. Task '{}' errored out \
-                                                    during process. The runtime will continue with a forced empty message.", TASKS_IDS[#tid]);
-                                                    cumsg_output.clear_payload();
-                                                }
-                                                _Decision::Shutdown => {
-                                                    debug!("Process: SHUTDOWN decision from monitoring. Task '{}' errored out \
-                                                    during process. The runtime cannot continue.", TASKS_IDS[#tid]);
-                                                    return Err(_CuError::new_with_cause("Task errored out during process.", error));
-                                                }
-                                            }
-                                        }
+                                        let before_process = self.copper_runtime.clock.now();
+                                        cumsg_output.metadata.before_process = before_process.into();
+                                        cumsg_output.metadata.provenance = provenance;
+                                        let dt = self.copper_runtime.tasks_stats[#tid].time_since_last_process(before_process);
+                                        self.copper_runtime.scratches[#tid].reset();
+                                        let maybe_late = _record_edges_delivery(&mut self.copper_runtime.edges_stats, &self.copper_runtime.edges_by_dst[#tid], before_process);
+                                        let ctx = _CuContext::new(&self.copper_runtime.clock, dt)
+                                            .with_node(#node_id, TASKS_IDS[#tid], self.copper_runtime.tasks_configs[#tid].as_ref())
+                                            .with_pools(&self.copper_runtime.pools)
+                                            .with_scratch(&self.copper_runtime.scratches[#tid])
+                                            .with_edges(&self.copper_runtime.edges_stats)
+                                            .with_clock_domains(&self.copper_runtime.clock_domains)
+                                            .with_param_requests(&self.copper_runtime.param_requests);
+                                        _set_current_task(Some(TASKS_IDS[#tid]));
+                                        _set_attributed_task(Some(#tid));
+                                        let maybe_error = maybe_late
+                                            .and_then(|()| #inject)
+                                            .and_then(|()| #process);
+                                        _set_current_task(None);
+                                        _set_attributed_task(None);
+                                        let after_process = self.copper_runtime.clock.now();
+                                        cumsg_output.metadata.after_process = after_process.into();
+                                        self.copper_runtime.tasks_stats[#tid].record_process_time(before_process, after_process);
+                                        self.copper_runtime.tasks_stats[#tid].record_process(cumsg_output.metadata.before_process, &maybe_error);
+                                        self.copper_runtime.tasks_stats[#tid].record_reported_health(ctx.reported_health());
+                                        self.copper_runtime.tasks_stats[#tid].record_scratch(self.copper_runtime.scratches[#tid].used());
+                                        self.copper_runtime.tasks_stats[#tid].record_state(#task_instance.current_state());
+                                        _record_edges_output(&mut self.copper_runtime.edges_stats, &self.copper_runtime.edges_by_src[#tid], before_process, maybe_error.is_ok() && cumsg_output.payload().is_some());
+                                        #error_decision
                                     }
                                 }
                             } else {
@@ -461,46 +1302,289 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
                         }
                     };
 
-                    process_call
+                    process_calls.push(process_call.clone());
+
+                    // During a replay, the recorded output of a stubbed task replaces its process call.
+                    let (output_index, _) = step
+                        .output_msg_index_type
+                        .as_ref()
+                        .expect("Every task has an output message index.");
+                    let output_culist_index = int2sliceindex(*output_index);
+                    // A task not due or disabled keeps its last message in the copper list, see cu29::multirate.
+                    let due = gen_task_due(step);
+                    let not_due = quote! { _ if !#due => {} };
+                    quote! {
+                        match recorded.as_mut() {
+                            Some(recorded) if self.copper_runtime.stubbed[#tid] => {
+                                core::mem::swap(&mut msgs.#output_culist_index, &mut recorded.0.#output_culist_index);
+                            }
+                            #not_due
+                            _ => #process_call
+                        }
+                    }
                 }
                 CuExecutionUnit::Loop(_) => todo!("Needs to be implemented"),
             }
         }).collect();
     eprintln!("[Culist access order:  {:?}]", taskid_call_order);
 
+    // With parallel_workers, the independent tasks run on the workers, see cu29::parallel.
+    let parallel_workers = copper_config
+        .get_runtime_config()
+        .and_then(|runtime| runtime.parallel_workers)
+        .unwrap_or(0);
+    let stages = compute_parallel_stages(&runtime_plan);
+    let runtime_plan_code = if parallel_workers > 0 && stages.iter().any(|stage| stage.len() > 1) {
+        eprintln!("[Parallel stages: {:?}]", stages);
+        let stages_code = stages.iter().map(|stage| match stage.as_slice() {
+            [index] => match &runtime_plan.steps[*index] {
+                CuExecutionUnit::Step(step) => {
+                    let due = gen_task_due(step);
+                    let process_call = &process_calls[*index];
+                    quote! { if #due #process_call }
+                }
+                _ => process_calls[*index].clone(),
+            },
+            _ => {
+                let steps: Vec<&CuExecutionStep> = stage
+                    .iter()
+                    .map(|&index| match &runtime_plan.steps[index] {
+                        CuExecutionUnit::Step(step) => step.as_ref(),
+                        CuExecutionUnit::Loop(_) => unreachable!("The stages only hold steps."),
+                    })
+                    .collect();
+                gen_parallel_stage(&steps, &producers, faults.as_ref())
+            }
+        });
+        vec![quote! {
+            // The replay stays sequential.
+            if recorded.is_none() && self.copper_runtime.workers.is_some() {
+                #(#stages_code)*
+            } else {
+                #(#runtime_plan_code)*
+            }
+        }]
+    } else {
+        runtime_plan_code
+    };
+
+    // Which tasks with a base_period_ns run in this iteration, see cu29::multirate, the pruned and disabled ones never
+    // do.
+    let conditional: Vec<&CuExecutionStep> = runtime_plan
+        .steps
+        .iter()
+        .filter_map(|unit| match unit {
+            CuExecutionUnit::Step(step) if is_conditional(step) => Some(step.as_ref()),
+            _ => None,
+        })
+        .collect();
+    let dues = conditional.iter().map(|step| {
+        let tid = step.node_id as usize;
+        let due = gen_task_due(step);
+        match (step.node.get_base_period_ns(), step.node.is_optional()) {
+            (Some(_), true) => quote! {
+                let #due = !self.copper_runtime.pruned[#tid] && !self.copper_runtime.disabled[#tid] && self.copper_runtime.rates.is_due(#tid, now);
+            },
+            (Some(_), false) => quote! {
+                let #due = !self.copper_runtime.disabled[#tid] && self.copper_runtime.rates.is_due(#tid, now);
+            },
+            (None, _) => quote! { let #due = !self.copper_runtime.pruned[#tid] && !self.copper_runtime.disabled[#tid]; },
+        }
+    });
+    let now = conditional
+        .iter()
+        .any(|step| step.node.get_base_period_ns().is_some())
+        .then(|| quote! { let now = self.copper_runtime.clock.now(); });
+    let rates_code = quote! {
+        #now
+        #(#dues)*
+    };
+
     eprintln!("[build the copperlist support]");
-    let culist_support: proc_macro2::TokenStream =
-        gen_culist_support(&runtime_plan, &taskid_call_order);
+    let culist_support: proc_macro2::TokenStream = try_config!(
+        config_lit,
+        gen_culist_support(&runtime_plan, &taskid_call_order)
+    );
+
+    eprintln!("[build the loop or step methods]");
+    let loop_methods = if library {
+        quote! {
+            /// Advances the clock by dt then runs one iteration of the tasks.
+            /// The tasks need to be started with start_all_tasks before the first step.
+            /// With a time factor (see CuRuntime::pace_with), it first waits for the time of the step.
+            pub fn step(&mut self, dt: _CuDuration) -> _CuResult<()> {
+                let time = self.copper_runtime.clock.now() + dt;
+                self.copper_runtime.pace_time(time);
+                self.copper_clock_mock.increment(dt.into());
+                self.run_one_iteration()
+            }
+        }
+    } else {
+        quote! {
+            /// Runs the application until a task errors out or the process receives SIGINT or SIGTERM.
+            /// The current iteration completes, then all the tasks are stopped before returning.
+            pub fn run(&mut self) -> _CuResult<()> {
+                let stop = _StopFlag::new();
+                let _handlers = stop.install_handlers()?;
+                self.run_until(&stop)
+            }
+
+            /// Runs the application until a task errors out or the given flag is raised, then stops all the tasks.
+            /// The iterations are paced by the runtime section of the config if it sets a period.
+            pub fn run_until(&mut self, stop: &_StopFlag) -> _CuResult<()> {
+                self.start_all_tasks()?;
+                if self.copper_runtime.self_test_required() {
+                    let report = self.self_test_all_tasks();
+                    let checked = report.check();
+                    if checked.is_err() {
+                        debug!("Self test: {}", report.to_string());
+                        // The failed self test takes precedence over an error of the stop.
+                        let stopped = self.stop_all_tasks();
+                        return checked.and(stopped);
+                    }
+                }
+                self.copper_runtime.reset_pacing();
+                let mut outcome = Ok(());
+                while !stop.is_stopped() {
+                    self.copper_runtime.wait_next_iteration();
+                    if let Some(gap) = self.copper_runtime.check_suspend() {
+                        outcome = self.stop_all_tasks().and_then(|_| self.start_all_tasks());
+                        if outcome.is_err() {
+                            debug!("A task could not be restarted after a suspend: {}", &outcome);
+                            break;
+                        }
+                        self.copper_runtime.resume_after_suspend(gap);
+                    }
+                    outcome = self.run_one_iteration();
+                    if outcome.is_err() {
+                        debug!("A task errored out: {}", &outcome);
+                        break;
+                    }
+                }
+                if let Some(stats) = self.copper_runtime.loop_stats() {
+                    if stats.lateness.len() > 0 {
+                        let mean = stats.lateness.mean();
+                        let max = stats.lateness.max();
+                        let overruns = stats.overruns;
+                        debug!("Loop timing: lateness mean {} max {}, {} overruns.", mean, max, overruns);
+                    }
+                }
+                // Stop the tasks even if the loop errored out, the error of the loop takes precedence.
+                let stopped = self.stop_all_tasks();
+                outcome.and(stopped)
+            }
+        }
+    };
+
+    // The optional tasks that could not be created are pruned, and the pruned ones dropped, see cu29::optional.
+    let optional_indices: Vec<usize> = (0..all_tasks_optional.len())
+        .filter(|&index| all_tasks_optional[index])
+        .collect();
+    let optional_tasks: Vec<syn::Index> = optional_indices
+        .iter()
+        .map(|&index| int2sliceindex(index as u32))
+        .collect();
+    let prune_missing_tasks = quote! {
+        #(
+            if let Some(error) = copper_runtime.tasks.#optional_tasks.new_error().cloned() {
+                copper_runtime.prune_task(#optional_indices, &error);
+            }
+        )*
+    };
+    let drop_pruned_tasks = |runtime: proc_macro2::TokenStream| {
+        quote! {
+            #(
+                if #runtime.pruned[#optional_indices] {
+                    #runtime.tasks.#optional_tasks.prune();
+                }
+            )*
+        }
+    };
+    let drop_pruned_after_new = drop_pruned_tasks(quote! { copper_runtime });
+    let drop_pruned_after_start = drop_pruned_tasks(quote! { self.copper_runtime });
 
     eprintln!("[build the run method]");
     let run_method = quote! {
 
         pub fn start_all_tasks(&mut self) -> _CuResult<()> {
             self.copper_runtime.monitor.start(&self.copper_runtime.clock)?;
+            self.copper_runtime.rates.reset();
             #(#start_calls)*
+            #drop_pruned_after_start
+            self.copper_runtime.confirm_config_upgrade()?;
             Ok(())
         }
 
         #[inline]
         pub fn run_one_iteration(&mut self) -> _CuResult<()> {
+            self.process_iteration(None)
+        }
+
+        /// Runs one iteration where the stubbed tasks (see set_stubbed_tasks) are replaced by their outputs
+        /// in recorded, typically a copper list read back from a log with cu29_export::copperlists_dump.
+        /// The other tasks process the recorded outputs and the resulting copper list is logged as usual.
+        /// In replay mode (see new_replay), the robot clock is first moved to the time the recorded copper list started.
+        pub fn replay_one_iteration(&mut self, recorded: &mut CuList) -> _CuResult<()> {
+            self.copper_runtime.set_replay_time(recorded.start_time());
+            self.process_iteration(Some(&mut recorded.msgs))
+        }
+
+        /// Replays the copper lists of a recorded run, typically read back with cu29_export::copperlists_dump,
+        /// from an application created with new_replay: the tasks are started, every copper list is replayed
+        /// with replay_one_iteration and the tasks are stopped. Returns the number of copper lists replayed.
+        pub fn replay_log(&mut self, recorded: impl IntoIterator<Item = CuList>) -> _CuResult<usize> {
+            self.start_all_tasks()?;
+            let mut replayed = 0;
+            let outcome = recorded.into_iter().try_for_each(|mut culist| {
+                replayed += 1;
+                self.replay_one_iteration(&mut culist)
+            });
+            // Stop the tasks even if the replay errored out, the error of the replay takes precedence.
+            let stopped = self.stop_all_tasks();
+            outcome.and(stopped).map(|_| replayed)
+        }
+
+        /// Selects the tasks replaced by their recorded outputs in replay_one_iteration.
+        /// They are not started, processed nor stopped. Call it before start_all_tasks.
+        pub fn set_stubbed_tasks(&mut self, tasks: &[NodeIds]) -> _CuResult<()> {
+            let task_ids: Vec<&str> = tasks.iter().map(|task| task.id()).collect();
+            self.copper_runtime.set_stubbed_tasks(&task_ids)
+        }
+
+        /// Switches off a task until enable_task: it is not processed and the tasks downstream keep seeing its
+        /// last message, see CuRuntime::disable_task.
+        pub fn disable_task(&mut self, task: NodeIds) -> _CuResult<()> {
+            self.copper_runtime.disable_task(task as usize)
+        }
+
+        /// Processes again a task switched off with disable_task.
+        pub fn enable_task(&mut self, task: NodeIds) -> _CuResult<()> {
+            self.copper_runtime.enable_task(task as usize)
+        }
+
+        /// Switches to another mission of the config from the next iteration, see cu29::mission.
+        pub fn switch_mission(&mut self, mission: &str) -> _CuResult<()> {
+            self.copper_runtime.switch_mission(mission)
+        }
+
+        #[inline]
+        fn process_iteration(&mut self, mut recorded: Option<&mut CuMsgs>) -> _CuResult<()> {
             #(#preprocess_calls)*
             {
-                let mut culist = &mut self.copper_runtime.copper_lists_manager.create().expect("Ran out of space for copper lists"); // FIXME: error handling.
+                self.copper_runtime.reserve_copper_list()?;
+                let mut culist = &mut self.copper_runtime.copper_lists_manager.create().expect("A copper list was reserved.");
                 let id = culist.id;
                 culist.change_state(cu29::copperlist::CopperListState::Processing);
+                #rates_code
                 {
                     let msgs = &mut culist.msgs.0;
                     #(#runtime_plan_code)*
                 } // drop(msgs);
 
-                {
-                    // End of CL monitoring
-                    let md = collect_metadata(&culist);
-                    let e2e = md.last().unwrap().after_process.unwrap() - md.first().unwrap().before_process.unwrap();
-                    let e2en: u64 = e2e.into();
-                } // drop(md);
-
                 self.copper_runtime.monitor.process_copperlist(&collect_metadata(&culist))?;
+                self.copper_runtime.summary.record(&culist.msgs);
+                self.copper_runtime.derived.record(&culist.msgs);
+                self.copper_runtime.latches.record(&culist.msgs);
                 self.copper_runtime.end_of_processing(id);
 
            }// drop(culist); avoids a double mutable borrow
@@ -508,23 +1592,197 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
            Ok(())
         }
 
+        /// Runs the self tests of the started tasks, see cu29::selftest. The failures are logged.
+        pub fn self_test_all_tasks(&mut self) -> _CuSelfTestReport {
+            let mut report = _CuSelfTestReport::default();
+            #(#self_test_calls)*
+            let ready = report.is_ready();
+            debug!("Self test: hardware ready: {}.", ready);
+            report
+        }
+
+        /// Starts the tasks, runs their self tests and stops them, without running the loop.
+        /// This is the readiness check of the hardware, ie. behind a --self-test option of the application.
+        pub fn self_test(&mut self) -> _CuResult<_CuSelfTestReport> {
+            self.start_all_tasks()?;
+            let report = self.self_test_all_tasks();
+            self.stop_all_tasks()?;
+            Ok(report)
+        }
+
         pub fn stop_all_tasks(&mut self) -> _CuResult<()> {
             #(#stop_calls)*
             self.copper_runtime.monitor.stop(&self.copper_runtime.clock)?;
             Ok(())
         }
 
-        pub fn run(&mut self) -> _CuResult<()> {
-            self.start_all_tasks()?;
-            let error = loop {
-                let error = self.run_one_iteration();
-                if error.is_err() {
-                    break error;
-                }
+        /// Returns the structure of the task graph with the current rates and health of the tasks.
+        pub fn introspect(&self) -> cu29::introspection::CuGraphInfo {
+            self.copper_runtime.introspect()
+        }
+
+        /// Degraded when the application runs without some of its optional tasks, see cu29::optional.
+        pub fn health(&self) -> cu29::monitoring::CuHealth {
+            self.copper_runtime.health()
+        }
+
+        /// Returns the timing statistics of the loop when run() paces it.
+        pub fn loop_stats(&self) -> Option<&cu29::pacing::LoopStats> {
+            self.copper_runtime.loop_stats()
+        }
+
+        /// Returns how the schedule of the loop was adjusted before the current iteration, None if it started on time.
+        pub fn last_overrun(&self) -> Option<cu29::pacing::Overrun> {
+            self.copper_runtime.last_overrun()
+        }
+
+        /// The last message of the sources of the latched connections, what a new observer is sent first.
+        pub fn latched_msgs(&self) -> Vec<cu29::latch::CuLatchedMsg> {
+            self.copper_runtime.latched_msgs()
+        }
+
+        /// Returns a structured snapshot of every message computed during the last complete iteration.
+        pub fn dump_last_iteration(&self) -> Option<cu29::copperlist::CuListDump> {
+            self.copper_runtime.dump_last_iteration()
+        }
+
+        /// Marks the current time in the log with a label, see cu29::annotation.
+        pub fn annotate(&self, label: &str, severity: cu29::annotation::CuSeverity) -> _CuResult<()> {
+            self.copper_runtime.annotate(label, severity)
+        }
+
+        /// Writes annotations in the log from another thread, ie. from an operator's trigger.
+        pub fn annotator(&self) -> Option<cu29::annotation::CuAnnotator> {
+            self.copper_runtime.annotator()
+        }
+
+        /// Changes a parameter of a task while it runs and records the change in the log, see cu29::tuning.
+        pub fn set_param(
+            &mut self,
+            task_id: &str,
+            key: &str,
+            value: cu29::config::Value,
+            token: Option<&cu29::permissions::CuRoleToken>,
+        ) -> _CuResult<cu29::tuning::CuParamChange> {
+            self.copper_runtime.set_param(task_id, key, value, token)
+        }
+
+        /// Applies a change of a parameter recorded in the log, ie. during a replay before replay_one_iteration.
+        pub fn apply_param_change(&mut self, change: &cu29::tuning::CuParamChange) -> _CuResult<()> {
+            self.copper_runtime.apply_param_change(change)
+        }
+
+        /// The run of the application, see cu29::lineage.
+        pub fn run_id(&self) -> Option<&cu29::lineage::CuRunId> {
+            self.copper_runtime.run.as_ref()
+        }
+
+        /// Registers the conversions of the clock domains of the subgraphs to the robot clock, see cu29::clockdomain.
+        pub fn clock_domains(&self) -> std::sync::Arc<cu29::clockdomain::CuClockDomains> {
+            self.copper_runtime.clock_domains.clone()
+        }
+
+        #task_accessors
+
+        #loop_methods
+    };
+
+    // Only a config read from a file can be watched, see cu29::watch.
+    let watch_config = watched_file.map(|file| {
+        quote! { copper_runtime.watch_config(#file, &config, overrides); }
+    });
+    // Only a config read from a file can be upgraded in the field, see cu29::upgrade.
+    let boot_upgrade = watched_file.map(|file| {
+        quote! {
+            let config_upgrade = match _CuConfigUpgrade::boot(#file)? {
+                _CuUpgradeBoot::Upgrade(upgrade) => Some(upgrade),
+                _CuUpgradeBoot::RolledBack(_) | _CuUpgradeBoot::None => None,
             };
-            debug!("A task errored out: {}", &error);
-            self.stop_all_tasks()?;
-            error
+        }
+    });
+    let watch_upgrade = watched_file.map(|_| {
+        quote! {
+            if let Some(upgrade) = &config_upgrade {
+                upgrade.watch(&config);
+            }
+            copper_runtime.config_upgrade = config_upgrade;
+        }
+    });
+    let install_config = watched_file.map(|file| {
+        quote! {
+            /// Validates a new config and installs it in place of the config file, the application boots with it on
+            /// its next start. The current config is restored if the new one does not start all the tasks, see
+            /// cu29::upgrade.
+            pub fn install_config(&self, config: &str) -> _CuResult<()> {
+                cu29::upgrade::install_config(#file, config)
+            }
+        }
+    });
+    let new_body = quote! {
+        #boot_upgrade
+        let mut config = #read_config;
+        overrides.apply(&mut config)?;
+        let run = _CuRunId::from_config(&config);
+        run.apply(&mut config);
+
+        // The layout of the copper lists first, so a reader can check it can decode them.
+        _write_wire_header(unified_logger.clone(), <CuMsgs as _CuListDumper>::SCHEMA, config.robot_id(), Some(&run))?;
+        let copperlist_stream = _copperlist_stream(unified_logger.clone(), &config);
+
+        let mut copper_runtime = _CuRuntime::<CuTasks, CuMsgs, #monitor_type, #copperlists>::new(clock, &config, tasks_instanciator, monitor_instanciator, copperlist_stream)?;
+        copper_runtime.set_unified_logger(unified_logger);
+        copper_runtime.run = Some(run);
+        #prune_missing_tasks
+        #drop_pruned_after_new
+        copper_runtime.setup_realtime(&config)?;
+        #watch_config
+        #watch_upgrade
+    };
+    let new_method = if library {
+        quote! {
+            /// The runtime is driven by the given mock clock, typically from RobotClock::mock().
+            pub fn new(clock: _RobotClock, clock_mock: _RobotClockMock, unified_logger: _Arc<_Mutex<_UnifiedLoggerWrite>>) -> _CuResult<Self> {
+                Self::new_with_overrides(clock, clock_mock, unified_logger, &_CuParamOverrides::default())
+            }
+
+            /// Creates the application in replay mode, see replay_log and cu29::curuntime::CuRuntime::set_replay_mode.
+            /// The new copper lists are written to unified_logger.
+            pub fn new_replay(unified_logger: _Arc<_Mutex<_UnifiedLoggerWrite>>) -> _CuResult<Self> {
+                let (clock, clock_mock) = _RobotClock::mock();
+                let mut application = Self::new(clock, clock_mock.clone(), unified_logger)?;
+                application.copper_runtime.set_replay_mode(clock_mock);
+                Ok(application)
+            }
+
+            /// Same as new with some parameters of the tasks replaced, see CuParamOverrides.
+            pub fn new_with_overrides(clock: _RobotClock, clock_mock: _RobotClockMock, unified_logger: _Arc<_Mutex<_UnifiedLoggerWrite>>, overrides: &_CuParamOverrides) -> _CuResult<Self> {
+                #new_body
+                Ok(#name {
+                    copper_runtime,
+                    copper_clock_mock: clock_mock,
+                })
+            }
+        }
+    } else {
+        quote! {
+            pub fn new(clock:_RobotClock, unified_logger: _Arc<_Mutex<_UnifiedLoggerWrite>>) -> _CuResult<Self> {
+                Self::new_with_overrides(clock, unified_logger, &_CuParamOverrides::default())
+            }
+
+            /// Creates the application in replay mode, see replay_log and cu29::curuntime::CuRuntime::set_replay_mode.
+            /// The new copper lists are written to unified_logger.
+            pub fn new_replay(unified_logger: _Arc<_Mutex<_UnifiedLoggerWrite>>) -> _CuResult<Self> {
+                let (clock, clock_mock) = _RobotClock::mock();
+                let mut application = Self::new(clock, unified_logger)?;
+                application.copper_runtime.set_replay_mode(clock_mock);
+                Ok(application)
+            }
+
+            /// Same as new with some parameters of the tasks replaced, see CuParamOverrides.
+            pub fn new_with_overrides(clock:_RobotClock, unified_logger: _Arc<_Mutex<_UnifiedLoggerWrite>>, overrides: &_CuParamOverrides) -> _CuResult<Self> {
+                #new_body
+                Ok(#name { copper_runtime })
+            }
         }
     };
 
@@ -536,34 +1794,59 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
         use cu29::config::ComponentConfig as _ComponentConfig;
         use cu29::config::MonitorConfig as _MonitorConfig;
         use cu29::config::read_configuration as _read_configuration;
+        use cu29::config::CuParamOverrides as _CuParamOverrides;
         use cu29::curuntime::CuRuntime as _CuRuntime;
+        use cu29::curuntime::copperlist_stream as _copperlist_stream;
         use cu29::CuResult as _CuResult;
         use cu29::CuError as _CuError;
         use cu29::cutask::CuTaskLifecycle as _CuTaskLifecycle; // Needed for the instantiation of tasks
         use cu29::cutask::CuSrcTask as _CuSrcTask;
         use cu29::cutask::CuSinkTask as _CuSinkTask;
         use cu29::cutask::CuTask as _CuTask;
+        use cu29::optional::CuOptional as _CuOptional;
+        use cu29::upgrade::CuConfigUpgrade as _CuConfigUpgrade;
+        use cu29::upgrade::CuUpgradeBoot as _CuUpgradeBoot;
+        use cu29::errorpolicy::retry_process as _retry_process;
+        use cu29::errorpolicy::restart_task as _restart_task;
         use cu29::cutask::CuMsg as _CuMsg;
+        use cu29::provenance::CuProvenance as _CuProvenance;
         use cu29::cutask::CuMsgMetadata as _CuMsgMetadata;
         use cu29::copperlist::CopperList as _CopperList;
+        use cu29::copperlist::CuListDumper as _CuListDumper;
+        use cu29::copperlist::CuMsgDump as _CuMsgDump;
+        use cu29::clock::CuTime as _CuTime;
+        use cu29::summary::CuChannelStats as _CuChannelStats;
+        use cu29::dynmsg::DynCuMsg as _DynCuMsg;
+        use cu29::wire::write_wire_header as _write_wire_header;
+        use cu29::lineage::CuRunId as _CuRunId;
         use cu29::monitoring::CuMonitor as _CuMonitor; // Trait import.
+        use cu29::monitoring::record_edges_output as _record_edges_output;
+        use cu29::monitoring::record_edges_delivery as _record_edges_delivery;
+        use cu29::threads::set_current_task as _set_current_task;
+        use cu29::memory::set_attributed_task as _set_attributed_task;
+        use cu29::faults::inject_before_process as _inject_before_process;
+        use cu29::faults::inject_drop as _inject_drop;
+        use cu29::selftest::CuSelfTestReport as _CuSelfTestReport;
         use cu29::monitoring::NoMonitor as _NoMonitor;
         use cu29::monitoring::CuTaskState as _CuTaskState;
         use cu29::monitoring::Decision as _Decision;
         use cu29::clock::RobotClock as _RobotClock;
+        use cu29::context::CuContext as _CuContext;
         use cu29::clock::OptionCuTime as _OptionCuTime;
         use cu29::clock::ClockProvider as _ClockProvider;
+        use cu29::clock::RobotClockMock as _RobotClockMock;
+        use cu29::clock::CuDuration as _CuDuration;
+        use cu29::signal::StopFlag as _StopFlag;
         use std::sync::Arc as _Arc;
         use std::sync::Mutex as _Mutex;
+        use std::any::Any as _Any;
         use bincode::Encode as _Encode;
         use bincode::enc::Encoder as _Encoder;
         use bincode::error::EncodeError as _EncodeError;
         use bincode::Decode as _Decode;
         use bincode::de::Decoder as _Decoder;
         use bincode::error::DecodeError as _DecodeError;
-        use cu29_unifiedlog::stream_write as _stream_write;
         use cu29_unifiedlog::UnifiedLoggerWrite as _UnifiedLoggerWrite;
-        use cu29_traits::UnifiedLogType as _UnifiedLogType;
 
         // This is the heart of everything.
         // CuTasks is the list of all the tasks types.
@@ -572,9 +1855,21 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
 
         const TASKS_IDS: &'static [&'static str] = &[#( #all_tasks_ids ),*];
 
+        #type_aliases
+
+        #node_ids
+
         #culist_support
 
 
+        #[allow(dead_code)]
+        fn check_tasks_traits() {
+            fn _is_src_task<T: for<'cl> _CuSrcTask<'cl>>() {}
+            fn _is_task<T: for<'cl> _CuTask<'cl>>() {}
+            fn _is_sink_task<T: for<'cl> _CuSinkTask<'cl>>() {}
+            #(#task_trait_checks)*
+        }
+
         fn tasks_instanciator(all_instances_configs: Vec<Option<&_ComponentConfig>>) -> _CuResult<CuTasks> {
             Ok(( #(#task_instances_init_code),*, ))
         }
@@ -583,53 +1878,324 @@ pub fn copper_runtime(args: TokenStream, input: TokenStream) -> TokenStream {
             #monitor_type::new(monitor_config, TASKS_IDS).expect("Failed to create the given monitor.")
         }
 
+        #adapters_code
+
         pub #item_struct
 
         impl #name {
 
-            pub fn new(clock:_RobotClock, unified_logger: _Arc<_Mutex<_UnifiedLoggerWrite>>) -> _CuResult<Self> {
-                let config = _read_configuration(#config_file)?;
-
-                let copperlist_stream = _stream_write::<CuList>(
-                    unified_logger.clone(),
-                    _UnifiedLogType::CopperList,
-                    60 * 1024, // FIXME: make this a config
-                );
+            #new_method
 
-                Ok(#name {
-                    copper_runtime: _CuRuntime::<CuTasks, CuMsgs, #monitor_type, #DEFAULT_CLNB>::new(clock, &config, tasks_instanciator, monitor_instanciator, copperlist_stream)?
-                })
-            }
+            #install_config
 
             #run_method
         }
     };
-    let tokens: TokenStream = result.into();
+    result.into()
+}
 
-    // Print and format the generated code using rustfmt
-    // println!("Generated tokens: {}", tokens);
-    let formatted_code = rustfmt_generated_code(tokens.to_string());
-    eprintln!("\n     ===    Gen. Runtime ===\n");
-    eprintln!("{}", highlight_rust_code(formatted_code));
-    eprintln!("\n     === === === === === ===\n");
+/// Generates the adapter tasks for the transforms declared on the connections.
+/// Those are simple CuTasks mapping a numeric input value (or a field of it) to a numeric output.
+fn gen_transform_adapters(copper_config: &CuConfig) -> Result<proc_macro2::TokenStream, String> {
+    let adapters = copper_config
+        .get_all_nodes()
+        .iter()
+        .filter(|node| node.get_type().starts_with(ADAPTER_TYPE_PREFIX))
+        .map(|node| {
+            let name = format_ident!("{}", node.get_type());
+            let param = |key: &str| -> Option<String> { node.get_param::<String>(key) };
+            let msg_type = |key: &str| {
+                let msg = param(key).unwrap_or_default();
+                parse_str::<Type>(&msg).map_err(|_| {
+                    format!(
+                        "the message type '{}' of the transform {} is not a valid Rust type.",
+                        msg,
+                        node.get_id()
+                    )
+                })
+            };
+            let input_msg = msg_type("input_msg")?;
+            let output_msg = msg_type("output_msg")?;
+            let value_access = match param("field") {
+                Some(field) => {
+                    let path = field.split('.').map(|f| format_ident!("{}", f));
+                    quote! { payload #(.#path)* }
+                }
+                None => quote! { *payload },
+            };
+            Ok(quote! {
+                #[allow(non_camel_case_types)]
+                pub struct #name {
+                    factor: f64,
+                    offset: f64,
+                }
 
-    tokens
+                impl cu29::cutask::Freezable for #name {}
+
+                impl _CuTaskLifecycle for #name {
+                    fn new(config: Option<&_ComponentConfig>) -> _CuResult<Self> {
+                        let config = config.ok_or("Transform adapters need a config.")?;
+                        Ok(Self {
+                            factor: config.get::<f64>("factor").unwrap_or(1.0),
+                            offset: config.get::<f64>("offset").unwrap_or(0.0),
+                        })
+                    }
+                }
+
+                impl<'cl> _CuTask<'cl> for #name {
+                    type Input = &'cl _CuMsg<#input_msg>;
+                    type Output = &'cl mut _CuMsg<#output_msg>;
+
+                    fn process(&mut self, _ctx: &_CuContext, input: Self::Input, output: Self::Output) -> _CuResult<()> {
+                        match input.payload() {
+                            Some(payload) => {
+                                let value = (#value_access) as f64;
+                                output.set_payload((value * self.factor + self.offset) as #output_msg);
+                            }
+                            None => output.clear_payload(),
+                        }
+                        output.metadata.tov = input.metadata.tov;
+                        Ok(())
+                    }
+                }
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(quote! { #(#adapters)* })
 }
 
-fn read_config(config_file: &String) -> CuConfig {
+/// The path of the config relative to the root of the crate using the macro.
+fn config_full_path(config_file: &str) -> Result<String, String> {
     let mut config_full_path = utils::caller_crate_root();
     config_full_path.push(config_file);
-    let filename = config_full_path
-        .as_os_str()
-        .to_str()
-        .expect("Could not interpret the config file name");
+    config_full_path
+        .into_os_string()
+        .into_string()
+        .map_err(|_| "the path of the config is not valid unicode.".to_string())
+}
 
-    read_configuration(filename)
-        .unwrap_or_else(|_| panic!("Failed to read configuration file: {:?}", &config_full_path))
+fn read_config(config_file: &str) -> Result<CuConfig, String> {
+    read_configuration(&config_full_path(config_file)?).map_err(|e| e.to_string())
 }
 
+/// Generates the names user code can refer to the tasks with:
+/// - a `<Id>Task` alias of the type of each task and a `<Id>Output` alias of the message it sends,
+/// - a `NodeIds` enum of the task ids and an `EdgeIds` enum of the connections,
+/// - the `task` and `task_mut` accessors of the application struct, to get a task from its id.
+fn gen_task_names(
+    copper_config: &CuConfig,
+    runtime_plan: &CuExecutionLoop,
+    all_tasks_ids: &[String],
+    all_tasks_types: &[Type],
+) -> Result<
+    (
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+    ),
+    String,
+> {
+    let camel_names: Vec<String> = all_tasks_ids
+        .iter()
+        .map(|id| utils::id_to_camel_case(id))
+        .collect();
+    let variants: Vec<String> = all_tasks_ids
+        .iter()
+        .map(|id| utils::id_to_ident(id))
+        .collect();
+    for (i, camel) in camel_names.iter().enumerate() {
+        if let Some(j) = (0..i).find(|&j| camel_names[j] == *camel || variants[j] == variants[i]) {
+            return Err(format!(
+                "the task ids '{}' and '{}' give the same names in the generated code, rename one of them.",
+                all_tasks_ids[j], all_tasks_ids[i]
+            ));
+        }
+    }
+
+    let task_aliases = camel_names
+        .iter()
+        .zip(all_tasks_ids.iter().zip(all_tasks_types))
+        .map(|(camel, (id, task_type))| {
+            let alias = format_ident!("{}Task", camel);
+            let doc = format!("The type of the task '{}'.", id);
+            quote! {
+                #[doc = #doc]
+                pub type #alias = #task_type;
+            }
+        });
+    let output_aliases = runtime_plan
+        .steps
+        .iter()
+        .filter_map(|unit| match unit {
+            CuExecutionUnit::Step(step) if step.task_type != CuTaskType::Sink => step
+                .output_msg_index_type
+                .as_ref()
+                .map(|(_, msg_type)| (step.node_id as usize, msg_type)),
+            _ => None,
+        })
+        .map(|(node_id, msg_type)| {
+            let msg_type = parse_str::<Type>(msg_type).map_err(|_| {
+                format!(
+                    "the message type '{}' sent by the task '{}' is not a valid Rust type.",
+                    msg_type, all_tasks_ids[node_id]
+                )
+            })?;
+            let alias = format_ident!("{}Output", camel_names[node_id]);
+            let doc = format!(
+                "The type of the message sent by the task '{}'.",
+                all_tasks_ids[node_id]
+            );
+            Ok(quote! {
+                #[doc = #doc]
+                pub type #alias = #msg_type;
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let type_aliases = quote! {
+        #(#task_aliases)*
+        #(#output_aliases)*
+    };
+
+    // The connections are named after the tasks they link, ie. camera_to_detector.
+    let mut edges = Vec::new();
+    for edge in copper_config.graph.edge_indices() {
+        let (src, dst) = copper_config.graph.edge_endpoints(edge).unwrap();
+        let name = format!("{}_to_{}", variants[src.index()], variants[dst.index()]);
+        if edges.iter().any(|(other, _, _, _)| *other == name) {
+            return Err(format!(
+                "the tasks '{}' and '{}' are connected twice, the connections give the same names in the generated code.",
+                all_tasks_ids[src.index()], all_tasks_ids[dst.index()]
+            ));
+        }
+        let msg = copper_config.graph[edge].msg.clone();
+        edges.push((name, src.index(), dst.index(), msg));
+    }
+
+    let variants: Vec<syn::Ident> = variants.iter().map(|v| format_ident!("{}", v)).collect();
+    let tasks_count = variants.len();
+    let edge_variants: Vec<syn::Ident> = edges
+        .iter()
+        .map(|(name, _, _, _)| format_ident!("{}", name))
+        .collect();
+    let edges_count = edge_variants.len();
+    let edge_indices = 0..edges_count;
+    let edge_srcs = edges.iter().map(|(_, src, _, _)| &variants[*src]);
+    let edge_dsts = edges.iter().map(|(_, _, dst, _)| &variants[*dst]);
+    let edge_msgs = edges.iter().map(|(_, _, _, msg)| msg);
+    let node_ids = quote! {
+        /// The ids of the tasks, in their order in the config.
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum NodeIds {
+            #(#variants),*
+        }
+
+        impl NodeIds {
+            pub const ALL: [NodeIds; #tasks_count] = [#(NodeIds::#variants),*];
+
+            /// The id of the task in the config.
+            pub fn id(self) -> &'static str {
+                TASKS_IDS[self as usize]
+            }
+        }
+
+        /// The connections between the tasks, in their order in the config.
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum EdgeIds {
+            #(#edge_variants),*
+        }
+
+        impl EdgeIds {
+            pub const ALL: [EdgeIds; #edges_count] = [#(EdgeIds::#edge_variants),*];
+
+            /// The index of the connection in the statistics and the introspection of the runtime.
+            pub fn index(self) -> usize {
+                match self {
+                    #(EdgeIds::#edge_variants => #edge_indices,)*
+                }
+            }
+
+            /// The task sending the messages.
+            pub fn src(self) -> NodeIds {
+                match self {
+                    #(EdgeIds::#edge_variants => NodeIds::#edge_srcs,)*
+                }
+            }
+
+            /// The task receiving the messages.
+            pub fn dst(self) -> NodeIds {
+                match self {
+                    #(EdgeIds::#edge_variants => NodeIds::#edge_dsts,)*
+                }
+            }
+
+            /// The type of the messages, as written in the config.
+            pub fn msg(self) -> &'static str {
+                match self {
+                    #(EdgeIds::#edge_variants => #edge_msgs,)*
+                }
+            }
+
+            /// The channel of the connection for the bridges and the observers: its source in the namespace of the
+            /// robot, see cu29::namespace.
+            pub fn channel(self, robot_id: Option<&str>) -> String {
+                cu29::namespace::namespaced(robot_id, self.src().id())
+            }
+        }
+    };
+
+    // The optional tasks are held in a CuOptional, see cu29::optional.
+    let optional: Vec<bool> = copper_config
+        .get_all_nodes()
+        .iter()
+        .map(|node| node.is_optional())
+        .collect();
+    let (task_refs, task_muts): (Vec<_>, Vec<_>) = (0..all_tasks_ids.len())
+        .map(|i| {
+            let index = int2sliceindex(i as u32);
+            if optional[i] {
+                (
+                    quote! { tasks.#index.task()? },
+                    quote! { tasks.#index.task_mut()? },
+                )
+            } else {
+                (quote! { &tasks.#index }, quote! { &mut tasks.#index })
+            }
+        })
+        .unzip();
+    let task_accessors = quote! {
+        /// The task with this id, None if it is not a T or if it was pruned.
+        pub fn task<T: 'static>(&self, id: NodeIds) -> Option<&T> {
+            let tasks = &self.copper_runtime.tasks;
+            let task: &dyn _Any = match id {
+                #(NodeIds::#variants => #task_refs,)*
+            };
+            task.downcast_ref::<T>()
+        }
+
+        /// The task with this id, None if it is not a T or if it was pruned.
+        pub fn task_mut<T: 'static>(&mut self, id: NodeIds) -> Option<&mut T> {
+            let tasks = &mut self.copper_runtime.tasks;
+            let task: &mut dyn _Any = match id {
+                #(NodeIds::#variants => #task_muts,)*
+            };
+            task.downcast_mut::<T>()
+        }
+
+        /// How old the data on a connection is and how often it was late, see cu29::monitoring::CuEdgeStats.
+        pub fn edge_stats(&self, edge: EdgeIds) -> &cu29::monitoring::CuEdgeStats {
+            &self.copper_runtime.edges_stats[edge.index()]
+        }
+    };
+    Ok((type_aliases, node_ids, task_accessors))
+}
+
+/// The ids, the type names and the types of the tasks in their index order.
+type TasksTypes = (Vec<String>, Vec<String>, Vec<Type>);
+
 /// Extract all the tasks types in their index order and their ids.
-fn extract_tasks_types(copper_config: &CuConfig) -> (Vec<String>, Vec<String>, Vec<Type>) {
+fn extract_tasks_types(copper_config: &CuConfig) -> Result<TasksTypes, String> {
     let all_nodes = copper_config.get_all_nodes();
 
     // Get all the tasks Ids
@@ -647,38 +2213,137 @@ fn extract_tasks_types(copper_config: &CuConfig) -> (Vec<String>, Vec<String>, V
     // Transform them as Rust types
     let all_types: Vec<Type> = all_types_names
         .iter()
-        .map(|name| {
-            parse_str(name)
-                .unwrap_or_else(|_| panic!("Could not transform {} into a Task Rust type.", name))
+        .zip(&all_tasks_ids)
+        .map(|(name, id)| {
+            parse_str(name).map_err(|_| {
+                format!(
+                    "the type '{}' of the task '{}' is not a valid Rust type, expected a path like \"my_crate::MyTask\".",
+                    name, id
+                )
+            })
         })
-        .collect();
-    (all_tasks_ids, all_types_names, all_types)
+        .collect::<Result<_, _>>()?;
+    Ok((all_tasks_ids, all_types_names, all_types))
 }
 
-fn extract_msg_types(runtime_plan: &CuExecutionLoop) -> Vec<Type> {
+fn extract_msg_types(runtime_plan: &CuExecutionLoop) -> Result<Vec<Type>, String> {
     runtime_plan
         .steps
         .iter()
         .filter_map(|unit| match unit {
             CuExecutionUnit::Step(step) => {
-                if let Some((_, output_msg_type)) = &step.output_msg_index_type {
-                    Some(
-                        parse_str::<Type>(output_msg_type.as_str()).unwrap_or_else(|_| {
-                            panic!(
-                                "Could not transform {} into a message Rust type.",
-                                output_msg_type
-                            )
-                        }),
-                    )
-                } else {
-                    None
-                }
+                step.output_msg_index_type
+                    .as_ref()
+                    .map(|(_, output_msg_type)| {
+                        parse_str::<Type>(output_msg_type.as_str()).map_err(|_| {
+                            format!(
+                            "the message type '{}' sent by the task '{}' is not a valid Rust type.",
+                            output_msg_type,
+                            step.node.get_id()
+                        )
+                        })
+                    })
             }
             CuExecutionUnit::Loop(_) => todo!("Needs to be implemented"),
         })
         .collect()
 }
 
+/// Builds the dump of the CuList, labelling every message with the task that produced it.
+fn build_culist_tuple_dump(runtime_plan: &CuExecutionLoop) -> Result<ItemImpl, String> {
+    let schema: Vec<_> = runtime_plan
+        .steps
+        .iter()
+        .filter_map(|unit| match unit {
+            CuExecutionUnit::Step(step) => step
+                .output_msg_index_type
+                .as_ref()
+                .map(|(_, msg_type)| Ok((step.node.get_id(), msg_type.clone()))),
+            CuExecutionUnit::Loop(_) => Some(Err(
+                "the copper lists of a loop cannot be dumped yet.".to_string(),
+            )),
+        })
+        .collect::<Result<_, String>>()?;
+    let dumps: Vec<_> = schema
+        .iter()
+        .enumerate()
+        .map(|(i, (task_id, msg_type))| {
+            let idx = syn::Index::from(i);
+            quote! { _CuMsgDump::from_msg(#task_id, #msg_type, &self.0.#idx) }
+        })
+        .collect();
+    let schema_text = schema
+        .iter()
+        .map(|(task_id, msg_type)| format!("{task_id}:{msg_type}"))
+        .collect::<Vec<_>>()
+        .join(";");
+    let culist_size = dumps.len();
+    let start_times: Vec<_> = (0..culist_size)
+        .map(|i| {
+            let idx = syn::Index::from(i);
+            quote! { self.0.#idx.metadata.before_process.into() }
+        })
+        .collect();
+    let dyn_msgs: Vec<_> = schema
+        .iter()
+        .enumerate()
+        .map(|(i, (task_id, _))| {
+            let idx = syn::Index::from(i);
+            quote! {
+                if let Ok(msg) = _DynCuMsg::from_msg(&self.0.#idx) {
+                    msgs.push((#task_id, msg));
+                }
+            }
+        })
+        .collect();
+    let records: Vec<_> = (0..culist_size)
+        .map(|i| {
+            let idx = syn::Index::from(i);
+            quote! { channels[#i].record(&self.0.#idx); }
+        })
+        .collect();
+    let encodes: Vec<_> = (0..culist_size)
+        .map(|i| {
+            let idx = syn::Index::from(i);
+            quote! {
+                #i => self.0.#idx.payload().and_then(|payload| bincode::encode_to_vec(payload, cu29::wire::wire_config()).ok()),
+            }
+        })
+        .collect();
+
+    Ok(parse_quote! {
+        impl _CuListDumper for CuMsgs {
+            const SCHEMA: &'static str = #schema_text;
+
+            fn dump_msgs(&self) -> Vec<_CuMsgDump> {
+                vec![#(#dumps),*]
+            }
+
+            fn start_time(&self) -> Option<_CuTime> {
+                let start_times: [Option<_CuTime>; #culist_size] = [#(#start_times),*];
+                start_times.into_iter().flatten().min()
+            }
+
+            fn record_stats(&self, channels: &mut [_CuChannelStats]) {
+                #(#records)*
+            }
+
+            fn dyn_msgs(&self) -> Vec<(&'static str, _DynCuMsg)> {
+                let mut msgs = Vec::new();
+                #(#dyn_msgs)*
+                msgs
+            }
+
+            fn encode_payload(&self, index: usize) -> Option<Vec<u8>> {
+                match index {
+                    #(#encodes)*
+                    _ => None,
+                }
+            }
+        }
+    })
+}
+
 /// Builds the tuple of the CuList as a tuple off all the messages types.
 fn build_culist_tuple(all_msgs_types_in_culist_order: &Vec<Type>) -> TypeTuple {
     if all_msgs_types_in_culist_order.is_empty() {
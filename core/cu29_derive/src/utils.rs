@@ -37,3 +37,52 @@ pub fn caller_crate_root() -> PathBuf {
     }
     current_dir
 }
+
+/// A task id as a Rust identifier: the characters that cannot be in one are replaced by '_'.
+pub(crate) fn id_to_ident(id: &str) -> String {
+    let ident: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.starts_with(|c: char| c.is_ascii_digit()) || ident.is_empty() {
+        format!("_{}", ident)
+    } else {
+        ident
+    }
+}
+
+/// A task id in CamelCase for the type names: "left_wheel" gives "LeftWheel".
+pub(crate) fn id_to_camel_case(id: &str) -> String {
+    let camel: String = id
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    if camel.starts_with(|c: char| c.is_ascii_digit()) || camel.is_empty() {
+        format!("Task{}", camel)
+    } else {
+        camel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_names() {
+        assert_eq!(id_to_ident("imu"), "imu");
+        assert_eq!(id_to_ident("left-wheel"), "left_wheel");
+        assert_eq!(id_to_ident("3d"), "_3d");
+        assert_eq!(id_to_camel_case("imu"), "Imu");
+        assert_eq!(id_to_camel_case("left_wheel"), "LeftWheel");
+        assert_eq!(id_to_camel_case("cam-0"), "Cam0");
+        assert_eq!(id_to_camel_case("3d"), "Task3d");
+    }
+}
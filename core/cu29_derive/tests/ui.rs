@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+/// The compile errors of the copper_runtime macro for the common mistakes in a config.
+#[test]
+fn ui() {
+    // trybuild compiles the cases in its own crate under target/tests/trybuild, the macro looks for the configs there.
+    let target = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let configs = target.join("tests/trybuild/cu29-derive/tests/ui");
+    std::fs::create_dir_all(&configs).unwrap();
+    for entry in std::fs::read_dir("tests/ui").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "ron") {
+            std::fs::copy(&path, configs.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    // The structured logging of the generated code needs an index directory, usually set by the build.rs.
+    std::env::set_var("LOG_INDEX_DIR", env!("CARGO_TARGET_TMPDIR"));
+
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,6 @@
+use cu29_derive::copper_runtime;
+
+#[copper_runtime(config = "tests/ui/invalid_task_type.ron")]
+struct App {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use cu29_derive::copper_runtime;
+
+#[copper_runtime(config = "tests/ui/does_not_exist.ron")]
+struct App {}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use cu29_derive::copper_runtime;
+use cu29_log_derive::debug;
+
+pub struct NotATask {}
+
+#[copper_runtime(config = "tests/ui/not_a_task.ron")]
+struct App {}
+
+fn main() {}
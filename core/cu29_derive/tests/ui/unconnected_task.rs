@@ -0,0 +1,6 @@
+use cu29_derive::copper_runtime;
+
+#[copper_runtime(config = "tests/ui/unconnected_task.ron")]
+struct App {}
+
+fn main() {}
@@ -1,18 +1,40 @@
+// only for not macos platforms
+#[cfg(all(feature = "python", not(target_os = "macos")))]
+pub mod python;
+mod replay;
+
+pub use replay::{CuReplay, MAX_REPLAY_SPEED, MIN_REPLAY_SPEED};
+
 use std::fmt::{Display, Formatter};
-use std::io::Read;
+use std::io::{BufRead, Read};
 use std::path::{Path, PathBuf};
 
-use bincode::config::standard;
-use bincode::decode_from_std_read;
 use bincode::error::DecodeError;
-use cu29::copperlist::CopperList;
+use bincode::{decode_from_slice, decode_from_std_read};
+use cu29::annotation::CuAnnotation;
+use cu29::clock::CuTime;
+use cu29::clockcheck::CuClockEvent;
+use cu29::copperlist::{CopperList, CuListDumper, CuMsgDump};
+use cu29::derived::CuDerivedMsg;
+use cu29::dynmsg::DynCuMsg;
+use cu29::fixture::CuTaskFixture;
+use cu29::namespace::namespaced;
+use cu29::provenance::{trace, CuMsgId};
+use cu29::summary::CuLogSummary;
+use cu29::timefactor::CuTimeFactor;
+use cu29::tuning::CuParamChange;
+use cu29::wire::CuWireHeader;
 use cu29_intern_strs::read_interned_strings;
+use cu29_log::value::Value;
 use cu29_log::{rebuild_logline, CuLogEntry};
-use cu29_traits::{CuError, CuResult, UnifiedLogType};
+use cu29_traits::{wire_config, CuError, CuResult, UnifiedLogType};
 
 use clap::{Parser, Subcommand, ValueEnum};
 use cu29_traits::CopperListTuple;
-use cu29_unifiedlog::{UnifiedLogger, UnifiedLoggerBuilder, UnifiedLoggerIOReader};
+use cu29_unifiedlog::{
+    verify_log, LogKey, LogVerification, UnifiedLogger, UnifiedLoggerBuilder,
+    UnifiedLoggerIOReader, UnifiedLoggerRead,
+};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum ExportFormat {
@@ -37,6 +59,10 @@ pub struct LogReaderCli {
     /// for example for toto_0.copper, toto_1.copper ... the base name is toto.copper
     pub unifiedlog_base: PathBuf,
 
+    /// The file with the 32 bytes of the AES-256-GCM key, if the log is encrypted.
+    #[arg(long)]
+    pub key_file: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -50,24 +76,119 @@ pub enum Command {
         #[arg(short, long, default_value_t = ExportFormat::Json)]
         export_format: ExportFormat,
     },
+    /// List the annotations marking moments of the run
+    Annotations,
+    /// List the changes of the parameters of the tasks made during the run
+    ParamChanges,
+    /// Extract the downsampled channels declared in the logging section of the config
+    Derived {
+        /// Only the channels with this tag, ie. telemetry.
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// List the steps and the drifts of the robot clock against the wall clock, with the copper lists they affect
+    ClockEvents,
+    /// Print the messages a task computed its output from, directly or not, in every copper list
+    Provenance {
+        /// The task whose output is traced, ie. the motor driver.
+        task: String,
+        /// Only this copper list.
+        #[arg(long)]
+        culist: Option<u32>,
+    },
+    /// Print the recorded inputs and outputs of a task as a fixture for its unit tests, see cu29::fixture
+    Fixture {
+        /// The task under test.
+        task: String,
+        /// The tasks producing its inputs, in the order of the inputs of the task.
+        #[arg(long, value_delimiter = ',')]
+        inputs: Vec<String>,
+        /// How far a number of an output can be from the recorded one.
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f64,
+    },
+    /// Print the summary of the run written at the end of the log
+    Info,
+    /// Replay the copperlists at the pace they were recorded
+    Replay {
+        /// Start from this time of the robot clock, in seconds.
+        #[arg(long)]
+        from: Option<f64>,
+        /// From 0.1 (slower) to 10 (faster).
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Wait for Enter between copperlists instead, q to quit. Otherwise a line changes the speed while it plays:
+        /// a factor from 0 to 100, p to pause and Enter to resume.
+        #[arg(long)]
+        step: bool,
+    },
+    /// Check the signatures of a signed log
+    VerifyLog {
+        /// The file with the 32 bytes of the Ed25519 public key.
+        public_key_file: PathBuf,
+    },
 }
 
 /// This is a generator for a main function to build a log extractor.
 /// It depends on the specific type of the CopperList payload that is determined at compile time from the configuration.
 pub fn run_cli<P>() -> CuResult<()>
 where
-    P: CopperListTuple,
+    P: CopperListTuple + CuListDumper,
 {
     let args = LogReaderCli::parse();
     let unifiedlog_base = args.unifiedlog_base;
 
-    let UnifiedLogger::Read(dl) = UnifiedLoggerBuilder::new()
-        .file_base_name(&unifiedlog_base)
-        .build()
-        .expect("Failed to create logger")
-    else {
-        panic!("Failed to create logger");
+    if let Command::VerifyLog { public_key_file } = &args.command {
+        // The sections are checked as they are on disk, there is no need to decrypt them.
+        return match verify_log(&unifiedlog_base, &read_key_file(public_key_file)?)? {
+            LogVerification::Intact {
+                sections,
+                signatures,
+            } => {
+                println!(
+                    "The log is intact: {} sections covered by {} signatures.",
+                    sections, signatures
+                );
+                Ok(())
+            }
+            LogVerification::Broken { section, reason } => {
+                Err(format!("The log is broken from section {}: {}.", section, reason).into())
+            }
+            LogVerification::Truncated { sections } => Err(format!(
+                "The log is truncated: its {} sections are signed but its end was cut off.",
+                sections
+            )
+            .into()),
+        };
+    }
+
+    let key = args.key_file.as_deref().map(read_key_file).transpose()?;
+    let open_log = || {
+        let mut builder = UnifiedLoggerBuilder::new().file_base_name(&unifiedlog_base);
+        if let Some(key) = &key {
+            builder = builder.encryption_key(key);
+        }
+        let UnifiedLogger::Read(dl) = builder.build().expect("Failed to create logger") else {
+            panic!("Failed to create logger");
+        };
+        dl
     };
+    let dl = open_log();
+
+    if matches!(
+        args.command,
+        Command::ExtractCopperlist { .. }
+            | Command::Replay { .. }
+            | Command::Provenance { .. }
+            | Command::Fixture { .. }
+    ) {
+        match read_wire_header(open_log())? {
+            Some(header) => header.check(P::SCHEMA)?,
+            None => eprintln!(
+                "Warning: this log has no wire header, it may have been written by another version of copper."
+            ),
+        }
+    }
 
     match args.command {
         Command::ExtractLog { log_index } => {
@@ -82,18 +203,263 @@ where
                 println!("{:#?}", entry);
             }
         }
+        Command::Annotations => {
+            let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::Annotation);
+            for annotation in annotations_dump(reader) {
+                println!("{}", annotation);
+            }
+        }
+        Command::ParamChanges => {
+            let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::ParamChange);
+            for change in param_changes_dump(reader) {
+                println!("{}", change);
+            }
+        }
+        Command::Derived { tag } => {
+            let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::DerivedChannel);
+            for msg in derived_dump(reader) {
+                if tag.as_ref().is_none_or(|tag| *tag == msg.tag) {
+                    print_derived(&msg);
+                }
+            }
+        }
+        Command::ClockEvents => {
+            let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::ClockEvent);
+            for event in clock_events_dump(reader) {
+                println!("{}", event);
+            }
+        }
+        Command::Provenance { task, culist } => {
+            let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::CopperList);
+            for entry in copperlists_dump::<P>(reader) {
+                if culist.is_some_and(|culist| culist != entry.id) {
+                    continue;
+                }
+                let msgs = entry.msgs.dump_msgs();
+                let Some(slot) = msgs.iter().position(|msg| msg.task == task) else {
+                    return Err(format!("No task {} in the copper lists of this log.", task).into());
+                };
+                print!("{}", format_provenance(entry.id, &msgs, slot));
+            }
+        }
+        Command::Fixture {
+            task,
+            inputs,
+            tolerance,
+        } => {
+            let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::CopperList);
+            let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+            print!(
+                "{}",
+                task_fixture::<P>(reader, &task, &inputs, tolerance)?.to_ron()?
+            );
+        }
+        Command::Info => {
+            // The header of another version of the format is not decodable, the summary may still be.
+            if let Some(header) = read_wire_header(open_log()).ok().flatten() {
+                if let Some(robot_id) = header.robot_id {
+                    println!("Written by the robot {}.", robot_id);
+                }
+                if let Some(run) = header.run {
+                    println!("Written by the {}.", run);
+                }
+            }
+            match read_summary(dl)? {
+                Some(summary) => print!("{}", summary),
+                None => println!("This log has no summary, the application did not exit cleanly."),
+            }
+        }
+        Command::Replay { from, speed, step } => {
+            let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::CopperList);
+            let changes = UnifiedLoggerIOReader::new(open_log(), UnifiedLogType::ParamChange);
+            let mut replay = CuReplay::<P>::from_reader(reader)
+                .with_param_changes(param_changes_dump(changes).collect());
+            replay.set_speed(speed)?;
+            if let Some(from) = from {
+                replay.seek(CuTime::from((from * 1_000_000_000.0) as u64));
+            }
+            if step {
+                let mut lines = std::io::stdin().lock().lines();
+                loop {
+                    let changes = replay.due_param_changes();
+                    let Some(entry) = replay.step() else {
+                        break;
+                    };
+                    print_replayed(&changes, entry);
+                    match lines.next() {
+                        Some(Ok(line)) if line.trim() != "q" => {}
+                        _ => break,
+                    }
+                }
+            } else {
+                let factor = replay.time_factor();
+                std::thread::spawn(move || control_speed(&factor, std::io::stdin().lock().lines()));
+                replay.play(|changes, entry| {
+                    print_replayed(changes, entry);
+                    Ok(true)
+                })?;
+            }
+        }
+        Command::VerifyLog { .. } => unreachable!(),
     }
 
     Ok(())
 }
 
+/// Changes the speed of a replay from the lines typed while it plays: a factor, p to pause and an empty line to
+/// resume at the speed before the pause.
+fn control_speed(factor: &CuTimeFactor, lines: impl Iterator<Item = std::io::Result<String>>) {
+    let mut resumed = factor.get();
+    for line in lines.map_while(Result::ok) {
+        let outcome = match line.trim() {
+            "p" => {
+                if !factor.is_paused() {
+                    resumed = factor.get();
+                }
+                factor.pause();
+                Ok(())
+            }
+            "" => factor.set(resumed),
+            speed => speed
+                .parse::<f64>()
+                .map_err(|_| CuError::from(format!("Invalid speed '{}'.", speed)))
+                .and_then(|speed| factor.set(speed)),
+        };
+        match outcome {
+            Ok(()) => eprintln!("Speed: {}x.", factor.get()),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+fn print_replayed<P: CopperListTuple + CuListDumper>(
+    changes: &[CuParamChange],
+    entry: &CopperList<P>,
+) {
+    for change in changes {
+        println!("Parameter change: {}", change);
+    }
+    match entry.start_time() {
+        Some(time) => println!("{}: {:#?}", time, entry),
+        None => println!("{:#?}", entry),
+    }
+}
+
+/// The provenance chain of a message of a copper list, one message per line indented by its depth.
+pub fn format_provenance(culist: u32, msgs: &[CuMsgDump], slot: usize) -> String {
+    let mut text = format!("copper list {}:\n", culist);
+    for (depth, slot) in trace(msgs, slot) {
+        let msg = &msgs[slot];
+        let produced = if msg.payload.is_some() {
+            ""
+        } else {
+            " (no payload)"
+        };
+        text.push_str(&format!(
+            "{}{} {}{}\n",
+            "  ".repeat(depth),
+            CuMsgId {
+                culist,
+                slot: slot as u16
+            },
+            msg.task,
+            produced
+        ));
+    }
+    text
+}
+
+/// The payload as JSON if its type is registered in cu29::registry.
+fn print_derived(msg: &CuDerivedMsg) {
+    let payload = DynCuMsg::decode(&msg.msg_type, &msg.payload)
+        .map(|payload| payload.to_json())
+        .unwrap_or_else(|_| format!("<{} bytes of {}>", msg.payload.len(), msg.msg_type));
+    println!("{} [{}] {}: {}", msg.time, msg.tag, msg.task, payload);
+}
+
+/// Reads a raw 32 bytes key.
+fn read_key_file(path: &Path) -> CuResult<LogKey> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| CuError::new_with_cause("Could not read the key file", e))?;
+    bytes.as_slice().try_into().map_err(|_| {
+        format!(
+            "The key file {} must contain exactly 32 bytes, it has {}.",
+            path.display(),
+            bytes.len()
+        )
+        .into()
+    })
+}
+
+/// Extracts the annotations from their binary representation, see cu29::annotation.
+pub fn annotations_dump(mut src: impl Read) -> impl Iterator<Item = CuAnnotation> {
+    std::iter::from_fn(move || {
+        decode_from_std_read::<CuAnnotation, _, _>(&mut src, wire_config()).ok()
+    })
+}
+
+/// Extracts the changes of the parameters from their binary representation, see cu29::tuning.
+pub fn param_changes_dump(mut src: impl Read) -> impl Iterator<Item = CuParamChange> {
+    std::iter::from_fn(move || {
+        decode_from_std_read::<CuParamChange, _, _>(&mut src, wire_config()).ok()
+    })
+}
+
+/// Extracts the messages of the derived channels from their binary representation, see cu29::derived.
+pub fn derived_dump(mut src: impl Read) -> impl Iterator<Item = CuDerivedMsg> {
+    std::iter::from_fn(move || {
+        decode_from_std_read::<CuDerivedMsg, _, _>(&mut src, wire_config()).ok()
+    })
+}
+
+/// Extracts the clock events from their binary representation, see cu29::clockcheck.
+pub fn clock_events_dump(mut src: impl Read) -> impl Iterator<Item = CuClockEvent> {
+    std::iter::from_fn(move || {
+        decode_from_std_read::<CuClockEvent, _, _>(&mut src, wire_config()).ok()
+    })
+}
+
+/// Reads the summary written at the end of a log, None if there is none.
+pub fn read_summary(mut reader: UnifiedLoggerRead) -> CuResult<Option<CuLogSummary>> {
+    let Some(section) = reader.read_next_section_type(UnifiedLogType::Summary)? else {
+        return Ok(None);
+    };
+    let (summary, _) = decode_from_slice::<CuLogSummary, _>(&section, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not decode the log summary", e))?;
+    Ok(Some(summary))
+}
+
+/// Reads the version of the wire format and the layout of the copper lists of a log, None if it has none.
+pub fn read_wire_header(mut reader: UnifiedLoggerRead) -> CuResult<Option<CuWireHeader>> {
+    let Some(section) = reader.read_next_section_type(UnifiedLogType::Schema)? else {
+        return Ok(None);
+    };
+    let (header, _) = decode_from_slice::<CuWireHeader, _>(&section, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not decode the wire header", e))?;
+    Ok(Some(header))
+}
+
+/// The process calls of a task in the copper lists of a log, with the messages of the tasks producing its inputs.
+pub fn task_fixture<P: CopperListTuple + CuListDumper>(
+    src: impl Read,
+    task: &str,
+    inputs: &[&str],
+    tolerance: f64,
+) -> CuResult<CuTaskFixture> {
+    let mut fixture = CuTaskFixture::new(task, inputs, tolerance);
+    for culist in copperlists_dump::<P>(src) {
+        fixture.record(&culist)?;
+    }
+    Ok(fixture)
+}
+
 /// Extracts the copper lists from a binary representation.
 /// P is the Payload determined by the configuration of the application.
 pub fn copperlists_dump<P: CopperListTuple>(
     mut src: impl Read,
 ) -> impl Iterator<Item = CopperList<P>> {
     std::iter::from_fn(move || {
-        let entry = decode_from_std_read::<CopperList<P>, _, _>(&mut src, standard());
+        let entry = decode_from_std_read::<CopperList<P>, _, _>(&mut src, wire_config());
         match entry {
             Ok(entry) => Some(entry),
             Err(e) => match e {
@@ -122,7 +488,7 @@ pub fn copperlists_dump<P: CopperListTuple>(
 pub fn textlog_dump(mut src: impl Read, index: &Path) -> CuResult<()> {
     let all_strings = read_interned_strings(index)?;
     loop {
-        let entry = decode_from_std_read::<CuLogEntry, _, _>(&mut src, standard());
+        let entry = decode_from_std_read::<CuLogEntry, _, _>(&mut src, wire_config());
 
         match entry {
             Err(DecodeError::UnexpectedEnd { .. }) => return Ok(()),
@@ -155,185 +521,69 @@ pub fn textlog_dump(mut src: impl Read, index: &Path) -> CuResult<()> {
     Ok(())
 }
 
-// only for not macos platforms
-#[cfg(not(target_os = "macos"))]
-mod python {
-    use bincode::config::standard;
-    use bincode::decode_from_std_read;
-    use bincode::error::DecodeError;
-    use cu29_intern_strs::read_interned_strings;
-    use cu29_log::value::Value;
-    use cu29_log::CuLogEntry;
-    use cu29_traits::UnifiedLogType;
-    use cu29_unifiedlog::{UnifiedLogger, UnifiedLoggerBuilder, UnifiedLoggerIOReader};
-    use pyo3::exceptions::PyIOError;
-    use pyo3::prelude::*;
-    use pyo3::types::{PyDelta, PyDict, PyList};
-    use std::io::Read;
-    use std::path::Path;
-
-    #[pyclass]
-    pub struct PyLogIterator {
-        reader: Box<dyn Read + Send>,
-    }
-
-    #[pymethods]
-    impl PyLogIterator {
-        fn __iter__(slf: PyRefMut<Self>) -> PyRefMut<Self> {
-            slf
-        }
-
-        fn __next__(mut slf: PyRefMut<Self>) -> Option<PyResult<PyCuLogEntry>> {
-            match decode_from_std_read::<CuLogEntry, _, _>(&mut slf.reader, standard()) {
-                Ok(entry) => {
-                    if entry.msg_index == 0 {
-                        None
-                    } else {
-                        Some(Ok(PyCuLogEntry { inner: entry }))
-                    }
-                }
-                Err(DecodeError::UnexpectedEnd { .. }) => None,
-                Err(DecodeError::Io { inner, .. })
-                    if inner.kind() == std::io::ErrorKind::UnexpectedEof =>
-                {
-                    None
-                }
-                Err(e) => Some(Err(PyIOError::new_err(e.to_string()))),
-            }
-        }
-    }
-
-    /// Creates an iterator of CuLogEntries from a bare binary structured log file (ie. not within a unified log).
-    /// This is mainly used for using the structured logging out of the Copper framework.
-    /// it returns a tuple with the iterator of log entries and the list of interned strings.
-    #[pyfunction]
-    pub fn struct_log_iterator_bare(
-        bare_struct_src_path: &str,
-        index_path: &str,
-    ) -> PyResult<(PyLogIterator, Vec<String>)> {
-        let file = std::fs::File::open(bare_struct_src_path)
-            .map_err(|e| PyIOError::new_err(e.to_string()))?;
-        let all_strings = read_interned_strings(Path::new(index_path))
-            .map_err(|e| PyIOError::new_err(e.to_string()))?;
-        Ok((
-            PyLogIterator {
-                reader: Box::new(file),
-            },
-            all_strings,
-        ))
-    }
-    /// Creates an iterator of CuLogEntries from a unified log file.
-    /// This function allows you to easily use python to datamind Copper's structured text logs.
-    /// it returns a tuple with the iterator of log entries and the list of interned strings.
-    #[pyfunction]
-    pub fn struct_log_iterator_unified(
-        unified_src_path: &str,
-        index_path: &str,
-    ) -> PyResult<(PyLogIterator, Vec<String>)> {
-        let all_strings = read_interned_strings(Path::new(index_path))
-            .map_err(|e| PyIOError::new_err(e.to_string()))?;
-
-        let UnifiedLogger::Read(dl) = UnifiedLoggerBuilder::new()
-            .file_base_name(Path::new(unified_src_path))
-            .build()
-            .expect("Failed to create logger")
-        else {
-            panic!("Failed to create logger");
-        };
-
-        let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::StructuredLogLine);
-        Ok((
-            PyLogIterator {
-                reader: Box::new(reader),
-            },
-            all_strings,
-        ))
-    }
-
-    /// This is a python wrapper for CuLogEntries.
-    #[pyclass]
-    pub struct PyCuLogEntry {
-        pub inner: CuLogEntry,
-    }
-
-    #[pymethods]
-    impl PyCuLogEntry {
-        /// Returns the timestamp of the log entry.
-        pub fn ts<'a>(&self, py: Python<'a>) -> Bound<'a, PyDelta> {
-            let nanoseconds = self.inner.time.0;
-
-            // Convert nanoseconds to seconds and microseconds
-            let days = (nanoseconds / 86_400_000_000_000) as i32;
-            let seconds = (nanoseconds / 1_000_000_000) as i32;
-            let microseconds = ((nanoseconds % 1_000_000_000) / 1_000) as i32;
-
-            PyDelta::new_bound(py, days, seconds, microseconds, false).unwrap()
-        }
-
-        /// Returns the index of the message in the vector of interned strings.
-        pub fn msg_index(&self) -> u32 {
-            self.inner.msg_index
-        }
-
-        /// Returns the index of the parameter names in the vector of interned strings.
-        pub fn paramname_indexes(&self) -> Vec<u32> {
-            self.inner.paramname_indexes.iter().copied().collect()
-        }
+/// The channels of a schema of copper lists (see cu29::wire), as the task producing them and their message type.
+pub fn schema_channels(schema: &str) -> Vec<(String, String)> {
+    schema
+        .split(';')
+        .filter_map(|channel| channel.split_once(':'))
+        .map(|(task, msg_type)| (task.to_string(), msg_type.to_string()))
+        .collect()
+}
 
-        /// Returns the parameters of this log line
-        pub fn params(&self) -> Vec<PyObject> {
-            self.inner.params.iter().map(value_to_py).collect()
-        }
-    }
+/// A message of a copper list with its payload as a value, for the readers that do not know the payload types.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuLogMsg {
+    /// The id of its copper list.
+    pub culist: u32,
+    /// When the iteration of its copper list started.
+    pub time: Option<CuTime>,
+    pub task: String,
+    pub msg_type: String,
+    pub tov: Option<CuTime>,
+    /// The fields of the payload if its type is registered in cu29::registry, its Debug representation otherwise,
+    /// None if the task produced nothing.
+    pub payload: Option<Value>,
+    /// The robot which wrote the log, from its wire header, see cu29::namespace.
+    pub robot_id: Option<String>,
+}
 
-    #[pymodule]
-    fn cu29_export(m: &Bound<'_, PyModule>) -> PyResult<()> {
-        m.add_class::<PyCuLogEntry>()?;
-        m.add_class::<PyLogIterator>()?;
-        m.add_function(wrap_pyfunction!(struct_log_iterator_bare, m)?)?;
-        m.add_function(wrap_pyfunction!(struct_log_iterator_unified, m)?)?;
-        Ok(())
+impl CuLogMsg {
+    /// The task in the namespace of the robot, ie. `r2/camera`, to aggregate the logs of several robots.
+    pub fn channel(&self) -> String {
+        namespaced(self.robot_id.as_deref(), &self.task)
     }
+}
 
-    fn value_to_py(value: &Value) -> PyObject {
-        match value {
-            Value::String(s) => Python::with_gil(|py| s.to_object(py)),
-            Value::U64(u) => Python::with_gil(|py| u.to_object(py)),
-            Value::I64(i) => Python::with_gil(|py| i.to_object(py)),
-            Value::F64(f) => Python::with_gil(|py| f.to_object(py)),
-            Value::Bool(b) => Python::with_gil(|py| b.to_object(py)),
-            Value::CuTime(t) => Python::with_gil(|py| t.0.to_object(py)),
-            Value::Bytes(b) => Python::with_gil(|py| b.to_object(py)),
-            Value::Char(c) => Python::with_gil(|py| c.to_object(py)),
-            Value::I8(i) => Python::with_gil(|py| i.to_object(py)),
-            Value::U8(u) => Python::with_gil(|py| u.to_object(py)),
-            Value::I16(i) => Python::with_gil(|py| i.to_object(py)),
-            Value::U16(u) => Python::with_gil(|py| u.to_object(py)),
-            Value::I32(i) => Python::with_gil(|py| i.to_object(py)),
-            Value::U32(u) => Python::with_gil(|py| u.to_object(py)),
-            Value::Map(m) => Python::with_gil(|py| {
-                let dict = PyDict::new_bound(py);
-                for (k, v) in m.iter() {
-                    dict.set_item(value_to_py(k), value_to_py(v)).unwrap();
-                }
-                dict.to_object(py)
-            }),
-            Value::F32(f) => Python::with_gil(|py| f.to_object(py)),
-            Value::Option(o) => Python::with_gil(|py| {
-                if o.is_none() {
-                    py.None()
-                } else {
-                    o.clone().map(|v| value_to_py(&v)).unwrap()
+/// Extracts the messages of the copper lists from a binary representation, in the order they were computed.
+/// robot_id is the one of the wire header of the log.
+pub fn messages_dump<P: CopperListTuple + CuListDumper>(
+    src: impl Read,
+    robot_id: Option<String>,
+) -> impl Iterator<Item = CuLogMsg> {
+    copperlists_dump::<P>(src).flat_map(move |culist| {
+        let time = culist.start_time();
+        let mut dyn_msgs = culist.msgs.dyn_msgs();
+        culist
+            .msgs
+            .dump_msgs()
+            .into_iter()
+            .map(|msg| {
+                let payload = match dyn_msgs.iter().position(|(task, _)| *task == msg.task) {
+                    Some(index) => dyn_msgs.swap_remove(index).1.payload,
+                    None => msg.payload.map(Value::String),
+                };
+                CuLogMsg {
+                    culist: culist.id,
+                    time,
+                    task: msg.task,
+                    msg_type: msg.msg_type,
+                    tov: msg.tov,
+                    payload,
+                    robot_id: robot_id.clone(),
                 }
-            }),
-            Value::Unit => Python::with_gil(|py| py.None()),
-            Value::Newtype(v) => value_to_py(v),
-            Value::Seq(s) => Python::with_gil(|py| {
-                let list = PyList::new_bound(py, s.iter().map(value_to_py));
-                list.to_object(py)
-            }),
-        }
-    }
+            })
+            .collect::<Vec<_>>()
+    })
 }
 
 #[cfg(test)]
@@ -344,6 +594,15 @@ mod tests {
     use std::sync::{Arc, Mutex};
     use tempfile::{tempdir, TempDir};
 
+    use cu29::annotation::{CuAnnotator, CuSeverity};
+    use cu29::config::CuConfig;
+    use cu29::cutask::CuMsg;
+    use cu29::derived::CuDerivedLogger;
+    use cu29::dynmsg::DynCuMsg;
+    use cu29::lineage::CuRunId;
+    use cu29::provenance::CuProvenance;
+    use cu29::registry::register_json_msg_type;
+    use cu29::summary::CuChannelStats;
     use cu29_clock::RobotClock;
     use cu29_log::value::Value;
     use cu29_log_runtime::LoggerRuntime;
@@ -371,7 +630,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let temp_path = copy_stringindex_to_temp(&temp_dir);
         let entry = CuLogEntry::new(3);
-        let bytes = bincode::encode_to_vec(&entry, standard()).unwrap();
+        let bytes = bincode::encode_to_vec(&entry, wire_config()).unwrap();
         let reader = Cursor::new(bytes.as_slice());
         textlog_dump(reader, temp_path.as_path()).unwrap();
     }
@@ -428,6 +687,160 @@ mod tests {
         .expect("Failed to dump log");
     }
 
+    #[test]
+    fn test_read_summary() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("test_read_summary.copper");
+        let summary = CuLogSummary {
+            copper_lists: 12,
+            duration: 1_000_000.into(),
+            channels: Vec::new(),
+            tasks: Vec::new(),
+            memory: Vec::new(),
+        };
+        {
+            let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+                .write(true)
+                .create(true)
+                .file_base_name(&path)
+                .preallocated_size(100000)
+                .build()
+                .expect("Failed to create logger")
+            else {
+                panic!("Failed to create logger")
+            };
+            cu29::summary::write_summary(Arc::new(Mutex::new(logger)), &summary).unwrap();
+        }
+        let UnifiedLogger::Read(logger) = UnifiedLoggerBuilder::new()
+            .file_base_name(&path)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        assert_eq!(read_summary(logger).unwrap(), Some(summary));
+    }
+
+    #[test]
+    fn test_read_wire_header() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("test_read_wire_header.copper");
+        {
+            let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+                .write(true)
+                .create(true)
+                .file_base_name(&path)
+                .preallocated_size(100000)
+                .build()
+                .expect("Failed to create logger")
+            else {
+                panic!("Failed to create logger")
+            };
+            let run = CuRunId::start(None).unwrap();
+            cu29::wire::write_wire_header(
+                Arc::new(Mutex::new(logger)),
+                "src:u32",
+                Some("r2"),
+                Some(&run),
+            )
+            .unwrap();
+        }
+        let UnifiedLogger::Read(logger) = UnifiedLoggerBuilder::new()
+            .file_base_name(&path)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let header = read_wire_header(logger).unwrap().unwrap();
+        assert_eq!(header.robot_id.as_deref(), Some("r2"));
+        assert_eq!(header.run.as_ref().unwrap().counter, 0);
+        assert!(header.check("src:u32").is_ok());
+        assert!(header.check("src:u64").is_err());
+    }
+
+    #[test]
+    fn test_annotations_dump() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("test_annotations_dump.copper");
+        {
+            let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+                .write(true)
+                .create(true)
+                .file_base_name(&path)
+                .preallocated_size(100000)
+                .build()
+                .expect("Failed to create logger")
+            else {
+                panic!("Failed to create logger")
+            };
+            let (clock, mock) = RobotClock::mock();
+            let annotator = CuAnnotator::new(clock, Arc::new(Mutex::new(logger)));
+            mock.increment(std::time::Duration::from_secs(2));
+            annotator
+                .annotate("picked up object", CuSeverity::Info)
+                .unwrap();
+            annotator.annotate("bumped", CuSeverity::Critical).unwrap();
+        }
+        let UnifiedLogger::Read(logger) = UnifiedLoggerBuilder::new()
+            .file_base_name(&path)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let reader = UnifiedLoggerIOReader::new(logger, UnifiedLogType::Annotation);
+        let annotations: Vec<_> = annotations_dump(reader).collect();
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0].label, "picked up object");
+        assert_eq!(annotations[0].time, CuTime::from(2_000_000_000));
+        assert_eq!(annotations[1].severity, CuSeverity::Critical);
+        assert_eq!(annotations[1].to_string(), "2.000 s [critical] bumped");
+    }
+
+    #[test]
+    fn test_derived_dump() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("test_derived_dump.copper");
+        {
+            let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+                .write(true)
+                .create(true)
+                .file_base_name(&path)
+                .preallocated_size(100000)
+                .build()
+                .expect("Failed to create logger")
+            else {
+                panic!("Failed to create logger")
+            };
+            let config = CuConfig::deserialize_ron(
+                r#"( tasks: [(id: "clock", type: "Clock"), (id: "imu", type: "Imu")], cnx: [],
+                     logging: (derived: [(task: "imu", rate_hz: 100.0, tag: "telemetry")]) )"#,
+            );
+            let mut derived = CuDerivedLogger::new(&config).unwrap();
+            derived.set_logger(Arc::new(Mutex::new(logger)));
+            for i in 0..50u64 {
+                let mut clock = CuMsg::new(Some(i as u32));
+                clock.metadata.before_process = CuTime::from(i * 1_000_000).into();
+                derived.record(&ImuPayload(clock, CuMsg::new(Some(i as f32))));
+            }
+        }
+        let UnifiedLogger::Read(logger) = UnifiedLoggerBuilder::new()
+            .file_base_name(&path)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let reader = UnifiedLoggerIOReader::new(logger, UnifiedLogType::DerivedChannel);
+        let msgs: Vec<_> = derived_dump(reader).collect();
+        assert_eq!(msgs.len(), 5);
+        assert_eq!(msgs[1].time, CuTime::from(10_000_000));
+        assert_eq!(msgs[1].msg_type, "f32");
+        let (value, _) = decode_from_slice::<f32, _>(&msgs[1].payload, wire_config()).unwrap();
+        assert_eq!(value, 10.0);
+    }
+
     // This is normally generated at compile time in CuPayload.
     type MyCuPayload = (u8, i32, f32);
 
@@ -441,7 +854,7 @@ mod tests {
         for pl in mypls.iter() {
             let cl = CopperList::<MyCuPayload>::new(1, *pl);
             offset +=
-                encode_into_slice(&cl, &mut data.as_mut_slice()[offset..], standard()).unwrap();
+                encode_into_slice(&cl, &mut data.as_mut_slice()[offset..], wire_config()).unwrap();
         }
 
         let reader = Cursor::new(data);
@@ -452,4 +865,132 @@ mod tests {
         assert_eq!(iter.next().unwrap().msgs, (3, 4, 5.0));
         assert_eq!(iter.next().unwrap().msgs, (4, 5, 6.0));
     }
+
+    #[derive(Debug, bincode::Encode, bincode::Decode)]
+    struct ImuPayload(CuMsg<u32>, CuMsg<f32>);
+
+    impl CuListDumper for ImuPayload {
+        const SCHEMA: &'static str = "clock:u32;imu:f32";
+
+        fn dump_msgs(&self) -> Vec<CuMsgDump> {
+            vec![
+                CuMsgDump::from_msg("clock", "u32", &self.0),
+                CuMsgDump::from_msg("imu", "f32", &self.1),
+            ]
+        }
+
+        fn start_time(&self) -> Option<CuTime> {
+            self.0.metadata.before_process.into()
+        }
+
+        fn record_stats(&self, _channels: &mut [CuChannelStats]) {}
+
+        fn dyn_msgs(&self) -> Vec<(&'static str, DynCuMsg)> {
+            DynCuMsg::from_msg(&self.1)
+                .into_iter()
+                .map(|msg| ("imu", msg))
+                .collect()
+        }
+
+        fn encode_payload(&self, index: usize) -> Option<Vec<u8>> {
+            match index {
+                0 => self
+                    .0
+                    .payload()
+                    .map(|payload| bincode::encode_to_vec(payload, wire_config()).unwrap()),
+                1 => self
+                    .1
+                    .payload()
+                    .map(|payload| bincode::encode_to_vec(payload, wire_config()).unwrap()),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_messages_dump() {
+        register_json_msg_type::<f32>("f32").unwrap();
+        let mut data = Vec::new();
+        for i in 0..2u32 {
+            let mut clock = CuMsg::new(Some(i));
+            clock.metadata.before_process = CuTime::from(i as u64 * 1000).into();
+            let imu = CuMsg::new(if i == 0 { Some(9.5) } else { None });
+            let cl = CopperList::new(i, ImuPayload(clock, imu));
+            data.extend(bincode::encode_to_vec(&cl, wire_config()).unwrap());
+        }
+
+        let msgs: Vec<CuLogMsg> =
+            messages_dump::<ImuPayload>(Cursor::new(data), Some("r2".to_string())).collect();
+        assert_eq!(msgs.len(), 4);
+        assert_eq!(msgs[0].task, "clock");
+        assert_eq!(msgs[0].payload, Some(Value::String("0".to_string())));
+        assert_eq!(msgs[1].payload, Some(Value::F32(9.5)));
+        assert_eq!(msgs[3].culist, 1);
+        assert_eq!(msgs[3].time, Some(CuTime::from(1000)));
+        assert_eq!(msgs[3].payload, None);
+        assert_eq!(msgs[3].channel(), "r2/imu");
+
+        assert_eq!(
+            schema_channels(ImuPayload::SCHEMA),
+            vec![
+                ("clock".to_string(), "u32".to_string()),
+                ("imu".to_string(), "f32".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_task_fixture() {
+        let mut data = Vec::new();
+        for i in 0..3u32 {
+            let clock = CuMsg::new(Some(i));
+            let mut imu = CuMsg::new(Some(i as f32 / 2.0));
+            // The imu did not run in the last copper list.
+            if i < 2 {
+                imu.metadata.before_process = CuTime::from(i as u64 * 1000).into();
+            }
+            let cl = CopperList::new(i, ImuPayload(clock, imu));
+            data.extend(bincode::encode_to_vec(&cl, wire_config()).unwrap());
+        }
+
+        let fixture =
+            task_fixture::<ImuPayload>(Cursor::new(data.clone()), "imu", &["clock"], 0.01).unwrap();
+        assert_eq!(fixture.cases.len(), 2);
+        assert_eq!(fixture.cases[1].culist, 1);
+        assert_eq!(fixture.cases[1].inputs[0].debug.as_deref(), Some("1"));
+        assert_eq!(fixture.cases[1].expected.as_deref(), Some("0.5"));
+        assert!(fixture
+            .run(|case| Ok(case.input::<u32>(0)?.payload().map(|&i| i as f32 / 2.0)))
+            .is_ok());
+
+        assert!(task_fixture::<ImuPayload>(Cursor::new(data), "imu", &["gps"], 0.01).is_err());
+    }
+
+    #[test]
+    fn test_format_provenance() {
+        let clock = CuMsg::new(Some(1u32));
+        let mut imu = CuMsg::new(None::<f32>);
+        imu.metadata.provenance = CuProvenance::from_inputs(&[(0, true)]);
+        let msgs = ImuPayload(clock, imu).dump_msgs();
+        assert_eq!(
+            format_provenance(7, &msgs, 1),
+            "copper list 7:\n7#1 imu (no payload)\n  7#0 clock\n"
+        );
+    }
+
+    #[test]
+    fn test_control_speed() {
+        let factor = CuTimeFactor::default();
+        let observed = factor.clone();
+        // The speed before every line, the next one is read once the previous one is applied.
+        let mut speeds = Vec::new();
+        let lines = ["4", "p", "p", "", "fast", "0.25"].into_iter().map(|line| {
+            speeds.push(observed.get());
+            Ok(line.to_string())
+        });
+        control_speed(&factor, lines);
+        speeds.push(factor.get());
+        // A pause is resumed at the speed before it.
+        assert_eq!(speeds, vec![1.0, 4.0, 0.0, 0.0, 4.0, 4.0, 0.25]);
+    }
 }
@@ -0,0 +1,328 @@
+//! The python module of the log reader: the structured log lines of any log and, from the module of an application,
+//! its copper lists with the payloads as dicts.
+//!
+//! The module `cu29_export` built with this crate reads the structured logs. To read the copper lists, the application
+//! builds its own module with the type of its copper lists, ie. in a cdylib crate depending on pyo3:
+//!
+//! ```ignore
+//! gen_cumsgs!("copperconfig.ron");
+//!
+//! #[pymodule]
+//! fn my_robot_logs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+//!     cu29_export::python::add_log_reader::<CuMsgs>(m)
+//! }
+//! ```
+//!
+//! ```python
+//! log = my_robot_logs.CopperLog("robot.copper")
+//! log.channels()  # [("imu", "sensors::ImuPayload"), ...]
+//! for msg in log.messages("imu", start=10_000_000_000, end=20_000_000_000):
+//!     print(msg["time"], msg["payload"]["acc_z"])
+//! ```
+//!
+//! The payloads of the types registered in cu29::registry are dicts of their fields, the others are given as their
+//! Debug representation. Register them before opening a log.
+
+// The wrappers pyo3 generates for the functions returning a PyResult convert its error into a PyErr.
+#![allow(clippy::useless_conversion)]
+
+use crate::{messages_dump, read_wire_header, schema_channels, CuLogMsg};
+use bincode::decode_from_std_read;
+use bincode::error::DecodeError;
+use cu29::copperlist::CuListDumper;
+use cu29_intern_strs::read_interned_strings;
+use cu29_log::value::Value;
+use cu29_log::CuLogEntry;
+use cu29_traits::{wire_config, CopperListTuple, CuError, CuResult, UnifiedLogType};
+use cu29_unifiedlog::{
+    UnifiedLogger, UnifiedLoggerBuilder, UnifiedLoggerIOReader, UnifiedLoggerRead,
+};
+use pyo3::exceptions::{PyIOError, PyRuntimeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDelta, PyDict, PyList};
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+#[pyclass]
+pub struct PyLogIterator {
+    reader: Box<dyn Read + Send>,
+}
+
+#[pymethods]
+impl PyLogIterator {
+    fn __iter__(slf: PyRefMut<Self>) -> PyRefMut<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> Option<PyResult<PyCuLogEntry>> {
+        match decode_from_std_read::<CuLogEntry, _, _>(&mut slf.reader, wire_config()) {
+            Ok(entry) => {
+                if entry.msg_index == 0 {
+                    None
+                } else {
+                    Some(Ok(PyCuLogEntry { inner: entry }))
+                }
+            }
+            Err(DecodeError::UnexpectedEnd { .. }) => None,
+            Err(DecodeError::Io { inner, .. })
+                if inner.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                None
+            }
+            Err(e) => Some(Err(PyIOError::new_err(e.to_string()))),
+        }
+    }
+}
+
+/// Creates an iterator of CuLogEntries from a bare binary structured log file (ie. not within a unified log).
+/// This is mainly used for using the structured logging out of the Copper framework.
+/// it returns a tuple with the iterator of log entries and the list of interned strings.
+#[pyfunction]
+pub fn struct_log_iterator_bare(
+    bare_struct_src_path: &str,
+    index_path: &str,
+) -> PyResult<(PyLogIterator, Vec<String>)> {
+    let file =
+        std::fs::File::open(bare_struct_src_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+    let all_strings = read_interned_strings(Path::new(index_path))
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    Ok((
+        PyLogIterator {
+            reader: Box::new(file),
+        },
+        all_strings,
+    ))
+}
+/// Creates an iterator of CuLogEntries from a unified log file.
+/// This function allows you to easily use python to datamind Copper's structured text logs.
+/// it returns a tuple with the iterator of log entries and the list of interned strings.
+#[pyfunction]
+pub fn struct_log_iterator_unified(
+    unified_src_path: &str,
+    index_path: &str,
+) -> PyResult<(PyLogIterator, Vec<String>)> {
+    let all_strings = read_interned_strings(Path::new(index_path))
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+
+    let UnifiedLogger::Read(dl) = UnifiedLoggerBuilder::new()
+        .file_base_name(Path::new(unified_src_path))
+        .build()
+        .expect("Failed to create logger")
+    else {
+        panic!("Failed to create logger");
+    };
+
+    let reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::StructuredLogLine);
+    Ok((
+        PyLogIterator {
+            reader: Box::new(reader),
+        },
+        all_strings,
+    ))
+}
+
+/// This is a python wrapper for CuLogEntries.
+#[pyclass]
+pub struct PyCuLogEntry {
+    pub inner: CuLogEntry,
+}
+
+#[pymethods]
+impl PyCuLogEntry {
+    /// Returns the timestamp of the log entry.
+    pub fn ts<'a>(&self, py: Python<'a>) -> Bound<'a, PyDelta> {
+        let nanoseconds = self.inner.time.0;
+
+        // Convert nanoseconds to seconds and microseconds
+        let days = (nanoseconds / 86_400_000_000_000) as i32;
+        let seconds = (nanoseconds / 1_000_000_000) as i32;
+        let microseconds = ((nanoseconds % 1_000_000_000) / 1_000) as i32;
+
+        PyDelta::new_bound(py, days, seconds, microseconds, false).unwrap()
+    }
+
+    /// Returns the index of the message in the vector of interned strings.
+    pub fn msg_index(&self) -> u32 {
+        self.inner.msg_index
+    }
+
+    /// Returns the index of the parameter names in the vector of interned strings.
+    pub fn paramname_indexes(&self) -> Vec<u32> {
+        self.inner.paramname_indexes.iter().copied().collect()
+    }
+
+    /// Returns the parameters of this log line
+    pub fn params(&self) -> Vec<PyObject> {
+        self.inner.params.iter().map(value_to_py).collect()
+    }
+}
+
+/// The channels of a log, see schema_channels, and its messages.
+type LogMessages = (Vec<(String, String)>, Vec<CuLogMsg>);
+
+/// Reads a log with the copper lists of the application.
+type ReadMessagesFn = fn(&Path) -> CuResult<LogMessages>;
+
+/// Set by the module of the application, see add_log_reader.
+static READ_MESSAGES: OnceLock<ReadMessagesFn> = OnceLock::new();
+
+fn open_log(path: &Path) -> CuResult<UnifiedLoggerRead> {
+    let UnifiedLogger::Read(dl) = UnifiedLoggerBuilder::new()
+        .file_base_name(path)
+        .build()
+        .map_err(|e| CuError::new_with_cause("Could not open the log", e))?
+    else {
+        return Err("Could not open the log for reading.".into());
+    };
+    Ok(dl)
+}
+
+fn read_messages<P: CopperListTuple + CuListDumper>(path: &Path) -> CuResult<LogMessages> {
+    let header = read_wire_header(open_log(path)?)?;
+    if let Some(header) = &header {
+        header.check(P::SCHEMA)?;
+    }
+    let robot_id = header.and_then(|header| header.robot_id);
+    let reader = UnifiedLoggerIOReader::new(open_log(path)?, UnifiedLogType::CopperList);
+    Ok((
+        schema_channels(P::SCHEMA),
+        messages_dump::<P>(reader, robot_id).collect(),
+    ))
+}
+
+/// Adds the log reader to the python module of an application, P being the type of its copper lists (CuMsgs).
+pub fn add_log_reader<P: CopperListTuple + CuListDumper>(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    // One module per application: a second call for the same P changes nothing.
+    let _ = READ_MESSAGES.set(read_messages::<P>);
+    add_struct_log_reader(m)?;
+    m.add_class::<PyCopperLog>()
+}
+
+/// A log opened with the copper lists of the application, loaded in memory.
+#[pyclass(name = "CopperLog")]
+pub struct PyCopperLog {
+    channels: Vec<(String, String)>,
+    msgs: Vec<CuLogMsg>,
+}
+
+#[pymethods]
+impl PyCopperLog {
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        let read_messages = READ_MESSAGES.get().ok_or_else(|| {
+            PyRuntimeError::new_err(
+                "This module cannot decode copper lists, open the log with the module of the application.",
+            )
+        })?;
+        let (channels, msgs) =
+            read_messages(Path::new(path)).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        Ok(PyCopperLog { channels, msgs })
+    }
+
+    /// The tasks producing messages and the types of their messages.
+    fn channels(&self) -> Vec<(String, String)> {
+        self.channels.clone()
+    }
+
+    /// The start times of the first and of the last copper lists, in ns of the robot clock.
+    fn time_range(&self) -> Option<(u64, u64)> {
+        let mut times = self.msgs.iter().filter_map(|msg| msg.time);
+        let first = times.next()?;
+        let last = times.next_back().unwrap_or(first);
+        Some((first.0, last.0))
+    }
+
+    /// The robot which wrote the log, None outside of a fleet.
+    fn robot_id(&self) -> Option<String> {
+        self.msgs.first().and_then(|msg| msg.robot_id.clone())
+    }
+
+    /// The messages as dicts, of one channel (see channels, or namespaced by the robot ie. "r2/camera") or of all
+    /// of them, from the copper lists started between start (included) and end (excluded) in ns of the robot clock.
+    #[pyo3(signature = (channel=None, start=None, end=None))]
+    fn messages(
+        &self,
+        py: Python<'_>,
+        channel: Option<&str>,
+        start: Option<u64>,
+        end: Option<u64>,
+    ) -> Vec<PyObject> {
+        self.msgs
+            .iter()
+            .filter(|msg| {
+                channel.is_none_or(|channel| msg.task == channel || msg.channel() == channel)
+            })
+            .filter(|msg| {
+                let time = msg.time.map(|time| time.0);
+                start.is_none_or(|start| time.is_some_and(|time| time >= start))
+                    && end.is_none_or(|end| time.is_some_and(|time| time < end))
+            })
+            .map(|msg| {
+                let dict = PyDict::new_bound(py);
+                dict.set_item("culist", msg.culist).unwrap();
+                dict.set_item("time", msg.time.map(|time| time.0)).unwrap();
+                dict.set_item("task", &msg.task).unwrap();
+                dict.set_item("robot", &msg.robot_id).unwrap();
+                dict.set_item("type", &msg.msg_type).unwrap();
+                dict.set_item("tov", msg.tov.map(|tov| tov.0)).unwrap();
+                dict.set_item("payload", msg.payload.as_ref().map(value_to_py))
+                    .unwrap();
+                dict.to_object(py)
+            })
+            .collect()
+    }
+}
+
+fn add_struct_log_reader(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCuLogEntry>()?;
+    m.add_class::<PyLogIterator>()?;
+    m.add_function(wrap_pyfunction!(struct_log_iterator_bare, m)?)?;
+    m.add_function(wrap_pyfunction!(struct_log_iterator_unified, m)?)?;
+    Ok(())
+}
+
+#[pymodule]
+fn cu29_export(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    add_struct_log_reader(m)
+}
+
+fn value_to_py(value: &Value) -> PyObject {
+    match value {
+        Value::String(s) => Python::with_gil(|py| s.to_object(py)),
+        Value::U64(u) => Python::with_gil(|py| u.to_object(py)),
+        Value::I64(i) => Python::with_gil(|py| i.to_object(py)),
+        Value::F64(f) => Python::with_gil(|py| f.to_object(py)),
+        Value::Bool(b) => Python::with_gil(|py| b.to_object(py)),
+        Value::CuTime(t) => Python::with_gil(|py| t.0.to_object(py)),
+        Value::Bytes(b) => Python::with_gil(|py| b.to_object(py)),
+        Value::Char(c) => Python::with_gil(|py| c.to_object(py)),
+        Value::I8(i) => Python::with_gil(|py| i.to_object(py)),
+        Value::U8(u) => Python::with_gil(|py| u.to_object(py)),
+        Value::I16(i) => Python::with_gil(|py| i.to_object(py)),
+        Value::U16(u) => Python::with_gil(|py| u.to_object(py)),
+        Value::I32(i) => Python::with_gil(|py| i.to_object(py)),
+        Value::U32(u) => Python::with_gil(|py| u.to_object(py)),
+        Value::Map(m) => Python::with_gil(|py| {
+            let dict = PyDict::new_bound(py);
+            for (k, v) in m.iter() {
+                dict.set_item(value_to_py(k), value_to_py(v)).unwrap();
+            }
+            dict.to_object(py)
+        }),
+        Value::F32(f) => Python::with_gil(|py| f.to_object(py)),
+        Value::Option(o) => Python::with_gil(|py| {
+            if o.is_none() {
+                py.None()
+            } else {
+                o.clone().map(|v| value_to_py(&v)).unwrap()
+            }
+        }),
+        Value::Unit => Python::with_gil(|py| py.None()),
+        Value::Newtype(v) => value_to_py(v),
+        Value::Seq(s) => Python::with_gil(|py| {
+            let list = PyList::new_bound(py, s.iter().map(value_to_py));
+            list.to_object(py)
+        }),
+    }
+}
@@ -0,0 +1,328 @@
+use crate::copperlists_dump;
+use cu29::clock::CuTime;
+use cu29::copperlist::{CopperList, CuListDumper};
+use cu29::timefactor::{CuTimeFactor, CuTimePacer};
+use cu29::tuning::CuParamChange;
+use cu29_traits::{CopperListTuple, CuResult};
+use std::io::Read;
+
+pub const MIN_REPLAY_SPEED: f64 = 0.1;
+pub const MAX_REPLAY_SPEED: f64 = 10.0;
+
+/// The copper lists of a log loaded for an interactive replay: seek to a time, play them at a given speed,
+/// changed or paused while they play through time_factor, or step through them one at a time.
+/// Each copper list can be given to the `replay_one_iteration` of the application to re-execute it, after the
+/// changes of parameters recorded up to its time are given to `apply_param_change`.
+pub struct CuReplay<P: CopperListTuple> {
+    lists: Vec<CopperList<P>>,
+    /// The start time of every copper list, the one of the previous list if no task ran.
+    times: Vec<CuTime>,
+    position: usize,
+    pacer: CuTimePacer,
+    /// The changes of parameters recorded in the log, in time order.
+    param_changes: Vec<CuParamChange>,
+    /// How many of them were given to the application.
+    applied: usize,
+}
+
+impl<P: CopperListTuple + CuListDumper> CuReplay<P> {
+    pub fn new(lists: Vec<CopperList<P>>) -> Self {
+        let mut last = CuTime::default();
+        let times = lists
+            .iter()
+            .map(|list| {
+                last = list.start_time().unwrap_or(last);
+                last
+            })
+            .collect();
+        CuReplay {
+            lists,
+            times,
+            position: 0,
+            pacer: CuTimePacer::new(CuTimeFactor::default()),
+            param_changes: Vec::new(),
+            applied: 0,
+        }
+    }
+
+    /// Replays the changes of parameters recorded in the log along with the copper lists, see param_changes_dump.
+    pub fn with_param_changes(mut self, mut changes: Vec<CuParamChange>) -> Self {
+        changes.sort_by_key(|change| change.time);
+        self.param_changes = changes;
+        self.applied = 0;
+        self
+    }
+
+    /// Loads all the copper lists of a log, see copperlists_dump.
+    pub fn from_reader(src: impl Read) -> Self {
+        Self::new(copperlists_dump::<P>(src).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+
+    /// The index of the next copper list to replay.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The time of the next copper list to replay, None at the end of the log.
+    pub fn time(&self) -> Option<CuTime> {
+        self.times.get(self.position).copied()
+    }
+
+    /// The times of the first and last copper lists.
+    pub fn time_range(&self) -> Option<(CuTime, CuTime)> {
+        Some((*self.times.first()?, *self.times.last()?))
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.pacer.factor().get()
+    }
+
+    /// Sets the speed of play, from 0.1 (10 times slower than recorded) to 10 (10 times faster).
+    pub fn set_speed(&mut self, speed: f64) -> CuResult<()> {
+        if !(MIN_REPLAY_SPEED..=MAX_REPLAY_SPEED).contains(&speed) {
+            return Err(format!(
+                "Invalid replay speed {}, it must be between {} and {}.",
+                speed, MIN_REPLAY_SPEED, MAX_REPLAY_SPEED
+            )
+            .into());
+        }
+        self.pacer.factor().set(speed)
+    }
+
+    /// The speed of play, to change or pause it from another thread while the copper lists play, see
+    /// cu29::timefactor.
+    pub fn time_factor(&self) -> CuTimeFactor {
+        self.pacer.factor().clone()
+    }
+
+    /// Moves to the first copper list started at or after time and returns its position.
+    /// Seeking after the end of the log moves to the end.
+    pub fn seek(&mut self, time: CuTime) -> usize {
+        self.position = self.times.partition_point(|start| *start < time);
+        self.position
+    }
+
+    /// Moves back to the first copper list.
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    /// The changes of parameters to apply before the next copper list: the ones recorded up to its time and not
+    /// applied yet. After seeking backward, the changes made after its time reverted, the latest first.
+    pub fn due_param_changes(&mut self) -> Vec<CuParamChange> {
+        let due = match self.time() {
+            Some(time) => self
+                .param_changes
+                .partition_point(|change| change.time <= time),
+            None => self.param_changes.len(),
+        };
+        let changes = if due >= self.applied {
+            self.param_changes[self.applied..due].to_vec()
+        } else {
+            self.param_changes[due..self.applied]
+                .iter()
+                .rev()
+                .map(CuParamChange::reverted)
+                .collect()
+        };
+        self.applied = due;
+        changes
+    }
+
+    /// The next copper list, None at the end of the log.
+    pub fn step(&mut self) -> Option<&mut CopperList<P>> {
+        let list = self.lists.get_mut(self.position)?;
+        self.position += 1;
+        Some(list)
+    }
+
+    /// Replays the copper lists from the position to the end of the log, waiting between two of them
+    /// as long as they were apart in the log divided by the speed, as it is when they play. Every copper list is
+    /// given with the changes of parameters due before it, see due_param_changes.
+    /// It stops early when replay returns false, and returns the number of copper lists replayed.
+    pub fn play<F>(&mut self, mut replay: F) -> CuResult<usize>
+    where
+        F: FnMut(&[CuParamChange], &mut CopperList<P>) -> CuResult<bool>,
+    {
+        self.pacer.reset();
+        let mut played = 0;
+        while let Some(time) = self.time() {
+            self.pacer.wait_until(time);
+            played += 1;
+            let changes = self.due_param_changes();
+            let list = self.step().expect("a copper list at this position");
+            if !replay(&changes, list)? {
+                break;
+            }
+        }
+        Ok(played)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::{Decode, Encode};
+    use cu29::copperlist::CuMsgDump;
+    use cu29::cutask::CuMsg;
+    use cu29::dynmsg::DynCuMsg;
+    use cu29::summary::CuChannelStats;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[derive(Debug, Encode, Decode)]
+    struct Payload(CuMsg<u32>);
+
+    impl CuListDumper for Payload {
+        const SCHEMA: &'static str = "src:u32";
+
+        fn dump_msgs(&self) -> Vec<CuMsgDump> {
+            vec![CuMsgDump::from_msg("src", "u32", &self.0)]
+        }
+
+        fn start_time(&self) -> Option<CuTime> {
+            self.0.metadata.before_process.into()
+        }
+
+        fn record_stats(&self, channels: &mut [CuChannelStats]) {
+            channels[0].record(&self.0);
+        }
+
+        fn dyn_msgs(&self) -> Vec<(&'static str, DynCuMsg)> {
+            Vec::new()
+        }
+
+        fn encode_payload(&self, _index: usize) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    fn replay_every_ms(count: u32, period_ms: u64) -> CuReplay<Payload> {
+        let lists = (0..count)
+            .map(|i| {
+                let mut msg = CuMsg::new(Some(i));
+                msg.metadata.before_process =
+                    CuTime::from(Duration::from_millis(i as u64 * period_ms)).into();
+                CopperList::new(i, Payload(msg))
+            })
+            .collect();
+        CuReplay::new(lists)
+    }
+
+    #[test]
+    fn test_seek_and_step() {
+        let mut replay = replay_every_ms(5, 100);
+        assert_eq!(replay.len(), 5);
+        assert_eq!(
+            replay.time_range(),
+            Some((CuTime::from(0), CuTime::from(Duration::from_millis(400))))
+        );
+        assert_eq!(replay.seek(CuTime::from(Duration::from_millis(150))), 2);
+        assert_eq!(replay.step().unwrap().id, 2);
+        assert_eq!(replay.step().unwrap().id, 3);
+        assert_eq!(replay.seek(CuTime::from(Duration::from_millis(300))), 3);
+        assert_eq!(replay.seek(CuTime::from(Duration::from_secs(1))), 5);
+        assert!(replay.step().is_none());
+        assert!(replay.time().is_none());
+        replay.rewind();
+        assert_eq!(replay.time(), Some(CuTime::from(0)));
+    }
+
+    #[test]
+    fn test_play() {
+        let mut replay = replay_every_ms(4, 100);
+        assert!(replay.set_speed(20.0).is_err());
+        assert!(replay.set_speed(0.01).is_err());
+        replay.set_speed(10.0).unwrap();
+        replay.seek(CuTime::from(Duration::from_millis(100)));
+
+        let started = Instant::now();
+        let mut ids = Vec::new();
+        let played = replay
+            .play(|_, list| {
+                ids.push(list.id);
+                Ok(true)
+            })
+            .unwrap();
+        // 200ms of log played 10 times faster.
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(played, 3);
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        replay.rewind();
+        let played = replay.play(|_, list| Ok(list.id < 1)).unwrap();
+        assert_eq!(played, 2);
+        assert_eq!(replay.position(), 2);
+    }
+
+    #[test]
+    fn test_pause_while_playing() {
+        let mut replay = replay_every_ms(3, 100);
+        replay.set_speed(10.0).unwrap();
+        let factor = replay.time_factor();
+        let started = Instant::now();
+        let mut resumer = None;
+        replay
+            .play(|_, list| {
+                if list.id == 0 {
+                    // Paused for 50ms then played 4 times faster.
+                    factor.pause();
+                    let factor = factor.clone();
+                    resumer = Some(thread::spawn(move || {
+                        thread::sleep(Duration::from_millis(50));
+                        factor.set(4.0).unwrap();
+                    }));
+                }
+                Ok(true)
+            })
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(replay.speed(), 4.0);
+        resumer.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_param_changes() {
+        let change = |ms: u64, new: &str| CuParamChange {
+            time: CuTime::from(Duration::from_millis(ms)),
+            task: "pid".to_string(),
+            key: "kp".to_string(),
+            old: None,
+            new: Some(new.to_string()),
+            origin: "alice".to_string(),
+        };
+        let mut replay =
+            replay_every_ms(4, 100).with_param_changes(vec![change(250, "3.0"), change(50, "2.0")]);
+        let mut applied = Vec::new();
+        replay
+            .play(|changes, list| {
+                applied.push((list.id, changes.to_vec()));
+                Ok(true)
+            })
+            .unwrap();
+        assert!(applied[0].1.is_empty());
+        assert_eq!(applied[1].1, vec![change(50, "2.0")]);
+        assert!(applied[2].1.is_empty());
+        assert_eq!(applied[3].1, vec![change(250, "3.0")]);
+
+        replay.seek(CuTime::from(Duration::from_millis(100)));
+        assert_eq!(
+            replay.due_param_changes(),
+            vec![change(250, "3.0").reverted()]
+        );
+        replay.rewind();
+        assert_eq!(
+            replay.due_param_changes(),
+            vec![change(50, "2.0").reverted()]
+        );
+        assert!(replay.due_param_changes().is_empty());
+    }
+}
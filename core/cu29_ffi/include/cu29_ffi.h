@@ -0,0 +1,54 @@
+/* The C API of a copper application exported with cu29_ffi::export_application!, see src/lib.rs. */
+#ifndef CU29_FFI_H
+#define CU29_FFI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CuApp CuApp;
+
+typedef enum CuFfiHealth {
+    CU_HEALTH_UNKNOWN = 0,
+    CU_HEALTH_NOMINAL = 1,
+    CU_HEALTH_DEGRADED = 2,
+    CU_HEALTH_FAILED = 3,
+} CuFfiHealth;
+
+/* Called after a step for every task whose health changed. */
+typedef void (*CuHealthCallback)(const char *task, CuFfiHealth health, void *user_data);
+
+/* Creates the application logging in log_path, NULL on error. */
+CuApp *cu_app_new(const char *log_path);
+
+/* Frees the application, its tasks are stopped with cu_app_stop first. */
+void cu_app_free(CuApp *app);
+
+int cu_app_start(CuApp *app);
+
+/* Runs one iteration of the graph after advancing the clock of the application by dt_ns. */
+int cu_app_step(CuApp *app, uint64_t dt_ns);
+
+int cu_app_stop(CuApp *app);
+
+/* Queues a message in the wire format for the CuFfiSource of the channel, msg_type is its registered name. */
+int cu_app_push(CuApp *app, const char *channel, const char *msg_type, const uint8_t *bytes, size_t len);
+
+/* Copies the last message the CuFfiSink of the channel received. Returns its size, 0 if there is no new message,
+ * -1 on error or if the buffer is too small. */
+int64_t cu_app_pull(CuApp *app, const char *channel, const char *msg_type, uint8_t *buffer, size_t capacity);
+
+/* Calls callback after the steps with the tasks whose health changed, NULL to remove it. */
+int cu_app_set_health_callback(CuApp *app, CuHealthCallback callback, void *user_data);
+
+/* The message of the last error of this thread, valid until the next call. */
+const char *cu_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CU29_FFI_H */
@@ -0,0 +1,244 @@
+//! The channels between the C program and the graph: a CuFfiSource sends in the graph the messages pushed on its
+//! channel, a CuFfiSink keeps the last message it received on its channel for the C program to pull.
+
+use bincode::{decode_from_slice, encode_to_vec};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuMsgPayload, CuSinkTask, CuSrcTask, CuTaskLifecycle, Freezable};
+use cu29::registry::msg_type_of;
+use cu29::wire::wire_config;
+use cu29::{input_msg, output_msg};
+use cu29_traits::lockaudit::{self, AuditedGuard, LockSite};
+use cu29_traits::{CuError, CuResult};
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// The C program pushes faster than the graph consumes, the oldest messages are dropped past this.
+const MAX_PENDING: usize = 64;
+
+#[derive(Debug)]
+struct Channel {
+    /// The name the payload type is registered with in cu29::registry.
+    msg_type: String,
+    /// The messages pushed by the C program, oldest first.
+    pending: VecDeque<Vec<u8>>,
+    /// The last message received by a sink, until it is pulled.
+    last: Option<Vec<u8>>,
+}
+
+static CHANNELS_LOCK: LockSite = LockSite::new("ffi_channels");
+
+fn channels() -> AuditedGuard<MutexGuard<'static, HashMap<String, Channel>>> {
+    static CHANNELS: OnceLock<Mutex<HashMap<String, Channel>>> = OnceLock::new();
+    let channels = CHANNELS.get_or_init(|| Mutex::new(HashMap::new()));
+    lockaudit::lock(&CHANNELS_LOCK, channels).unwrap()
+}
+
+fn with_channel<R>(
+    name: &str,
+    msg_type: &str,
+    f: impl FnOnce(&mut Channel) -> CuResult<R>,
+) -> CuResult<R> {
+    let mut channels = channels();
+    let channel = channels
+        .get_mut(name)
+        .ok_or_else(|| CuError::from(format!("There is no channel '{}'.", name)))?;
+    if channel.msg_type != msg_type {
+        return Err(format!(
+            "The channel '{}' carries {} messages, not {}.",
+            name, channel.msg_type, msg_type
+        )
+        .into());
+    }
+    f(channel)
+}
+
+/// Declares the channel of a task, the name of its payload type comes from the registry.
+fn declare<T: 'static>(config: Option<&ComponentConfig>) -> CuResult<String> {
+    let name = config
+        .and_then(|config| config.get::<String>("channel"))
+        .ok_or(
+            "A C channel needs the name of its channel in its config, ie. \"channel\": \"cmd\".",
+        )?;
+    let msg_type = msg_type_of::<T>().ok_or_else(|| {
+        CuError::from(format!(
+            "The payload type {} of the channel '{}' is not registered, see cu29::registry.",
+            std::any::type_name::<T>(),
+            name
+        ))
+    })?;
+    let mut channels = channels();
+    let channel = channels.entry(name.clone()).or_insert_with(|| Channel {
+        msg_type: msg_type.name().to_string(),
+        pending: VecDeque::new(),
+        last: None,
+    });
+    if channel.msg_type != msg_type.name() {
+        return Err(format!(
+            "The channel '{}' is declared with {} and {} messages.",
+            name,
+            channel.msg_type,
+            msg_type.name()
+        )
+        .into());
+    }
+    Ok(name)
+}
+
+/// Queues a message in the wire format for the source of the channel.
+pub fn push(name: &str, msg_type: &str, bytes: &[u8]) -> CuResult<()> {
+    with_channel(name, msg_type, |channel| {
+        if channel.pending.len() == MAX_PENDING {
+            channel.pending.pop_front();
+        }
+        channel.pending.push_back(bytes.to_vec());
+        Ok(())
+    })
+}
+
+/// Takes the last message received by the sink of the channel, None if there is no new one.
+/// The message stays in the channel if it is larger than max_len.
+pub fn pull(name: &str, msg_type: &str, max_len: usize) -> CuResult<Option<Vec<u8>>> {
+    with_channel(name, msg_type, |channel| match &channel.last {
+        Some(bytes) if bytes.len() > max_len => Err(format!(
+            "The message of the channel '{}' is {} bytes, the buffer only {}.",
+            name,
+            bytes.len(),
+            max_len
+        )
+        .into()),
+        _ => Ok(channel.last.take()),
+    })
+}
+
+/// Sends in the graph the messages the C program pushes on its channel, one per iteration.
+///
+/// config: {"channel": "cmd"}, the payload type has to be registered in cu29::registry.
+pub struct CuFfiSource<T> {
+    channel: String,
+    _payload: PhantomData<T>,
+}
+
+impl<T: 'static> CuTaskLifecycle for CuFfiSource<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuFfiSource {
+            channel: declare::<T>(config)?,
+            _payload: PhantomData,
+        })
+    }
+}
+
+impl<T> Freezable for CuFfiSource<T> {}
+
+impl<'cl, T: CuMsgPayload + 'static> CuSrcTask<'cl> for CuFfiSource<T> {
+    type Output = output_msg!('cl, T);
+
+    fn process(&mut self, _ctx: &CuContext, output: Self::Output) -> CuResult<()> {
+        let bytes = channels()
+            .get_mut(&self.channel)
+            .and_then(|channel| channel.pending.pop_front());
+        match bytes {
+            Some(bytes) => {
+                let (payload, _) =
+                    decode_from_slice::<T, _>(&bytes, wire_config()).map_err(|e| {
+                        CuError::new_with_cause(
+                            &format!("Invalid message pushed on the channel '{}'", self.channel),
+                            e,
+                        )
+                    })?;
+                output.set_payload(payload);
+            }
+            None => output.clear_payload(),
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the last message it receives for the C program to pull from its channel.
+///
+/// config: {"channel": "pose"}, the payload type has to be registered in cu29::registry.
+pub struct CuFfiSink<T> {
+    channel: String,
+    _payload: PhantomData<T>,
+}
+
+impl<T: 'static> CuTaskLifecycle for CuFfiSink<T> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuFfiSink {
+            channel: declare::<T>(config)?,
+            _payload: PhantomData,
+        })
+    }
+}
+
+impl<T> Freezable for CuFfiSink<T> {}
+
+impl<'cl, T: CuMsgPayload + 'static> CuSinkTask<'cl> for CuFfiSink<T> {
+    type Input = input_msg!('cl, T);
+
+    fn process(&mut self, _ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+        let Some(payload) = input.payload() else {
+            return Ok(());
+        };
+        let bytes = encode_to_vec(payload, wire_config())
+            .map_err(|e| CuError::new_with_cause("Could not encode the message", e))?;
+        if let Some(channel) = channels().get_mut(&self.channel) {
+            channel.last = Some(bytes);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::RobotClock;
+    use cu29::registry::register_msg_type;
+
+    fn config(channel: &str) -> ComponentConfig {
+        let mut config = ComponentConfig::new();
+        config.set("channel", channel.to_string());
+        config
+    }
+
+    #[test]
+    fn test_channels() {
+        register_msg_type::<u16>("u16").unwrap();
+        let clock = RobotClock::default();
+        let ctx = CuContext::new(&clock, None);
+
+        let mut source = CuFfiSource::<u16>::new(Some(&config("cmd"))).unwrap();
+        let mut output = CuMsg::new(None);
+        let bytes = encode_to_vec(7u16, wire_config()).unwrap();
+        assert!(push("cmd", "u32", &bytes).is_err());
+        assert!(push("speed", "u16", &bytes).is_err());
+        push("cmd", "u16", &bytes).unwrap();
+        source.process(&ctx, &mut output).unwrap();
+        assert_eq!(output.payload(), Some(&7));
+        source.process(&ctx, &mut output).unwrap();
+        assert_eq!(output.payload(), None);
+
+        let mut sink = CuFfiSink::<u16>::new(Some(&config("echo"))).unwrap();
+        assert_eq!(pull("echo", "u16", 16).unwrap(), None);
+        sink.process(&ctx, &CuMsg::new(Some(300))).unwrap();
+        assert!(pull("echo", "u16", 1).is_err());
+        let bytes = pull("echo", "u16", 16).unwrap().unwrap();
+        assert_eq!(
+            decode_from_slice::<u16, _>(&bytes, wire_config())
+                .unwrap()
+                .0,
+            300
+        );
+        assert_eq!(pull("echo", "u16", 16).unwrap(), None);
+
+        assert!(CuFfiSink::<u16>::new(None).is_err());
+        assert!(CuFfiSink::<i8>::new(Some(&config("other"))).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+//! The C API of a copper application, to embed it in an existing C or C++ program and adopt copper one part of the
+//! stack at a time. The header is include/cu29_ffi.h.
+//!
+//! The application is generated in library mode and exported from a cdylib or staticlib crate:
+//!
+//! ```ignore
+//! #[copper_runtime(config = "copperconfig.ron", library = true)]
+//! struct MyApplication {}
+//!
+//! cu29_ffi::export_application!(MyApplication);
+//! ```
+//!
+//! The C program creates it with `cu_app_new`, steps it with the time elapsed on its side and exchanges messages with
+//! the graph as bytes in the wire format: a `CuFfiSource<T>` task sends in the graph what is pushed on its channel, a
+//! `CuFfiSink<T>` task keeps the last message it receives for the C program to pull. Each message is given with the
+//! name its payload type is registered with in cu29::registry, a message of another type is refused.
+//!
+//! Every function returns 0 or a size on success and -1 on error, `cu_last_error` tells what went wrong.
+
+mod channels;
+
+pub use channels::{CuFfiSink, CuFfiSource};
+
+use cu29::clock::{CuDuration, RobotClock, RobotClockMock};
+use cu29::monitoring::CuHealth;
+use cu29_helpers::{basic_copper_setup, CopperContext};
+use cu29_traits::{CuError, CuResult};
+use cu29_unifiedlog::UnifiedLoggerWrite;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// What the C API needs from an application generated in library mode, implemented by export_application!.
+pub trait CuFfiApplication {
+    fn start_all_tasks(&mut self) -> CuResult<()>;
+
+    fn step(&mut self, dt: CuDuration) -> CuResult<()>;
+
+    fn stop_all_tasks(&mut self) -> CuResult<()>;
+
+    /// The health of every task with its id.
+    fn tasks_health(&self) -> Vec<(String, CuHealth)>;
+}
+
+/// The health of a task given to the health callback.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CuFfiHealth {
+    Unknown = 0,
+    Nominal = 1,
+    Degraded = 2,
+    Failed = 3,
+}
+
+impl From<CuHealth> for CuFfiHealth {
+    fn from(health: CuHealth) -> Self {
+        match health {
+            CuHealth::Unknown => CuFfiHealth::Unknown,
+            CuHealth::Nominal => CuFfiHealth::Nominal,
+            CuHealth::Degraded => CuFfiHealth::Degraded,
+            CuHealth::Failed => CuFfiHealth::Failed,
+        }
+    }
+}
+
+/// Called after a step with the id of every task whose health changed, and the user data it was registered with.
+pub type CuHealthCallback =
+    extern "C" fn(task: *const c_char, health: CuFfiHealth, user_data: *mut c_void);
+
+/// An application created by cu_app_new, opaque for the C program.
+pub struct CuApp {
+    app: Box<dyn CuFfiApplication>,
+    health: Vec<(String, CuHealth)>,
+    callback: Option<(CuHealthCallback, *mut c_void)>,
+    // Dropped after the application, which logs until it is dropped.
+    _context: Option<CopperContext>,
+}
+
+impl CuApp {
+    pub fn new(app: Box<dyn CuFfiApplication>, context: Option<CopperContext>) -> Self {
+        CuApp {
+            health: app.tasks_health(),
+            app,
+            callback: None,
+            _context: context,
+        }
+    }
+
+    /// Steps the application and calls the health callback for the tasks whose health changed.
+    fn step(&mut self, dt: CuDuration) -> CuResult<()> {
+        let result = self.app.step(dt);
+        let health = self.app.tasks_health();
+        if let Some((callback, user_data)) = self.callback {
+            for (task, health) in &health {
+                let previous = self.health.iter().find(|(id, _)| id == task);
+                if previous.is_some_and(|(_, previous)| previous == health) {
+                    continue;
+                }
+                let task = CString::new(task.as_str()).unwrap_or_default();
+                callback(task.as_ptr(), (*health).into(), user_data);
+            }
+        }
+        self.health = health;
+        result
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(error: &CuError) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs a call of the C API: its error is kept for cu_last_error and a panic does not unwind in the C program.
+fn ffi_call<R>(f: impl FnOnce() -> CuResult<R>) -> Option<R> {
+    let result = catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err("The copper application panicked.".into()));
+    result.map_err(|e| set_last_error(&e)).ok()
+}
+
+fn status(result: Option<()>) -> c_int {
+    if result.is_some() {
+        0
+    } else {
+        -1
+    }
+}
+
+unsafe fn app_mut<'a>(app: *mut CuApp) -> CuResult<&'a mut CuApp> {
+    app.as_mut()
+        .ok_or_else(|| "The application is null.".into())
+}
+
+unsafe fn str_arg<'a>(arg: *const c_char, name: &str) -> CuResult<&'a str> {
+    if arg.is_null() {
+        return Err(format!("The {} is null.", name).into());
+    }
+    CStr::from_ptr(arg)
+        .to_str()
+        .map_err(|_| format!("The {} is not valid UTF-8.", name).into())
+}
+
+/// The constructor of the application behind cu_app_new, see export_application!.
+pub type CuAppConstructor = fn(
+    RobotClock,
+    RobotClockMock,
+    Arc<Mutex<UnifiedLoggerWrite>>,
+) -> CuResult<Box<dyn CuFfiApplication>>;
+
+/// Creates the application logging in log_path, null on error.
+///
+/// # Safety
+/// log_path is a valid C string.
+pub unsafe fn cu_app_new_with(log_path: *const c_char, new: CuAppConstructor) -> *mut CuApp {
+    ffi_call(|| {
+        let log_path = PathBuf::from(str_arg(log_path, "log path")?);
+        let context = basic_copper_setup(&log_path, None, false)?;
+        // The time of copper is the time of the C program, advanced on every step.
+        let (clock, clock_mock) = RobotClock::mock();
+        let app = new(clock, clock_mock, context.unified_logger.clone())?;
+        Ok(Box::into_raw(Box::new(CuApp::new(app, Some(context)))))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Frees the application, its tasks are stopped with cu_app_stop first.
+///
+/// # Safety
+/// app was created by cu_app_new and is not used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn cu_app_free(app: *mut CuApp) {
+    if !app.is_null() {
+        ffi_call(|| {
+            drop(Box::from_raw(app));
+            Ok(())
+        });
+    }
+}
+
+/// Starts all the tasks.
+///
+/// # Safety
+/// app was created by cu_app_new.
+#[no_mangle]
+pub unsafe extern "C" fn cu_app_start(app: *mut CuApp) -> c_int {
+    status(ffi_call(|| app_mut(app)?.app.start_all_tasks()))
+}
+
+/// Runs one iteration of the graph after advancing the clock of the application by dt_ns.
+///
+/// # Safety
+/// app was created by cu_app_new.
+#[no_mangle]
+pub unsafe extern "C" fn cu_app_step(app: *mut CuApp, dt_ns: u64) -> c_int {
+    status(ffi_call(|| app_mut(app)?.step(CuDuration(dt_ns))))
+}
+
+/// Stops all the tasks.
+///
+/// # Safety
+/// app was created by cu_app_new.
+#[no_mangle]
+pub unsafe extern "C" fn cu_app_stop(app: *mut CuApp) -> c_int {
+    status(ffi_call(|| app_mut(app)?.app.stop_all_tasks()))
+}
+
+/// Queues a message in the wire format for the CuFfiSource of the channel, msg_type is its registered name.
+///
+/// # Safety
+/// app was created by cu_app_new, channel and msg_type are valid C strings, bytes points to len bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cu_app_push(
+    app: *mut CuApp,
+    channel: *const c_char,
+    msg_type: *const c_char,
+    bytes: *const u8,
+    len: usize,
+) -> c_int {
+    status(ffi_call(|| {
+        app_mut(app)?;
+        if bytes.is_null() && len > 0 {
+            return Err("The message is null.".into());
+        }
+        let bytes = if len == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(bytes, len)
+        };
+        channels::push(
+            str_arg(channel, "channel")?,
+            str_arg(msg_type, "message type")?,
+            bytes,
+        )
+    }))
+}
+
+/// Copies in buffer the last message the CuFfiSink of the channel received, in the wire format.
+/// Returns its size, 0 if there is no new message since the last pull, -1 on error or if the buffer is too small.
+///
+/// # Safety
+/// app was created by cu_app_new, channel and msg_type are valid C strings, buffer points to capacity bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cu_app_pull(
+    app: *mut CuApp,
+    channel: *const c_char,
+    msg_type: *const c_char,
+    buffer: *mut u8,
+    capacity: usize,
+) -> i64 {
+    ffi_call(|| {
+        app_mut(app)?;
+        if buffer.is_null() {
+            return Err("The buffer is null.".into());
+        }
+        let message = channels::pull(
+            str_arg(channel, "channel")?,
+            str_arg(msg_type, "message type")?,
+            capacity,
+        )?;
+        let Some(message) = message else {
+            return Ok(0);
+        };
+        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, message.len());
+        Ok(message.len() as i64)
+    })
+    .unwrap_or(-1)
+}
+
+/// Calls callback after the steps with the tasks whose health changed, null to remove it.
+///
+/// # Safety
+/// app was created by cu_app_new, user_data is valid as long as the callback is set.
+#[no_mangle]
+pub unsafe extern "C" fn cu_app_set_health_callback(
+    app: *mut CuApp,
+    callback: Option<CuHealthCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    status(ffi_call(|| {
+        app_mut(app)?.callback = callback.map(|callback| (callback, user_data));
+        Ok(())
+    }))
+}
+
+/// The message of the last error of this thread, valid until the next call of the C API.
+#[no_mangle]
+pub extern "C" fn cu_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Exports an application generated with #[copper_runtime(config = "...", library = true)] as cu_app_new.
+#[macro_export]
+macro_rules! export_application {
+    ($app:ty) => {
+        impl $crate::CuFfiApplication for $app {
+            fn start_all_tasks(&mut self) -> cu29::CuResult<()> {
+                <$app>::start_all_tasks(self)
+            }
+
+            fn step(&mut self, dt: cu29::clock::CuDuration) -> cu29::CuResult<()> {
+                <$app>::step(self, dt)
+            }
+
+            fn stop_all_tasks(&mut self) -> cu29::CuResult<()> {
+                <$app>::stop_all_tasks(self)
+            }
+
+            fn tasks_health(&self) -> Vec<(String, cu29::monitoring::CuHealth)> {
+                self.introspect()
+                    .nodes
+                    .into_iter()
+                    .map(|node| (node.id, node.health))
+                    .collect()
+            }
+        }
+
+        /// Creates the application logging in log_path, null on error.
+        ///
+        /// # Safety
+        /// log_path is a valid C string.
+        #[no_mangle]
+        pub unsafe extern "C" fn cu_app_new(
+            log_path: *const std::ffi::c_char,
+        ) -> *mut $crate::CuApp {
+            $crate::cu_app_new_with(log_path, |clock, clock_mock, logger| {
+                Ok(Box::new(<$app>::new(clock, clock_mock, logger)?))
+            })
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A task failing on its second step.
+    struct FakeApp {
+        steps: u32,
+    }
+
+    impl CuFfiApplication for FakeApp {
+        fn start_all_tasks(&mut self) -> CuResult<()> {
+            Ok(())
+        }
+
+        fn step(&mut self, _dt: CuDuration) -> CuResult<()> {
+            self.steps += 1;
+            if self.steps == 2 {
+                return Err("Task failed.".into());
+            }
+            Ok(())
+        }
+
+        fn stop_all_tasks(&mut self) -> CuResult<()> {
+            Ok(())
+        }
+
+        fn tasks_health(&self) -> Vec<(String, CuHealth)> {
+            let health = match self.steps {
+                0 => CuHealth::Unknown,
+                2 => CuHealth::Failed,
+                _ => CuHealth::Nominal,
+            };
+            vec![
+                ("imu".to_string(), health),
+                ("gps".to_string(), CuHealth::Unknown),
+            ]
+        }
+    }
+
+    extern "C" fn record(task: *const c_char, health: CuFfiHealth, user_data: *mut c_void) {
+        let changes = unsafe { &mut *(user_data as *mut Vec<(String, CuFfiHealth)>) };
+        let task = unsafe { CStr::from_ptr(task) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        changes.push((task, health));
+    }
+
+    #[test]
+    fn test_steps_and_health_callback() {
+        let app = Box::into_raw(Box::new(CuApp::new(Box::new(FakeApp { steps: 0 }), None)));
+        let mut changes: Vec<(String, CuFfiHealth)> = Vec::new();
+        unsafe {
+            let user_data = &mut changes as *mut _ as *mut c_void;
+            assert_eq!(cu_app_set_health_callback(app, Some(record), user_data), 0);
+            assert_eq!(cu_app_start(app), 0);
+            assert_eq!(cu_app_step(app, 1_000_000), 0);
+            assert_eq!(cu_app_step(app, 1_000_000), -1);
+            let error = CStr::from_ptr(cu_last_error()).to_str().unwrap();
+            assert!(error.contains("Task failed."));
+            assert_eq!(cu_app_step(app, 1_000_000), 0);
+            assert_eq!(cu_app_stop(app), 0);
+
+            assert_eq!(cu_app_step(std::ptr::null_mut(), 0), -1);
+            let mut buffer = [0u8; 8];
+            let channel = c"unknown";
+            let pulled = cu_app_pull(
+                app,
+                channel.as_ptr(),
+                c"u8".as_ptr(),
+                buffer.as_mut_ptr(),
+                8,
+            );
+            assert_eq!(pulled, -1);
+            cu_app_free(app);
+        }
+        assert_eq!(
+            changes,
+            vec![
+                ("imu".to_string(), CuFfiHealth::Nominal),
+                ("imu".to_string(), CuFfiHealth::Failed),
+                ("imu".to_string(), CuFfiHealth::Nominal),
+            ]
+        );
+    }
+}
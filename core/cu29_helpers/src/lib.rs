@@ -1,7 +1,9 @@
 use cu29_clock::RobotClock;
 use cu29_log_runtime::LoggerRuntime;
 use cu29_traits::{CuResult, UnifiedLogType};
-use cu29_unifiedlog::{stream_write, UnifiedLogger, UnifiedLoggerBuilder, UnifiedLoggerWrite};
+use cu29_unifiedlog::{
+    stream_write, LogKey, UnifiedLogger, UnifiedLoggerBuilder, UnifiedLoggerWrite,
+};
 use simplelog::TermLogger;
 #[cfg(debug_assertions)]
 use simplelog::{ColorChoice, Config, LevelFilter, TerminalMode};
@@ -28,19 +30,31 @@ pub struct CopperContext {
 ///
 /// slab_size: The logger will pre-allocate large files of those sizes. With the name of the given file _0, _1 etc.
 pub fn basic_copper_setup(
+    unifiedlogger_output_base_name: &Path,
+    slab_size: Option<usize>,
+    text_log: bool,
+) -> CuResult<CopperContext> {
+    encrypted_copper_setup(unifiedlogger_output_base_name, slab_size, text_log, None)
+}
+
+/// Same as [basic_copper_setup], with the sections of the log encrypted with AES-256-GCM if a key is given.
+/// The same key is needed to read the log back.
+pub fn encrypted_copper_setup(
     unifiedlogger_output_base_name: &Path,
     slab_size: Option<usize>,
     _text_log: bool,
+    encryption_key: Option<&LogKey>,
 ) -> CuResult<CopperContext> {
     let preallocated_size = slab_size.unwrap_or(1024 * 1024 * 10);
-    let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+    let mut builder = UnifiedLoggerBuilder::new()
         .write(true)
         .create(true)
         .file_base_name(unifiedlogger_output_base_name)
-        .preallocated_size(preallocated_size)
-        .build()
-        .expect("Failed to create logger")
-    else {
+        .preallocated_size(preallocated_size);
+    if let Some(key) = encryption_key {
+        builder = builder.encryption_key(key);
+    }
+    let UnifiedLogger::Write(logger) = builder.build().expect("Failed to create logger") else {
         panic!("Failed to create logger")
     };
     let unified_logger = Arc::new(Mutex::new(logger));
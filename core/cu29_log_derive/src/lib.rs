@@ -32,6 +32,24 @@ use syn::{Expr, ExprAssign, ExprLit, Lit};
 /// In release mode, the log will be only be written to the unified logger.
 #[proc_macro]
 pub fn debug(input: TokenStream) -> TokenStream {
+    log_macro(input, false)
+}
+
+/// Same as debug!, for errors that can repeat at the rate of the loop, like a sensor failing.
+/// The first occurrence is logged, the following ones at the same call site are only counted and logged at most
+/// once per second as a summary: `[repeated {} times]` followed by the message.
+/// The counts are in cu29_log_runtime::error_sites and in the metrics of the runtime.
+/// # Example
+/// ```ignore
+/// use cu29_log_derive::error;
+/// error!("Could not read the IMU: {}", e.to_string());
+/// ```
+#[proc_macro]
+pub fn error(input: TokenStream) -> TokenStream {
+    log_macro(input, true)
+}
+
+fn log_macro(input: TokenStream, error: bool) -> TokenStream {
     let parser = syn::punctuated::Punctuated::<Expr, Token![,]>::parse_terminated;
     let exprs = parser.parse(input).expect("Failed to parse input");
 
@@ -83,9 +101,18 @@ pub fn debug(input: TokenStream) -> TokenStream {
         }
     });
 
+    let summary_msg = format!("[repeated {{}} times] {}", _msg);
+    let summary_index =
+        error.then(|| intern_string(&summary_msg).expect("Failed to insert log string."));
+
     #[cfg(not(debug_assertions))]
-    let log_stmt = quote! {
-        let r = cu29_log_runtime::log(&mut log_entry);
+    let log_stmt = match summary_index {
+        Some(summary_index) => quote! {
+            let r = cu29_log_runtime::log_error(&mut log_entry, #summary_index).map(|_| ());
+        },
+        None => quote! {
+            let r = cu29_log_runtime::log(&mut log_entry);
+        },
     };
 
     #[cfg(debug_assertions)]
@@ -98,8 +125,19 @@ pub fn debug(input: TokenStream) -> TokenStream {
                 quote!(#lit_str)
             })
             .collect();
-        quote! {
-            let r = cu29_log_runtime::log_debug_mode(&mut log_entry, #_msg, &[#(#keys),*]);
+        match summary_index {
+            Some(summary_index) => quote! {
+                let r = cu29_log_runtime::log_error_debug_mode(
+                    &mut log_entry,
+                    #summary_index,
+                    #_msg,
+                    #summary_msg,
+                    &[#(#keys),*],
+                );
+            },
+            None => quote! {
+                let r = cu29_log_runtime::log_debug_mode(&mut log_entry, #_msg, &[#(#keys),*]);
+            },
         }
     };
 
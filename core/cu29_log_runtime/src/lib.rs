@@ -1,11 +1,12 @@
-use bincode::config::Configuration;
 use bincode::enc::write::Writer;
 use bincode::enc::Encode;
 use bincode::enc::{Encoder, EncoderImpl};
 use bincode::error::EncodeError;
-use cu29_clock::RobotClock;
-use cu29_log::CuLogEntry;
-use cu29_traits::{CuResult, WriteStream};
+use cu29_clock::{CuDuration, CuTime, RobotClock};
+use cu29_log::value::Value;
+use cu29_log::{CuLogEntry, ANONYMOUS};
+use cu29_traits::lockaudit::{self, LockSite};
+use cu29_traits::{wire_config, CuResult, WireConfig, WriteStream};
 use log::Log;
 
 #[cfg(debug_assertions)]
@@ -15,11 +16,14 @@ use log::Record;
 #[cfg(debug_assertions)]
 use std::collections::HashMap;
 
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Formatter};
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::PathBuf;
-use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, TryLockError};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 struct DummyWriteStream;
@@ -32,6 +36,97 @@ impl WriteStream<CuLogEntry> for DummyWriteStream {
 }
 static WRITER: OnceLock<(Mutex<Box<dyn WriteStream<CuLogEntry>>>, RobotClock)> = OnceLock::new();
 
+/// The lock of the writer of the structured log, taken by every log line of every thread.
+pub static WRITER_LOCK: LockSite = LockSite::new("log_writer");
+
+/// How often an error repeating at the same call site is summarized in the log, see [log_error].
+pub const ERROR_SUMMARY_PERIOD: CuDuration = CuDuration(1_000_000_000);
+
+/// The lock of the errors logged by call site.
+pub static ERROR_SITES_LOCK: LockSite = LockSite::new("log_error_sites");
+
+/// The errors logged with error!, by interned message.
+static ERROR_SITES: Mutex<BTreeMap<u32, ErrorSite>> = Mutex::new(BTreeMap::new());
+static ERROR_COUNT: AtomicU64 = AtomicU64::new(0);
+static SUPPRESSED_ERROR_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// How many errors were logged with error!, at a call site or in total.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CuErrorCounts {
+    /// Every occurrence, written in the log or not.
+    pub count: u64,
+    /// The occurrences that did not write a line, only counted in the next summary.
+    pub suppressed: u64,
+}
+
+/// What to write in the log for an occurrence of an error.
+#[derive(Debug, PartialEq)]
+enum ErrorLine {
+    /// The error as it is, it did not happen for a while.
+    First,
+    /// A summary of the error, repeated that many times since its last line.
+    Repeated(u64),
+    Suppressed,
+}
+
+#[derive(Default)]
+struct ErrorSite {
+    counts: CuErrorCounts,
+    /// The occurrences suppressed since the last line.
+    pending: u64,
+    last_line: Option<CuTime>,
+}
+
+impl ErrorSite {
+    fn record(&mut self, now: CuTime) -> ErrorLine {
+        self.counts.count += 1;
+        match self.last_line {
+            Some(last_line) if now < last_line + ERROR_SUMMARY_PERIOD => {
+                self.pending += 1;
+                self.counts.suppressed += 1;
+                ErrorLine::Suppressed
+            }
+            Some(_) if self.pending > 0 => {
+                let repeated = self.pending + 1;
+                self.pending = 0;
+                self.last_line = Some(now);
+                ErrorLine::Repeated(repeated)
+            }
+            _ => {
+                self.last_line = Some(now);
+                ErrorLine::First
+            }
+        }
+    }
+}
+
+fn record_error(msg_index: u32, now: CuTime) -> ErrorLine {
+    let mut sites = lockaudit::lock(&ERROR_SITES_LOCK, &ERROR_SITES).unwrap();
+    let line = sites.entry(msg_index).or_default().record(now);
+    ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+    if line == ErrorLine::Suppressed {
+        SUPPRESSED_ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    line
+}
+
+/// The errors logged with error! by call site, ie. by the interned index of their message.
+pub fn error_sites() -> Vec<(u32, CuErrorCounts)> {
+    lockaudit::lock(&ERROR_SITES_LOCK, &ERROR_SITES)
+        .unwrap()
+        .iter()
+        .map(|(msg_index, site)| (*msg_index, site.counts))
+        .collect()
+}
+
+/// The errors logged with error! at every call site. Does not lock nor allocate.
+pub fn error_counts() -> CuErrorCounts {
+    CuErrorCounts {
+        count: ERROR_COUNT.load(Ordering::Relaxed),
+        suppressed: SUPPRESSED_ERROR_COUNT.load(Ordering::Relaxed),
+    }
+}
+
 #[cfg(debug_assertions)]
 static EXTRA_TEXT_LOGGER: OnceLock<Option<Box<dyn Log>>> = OnceLock::new();
 
@@ -61,7 +156,7 @@ impl LoggerRuntime {
         // If WRITER is already initialized, update the inner value.
         // This should only be useful for unit testing.
         if let Some((writer, _)) = WRITER.get() {
-            let mut writer_guard = writer.lock().unwrap();
+            let mut writer_guard = lockaudit::lock(&WRITER_LOCK, writer).unwrap();
             *writer_guard = Box::new(destination);
         } else {
             WRITER
@@ -76,26 +171,49 @@ impl LoggerRuntime {
     }
 
     pub fn flush(&self) {
-        if let Some((writer, _clock)) = WRITER.get() {
-            if let Ok(mut writer) = writer.lock() {
-                if let Err(err) = writer.flush() {
-                    eprintln!("cu29_log: Failed to flush writer: {}", err);
-                }
-            } else {
-                eprintln!("cu29_log: Failed to lock writer.");
+        flush_log();
+    }
+}
+
+/// Flushes the structured log so its lines can be read back even if the process dies right after.
+pub fn flush_log() {
+    if let Some((writer, _clock)) = WRITER.get() {
+        if let Ok(mut writer) = lockaudit::lock(&WRITER_LOCK, writer) {
+            if let Err(err) = writer.flush() {
+                eprintln!("cu29_log: Failed to flush writer: {}", err);
             }
         } else {
-            eprintln!("cu29_log: Logger not initialized.");
+            eprintln!("cu29_log: Failed to lock writer.");
+        }
+    } else {
+        eprintln!("cu29_log: Logger not initialized.");
+    }
+}
+
+/// Whether a log line can be written without waiting for the writer for longer than timeout.
+/// It is not the case when the logger is not initialized, or when the current thread panicked while holding it.
+pub fn writer_available(timeout: Duration) -> bool {
+    let Some((writer, _clock)) = WRITER.get() else {
+        return false;
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        match writer.try_lock() {
+            Ok(_) => return true,
+            Err(TryLockError::Poisoned(_)) => return false,
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(1))
+            }
+            Err(TryLockError::WouldBlock) => return false,
         }
     }
 }
 
 impl Drop for LoggerRuntime {
     fn drop(&mut self) {
-        self.flush();
         if let Some((mutex, _clock)) = WRITER.get() {
-            if let Ok(mut writer_guard) = mutex.lock() {
-                // Replace the current WriteStream with a DummyWriteStream
+            if let Ok(mut writer_guard) = lockaudit::lock(&WRITER_LOCK, mutex) {
+                // Replace the current WriteStream with a DummyWriteStream, dropping the stream closes it.
                 *writer_guard = Box::new(DummyWriteStream);
             }
         }
@@ -112,7 +230,7 @@ pub fn log(entry: &mut CuLogEntry) -> CuResult<()> {
     }
     let (writer, clock) = d.unwrap();
     entry.time = clock.now();
-    if let Err(err) = writer.lock().unwrap().log(entry) {
+    if let Err(err) = lockaudit::lock(&WRITER_LOCK, writer).unwrap().log(entry) {
         eprintln!("Failed to log data: {}", err);
     }
     // This is only for debug builds with standard textual logging implemented.
@@ -125,6 +243,28 @@ pub fn log(entry: &mut CuLogEntry) -> CuResult<()> {
     Ok(())
 }
 
+/// Function called from the code generated by error! to log data.
+/// The first occurrence of an error is logged as it is, the ones following within ERROR_SUMMARY_PERIOD are only
+/// counted. The next occurrence after that is logged with the message at summary_index instead, the same message
+/// prefixed with the number of times it repeated. Returns whether a line was written.
+#[inline(always)]
+pub fn log_error(entry: &mut CuLogEntry, summary_index: u32) -> CuResult<bool> {
+    let Some((_writer, clock)) = WRITER.get() else {
+        return Err("Logger not initialized.".into());
+    };
+    match record_error(entry.msg_index, clock.now()) {
+        ErrorLine::First => {}
+        ErrorLine::Repeated(repeated) => {
+            entry.msg_index = summary_index;
+            entry.paramname_indexes.insert(0, ANONYMOUS);
+            entry.params.insert(0, Value::U64(repeated));
+        }
+        ErrorLine::Suppressed => return Ok(false),
+    }
+    log(entry)?;
+    Ok(true)
+}
+
 /// This version of log is only compiled in debug mode
 /// This allows a normal logging framework to be bridged.
 #[cfg(debug_assertions)]
@@ -134,7 +274,31 @@ pub fn log_debug_mode(
     param_names: &[&str],
 ) -> CuResult<()> {
     log(entry)?;
+    log_text(entry, format_str, param_names)
+}
+
+/// The debug mode version of log_error, summary_format_str is the message at summary_index.
+#[cfg(debug_assertions)]
+pub fn log_error_debug_mode(
+    entry: &mut CuLogEntry,
+    summary_index: u32,
+    format_str: &str,
+    summary_format_str: &str,
+    param_names: &[&str],
+) -> CuResult<()> {
+    if !log_error(entry, summary_index)? {
+        return Ok(());
+    }
+    if entry.msg_index == summary_index {
+        log_text(entry, summary_format_str, param_names)
+    } else {
+        log_text(entry, format_str, param_names)
+    }
+}
 
+/// Bridges a log line to the text logger, if any.
+#[cfg(debug_assertions)]
+fn log_text(entry: &CuLogEntry, format_str: &str, param_names: &[&str]) -> CuResult<()> {
     let guarded_logger = EXTRA_TEXT_LOGGER.get();
     if guarded_logger.is_none() {
         return Ok(());
@@ -211,7 +375,7 @@ impl<W: Write> Writer for OwningIoWriter<W> {
 /// This allows this crate to be used outside of Copper (ie. decoupling it from the unifiedlog.
 pub struct SimpleFileWriter {
     path: PathBuf,
-    encoder: EncoderImpl<OwningIoWriter<File>, Configuration>,
+    encoder: EncoderImpl<OwningIoWriter<File>, WireConfig>,
 }
 
 impl SimpleFileWriter {
@@ -223,7 +387,7 @@ impl SimpleFileWriter {
             .map_err(|e| format!("Failed to open file: {:?}", e))?;
 
         let writer = OwningIoWriter::new(file);
-        let encoder = EncoderImpl::new(writer, bincode::config::standard());
+        let encoder = EncoderImpl::new(writer, wire_config());
 
         Ok(SimpleFileWriter {
             path: path.clone(),
@@ -257,8 +421,9 @@ impl WriteStream<CuLogEntry> for SimpleFileWriter {
 
 #[cfg(test)]
 mod tests {
-    use crate::CuLogEntry;
+    use crate::{CuLogEntry, ErrorLine, ErrorSite};
     use bincode::config::standard;
+    use cu29_clock::CuTime;
     use cu29_log::value::Value;
     use smallvec::smallvec;
 
@@ -276,4 +441,25 @@ mod tests {
             bincode::decode_from_slice(&encoded, standard()).unwrap();
         assert_eq!(log_entry, decoded_tuple.0);
     }
+
+    #[test]
+    fn test_error_site() {
+        const MS: u64 = 1_000_000;
+        let mut site = ErrorSite::default();
+        let lines: Vec<ErrorLine> = (0..25)
+            .map(|i| site.record(CuTime::from(i * 100 * MS)))
+            .collect();
+        assert_eq!(lines[0], ErrorLine::First);
+        assert!(lines[1..10]
+            .iter()
+            .all(|line| *line == ErrorLine::Suppressed));
+        assert_eq!(lines[10], ErrorLine::Repeated(10));
+        assert_eq!(lines[20], ErrorLine::Repeated(10));
+        assert_eq!(site.counts.count, 25);
+        assert_eq!(site.counts.suppressed, 22);
+
+        // The last ones are summarized by the next occurrence, then it is logged as it is after a quiet period.
+        assert_eq!(site.record(CuTime::from(3000 * MS)), ErrorLine::Repeated(5));
+        assert_eq!(site.record(CuTime::from(5000 * MS)), ErrorLine::First);
+    }
 }
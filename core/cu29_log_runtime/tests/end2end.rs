@@ -1,5 +1,6 @@
 use cu29_helpers::basic_copper_setup;
-use cu29_log_derive::debug;
+use cu29_log_derive::{debug, error};
+use cu29_log_runtime::CuErrorCounts;
 use serde::Serialize;
 use tempdir::TempDir;
 
@@ -25,5 +26,17 @@ fn log_derive_end2end() {
         debug!("mixed named param constants, {} {} {}", a = 3, 54, b = 2);
         debug!("complex tuple", mytuple);
         debug!("Struct", Test { a: 3, b: 4 });
+        for i in 0..3 {
+            error!("repeated error {}", i);
+        }
+        let sites = cu29_log_runtime::error_sites();
+        assert_eq!(sites.len(), 1);
+        assert_eq!(
+            sites[0].1,
+            CuErrorCounts {
+                count: 3,
+                suppressed: 2
+            }
+        );
     }
 }
@@ -0,0 +1,413 @@
+//! The plugin side: the tasks of the plugin behind the functions of CuPluginApi.
+
+use crate::{
+    CuPluginApi, CuPluginHandle, CU_PLUGIN_ERROR, CU_PLUGIN_NO_DT, CU_PLUGIN_NO_OUTPUT,
+    CU_PLUGIN_OUTPUT, PLUGIN_ABI_VERSION,
+};
+use bincode::{decode_from_slice, encode_to_vec};
+use cu29::clock::{CuDuration, RobotClock, RobotClockMock};
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{CuMsg, CuMsgPayload, CuSinkTask, CuSrcTask, CuTask};
+use cu29::registry::msg_type_of;
+use cu29::wire::{wire_config, WIRE_FORMAT_VERSION};
+use cu29_traits::{CuError, CuResult};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::marker::PhantomData;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::OnceLock;
+
+/// A task the plugin exports, see export_plugin!.
+pub struct CuPluginTaskType {
+    name: &'static str,
+    /// The registered names of its payload types, None if it has no input or no output.
+    input: fn() -> CuResult<Option<String>>,
+    output: fn() -> CuResult<Option<String>>,
+    create: fn(Option<&ComponentConfig>) -> CuResult<Box<dyn PluginTask>>,
+}
+
+/// A task of the plugin with its payloads in the wire format.
+trait PluginTask {
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()>;
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()>;
+
+    /// Encodes the output in output, returns if there is one.
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Option<&[u8]>,
+        output: &mut Vec<u8>,
+    ) -> CuResult<bool>;
+}
+
+fn no_payload() -> CuResult<Option<String>> {
+    Ok(None)
+}
+
+fn payload_name<T: 'static>() -> CuResult<Option<String>> {
+    let msg_type = msg_type_of::<T>().ok_or_else(|| {
+        CuError::from(format!(
+            "The payload type {} is not registered in the plugin, see cu29::registry.",
+            std::any::type_name::<T>()
+        ))
+    })?;
+    Ok(Some(msg_type.name().to_string()))
+}
+
+fn decode<T: CuMsgPayload>(input: Option<&[u8]>) -> CuResult<CuMsg<T>> {
+    let payload = input
+        .map(|bytes| {
+            decode_from_slice::<T, _>(bytes, wire_config())
+                .map(|(payload, _)| payload)
+                .map_err(|e| CuError::new_with_cause("Invalid input given to the plugin", e))
+        })
+        .transpose()?;
+    Ok(CuMsg::new(payload))
+}
+
+fn encode<T: CuMsgPayload>(msg: &CuMsg<T>, output: &mut Vec<u8>) -> CuResult<bool> {
+    let Some(payload) = msg.payload() else {
+        return Ok(false);
+    };
+    *output = encode_to_vec(payload, wire_config())
+        .map_err(|e| CuError::new_with_cause("Could not encode the output of the plugin", e))?;
+    Ok(true)
+}
+
+struct Source<T, O>(T, PhantomData<O>);
+
+impl<T, O> PluginTask for Source<T, O>
+where
+    T: for<'cl> CuSrcTask<'cl, Output = &'cl mut CuMsg<O>>,
+    O: CuMsgPayload,
+{
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.0.start(clock)
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.0.stop(clock)
+    }
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        _: Option<&[u8]>,
+        output: &mut Vec<u8>,
+    ) -> CuResult<bool> {
+        let mut msg = CuMsg::<O>::new(None);
+        self.0.process(ctx, &mut msg)?;
+        encode(&msg, output)
+    }
+}
+
+struct Task<T, I, O>(T, PhantomData<(I, O)>);
+
+impl<T, I, O> PluginTask for Task<T, I, O>
+where
+    T: for<'cl> CuTask<'cl, Input = &'cl CuMsg<I>, Output = &'cl mut CuMsg<O>>,
+    I: CuMsgPayload,
+    O: CuMsgPayload,
+{
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.0.start(clock)
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.0.stop(clock)
+    }
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Option<&[u8]>,
+        output: &mut Vec<u8>,
+    ) -> CuResult<bool> {
+        let input = decode::<I>(input)?;
+        let mut msg = CuMsg::<O>::new(None);
+        self.0.process(ctx, &input, &mut msg)?;
+        encode(&msg, output)
+    }
+}
+
+struct Sink<T, I>(T, PhantomData<I>);
+
+impl<T, I> PluginTask for Sink<T, I>
+where
+    T: for<'cl> CuSinkTask<'cl, Input = &'cl CuMsg<I>>,
+    I: CuMsgPayload,
+{
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.0.start(clock)
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.0.stop(clock)
+    }
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Option<&[u8]>,
+        _: &mut Vec<u8>,
+    ) -> CuResult<bool> {
+        self.0.process(ctx, &decode::<I>(input)?)?;
+        Ok(false)
+    }
+}
+
+/// A source task exported under this name.
+pub fn source<T, O>(name: &'static str) -> CuPluginTaskType
+where
+    T: for<'cl> CuSrcTask<'cl, Output = &'cl mut CuMsg<O>> + 'static,
+    O: CuMsgPayload + 'static,
+{
+    CuPluginTaskType {
+        name,
+        input: no_payload,
+        output: payload_name::<O>,
+        create: |config| Ok(Box::new(Source::<T, O>(T::new(config)?, PhantomData))),
+    }
+}
+
+/// A task with one input exported under this name.
+pub fn task<T, I, O>(name: &'static str) -> CuPluginTaskType
+where
+    T: for<'cl> CuTask<'cl, Input = &'cl CuMsg<I>, Output = &'cl mut CuMsg<O>> + 'static,
+    I: CuMsgPayload + 'static,
+    O: CuMsgPayload + 'static,
+{
+    CuPluginTaskType {
+        name,
+        input: payload_name::<I>,
+        output: payload_name::<O>,
+        create: |config| Ok(Box::new(Task::<T, I, O>(T::new(config)?, PhantomData))),
+    }
+}
+
+/// A sink task exported under this name.
+pub fn sink<T, I>(name: &'static str) -> CuPluginTaskType
+where
+    T: for<'cl> CuSinkTask<'cl, Input = &'cl CuMsg<I>> + 'static,
+    I: CuMsgPayload + 'static,
+{
+    CuPluginTaskType {
+        name,
+        input: payload_name::<I>,
+        output: no_payload,
+        create: |config| Ok(Box::new(Sink::<T, I>(T::new(config)?, PhantomData))),
+    }
+}
+
+/// A task instantiated by create, with the clock it sees set to the time of the application on every call.
+struct Instance {
+    task: Box<dyn PluginTask>,
+    clock: RobotClock,
+    mock: RobotClockMock,
+    output: Vec<u8>,
+}
+
+struct Plugin {
+    types: Vec<CuPluginTaskType>,
+    /// The names of the types, one per line.
+    names: CString,
+}
+
+static PLUGIN: OnceLock<Plugin> = OnceLock::new();
+
+fn plugin() -> &'static Plugin {
+    // Initialized by plugin_api before the application gets any function.
+    PLUGIN.get().expect("cu_plugin_api was not called")
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Runs a function of the API: its error is kept for last_error and a panic does not unwind in the application.
+fn plugin_call<R>(f: impl FnOnce() -> CuResult<R>) -> Option<R> {
+    let result =
+        catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Err("The plugin panicked.".into()));
+    result
+        .map_err(|e| {
+            let message = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+            LAST_ERROR.with(|last| *last.borrow_mut() = message);
+        })
+        .ok()
+}
+
+unsafe fn string<'a>(s: *const c_char) -> CuResult<&'a str> {
+    if s.is_null() {
+        return Err("A string given to the plugin is null.".into());
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| CuError::new_with_cause("A string given to the plugin is not UTF-8", e))
+}
+
+unsafe fn instance<'a>(handle: CuPluginHandle) -> CuResult<&'a mut Instance> {
+    (handle as *mut Instance)
+        .as_mut()
+        .ok_or_else(|| "The task of the plugin is null.".into())
+}
+
+fn check_payload(
+    task: &str,
+    side: &str,
+    declared: CuResult<Option<String>>,
+    connected: &str,
+) -> CuResult<()> {
+    let declared = declared?.unwrap_or_default();
+    if declared != connected {
+        return Err(format!(
+            "The task {} of the plugin has {} as {}, the application connects it with {}.",
+            task,
+            if declared.is_empty() {
+                "nothing"
+            } else {
+                &declared
+            },
+            side,
+            if connected.is_empty() {
+                "nothing"
+            } else {
+                connected
+            },
+        )
+        .into());
+    }
+    Ok(())
+}
+
+extern "C" fn task_types() -> *const c_char {
+    plugin().names.as_ptr()
+}
+
+unsafe extern "C" fn create(
+    task_type: *const c_char,
+    input_type: *const c_char,
+    output_type: *const c_char,
+    config: *const c_char,
+) -> CuPluginHandle {
+    plugin_call(|| {
+        let name = string(task_type)?;
+        let task_type = plugin()
+            .types
+            .iter()
+            .find(|task_type| task_type.name == name)
+            .ok_or_else(|| CuError::from(format!("The plugin has no task {}.", name)))?;
+        check_payload(name, "input", (task_type.input)(), string(input_type)?)?;
+        check_payload(name, "output", (task_type.output)(), string(output_type)?)?;
+        let config: Option<ComponentConfig> = ron::from_str(string(config)?)
+            .map_err(|e| CuError::new_with_cause("Invalid config given to the plugin", e))?;
+        let (clock, mock) = RobotClock::mock();
+        let instance = Instance {
+            task: (task_type.create)(config.as_ref())?,
+            clock,
+            mock,
+            output: Vec::new(),
+        };
+        Ok(Box::into_raw(Box::new(instance)) as CuPluginHandle)
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+fn status(result: Option<i32>) -> i32 {
+    result.unwrap_or(CU_PLUGIN_ERROR)
+}
+
+unsafe extern "C" fn start(handle: CuPluginHandle, now: u64) -> i32 {
+    status(plugin_call(|| {
+        let instance = instance(handle)?;
+        instance.mock.set_value(now);
+        instance.task.start(&instance.clock)?;
+        Ok(CU_PLUGIN_OUTPUT)
+    }))
+}
+
+unsafe extern "C" fn stop(handle: CuPluginHandle, now: u64) -> i32 {
+    status(plugin_call(|| {
+        let instance = instance(handle)?;
+        instance.mock.set_value(now);
+        instance.task.stop(&instance.clock)?;
+        Ok(CU_PLUGIN_OUTPUT)
+    }))
+}
+
+unsafe extern "C" fn process(
+    handle: CuPluginHandle,
+    now: u64,
+    dt: u64,
+    input: *const u8,
+    input_len: usize,
+    output_len: *mut usize,
+) -> i32 {
+    status(plugin_call(|| {
+        let instance = instance(handle)?;
+        instance.mock.set_value(now);
+        let input = (!input.is_null()).then(|| std::slice::from_raw_parts(input, input_len));
+        let dt = (dt != CU_PLUGIN_NO_DT).then_some(CuDuration(dt));
+        let ctx = CuContext::new(&instance.clock, dt);
+        if !instance.task.process(&ctx, input, &mut instance.output)? {
+            return Ok(CU_PLUGIN_NO_OUTPUT);
+        }
+        if let Some(output_len) = output_len.as_mut() {
+            *output_len = instance.output.len();
+        }
+        Ok(CU_PLUGIN_OUTPUT)
+    }))
+}
+
+unsafe extern "C" fn read_output(handle: CuPluginHandle, buffer: *mut u8, len: usize) -> usize {
+    plugin_call(|| {
+        let instance = instance(handle)?;
+        let len = len.min(instance.output.len());
+        if !buffer.is_null() {
+            std::ptr::copy_nonoverlapping(instance.output.as_ptr(), buffer, len);
+        }
+        Ok(len)
+    })
+    .unwrap_or(0)
+}
+
+unsafe extern "C" fn free(handle: CuPluginHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle as *mut Instance));
+    }
+}
+
+extern "C" fn last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+static API: CuPluginApi = CuPluginApi {
+    abi_version: PLUGIN_ABI_VERSION,
+    wire_version: WIRE_FORMAT_VERSION,
+    task_types,
+    create,
+    start,
+    stop,
+    process,
+    read_output,
+    free,
+    last_error,
+};
+
+/// The API of the plugin exporting these task types, called by the entry point export_plugin! defines.
+pub fn plugin_api(types: fn() -> Vec<CuPluginTaskType>) -> *const CuPluginApi {
+    PLUGIN.get_or_init(|| {
+        let types = types();
+        let names = types
+            .iter()
+            .map(|task_type| task_type.name)
+            .collect::<Vec<_>>()
+            .join("\n");
+        Plugin {
+            types,
+            names: CString::new(names).unwrap_or_default(),
+        }
+    });
+    &API
+}
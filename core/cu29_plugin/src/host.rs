@@ -0,0 +1,560 @@
+//! The application side: the plugins loaded in the process and the tasks standing for the ones they export.
+
+use crate::{
+    CuPluginApi, CuPluginHandle, CU_PLUGIN_NO_DT, CU_PLUGIN_NO_OUTPUT, CU_PLUGIN_OUTPUT,
+    PLUGIN_ABI_VERSION, PLUGIN_ENTRY_POINT,
+};
+use bincode::{decode_from_slice, encode_to_vec};
+use cu29::clock::RobotClock;
+use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
+use cu29::cutask::{
+    CuMsg, CuMsgPayload, CuSinkTask, CuSrcTask, CuTask, CuTaskLifecycle, Freezable,
+};
+use cu29::registry::msg_type_of;
+use cu29::wire::{wire_config, WIRE_FORMAT_VERSION};
+use cu29::{input_msg, output_msg};
+use cu29_traits::lockaudit::{self, AuditedGuard, LockSite};
+use cu29_traits::{CuError, CuResult};
+use libloading::Library;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+/// A plugin loaded in the process, never unloaded.
+struct Plugin {
+    /// The path of its library or the name it was registered with.
+    name: String,
+    api: &'static CuPluginApi,
+    task_types: Vec<String>,
+    _library: Option<Library>,
+}
+
+static PLUGINS_LOCK: LockSite = LockSite::new("plugins");
+
+fn plugins() -> AuditedGuard<MutexGuard<'static, Vec<Arc<Plugin>>>> {
+    static PLUGINS: OnceLock<Mutex<Vec<Arc<Plugin>>>> = OnceLock::new();
+    let plugins = PLUGINS.get_or_init(|| Mutex::new(Vec::new()));
+    lockaudit::lock(&PLUGINS_LOCK, plugins).unwrap()
+}
+
+fn add_plugin(
+    name: String,
+    api: &'static CuPluginApi,
+    library: Option<Library>,
+) -> CuResult<Arc<Plugin>> {
+    if api.abi_version != PLUGIN_ABI_VERSION || api.wire_version != WIRE_FORMAT_VERSION {
+        return Err(format!(
+            "The plugin {} is built for the plugin API {} and the wire format {}, the application for {} and {}.",
+            name, api.abi_version, api.wire_version, PLUGIN_ABI_VERSION, WIRE_FORMAT_VERSION
+        )
+        .into());
+    }
+    // Safety: the plugin returns a static C string.
+    let task_types = unsafe { CStr::from_ptr((api.task_types)()) }
+        .to_string_lossy()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    let plugin = Arc::new(Plugin {
+        name,
+        api,
+        task_types,
+        _library: library,
+    });
+    plugins().push(plugin.clone());
+    Ok(plugin)
+}
+
+fn load(path: &Path) -> CuResult<Arc<Plugin>> {
+    let name = path.display().to_string();
+    if let Some(plugin) = plugins().iter().find(|plugin| plugin.name == name) {
+        return Ok(plugin.clone());
+    }
+    // Safety: the library runs its initializers, it is trusted like any code the application is linked with.
+    let library = unsafe { Library::new(path) }
+        .map_err(|e| CuError::new_with_cause(&format!("Could not load the plugin {}", name), e))?;
+    // Safety: export_plugin! defines the entry point with this signature.
+    let api = unsafe {
+        let entry = library
+            .get::<extern "C" fn() -> *const CuPluginApi>(PLUGIN_ENTRY_POINT.as_bytes())
+            .map_err(|e| {
+                CuError::new_with_cause(
+                    &format!("{} is not a copper plugin, see export_plugin!", name),
+                    e,
+                )
+            })?;
+        // The API is static in the library, which stays loaded.
+        &*entry()
+    };
+    add_plugin(name, api, Some(library))
+}
+
+/// Loads the plugin in this dynamic library, if it is not already, for its tasks to be resolved by name.
+pub fn load_plugin(path: impl AsRef<Path>) -> CuResult<()> {
+    load(path.as_ref()).map(|_| ())
+}
+
+/// Registers a plugin already in memory, ie. loaded by other means, under a name.
+pub fn register_plugin(name: &str, api: &'static CuPluginApi) -> CuResult<()> {
+    add_plugin(name.to_string(), api, None).map(|_| ())
+}
+
+/// The names of the tasks exported by the loaded plugins.
+pub fn plugin_task_types() -> Vec<String> {
+    plugins()
+        .iter()
+        .flat_map(|plugin| plugin.task_types.iter().cloned())
+        .collect()
+}
+
+/// The registered name of a payload type, empty for none.
+fn payload_name<T: 'static>(task: &str) -> CuResult<String> {
+    msg_type_of::<T>()
+        .map(|msg_type| msg_type.name().to_string())
+        .ok_or_else(|| {
+            format!(
+                "The payload type {} of the plugin task {} is not registered, see cu29::registry.",
+                std::any::type_name::<T>(),
+                task
+            )
+            .into()
+        })
+}
+
+fn c_string(s: &str) -> CuResult<CString> {
+    CString::new(s).map_err(|e| CuError::new_with_cause("Invalid string for a plugin", e))
+}
+
+/// A task instantiated in a plugin.
+struct Instance {
+    plugin: Arc<Plugin>,
+    handle: CuPluginHandle,
+    task: String,
+}
+
+// The task is only used by the thread running it, like the other tasks of the application.
+unsafe impl Send for Instance {}
+
+impl Instance {
+    /// Resolves the task of the config in its library, or in the loaded plugins if it has none.
+    fn new(config: Option<&ComponentConfig>, input: &str, output: &str) -> CuResult<Self> {
+        let task = config
+            .and_then(|config| config.get::<String>("task"))
+            .ok_or("A plugin task needs the name of its task in its config, ie. \"task\": \"vendor::Lidar\".")?;
+        let library = config.and_then(|config| config.get::<String>("library"));
+        let plugin = match library {
+            Some(library) => load(Path::new(&library))?,
+            None => plugins()
+                .iter()
+                .find(|plugin| plugin.task_types.contains(&task))
+                .cloned()
+                .ok_or_else(|| {
+                    CuError::from(format!(
+                        "No loaded plugin exports the task {}, give its \"library\" in the config.",
+                        task
+                    ))
+                })?,
+        };
+        if !plugin.task_types.contains(&task) {
+            return Err(format!(
+                "The plugin {} does not export the task {}, only {}.",
+                plugin.name,
+                task,
+                plugin.task_types.join(", ")
+            )
+            .into());
+        }
+        let config = ron::to_string(&config)
+            .map_err(|e| CuError::new_with_cause("Could not write the config for the plugin", e))?;
+        let (task_type, input, output, config) = (
+            c_string(&task)?,
+            c_string(input)?,
+            c_string(output)?,
+            c_string(&config)?,
+        );
+        // Safety: the strings live until the call returns.
+        let handle = unsafe {
+            (plugin.api.create)(
+                task_type.as_ptr(),
+                input.as_ptr(),
+                output.as_ptr(),
+                config.as_ptr(),
+            )
+        };
+        let instance = Instance {
+            plugin,
+            handle,
+            task,
+        };
+        if handle.is_null() {
+            return Err(instance.error());
+        }
+        Ok(instance)
+    }
+
+    /// The error of the last call of the plugin.
+    fn error(&self) -> CuError {
+        // Safety: the plugin returns a C string valid until its next call on this thread.
+        let error = unsafe { CStr::from_ptr((self.plugin.api.last_error)()) };
+        format!("Plugin task {}: {}", self.task, error.to_string_lossy()).into()
+    }
+
+    fn check(&self, status: i32) -> CuResult<i32> {
+        match status {
+            CU_PLUGIN_OUTPUT | CU_PLUGIN_NO_OUTPUT => Ok(status),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        // Safety: the handle was created by the plugin and is freed on drop only.
+        self.check(unsafe { (self.plugin.api.start)(self.handle, clock.now().0) })
+            .map(|_| ())
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        // Safety: see start.
+        self.check(unsafe { (self.plugin.api.stop)(self.handle, clock.now().0) })
+            .map(|_| ())
+    }
+
+    /// Runs process with the input in the wire format and returns the output in the wire format.
+    fn process(&mut self, ctx: &CuContext, input: Option<&[u8]>) -> CuResult<Option<Vec<u8>>> {
+        let (input, input_len) =
+            input.map_or((std::ptr::null(), 0), |input| (input.as_ptr(), input.len()));
+        let dt = ctx.dt.map_or(CU_PLUGIN_NO_DT, |dt| dt.0);
+        let mut output_len = 0;
+        // Safety: see start, the input lives until the call returns.
+        let status = unsafe {
+            (self.plugin.api.process)(
+                self.handle,
+                ctx.clock.now().0,
+                dt,
+                input,
+                input_len,
+                &mut output_len,
+            )
+        };
+        if self.check(status)? == CU_PLUGIN_NO_OUTPUT {
+            return Ok(None);
+        }
+        let mut output = vec![0u8; output_len];
+        // Safety: see start, the buffer is output_len long.
+        unsafe { (self.plugin.api.read_output)(self.handle, output.as_mut_ptr(), output_len) };
+        Ok(Some(output))
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // Safety: see start.
+        unsafe { (self.plugin.api.free)(self.handle) };
+    }
+}
+
+fn encode<T: CuMsgPayload>(msg: &CuMsg<T>) -> CuResult<Option<Vec<u8>>> {
+    msg.payload()
+        .map(|payload| {
+            encode_to_vec(payload, wire_config())
+                .map_err(|e| CuError::new_with_cause("Could not encode the message", e))
+        })
+        .transpose()
+}
+
+fn decode<T: CuMsgPayload>(task: &str, bytes: Option<Vec<u8>>, msg: &mut CuMsg<T>) -> CuResult<()> {
+    match bytes {
+        Some(bytes) => {
+            let (payload, _) = decode_from_slice::<T, _>(&bytes, wire_config()).map_err(|e| {
+                CuError::new_with_cause(&format!("Invalid output of the plugin task {}", task), e)
+            })?;
+            msg.set_payload(payload);
+        }
+        None => msg.clear_payload(),
+    }
+    Ok(())
+}
+
+/// A source task exported by a plugin.
+///
+/// config: {"task": "vendor::Lidar", "library": "liblidar.so"} and the config of the task, the library can be left
+/// out if the plugin is already loaded. The payload type has to be registered in cu29::registry.
+pub struct CuPluginSource<O> {
+    instance: Instance,
+    _payload: PhantomData<O>,
+}
+
+impl<O: 'static> CuTaskLifecycle for CuPluginSource<O> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuPluginSource {
+            instance: Instance::new(config, "", &payload_name::<O>("source")?)?,
+            _payload: PhantomData,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.instance.start(clock)
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.instance.stop(clock)
+    }
+}
+
+impl<O> Freezable for CuPluginSource<O> {}
+
+impl<'cl, O: CuMsgPayload + 'static> CuSrcTask<'cl> for CuPluginSource<O> {
+    type Output = output_msg!('cl, O);
+
+    fn process(&mut self, ctx: &CuContext, output: Self::Output) -> CuResult<()> {
+        let bytes = self.instance.process(ctx, None)?;
+        decode(&self.instance.task, bytes, output)
+    }
+}
+
+/// A task with one input exported by a plugin, configured like CuPluginSource.
+pub struct CuPluginTask<I, O> {
+    instance: Instance,
+    _payloads: PhantomData<(I, O)>,
+}
+
+impl<I: 'static, O: 'static> CuTaskLifecycle for CuPluginTask<I, O> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuPluginTask {
+            instance: Instance::new(
+                config,
+                &payload_name::<I>("task")?,
+                &payload_name::<O>("task")?,
+            )?,
+            _payloads: PhantomData,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.instance.start(clock)
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.instance.stop(clock)
+    }
+}
+
+impl<I, O> Freezable for CuPluginTask<I, O> {}
+
+impl<'cl, I: CuMsgPayload + 'static, O: CuMsgPayload + 'static> CuTask<'cl> for CuPluginTask<I, O> {
+    type Input = input_msg!('cl, I);
+    type Output = output_msg!('cl, O);
+
+    fn process(
+        &mut self,
+        ctx: &CuContext,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> CuResult<()> {
+        let bytes = self.instance.process(ctx, encode(input)?.as_deref())?;
+        decode(&self.instance.task, bytes, output)
+    }
+}
+
+/// A sink task exported by a plugin, configured like CuPluginSource.
+pub struct CuPluginSink<I> {
+    instance: Instance,
+    _payload: PhantomData<I>,
+}
+
+impl<I: 'static> CuTaskLifecycle for CuPluginSink<I> {
+    fn new(config: Option<&ComponentConfig>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(CuPluginSink {
+            instance: Instance::new(config, &payload_name::<I>("sink")?, "")?,
+            _payload: PhantomData,
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.instance.start(clock)
+    }
+
+    fn stop(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.instance.stop(clock)
+    }
+}
+
+impl<I> Freezable for CuPluginSink<I> {}
+
+impl<'cl, I: CuMsgPayload + 'static> CuSinkTask<'cl> for CuPluginSink<I> {
+    type Input = input_msg!('cl, I);
+
+    fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+        self.instance
+            .process(ctx, encode(input)?.as_deref())
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::CuDuration;
+    use cu29::registry::register_msg_type;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static RECEIVED: AtomicU32 = AtomicU32::new(0);
+
+    /// Counts from the offset in its config, every other iteration.
+    struct Counter {
+        next: u32,
+    }
+
+    impl Freezable for Counter {}
+
+    impl CuTaskLifecycle for Counter {
+        fn new(config: Option<&ComponentConfig>) -> CuResult<Self> {
+            let next = config
+                .and_then(|config| config.get::<u32>("offset"))
+                .ok_or("no offset")?;
+            Ok(Counter { next })
+        }
+    }
+
+    impl<'cl> CuSrcTask<'cl> for Counter {
+        type Output = output_msg!('cl, u32);
+
+        fn process(&mut self, ctx: &CuContext, output: Self::Output) -> CuResult<()> {
+            if ctx.clock.now().0.is_multiple_of(2) {
+                output.set_payload(self.next);
+                self.next += 1;
+            }
+            Ok(())
+        }
+    }
+
+    /// Doubles its input, and gives the dt it got when it has no input.
+    struct Doubler;
+
+    impl Freezable for Doubler {}
+
+    impl CuTaskLifecycle for Doubler {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self> {
+            Ok(Doubler)
+        }
+    }
+
+    impl<'cl> CuTask<'cl> for Doubler {
+        type Input = input_msg!('cl, u32);
+        type Output = output_msg!('cl, u64);
+
+        fn process(
+            &mut self,
+            ctx: &CuContext,
+            input: Self::Input,
+            output: Self::Output,
+        ) -> CuResult<()> {
+            match input.payload() {
+                Some(value) => output.set_payload(*value as u64 * 2),
+                None => output.set_payload(ctx.dt.map_or(0, |dt| dt.0)),
+            }
+            Ok(())
+        }
+    }
+
+    struct Receiver;
+
+    impl Freezable for Receiver {}
+
+    impl CuTaskLifecycle for Receiver {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self> {
+            Ok(Receiver)
+        }
+
+        fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+            Err("the device is gone".into())
+        }
+    }
+
+    impl<'cl> CuSinkTask<'cl> for Receiver {
+        type Input = input_msg!('cl, u64);
+
+        fn process(&mut self, _ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+            if let Some(value) = input.payload() {
+                RECEIVED.store(*value as u32, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    fn register_types() {
+        register_msg_type::<u32>("u32").unwrap();
+        register_msg_type::<u64>("u64").unwrap();
+    }
+
+    crate::export_plugin! {
+        init: register_types;
+        source "test::Counter" => Counter,
+        task "test::Doubler" => Doubler,
+        sink "test::Receiver" => Receiver,
+    }
+
+    fn config(task: &str) -> ComponentConfig {
+        let mut config = ComponentConfig::new();
+        config.set("task", task.to_string());
+        config
+    }
+
+    #[test]
+    fn test_plugin_tasks() {
+        // The plugin is linked in the test, it goes through its C API all the same.
+        register_plugin("test", unsafe { &*cu_plugin_api() }).unwrap();
+        assert_eq!(
+            plugin_task_types(),
+            ["test::Counter", "test::Doubler", "test::Receiver"]
+        );
+
+        let (clock, mock) = RobotClock::mock();
+        let mut counter_config = config("test::Counter");
+        counter_config.set("offset", 10u32);
+        let mut counter = CuPluginSource::<u32>::new(Some(&counter_config)).unwrap();
+        let mut doubler = CuPluginTask::<u32, u64>::new(Some(&config("test::Doubler"))).unwrap();
+        let mut receiver = CuPluginSink::<u64>::new(Some(&config("test::Receiver"))).unwrap();
+        counter.start(&clock).unwrap();
+
+        let mut count = CuMsg::new(None);
+        let mut doubled = CuMsg::new(None);
+        for (now, expected) in [(2, Some(10)), (3, None), (4, Some(11))] {
+            mock.set_value(now);
+            let ctx = CuContext::new(&clock, Some(CuDuration(1)));
+            counter.process(&ctx, &mut count).unwrap();
+            assert_eq!(count.payload().copied(), expected);
+            doubler.process(&ctx, &count, &mut doubled).unwrap();
+            receiver.process(&ctx, &doubled).unwrap();
+        }
+        assert_eq!(doubled.payload(), Some(&22));
+        assert_eq!(RECEIVED.load(Ordering::SeqCst), 22);
+        // The dt crosses the library boundary.
+        doubler
+            .process(
+                &CuContext::new(&clock, Some(CuDuration(7))),
+                &CuMsg::new(None),
+                &mut doubled,
+            )
+            .unwrap();
+        assert_eq!(doubled.payload(), Some(&7));
+
+        let error = receiver.stop(&clock).unwrap_err().to_string();
+        assert!(error.contains("the device is gone"), "{}", error);
+        // The config, the name and the payload types are checked by the plugin.
+        assert!(CuPluginSource::<u32>::new(Some(&config("test::Counter"))).is_err());
+        assert!(CuPluginSource::<u32>::new(Some(&config("test::Radar"))).is_err());
+        assert!(CuPluginSource::<u64>::new(Some(&counter_config)).is_err());
+        assert!(CuPluginSink::<u64>::new(None).is_err());
+    }
+}
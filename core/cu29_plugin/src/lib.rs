@@ -0,0 +1,122 @@
+//! Copper tasks built in dynamic libraries (cdylib) and loaded at startup, so a closed-source vendor driver or a
+//! research task iterated on quickly can be swapped without recompiling the application.
+//!
+//! The plugin exports its tasks by name:
+//!
+//! ```ignore
+//! cu29_plugin::export_plugin! {
+//!     init: my_msgs::register_types;
+//!     source "vendor::Lidar" => Lidar,
+//!     task "vendor::Filter" => Filter,
+//! }
+//! ```
+//!
+//! The application declares them with the generic tasks of this crate, the task is resolved by its name in the
+//! library given in the config, or in the plugins already loaded with load_plugin:
+//!
+//! ```ron
+//! (id: "lidar", type: "cu29_plugin::CuPluginSource<my_msgs::Scan>",
+//!  config: {"library": "/opt/vendor/liblidar.so", "task": "vendor::Lidar", "port": "/dev/ttyUSB0"}),
+//! ```
+//!
+//! The whole config of the task is given to the plugin. The payloads cross the library boundary as bytes in the
+//! wire format with the name their type is registered with in cu29::registry, on both sides, so the plugin and the
+//! application do not need to be built by the same compiler. Only the payload of the messages crosses it, not their
+//! metadata, and only start, process and stop are called on the tasks of a plugin.
+
+mod export;
+mod host;
+
+pub use export::{plugin_api, sink, source, task, CuPluginTaskType};
+pub use host::{load_plugin, plugin_task_types, register_plugin};
+pub use host::{CuPluginSink, CuPluginSource, CuPluginTask};
+
+use std::ffi::{c_char, c_void};
+
+/// Bumped on every change of CuPluginApi, a plugin built for another version is refused.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// The symbol export_plugin! defines, a function returning the CuPluginApi of the plugin.
+pub const PLUGIN_ENTRY_POINT: &str = "cu_plugin_api";
+
+/// process produced an output, see read_output.
+pub const CU_PLUGIN_OUTPUT: i32 = 0;
+/// process produced no output.
+pub const CU_PLUGIN_NO_OUTPUT: i32 = 1;
+/// The call failed, see last_error.
+pub const CU_PLUGIN_ERROR: i32 = -1;
+
+/// dt of the first process call of a task.
+pub const CU_PLUGIN_NO_DT: u64 = u64::MAX;
+
+/// A task instantiated in a plugin, opaque for the application.
+pub type CuPluginHandle = *mut c_void;
+
+/// The functions of a plugin. The strings are C strings, an empty one stands for no payload type.
+/// The times are in ns on the robot clock of the application.
+#[repr(C)]
+pub struct CuPluginApi {
+    pub abi_version: u32,
+    /// The version of the wire format of the payloads.
+    pub wire_version: u16,
+    /// The names of the task types of the plugin, one per line.
+    pub task_types: extern "C" fn() -> *const c_char,
+    /// Instantiates a task with the registered names of the payload types the application connects it with and its
+    /// config in RON, null on error.
+    pub create: unsafe extern "C" fn(
+        task_type: *const c_char,
+        input_type: *const c_char,
+        output_type: *const c_char,
+        config: *const c_char,
+    ) -> CuPluginHandle,
+    pub start: unsafe extern "C" fn(handle: CuPluginHandle, now: u64) -> i32,
+    pub stop: unsafe extern "C" fn(handle: CuPluginHandle, now: u64) -> i32,
+    /// Processes the input (null for a source or if there is none), the length of the output is written in
+    /// output_len if there is one.
+    pub process: unsafe extern "C" fn(
+        handle: CuPluginHandle,
+        now: u64,
+        dt: u64,
+        input: *const u8,
+        input_len: usize,
+        output_len: *mut usize,
+    ) -> i32,
+    /// Copies the output of the last process call, returns the number of bytes copied.
+    pub read_output:
+        unsafe extern "C" fn(handle: CuPluginHandle, buffer: *mut u8, len: usize) -> usize,
+    pub free: unsafe extern "C" fn(handle: CuPluginHandle),
+    /// The error of the last failed call on this thread.
+    pub last_error: extern "C" fn() -> *const c_char,
+}
+
+/// Exports the tasks of a plugin under their names, with a function registering the payload types in
+/// cu29::registry called once before anything else.
+#[macro_export]
+macro_rules! export_plugin {
+    (init: $init:path; $($kind:ident $name:literal => $task:ty),* $(,)?) => {
+        fn __cu_plugin_task_types() -> Vec<$crate::CuPluginTaskType> {
+            $init();
+            vec![$($crate::export_plugin!(@type $kind $name $task)),*]
+        }
+
+        #[no_mangle]
+        pub extern "C" fn cu_plugin_api() -> *const $crate::CuPluginApi {
+            $crate::plugin_api(__cu_plugin_task_types)
+        }
+    };
+    ($($kind:ident $name:literal => $task:ty),* $(,)?) => {
+        $crate::export_plugin!(init: $crate::__no_init; $($kind $name => $task),*);
+    };
+    (@type source $name:literal $task:ty) => {
+        $crate::source::<$task, _>($name)
+    };
+    (@type task $name:literal $task:ty) => {
+        $crate::task::<$task, _, _>($name)
+    };
+    (@type sink $name:literal $task:ty) => {
+        $crate::sink::<$task, _>($name)
+    };
+}
+
+#[doc(hidden)]
+pub fn __no_init() {}
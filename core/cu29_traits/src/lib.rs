@@ -1,3 +1,5 @@
+pub mod lockaudit;
+
 use bincode::{Decode as dDecode, Encode, Encode as dEncode};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
@@ -73,6 +75,33 @@ pub enum UnifiedLogType {
     StructuredLogLine, // This is for the structured logs (ie. debug! etc..)
     CopperList,        // This is the actual data log storing activities between tasks.
     LastEntry,         // This is a special entry that is used to signal the end of the log.
+    Signature,         // This signs the hashes of the sections written before it.
+    Summary,           // The overview of the run, written when the application is dropped.
+    Annotation,        // A marker flagging a moment of the run.
+    Schema,            // The version of the wire format and the layout of the copper lists.
+    ParamChange,       // A parameter of a task changed while the application runs.
+    DerivedChannel,    // Downsampled copies of the outputs of tasks, ie. for the telemetry exports.
+    ClockEvent,        // A step or a drift of the robot clock against the wall clock.
+}
+
+/// The version of the wire format of the log, bumped on any change of the encoding of what copper writes.
+/// See doc/wire_format.md.
+pub const WIRE_FORMAT_VERSION: u16 = 5;
+
+/// The bincode configuration of the wire format.
+pub type WireConfig = bincode::config::Configuration<
+    bincode::config::LittleEndian,
+    bincode::config::Varint,
+    bincode::config::NoLimit,
+>;
+
+/// The encoding of everything copper writes in the log, spelled out rather than relying on the defaults of
+/// bincode so the log reads the same on every architecture and across upgrades.
+pub const fn wire_config() -> WireConfig {
+    bincode::config::standard()
+        .with_little_endian()
+        .with_variable_int_encoding()
+        .with_no_limit()
 }
 
 /// A CopperListTuple needs to be encodable, decodable and fixed size in memory.
@@ -0,0 +1,314 @@
+//! Auditing of the locks shared by the threads of copper, to find the priority inversions between the logger, the
+//! pools and the control loop.
+//!
+//! The internal locks are taken through `lock`, `read` and `write` with a static `LockSite` naming them. Without the
+//! `lock_audit` feature these are the plain std calls. With it, every site counts how often it was contended and how
+//! long it was waited for and held, and a wait or a hold longer than the warning threshold (1ms by default) is
+//! reported on stderr with the thread, and the task if known, on both sides of the lock. It never goes to the
+//! structured log, which is behind one of the audited locks.
+
+use std::sync::{LockResult, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+#[cfg(feature = "lock_audit")]
+pub use audit::AuditedGuard;
+
+/// The guard of an audited lock, the plain guard without the lock_audit feature.
+#[cfg(not(feature = "lock_audit"))]
+pub type AuditedGuard<G> = G;
+
+/// A place in the code taking a lock, declared as a static.
+pub struct LockSite {
+    pub name: &'static str,
+    #[cfg(feature = "lock_audit")]
+    state: audit::SiteState,
+}
+
+impl LockSite {
+    pub const fn new(name: &'static str) -> Self {
+        LockSite {
+            name,
+            #[cfg(feature = "lock_audit")]
+            state: audit::SiteState::new(),
+        }
+    }
+}
+
+/// The statistics of a lock site since the start of the process.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LockStats {
+    pub name: &'static str,
+    pub acquisitions: u64,
+    /// Acquisitions which had to wait for another thread.
+    pub contentions: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    pub max_hold: Duration,
+}
+
+/// Locks the mutex, audited with the lock_audit feature.
+#[inline]
+pub fn lock<'a, T: ?Sized>(
+    site: &'static LockSite,
+    mutex: &'a Mutex<T>,
+) -> LockResult<AuditedGuard<MutexGuard<'a, T>>> {
+    #[cfg(feature = "lock_audit")]
+    return audit::acquire(site, || mutex.try_lock(), || mutex.lock());
+    #[cfg(not(feature = "lock_audit"))]
+    {
+        let _ = site;
+        mutex.lock()
+    }
+}
+
+/// Locks the rwlock for reading, audited with the lock_audit feature.
+#[inline]
+pub fn read<'a, T: ?Sized>(
+    site: &'static LockSite,
+    rwlock: &'a RwLock<T>,
+) -> LockResult<AuditedGuard<RwLockReadGuard<'a, T>>> {
+    #[cfg(feature = "lock_audit")]
+    return audit::acquire(site, || rwlock.try_read(), || rwlock.read());
+    #[cfg(not(feature = "lock_audit"))]
+    {
+        let _ = site;
+        rwlock.read()
+    }
+}
+
+/// Locks the rwlock for writing, audited with the lock_audit feature.
+#[inline]
+pub fn write<'a, T: ?Sized>(
+    site: &'static LockSite,
+    rwlock: &'a RwLock<T>,
+) -> LockResult<AuditedGuard<RwLockWriteGuard<'a, T>>> {
+    #[cfg(feature = "lock_audit")]
+    return audit::acquire(site, || rwlock.try_write(), || rwlock.write());
+    #[cfg(not(feature = "lock_audit"))]
+    {
+        let _ = site;
+        rwlock.write()
+    }
+}
+
+/// Sets how the current thread is described in the warnings, ie. with the task it is running.
+/// By default it is the name of the thread.
+pub fn set_attribution(describe: fn() -> String) {
+    #[cfg(feature = "lock_audit")]
+    let _ = audit::ATTRIBUTION.set(describe);
+    #[cfg(not(feature = "lock_audit"))]
+    let _ = describe;
+}
+
+/// Sets the wait or hold duration above which a lock is reported.
+pub fn set_warn_threshold(threshold: Duration) {
+    #[cfg(feature = "lock_audit")]
+    audit::WARN_THRESHOLD_NS.store(
+        threshold.as_nanos() as u64,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    #[cfg(not(feature = "lock_audit"))]
+    let _ = threshold;
+}
+
+/// The statistics of all the lock sites used so far, empty without the lock_audit feature.
+pub fn lock_stats() -> Vec<LockStats> {
+    #[cfg(feature = "lock_audit")]
+    return audit::stats();
+    #[cfg(not(feature = "lock_audit"))]
+    Vec::new()
+}
+
+#[cfg(feature = "lock_audit")]
+mod audit {
+    use super::{LockSite, LockStats};
+    use std::ops::{Deref, DerefMut};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::{LockResult, Mutex, OnceLock, PoisonError, TryLockError, TryLockResult};
+    use std::time::{Duration, Instant};
+
+    pub(super) static ATTRIBUTION: OnceLock<fn() -> String> = OnceLock::new();
+    pub(super) static WARN_THRESHOLD_NS: AtomicU64 = AtomicU64::new(1_000_000);
+    static SITES: Mutex<Vec<&'static LockSite>> = Mutex::new(Vec::new());
+
+    pub(super) struct SiteState {
+        registered: AtomicBool,
+        acquisitions: AtomicU64,
+        contentions: AtomicU64,
+        total_wait_ns: AtomicU64,
+        max_wait_ns: AtomicU64,
+        max_hold_ns: AtomicU64,
+        /// Who holds the lock, for the warnings of the threads waiting for it.
+        holder: Mutex<String>,
+    }
+
+    impl SiteState {
+        pub(super) const fn new() -> Self {
+            SiteState {
+                registered: AtomicBool::new(false),
+                acquisitions: AtomicU64::new(0),
+                contentions: AtomicU64::new(0),
+                total_wait_ns: AtomicU64::new(0),
+                max_wait_ns: AtomicU64::new(0),
+                max_hold_ns: AtomicU64::new(0),
+                holder: Mutex::new(String::new()),
+            }
+        }
+    }
+
+    fn describe_current() -> String {
+        match ATTRIBUTION.get() {
+            Some(describe) => describe(),
+            None => std::thread::current()
+                .name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{:?}", std::thread::current().id())),
+        }
+    }
+
+    fn threshold() -> Duration {
+        Duration::from_nanos(WARN_THRESHOLD_NS.load(Ordering::Relaxed))
+    }
+
+    /// The guard of an audited lock, it records how long the lock was held when dropped.
+    pub struct AuditedGuard<G> {
+        guard: G,
+        site: &'static LockSite,
+        since: Instant,
+    }
+
+    impl<G: Deref> Deref for AuditedGuard<G> {
+        type Target = G::Target;
+
+        fn deref(&self) -> &Self::Target {
+            &self.guard
+        }
+    }
+
+    impl<G: DerefMut> DerefMut for AuditedGuard<G> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.guard
+        }
+    }
+
+    impl<G> Drop for AuditedGuard<G> {
+        fn drop(&mut self) {
+            let held = self.since.elapsed();
+            let state = &self.site.state;
+            let previous_max = state
+                .max_hold_ns
+                .fetch_max(held.as_nanos() as u64, Ordering::Relaxed);
+            // Only the new worst holds, a slow lock would flood stderr otherwise.
+            if held > threshold() && held.as_nanos() as u64 > previous_max {
+                eprintln!(
+                    "Lock audit: '{}' held for {:?} by {}.",
+                    self.site.name,
+                    held,
+                    describe_current()
+                );
+            }
+        }
+    }
+
+    pub(super) fn acquire<G>(
+        site: &'static LockSite,
+        try_lock: impl FnOnce() -> TryLockResult<G>,
+        lock: impl FnOnce() -> LockResult<G>,
+    ) -> LockResult<AuditedGuard<G>> {
+        let state = &site.state;
+        if !state.registered.swap(true, Ordering::Relaxed) {
+            SITES.lock().unwrap().push(site);
+        }
+        let outcome = match try_lock() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Err(poisoned),
+            Err(TryLockError::WouldBlock) => {
+                let holder = state.holder.lock().unwrap().clone();
+                let start = Instant::now();
+                let outcome = lock();
+                let waited = start.elapsed();
+                state.contentions.fetch_add(1, Ordering::Relaxed);
+                state
+                    .total_wait_ns
+                    .fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+                let previous_max = state
+                    .max_wait_ns
+                    .fetch_max(waited.as_nanos() as u64, Ordering::Relaxed);
+                if waited > threshold() && waited.as_nanos() as u64 > previous_max {
+                    eprintln!(
+                        "Lock audit: {} waited {:?} for '{}' held by {}.",
+                        describe_current(),
+                        waited,
+                        site.name,
+                        holder
+                    );
+                }
+                outcome
+            }
+        };
+        state.acquisitions.fetch_add(1, Ordering::Relaxed);
+        *state.holder.lock().unwrap() = describe_current();
+        let audited = |guard| AuditedGuard {
+            guard,
+            site,
+            since: Instant::now(),
+        };
+        match outcome {
+            Ok(guard) => Ok(audited(guard)),
+            Err(poisoned) => Err(PoisonError::new(audited(poisoned.into_inner()))),
+        }
+    }
+
+    pub(super) fn stats() -> Vec<LockStats> {
+        SITES
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|site| {
+                let state = &site.state;
+                let ns = |value: &AtomicU64| Duration::from_nanos(value.load(Ordering::Relaxed));
+                LockStats {
+                    name: site.name,
+                    acquisitions: state.acquisitions.load(Ordering::Relaxed),
+                    contentions: state.contentions.load(Ordering::Relaxed),
+                    total_wait: ns(&state.total_wait_ns),
+                    max_wait: ns(&state.max_wait_ns),
+                    max_hold: ns(&state.max_hold_ns),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    static TEST_SITE: LockSite = LockSite::new("test");
+
+    #[test]
+    fn test_audited_lock() {
+        let mutex = Arc::new(Mutex::new(0u32));
+        let guard = lock(&TEST_SITE, &mutex).unwrap();
+        let contender = {
+            let mutex = mutex.clone();
+            std::thread::spawn(move || *lock(&TEST_SITE, &mutex).unwrap() += 1)
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        drop(guard);
+        contender.join().unwrap();
+        assert_eq!(*lock(&TEST_SITE, &mutex).unwrap(), 1);
+
+        let stats = lock_stats();
+        if cfg!(feature = "lock_audit") {
+            let test = stats.iter().find(|s| s.name == "test").unwrap();
+            assert_eq!(test.acquisitions, 3);
+            assert_eq!(test.contentions, 1);
+            assert!(test.max_wait >= Duration::from_millis(10));
+            assert!(test.max_hold >= Duration::from_millis(10));
+        } else {
+            assert!(stats.is_empty());
+        }
+    }
+}
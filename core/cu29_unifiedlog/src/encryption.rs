@@ -0,0 +1,115 @@
+//! At rest encryption of the sections of the log with AES-256-GCM.
+//! Each section is encrypted in place when it is flushed, with a random nonce. The nonce and the
+//! authentication tag are appended to its content, so a section is read back and checked on its own.
+
+use aes_gcm::aead::{AeadCore, AeadInPlace, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce, Tag};
+use cu29_traits::{CuError, CuResult, UnifiedLogType};
+
+/// A 256 bits AES key.
+pub type LogKey = [u8; 32];
+
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+/// The bytes appended to the content of each section.
+pub(crate) const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+pub(crate) struct SectionCipher {
+    cipher: Aes256Gcm,
+}
+
+impl SectionCipher {
+    pub(crate) fn new(key: &LogKey) -> Self {
+        SectionCipher {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    /// Encrypts content in place and returns the trailer to append to it.
+    /// The entry type and the position of the section in the log are authenticated too so a section cannot be
+    /// passed for another type, or moved, dropped or replayed elsewhere in the log.
+    pub(crate) fn seal(
+        &self,
+        entry_type: UnifiedLogType,
+        sequence: u64,
+        content: &mut [u8],
+    ) -> CuResult<[u8; ENCRYPTION_OVERHEAD]> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&nonce, &associated_data(entry_type, sequence), content)
+            .map_err(|_| CuError::from("Failed to encrypt a section of the log."))?;
+        let mut trailer = [0u8; ENCRYPTION_OVERHEAD];
+        trailer[..NONCE_SIZE].copy_from_slice(&nonce);
+        trailer[NONCE_SIZE..].copy_from_slice(&tag);
+        Ok(trailer)
+    }
+
+    /// Decrypts a section sealed by [SectionCipher::seal], trailer included, and returns its content.
+    pub(crate) fn open(
+        &self,
+        entry_type: UnifiedLogType,
+        sequence: u64,
+        mut section: Vec<u8>,
+    ) -> CuResult<Vec<u8>> {
+        if section.len() < ENCRYPTION_OVERHEAD {
+            return Err("An encrypted section of the log is truncated.".into());
+        }
+        let trailer = section.split_off(section.len() - ENCRYPTION_OVERHEAD);
+        let nonce = Nonce::from_slice(&trailer[..NONCE_SIZE]);
+        let tag = Tag::from_slice(&trailer[NONCE_SIZE..]);
+        self.cipher
+            .decrypt_in_place_detached(
+                nonce,
+                &associated_data(entry_type, sequence),
+                &mut section,
+                tag,
+            )
+            .map_err(|_| {
+                CuError::from("Failed to decrypt a section of the log: wrong key or corrupted log.")
+            })?;
+        Ok(section)
+    }
+}
+
+fn associated_data(entry_type: UnifiedLogType, sequence: u64) -> [u8; 9] {
+    let mut data = [0u8; 9];
+    data[0] = entry_type as u8;
+    data[1..].copy_from_slice(&sequence.to_le_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let cipher = SectionCipher::new(&[7; 32]);
+        let mut content = b"some camera footage".to_vec();
+        let trailer = cipher
+            .seal(UnifiedLogType::CopperList, 3, &mut content)
+            .unwrap();
+        assert_ne!(content, b"some camera footage");
+        content.extend_from_slice(&trailer);
+
+        let opened = cipher
+            .open(UnifiedLogType::CopperList, 3, content.clone())
+            .unwrap();
+        assert_eq!(opened, b"some camera footage");
+
+        assert!(cipher
+            .open(UnifiedLogType::StructuredLogLine, 3, content.clone())
+            .is_err());
+        assert!(SectionCipher::new(&[8; 32])
+            .open(UnifiedLogType::CopperList, 3, content.clone())
+            .is_err());
+        // Moved to another position in the log.
+        assert!(cipher
+            .open(UnifiedLogType::CopperList, 4, content.clone())
+            .is_err());
+        content[3] ^= 1;
+        assert!(cipher.open(UnifiedLogType::CopperList, 3, content).is_err());
+    }
+}
@@ -5,17 +5,36 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::slice::from_raw_parts_mut;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{io, mem};
 
-use bincode::config::standard;
 use bincode::decode_from_slice;
-use bincode::encode_into_slice;
 use bincode::error::EncodeError;
+use bincode::{encode_into_slice, encode_to_vec};
 use bincode::{Decode, Encode};
-use cu29_traits::{CuError, CuResult, UnifiedLogType, WriteStream};
+use cu29_traits::lockaudit::{self, LockSite};
+use cu29_traits::{wire_config, CuError, CuResult, UnifiedLogType, WriteStream};
+
+mod encryption;
+mod quota;
+mod signing;
+mod staging;
+pub use encryption::LogKey;
+use encryption::{SectionCipher, ENCRYPTION_OVERHEAD};
+use quota::{reserve_for, state_after_refusal};
+pub use quota::{LogFullPolicy, LogHealth, LogHealthSnapshot, LogState};
+use signing::LogSigner;
+pub use signing::{
+    verify_log, verifying_key, LogSigningKey, LogVerification, LogVerifyingKey,
+    DEFAULT_SIGNATURE_PERIOD,
+};
+pub use staging::{stream_write_staged, StagedStream, StagingSnapshot, StagingStats};
 
 const MAIN_MAGIC: [u8; 4] = [0xB4, 0xA5, 0x50, 0xFF];
 
+/// The magic number of a log with encrypted sections, so a reader not expecting it rejects it.
+const ENCRYPTED_MAIN_MAGIC: [u8; 4] = [0xB4, 0xA5, 0x50, 0xEC];
+
 const SECTION_MAGIC: [u8; 2] = [0xFA, 0x57];
 
 /// The main file header of the datalogger.
@@ -57,26 +76,66 @@ struct MmapStream {
     current_section: SectionHandle,
     current_position: usize,
     minimum_allocation_amount: usize,
+    health: Arc<LogHealth>,
+    /// Set once the log is full, everything logged is dropped from then on.
+    dropping: bool,
 }
 
+/// The lock of the unified logger, taken by all the streams writing to it to allocate their sections.
+pub static LOGGER_LOCK: LockSite = LockSite::new("unified_logger");
+
 impl MmapStream {
     fn new(
         entry_type: UnifiedLogType,
         parent_logger: Arc<Mutex<UnifiedLoggerWrite>>,
         minimum_allocation_amount: usize,
     ) -> Self {
-        let section = parent_logger
-            .lock()
-            .unwrap()
-            .add_section(entry_type, minimum_allocation_amount);
+        let mut logger_guard = lockaudit::lock(&LOGGER_LOCK, &parent_logger).unwrap();
+        let health = logger_guard.health();
+        let section = logger_guard.add_section(entry_type, minimum_allocation_amount);
+        drop(logger_guard);
         Self {
             entry_type,
             parent_logger,
-            current_section: section,
+            dropping: section.is_none(),
+            current_section: section.unwrap_or_default(),
             current_position: 0,
             minimum_allocation_amount,
+            health,
+        }
+    }
+}
+
+impl MmapStream {
+    /// Closes the current section and opens a new one of size bytes, or starts dropping if the log is full.
+    fn renew_section(&mut self, size: usize) {
+        let mut logger_guard = lockaudit::lock(&LOGGER_LOCK, &self.parent_logger).unwrap();
+        logger_guard.flush_section(&mut self.current_section);
+        match logger_guard.add_section(self.entry_type, size) {
+            Some(section) => self.current_section = section,
+            None => {
+                self.current_section = SectionHandle::default();
+                self.dropping = true;
+            }
         }
     }
+
+    /// Writes an object already encoded, in a new section if it does not fit in the current one.
+    fn log_bytes(&mut self, bytes: &[u8]) {
+        if !self.dropping && bytes.len() > self.current_section.get_user_buffer().len() {
+            let size = self
+                .minimum_allocation_amount
+                .max(bytes.len() + MAX_HEADER_SIZE);
+            self.renew_section(size);
+        }
+        if self.dropping {
+            self.health.record_drop();
+            return;
+        }
+        self.current_section.get_user_buffer()[..bytes.len()].copy_from_slice(bytes);
+        self.current_position += bytes.len();
+        self.current_section.used += bytes.len() as u32;
+    }
 }
 
 impl Debug for MmapStream {
@@ -87,8 +146,12 @@ impl Debug for MmapStream {
 
 impl<E: Encode> WriteStream<E> for MmapStream {
     fn log(&mut self, obj: &E) -> CuResult<()> {
+        if self.dropping {
+            self.health.record_drop();
+            return Ok(());
+        }
         let dst = self.current_section.get_user_buffer();
-        let result = encode_into_slice(obj, dst, standard());
+        let result = encode_into_slice(obj, dst, wire_config());
         match result {
             Ok(nb_bytes) => {
                 self.current_position += nb_bytes;
@@ -97,15 +160,15 @@ impl<E: Encode> WriteStream<E> for MmapStream {
             }
             Err(e) => match e {
                 EncodeError::UnexpectedEnd => {
-                    let mut logger_guard = self.parent_logger.lock().unwrap();
-                    logger_guard.flush_section(&mut self.current_section);
-                    self.current_section =
-                        logger_guard.add_section(self.entry_type, self.minimum_allocation_amount);
-
+                    self.renew_section(self.minimum_allocation_amount);
+                    if self.dropping {
+                        self.health.record_drop();
+                        return Ok(());
+                    }
                     let result = encode_into_slice(
                         obj,
                         self.current_section.get_user_buffer(),
-                        standard(),
+                        wire_config(),
                     )
                     .expect(
                         "Failed to encode object in a newly minted section. Unrecoverable failure.",
@@ -123,11 +186,36 @@ impl<E: Encode> WriteStream<E> for MmapStream {
             },
         }
     }
+
+    /// Closes the current section so what was logged so far can be read back, even if the process dies right
+    /// after. It does not wait for long on the logger: a thread panicking while holding it must not deadlock.
+    fn flush(&mut self) -> CuResult<()> {
+        if self.dropping || self.current_section.used == 0 {
+            return Ok(());
+        }
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let mut logger_guard = loop {
+            match self.parent_logger.try_lock() {
+                Ok(guard) => break guard,
+                Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(1)),
+                Err(_) => return Err("The logger is busy, the stream was not flushed.".into()),
+            }
+        };
+        logger_guard.flush_section(&mut self.current_section);
+        match logger_guard.add_section(self.entry_type, self.minimum_allocation_amount) {
+            Some(section) => self.current_section = section,
+            None => {
+                self.current_section = SectionHandle::default();
+                self.dropping = true;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Drop for MmapStream {
     fn drop(&mut self) {
-        let mut logger_guard = self.parent_logger.lock().unwrap();
+        let mut logger_guard = lockaudit::lock(&LOGGER_LOCK, &self.parent_logger).unwrap();
         logger_guard.flush_section(&mut self.current_section);
     }
 }
@@ -153,6 +241,11 @@ pub struct UnifiedLoggerBuilder {
     preallocated_size: Option<usize>,
     write: bool,
     create: bool,
+    encryption_key: Option<LogKey>,
+    signing_key: Option<LogSigningKey>,
+    signature_period: usize,
+    max_size: Option<usize>,
+    full_policy: LogFullPolicy,
 }
 
 impl Default for UnifiedLoggerBuilder {
@@ -168,6 +261,11 @@ impl UnifiedLoggerBuilder {
             preallocated_size: None,
             write: false,
             create: false, // This is the safest default
+            encryption_key: None,
+            signing_key: None,
+            signature_period: DEFAULT_SIGNATURE_PERIOD,
+            max_size: None,
+            full_policy: LogFullPolicy::default(),
         }
     }
 
@@ -192,22 +290,57 @@ impl UnifiedLoggerBuilder {
         self
     }
 
+    /// Encrypts the sections written with this AES-256-GCM key, or decrypts the sections read.
+    pub fn encryption_key(mut self, key: &LogKey) -> Self {
+        self.encryption_key = Some(*key);
+        self
+    }
+
+    /// Signs the sections written with this Ed25519 key, see [verify_log] to check them.
+    pub fn signing_key(mut self, key: &LogSigningKey) -> Self {
+        self.signing_key = Some(*key);
+        self
+    }
+
+    /// The number of sections covered by each signature, [DEFAULT_SIGNATURE_PERIOD] by default.
+    pub fn signature_period(mut self, sections: usize) -> Self {
+        self.signature_period = sections;
+        self
+    }
+
+    /// The maximum size in bytes of all the slabs of the log, it is only limited by the disk by default.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// What the logger keeps once it reaches its max size or the disk is full, see [LogFullPolicy].
+    pub fn full_policy(mut self, policy: LogFullPolicy) -> Self {
+        self.full_policy = policy;
+        self
+    }
+
     pub fn build(self) -> io::Result<UnifiedLogger> {
         let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
 
         if self.write && self.create {
-            let ulw = UnifiedLoggerWrite::new(
+            let mut ulw = UnifiedLoggerWrite::new(
                 &self.file_base_name.unwrap(),
                 self.preallocated_size.unwrap(),
                 page_size,
-            );
+                self.encryption_key.as_ref(),
+                self.signing_key
+                    .map(|key| Box::new(LogSigner::new(&key, self.signature_period))),
+            )?;
+            ulw.max_size = self.max_size;
+            ulw.full_policy = self.full_policy;
 
             Ok(UnifiedLogger::Write(ulw))
         } else {
             let file_path = self.file_base_name.ok_or_else(|| {
                 io::Error::new(io::ErrorKind::InvalidInput, "File path is required")
             })?;
-            let ulr = UnifiedLoggerRead::new(&file_path)?;
+            let ulr = UnifiedLoggerRead::open(&file_path, self.encryption_key.as_ref())?;
             Ok(UnifiedLogger::Read(ulr))
         }
     }
@@ -220,6 +353,9 @@ pub struct UnifiedLoggerRead {
     current_file: File,
     current_slab_index: usize,
     current_reading_position: usize,
+    /// The position in the log of the section at the reading position.
+    sequence: u64,
+    cipher: Option<SectionCipher>,
 }
 
 struct SlabEntry {
@@ -292,7 +428,7 @@ impl SlabEntry {
         // Be sure that the header reflects the actual size of the section.
         section.update_header();
 
-        let _sz = encode_into_slice(&section.section_header, section.buffer, standard())
+        let _sz = encode_into_slice(&section.section_header, section.buffer, wire_config())
             .expect("Failed to encode section header");
 
         let base = self.mmap_buffer.as_ptr() as usize;
@@ -314,18 +450,41 @@ impl SlabEntry {
         (ptr + self.page_size - 1) & !(self.page_size - 1)
     }
 
-    /// The returned slice is section_size or greater.
+    /// Writes every page left for new sections with its own content, so writing the sections does not fault.
+    fn prefault(&mut self) -> usize {
+        let page_size = self.page_size;
+        let start = self
+            .align_to_next_page(self.current_global_position)
+            .min(self.mmap_buffer.len());
+        let left = &mut self.mmap_buffer[start..];
+        for offset in (0..left.len()).step_by(page_size) {
+            let byte: *mut u8 = &mut left[offset];
+            // Volatile so the write of the same value is not optimized away.
+            unsafe { byte.write_volatile(byte.read_volatile()) };
+        }
+        left.len()
+    }
+
+    /// The room left for new sections.
+    fn room_left(&self) -> usize {
+        self.mmap_buffer
+            .len()
+            .saturating_sub(self.align_to_next_page(self.current_global_position))
+    }
+
+    /// The returned slice is section_size or greater. reserve is the room which has to be left after it.
     fn add_section(
         &mut self,
         entry_type: UnifiedLogType,
         requested_section_size: usize,
+        reserve: usize,
     ) -> AllocatedSection {
         // align current_position to the next page
         self.current_global_position = self.align_to_next_page(self.current_global_position);
         let section_size = self.align_to_next_page(requested_section_size) as u32;
 
         // We need to have enough space to store the section in that slab
-        if self.current_global_position + section_size as usize > self.mmap_buffer.len() {
+        if self.current_global_position + section_size as usize + reserve > self.mmap_buffer.len() {
             return AllocatedSection::NoMoreSpace;
         }
 
@@ -339,7 +498,7 @@ impl SlabEntry {
         let nb_bytes = encode_into_slice(
             &section_header,
             &mut self.mmap_buffer[self.current_global_position..],
-            standard(),
+            wire_config(),
         )
         .expect("Failed to encode section header");
         assert!(nb_bytes < self.page_size);
@@ -379,6 +538,9 @@ pub struct SectionHandle {
     section_header: SectionHeader,
     buffer: &'static mut [u8], // This includes the encoded header for end of section patching.
     used: u32,                 // this is the size of the used part of the buffer.
+    sequence: u64,             // the position of the section in the log.
+    // For an encrypted log, the content is written here and only reaches the memory map encrypted.
+    clear: Option<Box<[u8]>>,
 }
 
 // This is for a placeholder to unsure an orderly cleanup as we dodge the borrow checker.
@@ -403,10 +565,33 @@ impl SectionHandle {
             section_header,
             buffer,
             used: 0,
+            sequence: 0,
+            clear: None,
         }
     }
     pub fn get_user_buffer(&mut self) -> &mut [u8] {
-        &mut self.buffer[MAX_HEADER_SIZE + self.used as usize..]
+        match &mut self.clear {
+            Some(clear) => &mut clear[self.used as usize..],
+            None => &mut self.buffer[MAX_HEADER_SIZE + self.used as usize..],
+        }
+    }
+
+    /// Writes the content encrypted to the memory map, followed by the nonce and the tag.
+    fn seal(&mut self, cipher: &SectionCipher) -> CuResult<()> {
+        let Some(mut clear) = self.clear.take() else {
+            return Ok(());
+        };
+        if self.used == 0 {
+            return Ok(());
+        }
+        let used = self.used as usize;
+        let content = &mut clear[..used];
+        let trailer = cipher.seal(self.section_header.entry_type, self.sequence, content)?;
+        self.buffer[MAX_HEADER_SIZE..MAX_HEADER_SIZE + used].copy_from_slice(content);
+        self.buffer[MAX_HEADER_SIZE + used..MAX_HEADER_SIZE + used + ENCRYPTION_OVERHEAD]
+            .copy_from_slice(&trailer);
+        self.used += ENCRYPTION_OVERHEAD as u32;
+        Ok(())
     }
 
     pub fn update_header(&mut self) {
@@ -417,7 +602,7 @@ impl SectionHandle {
         self.section_header.filled_size = self.used;
 
         // FIX ME: This was flushed before and cannot be written back to.
-        // let _sz = encode_into_slice(&self.section_header, &mut self.buffer, standard())
+        // let _sz = encode_into_slice(&self.section_header, &mut self.buffer, wire_config())
         //     .expect("Failed to encode section header");
     }
 }
@@ -432,8 +617,23 @@ pub struct UnifiedLoggerWrite {
     base_file_path: PathBuf,
     /// allocation size for the backing files.
     slab_size: usize,
-    /// current suffix for the backing files.
+    /// suffix of the last backing file created, the front slab or the next one.
     front_slab_suffix: usize,
+    /// the slab allocated ahead, before the front one is full.
+    next_slab: Option<SlabEntry>,
+    /// the maximum size of all the backing files.
+    max_size: Option<usize>,
+    /// what is kept once the log cannot grow.
+    full_policy: LogFullPolicy,
+    /// set once the max size is reached or a backing file could not be allocated.
+    cannot_grow: bool,
+    health: Arc<LogHealth>,
+    /// encrypts the sections if the log is encrypted.
+    cipher: Option<SectionCipher>,
+    /// signs the sections if the log is signed.
+    signer: Option<Box<LogSigner>>,
+    /// the position in the log of the next section.
+    next_sequence: u64,
 }
 
 fn build_slab_path(base_file_path: &PathBuf, slab_index: usize) -> PathBuf {
@@ -447,51 +647,171 @@ fn build_slab_path(base_file_path: &PathBuf, slab_index: usize) -> PathBuf {
     file_path
 }
 
-fn make_slab_file(base_file_path: &PathBuf, slab_size: usize, slab_suffix: usize) -> File {
+/// Creates a slab with its blocks allocated on the disk: a full disk fails here rather than when the memory map is
+/// written to.
+fn make_slab_file(
+    base_file_path: &PathBuf,
+    slab_size: usize,
+    slab_suffix: usize,
+) -> io::Result<File> {
     let file_path = build_slab_path(base_file_path, slab_suffix);
     let file = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .open(&file_path)
-        .unwrap_or_else(|_| panic!("Failed to open file: {}", file_path.display()));
-    file.set_len(slab_size as u64)
-        .expect("Failed to set file length");
-    file
+        .open(&file_path)?;
+    #[cfg(target_os = "linux")]
+    let allocated = {
+        use std::os::fd::AsRawFd;
+        match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, slab_size as libc::off_t) } {
+            0 => Ok(()),
+            errno => Err(io::Error::from_raw_os_error(errno)),
+        }
+    };
+    #[cfg(not(target_os = "linux"))]
+    let allocated = file.set_len(slab_size as u64);
+    if let Err(e) = allocated {
+        drop(file);
+        let _ = std::fs::remove_file(&file_path);
+        return Err(e);
+    }
+    Ok(file)
 }
 
 impl UnifiedLoggerWrite {
-    fn next_slab(&mut self) -> File {
-        self.front_slab_suffix += 1;
+    /// The state of the log, shared with its streams.
+    pub fn health(&self) -> Arc<LogHealth> {
+        self.health.clone()
+    }
+
+    /// Faults in the space left in the log for writing, ie. before a real time loop: with the memory of the process
+    /// locked, the sections are then written without page faults until the next slab. Returns the bytes faulted in.
+    pub fn prefault(&mut self) -> usize {
+        self.front_slab.prefault() + self.next_slab.as_mut().map_or(0, SlabEntry::prefault)
+    }
+
+    fn can_grow(&self) -> bool {
+        self.next_slab.is_some() || !self.cannot_grow
+    }
+
+    fn create_next_slab(&mut self) -> Result<SlabEntry, String> {
+        let index = self.front_slab_suffix + 1;
+        if let Some(max_size) = self.max_size {
+            if (index + 1) * self.slab_size > max_size {
+                return Err(format!("it reached its max size of {} bytes", max_size));
+            }
+        }
+        let file = make_slab_file(&self.base_file_path, self.slab_size, index)
+            .map_err(|e| format!("the slab {} could not be allocated: {}", index, e))?;
+        self.front_slab_suffix = index;
+        Ok(SlabEntry::new(file, self.front_slab.page_size))
+    }
+
+    /// The slab to continue in, None if the log cannot grow.
+    fn take_next_slab(&mut self) -> Option<SlabEntry> {
+        if let Some(slab) = self.next_slab.take() {
+            return Some(slab);
+        }
+        if self.cannot_grow {
+            return None;
+        }
+        match self.create_next_slab() {
+            Ok(slab) => Some(slab),
+            Err(reason) => {
+                self.stop_growing(reason);
+                None
+            }
+        }
+    }
+
+    /// Allocates the next slab when the front one is getting full, to find out the log cannot grow while there is
+    /// still room left for the channels kept, see [LogFullPolicy].
+    fn prepare_next_slab(&mut self) {
+        let largest_reserve = reserve_for(
+            self.full_policy,
+            UnifiedLogType::CopperList,
+            self.slab_size,
+            self.front_slab.page_size,
+        );
+        if self.next_slab.is_some()
+            || self.cannot_grow
+            || self.front_slab.room_left() >= 2 * largest_reserve
+        {
+            return;
+        }
+        match self.create_next_slab() {
+            Ok(slab) => self.next_slab = Some(slab),
+            Err(reason) => self.stop_growing(reason),
+        }
+    }
 
-        make_slab_file(&self.base_file_path, self.slab_size, self.front_slab_suffix)
+    fn stop_growing(&mut self, reason: String) {
+        self.cannot_grow = true;
+        self.health.stop_growing(reason);
     }
 
-    fn new(base_file_path: &PathBuf, slab_size: usize, page_size: usize) -> Self {
-        let file = make_slab_file(base_file_path, slab_size, 0);
+    fn new(
+        base_file_path: &PathBuf,
+        slab_size: usize,
+        page_size: usize,
+        encryption_key: Option<&LogKey>,
+        signer: Option<Box<LogSigner>>,
+    ) -> io::Result<Self> {
+        let file = make_slab_file(base_file_path, slab_size, 0)?;
         let mut front_slab = SlabEntry::new(file, page_size);
 
         // This is the first slab so add the main header.
         let main_header = MainHeader {
-            magic: MAIN_MAGIC,
+            magic: if encryption_key.is_some() {
+                ENCRYPTED_MAIN_MAGIC
+            } else {
+                MAIN_MAGIC
+            },
             first_section_offset: page_size as u16,
             page_size: page_size as u16,
         };
-        let nb_bytes = encode_into_slice(&main_header, &mut front_slab.mmap_buffer[..], standard())
-            .expect("Failed to encode main header");
+        let nb_bytes =
+            encode_into_slice(&main_header, &mut front_slab.mmap_buffer[..], wire_config())
+                .expect("Failed to encode main header");
         assert!(nb_bytes < page_size);
         front_slab.current_global_position = page_size; // align to the next page
 
-        Self {
+        Ok(Self {
             front_slab,
             back_slabs: Vec::new(),
+            next_slab: None,
             base_file_path: base_file_path.clone(),
             slab_size,
             front_slab_suffix: 0,
-        }
+            cipher: encryption_key.map(SectionCipher::new),
+            signer,
+            next_sequence: 0,
+            max_size: None,
+            full_policy: LogFullPolicy::default(),
+            cannot_grow: false,
+            health: Arc::new(LogHealth::default()),
+        })
     }
 
     pub fn flush_section(&mut self, section: &mut SectionHandle) {
+        // The placeholder of a stream dropping its entries.
+        if section.buffer.is_empty() {
+            return;
+        }
+        if let Some(cipher) = &self.cipher {
+            section.seal(cipher).expect("Failed to encrypt section");
+        }
+        let sign = self.signer.as_mut().is_some_and(|signer| {
+            let content = &section.buffer[MAX_HEADER_SIZE..MAX_HEADER_SIZE + section.used as usize];
+            signer.record(section.sequence, section.section_header.entry_type, content)
+        });
+        self.flush_to_slab(section);
+        if sign {
+            self.write_signature(false);
+        }
+    }
+
+    fn flush_to_slab(&mut self, section: &mut SectionHandle) {
         for slab in self.back_slabs.iter_mut() {
             if slab.is_it_my_section(section) {
                 slab.flush_section(section);
@@ -501,6 +821,28 @@ impl UnifiedLoggerWrite {
         self.front_slab.flush_section(section);
     }
 
+    /// Writes a Signature section for the sections closed since the previous one, the last one is written even
+    /// without them to mark the end of the log.
+    fn write_signature(&mut self, last: bool) {
+        let Some(signer) = self
+            .signer
+            .as_mut()
+            .filter(|signer| last || signer.has_pending())
+        else {
+            return;
+        };
+        let record =
+            encode_to_vec(signer.sign(last), wire_config()).expect("Failed to encode signature");
+        let Some(mut section) =
+            self.allocate_section(UnifiedLogType::Signature, record.len() + MAX_HEADER_SIZE)
+        else {
+            return;
+        };
+        section.get_user_buffer()[..record.len()].copy_from_slice(&record);
+        section.used = record.len() as u32;
+        self.flush_to_slab(&mut section);
+    }
+
     fn garbage_collect_backslabs(&mut self) {
         self.back_slabs.retain_mut(|slab| {
             if slab.sections_offsets_in_flight.is_empty() {
@@ -512,28 +854,61 @@ impl UnifiedLoggerWrite {
         });
     }
 
-    /// The returned slice is section_size or greater.
+    /// The returned slice is section_size or greater, None if the log is full.
     fn add_section(
         &mut self,
         entry_type: UnifiedLogType,
         requested_section_size: usize,
-    ) -> SectionHandle {
+    ) -> Option<SectionHandle> {
+        let mut section = self.allocate_section(entry_type, requested_section_size)?;
+        if self.cipher.is_some() {
+            // The same room for the content as a clear section, the trailer is on top of it.
+            let len = section.buffer.len() - MAX_HEADER_SIZE - ENCRYPTION_OVERHEAD;
+            section.clear = Some(vec![0; len].into_boxed_slice());
+        }
+        Some(section)
+    }
+
+    fn allocate_section(
+        &mut self,
+        entry_type: UnifiedLogType,
+        requested_section_size: usize,
+    ) -> Option<SectionHandle> {
+        let requested_section_size = match self.cipher {
+            Some(_) => requested_section_size + ENCRYPTION_OVERHEAD,
+            None => requested_section_size,
+        };
         self.garbage_collect_backslabs(); // Take the opportunity to keep up and close stale back slabs.
 
-        let maybe_section = self
-            .front_slab
-            .add_section(entry_type, requested_section_size);
+        // Once the log cannot grow, the room left is kept for the channels with the highest priority.
+        let reserve = if self.can_grow() {
+            0
+        } else {
+            reserve_for(
+                self.full_policy,
+                entry_type,
+                self.slab_size,
+                self.front_slab.page_size,
+            )
+        };
+        let maybe_section =
+            self.front_slab
+                .add_section(entry_type, requested_section_size, reserve);
 
-        match maybe_section {
+        let mut section = match maybe_section {
             AllocatedSection::NoMoreSpace => {
+                let Some(new_slab) = self.take_next_slab() else {
+                    self.health
+                        .degrade(state_after_refusal(self.full_policy, entry_type));
+                    return None;
+                };
                 // move the front slab to the back slab.
-                let new_slab = SlabEntry::new(self.next_slab(), self.front_slab.page_size);
                 // keep the slab until all its sections has been flushed.
                 self.back_slabs
                     .push(mem::replace(&mut self.front_slab, new_slab));
                 match self
                     .front_slab
-                    .add_section(entry_type, requested_section_size)
+                    .add_section(entry_type, requested_section_size, 0)
                 {
                     AllocatedSection::NoMoreSpace => {
                         panic!("Failed to allocate a section in a new slab");
@@ -542,19 +917,38 @@ impl UnifiedLoggerWrite {
                 }
             }
             AllocatedSection::Section(section) => section,
-        }
+        };
+        section.sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.prepare_next_slab();
+        Some(section)
     }
 }
 
 impl Drop for UnifiedLoggerWrite {
     fn drop(&mut self) {
-        let mut section = self.add_section(UnifiedLogType::LastEntry, 80); // TODO: determine that exactly
-        self.front_slab.flush_section(&mut section);
+        self.write_signature(true);
+        if let Some(mut section) = self.add_section(UnifiedLogType::LastEntry, 80) {
+            // TODO: determine that exactly
+            self.front_slab.flush_section(&mut section);
+        }
         self.garbage_collect_backslabs();
+        // The next slab was allocated ahead but never used.
+        if let Some(slab) = self.next_slab.take() {
+            drop(slab);
+            let _ = std::fs::remove_file(build_slab_path(
+                &self.base_file_path,
+                self.front_slab_suffix,
+            ));
+        }
     }
 }
 
-fn open_slab_index(base_file_path: &PathBuf, slab_index: usize) -> io::Result<(File, Mmap, u16)> {
+/// Opens a slab and returns the offset of its first section, and for the first slab if the log is encrypted.
+fn open_slab_index(
+    base_file_path: &PathBuf,
+    slab_index: usize,
+) -> io::Result<(File, Mmap, u16, bool)> {
     let mut options = OpenOptions::new();
     let options = options.read(true);
 
@@ -562,12 +956,14 @@ fn open_slab_index(base_file_path: &PathBuf, slab_index: usize) -> io::Result<(F
     let file = options.open(file_path)?;
     let mmap = unsafe { Mmap::map(&file) }?;
     let mut prolog = 0u16;
+    let mut encrypted = false;
     if slab_index == 0 {
         let main_header: MainHeader;
         let _read: usize;
         (main_header, _read) =
-            decode_from_slice(&mmap[..], standard()).expect("Failed to decode main header");
-        if main_header.magic != MAIN_MAGIC {
+            decode_from_slice(&mmap[..], wire_config()).expect("Failed to decode main header");
+        encrypted = main_header.magic == ENCRYPTED_MAIN_MAGIC;
+        if main_header.magic != MAIN_MAGIC && !encrypted {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Invalid magic number in main header",
@@ -575,25 +971,50 @@ fn open_slab_index(base_file_path: &PathBuf, slab_index: usize) -> io::Result<(F
         }
         prolog = main_header.first_section_offset;
     }
-    Ok((file, mmap, prolog))
+    Ok((file, mmap, prolog, encrypted))
 }
 
 impl UnifiedLoggerRead {
     pub fn new(base_file_path: &PathBuf) -> io::Result<Self> {
-        let (file, mmap, prolog) = open_slab_index(base_file_path, 0)?;
+        Self::open(base_file_path, None)
+    }
 
-        Ok(Self {
+    /// Opens a log, encrypted or not. The key is required to read an encrypted log.
+    fn open(base_file_path: &PathBuf, encryption_key: Option<&LogKey>) -> io::Result<Self> {
+        let (mut reader, encrypted) = Self::open_raw(base_file_path)?;
+        reader.cipher = match (encrypted, encryption_key) {
+            (true, Some(key)) => Some(SectionCipher::new(key)),
+            (true, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The log is encrypted, a key is required to read it",
+                ))
+            }
+            (false, _) => None,
+        };
+        Ok(reader)
+    }
+
+    /// Opens a log to read its sections as they are on disk, and tells if it is encrypted.
+    fn open_raw(base_file_path: &PathBuf) -> io::Result<(Self, bool)> {
+        let (file, mmap, prolog, encrypted) = open_slab_index(base_file_path, 0)?;
+
+        let reader = Self {
             base_file_path: base_file_path.clone(),
             current_file: file,
             current_mmap_buffer: mmap,
             current_slab_index: 0,
             current_reading_position: prolog as usize,
-        })
+            sequence: 0,
+            cipher: None,
+        };
+        Ok((reader, encrypted))
     }
 
     fn next_slab(&mut self) -> io::Result<()> {
         self.current_slab_index += 1;
-        let (file, mmap, prolog) = open_slab_index(&self.base_file_path, self.current_slab_index)?;
+        let (file, mmap, prolog, _) =
+            open_slab_index(&self.base_file_path, self.current_slab_index)?;
         self.current_file = file;
         self.current_mmap_buffer = mmap;
         self.current_reading_position = prolog as usize;
@@ -630,12 +1051,36 @@ impl UnifiedLoggerRead {
             if header.entry_type == datalogtype {
                 let result = Some(self.read_section_content(&header)?);
                 self.current_reading_position += header.section_size as usize;
+                self.sequence += 1;
                 return Ok(result);
             }
 
             // Keep reading until we find the requested type
             self.current_reading_position += header.section_size as usize;
+            self.sequence += 1;
+        }
+    }
+
+    /// Reads the next section of any type as it is on disk, None at the end of the log.
+    fn read_raw_section(&mut self) -> CuResult<Option<(UnifiedLogType, Vec<u8>)>> {
+        if self.current_reading_position >= self.current_mmap_buffer.len() {
+            self.next_slab().map_err(|e| {
+                CuError::new_with_cause("Failed to read next slab, is the log complete?", e)
+            })?;
         }
+        let header = self.read_section_header()?;
+        if header.entry_type == UnifiedLogType::LastEntry {
+            return Ok(None);
+        }
+        let start_of_data = self.current_reading_position + MAX_HEADER_SIZE;
+        let content = self
+            .current_mmap_buffer
+            .get(start_of_data..start_of_data + header.filled_size as usize)
+            .ok_or_else(|| CuError::from("Section past the end of the slab"))?
+            .to_vec();
+        self.current_reading_position += header.section_size as usize;
+        self.sequence += 1;
+        Ok(Some((header.entry_type, content)))
     }
 
     /// Reads the section from the section header pos.
@@ -663,16 +1108,24 @@ impl UnifiedLoggerRead {
             &self.current_mmap_buffer[start_of_data..start_of_data + header.filled_size as usize],
         );
 
-        Ok(section)
+        match &self.cipher {
+            // The signatures are not encrypted.
+            Some(cipher)
+                if !section.is_empty() && header.entry_type != UnifiedLogType::Signature =>
+            {
+                cipher.open(header.entry_type, self.sequence, section)
+            }
+            _ => Ok(section),
+        }
     }
 
     fn read_section_header(&mut self) -> CuResult<SectionHeader> {
         let section_header: SectionHeader;
         (section_header, _) = decode_from_slice(
             &self.current_mmap_buffer[self.current_reading_position..],
-            standard(),
+            wire_config(),
         )
-        .expect("Failed to decode section header");
+        .map_err(|e| CuError::new_with_cause("Failed to decode section header", e))?;
         if section_header.magic != SECTION_MAGIC {
             return Err("Invalid magic number in section header".into());
         }
@@ -735,7 +1188,7 @@ impl Read for UnifiedLoggerIOReader {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bincode::decode_from_reader;
+    use bincode::{decode_from_reader, decode_from_std_read};
     use libc::{sysconf, _SC_PAGESIZE};
     use std::io::BufReader;
     use std::path::PathBuf;
@@ -800,6 +1253,24 @@ mod tests {
         //);
     }
 
+    #[test]
+    fn test_prefault() {
+        let tmp_dir = TempDir::new().expect("could not create a tmp dir");
+        let (logger, _) = make_a_logger(&tmp_dir, LARGE_SLAB);
+        let mut logger = logger.lock().unwrap();
+        let left = logger.front_slab.room_left();
+        assert_eq!(logger.prefault(), left);
+        // The content is left as it is.
+        assert!(
+            logger.front_slab.mmap_buffer[logger.front_slab.current_global_position..]
+                .iter()
+                .all(|&byte| byte == 0)
+        );
+        assert!(logger
+            .add_section(UnifiedLogType::CopperList, 1024)
+            .is_some());
+    }
+
     #[test]
     fn test_one_section_self_cleaning() {
         let tmp_dir = TempDir::new().expect("could not create a tmp dir");
@@ -944,9 +1415,9 @@ mod tests {
         let section = section.unwrap();
 
         let mut reader = BufReader::new(&section[..]);
-        let v1: u32 = decode_from_reader(&mut reader, standard()).unwrap();
-        let v2: u32 = decode_from_reader(&mut reader, standard()).unwrap();
-        let v3: u32 = decode_from_reader(&mut reader, standard()).unwrap();
+        let v1: u32 = decode_from_reader(&mut reader, wire_config()).unwrap();
+        let v2: u32 = decode_from_reader(&mut reader, wire_config()).unwrap();
+        let v3: u32 = decode_from_reader(&mut reader, wire_config()).unwrap();
         assert_eq!(v1, 1);
         assert_eq!(v2, 2);
         assert_eq!(v3, 3);
@@ -1000,8 +1471,10 @@ mod tests {
         let section = section.unwrap();
 
         let mut reader = BufReader::new(&section[..]);
-        let cl0: CopperList<(u32, u32, u32)> = decode_from_reader(&mut reader, standard()).unwrap();
-        let cl1: CopperList<(u32, u32, u32)> = decode_from_reader(&mut reader, standard()).unwrap();
+        let cl0: CopperList<(u32, u32, u32)> =
+            decode_from_reader(&mut reader, wire_config()).unwrap();
+        let cl1: CopperList<(u32, u32, u32)> =
+            decode_from_reader(&mut reader, wire_config()).unwrap();
         assert_eq!(cl0.payload.1, 2);
         assert_eq!(cl1.payload.2, 6);
     }
@@ -1045,7 +1518,7 @@ mod tests {
             let mut reader = BufReader::new(&section[..]);
             loop {
                 let maybe_cl: Result<CopperList<(u32, u32, u32)>, _> =
-                    decode_from_reader(&mut reader, standard());
+                    decode_from_reader(&mut reader, wire_config());
                 if maybe_cl.is_ok() {
                     total_readback += 1;
                 } else {
@@ -1055,4 +1528,359 @@ mod tests {
         }
         assert_eq!(total_readback, 10000);
     }
+
+    #[test]
+    fn test_staged_stream_end2end() {
+        let tmp_dir = TempDir::new().expect("could not create a tmp dir");
+        let (logger, f) = make_a_logger(&tmp_dir, SMALL_SLAB);
+        let saturations = Arc::new(Mutex::new(0));
+        {
+            let reported = saturations.clone();
+            // Small buffers so they are swapped a lot.
+            let mut stream =
+                stream_write_staged(logger.clone(), UnifiedLogType::CopperList, 1024, 256)
+                    .on_saturation(move |_| *reported.lock().unwrap() += 1);
+            let stats = stream.stats();
+            for i in 0..10000u32 {
+                let cl = CopperList {
+                    state: CopperListStateMock::Free,
+                    payload: (i, 2u32, 3u32),
+                };
+                stream.log(&cl).unwrap();
+            }
+            WriteStream::<CopperList<(u32, u32, u32)>>::flush(&mut stream).unwrap();
+            let snapshot = stats.snapshot();
+            assert_eq!(snapshot.capacity, 256);
+            assert!(!snapshot.in_flight);
+            assert_eq!(*saturations.lock().unwrap(), snapshot.saturations);
+        }
+        drop(logger);
+
+        let UnifiedLogger::Read(dl) = UnifiedLoggerBuilder::new()
+            .file_base_name(&f)
+            .build()
+            .expect("Failed to build logger")
+        else {
+            panic!("Failed to build logger");
+        };
+        let mut reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::CopperList);
+        for i in 0..10000u32 {
+            let cl: CopperList<(u32, u32, u32)> =
+                decode_from_std_read(&mut reader, wire_config()).unwrap();
+            assert_eq!(cl.payload.0, i);
+        }
+    }
+
+    #[test]
+    fn test_full_log_keeps_text_logs() {
+        let tmp_dir = TempDir::new().expect("could not create a tmp dir");
+        let file_path = tmp_dir.path().join("test.bin");
+        let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+            .write(true)
+            .create(true)
+            .file_base_name(&file_path)
+            .preallocated_size(LARGE_SLAB)
+            .max_size(LARGE_SLAB)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let health = logger.health();
+        let logger = Arc::new(Mutex::new(logger));
+        {
+            let mut text =
+                stream_write::<[u8; 32]>(logger.clone(), UnifiedLogType::StructuredLogLine, 1024);
+            let mut copperlists =
+                stream_write::<(u32, [u8; 32])>(logger.clone(), UnifiedLogType::CopperList, 1024);
+            for i in 0..10000u32 {
+                copperlists.log(&(i, [1u8; 32])).unwrap();
+            }
+            assert_eq!(health.state(), LogState::PayloadsDropped);
+            let dropped = health.snapshot().dropped;
+            assert!(dropped > 0);
+
+            text.log(&[2u8; 32]).unwrap();
+            assert_eq!(health.snapshot().dropped, dropped);
+            for _ in 0..10000 {
+                text.log(&[2u8; 32]).unwrap();
+            }
+            let snapshot = health.snapshot();
+            assert_eq!(snapshot.state, LogState::Full);
+            assert!(snapshot.reason.unwrap().contains("max size"));
+        }
+        drop(logger);
+        assert!(!build_slab_path(&file_path, 1).exists());
+
+        // The end of the log made it.
+        let UnifiedLogger::Read(mut dl) = UnifiedLoggerBuilder::new()
+            .file_base_name(&file_path)
+            .build()
+            .expect("Failed to build logger")
+        else {
+            panic!("Failed to build logger");
+        };
+        let mut text_sections = 0;
+        while dl
+            .read_next_section_type(UnifiedLogType::StructuredLogLine)
+            .unwrap()
+            .is_some()
+        {
+            text_sections += 1;
+        }
+        assert!(text_sections > 1);
+    }
+
+    #[test]
+    fn test_encrypted_end2end() {
+        let tmp_dir = TempDir::new().expect("could not create a tmp dir");
+        let f = tmp_dir.path().join("test.bin");
+        let key: LogKey = [42; 32];
+        let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+            .write(true)
+            .create(true)
+            .file_base_name(&f)
+            .preallocated_size(SMALL_SLAB)
+            .encryption_key(&key)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let logger = Arc::new(Mutex::new(logger));
+        {
+            let mut stream = stream_write(logger.clone(), UnifiedLogType::CopperList, 1024);
+            for i in 0..10000u32 {
+                let cl = CopperList {
+                    state: CopperListStateMock::Free,
+                    payload: (i, 0xC0FFEEu32, 3u32),
+                };
+                stream.log(&cl).unwrap();
+            }
+        }
+        drop(logger);
+
+        // Nothing in clear on the disk.
+        let slab = std::fs::read(build_slab_path(&f, 0)).unwrap();
+        let marker = bincode::encode_to_vec(0xC0FFEEu32, wire_config()).unwrap();
+        assert!(!slab.windows(marker.len()).any(|w| w == marker));
+
+        let no_key = UnifiedLoggerBuilder::new().file_base_name(&f).build();
+        assert!(no_key.is_err());
+
+        let UnifiedLogger::Read(dl) = UnifiedLoggerBuilder::new()
+            .file_base_name(&f)
+            .encryption_key(&key)
+            .build()
+            .expect("Failed to build logger")
+        else {
+            panic!("Failed to build logger");
+        };
+        let mut reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::CopperList);
+        for i in 0..10000u32 {
+            let cl: CopperList<(u32, u32, u32)> =
+                decode_from_std_read(&mut reader, wire_config()).unwrap();
+            assert_eq!(cl.payload, (i, 0xC0FFEE, 3));
+        }
+
+        let UnifiedLogger::Read(mut dl) = UnifiedLoggerBuilder::new()
+            .file_base_name(&f)
+            .encryption_key(&[0; 32])
+            .build()
+            .expect("Failed to build logger")
+        else {
+            panic!("Failed to build logger");
+        };
+        assert!(dl
+            .read_next_section_type(UnifiedLogType::CopperList)
+            .is_err());
+    }
+
+    #[test]
+    fn test_encrypted_reordered() {
+        let tmp_dir = TempDir::new().expect("could not create a tmp dir");
+        let f = tmp_dir.path().join("test.bin");
+        let key: LogKey = [42; 32];
+        let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+            .write(true)
+            .create(true)
+            .file_base_name(&f)
+            .preallocated_size(SMALL_SLAB)
+            .encryption_key(&key)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let logger = Arc::new(Mutex::new(logger));
+        {
+            let mut stream = stream_write(logger.clone(), UnifiedLogType::CopperList, 1024);
+            for i in 0..1000u32 {
+                stream.log(&i).unwrap();
+            }
+        }
+        drop(logger);
+
+        // Swap the first two sections on the disk.
+        let (mut reader, _) = UnifiedLoggerRead::open_raw(&f).unwrap();
+        let first = reader.current_reading_position;
+        reader.read_raw_section().unwrap().unwrap();
+        let second = reader.current_reading_position;
+        reader.read_raw_section().unwrap().unwrap();
+        let end = reader.current_reading_position;
+        assert_eq!(second - first, end - second);
+        drop(reader);
+        let path = build_slab_path(&f, 0);
+        let mut slab = std::fs::read(&path).unwrap();
+        slab[first..end].rotate_left(second - first);
+        std::fs::write(&path, slab).unwrap();
+
+        let UnifiedLogger::Read(mut dl) = UnifiedLoggerBuilder::new()
+            .file_base_name(&f)
+            .encryption_key(&key)
+            .build()
+            .expect("Failed to build logger")
+        else {
+            panic!("Failed to build logger");
+        };
+        assert!(dl
+            .read_next_section_type(UnifiedLogType::CopperList)
+            .is_err());
+    }
+
+    #[test]
+    fn test_signed_end2end() {
+        let tmp_dir = TempDir::new().expect("could not create a tmp dir");
+        let f = tmp_dir.path().join("test.bin");
+        let signing_key: LogSigningKey = [7; 32];
+        let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+            .write(true)
+            .create(true)
+            .file_base_name(&f)
+            .preallocated_size(SMALL_SLAB)
+            .encryption_key(&[42; 32])
+            .signing_key(&signing_key)
+            .signature_period(4)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let logger = Arc::new(Mutex::new(logger));
+        {
+            let mut stream = stream_write(logger.clone(), UnifiedLogType::CopperList, 1024);
+            for i in 0..10000u32 {
+                let cl = CopperList {
+                    state: CopperListStateMock::Free,
+                    payload: (i, 2u32, 3u32),
+                };
+                stream.log(&cl).unwrap();
+            }
+        }
+        drop(logger);
+
+        let public_key = verifying_key(&signing_key);
+        let LogVerification::Intact {
+            sections,
+            signatures,
+        } = verify_log(&f, &public_key).unwrap()
+        else {
+            panic!("The log should be intact");
+        };
+        assert!(signatures > 1);
+        assert!(sections > signatures * 4);
+        // Nothing is covered by a valid signature.
+        assert_eq!(
+            verify_log(&f, &verifying_key(&[8; 32])).unwrap(),
+            LogVerification::Broken {
+                section: 0,
+                reason: "not signed".to_string()
+            }
+        );
+
+        // The signatures are skipped when reading the data.
+        let UnifiedLogger::Read(dl) = UnifiedLoggerBuilder::new()
+            .file_base_name(&f)
+            .encryption_key(&[42; 32])
+            .build()
+            .expect("Failed to build logger")
+        else {
+            panic!("Failed to build logger");
+        };
+        let mut reader = UnifiedLoggerIOReader::new(dl, UnifiedLogType::CopperList);
+        for i in 0..10000u32 {
+            let cl: CopperList<(u32, u32, u32)> =
+                decode_from_std_read(&mut reader, wire_config()).unwrap();
+            assert_eq!(cl.payload.0, i);
+        }
+
+        // Tamper with the content of the first section.
+        let page_size = unsafe { sysconf(_SC_PAGESIZE) } as usize;
+        let slab_path = build_slab_path(&f, 0);
+        let mut slab = std::fs::read(&slab_path).unwrap();
+        slab[page_size + MAX_HEADER_SIZE + 10] ^= 1;
+        std::fs::write(&slab_path, slab).unwrap();
+        assert_eq!(
+            verify_log(&f, &public_key).unwrap(),
+            LogVerification::Broken {
+                section: 0,
+                reason: "does not match its signature".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_signed_log_truncated() {
+        let tmp_dir = TempDir::new().expect("could not create a tmp dir");
+        let f = tmp_dir.path().join("test.bin");
+        let signing_key: LogSigningKey = [7; 32];
+        let UnifiedLogger::Write(logger) = UnifiedLoggerBuilder::new()
+            .write(true)
+            .create(true)
+            .file_base_name(&f)
+            .preallocated_size(SMALL_SLAB)
+            .signing_key(&signing_key)
+            .signature_period(4)
+            .build()
+            .expect("Failed to create logger")
+        else {
+            panic!("Failed to create logger")
+        };
+        let logger = Arc::new(Mutex::new(logger));
+        {
+            let mut stream = stream_write(logger.clone(), UnifiedLogType::CopperList, 1024);
+            for i in 0..2000u32 {
+                stream.log(&(i, 2u32, 3u32)).unwrap();
+            }
+        }
+        drop(logger);
+        let public_key = verifying_key(&signing_key);
+        assert!(matches!(
+            verify_log(&f, &public_key).unwrap(),
+            LogVerification::Intact { .. }
+        ));
+
+        // Cut the log right after its first Signature section, ending it there.
+        let (mut reader, _) = UnifiedLoggerRead::open_raw(&f).unwrap();
+        while let Some((entry_type, _)) = reader.read_raw_section().unwrap() {
+            if entry_type == UnifiedLogType::Signature {
+                break;
+            }
+        }
+        let slab_path = build_slab_path(&f, reader.current_slab_index);
+        let position = reader.current_reading_position;
+        drop(reader);
+        let end = SectionHeader {
+            entry_type: UnifiedLogType::LastEntry,
+            ..SectionHeader::default()
+        };
+        let end = encode_to_vec(&end, wire_config()).unwrap();
+        let mut slab = std::fs::read(&slab_path).unwrap();
+        slab[position..position + end.len()].copy_from_slice(&end);
+        std::fs::write(&slab_path, slab).unwrap();
+        assert_eq!(
+            verify_log(&f, &public_key).unwrap(),
+            LogVerification::Truncated { sections: 5 }
+        );
+    }
 }
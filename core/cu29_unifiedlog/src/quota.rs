@@ -0,0 +1,132 @@
+//! What the logger does once it cannot grow anymore: its max size is reached or the disk is full.
+//!
+//! The logger allocates the next slab while the current one still has some room left, so it finds out early and
+//! keeps this room for the channels that matter most. The lowest priority channels stop first, the streams
+//! silently drop what they are given instead of erroring out of the control loop: the copper lists, the bulk of
+//! the log, go first, then the text logs and the annotations. The end of the log (signatures, summary and last
+//! entry) goes last so the log can still be read and verified.
+
+use cu29_traits::UnifiedLogType;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+/// Which channels the logger keeps once it cannot grow anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFullPolicy {
+    /// Stops logging the copper lists first and keeps the text logs and the annotations as long as possible.
+    #[default]
+    KeepTextLogs,
+    /// Stops logging everything but the end of the log as soon as it is full.
+    StopLogging,
+}
+
+/// How much of the log is still written, it only gets worse during a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogState {
+    #[default]
+    Nominal,
+    /// The log cannot grow anymore, what is left of the last slab is kept for the most important channels.
+    Degraded,
+    /// The copper lists are dropped.
+    PayloadsDropped,
+    /// Everything but the end of the log is dropped.
+    Full,
+}
+
+impl LogState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogState::Nominal,
+            1 => LogState::Degraded,
+            2 => LogState::PayloadsDropped,
+            _ => LogState::Full,
+        }
+    }
+}
+
+impl Display for LogState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            LogState::Nominal => "nominal",
+            LogState::Degraded => "degraded, it cannot grow anymore",
+            LogState::PayloadsDropped => "full, the copper lists are not logged anymore",
+            LogState::Full => "full, nothing is logged anymore",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// The state of the log shared by the logger and its streams, for the runtime to report it.
+#[derive(Debug, Default)]
+pub struct LogHealth {
+    state: AtomicU8,
+    dropped: AtomicU64,
+    reason: OnceLock<String>,
+}
+
+/// A snapshot of [LogHealth].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogHealthSnapshot {
+    pub state: LogState,
+    /// Number of entries the streams dropped.
+    pub dropped: u64,
+    /// Why the log cannot grow anymore.
+    pub reason: Option<String>,
+}
+
+impl LogHealth {
+    pub fn state(&self) -> LogState {
+        LogState::from_u8(self.state.load(Ordering::Relaxed))
+    }
+
+    pub fn snapshot(&self) -> LogHealthSnapshot {
+        LogHealthSnapshot {
+            state: self.state(),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            reason: self.reason.get().cloned(),
+        }
+    }
+
+    pub(crate) fn degrade(&self, state: LogState) {
+        self.state.fetch_max(state as u8, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stop_growing(&self, reason: String) {
+        let _ = self.reason.set(reason);
+        self.degrade(LogState::Degraded);
+    }
+
+    pub(crate) fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The room a channel needs to be left in the last slab to get a new section once the log cannot grow.
+pub(crate) fn reserve_for(
+    policy: LogFullPolicy,
+    entry_type: UnifiedLogType,
+    slab_size: usize,
+    page_size: usize,
+) -> usize {
+    let end_of_log = 4 * page_size;
+    match (policy, entry_type) {
+        (
+            _,
+            UnifiedLogType::LastEntry
+            | UnifiedLogType::Signature
+            | UnifiedLogType::Summary
+            | UnifiedLogType::Schema,
+        ) => 0,
+        (LogFullPolicy::KeepTextLogs, UnifiedLogType::CopperList) => slab_size / 8 + end_of_log,
+        _ => end_of_log,
+    }
+}
+
+/// What a refused section says about the log.
+pub(crate) fn state_after_refusal(policy: LogFullPolicy, entry_type: UnifiedLogType) -> LogState {
+    match (policy, entry_type) {
+        (LogFullPolicy::KeepTextLogs, UnifiedLogType::CopperList) => LogState::PayloadsDropped,
+        _ => LogState::Full,
+    }
+}
@@ -0,0 +1,190 @@
+//! Tamper evidence for the log: every closed section is hashed with its position in the log, and every few
+//! sections a Signature section lists those hashes, chained to the previous Signature section and signed
+//! with Ed25519. Modifying, swapping or replaying a section breaks the chain from there. The last Signature
+//! section, written when the log is closed, is marked as such so a log cut after any other one is detected.
+
+use crate::UnifiedLoggerRead;
+use bincode::{decode_from_slice, Decode, Encode};
+use cu29_traits::{wire_config, CuResult, UnifiedLogType};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// The secret Ed25519 key signing the log.
+pub type LogSigningKey = [u8; 32];
+
+/// The public Ed25519 key checking the signatures of the log.
+pub type LogVerifyingKey = [u8; 32];
+
+/// The number of sections covered by each signature by default.
+pub const DEFAULT_SIGNATURE_PERIOD: usize = 16;
+
+type Hash = [u8; 32];
+
+/// The public key matching a signing key.
+pub fn verifying_key(key: &LogSigningKey) -> LogVerifyingKey {
+    SigningKey::from_bytes(key).verifying_key().to_bytes()
+}
+
+/// The hash of a section as it is on disk, encrypted or not, at its position in the log.
+fn section_digest(sequence: u64, entry_type: UnifiedLogType, content: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(sequence.to_le_bytes());
+    hasher.update([entry_type as u8]);
+    hasher.update((content.len() as u64).to_le_bytes());
+    hasher.update(content);
+    hasher.finalize().into()
+}
+
+fn chain(previous: &Hash, sections: &[(u64, Hash)], last: bool) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    for (sequence, digest) in sections {
+        hasher.update(sequence.to_le_bytes());
+        hasher.update(digest);
+    }
+    hasher.update([last as u8]);
+    hasher.finalize().into()
+}
+
+/// The content of a Signature section.
+#[derive(Encode, Decode)]
+pub(crate) struct SignatureRecord {
+    /// The position in the log and the hash of the sections closed since the previous signature.
+    sections: Vec<(u64, Hash)>,
+    /// Written when the log is closed, nothing follows it.
+    last: bool,
+    /// The signature of the chained hash.
+    signature: [u8; 64],
+}
+
+pub(crate) struct LogSigner {
+    key: SigningKey,
+    period: usize,
+    chain: Hash,
+    pending: Vec<(u64, Hash)>,
+}
+
+impl LogSigner {
+    pub(crate) fn new(key: &LogSigningKey, period: usize) -> Self {
+        LogSigner {
+            key: SigningKey::from_bytes(key),
+            period: period.max(1),
+            chain: [0; 32],
+            pending: Vec::with_capacity(period),
+        }
+    }
+
+    /// Hashes a closed section, returns true when enough sections are pending to be signed.
+    pub(crate) fn record(
+        &mut self,
+        sequence: u64,
+        entry_type: UnifiedLogType,
+        content: &[u8],
+    ) -> bool {
+        self.pending
+            .push((sequence, section_digest(sequence, entry_type, content)));
+        self.pending.len() >= self.period
+    }
+
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Signs the pending sections, last when the log is closed.
+    pub(crate) fn sign(&mut self, last: bool) -> SignatureRecord {
+        self.chain = chain(&self.chain, &self.pending, last);
+        SignatureRecord {
+            sections: self.pending.drain(..).collect(),
+            last,
+            signature: self.key.sign(&self.chain).to_bytes(),
+        }
+    }
+}
+
+/// The result of [verify_log].
+#[derive(Debug, PartialEq)]
+pub enum LogVerification {
+    /// Every section is covered by a valid signature.
+    Intact { sections: u64, signatures: u64 },
+    /// The first section, in the order of the log, that does not match the signatures or is not signed.
+    Broken { section: u64, reason: String },
+    /// The sections read are covered by valid signatures but the log does not end with its last signature:
+    /// its end was cut off, or the application did not close it.
+    Truncated { sections: u64 },
+}
+
+/// Checks the signatures of a log against the public key, reading the sections as they are on disk
+/// so an encrypted log does not need its encryption key.
+pub fn verify_log(base_file_path: &Path, key: &LogVerifyingKey) -> CuResult<LogVerification> {
+    let key = VerifyingKey::from_bytes(key).map_err(|e| format!("Invalid verifying key: {}", e))?;
+    let (mut reader, _) = UnifiedLoggerRead::open_raw(&base_file_path.to_path_buf())
+        .map_err(|e| format!("Could not open the log: {}", e))?;
+
+    // The hash of each section read, None for the Signature sections.
+    let mut digests: Vec<Option<Hash>> = Vec::new();
+    let mut signed: Vec<bool> = Vec::new();
+    let mut previous: Hash = [0; 32];
+    let mut signatures = 0u64;
+    let mut closed = false;
+    let mut broken: Option<(u64, String)> = None;
+    let mut report = |section: u64, reason: String| {
+        if broken.as_ref().is_none_or(|(first, _)| section < *first) {
+            broken = Some((section, reason));
+        }
+    };
+
+    loop {
+        let sequence = digests.len() as u64;
+        let (entry_type, content) = match reader.read_raw_section() {
+            Ok(Some(section)) => section,
+            Ok(None) => break,
+            Err(e) => {
+                report(sequence, format!("unreadable section: {}", e));
+                break;
+            }
+        };
+        if entry_type != UnifiedLogType::Signature {
+            digests.push(Some(section_digest(sequence, entry_type, &content)));
+            signed.push(false);
+            continue;
+        }
+        digests.push(None);
+        signed.push(true);
+        let Ok((record, _)) = decode_from_slice::<SignatureRecord, _>(&content, wire_config())
+        else {
+            report(sequence, "undecodable signature".to_string());
+            continue;
+        };
+        previous = chain(&previous, &record.sections, record.last);
+        if key
+            .verify(&previous, &Signature::from_bytes(&record.signature))
+            .is_err()
+        {
+            report(sequence, "invalid signature".to_string());
+            continue;
+        }
+        signatures += 1;
+        closed = record.last;
+        for (section, digest) in record.sections {
+            match digests.get(section as usize) {
+                Some(Some(actual)) if *actual == digest => signed[section as usize] = true,
+                _ => report(section, "does not match its signature".to_string()),
+            }
+        }
+    }
+
+    if let Some(section) = signed.iter().position(|signed| !signed) {
+        report(section as u64, "not signed".to_string());
+    }
+    Ok(match broken {
+        Some((section, reason)) => LogVerification::Broken { section, reason },
+        None if !closed => LogVerification::Truncated {
+            sections: digests.len() as u64,
+        },
+        None => LogVerification::Intact {
+            sections: digests.len() as u64,
+            signatures,
+        },
+    })
+}
@@ -0,0 +1,279 @@
+//! Double buffering of a stream: the objects are encoded in a preallocated staging buffer while another
+//! thread writes the previous one to the log. A slow flush of the memory map or a new slab file only stalls
+//! the writer thread, the caller only waits if it fills a whole buffer before the previous one is written.
+
+use crate::{MmapStream, UnifiedLoggerWrite};
+use bincode::encode_into_slice;
+use bincode::error::EncodeError;
+use bincode::Encode;
+use cu29_traits::{wire_config, CuError, CuResult, UnifiedLogType, WriteStream};
+use std::fmt::{Debug, Formatter};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// The fill level of the staging buffer above which a saturation is reported if the previous buffer
+/// is still being written.
+const SATURATION_THRESHOLD: f32 = 0.75;
+
+/// The usage of the staging buffers of a stream, updated as it logs.
+#[derive(Debug)]
+pub struct StagingStats {
+    id: String,
+    capacity: AtomicUsize,
+    used: AtomicUsize,
+    in_flight: AtomicBool,
+    saturations: AtomicU64,
+    stalls: AtomicU64,
+}
+
+/// A snapshot of [StagingStats].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagingSnapshot {
+    pub id: String,
+    /// Size in bytes of each of the 2 buffers.
+    pub capacity: usize,
+    /// Bytes used in the buffer being filled.
+    pub used: usize,
+    /// True while the other buffer is being written to the log.
+    pub in_flight: bool,
+    /// Number of times the buffer being filled went above the saturation threshold while the other
+    /// one was still being written.
+    pub saturations: u64,
+    /// Number of times logging had to wait for the other buffer to be written.
+    pub stalls: u64,
+}
+
+impl StagingStats {
+    pub fn snapshot(&self) -> StagingSnapshot {
+        StagingSnapshot {
+            id: self.id.clone(),
+            capacity: self.capacity.load(Ordering::Relaxed),
+            used: self.used.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            saturations: self.saturations.load(Ordering::Relaxed),
+            stalls: self.stalls.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A staging buffer with the end offsets of the objects encoded in it, so they are not split across sections.
+struct Staging {
+    bytes: Vec<u8>,
+    used: usize,
+    ends: Vec<usize>,
+}
+
+impl Staging {
+    fn new(capacity: usize) -> Self {
+        Staging {
+            bytes: vec![0; capacity],
+            used: 0,
+            ends: Vec::with_capacity(1024),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.used = 0;
+        self.ends.clear();
+    }
+}
+
+type SaturationCallback = Box<dyn Fn(&StagingSnapshot) + Send + Sync>;
+
+/// A stream logging through 2 staging buffers, see [stream_write_staged].
+pub struct StagedStream {
+    front: Staging,
+    /// The other buffer once it is back from the writer thread.
+    spare: Option<Staging>,
+    to_writer: Option<SyncSender<Staging>>,
+    // Only the logging side receives, the mutex makes the stream Sync.
+    returned: Mutex<Receiver<Staging>>,
+    writer: Option<JoinHandle<()>>,
+    stats: Arc<StagingStats>,
+    on_saturation: Option<SaturationCallback>,
+    saturation_reported: bool,
+}
+
+impl StagedStream {
+    /// The usage of the buffers, shared with the stream.
+    pub fn stats(&self) -> Arc<StagingStats> {
+        self.stats.clone()
+    }
+
+    /// Calls callback when the buffer being filled goes above 75% while the previous one is still being written:
+    /// the storage does not keep up and logging is about to stall.
+    pub fn on_saturation(
+        mut self,
+        callback: impl Fn(&StagingSnapshot) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_saturation = Some(Box::new(callback));
+        self
+    }
+
+    /// Hands the front buffer to the writer thread and takes the other one back, waiting for it if needed.
+    fn swap(&mut self) -> CuResult<()> {
+        let back = match self.spare.take() {
+            Some(back) => back,
+            None => self.wait_spare(true)?,
+        };
+        let full = mem::replace(&mut self.front, back);
+        self.stats.in_flight.store(true, Ordering::Relaxed);
+        self.stats
+            .capacity
+            .store(self.front.bytes.len(), Ordering::Relaxed);
+        self.stats.used.store(0, Ordering::Relaxed);
+        self.saturation_reported = false;
+        self.to_writer
+            .as_ref()
+            .expect("The stream is closed")
+            .send(full)
+            .map_err(|_| CuError::from("The log writer thread stopped."))
+    }
+
+    /// Receives the buffer the writer thread is done with, a stall if it has to wait for it.
+    fn wait_spare(&self, count_stall: bool) -> CuResult<Staging> {
+        let returned = self.returned.lock().unwrap();
+        match returned.try_recv() {
+            Ok(back) => Ok(back),
+            Err(TryRecvError::Empty) => {
+                if count_stall {
+                    self.stats.stalls.fetch_add(1, Ordering::Relaxed);
+                }
+                returned
+                    .recv()
+                    .map_err(|_| CuError::from("The log writer thread stopped."))
+            }
+            Err(TryRecvError::Disconnected) => Err("The log writer thread stopped.".into()),
+        }
+    }
+
+    fn check_saturation(&mut self) {
+        if self.saturation_reported || !self.stats.in_flight.load(Ordering::Relaxed) {
+            return;
+        }
+        if (self.front.used as f32) < self.front.bytes.len() as f32 * SATURATION_THRESHOLD {
+            return;
+        }
+        self.saturation_reported = true;
+        self.stats.saturations.fetch_add(1, Ordering::Relaxed);
+        if let Some(callback) = &self.on_saturation {
+            callback(&self.stats.snapshot());
+        }
+    }
+}
+
+impl Debug for StagedStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StagedStream {{ {:?} }}", self.stats.snapshot())
+    }
+}
+
+impl<E: Encode> WriteStream<E> for StagedStream {
+    fn log(&mut self, obj: &E) -> CuResult<()> {
+        loop {
+            let front = &mut self.front;
+            match encode_into_slice(obj, &mut front.bytes[front.used..], wire_config()) {
+                Ok(nb_bytes) => {
+                    front.used += nb_bytes;
+                    front.ends.push(front.used);
+                    self.stats.used.store(front.used, Ordering::Relaxed);
+                    self.check_saturation();
+                    return Ok(());
+                }
+                Err(EncodeError::UnexpectedEnd) if front.used == 0 => {
+                    // Bigger than a whole buffer, this allocates but only once.
+                    let capacity = front.bytes.len().max(1) * 2;
+                    front.bytes.resize(capacity, 0);
+                    self.stats.capacity.store(capacity, Ordering::Relaxed);
+                }
+                Err(EncodeError::UnexpectedEnd) => self.swap()?,
+                Err(e) => {
+                    return Err(CuError::from("Unexpected error while encoding object.")
+                        .add_cause(e.to_string().as_str()))
+                }
+            }
+        }
+    }
+
+    /// Writes what is staged to the log and waits for it.
+    fn flush(&mut self) -> CuResult<()> {
+        if self.front.used > 0 {
+            self.swap()?;
+        }
+        if self.spare.is_none() {
+            self.spare = Some(self.wait_spare(false)?);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StagedStream {
+    fn drop(&mut self) {
+        if let Some(to_writer) = self.to_writer.take() {
+            if self.front.used > 0 {
+                let front = mem::replace(&mut self.front, Staging::new(0));
+                let _ = to_writer.send(front);
+            }
+        }
+        // The writer thread ends once the channel is closed and everything is written.
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Creates a stream to the unified logger with 2 staging buffers of staging_size bytes,
+/// see [crate::stream_write] for the other parameters.
+pub fn stream_write_staged(
+    logger: Arc<Mutex<UnifiedLoggerWrite>>,
+    entry_type: UnifiedLogType,
+    minimum_allocation_amount: usize,
+    staging_size: usize,
+) -> StagedStream {
+    let stats = Arc::new(StagingStats {
+        id: format!("{:?} staging", entry_type),
+        capacity: AtomicUsize::new(staging_size),
+        used: AtomicUsize::new(0),
+        in_flight: AtomicBool::new(false),
+        saturations: AtomicU64::new(0),
+        stalls: AtomicU64::new(0),
+    });
+    // There are only 2 buffers so sending never blocks.
+    let (to_writer, from_stream) = sync_channel::<Staging>(2);
+    let (to_stream, returned) = sync_channel::<Staging>(2);
+
+    let mut stream = MmapStream::new(entry_type, logger, minimum_allocation_amount);
+    let writer_stats = stats.clone();
+    let writer = std::thread::Builder::new()
+        .name("cu:logger".to_string())
+        .spawn(move || {
+            for mut staging in from_stream {
+                let mut start = 0;
+                for &end in staging.ends.iter() {
+                    stream.log_bytes(&staging.bytes[start..end]);
+                    start = end;
+                }
+                staging.clear();
+                writer_stats.in_flight.store(false, Ordering::Relaxed);
+                if to_stream.send(staging).is_err() {
+                    break;
+                }
+            }
+            // Dropping the stream flushes its last section.
+        })
+        .expect("Failed to start the log writer thread");
+
+    StagedStream {
+        front: Staging::new(staging_size),
+        spare: Some(Staging::new(staging_size)),
+        to_writer: Some(to_writer),
+        returned: Mutex::new(returned),
+        writer: Some(writer),
+        stats,
+        on_saturation: None,
+        saturation_reported: false,
+    }
+}
@@ -2,8 +2,9 @@ use bincode::de::Decoder;
 use bincode::enc::Encoder;
 use bincode::error::{DecodeError, EncodeError};
 use bincode::{Decode, Encode};
-use cu29::clock::{OptionCuTime, RobotClock};
+use cu29::clock::OptionCuTime;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuSrcTask, CuTask, CuTaskLifecycle, Freezable};
 use cu29::{input_msg, output_msg, CuResult};
 use cu_rp_gpio::RPGpioPayload;
@@ -36,12 +37,12 @@ impl CuTaskLifecycle for CaterpillarSource {
 impl<'cl> CuSrcTask<'cl> for CaterpillarSource {
     type Output = output_msg!('cl, RPGpioPayload);
 
-    fn process(&mut self, clock: &RobotClock, output: Self::Output) -> CuResult<()> {
+    fn process(&mut self, ctx: &CuContext, output: Self::Output) -> CuResult<()> {
         // forward the state to the next task
         self.state = !self.state;
         output.set_payload(RPGpioPayload {
             on: self.state,
-            creation: clock.now().into(),
+            creation: ctx.now().into(),
             actuation: OptionCuTime::none(),
         });
         output.metadata.set_status(self.state);
@@ -68,7 +69,7 @@ impl<'cl> CuTask<'cl> for CaterpillarTask {
 
     fn process(
         &mut self,
-        _clock: &RobotClock,
+        _ctx: &CuContext,
         input: Self::Input,
         output: Self::Output,
     ) -> CuResult<()> {
@@ -8,8 +8,8 @@ use cu29_traits::{CuError, CuResult};
 use std::path::PathBuf;
 
 pub mod tasks {
-    use cu29::clock::RobotClock;
     use cu29::config::ComponentConfig;
+    use cu29::context::CuContext;
     use cu29::cutask::{CuMsg, CuSinkTask, CuSrcTask, CuTask, CuTaskLifecycle, Freezable};
     use cu29::{input_msg, output_msg};
     use cu29_traits::CuResult;
@@ -30,7 +30,7 @@ pub mod tasks {
     impl<'cl> CuSrcTask<'cl> for ExampleSrc {
         type Output = output_msg!('cl, i32);
 
-        fn process(&mut self, _clock: &RobotClock, new_msg: Self::Output) -> CuResult<()> {
+        fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
             new_msg.set_payload(42);
             Ok(())
         }
@@ -55,7 +55,7 @@ pub mod tasks {
 
         fn process(
             &mut self,
-            _clock: &RobotClock,
+            _ctx: &CuContext,
             input: Self::Input,
             output: Self::Output,
         ) -> CuResult<()> {
@@ -80,7 +80,7 @@ pub mod tasks {
     impl<'cl> CuSinkTask<'cl> for ExampleSink {
         type Input = input_msg!('cl, i32);
 
-        fn process(&mut self, _clock: &RobotClock, _input: Self::Input) -> CuResult<()> {
+        fn process(&mut self, _ctx: &CuContext, _input: Self::Input) -> CuResult<()> {
             Ok(())
         }
     }
@@ -1,5 +1,5 @@
-use cu29::clock::RobotClock;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuSinkTask, CuTask, CuTaskLifecycle};
 use cu29::cutask::{CuSrcTask, Freezable};
 
@@ -23,7 +23,7 @@ impl CuTaskLifecycle for IntegerSrcTask {
 impl<'cl> CuSrcTask<'cl> for IntegerSrcTask {
     type Output = output_msg!('cl, i32);
 
-    fn process(&mut self, _clock: &RobotClock, output: Self::Output) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, output: Self::Output) -> CuResult<()> {
         self.value += 1;
         output.set_payload(self.value);
         Ok(())
@@ -46,7 +46,7 @@ impl CuTaskLifecycle for FloatSrcTask {
 impl<'cl> CuSrcTask<'cl> for FloatSrcTask {
     type Output = output_msg!('cl, f32);
 
-    fn process(&mut self, _clock: &RobotClock, output: Self::Output) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, output: Self::Output) -> CuResult<()> {
         self.value += 1.0;
         output.set_payload(self.value);
         Ok(())
@@ -68,7 +68,7 @@ impl<'cl> CuSinkTask<'cl> for MergingSinkTask {
     /// The input is an i32 from the IntegerSrcTask and a f32 from the FloatSrcTask.
     type Input = input_msg!('cl, i32, f32);
 
-    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, input: Self::Input) -> CuResult<()> {
         let (i, f) = input;
         println!(
             "SinkTask1 received: {}, {}",
@@ -99,7 +99,7 @@ impl<'cl> CuTask<'cl> for MergerTask {
 
     fn process(
         &mut self,
-        _clock: &RobotClock,
+        _ctx: &CuContext,
         input: Self::Input,
         output: Self::Output,
     ) -> CuResult<()> {
@@ -125,7 +125,7 @@ impl CuTaskLifecycle for MergedSinkTask {
 impl<'cl> CuSinkTask<'cl> for MergedSinkTask {
     type Input = input_msg!('cl, (i32, f32));
 
-    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, input: Self::Input) -> CuResult<()> {
         println!("SinkTask2 received: {:?}", input.payload().unwrap());
         Ok(())
     }
@@ -4,7 +4,6 @@ use cu29_derive::copper_runtime;
 use cu29_helpers::basic_copper_setup;
 use cu29_log_derive::debug;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
 
 #[copper_runtime(config = "copperconfig.ron")]
 struct BalanceBot {}
@@ -12,7 +11,6 @@ struct BalanceBot {}
 const SLAB_SIZE: Option<usize> = Some(1 * 1024 * 1024 * 1024); // preallocate a lot.
 
 fn main() {
-    static STOP_FLAG: AtomicBool = AtomicBool::new(false);
     let logger_path = "logs/balance.copper";
     let copper_ctx = basic_copper_setup(&PathBuf::from(logger_path), SLAB_SIZE, false)
         .expect("Failed to setup logger.");
@@ -23,23 +21,26 @@ fn main() {
     let mut application = BalanceBot::new(clock.clone(), copper_ctx.unified_logger.clone())
         .expect("Failed to create runtime.");
 
-    ctrlc::set_handler(move || {
-        println!("Ctrl-C pressed. Stopping all tasks...");
-        STOP_FLAG.store(true, Ordering::SeqCst);
-    })
-    .expect("Error setting Ctrl-C handler");
+    // `balancebot --self-test` checks the hardware and prints its readiness without running the robot.
+    if std::env::args().any(|arg| arg == "--self-test") {
+        let report = application
+            .self_test()
+            .expect("Failed to run the self tests.");
+        print!("{}", report);
+        if !report.is_ready() {
+            drop(application);
+            drop(copper_ctx);
+            std::process::exit(1);
+        }
+        return;
+    }
 
     debug!("Running... starting clock: {}.", clock.now());
-    application
-        .start_all_tasks()
-        .expect("Failed to start all tasks.");
-    while !STOP_FLAG.load(Ordering::SeqCst) {
-        application
-            .run_one_iteration()
-            .expect("Failed to run application.");
+    // Runs until ctrl-c, then stops all the tasks.
+    if let Err(error) = application.run() {
+        debug!("Application stopped: {}.", error);
+        // ie. the report of a self test required by the config which failed.
+        eprintln!("Application stopped: {}", error);
     }
-    application
-        .stop_all_tasks()
-        .expect("Failed to stop all tasks.");
     debug!("End of app: final clock: {}.", clock.now());
 }
@@ -1,5 +1,5 @@
-use cu29::clock::RobotClock;
 use cu29::config::ComponentConfig;
+use cu29::context::CuContext;
 use cu29::cutask::{CuMsg, CuTask, CuTaskLifecycle, Freezable};
 use cu29::{input_msg, output_msg, CuResult};
 use cu29_traits::CuError;
@@ -30,7 +30,7 @@ impl<'cl> CuTask<'cl> for PIDMerger {
 
     fn process(
         &mut self,
-        _clock: &RobotClock,
+        _ctx: &CuContext,
         input: Self::Input,
         output: Self::Output,
     ) -> CuResult<()> {
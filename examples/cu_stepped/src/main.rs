@@ -0,0 +1,780 @@
+use cu29::clock::{CuDuration, RobotClock};
+use cu29::config::CuParamOverrides;
+use cu29_derive::copper_runtime;
+use cu29_helpers::basic_copper_setup;
+use cu29_log_derive::debug;
+use std::path::PathBuf;
+
+pub mod tasks {
+    use cu29::config::ComponentConfig;
+    use cu29::context::CuContext;
+    use cu29::cutask::{CuMsg, CuSinkTask, CuSrcTask, CuTaskLifecycle, Freezable};
+    use cu29::{input_msg, output_msg};
+    use cu29_traits::CuResult;
+
+    /// Emits the time of the copper clock in ns.
+    pub struct TimeSrc {}
+
+    impl CuTaskLifecycle for TimeSrc {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Ok(Self {})
+        }
+    }
+
+    impl Freezable for TimeSrc {}
+
+    impl<'cl> CuSrcTask<'cl> for TimeSrc {
+        type Output = output_msg!('cl, u64);
+
+        fn process(&mut self, ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+            new_msg.set_payload(ctx.now().into());
+            Ok(())
+        }
+    }
+
+    pub struct TimeSink {
+        pub received: u32,
+    }
+
+    impl CuTaskLifecycle for TimeSink {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Ok(Self { received: 0 })
+        }
+    }
+
+    impl Freezable for TimeSink {}
+
+    impl<'cl> CuSinkTask<'cl> for TimeSink {
+        type Input = input_msg!('cl, u64);
+
+        fn process(&mut self, _ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+            if let Some(time) = input.payload() {
+                println!("Copper time: {}ns", time);
+                self.received += 1;
+            }
+            Ok(())
+        }
+    }
+}
+
+// library = true: no run() loop, the application steps the runtime itself.
+#[copper_runtime(config = "copperconfig.ron", library = true)]
+struct SteppedApp {}
+
+const SLAB_SIZE: Option<usize> = Some(1024 * 1024);
+
+fn main() {
+    let logger_path = "stepped.copper";
+    let copper_ctx = basic_copper_setup(&PathBuf::from(logger_path), SLAB_SIZE, true)
+        .expect("Failed to setup logger.");
+    debug!("Logger created at {}.", path = logger_path);
+
+    // The time of copper is the time of the host application, not the wall clock.
+    let (clock, clock_mock) = RobotClock::mock();
+    // The parameters of the tasks can be changed from the command line to compare runs: `cu-stepped node.key=value`.
+    // `cu-stepped --dry-run` only creates the tasks, to check the config on a machine without the hardware.
+    let dry_run = std::env::args().any(|arg| arg == "--dry-run");
+    let mut overrides = CuParamOverrides::new();
+    for arg in std::env::args().skip(1).filter(|arg| arg != "--dry-run") {
+        overrides.parse(&arg).expect("Invalid parameter override.");
+    }
+    if dry_run {
+        overrides = overrides.dry_run();
+    }
+    let mut application = SteppedApp::new_with_overrides(
+        clock,
+        clock_mock,
+        copper_ctx.unified_logger.clone(),
+        &overrides,
+    )
+    .expect("Failed to create runtime.");
+    if dry_run {
+        println!("The config is valid, all the tasks were created.");
+        return;
+    }
+    application
+        .start_all_tasks()
+        .expect("Failed to start application.");
+
+    // For example the frames of a game engine at 60Hz.
+    let frame = CuDuration::from(1_000_000_000 / 60);
+    for _ in 0..10 {
+        application
+            .step(frame)
+            .expect("Failed to step application.");
+    }
+    application
+        .stop_all_tasks()
+        .expect("Failed to stop application.");
+
+    // The host application can reach the tasks from their ids.
+    let sink = application
+        .task::<SinkTask>(NodeIds::sink)
+        .expect("No sink task.");
+    println!("The sink received {} messages.", sink.received);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cu29::clock::CuTime;
+    use cu29::config::ComponentConfig;
+    use cu29::context::CuContext;
+    use cu29::cutask::{CuMsg, CuSinkTask, CuSrcTask, CuTask, CuTaskLifecycle, Freezable};
+    use cu29::monitoring::CuHealth;
+    use cu29::testing::CuTestRun;
+    use cu29::timefactor::CuTimeFactor;
+    use cu29::{input_msg, output_msg};
+    use cu29_derive::copper_test;
+    use cu29_export::copperlists_dump;
+    use cu29_traits::{CuResult, UnifiedLogType};
+    use cu29_unifiedlog::{UnifiedLogger, UnifiedLoggerBuilder, UnifiedLoggerIOReader};
+    use std::sync::{Arc, Mutex};
+
+    /// Sends 42 instead of the time.
+    pub struct ConstSrc {}
+
+    impl CuTaskLifecycle for ConstSrc {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Ok(Self {})
+        }
+    }
+
+    impl Freezable for ConstSrc {}
+
+    impl<'cl> CuSrcTask<'cl> for ConstSrc {
+        type Output = output_msg!('cl, u64);
+
+        fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+            new_msg.set_payload(42);
+            Ok(())
+        }
+    }
+
+    /// A camera that is not plugged in.
+    pub struct MissingCamera {}
+
+    impl CuTaskLifecycle for MissingCamera {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Err("No camera on /dev/video0.".into())
+        }
+    }
+
+    impl Freezable for MissingCamera {}
+
+    impl<'cl> CuSrcTask<'cl> for MissingCamera {
+        type Output = output_msg!('cl, u64);
+
+        fn process(&mut self, _ctx: &CuContext, _new_msg: Self::Output) -> CuResult<()> {
+            unreachable!("The camera is missing.")
+        }
+    }
+
+    /// A sensor failing on its second read after every creation.
+    pub struct FlakySrc {
+        reads: u32,
+    }
+
+    impl CuTaskLifecycle for FlakySrc {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Ok(Self { reads: 0 })
+        }
+    }
+
+    impl Freezable for FlakySrc {}
+
+    impl<'cl> CuSrcTask<'cl> for FlakySrc {
+        type Output = output_msg!('cl, u64);
+
+        fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+            self.reads += 1;
+            if self.reads == 2 {
+                return Err("Checksum mismatch.".into());
+            }
+            new_msg.set_payload(self.reads.into());
+            Ok(())
+        }
+    }
+
+    /// A sensor failing on its second and third reads.
+    pub struct OutageSrc {
+        reads: u32,
+    }
+
+    impl CuTaskLifecycle for OutageSrc {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Ok(Self { reads: 0 })
+        }
+    }
+
+    impl Freezable for OutageSrc {}
+
+    impl<'cl> CuSrcTask<'cl> for OutageSrc {
+        type Output = output_msg!('cl, u64);
+
+        fn process(&mut self, _ctx: &CuContext, new_msg: Self::Output) -> CuResult<()> {
+            self.reads += 1;
+            if self.reads == 2 || self.reads == 3 {
+                return Err("No fix.".into());
+            }
+            new_msg.set_payload(self.reads.into());
+            Ok(())
+        }
+    }
+
+    /// Slows its source down to 30ms on its first message.
+    pub struct Throttle {}
+
+    impl CuTaskLifecycle for Throttle {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Ok(Self {})
+        }
+    }
+
+    impl Freezable for Throttle {}
+
+    impl<'cl> CuSinkTask<'cl> for Throttle {
+        type Input = input_msg!('cl, u64);
+
+        fn process(&mut self, ctx: &CuContext, input: Self::Input) -> CuResult<()> {
+            if input.payload() == Some(&10_000_000) {
+                ctx.request_param("src", "base_period_ns", 30_000_000u64)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Doubles the time.
+    pub struct Double {}
+
+    impl CuTaskLifecycle for Double {
+        fn new(_config: Option<&ComponentConfig>) -> CuResult<Self>
+        where
+            Self: Sized,
+        {
+            Ok(Self {})
+        }
+    }
+
+    impl Freezable for Double {}
+
+    impl<'cl> CuTask<'cl> for Double {
+        type Input = input_msg!('cl, u64);
+        type Output = output_msg!('cl, u64);
+
+        fn process(
+            &mut self,
+            _ctx: &CuContext,
+            input: Self::Input,
+            output: Self::Output,
+        ) -> CuResult<()> {
+            if let Some(time) = input.payload() {
+                output.set_payload(time * 2);
+            }
+            Ok(())
+        }
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [(id: "src", type: "tasks::TimeSrc"), (id: "sink", type: "tasks::TimeSink")],
+            cnx: [(src: "src", dst: "sink", msg: "u64")],
+        )"#,
+        mocks(src = ConstSrc),
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn sink_receives_the_mocked_source(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(run.outputs("src"), vec![Some("42"); 5]);
+        let sink = app.task::<SinkTask>(NodeIds::sink).unwrap();
+        assert_eq!(sink.received, 5);
+        assert_eq!(app.edge_stats(EdgeIds::src_to_sink).dst, "sink");
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [(id: "src", type: "tasks::TimeSrc"), (id: "sink", type: "tasks::TimeSink")],
+            cnx: [(src: "src", dst: "sink", msg: "u64", transform: (scale: 2.0, offset: 1.0))],
+        )"#,
+        mocks(src = ConstSrc),
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn transforms_run_in_generated_adapters(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(run.outputs("src_to_sink_0"), vec![Some("85"); 5]);
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 5);
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "camera", type: "tests::MissingCamera", optional: true),
+                (id: "double", type: "tests::Double", optional: true),
+                (id: "log", type: "tasks::TimeSink"),
+                (id: "src", type: "tasks::TimeSrc"),
+                (id: "sink", type: "tasks::TimeSink"),
+            ],
+            cnx: [
+                (src: "camera", dst: "double", msg: "u64"),
+                (src: "double", dst: "log", msg: "u64"),
+                (src: "src", dst: "sink", msg: "u64"),
+            ],
+        )"#,
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn missing_optional_tasks_are_pruned(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(app.health(), CuHealth::Degraded);
+        assert!(app.task::<Double>(NodeIds::double).is_none());
+        assert_eq!(run.outputs("camera"), vec![None; 5]);
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 5);
+        assert_eq!(app.task::<LogTask>(NodeIds::log).unwrap().received, 0);
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [(id: "src", type: "tasks::TimeSrc"), (id: "sink", type: "tasks::TimeSink")],
+            cnx: [(src: "src", dst: "sink", msg: "u64")],
+            runtime: (copperlists: 3),
+        )"#,
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn copperlists_are_sized_from_the_config(app: &mut CopperTestApp, _run: &CuTestRun) {
+        assert_eq!(app.copper_runtime.available_copper_lists(), 3);
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 5);
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc", core_affinity: [0]),
+                (id: "sink", type: "tasks::TimeSink"),
+                (id: "other_src", type: "tasks::TimeSrc"),
+                (id: "other_sink", type: "tasks::TimeSink", core_affinity: [0]),
+            ],
+            cnx: [
+                (src: "src", dst: "sink", msg: "u64"),
+                (src: "other_src", dst: "other_sink", msg: "u64"),
+            ],
+            runtime: (parallel_workers: 2),
+        )"#,
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn pinned_tasks_are_processed(app: &mut CopperTestApp, run: &CuTestRun) {
+        let scheduling = app.copper_runtime.schedulings[NodeIds::other_sink as usize].as_ref();
+        assert_eq!(scheduling.unwrap().core_affinity, vec![0]);
+        assert_eq!(run.outputs("src").len(), 5);
+        for node in [NodeIds::sink, NodeIds::other_sink] {
+            assert_eq!(app.task::<SinkTask>(node).unwrap().received, 5);
+        }
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc"),
+                (id: "sink", type: "tasks::TimeSink"),
+                (id: "other_src", type: "tasks::TimeSrc"),
+                (id: "other_sink", type: "tasks::TimeSink"),
+            ],
+            cnx: [
+                (src: "src", dst: "sink", msg: "u64"),
+                (src: "other_src", dst: "other_sink", msg: "u64"),
+            ],
+            faults: (drops: [(src: "src", dst: "sink", probability: 1.0)]),
+        )"#,
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn faults_are_injected_in_their_connections_only(app: &mut CopperTestApp, _run: &CuTestRun) {
+        assert_eq!(app.copper_runtime.faults.as_ref().unwrap().stats.dropped, 5);
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 0);
+        assert_eq!(
+            app.task::<SinkTask>(NodeIds::other_sink).unwrap().received,
+            5
+        );
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc", base_period_ns: 20000000),
+                (id: "gps", type: "tests::OutageSrc", on_error: Abort),
+                (id: "sink", type: "tasks::TimeSink"),
+                (id: "gps_sink", type: "tasks::TimeSink"),
+            ],
+            cnx: [
+                (src: "src", dst: "sink", msg: "u64"),
+                (src: "gps", dst: "gps_sink", msg: "u64"),
+            ],
+            runtime: (copperlists: 2, exhaustion_policy: DropOldest),
+        )"#,
+        iterations = 1,
+        period = "10ms"
+    )]
+    fn dropped_copper_lists_keep_the_latest_messages(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(run.outputs("src"), vec![Some("10000000")]);
+        // The outage of the gps abandons 2 iterations, src runs again at 30ms in the second one.
+        for _ in 0..2 {
+            assert!(app.step(CuDuration(10_000_000)).is_err());
+        }
+        assert_eq!(app.copper_runtime.available_copper_lists(), 0);
+        // The oldest copper list is dropped for the iteration at 40ms, src is not due and its message carried over
+        // is the one of 30ms.
+        app.step(CuDuration(10_000_000)).unwrap();
+        assert_eq!(app.copper_runtime.copperlists_exhausted, 1);
+        let mut last = CuTestRun::default();
+        last.record(app.dump_last_iteration());
+        assert_eq!(last.outputs("src"), vec![Some("30000000")]);
+        assert_eq!(last.outputs("gps"), vec![Some("4")]);
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tests::FlakySrc", on_error: Retry(1)),
+                (id: "sink", type: "tasks::TimeSink"),
+            ],
+            cnx: [(src: "src", dst: "sink", msg: "u64")],
+        )"#,
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn failed_process_is_retried(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(
+            run.outputs("src"),
+            vec![Some("1"), Some("3"), Some("4"), Some("5"), Some("6")]
+        );
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 5);
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tests::FlakySrc", on_error: Restart),
+                (id: "sink", type: "tasks::TimeSink"),
+            ],
+            cnx: [(src: "src", dst: "sink", msg: "u64")],
+        )"#,
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn failing_task_is_restarted(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(
+            run.outputs("src"),
+            vec![Some("1"), None, Some("1"), None, Some("1")]
+        );
+        let stats = &app.copper_runtime.tasks_stats[NodeIds::src as usize];
+        assert_eq!(stats.start_count, 3);
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 3);
+    }
+
+    #[test]
+    fn edges_are_named_after_their_tasks() {
+        let edge = EdgeIds::src_to_sink;
+        assert_eq!(EdgeIds::ALL, [edge]);
+        assert_eq!(edge.index(), 0);
+        assert_eq!((edge.src(), edge.dst()), (NodeIds::src, NodeIds::sink));
+        assert_eq!(edge.msg(), "u64");
+        assert_eq!(edge.channel(Some("r2")), "r2/src");
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [(id: "src", type: "tasks::TimeSrc"), (id: "sink", type: "tasks::TimeSink")],
+            cnx: [(src: "src", dst: "sink", msg: "u64")],
+        )"#,
+        iterations = 3,
+        period = "10ms"
+    )]
+    fn source_follows_the_mock_clock(_app: &mut CopperTestApp, run: &CuTestRun) {
+        let times: Vec<Option<&str>> = run.outputs("src");
+        assert_eq!(
+            times,
+            vec![Some("10000000"), Some("20000000"), Some("30000000")]
+        );
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc"),
+                (id: "double", type: "tests::Double"),
+                (id: "sink", type: "tasks::TimeSink"),
+                (id: "other_src", type: "tasks::TimeSrc"),
+                (id: "other_sink", type: "tasks::TimeSink"),
+            ],
+            cnx: [
+                (src: "src", dst: "double", msg: "u64"),
+                (src: "double", dst: "sink", msg: "u64"),
+                (src: "other_src", dst: "other_sink", msg: "u64"),
+            ],
+            runtime: (parallel_workers: 2),
+        )"#,
+        iterations = 3,
+        period = "10ms"
+    )]
+    fn independent_branches_run_in_parallel(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert!(app.copper_runtime.workers.is_some());
+        let times = vec![Some("10000000"), Some("20000000"), Some("30000000")];
+        assert_eq!(run.outputs("src"), times);
+        assert_eq!(run.outputs("other_src"), times);
+        assert_eq!(
+            run.outputs("double"),
+            vec![Some("20000000"), Some("40000000"), Some("60000000")]
+        );
+        for node in [NodeIds::sink, NodeIds::other_sink] {
+            let sink = app.task::<tasks::TimeSink>(node).unwrap();
+            assert_eq!(sink.received, 3);
+        }
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc", base_period_ns: 10000000),
+                (id: "throttle", type: "tests::Throttle"),
+            ],
+            cnx: [(src: "src", dst: "throttle", msg: "u64")],
+        )"#,
+        iterations = 5,
+        period = "10ms"
+    )]
+    fn tasks_can_change_the_rate_of_others(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(
+            run.outputs("src"),
+            vec![
+                Some("10000000"),
+                Some("10000000"),
+                Some("10000000"),
+                Some("40000000"),
+                Some("40000000"),
+            ]
+        );
+        let src = app.copper_runtime.tasks_configs[NodeIds::src as usize].as_ref();
+        assert_eq!(
+            src.unwrap().try_get::<u64>("base_period_ns").unwrap(),
+            Some(30_000_000)
+        );
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc"),
+                (id: "constant", type: "tests::ConstSrc"),
+                (id: "sink", type: "tasks::TimeSink"),
+                (id: "other_sink", type: "tasks::TimeSink"),
+            ],
+            cnx: [(src: "src", dst: "sink", msg: "u64"), (src: "constant", dst: "other_sink", msg: "u64")],
+            missions: [(id: "clock", tasks: ["src"]), (id: "fixed", tasks: ["constant"])],
+        )"#,
+        iterations = 2,
+        period = "10ms"
+    )]
+    fn missions_select_the_tasks_processed(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(run.outputs("src"), vec![Some("10000000"), Some("20000000")]);
+        assert_eq!(run.outputs("constant"), vec![None, None]);
+        app.switch_mission("fixed").unwrap();
+        app.step(CuDuration::from(10_000_000)).unwrap();
+        let mut switched = CuTestRun::default();
+        switched.record(app.dump_last_iteration());
+        assert_eq!(switched.outputs("constant"), vec![Some("42")]);
+        assert_eq!(
+            app.task::<SinkTask>(NodeIds::other_sink).unwrap().received,
+            1
+        );
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc", base_period_ns: 20000000),
+                (id: "double", type: "tests::Double"),
+                (id: "sink", type: "tasks::TimeSink", base_period_ns: 30000000),
+            ],
+            cnx: [
+                (src: "src", dst: "double", msg: "u64"),
+                (src: "double", dst: "sink", msg: "u64"),
+            ],
+        )"#,
+        iterations = 6,
+        period = "10ms"
+    )]
+    fn tasks_run_at_their_base_period(app: &mut CopperTestApp, run: &CuTestRun) {
+        // The latest message of src is carried over between its runs.
+        assert_eq!(
+            run.outputs("src"),
+            vec![
+                Some("10000000"),
+                Some("10000000"),
+                Some("30000000"),
+                Some("30000000"),
+                Some("50000000"),
+                Some("50000000"),
+            ]
+        );
+        assert_eq!(
+            run.outputs("double"),
+            vec![
+                Some("20000000"),
+                Some("20000000"),
+                Some("60000000"),
+                Some("60000000"),
+                Some("100000000"),
+                Some("100000000"),
+            ]
+        );
+        let sink = app.task::<tasks::TimeSink>(NodeIds::sink).unwrap();
+        assert_eq!(sink.received, 2);
+    }
+
+    #[copper_test(
+        config = r#"(
+            tasks: [
+                (id: "src", type: "tasks::TimeSrc"),
+                (id: "sink", type: "tasks::TimeSink"),
+                (id: "other_src", type: "tasks::TimeSrc", base_period_ns: 20000000),
+                (id: "other_sink", type: "tasks::TimeSink", base_period_ns: 30000000),
+            ],
+            cnx: [
+                (src: "src", dst: "sink", msg: "u64"),
+                (src: "other_src", dst: "other_sink", msg: "u64"),
+            ],
+            runtime: (parallel_workers: 2),
+        )"#,
+        iterations = 6,
+        period = "10ms"
+    )]
+    fn parallel_tasks_run_at_their_base_period(app: &mut CopperTestApp, run: &CuTestRun) {
+        assert_eq!(run.outputs("src").len(), 6);
+        assert_eq!(
+            run.outputs("other_src"),
+            vec![
+                Some("10000000"),
+                Some("10000000"),
+                Some("30000000"),
+                Some("30000000"),
+                Some("50000000"),
+                Some("50000000"),
+            ]
+        );
+        let sink = app.task::<tasks::TimeSink>(NodeIds::sink).unwrap();
+        assert_eq!(sink.received, 6);
+        let other_sink = app.task::<tasks::TimeSink>(NodeIds::other_sink).unwrap();
+        assert_eq!(other_sink.received, 2);
+    }
+
+    #[test]
+    fn disabled_tasks_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let UnifiedLogger::Write(logger) = log_for(&dir.path().join("disabled.copper"), true)
+        else {
+            panic!("Could not create the log.");
+        };
+        let (clock, clock_mock) = RobotClock::mock();
+        let mut app = SteppedApp::new(clock, clock_mock, Arc::new(Mutex::new(logger))).unwrap();
+        app.start_all_tasks().unwrap();
+        let period = CuDuration::from(10_000_000);
+        app.step(period).unwrap();
+        app.disable_task(NodeIds::sink).unwrap();
+        app.step(period).unwrap();
+        app.step(period).unwrap();
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 1);
+        app.enable_task(NodeIds::sink).unwrap();
+        app.step(period).unwrap();
+        app.stop_all_tasks().unwrap();
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 2);
+    }
+
+    #[test]
+    fn steps_follow_the_time_factor() {
+        let dir = tempfile::tempdir().unwrap();
+        let UnifiedLogger::Write(logger) = log_for(&dir.path().join("paced.copper"), true) else {
+            panic!("Could not create the log.");
+        };
+        let (clock, clock_mock) = RobotClock::mock();
+        let mut app = SteppedApp::new(clock, clock_mock, Arc::new(Mutex::new(logger))).unwrap();
+        let factor = CuTimeFactor::default();
+        factor.set(2.0).unwrap();
+        app.copper_runtime.pace_with(factor);
+        app.start_all_tasks().unwrap();
+        let started = std::time::Instant::now();
+        // 40ms of simulation after the first step, 2 times faster.
+        for _ in 0..3 {
+            app.step(CuDuration::from(20_000_000)).unwrap();
+        }
+        assert!(started.elapsed() >= std::time::Duration::from_millis(20));
+        app.stop_all_tasks().unwrap();
+        assert_eq!(app.task::<SinkTask>(NodeIds::sink).unwrap().received, 3);
+    }
+
+    fn log_for(path: &std::path::Path, write: bool) -> UnifiedLogger {
+        UnifiedLoggerBuilder::new()
+            .write(write)
+            .create(write)
+            .file_base_name(path)
+            .preallocated_size(1024 * 1024)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn replay_runs_the_tasks_on_the_recorded_copper_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorded = dir.path().join("recorded.copper");
+        {
+            let UnifiedLogger::Write(logger) = log_for(&recorded, true) else {
+                panic!("Could not create the log.");
+            };
+            let (clock, clock_mock) = RobotClock::mock();
+            let mut app = SteppedApp::new(clock, clock_mock, Arc::new(Mutex::new(logger))).unwrap();
+            app.start_all_tasks().unwrap();
+            for _ in 0..5 {
+                app.step(CuDuration::from(10_000_000)).unwrap();
+            }
+            app.stop_all_tasks().unwrap();
+        }
+
+        let UnifiedLogger::Read(reader) = log_for(&recorded, false) else {
+            panic!("Could not read the log.");
+        };
+        let reader = UnifiedLoggerIOReader::new(reader, UnifiedLogType::CopperList);
+        let lists: Vec<CuList> = copperlists_dump::<CuMsgs>(reader).collect();
+        assert_eq!(lists.len(), 5);
+
+        let UnifiedLogger::Write(logger) = log_for(&dir.path().join("replayed.copper"), true)
+        else {
+            panic!("Could not create the log.");
+        };
+        let mut app = SteppedApp::new_replay(Arc::new(Mutex::new(logger))).unwrap();
+        assert_eq!(app.replay_log(lists).unwrap(), 5);
+        let sink = app.task::<SinkTask>(NodeIds::sink).unwrap();
+        assert_eq!(sink.received, 5);
+        // The clock followed the recorded time instead of running the source.
+        assert_eq!(app.copper_runtime.clock.now(), CuTime::from(50_000_000));
+    }
+}
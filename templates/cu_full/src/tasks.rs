@@ -1,6 +1,6 @@
 use bincode::{Decode, Encode};
 
-use cu29::clock::RobotClock;
+use cu29::context::CuContext;
 use cu29::config::ComponentConfig;
 use cu29::cutask::{CuMsg, CuSinkTask, CuSrcTask, CuTask, CuTaskLifecycle, Freezable};
 use cu29::CuResult;
@@ -34,7 +34,7 @@ impl CuTaskLifecycle for MySource {
 impl<'cl> CuSrcTask<'cl> for MySource {
     type Output = output_msg!('cl, MyPayload);
 
-    fn process(&mut self, _clock: &RobotClock, output: Self::Output) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, output: Self::Output) -> CuResult<()> {
         // Generated a 42 message.
         output.set_payload(MyPayload { value: 42 });
         Ok(())
@@ -67,7 +67,7 @@ impl<'cl> CuTask<'cl> for MyTask {
 
     fn process(
         &mut self,
-        _clock: &RobotClock,
+        _ctx: &CuContext,
         input: Self::Input,
         output: Self::Output,
     ) -> CuResult<()> {
@@ -97,7 +97,7 @@ impl CuTaskLifecycle for MySink {
 impl<'cl> CuSinkTask<'cl> for MySink {
     type Input = input_msg!('cl, MyPayload);
 
-    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> CuResult<()> {
+    fn process(&mut self, _ctx: &CuContext, input: Self::Input) -> CuResult<()> {
         debug!("Sink Received message: {}", input.payload().unwrap().value);
         Ok(())
     }